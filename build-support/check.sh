@@ -0,0 +1,30 @@
+# Type-checks the kernel without linking it and makes sure that its assembly files assemble, which
+# is a lot faster than building an image to find compile errors.
+set -e
+
+start=$(date +%s)
+status=0
+
+# The include directories are passed the same way as in the kernel's build script.
+includes=$(find src/aero_kernel/src -name '*.inc' -exec dirname {} \; | sort -u | sed 's|.*|-I&/|')
+
+for file in $(find src/aero_kernel/src -name '*.asm'); do
+    if ! nasm -f elf64 $includes -o /dev/null "$file"; then
+        echo "check: failed to assemble $file"
+        status=1
+    fi
+done
+
+if ! (cd src && cargo check --package aero_kernel); then
+    status=1
+fi
+
+elapsed=$(($(date +%s) - start))
+
+if [ $status -eq 0 ]; then
+    echo "check: passed in ${elapsed}s"
+else
+    echo "check: failed in ${elapsed}s"
+fi
+
+exit $status
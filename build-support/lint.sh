@@ -0,0 +1,73 @@
+# Runs clippy on the kernel and on the userland Rust crates, and prints the diagnostics of all of
+# them in one report.
+#
+# Usage: lint.sh [fix]
+#
+# If `fix` is `yes`, clippy also applies the suggestions of the auto-fixable lints.
+
+start=$(date +%s)
+fix=$1
+
+# On top of the lints enabled by the crates themselves. `missing_panics_doc` is only reported, as
+# `-D warnings` does not apply to forced warnings.
+lints="-D warnings -D clippy::ptr_as_ptr -D clippy::needless_pass_by_value \
+    --force-warn clippy::missing_panics_doc"
+
+fix_flags=
+if [ "$fix" = yes ]; then
+    fix_flags="--fix --allow-dirty --allow-staged"
+fi
+
+logs=$(mktemp -d)
+trap 'rm -rf "$logs"' EXIT
+
+failed=
+skipped=
+
+# Usage: lint <name> <directory> [cargo flags...]
+lint() {
+    name=$1
+    dir=$2
+    shift 2
+
+    echo "lint: $name"
+
+    # The diagnostics are written to stderr, along with the progress of the build.
+    (cd "$dir" && cargo clippy $fix_flags "$@" -- $lints) 2> "$logs/log"
+    status=$?
+
+    grep -v '^ *\(Compiling\|Checking\|Finished\|Blocking\|Updating\|Locking\|Adding\|Fixed\) ' \
+        "$logs/log" | sed 's/^/    /'
+
+    if [ $status -ne 0 ]; then
+        failed="$failed $name"
+    fi
+}
+
+lint kernel src --package aero_kernel
+
+for manifest in $(find userland -name Cargo.toml -not -path '*/target/*' | sort); do
+    dir=$(dirname "$manifest")
+
+    # The crates using `/base_dir` paths are only built within the jinx container.
+    if grep -q '/base_dir/' "$manifest" && [ ! -d /base_dir ]; then
+        skipped="$skipped $dir"
+        continue
+    fi
+
+    lint "$dir" "$dir"
+done
+
+elapsed=$(($(date +%s) - start))
+
+echo
+if [ -n "$skipped" ]; then
+    echo "lint: skipped outside of the jinx container:$skipped"
+fi
+
+if [ -z "$failed" ]; then
+    echo "lint: passed in ${elapsed}s"
+else
+    echo "lint: failed in ${elapsed}s:$failed"
+    exit 1
+fi
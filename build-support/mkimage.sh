@@ -1,8 +1,29 @@
 IMAGE_PATH=target/disk.img
+HASH_CACHE=target/disk.img.sha256
+
+# Usage: mkimage.sh [profile]
+PROFILE=${1:-release}
 
 ./target/jinx sysroot
+./build-support/package.sh $PROFILE
+
+# The image only holds the sysroot and the userland staging sysroot, so it is only rebuilt if one
+# of their files was added, removed or changed since the last time. The manifest is left out since
+# it records the git revision, which changes with every commit. This script is hashed as well, so
+# that changes to the partition layout or the filesystem options also rebuild the image.
+SYSROOT_HASHES=$(sha256sum "$0" && for dir in sysroot target/userland-sysroot; do
+    (cd $dir && find . -not -path ./usr/share/aero/manifest.json -printf '%y %m %p %l\n' | \
+        LC_ALL=C sort && \
+        find . -type f -not -path ./usr/share/aero/manifest.json -print0 | LC_ALL=C sort -z | \
+        xargs -0 -r sha256sum)
+done)
 
-rm -rf $IMAGE_PATH
+if [ -f $IMAGE_PATH ] && [ -f $HASH_CACHE ] && [ "$SYSROOT_HASHES" = "$(cat $HASH_CACHE)" ]; then
+    echo 'mkimage.sh: image up to date'
+    exit 0
+fi
+
+rm -rf $IMAGE_PATH $HASH_CACHE
 
 dd if=/dev/zero of=$IMAGE_PATH bs=1G count=0 seek=512
 parted -s $IMAGE_PATH mklabel gpt
@@ -15,14 +36,16 @@ if ! lsmod | grep -q 'loop'; then
 fi
 
 sudo losetup -Pf --show $IMAGE_PATH > loopback_dev
-sudo mkfs.ext2 `cat loopback_dev`p1 -I128
+# 256 byte inodes have room for the creation time of the files.
+sudo mkfs.ext2 `cat loopback_dev`p1 -I256
 
 rm -rf target/disk_image/
 mkdir target/disk_image
 sudo mount `cat loopback_dev`p1 target/disk_image
 sudo cp -r -v sysroot/. target/disk_image/
+sudo cp -r -v target/userland-sysroot/. target/disk_image/
 pushd target/disk_image
-sudo mkdir dev proc tmp
+sudo mkdir dev proc run tmp
 popd
 sync
 sudo umount target/disk_image/
@@ -31,3 +54,5 @@ sync
 
 rm -rf loopback_dev
 rm -rf target/disk_image
+
+echo "$SYSROOT_HASHES" > $HASH_CACHE
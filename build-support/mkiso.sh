@@ -1,5 +1,8 @@
+# Usage: mkiso.sh <kernel> [iso path] [extra kernel command line arguments]
 set -ex
 
+ISO_PATH=${2:-target/aero.iso}
+
 ./target/jinx host-build limine
 
 rm -rf target/iso_root
@@ -8,6 +11,13 @@ mkdir -pv target/iso_root/boot
 cp $1 target/iso_root/aero
 cp build-support/limine.cfg src/.cargo/term_background.bmp target/iso_root/
 
+if [ -n "$3" ]; then
+    # The arguments are passed through the environment, so that they are appended as they are.
+    EXTRA_CMDLINE="$3" awk '/^CMDLINE=/ { $0 = $0 " " ENVIRON["EXTRA_CMDLINE"] } { print }' \
+        target/iso_root/limine.cfg > target/iso_root/limine.cfg.new
+    mv target/iso_root/limine.cfg.new target/iso_root/limine.cfg
+fi
+
 # Install the limine binaries
 cp host-pkgs/limine/usr/local/share/limine/limine-bios.sys target/iso_root/boot/
 cp host-pkgs/limine/usr/local/share/limine/limine-bios-cd.bin target/iso_root/boot/
@@ -18,7 +28,7 @@ cp host-pkgs/limine/usr/local/share/limine/BOOT*.EFI target/iso_root/EFI/BOOT/
 # Create the disk image.
 xorriso -as mkisofs -b boot/limine-bios-cd.bin -no-emul-boot -boot-load-size 4 \
     -boot-info-table --efi-boot boot/limine-uefi-cd.bin -efi-boot-part \
-    --efi-boot-image --protective-msdos-label target/iso_root -o target/aero.iso
+    --efi-boot-image --protective-msdos-label target/iso_root -o $ISO_PATH
 
 # Install limine.
-host-pkgs/limine/usr/local/bin/limine bios-install target/aero.iso
+host-pkgs/limine/usr/local/bin/limine bios-install $ISO_PATH
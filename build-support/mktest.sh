@@ -0,0 +1,42 @@
+# Builds the kernel tests into target/aero-test.iso.
+#
+# Usage: mktest.sh <profile> [filter] [list]
+#
+# Only the kernel tests whose path matches the regular expression `filter` are run, and the
+# userland tests are skipped.
+# If `list` is `yes`, the kernel prints the names of the tests instead of running them.
+set -e
+
+profile=$1
+filter=$2
+list=$3
+
+cmdline=
+
+if [ -n "$filter" ]; then
+    # The kernel command line is split on whitespace.
+    case "$filter" in
+        *[[:space:]]*)
+            echo "mktest.sh: the filter cannot contain whitespace" >&2
+            exit 1
+            ;;
+    esac
+
+    cmdline="test-filter=$filter"
+fi
+
+if [ "$list" = yes ]; then
+    cmdline="$cmdline test-list"
+fi
+
+# With the `ci` feature, the kernel exits QEMU once the tests are done. The test binary is the
+# last artifact built, and the only executable one.
+kernel=$(cd src && cargo test --package aero_kernel --profile $profile --features ci --no-run \
+    --message-format=json-render-diagnostics | sed -n 's/.*"executable":"\([^"]*\)".*/\1/p' | tail -n 1)
+
+if [ -z "$kernel" ]; then
+    echo 'mktest.sh: failed to find the kernel test binary' >&2
+    exit 1
+fi
+
+./build-support/mkiso.sh "$kernel" target/aero-test.iso "$cmdline"
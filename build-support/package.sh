@@ -0,0 +1,106 @@
+# Installs the userland into a staging sysroot with a manifest of its files, and archives it.
+#
+# Usage: package.sh [profile]
+#        package.sh --verify <image> [manifest]
+#
+# The staging sysroot is target/userland-sysroot and the archive target/userland.tar. The manifest
+# records the git revision, the build profile and the path, size and blake3 hash of every file. It
+# is written to target/userland-manifest.json and installed as usr/share/aero/manifest.json, which
+# is not listed in itself.
+#
+# With `--verify`, the files listed in the manifest (by default the one of the last package) are
+# read back from the disk image and their hashes are compared against it.
+set -e
+
+STAGING_DIR=target/userland-sysroot
+MANIFEST_PATH=target/userland-manifest.json
+ARCHIVE_PATH=target/userland.tar
+IMAGE_MANIFEST=usr/share/aero/manifest.json
+
+# The ext2 partition starts at sector 2048, see mkimage.sh.
+PARTITION_OFFSET=$((2048 * 512))
+
+if ! command -v b3sum > /dev/null; then
+    echo 'package.sh: b3sum not found, install it to package the userland' >&2
+    exit 1
+fi
+
+if [ "$1" = --verify ]; then
+    image=$2
+    manifest=${3:-$MANIFEST_PATH}
+
+    if [ -z "$image" ] || [ ! -f "$manifest" ]; then
+        echo 'package.sh: usage: package.sh --verify <image> [manifest]' >&2
+        exit 1
+    fi
+
+    dump_dir=$(mktemp -d)
+    trap 'rm -rf "$dump_dir"' EXIT
+
+    # Every file entry of the manifest is on its own line.
+    entries=$(sed -n 's|.*"path": "\([^"]*\)", "size": \([0-9]*\), "blake3": "\([0-9a-f]*\)".*|\1 \2 \3|p' "$manifest")
+
+    # The files are dumped with debugfs, which does not need the image to be mounted.
+    echo "$entries" | awk -v dir="$dump_dir" '{ printf "dump -p /%s %s/%d\n", $1, dir, NR }' \
+        > "$dump_dir/commands"
+    debugfs -f "$dump_dir/commands" "$image?offset=$PARTITION_OFFSET" > /dev/null 2>&1
+
+    status=0
+    index=0
+
+    while read -r path size hash; do
+        index=$((index + 1))
+        file="$dump_dir/$index"
+
+        if [ ! -f "$file" ]; then
+            echo "package.sh: $path: missing from the image"
+            status=1
+        elif [ "$(stat -c %s "$file")" != "$size" ] || [ "$(b3sum --no-names "$file")" != "$hash" ]; then
+            echo "package.sh: $path: does not match the manifest"
+            status=1
+        fi
+    done <<END
+$entries
+END
+
+    if [ $status -eq 0 ]; then
+        echo "package.sh: $index files match $manifest"
+    fi
+
+    exit $status
+fi
+
+profile=${1:-release}
+revision=$(git describe --always --dirty)
+
+./target/jinx build userland
+
+rm -rf $STAGING_DIR
+mkdir -p $STAGING_DIR
+cp -a pkgs/userland/. $STAGING_DIR/
+mkdir -p $STAGING_DIR/etc $STAGING_DIR/usr/share/aero
+
+{
+    printf '{\n'
+    printf '  "revision": "%s",\n' "$revision"
+    printf '  "profile": "%s",\n' "$profile"
+    printf '  "files": [\n'
+
+    (cd $STAGING_DIR && find . -type f -printf '%P\n' | LC_ALL=C sort) | while read -r path; do
+        size=$(stat -c %s "$STAGING_DIR/$path")
+        hash=$(b3sum --no-names "$STAGING_DIR/$path")
+        printf '    {"path": "%s", "size": %s, "blake3": "%s"},\n' "$path" "$size" "$hash"
+    done | sed '$ s/,$//'
+
+    printf '  ]\n'
+    printf '}\n'
+} > $MANIFEST_PATH
+
+cp $MANIFEST_PATH $STAGING_DIR/$IMAGE_MANIFEST
+
+# The archive only depends on the contents of the files, so that two packages of the same userland
+# are identical.
+tar --sort=name --mtime=@0 --owner=0 --group=0 --numeric-owner --format=gnu \
+    -C $STAGING_DIR -cf $ARCHIVE_PATH .
+
+echo "package.sh: packaged $revision ($profile) into $ARCHIVE_PATH"
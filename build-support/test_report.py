@@ -0,0 +1,217 @@
+#!/usr/bin/env python3
+"""Checks the test events reported by a CI kernel (built with the `ci` feature).
+
+The kernel and the userland tests write one JSON record per line to the second serial port:
+
+    {"event":"test_start","suite":"kernel","name":"..."}
+    {"event":"test_ok","suite":"kernel","name":"..."}
+    {"event":"test_fail","suite":"kernel","name":"...","message":"..."}
+    {"event":"run_summary","suite":"kernel","passed":1,"failed":0,"filtered":0}
+
+Usage: test_report.py [--qemu-status N] [--allow-empty] <events.jsonl>
+
+Prints a report of the run and exits with 0 only if every suite that ran reported a summary
+without failures.
+"""
+
+import argparse
+import json
+import sys
+
+SUITES = ("kernel", "userland")
+
+# Fields of each event, besides `event` and `suite`, with their types.
+SCHEMA = {
+    "test_start": {"name": str},
+    "test_ok": {"name": str},
+    "test_fail": {"name": str, "message": str},
+    "run_summary": {"passed": int, "failed": int, "filtered": int},
+}
+
+# QEMU exits with `(code << 1) | 1`, for the codes written to the isa-debug-exit port.
+QEMU_SUCCESS = 0x10 << 1 | 1
+
+# Name of the failure reported when the kernel panics outside of a test, which can happen after
+# the kernel suite reported its summary.
+PANIC = "<panic>"
+
+
+class SchemaError(Exception):
+    pass
+
+
+def parse_event(line):
+    """Parses and validates a single event record."""
+    try:
+        event = json.loads(line)
+    except json.JSONDecodeError as e:
+        raise SchemaError(f"invalid JSON: {e}") from None
+
+    if not isinstance(event, dict):
+        raise SchemaError("an event must be an object")
+
+    kind = event.get("event")
+    if kind not in SCHEMA:
+        raise SchemaError(f"unknown event {kind!r}")
+
+    if event.get("suite") not in SUITES:
+        raise SchemaError(f"unknown suite {event.get('suite')!r}")
+
+    fields = SCHEMA[kind]
+    extra = event.keys() - fields.keys() - {"event", "suite"}
+    if extra:
+        raise SchemaError(f"unexpected fields in {kind}: {', '.join(sorted(extra))}")
+
+    for field, ty in fields.items():
+        value = event.get(field)
+
+        # `bool` is a subclass of `int`.
+        if not isinstance(value, ty) or isinstance(value, bool):
+            raise SchemaError(f"{kind}.{field} must be a {ty.__name__}")
+
+        if ty is int and value < 0:
+            raise SchemaError(f"{kind}.{field} must not be negative")
+
+    return event
+
+
+class Suite:
+    def __init__(self, name):
+        self.name = name
+        self.passed = []
+        self.failed = []
+        self.running = None
+        self.summary = None
+
+
+def collect(lines):
+    """Parses the events into the suites that ran, checking that they come in a valid order.
+
+    A truncated last line is ignored, as QEMU may exit before the serial port is flushed.
+    """
+    suites = {}
+    lines = list(lines)
+
+    for number, line in enumerate(lines, 1):
+        if not line.strip():
+            continue
+
+        try:
+            event = parse_event(line)
+        except SchemaError as e:
+            if number == len(lines) and not line.endswith("\n"):
+                break
+
+            raise SchemaError(f"line {number}: {e}") from None
+
+        suite = suites.setdefault(event["suite"], Suite(event["suite"]))
+        kind = event["event"]
+
+        if suite.summary is not None and event.get("name") != PANIC:
+            raise SchemaError(f"line {number}: {kind} after the {suite.name} summary")
+
+        if kind == "test_start":
+            if suite.running is not None:
+                raise SchemaError(f"line {number}: {event['name']} started while "
+                                  f"{suite.running} is running")
+
+            suite.running = event["name"]
+        elif kind == "test_ok":
+            if event["name"] != suite.running:
+                raise SchemaError(f"line {number}: {event['name']} passed without running")
+
+            suite.passed.append(event["name"])
+            suite.running = None
+        elif kind == "test_fail":
+            suite.failed.append((event["name"], event["message"]))
+            suite.running = None
+        else:
+            suite.summary = event
+
+    return suites
+
+
+def check(suites, qemu_status=None, allow_empty=False):
+    """Returns the problems found with the run, which passed if there are none."""
+    problems = []
+
+    if not suites and not allow_empty:
+        problems.append("no test events were reported")
+
+    for suite in suites.values():
+        for name, message in suite.failed:
+            problems.append(f"{suite.name}: {name} failed: {message}")
+
+        if suite.running is not None:
+            problems.append(f"{suite.name}: {suite.running} never finished")
+
+        if suite.summary is None:
+            problems.append(f"{suite.name}: the run ended without a summary")
+            continue
+
+        passed, failed = suite.summary["passed"], suite.summary["failed"]
+
+        if failed and not suite.failed:
+            problems.append(f"{suite.name}: the summary reports {failed} failed tests")
+
+        if passed != len(suite.passed):
+            problems.append(f"{suite.name}: the summary reports {passed} passed tests, "
+                            f"but {len(suite.passed)} did")
+
+    if qemu_status is not None and qemu_status != QEMU_SUCCESS:
+        problems.append(f"QEMU exited with status {qemu_status}")
+
+    return problems
+
+
+def render(suites, problems):
+    lines = []
+
+    for suite in suites.values():
+        lines.append(f"{suite.name} tests:")
+
+        for name in suite.passed:
+            lines.append(f"    {name} ... ok")
+
+        for name, message in suite.failed:
+            lines.append(f"    {name} ... FAILED ({message})")
+
+        if suite.summary is not None:
+            summary = suite.summary
+            lines.append(f"    {summary['passed']} passed; {summary['failed']} failed; "
+                         f"{summary['filtered']} filtered out")
+
+        lines.append("")
+
+    if problems:
+        lines.append("test run: FAILED")
+        lines.extend(f"    {problem}" for problem in problems)
+    else:
+        lines.append("test run: ok")
+
+    return "\n".join(lines)
+
+
+def main(argv=None):
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("events", help="file the events serial port was written to")
+    parser.add_argument("--qemu-status", type=int, help="exit status of QEMU")
+    parser.add_argument("--allow-empty", action="store_true",
+                        help="do not fail if no tests ran (e.g. when they were only listed)")
+    args = parser.parse_args(argv)
+
+    try:
+        with open(args.events, newline="") as file:
+            suites = collect(file)
+    except (OSError, SchemaError) as e:
+        print(f"test_report: {args.events}: {e}", file=sys.stderr)
+        return 1
+
+    problems = check(suites, args.qemu_status, args.allow_empty)
+    print(render(suites, problems))
+
+    return 1 if problems else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
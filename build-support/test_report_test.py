@@ -0,0 +1,169 @@
+"""Tests of test_report.py, so that the format of the test events stays stable.
+
+Run with `python3 -m unittest discover -s build-support -p '*_test.py'`.
+"""
+
+import json
+import os
+import tempfile
+import unittest
+
+from test_report import QEMU_SUCCESS, SchemaError, check, collect, main, parse_event
+
+
+def line(**event):
+    return json.dumps(event, separators=(",", ":")) + "\n"
+
+
+def passing_run(suite="kernel", tests=("a", "b")):
+    lines = []
+
+    for name in tests:
+        lines.append(line(event="test_start", suite=suite, name=name))
+        lines.append(line(event="test_ok", suite=suite, name=name))
+
+    lines.append(line(event="run_summary", suite=suite, passed=len(tests), failed=0, filtered=0))
+    return lines
+
+
+class SchemaTest(unittest.TestCase):
+    def test_kernel_records(self):
+        # Written by `emu::Event`, which does not go through a JSON library.
+        records = [
+            '{"event":"test_start","suite":"kernel","name":"aero_kernel::tests::a"}',
+            '{"event":"test_ok","suite":"kernel","name":"aero_kernel::tests::a"}',
+            '{"event":"test_fail","suite":"kernel","name":"aero_kernel::tests::a",'
+            '"message":"assertion `left == right` failed\\n  left: \\"a\\""}',
+            '{"event":"run_summary","suite":"kernel","passed":41,"failed":0,"filtered":3}',
+        ]
+
+        for record in records:
+            parse_event(record)
+
+    def test_userland_records(self):
+        # Written by `ci_event` and `ci_summary` in utest.cc.
+        records = [
+            '{"event":"test_start","suite":"userland","name":"sigchld_stop"}',
+            '{"event":"test_ok","suite":"userland","name":"sigchld_stop"}',
+            '{"event":"test_fail","suite":"userland","name":"sigchld_stop",'
+            '"message":"killed by signal 6"}',
+            '{"event":"run_summary","suite":"userland","passed":2,"failed":1,"filtered":0}',
+        ]
+
+        for record in records:
+            parse_event(record)
+
+    def test_invalid_records(self):
+        records = [
+            "not json",
+            "[]",
+            '{"event":"test_start","suite":"kernel"}',
+            '{"event":"test_begin","suite":"kernel","name":"a"}',
+            '{"event":"test_start","suite":"bootloader","name":"a"}',
+            '{"event":"test_start","name":"a"}',
+            '{"event":"test_start","suite":"kernel","name":1}',
+            '{"event":"test_fail","suite":"kernel","name":"a"}',
+            '{"event":"test_ok","suite":"kernel","name":"a","message":"b"}',
+            '{"event":"run_summary","suite":"kernel","passed":1,"failed":0}',
+            '{"event":"run_summary","suite":"kernel","passed":"1","failed":0,"filtered":0}',
+            '{"event":"run_summary","suite":"kernel","passed":true,"failed":0,"filtered":0}',
+            '{"event":"run_summary","suite":"kernel","passed":-1,"failed":0,"filtered":0}',
+        ]
+
+        for record in records:
+            with self.subTest(record=record), self.assertRaises(SchemaError):
+                parse_event(record)
+
+
+class CheckTest(unittest.TestCase):
+    def test_passing_run(self):
+        suites = collect(passing_run("kernel") + passing_run("userland", ["c"]))
+
+        self.assertEqual(suites["kernel"].passed, ["a", "b"])
+        self.assertEqual(suites["userland"].passed, ["c"])
+        self.assertEqual(check(suites, QEMU_SUCCESS), [])
+
+    def test_failed_test(self):
+        lines = passing_run() + [
+            line(event="test_start", suite="userland", name="c"),
+            line(event="test_fail", suite="userland", name="c", message="killed by signal 6"),
+            line(event="run_summary", suite="userland", passed=0, failed=1, filtered=0),
+        ]
+
+        problems = check(collect(lines))
+        self.assertEqual(problems, ["userland: c failed: killed by signal 6"])
+
+    def test_kernel_panic_outside_of_a_test(self):
+        lines = passing_run() + [
+            line(event="test_fail", suite="kernel", name="<panic>", message="oops"),
+        ]
+
+        self.assertEqual(check(collect(lines)), ["kernel: <panic> failed: oops"])
+
+    def test_missing_summary(self):
+        lines = passing_run() + [line(event="test_start", suite="userland", name="c")]
+
+        self.assertEqual(check(collect(lines)), [
+            "userland: c never finished",
+            "userland: the run ended without a summary",
+        ])
+
+    def test_summary_mismatch(self):
+        lines = passing_run()[:-1] + [
+            line(event="run_summary", suite="kernel", passed=3, failed=1, filtered=0),
+        ]
+
+        self.assertEqual(check(collect(lines)), [
+            "kernel: the summary reports 1 failed tests",
+            "kernel: the summary reports 3 passed tests, but 2 did",
+        ])
+
+    def test_out_of_order(self):
+        runs = [
+            [line(event="test_ok", suite="kernel", name="a")],
+            [
+                line(event="test_start", suite="kernel", name="a"),
+                line(event="test_start", suite="kernel", name="b"),
+            ],
+            passing_run() + [line(event="test_start", suite="kernel", name="c")],
+        ]
+
+        for lines in runs:
+            with self.subTest(lines=lines), self.assertRaises(SchemaError):
+                collect(lines)
+
+    def test_truncated_last_line(self):
+        lines = passing_run() + ['{"event":"test_sta']
+        self.assertEqual(check(collect(lines)), [])
+
+        with self.assertRaises(SchemaError):
+            collect(passing_run() + ['{"event":"test_sta\n'])
+
+    def test_empty_run(self):
+        self.assertEqual(check({}), ["no test events were reported"])
+        self.assertEqual(check({}, allow_empty=True), [])
+
+    def test_qemu_status(self):
+        self.assertEqual(check(collect(passing_run()), 35), ["QEMU exited with status 35"])
+
+
+class MainTest(unittest.TestCase):
+    def run_main(self, lines, *args):
+        with tempfile.NamedTemporaryFile("w", suffix=".jsonl", delete=False) as file:
+            file.writelines(lines)
+
+        try:
+            return main([file.name, *args])
+        finally:
+            os.unlink(file.name)
+
+    def test_exit_status(self):
+        self.assertEqual(self.run_main(passing_run(), "--qemu-status", str(QEMU_SUCCESS)), 0)
+        self.assertEqual(self.run_main(passing_run()[:-1]), 1)
+        self.assertEqual(self.run_main(["garbage\n"]), 1)
+        self.assertEqual(self.run_main([]), 1)
+        self.assertEqual(self.run_main([], "--allow-empty"), 0)
+
+
+if __name__ == "__main__":
+    unittest.main()
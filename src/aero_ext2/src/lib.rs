@@ -0,0 +1,553 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! On-disk structures of the ext2 filesystem.
+//!
+//! They are shared by the kernel driver and the `mkfs.ext2` and `fsck.ext2` utilities, so the
+//! formats cannot drift apart. All of the fields are little-endian.
+
+#![no_std]
+
+#[macro_use]
+extern crate static_assertions;
+
+#[cfg(test)]
+extern crate std;
+
+use core::time::Duration;
+
+/// Offset of the superblock from the start of the device, in bytes.
+pub const SUPERBLOCK_OFFSET: usize = 1024;
+
+/// The inode of the root directory.
+pub const ROOT_INODE: usize = 2;
+
+/// The first inode that is not reserved, in revision 0 filesystems.
+pub const GOOD_OLD_FIRST_INODE: usize = 11;
+
+/// Size of the inodes in revision 0 filesystems.
+pub const GOOD_OLD_INODE_SIZE: usize = 128;
+
+/// Structures that are read from and written to the disk as they are laid out in memory. Any
+/// bit pattern is a valid value.
+///
+/// ## Safety
+/// The implementor must be `repr(C)` or `repr(C, packed)` and must not contain padding.
+pub unsafe trait OnDisk: Copy + Sized {
+    /// Reads the structure from the start of `bytes`.
+    ///
+    /// ## Panics
+    /// * If `bytes` is smaller than the structure.
+    fn from_bytes(bytes: &[u8]) -> Self {
+        assert!(bytes.len() >= core::mem::size_of::<Self>());
+
+        // SAFETY: The buffer is large enough and any bit pattern is valid.
+        unsafe { core::ptr::read_unaligned(bytes.as_ptr().cast()) }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        // SAFETY: The structure does not contain padding.
+        unsafe {
+            core::slice::from_raw_parts(
+                core::ptr::from_ref(self).cast(),
+                core::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Revision {
+    Revision0,
+    /// Revision 1 introduces variable inode sizes, extended
+    /// attributes, etc.
+    Revision1,
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct SuperBlock {
+    pub inodes_count: u32,
+    pub blocks_count: u32,
+    pub r_blocks_count: u32,
+    pub free_blocks_count: u32,
+    pub free_inodes_count: u32,
+    pub first_data_block: u32,
+    pub log_block_size: u32,
+    pub log_frag_size: u32,
+    pub blocks_per_group: u32,
+    pub frags_per_group: u32,
+    pub inodes_per_group: u32,
+    pub mtime: u32,
+    pub wtime: u32,
+    pub mnt_count: u16,
+    pub max_mnt_count: u16,
+    pub magic: u16,
+    pub state: u16,
+    pub errors: u16,
+    pub minor_rev_level: u16,
+    pub lastcheck: u32,
+    pub checkinterval: u32,
+    pub creator_os: u32,
+    pub rev_level: u32,
+    pub def_resuid: u16,
+    pub def_gid: u16,
+
+    // Extended Superblock fields
+    //
+    // XXX: If version number >= 1, we have to use the ext2 extended superblock as well :)
+    pub first_ino: u32,
+    pub inode_size: u16,
+    pub block_group_nr: u16,
+    pub feature_compat: u32,
+    pub feature_incompat: u32,
+    pub feature_ro_compat: u32,
+    pub uuid: [u64; 2usize],
+    pub volume_name: [u8; 16usize],
+    pub last_mounted: [u64; 8usize],
+    pub compression_info: u32,
+    pub prealloc_blocks: u8,
+    pub prealloc_dir_blocks: u8,
+    pub reserved_gdt_blocks: u16,
+    pub journal_uuid: [u8; 16usize],
+    pub journal_inum: u32,
+    pub journal_dev: u32,
+    pub last_orphan: u32,
+    pub hash_seed: [u32; 4usize],
+    pub def_hash_version: u8,
+    pub jnl_backup_type: u8,
+    pub group_desc_size: u16,
+    pub default_mount_opts: u32,
+    pub first_meta_bg: u32,
+    pub mkfs_time: u32,
+    pub jnl_blocks: [u32; 17usize],
+}
+
+const_assert_eq!(core::mem::size_of::<SuperBlock>(), 336);
+
+unsafe impl OnDisk for SuperBlock {}
+
+impl SuperBlock {
+    /// `errors`: continue as if nothing happened.
+    pub const ERRORS_CONTINUE: u16 = 1;
+    /// `feature_incompat`: directory entries record the file type.
+    pub const FEATURE_INCOMPAT_FILETYPE: u32 = 0x2;
+    /// `feature_ro_compat`: only some of the block groups contain a copy of the superblock and
+    /// of the block group descriptor table.
+    pub const FEATURE_RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+    pub const MAGIC: u16 = 0xef53;
+    /// `state`: errors were detected in the filesystem.
+    pub const STATE_ERROR: u16 = 2;
+    /// `state`: the filesystem was cleanly unmounted.
+    pub const STATE_VALID: u16 = 1;
+
+    /// Returns the number of entries per block.
+    pub fn entries_per_block(&self) -> usize {
+        self.block_size() / core::mem::size_of::<u32>()
+    }
+
+    pub fn revision(&self) -> Revision {
+        match self.rev_level {
+            0 => Revision::Revision0,
+            1 => Revision::Revision1,
+            revison => unreachable!("ext2: invalid revison {revison}"),
+        }
+    }
+
+    /// Returns the size of a block in bytes.
+    pub fn block_size(&self) -> usize {
+        1024usize << self.log_block_size
+    }
+
+    /// Returns the length of the BGDT.
+    pub fn bgdt_len(&self) -> usize {
+        // The block groups start at the first data block.
+        (self.blocks_count - self.first_data_block).div_ceil(self.blocks_per_group) as usize
+    }
+
+    /// Returns the number of blocks used by a copy of the BGDT.
+    pub fn bgdt_blocks(&self) -> usize {
+        (self.bgdt_len() * core::mem::size_of::<GroupDescriptor>()).div_ceil(self.block_size())
+    }
+
+    pub fn bgdt_block(&self) -> usize {
+        // XXX: The block group descriptors are always located in the block immediately
+        // following the superblock.
+        let block_size = self.block_size();
+
+        if block_size >= 2048 {
+            block_size
+        } else {
+            block_size * 2
+        }
+    }
+
+    /// Returns the first block of the block group `group`.
+    pub fn group_first_block(&self, group: usize) -> usize {
+        self.first_data_block as usize + group * self.blocks_per_group as usize
+    }
+
+    /// Returns the number of blocks in the block group `group`. Only the last group may be
+    /// smaller than `blocks_per_group`.
+    pub fn group_blocks(&self, group: usize) -> usize {
+        let end = self.blocks_count as usize;
+        (end - self.group_first_block(group)).min(self.blocks_per_group as usize)
+    }
+
+    /// Returns whether the block group `group` starts with a copy of the superblock and of the
+    /// BGDT.
+    pub fn has_superblock(&self, group: usize) -> bool {
+        if self.feature_ro_compat & Self::FEATURE_RO_COMPAT_SPARSE_SUPER == 0 || group <= 1 {
+            return true;
+        }
+
+        // With sparse superblocks, only the groups that are a power of 3, 5 or 7 have a copy.
+        [3, 5, 7].into_iter().any(|base| {
+            let mut power = base;
+
+            while power < group {
+                power *= base;
+            }
+
+            power == group
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct GroupDescriptor {
+    pub block_bitmap: u32,
+    pub inode_bitmap: u32,
+    pub inode_table: u32,
+    pub free_blocks_count: u16,
+    pub free_inodes_count: u16,
+    pub used_dirs_count: u16,
+    pub pad: u16,
+    pub reserved: [u8; 12usize],
+}
+
+const_assert_eq!(core::mem::size_of::<GroupDescriptor>(), 32);
+
+unsafe impl OnDisk for GroupDescriptor {}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct DirEntry {
+    pub inode: u32,
+    pub entry_size: u16,
+    pub name_size: u8,
+    pub file_type: u8,
+    name: [u8; 0],
+}
+
+impl DirEntry {
+    /// Returns the smallest size of an entry whose name is `name_size` bytes long.
+    pub const fn record_size(name_size: usize) -> usize {
+        (core::mem::size_of::<Self>() + name_size).next_multiple_of(4)
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        assert!(name.len() < u8::MAX as usize);
+
+        self.name_size = name.len() as u8;
+
+        // SAFETY: The name follows the entry, which is at least `entry_size` bytes long.
+        let name_bytes =
+            unsafe { core::slice::from_raw_parts_mut(self.name.as_mut_ptr(), name.len()) };
+        name_bytes.copy_from_slice(name.as_bytes());
+    }
+
+    pub fn name(&self) -> &str {
+        unsafe {
+            let name = core::slice::from_raw_parts(self.name.as_ptr(), self.name_size as usize);
+            core::str::from_utf8_unchecked(name)
+        }
+    }
+
+    #[inline]
+    pub fn is_used(&self) -> bool {
+        // value of 0 indicates that the entry is not used
+        self.inode != 0
+    }
+}
+
+const_assert_eq!(core::mem::size_of::<DirEntry>(), 8);
+
+#[repr(u8)]
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FileType {
+    Unknown = 0,
+    Fifo = 1,
+    CharDev = 2,
+    Directory = 4,
+    BlockDev = 6,
+    File = 8,
+    Symlink = 10,
+    Socket = 12,
+}
+
+impl FileType {
+    pub fn bits(&self) -> u16 {
+        let val = *self as u8;
+        (val as u16) << 12
+    }
+
+    /// Returns the type recorded in the directory entries of files of this type, if the
+    /// filesystem has [`SuperBlock::FEATURE_INCOMPAT_FILETYPE`].
+    pub fn dirent_type(&self) -> u8 {
+        match self {
+            Self::Unknown => 0,
+            Self::File => 1,
+            Self::Directory => 2,
+            Self::CharDev => 3,
+            Self::BlockDev => 4,
+            Self::Fifo => 5,
+            Self::Socket => 6,
+            Self::Symlink => 7,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct INode {
+    type_and_perm: u16,
+    pub user_id: u16,
+    size_lower: u32,
+    last_access: u32,
+    pub creation_time: u32,
+    last_modification: u32,
+    pub deletion_time: u32,
+    pub group_id: u16,
+    pub hl_count: u16,
+    pub block_count: u32,
+    pub flags: u32,
+    pub os_specific: u32,
+    pub data_ptr: [u32; 15],
+    pub gen_number: u32,
+    pub ext_attr_block: u32,
+    pub size_or_acl: u32,
+    pub fragment_address: u32,
+    pub os_specific2: [u8; 12],
+}
+
+const_assert_eq!(core::mem::size_of::<INode>(), 128);
+
+unsafe impl OnDisk for INode {}
+
+impl INode {
+    /// Index of the doubly indirect block in `data_ptr`.
+    pub const DOUBLY_INDIRECT: usize = 13;
+    /// Index of the singly indirect block in `data_ptr`; the blocks before it are direct.
+    pub const SINGLY_INDIRECT: usize = 12;
+    /// Index of the triply indirect block in `data_ptr`.
+    pub const TRIPLY_INDIRECT: usize = 14;
+
+    pub fn set_file_type(&mut self, file_type: FileType) {
+        // The last 4 bits are used to store the filetype.
+        let mask = 0b0000_1111_1111_1111u16;
+        self.type_and_perm = file_type.bits() | (self.type_and_perm & mask);
+    }
+
+    pub fn set_size(&mut self, size: usize) {
+        self.size_lower = size as u32;
+        self.size_or_acl = (size >> 32) as u32;
+    }
+
+    pub fn size(&self) -> usize {
+        self.size_lower as usize | ((self.size_or_acl as usize) << 32)
+    }
+
+    pub fn set_permissions(&mut self, permissions: u16) {
+        let mask = 0b0000_1111_1111_1111u16;
+        self.type_and_perm = (self.type_and_perm & !mask) | (permissions & mask);
+    }
+
+    pub fn permissions(&self) -> u16 {
+        self.type_and_perm & 0b0000_1111_1111_1111
+    }
+
+    /// Returns whether the inode is in use, which is the case if it has a file type.
+    pub fn is_used(&self) -> bool {
+        self.type_and_perm != 0 && self.deletion_time == 0
+    }
+
+    pub fn file_type(&self) -> FileType {
+        let ty = self.type_and_perm >> 12;
+
+        match ty {
+            0x1 => FileType::Fifo,
+            0x2 => FileType::CharDev,
+            0x4 => FileType::Directory,
+            0x6 => FileType::BlockDev,
+            0x8 => FileType::File,
+            0xa => FileType::Symlink,
+            0xc => FileType::Socket,
+            _ => FileType::Unknown,
+        }
+    }
+
+    /// Returns whether the target of the symbolic link is stored in `data_ptr` instead of in a
+    /// data block.
+    pub fn is_fast_symlink(&self) -> bool {
+        self.file_type() == FileType::Symlink && self.size() < 60 && self.block_count == 0
+    }
+
+    #[inline]
+    pub fn last_access(&self) -> Duration {
+        Duration::from_secs(self.last_access as u64)
+    }
+
+    #[inline]
+    pub fn set_last_access(&mut self, time: Duration) {
+        self.last_access = time.as_secs() as u32;
+    }
+
+    #[inline]
+    pub fn last_modification(&self) -> Duration {
+        Duration::from_secs(self.last_modification as u64)
+    }
+
+    #[inline]
+    pub fn set_last_modification(&mut self, time: Duration) {
+        self.last_modification = time.as_secs() as u32;
+    }
+
+    #[inline]
+    pub fn creation_time(&self) -> Duration {
+        Duration::from_secs(self.creation_time as u64)
+    }
+}
+
+/// The fields that follow [`INode`] in revision 1 inodes that are larger than 128 bytes. Only
+/// the first `extra_isize` bytes are in use.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct INodeExtra {
+    pub extra_isize: u16,
+    pub checksum_hi: u16,
+    pub ctime_extra: u32,
+    pub mtime_extra: u32,
+    pub atime_extra: u32,
+    birth_time: u32,
+    birth_time_extra: u32,
+}
+
+const_assert_eq!(core::mem::size_of::<INodeExtra>(), 24);
+
+unsafe impl OnDisk for INodeExtra {}
+
+impl INodeExtra {
+    /// Returns the extra fields of an inode created at `birth_time`.
+    pub fn new(birth_time: Duration) -> Self {
+        let secs = birth_time.as_secs();
+
+        Self {
+            extra_isize: core::mem::size_of::<Self>() as u16,
+            birth_time: secs as u32,
+            birth_time_extra: (birth_time.subsec_nanos() << 2) | ((secs >> 32) as u32 & 0b11),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the time at which the inode was created, if it is stored.
+    pub fn birth_time(&self) -> Option<Duration> {
+        if (self.extra_isize as usize) < core::mem::size_of::<Self>() {
+            return None;
+        }
+
+        // The lower two bits of the extra field extend the seconds, and the rest are the
+        // nanoseconds.
+        let secs = self.birth_time as u64 | ((self.birth_time_extra as u64 & 0b11) << 32);
+        Some(Duration::new(secs, self.birth_time_extra >> 2))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn superblock(blocks_count: u32, log_block_size: u32) -> SuperBlock {
+        let mut superblock = SuperBlock::from_bytes(&[0; core::mem::size_of::<SuperBlock>()]);
+
+        superblock.blocks_count = blocks_count;
+        superblock.log_block_size = log_block_size;
+        superblock.first_data_block = (log_block_size == 0) as u32;
+        superblock.blocks_per_group = 8 * superblock.block_size() as u32;
+        superblock
+    }
+
+    fn superblock_with_groups(groups: u32) -> SuperBlock {
+        superblock((groups - 1) * 32768 + 100, 2)
+    }
+
+    #[test]
+    fn group_layout() {
+        // With 1 KiB blocks, the first block is not part of any group.
+        let superblock = superblock(8193, 0);
+        assert_eq!(superblock.bgdt_len(), 1);
+        assert_eq!(superblock.bgdt_block(), 2048);
+        assert_eq!(superblock.group_blocks(0), 8192);
+
+        let superblock = superblock_with_groups(3);
+        assert_eq!(superblock.bgdt_len(), 3);
+        assert_eq!(superblock.bgdt_block(), 4096);
+        assert_eq!(superblock.group_first_block(2), 2 * 32768);
+        assert_eq!(superblock.group_blocks(2), 100);
+    }
+
+    #[test]
+    fn sparse_superblocks() {
+        let mut superblock = superblock_with_groups(50);
+
+        assert!((0..50).all(|group| superblock.has_superblock(group)));
+
+        superblock.feature_ro_compat = SuperBlock::FEATURE_RO_COMPAT_SPARSE_SUPER;
+
+        let groups = (0..50)
+            .filter(|&group| superblock.has_superblock(group))
+            .collect::<std::vec::Vec<_>>();
+
+        assert_eq!(groups, [0, 1, 3, 5, 7, 9, 25, 27, 49]);
+    }
+
+    #[test]
+    fn dirent_record_size() {
+        assert_eq!(DirEntry::record_size(1), 12);
+        assert_eq!(DirEntry::record_size(2), 12);
+        assert_eq!(DirEntry::record_size(4), 12);
+        assert_eq!(DirEntry::record_size(5), 16);
+    }
+
+    #[test]
+    fn inode_fields() {
+        let mut inode = INode::default();
+
+        inode.set_file_type(FileType::Directory);
+        inode.set_permissions(0o755);
+        inode.set_size(5 << 32 | 1024);
+
+        assert_eq!(inode.file_type(), FileType::Directory);
+        assert_eq!(inode.permissions(), 0o755);
+        assert_eq!(inode.size(), 5 << 32 | 1024);
+        assert_eq!(&inode.as_bytes()[..2], &0o40755u16.to_le_bytes());
+
+        let birth_time = Duration::new((1 << 32) + 7, 999);
+        assert_eq!(INodeExtra::new(birth_time).birth_time(), Some(birth_time));
+    }
+}
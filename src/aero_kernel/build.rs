@@ -20,7 +20,8 @@ use std::fs;
 use std::error::Error;
 use std::ffi::OsString;
 use std::fs::DirEntry;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 // this is all magic, yes dont ever let anyone see this shit
 
@@ -43,7 +44,54 @@ fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&DirEntry)) -> std::io::Result<()>
     Ok(())
 }
 
+/// Returns the name of the object file of the assembly source file at `path`.
+fn object_name(path: &Path) -> &str {
+    path.file_name()
+        .expect("Failed to get file name")
+        .to_str()
+        .expect("Invalid UTF-8 for file name")
+}
+
+/// Runs `program` and returns its trimmed standard output, or `None` if it failed.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|output| output.trim().to_string())
+}
+
+/// Generates `version.rs` in the output directory, with the commit the kernel is built from
+/// and the build date. These make up the version reported by `uname`.
+fn generate_version() -> Result<(), Box<dyn Error>> {
+    let commit = command_output("git", &["describe", "--always", "--dirty", "--abbrev=12"])
+        .unwrap_or_else(|| "unknown".to_string());
+    let date = command_output("date", &["-u", "+%a %b %e %H:%M:%S UTC %Y"])
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+
+    fs::write(
+        out_dir.join("version.rs"),
+        format!("pub const COMMIT: &str = {commit:?};\npub const BUILD_DATE: &str = {date:?};\n"),
+    )?;
+
+    // Re-run when a commit is checked out or made, or the working tree is staged.
+    if let Some(git_dir) = command_output("git", &["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={git_dir}/HEAD");
+        println!("cargo:rerun-if-changed={git_dir}/index");
+    }
+
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    generate_version()?;
+
     let target = std::env::var("TARGET").expect("target triple is not set");
 
     if target.contains("aarch64") {
@@ -80,38 +128,48 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Now that we have assembled all of the real files, we can go ahead and assemble the source
     // files.
+    let mut asm_files = vec![];
+
     visit_dirs(Path::new("src"), &mut |entry: &DirEntry| {
         let path = entry.path();
 
-        let object_os = path.file_name().expect("Failed to get file name");
-        let object_file = object_os.to_str().expect("Invalid UTF-8 for file name");
-
         match path.extension() {
-            Some(ext) if ext.eq(&OsString::from("asm")) => {
+            Some(ext) if ext.eq(&OsString::from("asm")) => asm_files.push(path),
+            _ => (),
+        }
+    })?;
+
+    println!("{:?}", inc_files);
+
+    // The source files do not depend on each other, so they are assembled in parallel.
+    std::thread::scope(|scope| {
+        for path in &asm_files {
+            let inc_files = &inc_files;
+
+            scope.spawn(move || {
+                let object_file = object_name(path);
                 let mut build = nasm_rs::Build::new();
 
                 build
-                    .file(&path)
+                    .file(path)
                     .flag("-felf64")
                     .target("x86_64-unknown-none");
 
-                println!("{:?}", inc_files);
-
-                for include in &inc_files {
+                for include in inc_files {
                     build.include(include);
                 }
 
                 build
                     .compile(object_file)
                     .expect("failed to compile assembly: skill issue");
-
-                // Link it as a static library.
-                println!("cargo:rustc-link-lib=static={}", object_file);
-            }
-
-            _ => (),
+            });
         }
-    })?;
+    });
+
+    // Link them as static libraries.
+    for path in &asm_files {
+        println!("cargo:rustc-link-lib=static={}", object_name(path));
+    }
 
     // Tell cargo to pass the linker script to the linker..
     println!("cargo:rustc-link-arg=-T.cargo/kernel.ld");
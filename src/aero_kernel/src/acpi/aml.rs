@@ -26,6 +26,11 @@ pub fn get_subsystem() -> Arc<dyn AmlSubsystem> {
     AML_SUBSYSTEM.get().unwrap().clone()
 }
 
+/// Returns the AML subsystem, or `None` if ACPI is not enabled.
+pub fn try_get_subsystem() -> Option<Arc<dyn AmlSubsystem>> {
+    AML_SUBSYSTEM.get().cloned()
+}
+
 pub fn init(subsystem: Arc<dyn AmlSubsystem>) {
     assert!(
         AML_SUBSYSTEM.get().is_none(),
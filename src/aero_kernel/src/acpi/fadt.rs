@@ -20,10 +20,16 @@
 //!
 //! **Notes**: <https://wiki.osdev.org/FADT>
 
+use core::mem::offset_of;
+
 use super::sdt::Sdt;
+use super::GenericAddressStructure;
 
 pub const SIGNATURE: &str = "FACP";
 
+/// The reset register is supported (`RESET_REG_SUP`).
+const RESET_REG_SUPPORTED: u32 = 1 << 10;
+
 #[repr(C, packed)]
 pub struct Fadt {
     pub header: Sdt,
@@ -71,4 +77,22 @@ pub struct Fadt {
     reserved2: u8,
 
     pub flags: u32,
+
+    // Used since ACPI 2.0+
+    pub reset_reg: GenericAddressStructure,
+    pub reset_value: u8,
+}
+
+impl Fadt {
+    /// Returns the reset register and the value to write to it to reset the system, if the
+    /// firmware supports it.
+    pub fn reset_register(&self) -> Option<(GenericAddressStructure, u8)> {
+        let length = self.header.length as usize;
+
+        if length <= offset_of!(Fadt, reset_value) || self.flags & RESET_REG_SUPPORTED == 0 {
+            return None;
+        }
+
+        Some((self.reset_reg, self.reset_value))
+    }
 }
@@ -21,6 +21,14 @@ pub fn get_uptime_ticks() -> usize {
     unimplemented!()
 }
 
+pub fn get_uptime_ms() -> usize {
+    unimplemented!()
+}
+
+pub fn get_uptime_ns() -> u64 {
+    unimplemented!()
+}
+
 pub fn get_realtime_clock() -> TimeSpec {
     unimplemented!()
 }
@@ -16,7 +16,7 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 use core::ptr;
-use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
 use crate::arch::interrupts;
 use crate::arch::interrupts::InterruptStack;
@@ -24,7 +24,7 @@ use crate::mem::paging::{PhysAddr, VirtAddr};
 use raw_cpuid::{CpuId, FeatureInfo};
 use spin::Once;
 
-use crate::utils::sync::{Mutex, MutexGuard};
+use crate::utils::sync::{BMutex, Mutex, MutexGuard};
 
 use super::{io, time};
 
@@ -53,6 +53,13 @@ const XAPIC_ID: u32 = 0x020;
 /// LVT Timer register. Read/write. See Figure 10-8 for reserved bits.
 const XAPIC_LVT_TIMER: u32 = 0x320;
 
+/// LVT Timer: periodic mode, the timer is reloaded with the initial count when it reaches zero.
+const LVT_TIMER_PERIODIC: u32 = 0b01 << 17;
+
+/// LVT Timer: TSC-deadline mode, the timer fires when the time stamp counter reaches the value of
+/// [`io::IA32_TSC_DEADLINE`].
+const LVT_TIMER_TSC_DEADLINE: u32 = 0b10 << 17;
+
 /// Initial Count register (for Timer). Read/write.
 const XAPIC_TIMER_INIT_COUNT: u32 = 0x380;
 
@@ -63,6 +70,19 @@ const XAPIC_TIMER_DIV_CONF: u32 = 0x3E0;
 /// Current Count register (for Timer). Read-only.
 pub const XAPIC_TIMER_CURRENT_COUNT: u32 = 0x390;
 
+/// Interrupt Command Register. In XAPIC mode, it is split in two 32-bit registers and the high
+/// half is at `XAPIC_ICR + 0x10`.
+const XAPIC_ICR: u32 = 0x300;
+
+/// ICR: destination shorthand of all the CPUs but the sender.
+const ICR_ALL_EXCLUDING_SELF: u64 = 0b11 << 18;
+
+/// ICR: level assert, required for fixed interrupts.
+const ICR_LEVEL_ASSERT: u64 = 1 << 14;
+
+/// ICR: the interrupt has not been accepted by its destinations yet (XAPIC only).
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+
 const X2APIC_BASE_MSR: u32 = 0x800;
 
 static LOCAL_APIC: Once<Mutex<LocalApic>> = Once::new();
@@ -73,6 +93,15 @@ pub static CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 static BSP_READY: AtomicBool = AtomicBool::new(false);
 
+/// Number of CPUs whose local APIC is enabled, and thus accept inter-processor interrupts.
+static ONLINE_CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+static FENCE_VECTOR: Once<u8> = Once::new();
+/// Number of CPUs that have not executed the memory barrier requested by [`fence_all`] yet.
+static FENCE_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+static HALT_VECTOR: Once<u8> = Once::new();
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ApicType {
     Xapic,
@@ -107,6 +136,15 @@ fn lapic_error_handler(_stack: &mut InterruptStack) {
     log::error!("ESR={:#0x}", self::get_local_apic().get_esr());
 }
 
+fn fence_handler(_stack: &mut InterruptStack) {
+    fence(Ordering::SeqCst);
+    FENCE_PENDING.fetch_sub(1, Ordering::SeqCst);
+}
+
+fn halt_handler(_stack: &mut InterruptStack) {
+    super::power::halt()
+}
+
 #[cpu_local]
 static mut LAPIC_TIMER_FREQUENCY: u32 = 0;
 
@@ -185,6 +223,22 @@ impl LocalApic {
         }
     }
 
+    /// Sends the fixed interrupt `vector` to every CPU except the current one.
+    pub fn send_ipi_all_excluding_self(&mut self, vector: u8) {
+        unsafe {
+            self.write_long(
+                XAPIC_ICR,
+                ICR_ALL_EXCLUDING_SELF | ICR_LEVEL_ASSERT | vector as u64,
+            );
+
+            if self.apic_type == ApicType::Xapic {
+                while self.read(XAPIC_ICR) & ICR_DELIVERY_PENDING != 0 {
+                    core::hint::spin_loop();
+                }
+            }
+        }
+    }
+
     /// Stops the APIC timer.
     pub fn timer_stop(&mut self) {
         unsafe {
@@ -193,19 +247,35 @@ impl LocalApic {
         }
     }
 
-    pub fn timer_oneshot(&mut self, vec: u8, us: usize) {
+    /// Fires the interrupt `vec` every `us` microseconds.
+    pub fn timer_periodic(&mut self, vec: u8, us: usize) {
         self.timer_stop();
 
         let lapic_timer_frequency = unsafe { *LAPIC_TIMER_FREQUENCY };
         let ticks = us * (lapic_timer_frequency / 1000000) as usize;
 
         unsafe {
-            self.write(XAPIC_LVT_TIMER, vec as u32);
+            self.write(XAPIC_LVT_TIMER, LVT_TIMER_PERIODIC | vec as u32);
             self.write(XAPIC_TIMER_DIV_CONF, 0);
             self.write(XAPIC_TIMER_INIT_COUNT, ticks as u32);
         }
     }
 
+    /// Fires the interrupt `vec` once the time stamp counter reaches `tsc`, right away if it
+    /// already did. Requires the TSC-deadline mode, see [`has_tsc_deadline`].
+    pub fn timer_tsc_deadline(&mut self, vec: u8, tsc: u64) {
+        unsafe {
+            self.write(XAPIC_LVT_TIMER, LVT_TIMER_TSC_DEADLINE | vec as u32);
+
+            // The write to the LVT has to be visible before the deadline is armed, which the
+            // `wrmsr` does not order on its own in XAPIC mode.
+            fence(Ordering::SeqCst);
+
+            // A deadline of zero disarms the timer.
+            io::wrmsr(io::IA32_TSC_DEADLINE, tsc.max(1));
+        }
+    }
+
     /// Calibrates the local APIC timer using the programmable interval timer.
     pub fn timer_calibrate(&mut self) {
         self.timer_stop();
@@ -219,15 +289,22 @@ impl LocalApic {
             time::set_reload_value(0xffff);
 
             let initial_pit_tick = time::get_current_count();
+            let initial_tsc = core::arch::x86_64::_rdtsc();
             self.write(XAPIC_TIMER_INIT_COUNT, SAMPLES);
 
             while self.read(XAPIC_TIMER_CURRENT_COUNT) != 0 {}
 
             let final_pit_tick = time::get_current_count();
+            let tsc_ticks = core::arch::x86_64::_rdtsc() - initial_tsc;
+
             let pit_ticks = initial_pit_tick - final_pit_tick;
             let timer_frequency = (SAMPLES / pit_ticks as u32) * time::PIT_DIVIDEND as u32;
 
             *LAPIC_TIMER_FREQUENCY = timer_frequency;
+
+            // The time stamp counter is sampled over the same interval.
+            let tsc_frequency = tsc_ticks * time::PIT_DIVIDEND as u64 / pit_ticks as u64;
+            time::TSC_FREQUENCY.store(tsc_frequency as usize, Ordering::Relaxed);
         }
 
         self.timer_stop();
@@ -308,6 +385,39 @@ impl LocalApic {
             ApicType::None => unreachable!(),
         }
     }
+
+    /// Writes the provided 64-bit value (`value`) to the provided 64-bit wide APIC register
+    /// (`register`).
+    ///
+    /// ## Panics
+    /// * If the APIC type is set to [`ApicType::None`].
+    ///
+    /// ## Notes
+    /// This function works for both XAPIC and X2APIC. In XAPIC mode, the high half is written
+    /// first as writing to the low half of the ICR sends the interrupt.
+    ///
+    /// ## Safety
+    /// The provided `register` must be a valid 64-bit wide APIC register and the `value` must
+    /// be a valid value for it.
+    unsafe fn write_long(&mut self, register: u32, value: u64) {
+        match self.apic_type {
+            ApicType::X2apic => {
+                let msr = self.register_to_x2apic_msr(register);
+                io::wrmsr(msr, value);
+            }
+
+            ApicType::Xapic => {
+                let addr = self.register_to_xapic_addr(register);
+
+                (addr + 0x10u64)
+                    .as_mut_ptr::<u32>()
+                    .write_volatile((value >> 32) as u32);
+                addr.as_mut_ptr::<u32>().write_volatile(value as u32);
+            }
+
+            ApicType::None => unreachable!(),
+        }
+    }
 }
 
 /// Get a mutable reference to the local apic.
@@ -340,6 +450,49 @@ pub fn mark_bsp_ready(value: bool) {
     BSP_READY.store(value, Ordering::SeqCst);
 }
 
+/// Executes a full memory barrier on every CPU whose local APIC is enabled, and returns once all
+/// of them did.
+pub fn fence_all() {
+    static LOCK: BMutex<()> = BMutex::new(());
+
+    // Only one request can be waited for at a time.
+    let _guard = LOCK.lock();
+
+    fence(Ordering::SeqCst);
+
+    let others = ONLINE_CPU_COUNT.load(Ordering::SeqCst).saturating_sub(1);
+
+    if others == 0 {
+        return;
+    }
+
+    FENCE_PENDING.store(others, Ordering::SeqCst);
+
+    LOCAL_APIC
+        .get()
+        .expect("Attempted to get the local apic before it was initialized")
+        .lock_irq()
+        .send_ipi_all_excluding_self(*FENCE_VECTOR.get().unwrap());
+
+    while FENCE_PENDING.load(Ordering::SeqCst) != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Halts every CPU whose local APIC is enabled, except the current one. They do not return from
+/// the interrupt.
+pub fn halt_others() {
+    if ONLINE_CPU_COUNT.load(Ordering::SeqCst) <= 1 {
+        return;
+    }
+
+    LOCAL_APIC
+        .get()
+        .expect("Attempted to get the local apic before it was initialized")
+        .lock_irq()
+        .send_ipi_all_excluding_self(*HALT_VECTOR.get().unwrap());
+}
+
 /// Read from the `io_apic_id` I/O APIC as described by the MADT.
 pub unsafe fn io_apic_read(io_apic_id: usize, register: u32) -> u32 {
     let io_apic = madt::IO_APICS.read()[io_apic_id];
@@ -431,6 +584,13 @@ pub fn io_apic_setup_legacy_irq(irq: u8, vec: u8, status: i32) {
     io_apic_set_redirect(vec, irq as u32, 0, status)
 }
 
+/// Returns whether the local APIC timer supports the TSC-deadline mode.
+pub fn has_tsc_deadline() -> bool {
+    CpuId::new()
+        .get_feature_info()
+        .is_some_and(|feature_info| feature_info.has_tsc_deadline())
+}
+
 /// Initialize the local apic.
 pub fn init() -> ApicType {
     let feature_info = CpuId::new()
@@ -458,6 +618,19 @@ pub fn init() -> ApicType {
 
     BSP_APIC_ID.store(bsp_id as u64, Ordering::SeqCst);
     LOCAL_APIC.call_once(move || Mutex::new(local_apic));
+    ONLINE_CPU_COUNT.fetch_add(1, Ordering::SeqCst);
+
+    FENCE_VECTOR.call_once(|| {
+        let vector = interrupts::allocate_vector();
+        interrupts::register_handler(vector, fence_handler);
+        vector
+    });
+
+    HALT_VECTOR.call_once(|| {
+        let vector = interrupts::allocate_vector();
+        interrupts::register_handler(vector, halt_handler);
+        vector
+    });
 
     #[cfg(target_arch = "x86_64")]
     {
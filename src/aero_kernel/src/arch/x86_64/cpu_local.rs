@@ -75,6 +75,11 @@ static SELF_PTR: u64 = 0;
 #[cpu_local]
 static mut CPUID: usize = 0;
 
+/// Returns `true` if the CPU-local data of the current CPU has been set up.
+pub fn is_initialized() -> bool {
+    unsafe { io::rdmsr(io::IA32_GS_BASE) != 0 }
+}
+
 pub fn init(cpu_id: usize) {
     let start = VirtAddr::new(extern_sym!(__cpu_local_start).addr() as u64);
     let end = VirtAddr::new(extern_sym!(__cpu_local_end).addr() as u64);
@@ -0,0 +1,196 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Hardware watchpoints, set by a tracer through `PTRACE_SET_WATCHPOINT`.
+//!
+//! The address of each of the four watchpoints is held in DR0-DR3, and DR7 enables them and
+//! selects the access and length they trigger on:
+//!
+//! ```text
+//! [LEN3 R/W3] [LEN2 R/W2] [LEN1 R/W1] [LEN0 R/W0]  [...]  [G3 L3] [G2 L2] [G1 L1] [G0 L0]
+//!  31     28   27     24   23     20   19     16   15  8   7     6 5     4 3     2 1     0
+//! ```
+//!
+//! Watchpoints are local (L*n*) to the task: the registers are kept in its [`ArchTask`] and
+//! loaded on every context switch. When one of them triggers, a debug exception is raised and
+//! the matching B*n* bit is set in DR6.
+//!
+//! [`ArchTask`]: super::task::ArchTask
+
+use aero_syscall::{PtraceWatchpoint, SyscallError, WatchpointKind, PTRACE_WATCHPOINT_COUNT};
+use num_traits::FromPrimitive;
+
+use super::task::user_range_ok;
+
+/// Value of DR6 with no debug conditions detected.
+const DR6_RESET: u64 = 0xffff0ff0;
+/// Mask of the B*n* bits of DR6, set when the matching watchpoint triggered.
+const DR6_TRIGGERED: u64 = 0b1111;
+
+/// Exact watchpoint matching. Recommended on all processors that support it.
+const DR7_LE: u64 = 1 << 8;
+
+macro write_debug_register($reg:literal, $value:expr) {
+    unsafe {
+        core::arch::asm!(
+            concat!("mov ", $reg, ", {}"),
+            in(reg) $value,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+fn read_dr6() -> u64 {
+    let value: u64;
+
+    unsafe {
+        core::arch::asm!("mov {}, dr6", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Returns the watchpoints that caused the current debug exception as a bit mask, and resets
+/// DR6 for the next one.
+pub fn take_triggered() -> u8 {
+    let status = read_dr6();
+    write_debug_register!("dr6", DR6_RESET);
+
+    (status & DR6_TRIGGERED) as u8
+}
+
+/// Encodes the length of a watchpoint into its DR7 LEN bits.
+fn encode_len(len: u32) -> Option<u64> {
+    match len {
+        1 => Some(0b00),
+        2 => Some(0b01),
+        4 => Some(0b11),
+        8 => Some(0b10),
+        _ => None,
+    }
+}
+
+fn decode_len(bits: u64) -> u32 {
+    match bits {
+        0b00 => 1,
+        0b01 => 2,
+        0b11 => 4,
+        _ => 8,
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DebugRegisters {
+    addrs: [u64; PTRACE_WATCHPOINT_COUNT],
+    /// DR7 of the task.
+    control: u64,
+}
+
+impl DebugRegisters {
+    /// Returns whether any of the watchpoints is enabled.
+    pub fn is_active(&self) -> bool {
+        self.enabled() != 0
+    }
+
+    /// Returns the enabled watchpoints as a bit mask.
+    pub fn enabled(&self) -> u8 {
+        (0..PTRACE_WATCHPOINT_COUNT)
+            .filter(|i| self.control & (1 << (i * 2)) != 0)
+            .fold(0, |mask, i| mask | (1 << i))
+    }
+
+    /// Returns the address watched by the watchpoint at `index`.
+    pub fn addr(&self, index: usize) -> u64 {
+        self.addrs[index]
+    }
+
+    /// Returns whether the watchpoint at `index` triggers on instruction execution. Execute
+    /// watchpoints fire before the instruction runs, so it is restarted with RFLAGS.RF set.
+    pub fn is_execute(&self, index: usize) -> bool {
+        (self.control >> (16 + index * 4)) & 0b11 == WatchpointKind::Execute as u64
+    }
+
+    pub fn get(&self, index: usize) -> PtraceWatchpoint {
+        if self.enabled() & (1 << index) == 0 {
+            return PtraceWatchpoint::default();
+        }
+
+        let bits = self.control >> (16 + index * 4);
+
+        PtraceWatchpoint {
+            addr: self.addrs[index],
+            len: decode_len((bits >> 2) & 0b11),
+            kind: (bits & 0b11) as u32,
+        }
+    }
+
+    /// Sets the watchpoint at `index`, or disables it if the length of `watchpoint` is zero.
+    pub fn set(&mut self, index: usize, watchpoint: &PtraceWatchpoint) -> Result<(), SyscallError> {
+        if index >= PTRACE_WATCHPOINT_COUNT {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let field_shift = 16 + index * 4;
+        let field_mask = (0b1111 << field_shift) | (0b11 << (index * 2));
+
+        if watchpoint.len == 0 {
+            self.addrs[index] = 0;
+            self.control &= !field_mask;
+            self.update_exact();
+            return Ok(());
+        }
+
+        let kind = WatchpointKind::from_u32(watchpoint.kind).ok_or(SyscallError::EINVAL)?;
+        let len = encode_len(watchpoint.len).ok_or(SyscallError::EINVAL)?;
+
+        if watchpoint.addr % watchpoint.len as u64 != 0
+            || (kind == WatchpointKind::Execute && watchpoint.len != 1)
+            || !user_range_ok(watchpoint.addr as *const u8, watchpoint.len as usize)
+        {
+            return Err(SyscallError::EINVAL);
+        }
+
+        self.addrs[index] = watchpoint.addr;
+        self.control &= !field_mask;
+        self.control |= ((len << 2) | kind as u64) << field_shift;
+        self.control |= 1 << (index * 2);
+        self.update_exact();
+
+        Ok(())
+    }
+
+    fn update_exact(&mut self) {
+        if self.is_active() {
+            self.control |= DR7_LE;
+        } else {
+            self.control &= !DR7_LE;
+        }
+    }
+
+    /// Loads the watchpoints into the debug registers of the current CPU.
+    pub fn load(&self) {
+        // Disable the watchpoints while their addresses are updated.
+        write_debug_register!("dr7", 0u64);
+
+        write_debug_register!("dr0", self.addrs[0]);
+        write_debug_register!("dr1", self.addrs[1]);
+        write_debug_register!("dr2", self.addrs[2]);
+        write_debug_register!("dr3", self.addrs[3]);
+
+        write_debug_register!("dr7", self.control);
+    }
+}
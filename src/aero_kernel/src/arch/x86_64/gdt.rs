@@ -30,6 +30,8 @@ use core::ptr::addr_of;
 
 use alloc::alloc::alloc_zeroed;
 
+use crate::mem::kstack::KernelStack;
+
 bitflags::bitflags! {
     #[derive(Debug, Copy, Clone)]
     struct GdtEntryFlags: u8 {
@@ -360,6 +362,10 @@ pub fn init_boot() {
 
 static STK: [u8; 4096 * 16] = [0; 4096 * 16];
 
+/// Interrupt stack table index of the stack used by the double fault handler. Indices
+/// start at one, as zero means that the stack is not switched.
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 1;
+
 pub const USER_SS: SegmentSelector =
     SegmentSelector::new(GdtEntryIndex::USER_DATA, PrivilegeLevel::Ring3);
 
@@ -394,6 +400,11 @@ pub fn init() {
 
         TSS.rsp[0] = STK.as_ptr().offset(4096 * 16) as u64;
 
+        // The double fault handler gets its own stack, so that a kernel stack overflow can
+        // still be reported after the page fault on the guard page failed to push its frame.
+        let double_fault_stack = KernelStack::alloc().expect("gdt: failed to allocate IST");
+        TSS.ist[DOUBLE_FAULT_IST_INDEX as usize - 1] = double_fault_stack.top().as_u64();
+
         let gdt_descriptor = GdtDescriptor::new(
             (mem::size_of::<[GdtEntry; GDT_ENTRY_COUNT]>() - 1) as u16,
             gdt.as_ptr() as u64,
@@ -17,11 +17,13 @@
 
 use super::{io, InterruptErrorStack};
 
-use crate::arch::controlregs;
+use crate::arch::{controlregs, debugregs};
+use crate::mem::kstack;
 use crate::mem::paging::{PageFaultErrorCode, VirtAddr};
 
 use crate::unwind;
 use crate::userland::scheduler;
+use crate::userland::vm::PageFaultResult;
 
 #[cpu_local]
 pub static mut PF_RESUME: VirtAddr = VirtAddr::new(0);
@@ -63,12 +65,11 @@ macro interrupt_exception(fn $name:ident() => $message:expr) {
 }
 
 interrupt_exception!(fn divide_by_zero() => "Division by zero");
-interrupt_exception!(fn debug() => "Debug");
+interrupt_exception!(fn unexpected_debug() => "Debug");
 interrupt_exception!(fn non_maskable() => "Non Maskable");
 interrupt_exception!(fn overflow() => "Stack Overflow");
 interrupt_exception!(fn bound_range() => "Out of Bounds");
 interrupt_exception!(fn device_not_available() => "Device not Available");
-interrupt_exception!(fn double_fault() => "Double Fault");
 interrupt_exception!(fn invalid_tss() => "Invalid TSS");
 interrupt_exception!(fn segment_not_present() => "Segment not Present");
 interrupt_exception!(fn stack_segment() => "Stack Segment Fault");
@@ -79,6 +80,53 @@ interrupt_exception!(fn machine_check() => "Machine check fault");
 interrupt_exception!(fn virtualization() => "Virtualization fault");
 interrupt_exception!(fn security() => "Security exception");
 
+pub fn double_fault(stack: &mut InterruptErrorStack) {
+    // Running into the guard page of a kernel stack raises a page fault, whose frame cannot be
+    // pushed onto the exhausted stack, so it ends up here. This handler runs on its own stack,
+    // and CR2 holds the address of the failed push.
+    check_kernel_stack_overflow(controlregs::read_cr2());
+
+    unwind::prepare_panic();
+
+    log::error!("EXCEPTION: Double Fault");
+    log::error!("Stack: {:#x?}", stack);
+
+    unwind::unwind_stack_trace();
+
+    unsafe {
+        loop {
+            super::halt();
+        }
+    }
+}
+
+/// Panics with a kernel stack overflow if `accessed_address` is in the guard page of a kernel
+/// stack. The backtrace printed by the panic handler is the call chain that overflowed it.
+fn check_kernel_stack_overflow(accessed_address: VirtAddr) {
+    if !kstack::is_guard_page(accessed_address) {
+        return;
+    }
+
+    let task = scheduler::is_initialized()
+        .then(|| scheduler::get_scheduler().inner.current_task_optional())
+        .flatten()
+        .filter(|task| {
+            task.arch_task()
+                .kernel_stack()
+                .is_some_and(|stack| stack.guard_page_contains(accessed_address))
+        });
+
+    if let Some(task) = task {
+        panic!(
+            "kernel stack overflow in task {} (pid={}), call chain:",
+            task.tid().as_usize(),
+            task.pid().as_usize()
+        );
+    } else {
+        panic!("kernel stack overflow (guard page at {accessed_address:#x}), call chain:");
+    }
+}
+
 pub fn simd(stack: &mut InterruptErrorStack) {
     unwind::prepare_panic();
 
@@ -130,6 +178,26 @@ pub fn invalid_opcode(stack: &mut InterruptErrorStack) {
     }
 }
 
+pub fn debug(stack: &mut InterruptErrorStack) {
+    let triggered = debugregs::take_triggered();
+
+    if stack.stack.iret.is_user() {
+        scheduler::get_scheduler()
+            .current_task()
+            .ptrace_debug_trap(triggered, &mut stack.stack);
+        return;
+    }
+
+    // The kernel accessed user memory watched by the current task (e.g. in a user copy
+    // routine). Linux reports these too, but they are ignored here as the access was not made
+    // by the tracee itself.
+    if triggered != 0 {
+        return;
+    }
+
+    unexpected_debug(stack);
+}
+
 pub fn breakpoint(stack: &mut InterruptErrorStack) {
     // We will need to prevent RIP from going out of sync with
     // instructions.
@@ -140,12 +208,6 @@ pub fn breakpoint(stack: &mut InterruptErrorStack) {
 }
 
 pub(super) fn page_fault(stack: &mut InterruptErrorStack) {
-    let pf_resume = unsafe { *PF_RESUME };
-    if !pf_resume.is_zero() {
-        stack.stack.iret.rip = pf_resume.as_u64();
-        return;
-    }
-
     let accessed_address = controlregs::read_cr2();
     let reason = PageFaultErrorCode::from_bits_truncate(stack.code);
 
@@ -157,6 +219,12 @@ pub(super) fn page_fault(stack: &mut InterruptErrorStack) {
     // a non-mapped memory region while in RPL_3.
     let userland_last_address = super::super::task::userland_last_address();
 
+    // The faulting access did not exhaust the stack (e.g. a large stack frame skipped over the
+    // rest of it), so the page fault handler still got to run.
+    if !stack.stack.iret.is_user() {
+        check_kernel_stack_overflow(accessed_address);
+    }
+
     // prints out the error information for this page fault.
     let print_info = || {
         log::error!("");
@@ -172,12 +240,35 @@ pub(super) fn page_fault(stack: &mut InterruptErrorStack) {
     if accessed_address < userland_last_address && scheduler::is_initialized()
         || stack.stack.iret.is_user()
     {
-        let signal = scheduler::get_scheduler()
+        let result = scheduler::get_scheduler()
             .current_task()
             .vm
             .handle_page_fault(reason, accessed_address);
 
-        if !signal && stack.stack.iret.is_user() {
+        if result == PageFaultResult::Handled {
+            scheduler::get_scheduler()
+                .current_task()
+                .sched()
+                .count_page_fault();
+
+            return;
+        } else if result == PageFaultResult::LimitExceeded {
+            let task = scheduler::get_scheduler().current_task();
+
+            log::warn!(
+                "process (tid={}, pid={}) exceeded its memory limit",
+                task.tid().as_usize(),
+                task.pid().as_usize()
+            );
+
+            task.signal(aero_syscall::signal::SIGKILL);
+
+            // Faults inside of a user copy routine fail the copy with `EFAULT` below, the task
+            // is killed on its way back to userland.
+            if stack.stack.iret.is_user() {
+                return;
+            }
+        } else if stack.stack.iret.is_user() {
             log::error!("Segmentation fault");
             print_info();
 
@@ -206,12 +297,17 @@ pub(super) fn page_fault(stack: &mut InterruptErrorStack) {
             let task = scheduler::get_scheduler().current_task();
             task.signal(aero_syscall::signal::SIGSEGV);
             return;
-        } else if !signal {
-        } else {
-            return;
         }
     }
 
+    // The kernel faulted on an address that could not be resolved. If this happened inside of a
+    // user copy routine, resume at its fault handler so that the copy fails with `EFAULT`.
+    let pf_resume = unsafe { *PF_RESUME };
+    if !pf_resume.is_zero() && !stack.stack.iret.is_user() {
+        stack.stack.iret.rip = pf_resume.as_u64();
+        return;
+    }
+
     unwind::prepare_panic();
 
     log::error!("Page fault");
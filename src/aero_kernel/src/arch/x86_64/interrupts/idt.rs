@@ -26,9 +26,10 @@ pub(super) static mut IDT: [IdtEntry; IDT_ENTRIES] = [IdtEntry::EMPTY; IDT_ENTRI
 use core::mem::size_of;
 use core::ptr::addr_of;
 
+use aero_syscall::PtraceRegs;
 use bit_field::BitField;
 
-use crate::arch::gdt::{GdtEntryIndex, PrivilegeLevel, SegmentSelector};
+use crate::arch::gdt::{GdtEntryIndex, PrivilegeLevel, SegmentSelector, DOUBLE_FAULT_IST_INDEX};
 use crate::utils::sync::Mutex;
 
 #[repr(C, packed)]
@@ -76,6 +77,13 @@ impl EntryOptions {
     fn set_present(&mut self, present: bool) {
         self.bits.set_bit(15, present);
     }
+
+    /// Sets the interrupt stack table index of the stack to switch to (bits 2:0). Index zero
+    /// disables the stack switch.
+    #[inline]
+    fn set_stack_index(&mut self, index: u16) {
+        self.bits.set_bits(0..3, index);
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -112,6 +120,10 @@ impl IdtEntry {
 
         self.options.set_present(true);
     }
+
+    pub(crate) fn set_stack_index(&mut self, index: u16) {
+        self.options.set_stack_index(index);
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -164,6 +176,76 @@ pub struct InterruptStack {
     pub iret: IretRegisters,
 }
 
+impl InterruptStack {
+    /// `RFLAGS` bits that a tracer is allowed to change (`CF`, `PF`, `AF`, `ZF`, `SF`, `TF`,
+    /// `DF`, `OF`, `NT`, `RF` and `AC`).
+    const PTRACE_RFLAGS_MASK: u64 = 0x54dd5;
+
+    pub fn ptrace_regs(&self) -> PtraceRegs {
+        PtraceRegs {
+            r15: self.preserved.r15,
+            r14: self.preserved.r14,
+            r13: self.preserved.r13,
+            r12: self.preserved.r12,
+            rbp: self.preserved.rbp,
+            rbx: self.preserved.rbx,
+            r11: self.scratch.r11,
+            r10: self.scratch.r10,
+            r9: self.scratch.r9,
+            r8: self.scratch.r8,
+            rsi: self.scratch.rsi,
+            rdi: self.scratch.rdi,
+            rdx: self.scratch.rdx,
+            rcx: self.scratch.rcx,
+            rax: self.scratch.rax,
+            rip: self.iret.rip,
+            cs: self.iret.cs,
+            rflags: self.iret.rflags,
+            rsp: self.iret.rsp,
+            ss: self.iret.ss,
+        }
+    }
+
+    /// Updates the user register state from `regs`. The segment selectors and the privileged
+    /// `RFLAGS` bits are left untouched. Returns `false` if the new instruction or stack pointer
+    /// does not point to userland.
+    pub fn set_ptrace_regs(&mut self, regs: &PtraceRegs) -> bool {
+        let max_user_addr = crate::arch::task::userland_last_address().as_u64();
+
+        if regs.rip > max_user_addr || regs.rsp > max_user_addr {
+            return false;
+        }
+
+        self.preserved = PreservedRegisters {
+            r15: regs.r15,
+            r14: regs.r14,
+            r13: regs.r13,
+            r12: regs.r12,
+            rbp: regs.rbp,
+            rbx: regs.rbx,
+        };
+
+        self.scratch = ScratchRegisters {
+            r11: regs.r11,
+            r10: regs.r10,
+            r9: regs.r9,
+            r8: regs.r8,
+            rsi: regs.rsi,
+            rdi: regs.rdi,
+            rdx: regs.rdx,
+            rcx: regs.rcx,
+            rax: regs.rax,
+        };
+
+        self.iret.rip = regs.rip;
+        self.iret.rsp = regs.rsp;
+        self.iret.rflags = (self.iret.rflags & !Self::PTRACE_RFLAGS_MASK)
+            | (regs.rflags & Self::PTRACE_RFLAGS_MASK);
+
+        true
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 #[repr(C)]
 pub struct InterruptErrorStack {
@@ -200,6 +282,8 @@ pub fn init() {
 
             IDT[index].set_function(handler);
         }
+
+        IDT[8].set_stack_index(DOUBLE_FAULT_IST_INDEX);
     }
 
     INTERRUPT_HANDLERS.lock()[0] = IrqHandler::ErrorHandler(exceptions::divide_by_zero);
@@ -43,6 +43,9 @@ pub const IA32_SYSENTER_CS: u32 = 0x174;
 pub const IA32_SYSENTER_ESP: u32 = 0x175;
 pub const IA32_SYSENTER_EIP: u32 = 0x176;
 
+/// Page Attribute Table (R/W). See [`crate::mem::paging::PAT_LAYOUT`].
+pub const IA32_PAT: u32 = 0x277;
+
 /// APIC Location and Status (R/W).
 ///
 /// ```text
@@ -52,6 +55,10 @@ pub const IA32_SYSENTER_EIP: u32 = 0x176;
 /// ```
 pub const IA32_APIC_BASE: u32 = 0x1b;
 
+/// TSC Target of Local APIC's TSC Deadline Mode (R/W). The local APIC timer fires once the time
+/// stamp counter reaches this value, if it is in the TSC-deadline mode. Writing zero disarms it.
+pub const IA32_TSC_DEADLINE: u32 = 0x6e0;
+
 /// Wrapper function to the `outb` assembly instruction used to do the
 /// 8-bit low level port output.
 #[inline]
@@ -19,10 +19,13 @@ pub mod cpu_local;
 
 pub mod apic;
 pub mod controlregs;
+pub mod debugregs;
 pub mod gdt;
 pub mod interrupts;
 pub mod io;
 pub mod mem;
+pub mod perf;
+pub mod power;
 pub mod signals;
 pub mod syscall;
 pub mod task;
@@ -60,7 +63,9 @@ static MODULES: ModuleRequest = ModuleRequest::new();
 static FRAMEBUFFER: FramebufferRequest = FramebufferRequest::new();
 static RSDP: RsdpRequest = RsdpRequest::new();
 static BOOT_TIME: BootTimeRequest = BootTimeRequest::new();
-static STACK: StackSizeRequest = StackSizeRequest::new().with_size(0x1000 * 32); // 16KiB of stack for both the BSP and the APs
+static STACK: StackSizeRequest = StackSizeRequest::new().with_size(0x1000 * 32); // 16KiB of stack
+                                                                                 // for both the BSP
+                                                                                 // and the APs
 static HHDM: HhdmRequest = HhdmRequest::new();
 
 #[no_mangle]
@@ -153,7 +158,7 @@ extern "C" fn arch_aero_main() -> ! {
         .next()
         .expect("limine: no framebuffer found!");
 
-    rendy::init(framebuffer, &command_line);
+    rendy::init(framebuffer, command_line);
     logger::set_rendy_debug(true);
 
     interrupts::init();
@@ -167,7 +172,7 @@ extern "C" fn arch_aero_main() -> ! {
     acpi::init(rsdp);
     log::info!("loaded ACPI");
 
-    tls::init();
+    tls::init(0);
     cpu_local::init(0);
     log::info!("loaded TLS");
 
@@ -194,7 +199,7 @@ extern "C" fn x86_64_aero_ap_main(cpu: &Cpu) -> ! {
     gdt::init_boot();
     log::info!("AP{}: loaded boot GDT", ap_id);
 
-    tls::init();
+    tls::init(ap_id);
     cpu_local::init(ap_id);
     log::info!("AP{}: loaded TLS", ap_id);
 
@@ -202,6 +207,7 @@ extern "C" fn x86_64_aero_ap_main(cpu: &Cpu) -> ! {
     log::info!("AP{}: loaded GDT", ap_id);
 
     syscall::init();
+    init_pat();
 
     // Wait for the BSP to be ready (after the BSP has initialized
     // the scheduler).
@@ -244,7 +250,14 @@ pub fn has_fsgsbase() -> bool {
     })
 }
 
+/// Programs the page attribute table, which has to be the same on all of the CPUs.
+fn init_pat() {
+    unsafe { io::wrmsr(io::IA32_PAT, crate::mem::paging::PAT_LAYOUT) }
+}
+
 pub fn init_cpu() {
+    init_pat();
+
     unsafe {
         // Enable the no-execute page protection feature.
         io::wrmsr(io::IA32_EFER, io::rdmsr(io::IA32_EFER) | 1 << 11);
@@ -0,0 +1,271 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Hardware performance counters, exposed to userland through `perf_event_open`.
+//!
+//! Only general-purpose counter 0 of the architectural performance monitoring unit is used, so a
+//! single event can be counted at a time. The counter only runs while the thread that opened the
+//! event is running: the value of its event select register is kept in the [`ArchTask`] of the
+//! thread and loaded on every context switch. The counter itself is saved there when the thread
+//! is switched out and restored on the CPU that the thread is switched in on.
+//!
+//! [`ArchTask`]: super::task::ArchTask
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use aero_syscall::perf::{
+    PerfAttrFlags, PerfEventAttr, PerfHwId, PerfType, PERF_EVENT_IOC_DISABLE,
+    PERF_EVENT_IOC_ENABLE, PERF_EVENT_IOC_RESET,
+};
+use aero_syscall::SyscallError;
+
+use alloc::sync::{Arc, Weak};
+use num_traits::FromPrimitive;
+use raw_cpuid::CpuId;
+
+use crate::fs::inode::INodeInterface;
+use crate::fs::{self, FileSystemError};
+use crate::userland::scheduler;
+use crate::userland::task::Task;
+use crate::utils::sync::IrqGuard;
+
+use super::io;
+use super::task::ArchTask;
+
+/// Performance counter 0 (R/W).
+const IA32_PMC0: u32 = 0xc1;
+
+/// Event select of performance counter 0 (R/W).
+///
+/// ```text
+/// [........]   [.]  [.]   [...]  [.]  [.]  [.]   [........]   [........]
+/// 63      24   22   21    20..19  18   17   16    15      8    7       0
+///  cmask       EN   ANY   INT,PC   E    OS  USR    unit mask     event
+/// ```
+const IA32_PERFEVTSEL0: u32 = 0x186;
+
+/// Enables the general-purpose counters individually (R/W). Available from version 2 of the
+/// architectural performance monitoring.
+const IA32_PERF_GLOBAL_CTRL: u32 = 0x38f;
+
+/// Counts while the CPU is in user mode.
+const PERFEVTSEL_USR: u64 = 1 << 16;
+/// Counts while the CPU is in kernel mode.
+const PERFEVTSEL_OS: u64 = 1 << 17;
+/// Enables the counter.
+const PERFEVTSEL_EN: u64 = 1 << 22;
+
+/// Set while counter 0 is owned by a [`PerfEvent`].
+static COUNTER_IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// Stops the counter of `task` and saves its value, if it is counting.
+fn save(task: &mut ArchTask) {
+    if task.perf_select != 0 {
+        unsafe {
+            io::wrmsr(IA32_PERFEVTSEL0, 0);
+            task.perf_count = io::rdmsr(IA32_PMC0);
+        }
+    }
+}
+
+/// Loads the counter and event select value of `task`.
+fn load(task: &ArchTask) {
+    unsafe {
+        if task.perf_select != 0 {
+            io::wrmsr(IA32_PMC0, task.perf_count);
+        }
+
+        io::wrmsr(IA32_PERFEVTSEL0, task.perf_select);
+    }
+}
+
+/// Saves the counter of the thread that is switched out and loads the one of the thread that is
+/// switched to. See the module level documentation for more information.
+pub fn switch(from: &mut ArchTask, to: &ArchTask) {
+    save(from);
+    load(to);
+}
+
+/// Returns the event select value of the hardware event `config`, without the privilege levels
+/// to count at or the enable bit.
+///
+/// ## Errors
+/// * `EOPNOTSUPP`: The CPU does not support architectural performance monitoring.
+/// * `ENOENT`: The event is not supported.
+fn hardware_event(config: u64) -> Result<u64, SyscallError> {
+    let info = CpuId::new()
+        .get_performance_monitoring_info()
+        .filter(|info| info.version_id() != 0 && info.number_of_counters() != 0)
+        .ok_or(SyscallError::EOPNOTSUPP)?;
+
+    // The event number and unit mask of the pre-defined architectural events.
+    let (event, umask, unavailable) = match PerfHwId::from_u64(config) {
+        Some(PerfHwId::CpuCycles) => (0x3c, 0x00, info.is_core_cyc_ev_unavailable()),
+        Some(PerfHwId::Instructions) => (0xc0, 0x00, info.is_inst_ret_ev_unavailable()),
+        Some(PerfHwId::RefCpuCycles) => (0x3c, 0x01, info.is_ref_cycle_ev_unavailable()),
+        _ => return Err(SyscallError::ENOENT),
+    };
+
+    if unavailable {
+        return Err(SyscallError::ENOENT);
+    }
+
+    if info.version_id() >= 2 {
+        // SAFETY: The register exists from version 2 onwards.
+        unsafe { io::wrmsr(IA32_PERF_GLOBAL_CTRL, io::rdmsr(IA32_PERF_GLOBAL_CTRL) | 1) }
+    }
+
+    Ok(event | (umask << 8))
+}
+
+pub struct PerfEvent {
+    /// The thread that is measured.
+    owner: Weak<Task>,
+    /// Value of the event select register while the counter is enabled.
+    select: u64,
+}
+
+impl PerfEvent {
+    /// Creates a counter for the event described by `attr`, which counts the calling thread.
+    ///
+    /// ## Errors
+    /// * `ENOENT`: The event type or the event is not supported.
+    /// * `EOPNOTSUPP`: The CPU does not support architectural performance monitoring.
+    /// * `EBUSY`: The counter is already in use.
+    pub fn new(attr: &PerfEventAttr) -> Result<Arc<Self>, SyscallError> {
+        if PerfType::from_u32(attr.typ) != Some(PerfType::Hardware) {
+            return Err(SyscallError::ENOENT);
+        }
+
+        let mut select = hardware_event(attr.config)? | PERFEVTSEL_EN;
+
+        if !attr.flags.contains(PerfAttrFlags::EXCLUDE_USER) {
+            select |= PERFEVTSEL_USR;
+        }
+
+        if !attr.flags.contains(PerfAttrFlags::EXCLUDE_KERNEL) {
+            select |= PERFEVTSEL_OS;
+        }
+
+        if COUNTER_IN_USE.swap(true, Ordering::SeqCst) {
+            return Err(SyscallError::EBUSY);
+        }
+
+        let event = Arc::new(Self {
+            owner: Arc::downgrade(&scheduler::current_thread()),
+            select,
+        });
+
+        event.reset();
+        event.set_enabled(!attr.flags.contains(PerfAttrFlags::DISABLED));
+
+        Ok(event)
+    }
+
+    /// Returns whether `owner` is the calling thread and its counter is loaded on this CPU.
+    fn is_loaded(owner: &Arc<Task>) -> bool {
+        owner.arch_task().perf_select != 0 && Arc::ptr_eq(owner, &scheduler::current_thread())
+    }
+
+    /// Starts or stops counting. The event select value of the owner is updated, and loaded
+    /// right away if the owner is the calling thread.
+    fn set_enabled(&self, enabled: bool) {
+        let Some(owner) = self.owner.upgrade() else {
+            return;
+        };
+
+        let _guard = IrqGuard::new();
+        let task = owner.arch_task_mut();
+        let current = Arc::ptr_eq(&owner, &scheduler::current_thread());
+
+        if current {
+            save(task);
+        }
+
+        task.perf_select = if enabled { self.select } else { 0 };
+
+        if current {
+            load(task);
+        }
+    }
+
+    /// Sets the counter to zero. The counter of a thread that is running on another CPU is only
+    /// read and written when that thread is switched out and in, so the value saved for it is
+    /// reset instead.
+    fn reset(&self) {
+        let Some(owner) = self.owner.upgrade() else {
+            return;
+        };
+
+        let _guard = IrqGuard::new();
+
+        if Self::is_loaded(&owner) {
+            unsafe { io::wrmsr(IA32_PMC0, 0) }
+        }
+
+        owner.arch_task_mut().perf_count = 0;
+    }
+
+    /// Returns the value of the counter. For a thread that is not the calling thread, this is the
+    /// value saved when it was last switched out.
+    fn count(&self) -> u64 {
+        let Some(owner) = self.owner.upgrade() else {
+            return 0;
+        };
+
+        let _guard = IrqGuard::new();
+
+        if Self::is_loaded(&owner) {
+            unsafe { io::rdmsr(IA32_PMC0) }
+        } else {
+            owner.arch_task().perf_count
+        }
+    }
+}
+
+impl INodeInterface for PerfEvent {
+    /// Reads the 64-bit value of the counter.
+    fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> fs::Result<usize> {
+        let value = self.count();
+        let size = core::mem::size_of::<u64>();
+
+        if buffer.len() < size {
+            return Err(FileSystemError::InvalidInput);
+        }
+
+        buffer[..size].copy_from_slice(&value.to_ne_bytes());
+        Ok(size)
+    }
+
+    fn ioctl(&self, command: usize, _arg: usize) -> fs::Result<usize> {
+        match command {
+            PERF_EVENT_IOC_ENABLE => self.set_enabled(true),
+            PERF_EVENT_IOC_DISABLE => self.set_enabled(false),
+            PERF_EVENT_IOC_RESET => self.reset(),
+            _ => return Err(FileSystemError::NotSupported),
+        }
+
+        Ok(0)
+    }
+}
+
+impl Drop for PerfEvent {
+    fn drop(&mut self) {
+        self.set_enabled(false);
+        COUNTER_IN_USE.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! System reset and halt.
+//!
+//! **Notes**: <https://wiki.osdev.org/Reboot>
+
+use crate::acpi::{aml, fadt, get_acpi_table};
+use crate::mem::paging::PhysAddr;
+
+use super::{apic, interrupts, io};
+
+/// Reset control register of the PCI host bridge.
+const PCI_RESET_PORT: u16 = 0xcf9;
+/// Command and status port of the PS/2 keyboard controller.
+const KBD_CONTROLLER_PORT: u16 = 0x64;
+/// Port of QEMU's `isa-debug-exit` device.
+const QEMU_EXIT_PORT: u16 = 0xf4;
+
+/// Address spaces of an ACPI generic address structure.
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+const ADDRESS_SPACE_IO: u8 = 1;
+
+/// Number of port I/O delays (about a microsecond each) to wait for a reset to take effect
+/// before trying the next method.
+const RESET_TIMEOUT: usize = 100_000;
+
+/// Resets the system with the ACPI reset register. Falls back to the PCI reset control register
+/// and to pulsing the reset line of the keyboard controller. Halts all of the CPUs if every
+/// method fails.
+pub fn reset() -> ! {
+    unsafe { interrupts::disable_interrupts() }
+
+    if acpi_reset() {
+        io::delay(RESET_TIMEOUT);
+        log::warn!("power: ACPI reset failed");
+    }
+
+    unsafe {
+        // Clear the reset bit first, as the reset is triggered by its rising edge. Then request a
+        // hard reset (bit 1) of the system including the CPU (bit 2).
+        io::outb(PCI_RESET_PORT, 0x02);
+        io::delay(10);
+        io::outb(PCI_RESET_PORT, 0x06);
+    }
+
+    io::delay(RESET_TIMEOUT);
+    log::warn!("power: PCI reset failed");
+
+    unsafe {
+        // Wait for the input buffer of the controller to be empty before sending the command.
+        for _ in 0..RESET_TIMEOUT {
+            if io::inb(KBD_CONTROLLER_PORT) & 0b10 == 0 {
+                break;
+            }
+        }
+
+        io::outb(KBD_CONTROLLER_PORT, 0xfe);
+    }
+
+    io::delay(RESET_TIMEOUT);
+    log::error!("power: failed to reset the system");
+
+    halt_system()
+}
+
+/// Powers off the system by entering the ACPI S5 sleep state. Falls back to the exit port of
+/// QEMU if ACPI is not enabled. Halts all of the CPUs if that fails as well.
+pub fn power_off() -> ! {
+    unsafe { interrupts::disable_interrupts() }
+
+    if let Some(subsystem) = aml::try_get_subsystem() {
+        // Writes the S5 sleep type along with `SLP_EN` to the PM1a control register.
+        subsystem.enter_state(aml::SleepState::S5);
+
+        io::delay(RESET_TIMEOUT);
+        log::warn!("power: failed to enter ACPI S5");
+    }
+
+    unsafe { io::outb(QEMU_EXIT_PORT, 0x00) }
+
+    io::delay(RESET_TIMEOUT);
+    log::error!("power: failed to power off the system");
+
+    halt_system()
+}
+
+/// Writes the reset value to the ACPI reset register. Returns `false` if the firmware does not
+/// provide one.
+fn acpi_reset() -> bool {
+    let Some(fadt) = get_acpi_table().lookup_entry(fadt::SIGNATURE, 0) else {
+        return false;
+    };
+
+    let fadt: &'static fadt::Fadt = unsafe { fadt.as_ref() };
+    let Some((register, value)) = fadt.reset_register() else {
+        return false;
+    };
+
+    let address = register.address;
+
+    match register.address_space {
+        ADDRESS_SPACE_IO => unsafe { io::outb(address as u16, value) },
+        ADDRESS_SPACE_MEMORY => unsafe {
+            let ptr = PhysAddr::new(address).as_hhdm_virt().as_mut_ptr::<u8>();
+            ptr.write_volatile(value);
+        },
+
+        space => {
+            log::warn!("power: unsupported address space of the ACPI reset register ({space})");
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Halts all of the CPUs. The system stays powered on.
+pub fn halt_system() -> ! {
+    unsafe { interrupts::disable_interrupts() }
+    apic::halt_others();
+
+    halt()
+}
+
+/// Halts the current CPU with interrupts and the scheduler timer disabled. The other CPUs keep
+/// running; use [`halt_system`] to halt all of them.
+pub fn halt() -> ! {
+    unsafe { interrupts::disable_interrupts() }
+    apic::get_local_apic().timer_stop();
+
+    loop {
+        unsafe { interrupts::halt() }
+    }
+}
@@ -15,11 +15,12 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::signal::{SigProcMask, SignalFlags};
+use aero_syscall::signal::{SigInfo, SigProcMask, SignalFlags};
 use aero_syscall::SyscallError;
 
 use crate::userland;
 use crate::userland::scheduler;
+use crate::userland::signals::SignalEntry;
 use crate::utils::StackHelper;
 
 use super::interrupts::InterruptStack;
@@ -68,6 +69,51 @@ impl SignalFrame {
     }
 }
 
+/// Sets up `stack` to run the handler `func` of `entry` for `signal`. The handler returns to
+/// the `sigreturn` trampoline, which restores `signal_frame`.
+fn enter_handler(
+    stack: &mut InterruptStack,
+    func: extern "C" fn(usize),
+    signal: usize,
+    entry: &SignalEntry,
+    info: &SigInfo,
+    signal_frame: SignalFrame,
+) {
+    // We cannot straight away update the stack pointer from the stack
+    // helper, since it will created a reference to a packed field which
+    // is undefined behavior. So we create a copy of the current rsp and
+    // update the actual rsp with the updated rsp.
+    let mut ptr = stack.iret.rsp;
+    let mut writer = StackHelper::new(&mut ptr);
+
+    // Signal handlers are executed on the same stack, but 128 bytes
+    // known as the red zone is subtracted from the stack before
+    // anything is pushed to the stack. This allows small leaf
+    // functions to use 128 bytes of stack space without reserving
+    // stack space by subtracting from the stack pointer.
+    writer.skip_by(REDZONE_SIZE);
+
+    // Handlers installed with `SA_SIGINFO` are called as `handler(signal, info, context)`. The
+    // context is not provided.
+    let info = if entry.flags().contains(SignalFlags::SA_SIGINFO) {
+        unsafe { writer.write(*info) };
+        writer.top()
+    } else {
+        0
+    };
+
+    unsafe {
+        writer.write(signal_frame);
+        writer.write(entry.sigreturn());
+    }
+
+    stack.iret.rsp = ptr;
+    stack.iret.rip = func as u64;
+    stack.scratch.rdi = signal as u64;
+    stack.scratch.rsi = info;
+    stack.scratch.rdx = 0;
+}
+
 pub fn interrupt_check_signals(stack: &mut InterruptStack) {
     // SAFETY: If this interrupt did not originate from userland then we cannot
     // check for signals since the scheduler might not be initialized.
@@ -75,7 +121,7 @@ pub fn interrupt_check_signals(stack: &mut InterruptStack) {
         return;
     }
 
-    if let Some((signal, entry)) = userland::signals::check_for_signals() {
+    if let Some((signal, entry, info)) = userland::signals::check_for_signals(stack) {
         if let aero_syscall::signal::SignalHandler::Handle(func) = entry.handler() {
             let task = scheduler::get_scheduler().current_task();
 
@@ -85,43 +131,35 @@ pub fn interrupt_check_signals(stack: &mut InterruptStack) {
             let signal_frame = SignalFrame::from_interrupt(stack, old_mask);
             signals.set_mask(SigProcMask::Block, Some(1u64 << signal), None);
 
-            // We cannot straight away update the stack pointer from the stack
-            // helper, since it will created a reference to a packed field which
-            // is undefined behavior. So we create a copy of the current rsp and
-            // update the actual rsp with the updated rsp.
-            let mut ptr = stack.iret.rsp;
-            let mut writer = StackHelper::new(&mut ptr);
-
-            // Signal handlers are executed on the same stack, but 128 bytes
-            // known as the red zone is subtracted from the stack before
-            // anything is pushed to the stack. This allows small leaf
-            // functions to use 128 bytes of stack space without reserving
-            // stack space by subtracting from the stack pointer.
-            writer.skip_by(REDZONE_SIZE);
-
-            unsafe {
-                writer.write(signal_frame);
-                writer.write(entry.sigreturn());
-            }
-
-            stack.iret.rsp = ptr;
-            stack.iret.rip = func as u64;
-            stack.scratch.rdi = signal as u64;
+            enter_handler(stack, func, signal, &entry, &info, signal_frame);
         }
     }
 }
 
-pub fn syscall_check_signals(syscall_result: isize, stack: &mut InterruptStack) {
-    if let Some((signal, entry)) = userland::signals::check_for_signals() {
+/// Delivers the pending signals on return from a system call, and returns the value of `RAX` to
+/// return to userland with.
+///
+/// A system call that failed with [`SyscallError::ERESTARTSYS`] is restarted once the handler
+/// returns if it was installed with `SA_RESTART`, or right away if no handler runs. Otherwise, it
+/// fails with [`SyscallError::EINTR`].
+pub fn syscall_check_signals(syscall_result: isize, stack: &mut InterruptStack) -> u64 {
+    let restartable =
+        aero_syscall::isize_as_syscall_result(syscall_result) == Err(SyscallError::ERESTARTSYS);
+
+    let syscall_result = if restartable {
+        -(SyscallError::EINTR as isize)
+    } else {
+        syscall_result
+    };
+
+    if let Some((signal, entry, info)) = userland::signals::check_for_signals(stack) {
         if let aero_syscall::signal::SignalHandler::Handle(func) = entry.handler() {
             let task = scheduler::get_scheduler().current_task();
 
             let signals = task.signals();
             let old_mask = signals.blocked_mask();
 
-            let syscall_rresult = aero_syscall::isize_as_syscall_result(syscall_result);
-            let restart_syscall = syscall_rresult == Err(SyscallError::EINTR)
-                && entry.flags().contains(SignalFlags::SA_RESTART);
+            let restart_syscall = restartable && entry.flags().contains(SignalFlags::SA_RESTART);
 
             #[cfg(feature = "syslog")]
             log::warn!("syscall routine signaled: (restart={restart_syscall})");
@@ -130,30 +168,19 @@ pub fn syscall_check_signals(syscall_result: isize, stack: &mut InterruptStack)
                 SignalFrame::from_syscall(restart_syscall, syscall_result as _, stack, old_mask);
             signals.set_mask(SigProcMask::Block, Some(1u64 << signal), None);
 
-            // We cannot straight away update the stack pointer from the stack
-            // helper, since it will created a reference to a packed field which
-            // is undefined behavior. So we create a copy of the current rsp and
-            // update the actual rsp with the updated rsp.
-            let mut ptr = stack.iret.rsp;
-            let mut writer = StackHelper::new(&mut ptr);
-
-            // Signal handlers are executed on the same stack, but 128 bytes
-            // known as the red zone is subtracted from the stack before
-            // anything is pushed to the stack. This allows small leaf
-            // functions to use 128 bytes of stack space without reserving
-            // stack space by subtracting from the stack pointer.
-            writer.skip_by(REDZONE_SIZE);
-
-            unsafe {
-                writer.write(signal_frame);
-                writer.write(entry.sigreturn());
-            }
-
-            stack.iret.rsp = ptr;
-            stack.iret.rip = func as u64;
-            stack.scratch.rdi = signal as u64;
+            enter_handler(stack, func, signal, &entry, &info, signal_frame);
+            return syscall_result as u64;
         }
     }
+
+    if restartable {
+        // The signal was handled by its default action (e.g. stopping the task), so the system
+        // call is restarted transparently. `RAX` still holds the system call number.
+        stack.iret.rip -= SYSCALL_INSTRUCTION_SIZE;
+        return stack.scratch.rax;
+    }
+
+    syscall_result as u64
 }
 
 pub fn sigreturn(stack: &mut InterruptStack) {
@@ -1,4 +1,4 @@
-use aero_syscall::SyscallError;
+use aero_syscall::{SyscallError, ARCH_GET_FS, ARCH_GET_GS, ARCH_SET_FS, ARCH_SET_GS};
 use raw_cpuid::CpuId;
 
 use crate::arch::gdt::{GdtEntryIndex, Tss, USER_CS, USER_SS};
@@ -11,11 +11,6 @@ use super::{asm_macros, io};
 
 use core::mem::offset_of;
 
-const ARCH_SET_GS: usize = 0x1001;
-const ARCH_SET_FS: usize = 0x1002;
-const ARCH_GET_FS: usize = 0x1003;
-const ARCH_GET_GS: usize = 0x1004;
-
 /// 64-bit SYSCALL instruction entry point.
 ///
 /// The instruction supports to to 6 arguments in registers.
@@ -160,6 +155,14 @@ unsafe extern "C" fn x86_64_sysenter_handler() {
 }
 
 fn arch_prctl(command: usize, address: usize) -> Result<usize, SyscallError> {
+    // Loading a non-canonical or kernel address into the segment base would fault or leak the
+    // kernel mappings to the process.
+    if matches!(command, ARCH_SET_FS | ARCH_SET_GS)
+        && address as u64 > super::task::userland_last_address().as_u64()
+    {
+        return Err(SyscallError::EPERM);
+    }
+
     match command {
         ARCH_SET_FS => unsafe {
             let _guard = IrqGuard::new();
@@ -219,15 +222,7 @@ pub(super) extern "sysv64" fn x86_64_check_sysenter(stack: &mut InterruptErrorSt
 pub(super) extern "C" fn x86_64_do_syscall(stack: &mut InterruptErrorStack) {
     let stack = &mut stack.stack;
 
-    let syscall_number = stack.scratch.rax as usize; // syscall number
-    let a = stack.scratch.rdi as usize; // argument 1
-    let b = stack.scratch.rsi as usize; // argument 2
-    let c = stack.scratch.rdx as usize; // argument 3
-    let d = stack.scratch.r10 as usize; // argument 4
-    let e = stack.scratch.r8 as usize; // argument 5
-    let f = stack.scratch.r9 as usize; // argument 6
-
-    match syscall_number {
+    match stack.scratch.rax as usize {
         // handle arch-specific syscalls (`sigreturn` and `arch_prctl`):
         aero_syscall::prelude::SYS_SIGRETURN => {
             super::signals::sigreturn(stack);
@@ -235,7 +230,7 @@ pub(super) extern "C" fn x86_64_do_syscall(stack: &mut InterruptErrorStack) {
         }
 
         aero_syscall::prelude::SYS_ARCH_PRCTL => {
-            let result = self::arch_prctl(a, b);
+            let result = self::arch_prctl(stack.scratch.rdi as usize, stack.scratch.rsi as usize);
             let result_usize = aero_syscall::syscall_result_as_usize(result);
 
             stack.scratch.rax = result_usize as _;
@@ -246,10 +241,32 @@ pub(super) extern "C" fn x86_64_do_syscall(stack: &mut InterruptErrorStack) {
         _ => unsafe { super::interrupts::enable_interrupts() },
     }
 
+    // The tracer may inspect or change the arguments at the syscall-entry stop, so they are read
+    // from the frame afterwards.
+    scheduler::get_scheduler()
+        .current_task()
+        .ptrace_syscall_stop(stack);
+
+    let syscall_number = stack.scratch.rax as usize; // syscall number
+    let a = stack.scratch.rdi as usize; // argument 1
+    let b = stack.scratch.rsi as usize; // argument 2
+    let c = stack.scratch.rdx as usize; // argument 3
+    let d = stack.scratch.r10 as usize; // argument 4
+    let e = stack.scratch.r8 as usize; // argument 5
+    let f = stack.scratch.r9 as usize; // argument 6
+
     let result_usize = crate::syscall::generic_do_syscall(syscall_number, a, b, c, d, e, f);
 
-    super::signals::syscall_check_signals(result_usize as isize, stack);
+    // The result is reported in `RAX` at the syscall-exit stop. The syscall number is restored
+    // afterwards since it is needed to restart the syscall.
     stack.scratch.rax = result_usize as _;
+    scheduler::get_scheduler()
+        .current_task()
+        .ptrace_syscall_stop(stack);
+
+    let result_usize = core::mem::replace(&mut stack.scratch.rax, syscall_number as _) as usize;
+
+    stack.scratch.rax = super::signals::syscall_check_signals(result_usize as isize, stack);
 }
 
 /// Initializes support for the `syscall` and `sysret` instructions for the
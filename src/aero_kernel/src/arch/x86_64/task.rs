@@ -30,22 +30,21 @@
 //! does not have to worry about clobbering the user mode register values since
 //! they are safely stored on the kernel stack.
 
-use alloc::alloc::alloc_zeroed;
-
 use aero_syscall::{MMapFlags, MMapProt};
 use alloc::vec::Vec;
 use raw_cpuid::CpuId;
 
-use core::alloc::Layout;
 use core::ptr::Unique;
 
 use crate::arch::interrupts::InterruptErrorStack;
 use crate::fs::cache::DirCacheItem;
+use crate::mem::kstack::{KernelStack, KERNEL_STACK_SIZE};
 use crate::mem::paging::*;
 use crate::syscall::ExecArgs;
 use crate::userland::vm::Vm;
 use crate::utils::StackHelper;
 
+use super::debugregs::DebugRegisters;
 use super::{asm_macros, controlregs, io};
 
 use crate::mem::AddressSpace;
@@ -101,16 +100,17 @@ pub fn userland_last_address() -> VirtAddr {
     })
 }
 
-/// Returns whether the given pointer is within the userland address space.
-pub fn user_access_ok<T>(ptr: *const T) -> bool {
-    let size = core::mem::size_of::<T>();
-    VirtAddr::new(ptr as u64 + size as u64) <= super::task::userland_last_address()
+/// Returns whether the `size` bytes starting at `ptr` are within the userland address space.
+pub fn user_range_ok(ptr: *const u8, size: usize) -> bool {
+    (ptr as u64)
+        .checked_add(size as u64)
+        .is_some_and(|end| VirtAddr::new(end) <= userland_last_address())
 }
 
 const USERLAND_STACK_SIZE: u64 = 0x64000;
 
 //(1 << 47) - (Size4KiB::SIZE * 2)
-const USERLAND_STACK_TOP: VirtAddr = VirtAddr::new(0x7fffffffe000);
+pub const USERLAND_STACK_TOP: VirtAddr = VirtAddr::new(0x7fffffffe000);
 const USERLAND_STACK_BOTTOM: VirtAddr = USERLAND_STACK_TOP.const_sub_u64(USERLAND_STACK_SIZE);
 
 #[naked]
@@ -193,19 +193,34 @@ pub struct ArchTask {
     context: Unique<Context>,
 
     address_space: AddressSpace,
+    kernel_stack: Option<KernelStack>,
     context_switch_rsp: VirtAddr,
     user: bool,
 
     fs_base: VirtAddr,
     gs_base: VirtAddr,
 
+    /// Value of the performance counter event select register while the task runs. Zero if the
+    /// task is not measured; see [`super::perf`].
+    pub perf_select: u64,
+    /// Value of the performance counter, saved while the task is switched out or its counter is
+    /// disabled.
+    pub perf_count: u64,
+    /// Hardware watchpoints of the task, set by its tracer.
+    pub debug_regs: DebugRegisters,
+
     pub fpu_storage: Option<FpuState>,
+
+    /// Locks held by the task while it is switched out.
+    #[cfg(feature = "lockdep")]
+    held_locks: crate::utils::lockdep::HeldLocks,
 }
 
 impl ArchTask {
     pub fn new_idle() -> Self {
         Self {
             context: Unique::dangling(),
+            kernel_stack: None,
             context_switch_rsp: VirtAddr::zero(),
 
             // Since the IDLE task is a special kernel task, we use the kernel's
@@ -215,18 +230,20 @@ impl ArchTask {
 
             fs_base: VirtAddr::zero(),
             gs_base: VirtAddr::zero(),
+            perf_select: 0,
+            perf_count: 0,
+            debug_regs: DebugRegisters::default(),
 
             fpu_storage: None,
+
+            #[cfg(feature = "lockdep")]
+            held_locks: crate::utils::lockdep::HeldLocks::new(),
         }
     }
 
     pub fn new_kernel(entry_point: VirtAddr, enable_interrupts: bool) -> Self {
-        let switch_stack = Self::alloc_switch_stack().unwrap().as_mut_ptr::<u8>();
-
-        let task_stack = unsafe {
-            let layout = Layout::from_size_align_unchecked(4096 * 16, 0x1000);
-            alloc_zeroed(layout).add(layout.size())
-        };
+        let kernel_stack = KernelStack::alloc().unwrap();
+        let switch_stack = kernel_stack.top().as_mut_ptr::<u8>();
 
         let address_space = AddressSpace::this();
 
@@ -238,7 +255,9 @@ impl ArchTask {
         kframe.stack.iret.ss = 0x10; // kernel stack segment
         kframe.stack.iret.cs = 0x08; // kernel code segment
         kframe.stack.iret.rip = entry_point.as_u64();
-        kframe.stack.iret.rsp = task_stack as u64;
+        // Kernel tasks never enter the kernel from userland, so the switch frame is popped
+        // before the task starts and it runs on the same stack.
+        kframe.stack.iret.rsp = switch_stack as u64;
         kframe.stack.iret.rflags = if enable_interrupts { 0x200 } else { 0x00 };
 
         let context = unsafe { stack.offset::<Context>() };
@@ -250,13 +269,20 @@ impl ArchTask {
         Self {
             context: unsafe { Unique::new_unchecked(context) },
             address_space,
+            kernel_stack: Some(kernel_stack),
             context_switch_rsp: VirtAddr::new(switch_stack as u64),
             user: false,
 
             fs_base: VirtAddr::zero(),
             gs_base: VirtAddr::zero(),
+            perf_select: 0,
+            perf_count: 0,
+            debug_regs: DebugRegisters::default(),
 
             fpu_storage: None,
+
+            #[cfg(feature = "lockdep")]
+            held_locks: crate::utils::lockdep::HeldLocks::new(),
         }
     }
 
@@ -270,7 +296,8 @@ impl ArchTask {
         assert!(self.user, "cannot clone a kernel task");
 
         let address_space = AddressSpace::this();
-        let switch_stack = Self::alloc_switch_stack()?.as_mut_ptr::<u8>();
+        let kernel_stack = KernelStack::alloc()?;
+        let switch_stack = kernel_stack.top().as_mut_ptr::<u8>();
 
         let mut new_stack_ptr = switch_stack as u64;
         let mut new_stack = StackHelper::new(&mut new_stack_ptr);
@@ -301,15 +328,22 @@ impl ArchTask {
 
         Ok(Self {
             context: unsafe { Unique::new_unchecked(context) },
+            kernel_stack: Some(kernel_stack),
             context_switch_rsp: VirtAddr::new(switch_stack as u64),
             address_space,
             user: true,
 
             // The FS and GS bases are inherited from the parent process.
-            fs_base: VirtAddr::new(1),
+            fs_base: self.fs_base,
             gs_base: self.gs_base,
+            perf_select: 0,
+            perf_count: 0,
+            debug_regs: DebugRegisters::default(),
 
             fpu_storage: Some(fpu_storage),
+
+            #[cfg(feature = "lockdep")]
+            held_locks: crate::utils::lockdep::HeldLocks::new(),
         })
     }
 
@@ -323,7 +357,8 @@ impl ArchTask {
             asm!("mov cr3, {}", in(reg) controlregs::read_cr3_raw(), options(nostack));
         }
 
-        let switch_stack = Self::alloc_switch_stack()?.as_mut_ptr::<u8>();
+        let kernel_stack = KernelStack::alloc()?;
+        let switch_stack = kernel_stack.top().as_mut_ptr::<u8>();
 
         let mut old_stack_ptr = self.context_switch_rsp.as_u64();
         let mut old_stack = StackHelper::new(&mut old_stack_ptr);
@@ -350,6 +385,7 @@ impl ArchTask {
 
         Ok(Self {
             context: unsafe { Unique::new_unchecked(context) },
+            kernel_stack: Some(kernel_stack),
             context_switch_rsp: VirtAddr::new(switch_stack as u64),
             address_space,
             user: true,
@@ -357,8 +393,14 @@ impl ArchTask {
             // The FS and GS bases are inherited from the parent process.
             fs_base: self.fs_base,
             gs_base: self.gs_base,
+            perf_select: 0,
+            perf_count: 0,
+            debug_regs: DebugRegisters::default(),
 
             fpu_storage: Some(fpu_storage),
+
+            #[cfg(feature = "lockdep")]
+            held_locks: crate::utils::lockdep::HeldLocks::new(),
         })
     }
 
@@ -402,6 +444,10 @@ impl ArchTask {
         self.fs_base = VirtAddr::zero();
         self.gs_base = VirtAddr::zero();
 
+        // Watchpoints refer to the old image.
+        self.debug_regs = DebugRegisters::default();
+        self.debug_regs.load();
+
         let mut fpu_storage = FpuState::default();
 
         // unsafe {
@@ -497,14 +543,6 @@ impl ArchTask {
         Ok(())
     }
 
-    /// Allocates a new context switch stack for the process and returns the stack
-    /// top address. See the module level documentation for more information.
-    fn alloc_switch_stack() -> Result<VirtAddr, MapToError<Size4KiB>> {
-        let frame = FRAME_ALLOCATOR.alloc_zeroed(4096 * 4).unwrap();
-
-        Ok(frame.as_hhdm_virt() + (4096u64 * 4))
-    }
-
     fn unref_pt(&mut self) {
         assert!(self.user);
 
@@ -530,16 +568,27 @@ impl ArchTask {
             self.unref_pt();
         }
 
-        // deallocate the switch stack
-        {
-            let frame: PhysFrame<Size4KiB> = PhysFrame::containing_address(
-                (self.context_switch_rsp - Size4KiB::SIZE).as_hhdm_phys(),
-            );
-
-            FRAME_ALLOCATOR.deallocate_frame(frame);
+        if let Some(kernel_stack) = self.kernel_stack.take() {
+            kernel_stack.dealloc();
         }
     }
 
+    /// Returns the kernel stack of this task. The IDLE task runs on the boot stack and does
+    /// not have one.
+    pub fn kernel_stack(&self) -> Option<&KernelStack> {
+        self.kernel_stack.as_ref()
+    }
+
+    /// Returns whether this task runs a userland program.
+    pub fn is_user(&self) -> bool {
+        self.user
+    }
+
+    /// Returns the address space of this task.
+    pub fn address_space_mut(&mut self) -> &mut AddressSpace {
+        &mut self.address_space
+    }
+
     /// Returns the saved GS base for this task.
     pub fn get_gs_base(&self) -> VirtAddr {
         self.gs_base
@@ -679,6 +728,21 @@ pub fn arch_task_spinup(from: &mut ArchTask, to: &ArchTask) {
             xrstor(fpu);
         }
 
+        if let Some(used) = from
+            .kernel_stack
+            .as_mut()
+            .and_then(KernelStack::check_usage)
+        {
+            log::warn!(
+                "kernel stack at {:#x} has used {used} of {KERNEL_STACK_SIZE} bytes",
+                from.context_switch_rsp
+            );
+        }
+
+        // The stack of the new thread is used before the CR3 reload in `task_spinup`, so stale
+        // translations of a reused stack slot have to be flushed first.
+        crate::mem::kstack::flush_stale();
+
         // Load the new thread's kernel stack pointer everywhere it's needed.
         let kstackp = to.context_switch_rsp.as_u64();
         super::gdt::TSS.rsp[0] = kstackp;
@@ -691,6 +755,18 @@ pub fn arch_task_spinup(from: &mut ArchTask, to: &ArchTask) {
         io::set_fsbase(to.fs_base);
         io::set_inactive_gsbase(to.gs_base);
 
+        // The performance counter only runs while the task that owns it does.
+        if from.perf_select != to.perf_select {
+            super::perf::switch(from, to);
+        }
+
+        if from.debug_regs.is_active() || to.debug_regs.is_active() {
+            to.debug_regs.load();
+        }
+
+        #[cfg(feature = "lockdep")]
+        crate::utils::lockdep::switch(&mut from.held_locks, &to.held_locks);
+
         task_spinup(&mut from.context, to.context.as_ref());
     }
 }
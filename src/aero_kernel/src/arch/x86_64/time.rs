@@ -36,6 +36,10 @@ use crate::utils::sync::Mutex;
 const PIT_FREQUENCY_HZ: usize = 1000;
 pub const PIT_DIVIDEND: usize = 1193182;
 
+/// Frequency of the time stamp counter in Hz, measured against the PIT while the local APIC
+/// timer is calibrated.
+pub static TSC_FREQUENCY: AtomicUsize = AtomicUsize::new(0);
+
 static UPTIME_RAW: AtomicUsize = AtomicUsize::new(0);
 static UPTIME_SEC: AtomicUsize = AtomicUsize::new(0);
 
@@ -49,10 +53,43 @@ pub fn get_uptime_ticks() -> usize {
     UPTIME_SEC.load(Ordering::SeqCst)
 }
 
+/// Returns the time elapsed since the timer was initialized, in milliseconds.
+pub fn get_uptime_ms() -> usize {
+    UPTIME_RAW.load(Ordering::Relaxed) * 1000 / PIT_FREQUENCY_HZ
+}
+
 pub fn get_realtime_clock() -> TimeSpec {
     REALTIME_CLOCK.lock_irq().clone()
 }
 
+/// Returns the estimated frequency of the time stamp counter in kHz.
+pub fn tsc_khz() -> usize {
+    TSC_FREQUENCY.load(Ordering::Relaxed) / 1000
+}
+
+/// Returns the time elapsed since the CPU was reset, in nanoseconds, measured with the time stamp
+/// counter. It is the clock of the kernel timers and reads zero until the local APIC timer is
+/// calibrated.
+pub fn get_uptime_ns() -> u64 {
+    let frequency = TSC_FREQUENCY.load(Ordering::Relaxed) as u128;
+
+    if frequency == 0 {
+        return 0;
+    }
+
+    let tsc = unsafe { core::arch::x86_64::_rdtsc() } as u128;
+    (tsc * 1_000_000_000 / frequency) as u64
+}
+
+/// Returns the value of the time stamp counter at `ns` nanoseconds of [`get_uptime_ns`], rounded
+/// up so that it is not reached before.
+pub fn tsc_at(ns: u64) -> u64 {
+    let frequency = TSC_FREQUENCY.load(Ordering::Relaxed) as u128;
+    let tsc = (ns as u128 * frequency).div_ceil(1_000_000_000);
+
+    tsc.min(u64::MAX as u128) as u64
+}
+
 /// Returns the current amount of PIT ticks.
 pub fn get_current_count() -> u16 {
     unsafe {
@@ -109,7 +146,6 @@ fn pit_irq_handler(_stack: &mut InterruptStack) {
 
     if value % PIT_FREQUENCY_HZ == 0 {
         UPTIME_SEC.fetch_add(1, Ordering::Relaxed); // Increment uptime seconds
-        crate::syscall::time::check_timers();
     }
 }
 
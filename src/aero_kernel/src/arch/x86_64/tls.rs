@@ -27,40 +27,18 @@ use alloc::vec::Vec;
 
 use crate::utils::sync::Mutex;
 
-use raw_cpuid::FeatureInfo;
+use raw_cpuid::{CpuId, ExtendedFeatures, ExtendedProcessorFeatureIdentifiers, FeatureInfo};
 
 type ProcFsCpuFeature = (&'static str, fn(&FeatureInfo) -> bool);
-
+type ProcFsExtendedFeature = (&'static str, fn(&ExtendedFeatures) -> bool);
+type ProcFsExtendedProcessorFeature = (
+    &'static str,
+    fn(&ExtendedProcessorFeatureIdentifiers) -> bool,
+);
+
+// The features are named and ordered like in the `flags` line of Linux's `/proc/cpuinfo`, as
+// programs look them up by name.
 const CPU_FEATURES: &[ProcFsCpuFeature] = &[
-    ("sse3", FeatureInfo::has_sse3),
-    ("pclmulqdq", FeatureInfo::has_pclmulqdq),
-    ("ds_area", FeatureInfo::has_ds_area),
-    ("monitor_mwait", FeatureInfo::has_monitor_mwait),
-    ("cpl", FeatureInfo::has_cpl),
-    ("vmx", FeatureInfo::has_vmx),
-    ("smx", FeatureInfo::has_smx),
-    ("eist", FeatureInfo::has_eist),
-    ("tm2", FeatureInfo::has_tm2),
-    ("ssse3", FeatureInfo::has_ssse3),
-    ("cnxtid", FeatureInfo::has_cnxtid),
-    ("fma", FeatureInfo::has_fma),
-    ("cmpxchg16b", FeatureInfo::has_cmpxchg16b),
-    ("pdcm", FeatureInfo::has_pdcm),
-    ("pcid", FeatureInfo::has_pcid),
-    ("dca", FeatureInfo::has_dca),
-    ("sse41", FeatureInfo::has_sse41),
-    ("sse42", FeatureInfo::has_sse42),
-    ("x2apic", FeatureInfo::has_x2apic),
-    ("movbe", FeatureInfo::has_movbe),
-    ("popcnt", FeatureInfo::has_popcnt),
-    ("tsc_deadline", FeatureInfo::has_tsc_deadline),
-    ("aesni", FeatureInfo::has_aesni),
-    ("xsave", FeatureInfo::has_xsave),
-    ("oxsave", FeatureInfo::has_oxsave),
-    ("avx", FeatureInfo::has_avx),
-    ("f16c", FeatureInfo::has_f16c),
-    ("rdrand", FeatureInfo::has_rdrand),
-    ("hypervisor", FeatureInfo::has_hypervisor),
     ("fpu", FeatureInfo::has_fpu),
     ("vme", FeatureInfo::has_vme),
     ("de", FeatureInfo::has_de),
@@ -69,71 +47,170 @@ const CPU_FEATURES: &[ProcFsCpuFeature] = &[
     ("msr", FeatureInfo::has_msr),
     ("pae", FeatureInfo::has_pae),
     ("mce", FeatureInfo::has_mce),
-    ("cmpxchg8b", FeatureInfo::has_cmpxchg8b),
+    ("cx8", FeatureInfo::has_cmpxchg8b),
     ("apic", FeatureInfo::has_apic),
-    ("sysenter_sysexit", FeatureInfo::has_sysenter_sysexit),
+    ("sep", FeatureInfo::has_sysenter_sysexit),
     ("mtrr", FeatureInfo::has_mtrr),
     ("pge", FeatureInfo::has_pge),
     ("mca", FeatureInfo::has_mca),
     ("cmov", FeatureInfo::has_cmov),
     ("pat", FeatureInfo::has_pat),
     ("pse36", FeatureInfo::has_pse36),
-    ("psn", FeatureInfo::has_psn),
+    ("pn", FeatureInfo::has_psn),
     ("clflush", FeatureInfo::has_clflush),
-    ("ds", FeatureInfo::has_ds),
+    ("dts", FeatureInfo::has_ds),
     ("acpi", FeatureInfo::has_acpi),
     ("mmx", FeatureInfo::has_mmx),
-    ("fxsave_fxstor", FeatureInfo::has_fxsave_fxstor),
+    ("fxsr", FeatureInfo::has_fxsave_fxstor),
     ("sse", FeatureInfo::has_sse),
     ("sse2", FeatureInfo::has_sse2),
     ("ss", FeatureInfo::has_ss),
-    ("htt", FeatureInfo::has_htt),
+    ("ht", FeatureInfo::has_htt),
     ("tm", FeatureInfo::has_tm),
     ("pbe", FeatureInfo::has_pbe),
+    ("pni", FeatureInfo::has_sse3),
+    ("pclmulqdq", FeatureInfo::has_pclmulqdq),
+    ("dtes64", FeatureInfo::has_ds_area),
+    ("monitor", FeatureInfo::has_monitor_mwait),
+    ("ds_cpl", FeatureInfo::has_cpl),
+    ("vmx", FeatureInfo::has_vmx),
+    ("smx", FeatureInfo::has_smx),
+    ("est", FeatureInfo::has_eist),
+    ("tm2", FeatureInfo::has_tm2),
+    ("ssse3", FeatureInfo::has_ssse3),
+    ("cid", FeatureInfo::has_cnxtid),
+    ("fma", FeatureInfo::has_fma),
+    ("cx16", FeatureInfo::has_cmpxchg16b),
+    ("pdcm", FeatureInfo::has_pdcm),
+    ("pcid", FeatureInfo::has_pcid),
+    ("dca", FeatureInfo::has_dca),
+    ("sse4_1", FeatureInfo::has_sse41),
+    ("sse4_2", FeatureInfo::has_sse42),
+    ("x2apic", FeatureInfo::has_x2apic),
+    ("movbe", FeatureInfo::has_movbe),
+    ("popcnt", FeatureInfo::has_popcnt),
+    ("tsc_deadline_timer", FeatureInfo::has_tsc_deadline),
+    ("aes", FeatureInfo::has_aesni),
+    ("xsave", FeatureInfo::has_xsave),
+    ("osxsave", FeatureInfo::has_oxsave),
+    ("avx", FeatureInfo::has_avx),
+    ("f16c", FeatureInfo::has_f16c),
+    ("rdrand", FeatureInfo::has_rdrand),
+    ("hypervisor", FeatureInfo::has_hypervisor),
+];
+
+const CPU_EXTENDED_PROCESSOR_FEATURES: &[ProcFsExtendedProcessorFeature] = &[
+    (
+        "syscall",
+        ExtendedProcessorFeatureIdentifiers::has_syscall_sysret,
+    ),
+    (
+        "nx",
+        ExtendedProcessorFeatureIdentifiers::has_execute_disable,
+    ),
+    (
+        "pdpe1gb",
+        ExtendedProcessorFeatureIdentifiers::has_1gib_pages,
+    ),
+    ("rdtscp", ExtendedProcessorFeatureIdentifiers::has_rdtscp),
+    ("lm", ExtendedProcessorFeatureIdentifiers::has_64bit_mode),
+    (
+        "lahf_lm",
+        ExtendedProcessorFeatureIdentifiers::has_lahf_sahf,
+    ),
+    ("abm", ExtendedProcessorFeatureIdentifiers::has_lzcnt),
 ];
 
+const CPU_EXTENDED_FEATURES: &[ProcFsExtendedFeature] = &[
+    ("fsgsbase", ExtendedFeatures::has_fsgsbase),
+    ("bmi1", ExtendedFeatures::has_bmi1),
+    ("avx2", ExtendedFeatures::has_avx2),
+    ("smep", ExtendedFeatures::has_smep),
+    ("bmi2", ExtendedFeatures::has_bmi2),
+    ("erms", ExtendedFeatures::has_rep_movsb_stosb),
+    ("invpcid", ExtendedFeatures::has_invpcid),
+    ("avx512f", ExtendedFeatures::has_avx512f),
+    ("adx", ExtendedFeatures::has_adx),
+    ("smap", ExtendedFeatures::has_smap),
+    ("sha_ni", ExtendedFeatures::has_sha),
+    ("umip", ExtendedFeatures::has_umip),
+    ("la57", ExtendedFeatures::has_la57),
+];
+
+/// Information about the online CPUs, in the order they came online.
 static CPU_INFO: Mutex<Vec<CpuInfo>> = Mutex::new(Vec::new());
 
 pub struct CpuInfo {
     pub cpuid: usize,
+    pub apic_id: u8,
 
     pub fpu: bool,
     pub vendor: Option<String>,
     pub brand: Option<String>,
-    pub features: Vec<&'static &'static str>,
+
+    pub family: u8,
+    pub model: u8,
+    pub stepping: u8,
+
+    pub features: Vec<&'static str>,
 }
 
 pub fn get_cpuid() -> usize {
     0
 }
 
-pub fn init() {
-    let cpuid = raw_cpuid::CpuId::new();
+/// Records the information of the current CPU, which is online from now on.
+pub fn init(cpu_id: usize) {
+    let cpuid = CpuId::new();
+    let feature_info = cpuid.get_feature_info();
+
+    let mut features = Vec::new();
 
-    let features = cpuid
-        .get_feature_info()
-        .map(|cpu_features| {
+    if let Some(info) = feature_info.as_ref() {
+        features.extend(
             CPU_FEATURES
                 .iter()
-                .filter(|(_, check_fn)| (check_fn)(&cpu_features))
-                .map(|(name, _)| name)
-                .collect::<Vec<_>>()
-        })
-        .unwrap_or_default();
+                .filter(|(_, check_fn)| (check_fn)(info))
+                .map(|(name, _)| *name),
+        );
+    }
+
+    if let Some(info) = cpuid.get_extended_processor_and_feature_identifiers() {
+        features.extend(
+            CPU_EXTENDED_PROCESSOR_FEATURES
+                .iter()
+                .filter(|(_, check_fn)| (check_fn)(&info))
+                .map(|(name, _)| *name),
+        );
+    }
+
+    if let Some(info) = cpuid.get_extended_feature_info() {
+        features.extend(
+            CPU_EXTENDED_FEATURES
+                .iter()
+                .filter(|(_, check_fn)| (check_fn)(&info))
+                .map(|(name, _)| *name),
+        );
+    }
 
     CPU_INFO.lock().push(CpuInfo {
-        cpuid: 0,
+        cpuid: cpu_id,
+        apic_id: feature_info
+            .as_ref()
+            .map_or(0, |e| e.initial_local_apic_id()),
 
-        fpu: cpuid
-            .get_feature_info()
-            .map(|e| e.has_fpu())
-            .unwrap_or(false),
+        fpu: feature_info.as_ref().is_some_and(|e| e.has_fpu()),
 
-        features,
         vendor: cpuid.get_vendor_info().map(|e| String::from(e.as_str())),
         brand: cpuid
             .get_processor_brand_string()
-            .map(|e| String::from(e.as_str())),
+            .map(|e| String::from(e.as_str().trim())),
+
+        family: feature_info.as_ref().map_or(0, |e| e.family_id()),
+        model: feature_info.as_ref().map_or(0, |e| e.model_id()),
+        stepping: feature_info.as_ref().map_or(0, |e| e.stepping_id()),
+
+        features,
     })
 }
 
@@ -20,10 +20,10 @@ use core::mem::MaybeUninit;
 use core::ops::{Deref, DerefMut};
 
 use crate::interrupts::exceptions::PF_RESUME;
-use crate::mem::paging::VirtAddr;
+use crate::mem::paging::{PageSize, ReadErr, Size4KiB, VirtAddr};
 use crate::syscall::SysArg;
 
-use super::task::user_access_ok;
+use super::task::user_range_ok;
 
 /// Copy to/from a block of data from user space. Returns whether the copy was successful.
 ///
@@ -86,32 +86,141 @@ unsafe extern "C" fn copy_to_from_user(
     )
 }
 
-/// Copy a structure from userspace memory. Returns whether the copy was successful.
-#[must_use]
-fn copy_from_user<T>(dest: &mut MaybeUninit<T>, src: *const T) -> bool {
+/// Atomically adds zero to the byte at `addr`, which faults unless the byte is writable. Returns
+/// whether the byte was writable.
+///
+/// # Safety
+/// The `addr` pointer must be within the userland address space.
+#[naked]
+unsafe extern "C" fn probe_write_byte(addr: *mut u8, fault_resume: *const u8) -> bool {
+    // Registers used:
+    //
+    // %rdi = argument 1, `addr`
+    // %rsi = argument 2, `fault_resume`
+    asm!(
+        "lea rax, 1f",
+        "mov [rsi], rax",
+        // The add is atomic so that a concurrent write to the byte from userspace is not lost.
+        "lock add byte ptr [rdi], 0",
+        "mov eax, 1",
+        "2:",
+        "mov qword ptr [rsi], 0",
+        "ret",
+        "1:",
+        "xor eax, eax",
+        "jmp 2b",
+        options(noreturn)
+    )
+}
+
+/// Copies `size` bytes between kernel and userspace memory, failing with [`ReadErr::Fault`] if
+/// `user` does not lie within the userland address space or if the copy faults.
+///
+/// # Safety
+/// The kernel side of the copy must be valid for `size` bytes.
+unsafe fn copy_checked(
+    dest: *mut u8,
+    src: *const u8,
+    size: usize,
+    user: *const u8,
+) -> Result<(), ReadErr> {
+    if !user_range_ok(user, size) {
+        return Err(ReadErr::Fault);
+    }
+
     let fault_resume = unsafe { PF_RESUME.addr() }.as_ptr();
+
+    // SAFETY: We have verified that the user pointer is within the userland address space and
+    // any fault while accessing it is recovered by the page fault handler.
+    if unsafe { copy_to_from_user(dest, src, size, fault_resume) } {
+        Ok(())
+    } else {
+        Err(ReadErr::Fault)
+    }
+}
+
+/// Copy a structure from userspace memory.
+pub fn copy_from_user<T>(dest: &mut MaybeUninit<T>, src: *const T) -> Result<(), ReadErr> {
     let size = core::mem::size_of::<T>();
 
-    user_access_ok(src);
+    // SAFETY: `dest` is valid for `size_of::<T>()` bytes.
+    unsafe { copy_checked(dest.as_mut_ptr().cast(), src.cast(), size, src.cast()) }
+}
+
+/// Copy a slice from userspace memory.
+pub fn copy_slice_from_user<T: Copy>(dest: &mut [T], src: *const T) -> Result<(), ReadErr> {
+    let size = core::mem::size_of_val(dest);
+
+    // SAFETY: `dest` is valid for `size_of_val(dest)` bytes.
+    unsafe { copy_checked(dest.as_mut_ptr().cast(), src.cast(), size, src.cast()) }
+}
+
+/// Copy a structure to userspace memory.
+pub fn copy_to_user<T>(dest: *mut T, src: &T) -> Result<(), ReadErr> {
+    copy_slice_to_user(dest, core::slice::from_ref(src))
+}
+
+/// Copy a slice to userspace memory.
+pub fn copy_slice_to_user<T>(dest: *mut T, src: &[T]) -> Result<(), ReadErr> {
+    let size = core::mem::size_of_val(src);
+    let src_ptr = src.as_ptr();
+
+    // SAFETY: `src` is valid for `size_of_val(src)` bytes.
+    unsafe { copy_checked(dest.cast(), src_ptr.cast(), size, dest.cast()) }
+}
+
+/// Checks that `size` bytes starting at `ptr` are accessible from the kernel on behalf of the
+/// current process, faulting in any lazily mapped pages on the way.
+pub fn probe_user(ptr: *const u8, size: usize) -> Result<(), ReadErr> {
+    if !user_range_ok(ptr, size) {
+        return Err(ReadErr::Fault);
+    } else if size == 0 {
+        return Ok(());
+    }
+
+    let end = VirtAddr::new(ptr as u64 + size as u64);
+
+    let mut byte = MaybeUninit::<u8>::uninit();
+    let mut addr = VirtAddr::new(ptr as u64);
+
+    // Touch one byte in every page spanned by the region.
+    while addr < end {
+        copy_from_user(&mut byte, addr.as_ptr::<u8>())?;
+        addr = addr.align_down(Size4KiB::SIZE) + Size4KiB::SIZE;
+    }
 
-    // SAFETY: We have verified that the `src` pointer is within the userland address space.
-    unsafe { copy_to_from_user(dest.as_mut_ptr().cast(), src.cast(), size, fault_resume) }
+    Ok(())
 }
 
-/// Copy a structure from userspace memory. Returns whether the copy was successful.
-#[must_use]
-fn copy_to_user<T>(dest: *mut T, src: &T) -> bool {
+/// Like [`probe_user`], but also checks that the bytes are writable, breaking copy-on-write
+/// sharing of the pages on the way.
+pub fn probe_user_write(ptr: *mut u8, size: usize) -> Result<(), ReadErr> {
+    if !user_range_ok(ptr, size) {
+        return Err(ReadErr::Fault);
+    } else if size == 0 {
+        return Ok(());
+    }
+
+    let end = VirtAddr::new(ptr as u64 + size as u64);
+    let mut addr = VirtAddr::new(ptr as u64);
+
     let fault_resume = unsafe { PF_RESUME.addr() }.as_ptr();
-    let size = core::mem::size_of::<T>();
-    let src_ptr = src as *const T;
 
-    user_access_ok(dest);
+    while addr < end {
+        // SAFETY: We have verified that the region is within the userland address space and any
+        // fault while accessing it is recovered by the page fault handler.
+        if !unsafe { probe_write_byte(addr.as_mut_ptr(), fault_resume) } {
+            return Err(ReadErr::Fault);
+        }
+
+        addr = addr.align_down(Size4KiB::SIZE) + Size4KiB::SIZE;
+    }
 
-    // SAFETY: We have verified that the `dest` pointer is within the userland address space.
-    unsafe { copy_to_from_user(dest.cast(), src_ptr.cast(), size, fault_resume) }
+    Ok(())
 }
 
-/// A reference to a structure in userspace memory, which can be either read-only or read-write.
+/// A copy of a structure in userspace memory. Changes made to it are only copied back to
+/// userspace by [`UserRef::write_back`].
 ///
 /// Concurrent access, *including data races to/from userspace memory*, are permitted. See the
 /// documentation of [`copy_to_from_user`] for more information.
@@ -121,17 +230,23 @@ pub struct UserRef<T> {
 }
 
 impl<T> UserRef<T> {
-    pub unsafe fn new(address: VirtAddr) -> Self {
+    /// Copies the structure at `address` out of userspace memory. Fails with [`ReadErr::Fault`]
+    /// if `address` is not a mapped userspace address.
+    pub fn new(address: VirtAddr) -> Result<Self, ReadErr> {
         let mut val = MaybeUninit::<T>::uninit();
+        copy_from_user(&mut val, address.as_ptr())?;
 
-        // FIXME: Return an error if the copy fails.
-        assert!(copy_from_user(&mut val, address.as_ptr()));
-
-        Self {
+        Ok(Self {
             ptr: address.as_mut_ptr(),
             // SAFETY: We have initialized the value via `copy_from_user` above.
             val: unsafe { val.assume_init() },
-        }
+        })
+    }
+
+    /// Copies the structure back to userspace memory. Fails with [`ReadErr::Fault`] if userspace
+    /// unmapped it or made it read-only since it was copied in.
+    pub fn write_back(&self) -> Result<(), ReadErr> {
+        copy_to_user(self.ptr, &self.val)
     }
 
     pub fn take(self) -> T
@@ -156,12 +271,6 @@ impl<T> DerefMut for UserRef<T> {
     }
 }
 
-impl<T> Drop for UserRef<T> {
-    fn drop(&mut self) {
-        assert!(copy_to_user(self.ptr, &self.val));
-    }
-}
-
 impl<T: Debug> Display for UserRef<T> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.val.fmt(f)
@@ -175,8 +284,7 @@ impl<T: Debug> Debug for UserRef<T> {
 }
 
 impl<T: Debug> SysArg for UserRef<T> {
-    fn from_usize(value: usize) -> Self {
-        // TODO: SAFETY
-        unsafe { Self::new(VirtAddr::new(value.try_into().unwrap())) }
+    fn from_usize(value: usize) -> Result<Self, ReadErr> {
+        Self::new(VirtAddr::new(value as u64))
     }
 }
@@ -24,6 +24,7 @@ use limine::file::File;
 use crate::rendy;
 
 static RAW_CMDLINE_STR: Once<&'static str> = Once::new();
+static COMMAND_LINE: Once<CommandLine> = Once::new();
 
 pub struct CommandLine {
     /// If set, then the kernel logs will be redirected onto the framebuffer until
@@ -33,6 +34,20 @@ pub struct CommandLine {
     pub rendy_debug: bool,
     pub term_background: Option<&'static [u8]>,
     pub theme_background: u32,
+    /// PSF font of the debug renderer, loaded from the boot module with the given name
+    /// (`font=<module>`). The built-in VGA font is used if unset.
+    pub font: Option<&'static [u8]>,
+    /// Size of the `zram0` compressed RAM block device in bytes, rounded up to the page size
+    /// (e.g. `zram.size=256M`). The device is not created if unset.
+    pub zram_size: Option<usize>,
+    /// If set, only the kernel tests whose path matches this regular expression are run
+    /// (`test-filter=zram::(read|write)`).
+    pub test_filter: Option<&'static str>,
+    /// If set, the kernel tests are listed instead of being run (`test-list`).
+    pub test_list: bool,
+    /// If set, every message sent or received with `sys_ipc_send` and `sys_ipc_recv` is logged
+    /// (`ipctrace=1`). It can also be toggled at runtime with `/proc/sys/ipc_trace`.
+    pub ipc_trace: bool,
 }
 
 impl CommandLine {
@@ -41,6 +56,11 @@ impl CommandLine {
             rendy_debug: false,
             term_background: None,
             theme_background: rendy::DEFAULT_THEME_BACKGROUND,
+            font: None,
+            zram_size: None,
+            test_filter: None,
+            test_list: false,
+            ipc_trace: false,
         }
     }
 }
@@ -71,7 +91,19 @@ fn parse_number(mut string: &str) -> Result<usize, ParseIntError> {
     }
 }
 
-pub fn parse(cmdline: &'static str, modules: &[&File]) -> CommandLine {
+/// Parses a size with an optional `K`, `M` or `G` suffix (e.g. `256M`).
+fn parse_size(string: &str) -> Option<usize> {
+    let (number, shift) = match string.as_bytes().last()? {
+        b'K' | b'k' => (&string[..string.len() - 1], 10),
+        b'M' | b'm' => (&string[..string.len() - 1], 20),
+        b'G' | b'g' => (&string[..string.len() - 1], 30),
+        _ => (string, 0),
+    };
+
+    parse_number(number).ok()?.checked_mul(1 << shift)
+}
+
+pub fn parse(cmdline: &'static str, modules: &[&File]) -> &'static CommandLine {
     RAW_CMDLINE_STR.call_once(|| cmdline);
 
     // Chew up the leading spaces.
@@ -83,6 +115,7 @@ pub fn parse(cmdline: &'static str, modules: &[&File]) -> CommandLine {
     for argument in cmdline.split_whitespace() {
         match argument {
             "rendy-dbg" => result.rendy_debug = true,
+            "test-list" => result.test_list = true,
 
             _ => {
                 let mut pair = argument.splitn(2, '=');
@@ -110,6 +143,18 @@ pub fn parse(cmdline: &'static str, modules: &[&File]) -> CommandLine {
                                 result.theme_background = theme_bg as u32;
                             }
 
+                            "font" => result.font = Some(resolve_module(modules, value)),
+                            "test-filter" => result.test_filter = Some(value),
+                            "ipctrace" => result.ipc_trace = value == "1",
+
+                            "zram.size" => match parse_size(value) {
+                                Some(size) if size > 0 => {
+                                    result.zram_size = Some(size.next_multiple_of(4096))
+                                }
+
+                                _ => log::warn!("zram.size: invalid size '{}'", value),
+                            },
+
                             _ => bail(argument),
                         }
                     }
@@ -120,7 +165,13 @@ pub fn parse(cmdline: &'static str, modules: &[&File]) -> CommandLine {
         }
     }
 
-    result
+    COMMAND_LINE.call_once(|| result)
+}
+
+/// Returns the parsed kernel command line, or [`None`] if it has not been parsed using
+/// [`self::parse`] (which the aarch64 port does not do yet).
+pub fn get() -> Option<&'static CommandLine> {
+    COMMAND_LINE.get()
 }
 
 /// Returns the raw kernel command line string.
@@ -147,4 +198,16 @@ mod tests {
         assert!(parse_number("0xinvalid").is_err());
         assert!(parse_number("0oinvalid").is_err());
     }
+
+    #[test]
+    fn size_parser_test() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("256M"), Some(256 << 20));
+        assert_eq!(parse_size("0x2G"), Some(2 << 30));
+
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("12T"), None);
+    }
 }
@@ -15,16 +15,52 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+//! AHCI (Advanced Host Controller Interface) SATA driver.
+//!
+//! The ports of the HBA are scanned when the driver starts, and disks plugged in or removed
+//! afterwards are handled through the connect change interrupts of the ports:
+//!
+//! * When a device is connected, the port is reset (COMRESET) and, if the signature of the device
+//!   is the one of an ATA disk, the disk is identified and installed as a block device (`/dev/sdX`,
+//!   after the port) and scanned for partitions. Port multipliers and ATAPI devices are not
+//!   supported.
+//! * When the disk is disconnected, the in-flight commands are aborted and the block devices of the
+//!   disk and its partitions are removed from the page cache and `/dev`. Files that are still open
+//!   on them stay valid, but their I/O fails with EIO.
+//!
+//! With QEMU, a disk is hot-plugged and removed from the monitor on a free port of the
+//! controller (`ide.N` for the one built into `q35`), and the kernel log shows the port going
+//! through [`PortState`]:
+//!
+//! ```text
+//! (qemu) drive_add 0 if=none,id=disk1,file=disk1.img,format=raw
+//! (qemu) device_add ide-hd,drive=disk1,bus=ide.1,id=hd1
+//! (qemu) device_del hd1
+//! ```
+//!
+//! Note that stock QEMU does not allow hot-plugging on its AHCI buses, so this needs a QEMU
+//! build that does, or real hardware with hot-plug capable ports.
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use alloc::sync::Arc;
 
 use alloc::vec::Vec;
 use bit_field::BitField;
 use spin::Once;
 
+use crate::acpi::aml;
+use crate::arch::interrupts::{self, InterruptStack};
+use crate::arch::io;
+use crate::fs::block::{self, install_block_device, BlockDevice, BlockDeviceInterface};
 use crate::mem::paging::*;
 use crate::mem::AddressSpace;
 
-use crate::utils::sync::Mutex;
+use crate::userland::scheduler;
+use crate::userland::task::Task;
+use crate::utils::dma::Dma;
+use crate::utils::sync::{Mutex, WaitQueue};
 use crate::utils::VolatileCell;
 
 use crate::drivers::pci::*;
@@ -69,6 +105,7 @@ bitflags::bitflags! {
 }
 
 bitflags::bitflags! {
+    #[derive(Debug, Copy, Clone)]
     struct HbaCapabilities: u32 {
         const SXS           = 1 << 5;  // Supports External SATA
         const EMS           = 1 << 6;  // Enclosure Management Supported
@@ -492,7 +529,7 @@ enum HbaPortIpm {
 
 #[repr(transparent)]
 #[derive(Clone, Copy)]
-struct HbaSataStatus(u64);
+struct HbaSataStatus(u32);
 
 impl HbaSataStatus {
     fn device_detection(&self) -> HbaPortDd {
@@ -548,308 +585,694 @@ struct HbaCmdHeader {
     _reserved: [u32; 4],
 }
 
+const SECTOR_SIZE: usize = 512;
+
+/// Number of PRDT entries in each of the command tables.
+const PRDT_ENTRIES: usize = 8;
+/// Size of a command table with [`PRDT_ENTRIES`] entries: 64 + 16 + 48 + 16 * 8.
+const COMMAND_TABLE_SIZE: usize = 256;
+
+/// Timeout of a command, in microseconds.
+const COMMAND_TIMEOUT: usize = 5_000_000;
+/// Timeout for the link of a port to come up after a COMRESET, in microseconds.
+const LINK_TIMEOUT: usize = 1_000_000;
+/// Timeout for the command engine of a port to start or stop, in microseconds.
+const ENGINE_TIMEOUT: usize = 500_000;
+
+// Signatures reported in PxSIG by the device attached to a port.
+const SATA_SIG_ATA: u32 = 0x0000_0101;
+const SATA_SIG_ATAPI: u32 = 0xeb14_0101;
+const SATA_SIG_SEMB: u32 = 0xc33c_0101;
+const SATA_SIG_PM: u32 = 0x9669_0101;
+
+const ATA_DEV_ERR: u32 = 1 << 0;
+const ATA_DEV_DRQ: u32 = 1 << 3;
+const ATA_DEV_BUSY: u32 = 1 << 7;
+
+// Bits of PxSERR.DIAG, mirrored by PxIS.PRCS (PhyRdy change) and PxIS.PCS (exchanged).
+const SERR_DIAG_N: u32 = 1 << 16;
+const SERR_DIAG_X: u32 = 1 << 26;
+
+/// Polls `done` every microsecond until it returns true or `timeout` microseconds elapsed.
+fn poll(timeout: usize, mut done: impl FnMut() -> bool) -> bool {
+    for _ in 0..timeout {
+        if done() {
+            return true;
+        }
+
+        io::delay(1);
+    }
+
+    done()
+}
+
+/// Returns the number of sectors and the model of a disk from its IDENTIFY DEVICE data.
+pub(super) fn parse_identify(words: &[u16; 256]) -> (usize, String) {
+    // Word 83 bit 10 is set if the 48-bit address feature set is supported.
+    let sectors = if words[83].get_bit(10) {
+        words[100..=103]
+            .iter()
+            .rev()
+            .fold(0, |sectors, &word| (sectors << 16) | word as usize)
+    } else {
+        ((words[61] as usize) << 16) | words[60] as usize
+    };
+
+    // The model is a string of 40 ASCII characters, with the bytes of each word swapped.
+    let model = words[27..=46]
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .map(char::from)
+        .collect::<String>();
+
+    (sectors, String::from(model.trim()))
+}
+
 impl HbaPort {
-    fn cmd_header_at(&mut self, index: usize) -> &mut HbaCmdHeader {
+    fn cmd_header_at(&self, index: usize) -> &HbaCmdHeader {
         // Since the CLB holds the physical address, we make the address mapped
         // before reading it.
         let clb_mapped = self.clb.get().as_hhdm_virt();
         // Get the address of the command header at `index`.
         let clb_addr = clb_mapped + core::mem::size_of::<HbaCmdHeader>() * index;
 
-        // Cast it as [`HbaCmdHeader`] and return a mutable reference to it.
-        unsafe { &mut *(clb_addr.as_mut_ptr::<HbaCmdHeader>()) }
+        // Cast it as [`HbaCmdHeader`] and return a reference to it.
+        unsafe { &*(clb_addr.as_ptr::<HbaCmdHeader>()) }
     }
 
-    /// This function is responsible for allocating space for command lists,
-    /// tables, etc.. for a given this instance of HBA port.
-    fn start(&mut self, offset_table: &mut OffsetPageTable) -> Result<(), MapToError<Size4KiB>> {
-        self.stop_cmd(); // Stop the command engine before starting the port
+    #[allow(clippy::mut_from_ref)]
+    fn command_table(&self, index: usize) -> &mut HbaCmdTbl {
+        let ctb_mapped = self.cmd_header_at(index).ctb.get().as_hhdm_virt();
+        unsafe { &mut *(ctb_mapped.as_mut_ptr::<HbaCmdTbl>()) }
+    }
 
-        // size = sizeof(CTB) * 32 == 4KiB * 2 (so we need to allocate
-        // two 4KiB size frames).
-        let frame_addr = pmm_alloc(BuddyOrdering::Size8KiB);
-        let page_addr = crate::IO_VIRTUAL_BASE + frame_addr.as_u64();
+    /// Returns whether a device is present on the port and the communication with it is
+    /// established.
+    fn is_present(&self) -> bool {
+        let status = self.ssts.get();
 
-        for size in (0..0x2000u64).step_by(0x1000) {
-            unsafe {
-                offset_table
-                    .map_to(
-                        Page::<Size4KiB>::containing_address(page_addr + size),
-                        PhysFrame::<Size4KiB>::containing_address(frame_addr + size),
-                        PageTableFlags::PRESENT
-                            | PageTableFlags::WRITABLE
-                            | PageTableFlags::WRITE_THROUGH
-                            | PageTableFlags::NO_CACHE,
-                    )?
-                    .flush();
-            }
-        }
+        matches!(
+            (
+                status.device_detection(),
+                status.interface_power_management()
+            ),
+            (HbaPortDd::PresentAndE, HbaPortIpm::Active)
+        )
+    }
+
+    /// Points the port to the command list and tables in `memory`, and enables FIS reception.
+    /// The command engine must be stopped.
+    fn setup(&self, memory: &PortMemory) {
+        self.clb.set(memory.list);
+        self.fb.set(memory.fis());
 
         for i in 0..32 {
             let command_header = self.cmd_header_at(i);
 
-            // 8 prdt entries per command table
-            // 256 bytes per command table, 64 + 16 + 48 + 16 * 8
-            command_header.prdtl.set(8);
+            command_header.prdtl.set(0);
             command_header.prdbc.set(0);
-            command_header.ctb.set(PhysAddr::new(
-                (frame_addr.as_u64() as usize + 256 * i) as u64,
-            ));
+            command_header
+                .ctb
+                .set(memory.tables + (COMMAND_TABLE_SIZE * i) as u64);
         }
 
-        self.ie.set(HbaPortIE::all());
-        self.start_cmd(); // Start the command engine...
+        let value = self.cmd.get() | HbaPortCmd::FRE;
+        self.cmd.set(value);
+    }
 
-        Ok(())
+    /// Resets the link of the port (COMRESET) and returns whether a device came up on it.
+    fn reset(&self) -> bool {
+        // Request the COMRESET for at least 1ms.
+        self.sctl.set((self.sctl.get() & !0xf) | 1);
+        io::delay(1000);
+        self.sctl.set(self.sctl.get() & !0xf);
+
+        let present = poll(LINK_TIMEOUT, || self.is_present());
+
+        // Clear the errors logged while the link was down.
+        self.serr.set(u32::MAX);
+        present
+    }
+
+    /// Waits for the device to be ready to accept a command.
+    fn wait_ready(&self) -> bool {
+        poll(COMMAND_TIMEOUT, || {
+            self.tfd.get() & (ATA_DEV_BUSY | ATA_DEV_DRQ) == 0
+        })
     }
 
-    fn start_cmd(&mut self) {
-        while self.cmd.get().contains(HbaPortCmd::CR) {
-            core::hint::spin_loop();
+    fn start_cmd(&self) -> bool {
+        if !poll(ENGINE_TIMEOUT, || !self.cmd.get().contains(HbaPortCmd::CR)) {
+            return false;
         }
 
         let value = self.cmd.get() | (HbaPortCmd::FRE | HbaPortCmd::ST);
         self.cmd.set(value);
+
+        true
     }
 
-    fn stop_cmd(&mut self) {
+    fn stop_cmd(&self) -> bool {
         let mut cmd = self.cmd.get();
-        cmd.remove(HbaPortCmd::FRE | HbaPortCmd::ST);
-
+        cmd.remove(HbaPortCmd::ST);
         self.cmd.set(cmd);
 
-        while self.cmd.get().intersects(HbaPortCmd::FR | HbaPortCmd::CR) {
-            core::hint::spin_loop();
+        if !poll(ENGINE_TIMEOUT, || !self.cmd.get().contains(HbaPortCmd::CR)) {
+            return false;
         }
-    }
 
-    fn probe(
-        &mut self,
-        offset_table: &mut OffsetPageTable,
-        port: usize,
-    ) -> Result<bool, MapToError<Size4KiB>> {
-        let status = self.ssts.get();
+        let mut cmd = self.cmd.get();
+        cmd.remove(HbaPortCmd::FRE);
+        self.cmd.set(cmd);
+
+        poll(ENGINE_TIMEOUT, || !self.cmd.get().contains(HbaPortCmd::FR))
+    }
 
-        let ipm = status.interface_power_management();
-        let dd = status.device_detection();
+    /// Restarts the command engine after a failed command, which clears the command slots.
+    fn recover(&self) {
+        self.stop_cmd();
 
-        // Check if the port is active and is present. If that's the case
-        // we can start the AHCI port.
-        if let (HbaPortDd::PresentAndE, HbaPortIpm::Active) = (dd, ipm) {
-            log::trace!("ahci: enabling port {}", port);
+        // Leave the connect change bits alone, they are handled by the IRQ handler.
+        self.serr.set(!(SERR_DIAG_X | SERR_DIAG_N));
+        self.is
+            .set(HbaPortIS::TFES | HbaPortIS::HBFS | HbaPortIS::HBDS | HbaPortIS::IFS);
 
-            self.start(offset_table)?;
-            Ok(true)
-        } else {
-            // Else we can't enable the port.
-            Ok(false)
-        }
+        self.start_cmd();
     }
 
+    /// Runs `command` on `count` sectors starting at `sector`, transferring the data from or to
+    /// the `prdt` regions, and waits for it to complete. The command is aborted if `removed` is
+    /// set in the meantime.
     fn run_command(
-        &mut self,
+        &self,
         command: AtaCommand,
         sector: usize,
         count: usize,
-        slot: usize,
-        buffer: &[DmaBuffer],
-    ) {
-        let header = self.cmd_header_at(slot);
-        let mut flags = header.flags.get();
-
-        if command == AtaCommand::WriteDmaExt || command == AtaCommand::WriteDma {
-            flags.insert(HbaCmdHeaderFlags::W); // If its a write command add the write flag.
-        } else {
-            flags.remove(HbaCmdHeaderFlags::W); // If its a read command remove the write flag.
+        prdt: &[(PhysAddr, usize)],
+        removed: &AtomicBool,
+    ) -> bool {
+        debug_assert!(prdt.len() <= PRDT_ENTRIES);
+
+        if !self.wait_ready() {
+            return false;
         }
 
-        flags.insert(HbaCmdHeaderFlags::P | HbaCmdHeaderFlags::C);
-        flags.set_command_fis_size(core::mem::size_of::<FisRegH2D>() / 4);
+        // Commands are issued one at a time in the first slot, as the caller waits for them to
+        // complete anyway.
+        let header = self.cmd_header_at(0);
+        let mut flags = HbaCmdHeaderFlags::P;
 
-        header.flags.set(flags); // Update command header flags.
+        if command.is_write() {
+            flags.insert(HbaCmdHeaderFlags::W);
+        }
 
-        let length = ((count - 1) >> 4) + 1;
-        header.prdtl.set(length as _); // Update the number of PRD entries.
+        flags.set_command_fis_size(core::mem::size_of::<FisRegH2D>() / 4);
 
-        let command_table_addr = crate::IO_VIRTUAL_BASE + header.ctb.get().as_u64();
-        let command_table = unsafe { &mut *(command_table_addr).as_mut_ptr::<HbaCmdTbl>() };
+        header.flags.set(flags);
+        header.prdtl.set(prdt.len() as _);
+        header.prdbc.set(0);
 
-        for pri in 0..length {
-            let prdt = command_table.prdt_entry_mut(pri);
+        let command_table = self.command_table(0);
 
-            prdt.dba.set(buffer[pri].start);
-            prdt.set_data_byte_count(buffer[pri].data_size - 1);
-            prdt.set_interrupt_on_completion(pri == length - 1);
+        for (i, &(addr, size)) in prdt.iter().enumerate() {
+            let entry = command_table.prdt_entry_mut(i);
+
+            entry.dba.set(addr);
+            entry.flags.set(0);
+            entry.set_data_byte_count(size - 1);
         }
 
-        let fis = command_table.cfis_as_h2d_mut();
+        command_table.cfis.fill(0x00);
 
-        fis.control.set(0x00);
-        fis.icc.set(0x00);
-        fis.featurel.set(0x00);
-        fis.featureh.set(0x00);
-        fis._reserved.fill(0x00);
+        let fis = command_table.cfis_as_h2d_mut();
 
         fis.fis_type.set(FisType::RegH2D);
-        fis.device.set(1 << 6);
         fis.command.set(command);
+        fis.device.set(1 << 6); // LBA mode
         fis.count.set(count as _);
 
         fis.set_lba(sector);
         fis.set_command(true);
 
+        let errors = HbaPortIS::TFES | HbaPortIS::HBFS | HbaPortIS::HBDS | HbaPortIS::IFS;
+        self.is.set(errors);
+
         // Issue the command!
-        self.ci.set(1 << slot);
+        self.ci.set(1);
 
-        let mut spin = 100;
+        let done = || self.ci.get() & 1 == 0;
 
-        // Make sure the port is not busy.
-        while self.tfd.get() & 0x80 | 0x08 == 1 && spin > 0 {
-            core::hint::spin_loop();
-            spin -= 1;
-        }
+        poll(COMMAND_TIMEOUT, || {
+            done() || self.is.get().intersects(errors) || removed.load(Ordering::SeqCst)
+        });
 
-        if spin == 0 {
-            log::warn!("ahci: port hung");
-            return;
-        }
-
-        // Wait for the command to complete.
-        while self.ci.get() & (1 << slot) == 1 {
-            if self.is.get().contains(HbaPortIS::TFES) {
-                log::warn!("ahci: disk error (serr={:#x})", self.serr.get());
-                break;
-            }
-        }
+        done() && self.tfd.get() & ATA_DEV_ERR == 0 && !removed.load(Ordering::SeqCst)
     }
 }
 
 impl HbaMemory {
-    fn port_mut(&mut self, port: usize) -> &mut HbaPort {
-        unsafe { &mut *((self as *mut Self).offset(1) as *mut HbaPort).add(port) }
+    fn port(&self, port: usize) -> &HbaPort {
+        unsafe { &*(self as *const Self).offset(1).cast::<HbaPort>().add(port) }
     }
 }
 
-struct AhciCommand {
-    request: Arc<DmaRequest>,
+/// Returns the registers of the HBA mapped at `address`, which stay mapped for the lifetime of
+/// the kernel.
+fn hba_mem(address: VirtAddr) -> &'static HbaMemory {
+    unsafe { &*(address.as_ptr::<HbaMemory>()) }
 }
 
-struct AhciPortProtected {
-    address: VirtAddr,
-    cmds: [Option<AhciCommand>; 32],
-    free_cmds: usize,
+/// State of an AHCI port, driven by the connect changes reported by its interrupts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PortState {
+    /// No device is attached to the port.
+    Empty,
+    /// A device was connected and the port is being initialized.
+    Attaching,
+    /// A disk is attached and installed as a block device.
+    Active,
+    /// A device is connected, but it is not supported or could not be initialized.
+    Failed,
 }
 
-impl AhciPortProtected {
-    fn hba_port(&mut self) -> &mut HbaPort {
-        unsafe { &mut *(self.address.as_mut_ptr::<HbaPort>()) }
-    }
-
-    fn run_request(&mut self, request: Arc<DmaRequest>, mut offset: usize) -> usize {
-        let mut remaining = request.count - offset;
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PortEvent {
+    /// The link of the port is up: a device is present.
+    Connected,
+    /// The link of the port is down.
+    Disconnected,
+    /// The init sequence of the port succeeded.
+    Attached,
+    /// The init sequence of the port failed.
+    AttachFailed,
+}
 
-        while remaining > 0 {
-            let slot = {
-                let command =
-                    self.cmds
-                        .iter()
-                        .enumerate()
-                        .find_map(|(i, e)| if e.is_none() { Some(i) } else { None });
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PortAction {
+    None,
+    /// Run the init sequence of the port and install its disk.
+    Attach,
+    /// Remove the disk of the port.
+    Detach,
+}
 
-                if let Some(i) = command {
-                    let hba = self.hba_port();
-                    let count = core::cmp::min(remaining, 128);
+impl PortState {
+    /// Returns the state of the port after `event`, and the action to perform for it.
+    pub fn next(self, event: PortEvent) -> (PortState, PortAction) {
+        match (self, event) {
+            (Self::Empty | Self::Failed, PortEvent::Connected) => {
+                (Self::Attaching, PortAction::Attach)
+            }
+            (Self::Active, PortEvent::Disconnected) => (Self::Empty, PortAction::Detach),
+            (Self::Attaching | Self::Failed, PortEvent::Disconnected) => {
+                (Self::Empty, PortAction::None)
+            }
 
-                    hba.run_command(
-                        request.into_command(),
-                        request.sector + offset,
-                        count,
-                        i,
-                        request.at_offset(offset),
-                    );
+            (Self::Attaching, PortEvent::Attached) => (Self::Active, PortAction::None),
+            (Self::Attaching, PortEvent::AttachFailed) => (Self::Failed, PortAction::None),
 
-                    remaining -= count;
-                    offset += count;
+            // The link of an active port bounced, but the disk is still there.
+            (state, _) => (state, PortAction::None),
+        }
+    }
+}
 
-                    i
-                } else {
-                    return offset;
-                }
-            };
+/// Memory used by the HBA for a port.
+#[derive(Copy, Clone)]
+struct PortMemory {
+    /// The command list (1 KiB), followed by the received FIS area (256 bytes).
+    list: PhysAddr,
+    /// The command tables of the 32 command slots.
+    tables: PhysAddr,
+}
 
-            self.cmds[slot] = Some(AhciCommand {
-                request: request.clone(),
-            });
+impl PortMemory {
+    fn alloc() -> Self {
+        let list = pmm_alloc(BuddyOrdering::Size4KiB);
+        let tables = pmm_alloc(BuddyOrdering::Size8KiB);
 
-            self.free_cmds -= 1;
+        unsafe {
+            core::ptr::write_bytes(list.as_hhdm_virt().as_mut_ptr::<u8>(), 0, 0x1000);
+            core::ptr::write_bytes(tables.as_hhdm_virt().as_mut_ptr::<u8>(), 0, 0x2000);
         }
 
-        offset
+        Self { list, tables }
+    }
+
+    fn fis(&self) -> PhysAddr {
+        self.list + 0x400u64
     }
 }
 
+struct AhciPortProtected {
+    address: VirtAddr,
+}
+
+impl AhciPortProtected {
+    fn hba_port(&self) -> &HbaPort {
+        unsafe { &*(self.address.as_ptr::<HbaPort>()) }
+    }
+}
+
+/// A disk attached to an AHCI port.
 struct AhciPort {
+    index: usize,
     inner: Mutex<AhciPortProtected>,
+    /// Set once the disk is removed, after which its commands fail.
+    removed: AtomicBool,
+    sectors: usize,
 }
 
 impl AhciPort {
     #[inline]
-    fn new(address: VirtAddr) -> Self {
-        const EMPTY: Option<AhciCommand> = None;
-
+    fn new(index: usize, address: VirtAddr, sectors: usize) -> Self {
         Self {
-            inner: Mutex::new(AhciPortProtected {
-                address,
-                cmds: [EMPTY; 32],
-                free_cmds: 32,
-            }),
+            index,
+            inner: Mutex::new(AhciPortProtected { address }),
+            removed: AtomicBool::new(false),
+            sectors,
         }
     }
 
-    fn run_request(&self, request: Arc<DmaRequest>) -> Option<usize> {
-        let mut offset = 0x00;
+    /// Marks the disk removed and waits for the in-flight command to be aborted.
+    fn remove(&self) {
+        self.removed.store(true, Ordering::SeqCst);
+        drop(self.inner.lock());
+    }
+
+    /// Transfers the data of the `prdt` regions from or to the disk, starting at `sector`.
+    fn transfer(&self, write: bool, sector: usize, prdt: &[(PhysAddr, usize)]) -> Option<usize> {
+        let size = prdt.iter().map(|&(_, size)| size).sum::<usize>();
+        let count = size / SECTOR_SIZE;
 
-        // Run request and wait for it to complete.
-        while offset < request.count {
-            offset = self.inner.lock().run_request(request.clone(), offset);
+        if prdt.len() > PRDT_ENTRIES
+            || count > u16::MAX as usize
+            || sector.checked_add(count)? > self.sectors
+        {
+            return None;
         }
 
-        Some(request.count * 512)
-    }
+        let command = if write {
+            AtaCommand::WriteDmaExt
+        } else {
+            AtaCommand::ReadDmaExt
+        };
+
+        let inner = self.inner.lock();
 
-    fn read(&self, sector: usize, buffer: &mut [u8]) -> Option<usize> {
-        let count = (buffer.len() + 512 - 1) / 512;
-        let request = Arc::new(DmaRequest::new(sector, count));
+        if self.removed.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        let port = inner.hba_port();
 
-        let result = self.run_request(request.clone()); // Perform the DMA request.
+        if !port.run_command(command, sector, count, prdt, &self.removed) {
+            if !self.removed.load(Ordering::SeqCst) {
+                log::warn!(
+                    "ahci: port {}: {:?} failed (sector={}, tfd={:#x}, serr={:#x})",
+                    self.index,
+                    command,
+                    sector,
+                    port.tfd.get(),
+                    port.serr.get()
+                );
+
+                port.recover();
+            }
 
-        if result.is_some() {
-            request.copy_into(buffer); // Copy the result into the provided buffer.
+            return None;
         }
 
-        result
+        Some(size)
+    }
+}
+
+impl BlockDeviceInterface for AhciPort {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> usize {
+        self.sectors
+    }
+
+    fn read_dma(&self, sector: usize, start: PhysAddr, size: usize) -> Option<usize> {
+        self.transfer(false, sector, &[(start, size)])
+    }
+
+    fn write_dma(&self, sector: usize, start: PhysAddr, size: usize) -> Option<usize> {
+        self.transfer(true, sector, &[(start, size)])
+    }
+
+    fn read_block(&self, sector: usize, dest: &mut [MaybeUninit<u8>]) -> Option<usize> {
+        let buffer = Dma::<u8>::new_uninit_slice(dest.len().next_multiple_of(SECTOR_SIZE));
+        self.transfer(false, sector, &[(buffer.addr(), buffer.len())])?;
+
+        dest.copy_from_slice(&buffer[..dest.len()]);
+        Some(dest.len())
+    }
+
+    fn write_block(&self, sector: usize, buf: &[u8]) -> Option<usize> {
+        let mut buffer = Dma::<u8>::new_zeroed_slice(buf.len().next_multiple_of(SECTOR_SIZE));
+        MaybeUninit::copy_from_slice(&mut buffer[..buf.len()], buf);
+
+        self.transfer(true, sector, &[(buffer.addr(), buffer.len())])?;
+        Some(buf.len())
+    }
+
+    fn max_transfer_size(&self) -> usize {
+        PRDT_ENTRIES * Size4KiB::SIZE as usize
+    }
+
+    fn read_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        let prdt = frames
+            .iter()
+            .map(|frame| (frame.start_address(), Size4KiB::SIZE as usize))
+            .collect::<Vec<_>>();
+
+        self.transfer(false, sector, &prdt)
+    }
+
+    fn write_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        let prdt = frames
+            .iter()
+            .map(|frame| (frame.start_address(), Size4KiB::SIZE as usize))
+            .collect::<Vec<_>>();
+
+        self.transfer(true, sector, &prdt)
+    }
+}
+
+/// An implemented port of the HBA.
+struct PortSlot {
+    state: PortState,
+    memory: PortMemory,
+    port: Option<Arc<AhciPort>>,
+    device: Option<Arc<BlockDevice>>,
+}
+
+impl PortSlot {
+    fn new() -> Self {
+        Self {
+            state: PortState::Empty,
+            memory: PortMemory::alloc(),
+            port: None,
+            device: None,
+        }
     }
 }
 
 struct AhciProtected {
-    ports: [Option<Arc<AhciPort>>; 32],
+    ports: [Option<PortSlot>; 32],
     hba: VirtAddr,
 }
 
 impl AhciProtected {
     #[inline]
-    fn hba_mem(&self) -> &mut HbaMemory {
-        unsafe { &mut *(self.hba.as_u64() as *mut HbaMemory) }
+    fn hba_mem(&self) -> &'static HbaMemory {
+        hba_mem(self.hba)
     }
 
-    fn start_hba(
-        &mut self,
-        offset_table: &mut OffsetPageTable,
-    ) -> Result<(), MapToError<Size4KiB>> {
-        let mut hba = self.hba_mem();
-        let current_flags = hba.global_host_control.get();
+    /// Runs the state machine of the port at `index` for `event`. Partitions of an attached
+    /// disk are only scanned if `scan` is set, as the disks found at boot are scanned when the
+    /// filesystem is launched.
+    fn handle_event(&mut self, index: usize, event: PortEvent, scan: bool) {
+        let Some(slot) = self.ports[index].as_mut() else {
+            return;
+        };
 
-        hba.global_host_control.set(current_flags | HbaHostCont::IE); // Enable Interrupts
+        let (state, action) = slot.state.next(event);
+
+        log::debug!(
+            "ahci: port {}: {:?} -> {:?} ({:?})",
+            index,
+            slot.state,
+            state,
+            event
+        );
+
+        slot.state = state;
+
+        match action {
+            PortAction::None => {}
+
+            PortAction::Attach => {
+                let event = if self.attach(index, scan) {
+                    PortEvent::Attached
+                } else {
+                    // Leave the port idle until the next connect change.
+                    self.hba_mem().port(index).stop_cmd();
+                    PortEvent::AttachFailed
+                };
+
+                self.handle_event(index, event, scan);
+            }
+
+            PortAction::Detach => self.detach(index),
+        }
+    }
+
+    /// Runs the init sequence of the port at `index` and installs its disk as a block device.
+    fn attach(&mut self, index: usize, scan: bool) -> bool {
+        let port = self.hba_mem().port(index);
+        let slot = self.ports[index].as_mut().unwrap();
+
+        if !port.stop_cmd() {
+            log::warn!("ahci: port {}: failed to stop the command engine", index);
+            return false;
+        }
+
+        port.setup(&slot.memory);
+
+        if !port.reset() {
+            log::warn!("ahci: port {}: no device after COMRESET", index);
+            return false;
+        }
+
+        if !port.wait_ready() {
+            log::warn!("ahci: port {}: device is busy", index);
+            return false;
+        }
+
+        match port.sig.get() {
+            SATA_SIG_ATA => {}
+
+            SATA_SIG_PM => {
+                log::warn!("ahci: port {}: port multipliers are not supported", index);
+                return false;
+            }
+
+            SATA_SIG_ATAPI => {
+                log::warn!("ahci: port {}: ATAPI devices are not supported", index);
+                return false;
+            }
+
+            SATA_SIG_SEMB => {
+                log::warn!(
+                    "ahci: port {}: enclosure management bridges are not supported",
+                    index
+                );
+                return false;
+            }
+
+            signature => {
+                log::warn!("ahci: port {}: unknown signature {:#x}", index, signature);
+                return false;
+            }
+        }
+
+        if !port.start_cmd() {
+            log::warn!("ahci: port {}: failed to start the command engine", index);
+            return false;
+        }
+
+        let identify = Dma::<[u16; 256]>::zeroed();
+        let aborted = AtomicBool::new(false);
+
+        if !port.run_command(
+            AtaCommand::IdentifyDevice,
+            0,
+            0,
+            &[(identify.addr(), SECTOR_SIZE)],
+            &aborted,
+        ) {
+            log::warn!("ahci: port {}: IDENTIFY DEVICE failed", index);
+            return false;
+        }
+
+        let (sectors, model) = parse_identify(&identify);
+
+        if sectors == 0 {
+            log::warn!("ahci: port {}: disk `{}` has no sectors", index, model);
+            return false;
+        }
+
+        let name = alloc::format!("sd{}", (b'a' + index as u8) as char);
+        log::info!(
+            "ahci: port {}: found disk `{}` ({}, {} sectors)",
+            index,
+            model,
+            name,
+            sectors
+        );
+
+        let address = VirtAddr::new(port as *const _ as u64);
+        let port = Arc::new(AhciPort::new(index, address, sectors));
+        let device = BlockDevice::new(name, port.clone());
+
+        if let Err(err) = install_block_device(device.clone()) {
+            log::warn!(
+                "ahci: port {}: failed to install the disk: {:?}",
+                index,
+                err
+            );
+
+            port.remove();
+            return false;
+        }
+
+        slot.port = Some(port);
+        slot.device = Some(device.clone());
+
+        if scan {
+            if let Err(err) = block::scan_partitions(&device) {
+                log::warn!(
+                    "ahci: {}: failed to scan partitions: {:?}",
+                    device.name(),
+                    err
+                );
+            }
+        }
+
+        true
+    }
+
+    /// Removes the disk of the port at `index` after it was unplugged. The commands in flight
+    /// and the I/O on the files that are still open fail with EIO.
+    fn detach(&mut self, index: usize) {
+        let slot = self.ports[index].as_mut().unwrap();
+
+        if let Some(port) = slot.port.take() {
+            port.remove();
+        }
+
+        if let Some(device) = slot.device.take() {
+            log::warn!("ahci: port {}: {} was removed", index, device.name());
+
+            if let Err(err) = block::uninstall_block_device(&device) {
+                log::error!("ahci: failed to uninstall {}: {:?}", device.name(), err);
+            }
+        }
+
+        self.hba_mem().port(index).stop_cmd();
+    }
+
+    fn start_hba(&mut self) {
+        let hba = self.hba_mem();
+
+        // Switch the HBA to AHCI mode before accessing the ports.
+        let current_flags = hba.global_host_control.get();
+        hba.global_host_control.set(current_flags | HbaHostCont::AE);
 
         let version = hba.version.get();
         let major_version = version >> 16 & 0xffff;
@@ -861,35 +1284,54 @@ impl AhciProtected {
             minor_version
         );
 
+        let capabilities = hba.host_capability.get();
         let pi = hba.ports_implemented.get();
 
-        for i in 0..32 {
-            if pi.get_bit(i) {
-                let port = hba.port_mut(i);
+        for i in (0..32).filter(|i| pi.get_bit(*i)) {
+            let port = hba.port(i);
+            port.stop_cmd();
 
-                if port.probe(offset_table, i)? {
-                    // Get the address of the HBA port.
-                    let address = VirtAddr::new(port as *const _ as _);
-                    let port = Arc::new(AhciPort::new(address));
+            if capabilities.contains(HbaCapabilities::SSS) {
+                let value = port.cmd.get() | HbaPortCmd::SUD;
+                port.cmd.set(value);
+            }
 
-                    // Add the port to the ports array.
-                    self.ports[i] = Some(port);
+            // Only the connect changes are interrupt driven, commands are polled.
+            port.serr.set(u32::MAX);
+            port.is.set(HbaPortIS::all());
+            port.ie.set(HbaPortIE::PCE | HbaPortIE::PRCE);
 
-                    // Workaround to get access to the HBA and still satisfy the
-                    // borrow checker.
-                    hba = self.hba_mem();
-                }
-            }
+            self.ports[i] = Some(PortSlot::new());
+
+            let event = if port.is_present() {
+                PortEvent::Connected
+            } else {
+                PortEvent::Disconnected
+            };
+
+            self.handle_event(i, event, false);
         }
 
-        Ok(())
+        hba.interrupt_status.set(u32::MAX);
+
+        let current_flags = hba.global_host_control.get();
+        hba.global_host_control.set(current_flags | HbaHostCont::IE); // Enable Interrupts
     }
 
-    /// This function is responsible for enabling bus mastering and add AHCI
-    /// IRQ handler.
+    /// This function is responsible for routing the legacy IRQ of the HBA to [`irq_handler`].
     fn enable_interrupts(&mut self, header: &PciHeader) {
-        header.enable_mmio();
-        header.enable_bus_mastering();
+        let gsi = aml::get_subsystem().pci_route_pin(
+            0,
+            header.bus(),
+            header.device(),
+            header.function(),
+            header.interrupt_pin(),
+        );
+
+        let vector = interrupts::allocate_vector();
+        interrupts::register_handler(vector, irq_handler);
+
+        crate::arch::apic::io_apic_setup_legacy_irq(gsi, vector, 0);
     }
 
     /// This function is responsible for initializing and starting the AHCI driver.
@@ -897,6 +1339,9 @@ impl AhciProtected {
         let mut address_space = AddressSpace::this();
         let mut offset_table = address_space.offset_page_table();
 
+        header.enable_mmio();
+        header.enable_bus_mastering();
+
         let abar = header.get_bar(5).expect("Failed to get ABAR");
 
         let (abar_address, _) = match abar {
@@ -907,20 +1352,26 @@ impl AhciProtected {
 
         self.hba = crate::IO_VIRTUAL_BASE + abar_address; // Update the HBA address.
 
-        unsafe {
-            offset_table.map_to(
-                Page::containing_address(self.hba),
-                PhysFrame::containing_address(PhysAddr::new(abar_address)),
-                PageTableFlags::PRESENT
-                    | PageTableFlags::NO_CACHE
-                    | PageTableFlags::WRITABLE
-                    | PageTableFlags::WRITE_THROUGH,
-            )
-        }?
-        .flush();
-
-        self.start_hba(&mut offset_table)?;
+        // The registers of the 32 ports follow the generic host control registers, which
+        // spans two pages.
+        for offset in (0..0x2000u64).step_by(0x1000) {
+            unsafe {
+                offset_table.map_to(
+                    Page::containing_address(self.hba + offset),
+                    PhysFrame::containing_address(PhysAddr::new(abar_address + offset)),
+                    PageTableFlags::PRESENT
+                        | PageTableFlags::NO_CACHE
+                        | PageTableFlags::WRITABLE
+                        | PageTableFlags::WRITE_THROUGH,
+                )
+            }?
+            .flush();
+        }
+
+        get_ahci().hba.call_once(|| self.hba);
+
         self.enable_interrupts(header);
+        self.start_hba();
 
         Ok(())
     }
@@ -929,6 +1380,11 @@ impl AhciProtected {
 /// Structure representing the ACHI driver.
 struct AhciDriver {
     inner: Mutex<AhciProtected>,
+    /// The address of the HBA registers, used by the IRQ handler.
+    hba: Once<VirtAddr>,
+    /// Bit mask of the ports with a connect change that was not handled yet.
+    events: Mutex<u32>,
+    wq: WaitQueue,
 }
 
 impl PciDeviceHandle for AhciDriver {
@@ -944,11 +1400,67 @@ impl PciDeviceHandle for AhciDriver {
 
         get_ahci().inner.lock_irq().start_driver(header).unwrap(); // Start and initialize the AHCI controller.
 
-        // Temporary testing...
-        if let Some(port) = get_ahci().inner.lock().ports[0].clone() {
-            let buffer = &mut [0u8; 512];
-            port.read(0, buffer);
-            log::info!("Read sector 0: {:?}", buffer);
+        scheduler::get_scheduler().register_task(Task::new_kernel(hotplug_worker, true));
+    }
+}
+
+/// Acknowledges the connect changes of the ports and hands them to [`hotplug_worker`], as
+/// initializing a port takes too long to be done here.
+fn irq_handler(_stack: &mut InterruptStack) {
+    let Some(driver) = DRIVER.get() else {
+        return;
+    };
+
+    let Some(&hba) = driver.hba.get() else {
+        return;
+    };
+
+    let hba = hba_mem(hba);
+    let status = hba.interrupt_status.get();
+    let mut changed = 0;
+
+    for i in (0..32).filter(|i| status.get_bit(*i)) {
+        let port = hba.port(i);
+
+        if port.is.get().intersects(HbaPortIS::PCS | HbaPortIS::PRCS) {
+            // PCS and PRCS are cleared through the SError bits they mirror.
+            port.serr.set(SERR_DIAG_X | SERR_DIAG_N);
+            changed |= 1 << i;
+        }
+    }
+
+    hba.interrupt_status.set(status);
+
+    if changed != 0 {
+        *driver.events.lock_irq() |= changed;
+        driver.wq.notify();
+    }
+}
+
+/// Attaches and detaches the disks of the ports with a connect change.
+fn hotplug_worker() {
+    let driver = get_ahci();
+
+    loop {
+        let changed = {
+            let mut events = driver
+                .wq
+                .block_on(&driver.events, |events| **events != 0)
+                .expect("ahci: unexpected signal in the hot-plug worker");
+
+            core::mem::take(&mut **events)
+        };
+
+        let mut inner = driver.inner.lock();
+
+        for i in (0..32).filter(|i| changed.get_bit(*i)) {
+            let event = if inner.hba_mem().port(i).is_present() {
+                PortEvent::Connected
+            } else {
+                PortEvent::Disconnected
+            };
+
+            inner.handle_event(i, event, true);
         }
     }
 }
@@ -964,13 +1476,16 @@ fn get_ahci() -> &'static Arc<AhciDriver> {
 pub fn ahci_init() {
     // Initialize the AHCI driver instance.
     DRIVER.call_once(|| {
-        const EMPTY: Option<Arc<AhciPort>> = None; // To satisfy the Copy trait bound when the AHCI creating data.
+        const EMPTY: Option<PortSlot> = None; // To satisfy the Copy trait bound when the AHCI creating data.
 
         Arc::new(AhciDriver {
             inner: Mutex::new(AhciProtected {
                 ports: [EMPTY; 32],    // Initialize the AHCI ports to an empty slice.
                 hba: VirtAddr::zero(), // Initialize the AHCI HBA address to zero.
             }),
+            hba: Once::new(),
+            events: Mutex::new(0),
+            wq: WaitQueue::new(),
         })
     });
 
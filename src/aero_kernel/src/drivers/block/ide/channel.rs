@@ -20,7 +20,7 @@ use bit_field::BitField;
 
 use super::registers::*;
 
-use crate::drivers::block::ahci::{AtaCommand, DmaBuffer, DmaRequest};
+use crate::drivers::block::ahci::{self, AtaCommand, DmaBuffer, DmaRequest};
 use crate::mem::paging::*;
 
 use crate::arch::io::delay;
@@ -112,7 +112,8 @@ impl IdeChannelData {
         self.ctrl.software_reset();
     }
 
-    pub fn detect(&mut self, slave: bool) -> bool {
+    /// Returns the number of sectors of the drive, or `None` if there is no ATA drive.
+    pub fn detect(&mut self, slave: bool) -> Option<usize> {
         self.software_reset();
 
         let mut sel = BaseDriveSelReg::new();
@@ -127,26 +128,34 @@ impl IdeChannelData {
         let status = self.base.status();
 
         if status.is_empty() {
-            return false;
+            return None;
         }
 
         loop {
             if let Some(status) = self.base.try_status() {
                 if status.contains(BaseStatusReg::ERR) {
-                    return false;
+                    return None;
                 }
                 if !status.contains(BaseStatusReg::BSY) && status.contains(BaseStatusReg::DRQ) {
                     break;
                 }
             } else {
-                return false;
+                return None;
             }
         }
 
         let lm = self.base.lba_mid();
         let lh = self.base.lba_hi();
 
-        lm == 0 && lh == 0
+        if lm != 0 || lh != 0 {
+            return None;
+        }
+
+        let mut identify = [0u16; 256];
+        identify.fill_with(|| self.base.data());
+
+        let (sectors, _) = ahci::parse_identify(&identify);
+        Some(sectors)
     }
 
     pub fn setup_prdt(&mut self) {
@@ -240,7 +249,7 @@ impl IdeChannel {
         })
     }
 
-    pub fn detect(&self, slave: bool) -> bool {
+    pub fn detect(&self, slave: bool) -> Option<usize> {
         self.data.lock_irq().detect(slave)
     }
 
@@ -40,11 +40,16 @@ static DRIVER: Once<Arc<Ide>> = Once::new();
 pub struct IdeDrive {
     slave: bool,
     channel: Arc<IdeChannel>,
+    sectors: usize,
 }
 
 impl IdeDrive {
-    pub fn new(slave: bool, channel: Arc<IdeChannel>) -> Arc<IdeDrive> {
-        Arc::new(IdeDrive { slave, channel })
+    pub fn new(slave: bool, channel: Arc<IdeChannel>, sectors: usize) -> Arc<IdeDrive> {
+        Arc::new(IdeDrive {
+            slave,
+            channel,
+            sectors,
+        })
     }
 }
 
@@ -64,7 +69,11 @@ impl BlockDeviceInterface for IdeDrive {
     }
 
     fn block_size(&self) -> usize {
-        todo!()
+        512
+    }
+
+    fn block_count(&self) -> usize {
+        self.sectors
     }
 
     fn read_dma(
@@ -162,8 +171,8 @@ impl IdeDevice {
         let mut idx = 0;
         for (ci, c) in [c1, c2].iter().enumerate() {
             for &s in [false, true].iter() {
-                if c.detect(s) {
-                    self.ide_devs[idx] = Some(IdeDrive::new(s, c.clone()));
+                if let Some(sectors) = c.detect(s) {
+                    self.ide_devs[idx] = Some(IdeDrive::new(s, c.clone(), sectors));
                     idx += 1;
 
                     if self.channels[ci].is_none() {
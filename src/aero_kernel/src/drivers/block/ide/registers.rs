@@ -23,6 +23,7 @@ use crate::mem::paging::PhysAddr;
 use crate::arch::io;
 use crate::arch::io::BasedPort;
 
+const BASE_DATA: u16 = 0;
 const BASE_FEATURE: u16 = 1;
 const BASE_SECTOR_COUNT: u16 = 2;
 const BASE_LBA_LO: u16 = 3;
@@ -210,6 +211,10 @@ impl DevBaseReg {
         self.base.read_offset::<u8>(BASE_LBA_HI)
     }
 
+    pub fn data(&self) -> u16 {
+        self.base.read_offset::<u16>(BASE_DATA)
+    }
+
     pub fn set_sector_num(&mut self, lba48: bool, sector: usize) {
         match lba48 {
             true => self.set_sector_num_lba48(sector),
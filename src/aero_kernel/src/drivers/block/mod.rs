@@ -3,3 +3,4 @@ pub mod ahci;
 #[cfg(target_arch = "x86_64")]
 pub mod ide;
 pub mod nvme;
+pub mod zram;
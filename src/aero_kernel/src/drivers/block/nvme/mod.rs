@@ -261,6 +261,48 @@ impl<'a> Namespace<'a> {
 
         self.controller.io_queue.lock().submit_command(read_cmd);
     }
+
+    /// Returns the maximum number of pages transferred by a single command. Limited to what a
+    /// single page of PRP entries can describe.
+    fn max_frames(&self) -> usize {
+        self.max_prps
+            .min(Size4KiB::SIZE as usize / core::mem::size_of::<u64>())
+    }
+
+    /// Same as [`Namespace::rw_command`], but transfers one page from or into each of the
+    /// (possibly non-contiguous) `frames`.
+    fn rw_frames(&self, opcode: CommandOpcode, sector: usize, frames: &[PhysFrame]) {
+        assert!(!frames.is_empty() && frames.len() <= self.max_frames());
+
+        let blocks = (frames.len() * Size4KiB::SIZE as usize) / self.block_size;
+        let mut command = ReadWriteCommand {
+            opcode: opcode as u8,
+            nsid: self.nsid,
+            start_lba: sector as u64,
+            length: (blocks - 1) as u16,
+            ..Default::default()
+        };
+
+        command.data_ptr.prp1 = frames[0].start_address().as_u64();
+
+        // The PRP list is used by the command, so keep it locked until it completes.
+        let mut prps = self.prps.lock();
+
+        match frames {
+            [_] => {}
+            [_, second] => command.data_ptr.prp2 = second.start_address().as_u64(),
+            [_, rest @ ..] => {
+                for (entry, frame) in prps.iter_mut().zip(rest) {
+                    entry.write(frame.start_address().as_u64());
+                }
+
+                command.data_ptr.prp2 = prps.addr().as_u64();
+            }
+            [] => unreachable!(),
+        }
+
+        self.controller.io_queue.lock().submit_command(command);
+    }
 }
 
 struct Controller<'a> {
@@ -501,9 +543,27 @@ impl<'a> BlockDeviceInterface for Controller<'a> {
         self.namespaces.lock()[0].block_size
     }
 
+    fn block_count(&self) -> usize {
+        self.namespaces.lock()[0].blocks
+    }
+
     fn write_block(&self, _sector: usize, _buf: &[u8]) -> Option<usize> {
         unimplemented!()
     }
+
+    fn max_transfer_size(&self) -> usize {
+        self.namespaces.lock()[0].max_frames() * Size4KiB::SIZE as usize
+    }
+
+    fn read_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        self.namespaces.lock()[0].rw_frames(CommandOpcode::Read, sector, frames);
+        Some(frames.len() * Size4KiB::SIZE as usize)
+    }
+
+    fn write_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        self.namespaces.lock()[0].rw_frames(CommandOpcode::Write, sector, frames);
+        Some(frames.len() * Size4KiB::SIZE as usize)
+    }
 }
 
 // PCI device handler for NVMe controllers.
@@ -0,0 +1,195 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! LZ4 block format compression.
+//!
+//! The input is split into sequences of literals followed by a match, which copies bytes
+//! already decompressed:
+//!
+//! ```text
+//! token: u8 (literal length << 4 | (match length - 4))
+//! [extra literal length bytes]
+//! literals
+//! match offset: u16 (little endian)
+//! [extra match length bytes]
+//! ```
+//!
+//! A length of 15 in the token is continued by bytes added to it, up to the first one that is
+//! not 255. The last sequence only has literals. The compressor does not try hard to find
+//! matches: every position is hashed once, and the most recent position with the same hash is
+//! the only candidate.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+const MIN_MATCH: usize = 4;
+
+/// A match must start at least this many bytes before the end of the input.
+const MF_LIMIT: usize = 12;
+/// The input always ends with at least this many literals.
+const LAST_LITERALS: usize = 5;
+
+const HASH_LOG: u32 = 12;
+
+/// Compresses `input`, which must be smaller than 64KiB.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    assert!(input.len() <= u16::MAX as usize);
+
+    let mut output = Vec::with_capacity(input.len() + input.len() / 255 + 16);
+
+    // The most recent position of each hash.
+    let mut table = vec![0u16; 1 << HASH_LOG];
+
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    if input.len() > MF_LIMIT {
+        let match_limit = input.len() - MF_LIMIT;
+        let end_limit = input.len() - LAST_LITERALS;
+
+        while pos < match_limit {
+            let sequence = read_u32(input, pos);
+            let hash = hash(sequence);
+
+            let candidate = table[hash] as usize;
+            table[hash] = pos as u16;
+
+            if candidate >= pos || read_u32(input, candidate) != sequence {
+                pos += 1;
+                continue;
+            }
+
+            let mut len = MIN_MATCH;
+
+            while pos + len < end_limit && input[candidate + len] == input[pos + len] {
+                len += 1;
+            }
+
+            write_literals(&mut output, &input[anchor..pos], len - MIN_MATCH);
+            output.extend_from_slice(&((pos - candidate) as u16).to_le_bytes());
+
+            if len - MIN_MATCH >= 15 {
+                write_length(&mut output, len - MIN_MATCH - 15);
+            }
+
+            pos += len;
+            anchor = pos;
+        }
+    }
+
+    write_literals(&mut output, &input[anchor..], 0);
+    output
+}
+
+/// Decompresses `input` into `output` and returns the size of the decompressed data, or
+/// [`None`] if the input is malformed or does not fit.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut ip = 0;
+    let mut op = 0;
+
+    loop {
+        let token = *input.get(ip)?;
+        ip += 1;
+
+        let mut literals = (token >> 4) as usize;
+
+        if literals == 15 {
+            literals += read_length(input, &mut ip)?;
+        }
+
+        output
+            .get_mut(op..op + literals)?
+            .copy_from_slice(input.get(ip..ip + literals)?);
+
+        ip += literals;
+        op += literals;
+
+        if ip == input.len() {
+            return Some(op);
+        }
+
+        let offset = u16::from_le_bytes([*input.get(ip)?, *input.get(ip + 1)?]) as usize;
+        ip += 2;
+
+        if offset == 0 || offset > op {
+            return None;
+        }
+
+        let mut len = (token & 0xf) as usize;
+
+        if len == 15 {
+            len += read_length(input, &mut ip)?;
+        }
+
+        len += MIN_MATCH;
+
+        if op + len > output.len() {
+            return None;
+        }
+
+        // The match may overlap with the bytes it produces, so it is copied one byte at a time.
+        for i in op..op + len {
+            output[i] = output[i - offset];
+        }
+
+        op += len;
+    }
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+fn read_u32(input: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(input[pos..pos + 4].try_into().unwrap())
+}
+
+fn read_length(input: &[u8], ip: &mut usize) -> Option<usize> {
+    let mut len = 0;
+
+    loop {
+        let byte = *input.get(*ip)?;
+
+        *ip += 1;
+        len += byte as usize;
+
+        if byte != 255 {
+            return Some(len);
+        }
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        output.push(255);
+        len -= 255;
+    }
+
+    output.push(len as u8);
+}
+
+/// Writes the token and the literals of a sequence whose match length, minus the minimum, is
+/// `match_len`.
+fn write_literals(output: &mut Vec<u8>, literals: &[u8], match_len: usize) {
+    output.push(((literals.len().min(15) << 4) | match_len.min(15)) as u8);
+
+    if literals.len() >= 15 {
+        write_length(output, literals.len() - 15);
+    }
+
+    output.extend_from_slice(literals);
+}
@@ -0,0 +1,291 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! `zram0`, a block device whose pages are compressed and kept in memory.
+//!
+//! The size of the device is set with the `zram.size=<size>` kernel command line option, and the
+//! device is not created without it. Each page is stored in one of the following ways:
+//!
+//! * Not at all, if it has never been written. It reads as zeroes.
+//! * As a single 64-bit value, if the page is that value repeated (e.g. a zero page).
+//! * Compressed with LZ4 on the kernel heap, or as-is if it does not compress.
+//!
+//! The statistics are available in `/proc/zram`.
+
+pub mod lz4;
+
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+
+use spin::Once;
+
+use crate::fs::block::{self, BlockDevice, BlockDeviceInterface};
+use crate::mem::paging::{PageSize, PhysAddr, Size4KiB};
+use crate::utils::sync::Mutex;
+
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
+const SECTOR_SIZE: usize = 512;
+
+static ZRAM: Once<Arc<Zram>> = Once::new();
+
+enum Slot {
+    /// The page is the value repeated.
+    Filled(u64),
+    /// The compressed page, or the page itself if it did not compress.
+    Stored(Box<[u8]>),
+}
+
+impl Slot {
+    fn new(page: &[u8]) -> Self {
+        if let Some(value) = filled_value(page) {
+            return Self::Filled(value);
+        }
+
+        let compressed = lz4::compress(page);
+
+        if compressed.len() < PAGE_SIZE {
+            Self::Stored(compressed.into_boxed_slice())
+        } else {
+            Self::Stored(page.into())
+        }
+    }
+
+    fn compressed_size(&self) -> usize {
+        match self {
+            Self::Filled(_) => 0,
+            Self::Stored(data) => data.len(),
+        }
+    }
+
+    fn read(&self, page: &mut [u8]) {
+        match self {
+            Self::Filled(value) => {
+                for word in page.chunks_exact_mut(8) {
+                    word.copy_from_slice(&value.to_ne_bytes());
+                }
+            }
+
+            Self::Stored(data) if data.len() == PAGE_SIZE => page.copy_from_slice(data),
+            Self::Stored(data) => {
+                let size = lz4::decompress(data, page);
+                assert_eq!(size, Some(PAGE_SIZE), "zram: corrupted page");
+            }
+        }
+    }
+}
+
+/// Returns the value that `page` is filled with, if any.
+fn filled_value(page: &[u8]) -> Option<u64> {
+    let mut words = page
+        .chunks_exact(8)
+        .map(|word| u64::from_ne_bytes(word.try_into().unwrap()));
+
+    let first = words.next()?;
+    words.all(|word| word == first).then_some(first)
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZramStats {
+    /// Size of the pages that have been written, in bytes.
+    pub orig_data_size: usize,
+    /// Size of the compressed pages, in bytes.
+    pub compr_data_size: usize,
+    /// Memory used by the compressed pages and the bookkeeping of every page, in bytes.
+    pub mem_used_total: usize,
+    /// Number of pages stored as a single repeated value.
+    pub same_pages: usize,
+}
+
+impl ZramStats {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        for (name, value) in [
+            ("orig_data_size", self.orig_data_size),
+            ("compr_data_size", self.compr_data_size),
+            ("mem_used_total", self.mem_used_total),
+            ("same_pages", self.same_pages),
+        ] {
+            let _ = writeln!(out, "{name} {value}");
+        }
+
+        out
+    }
+}
+
+pub struct Zram {
+    /// Size of the device, in bytes. It is a multiple of the page size.
+    size: usize,
+    /// The pages that have been written, by index.
+    slots: Mutex<BTreeMap<usize, Slot>>,
+}
+
+impl Zram {
+    pub fn new(size: usize) -> Arc<Self> {
+        assert_eq!(size % PAGE_SIZE, 0);
+
+        Arc::new(Self {
+            size,
+            slots: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn stats(&self) -> ZramStats {
+        let slots = self.slots.lock();
+
+        let compr_data_size = slots.values().map(Slot::compressed_size).sum();
+        let same_pages = slots
+            .values()
+            .filter(|slot| matches!(slot, Slot::Filled(_)))
+            .count();
+
+        ZramStats {
+            orig_data_size: slots.len() * PAGE_SIZE,
+            compr_data_size,
+            mem_used_total: compr_data_size + slots.len() * core::mem::size_of::<(usize, Slot)>(),
+            same_pages,
+        }
+    }
+
+    fn read_page(&self, index: usize, page: &mut [u8]) {
+        match self.slots.lock().get(&index) {
+            Some(slot) => slot.read(page),
+            None => page.fill(0),
+        }
+    }
+
+    fn write_page(&self, index: usize, page: &[u8]) {
+        // Compress the page before taking the lock.
+        let slot = Slot::new(page);
+        self.slots.lock().insert(index, slot);
+    }
+
+    /// Reads `buffer.len()` bytes starting at the byte `offset` of the device.
+    pub fn read(&self, offset: usize, buffer: &mut [u8]) -> Option<usize> {
+        if offset.checked_add(buffer.len())? > self.size {
+            return None;
+        }
+
+        let mut page = vec![0; PAGE_SIZE];
+        let mut done = 0;
+
+        while done < buffer.len() {
+            let position = offset + done;
+            let page_offset = position % PAGE_SIZE;
+            let size = (PAGE_SIZE - page_offset).min(buffer.len() - done);
+            let dest = &mut buffer[done..done + size];
+
+            if size == PAGE_SIZE {
+                self.read_page(position / PAGE_SIZE, dest);
+            } else {
+                self.read_page(position / PAGE_SIZE, &mut page);
+                dest.copy_from_slice(&page[page_offset..page_offset + size]);
+            }
+
+            done += size;
+        }
+
+        Some(done)
+    }
+
+    /// Writes `buffer` starting at the byte `offset` of the device.
+    pub fn write(&self, offset: usize, buffer: &[u8]) -> Option<usize> {
+        if offset.checked_add(buffer.len())? > self.size {
+            return None;
+        }
+
+        let mut page = vec![0; PAGE_SIZE];
+        let mut done = 0;
+
+        while done < buffer.len() {
+            let position = offset + done;
+            let page_offset = position % PAGE_SIZE;
+            let size = (PAGE_SIZE - page_offset).min(buffer.len() - done);
+            let src = &buffer[done..done + size];
+
+            if size == PAGE_SIZE {
+                self.write_page(position / PAGE_SIZE, src);
+            } else {
+                // Partial writes have to update the rest of the page.
+                self.read_page(position / PAGE_SIZE, &mut page);
+                page[page_offset..page_offset + size].copy_from_slice(src);
+                self.write_page(position / PAGE_SIZE, &page);
+            }
+
+            done += size;
+        }
+
+        Some(done)
+    }
+}
+
+impl BlockDeviceInterface for Zram {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> usize {
+        self.size / SECTOR_SIZE
+    }
+
+    fn read_dma(&self, sector: usize, start: PhysAddr, size: usize) -> Option<usize> {
+        let buffer = start.as_hhdm_virt().as_bytes_mut(size);
+        self.read(sector * SECTOR_SIZE, buffer)
+    }
+
+    fn write_dma(&self, sector: usize, start: PhysAddr, size: usize) -> Option<usize> {
+        let buffer = start.as_hhdm_virt().as_bytes_mut(size);
+        self.write(sector * SECTOR_SIZE, buffer)
+    }
+
+    fn read_block(&self, sector: usize, dest: &mut [MaybeUninit<u8>]) -> Option<usize> {
+        let mut buffer = vec![0; dest.len()];
+        let size = self.read(sector * SECTOR_SIZE, &mut buffer)?;
+
+        MaybeUninit::copy_from_slice(dest, &buffer);
+        Some(size)
+    }
+
+    fn write_block(&self, sector: usize, buf: &[u8]) -> Option<usize> {
+        self.write(sector * SECTOR_SIZE, buf)
+    }
+}
+
+/// Returns the `zram0` device, if it was created.
+pub fn get() -> Option<&'static Arc<Zram>> {
+    ZRAM.get()
+}
+
+fn zram_init() {
+    let Some(size) = crate::cmdline::get().and_then(|cmdline| cmdline.zram_size) else {
+        return;
+    };
+
+    let zram = ZRAM.call_once(|| Zram::new(size));
+    let device = BlockDevice::new("zram0".into(), zram.clone());
+
+    block::install_block_device(device).unwrap();
+    log::info!("zram: created zram0 ({} KiB)", size / 1024);
+}
+
+crate::module_init!(zram_init, ModuleType::Block);
@@ -17,17 +17,20 @@
 
 mod rawfb;
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use bit_field::BitField;
 use hashbrown::HashMap;
 
-use crate::arch::user_copy::UserRef;
-use crate::fs;
-use crate::fs::inode::INodeInterface;
+use crate::arch::user_copy::{copy_from_user, copy_slice_to_user, UserRef};
+use crate::fs::cache::DirCacheItem;
+use crate::fs::file_table::FileHandle;
+use crate::fs::inode::{INodeInterface, MappableRegion};
 use crate::fs::{devfs, FileSystemError};
+use crate::{fs, rendy};
 
 use crate::mem::paging::*;
 use crate::utils::sync::Mutex;
@@ -42,6 +45,11 @@ trait ModeObject: Send + Sync {
     fn id(&self) -> u32;
     fn object(&self) -> Arc<dyn ModeObject>;
 
+    /// Returns the properties attached to this mode object along with their current values.
+    fn properties(&self) -> Vec<(PropertyKind, u64)> {
+        Vec::new()
+    }
+
     // Conversion methods:
 
     /// Converts this mode object into a connector.
@@ -63,6 +71,16 @@ trait ModeObject: Send + Sync {
     fn as_framebuffer(&self) -> Option<Arc<Framebuffer>> {
         self.object().downcast_arc::<Framebuffer>()
     }
+
+    /// Converts this mode object into a plane.
+    fn as_plane(&self) -> Option<Arc<Plane>> {
+        self.object().downcast_arc::<Plane>()
+    }
+
+    /// Converts this mode object into a property.
+    fn as_property(&self) -> Option<Arc<Property>> {
+        self.object().downcast_arc::<Property>()
+    }
 }
 
 trait DrmDevice: Send + Sync {
@@ -73,6 +91,10 @@ trait DrmDevice: Send + Sync {
     fn framebuffer_create(&self, buffer_object: &BufferObject, width: u32, height: u32, pitch: u32);
     fn commit(&self, buffer_obj: &BufferObject);
 
+    /// Checks whether the hardware can show `plane` in `state`, which has a framebuffer
+    /// attached. Only called for the planes installed by the driver.
+    fn check_plane(&self, plane: &Plane, state: &PlaneState) -> fs::Result<()>;
+
     /// Returns tuple containing the minimum dimensions (`xmin`, `ymin`).
     fn min_dim(&self) -> (usize, usize);
     /// Returns tuple containing the maximum dimensions (`xmax`, `ymax`).
@@ -82,6 +104,13 @@ trait DrmDevice: Send + Sync {
     fn driver_version(&self) -> (usize, usize, usize);
     /// Returns a tuple containing the driver name, desc and date respectively.
     fn driver_info(&self) -> (&'static str, &'static str, &'static str);
+
+    /// Called after the last client closed the device. Returns the framebuffer the kernel
+    /// console is handed back, which is the boot framebuffer unless the driver reprogrammed
+    /// the display to scan out of another one.
+    fn restore_console(&self) -> rendy::Scanout {
+        rendy::boot_scanout()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -194,6 +223,264 @@ impl ModeObject for Encoder {
     }
 }
 
+/// Properties of the mode objects, through which `DRM_IOCTL_MODE_ATOMIC` updates them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PropertyKind {
+    Type,
+    FbId,
+    CrtcId,
+    CrtcX,
+    CrtcY,
+    CrtcW,
+    CrtcH,
+    SrcX,
+    SrcY,
+    SrcW,
+    SrcH,
+}
+
+impl PropertyKind {
+    const ALL: [PropertyKind; 11] = [
+        PropertyKind::Type,
+        PropertyKind::FbId,
+        PropertyKind::CrtcId,
+        PropertyKind::CrtcX,
+        PropertyKind::CrtcY,
+        PropertyKind::CrtcW,
+        PropertyKind::CrtcH,
+        PropertyKind::SrcX,
+        PropertyKind::SrcY,
+        PropertyKind::SrcW,
+        PropertyKind::SrcH,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            PropertyKind::Type => "type",
+            PropertyKind::FbId => "FB_ID",
+            PropertyKind::CrtcId => "CRTC_ID",
+            PropertyKind::CrtcX => "CRTC_X",
+            PropertyKind::CrtcY => "CRTC_Y",
+            PropertyKind::CrtcW => "CRTC_W",
+            PropertyKind::CrtcH => "CRTC_H",
+            PropertyKind::SrcX => "SRC_X",
+            PropertyKind::SrcY => "SRC_Y",
+            PropertyKind::SrcW => "SRC_W",
+            PropertyKind::SrcH => "SRC_H",
+        }
+    }
+
+    fn flags(&self) -> u32 {
+        match self {
+            PropertyKind::Type => DRM_MODE_PROP_ENUM | DRM_MODE_PROP_IMMUTABLE,
+            PropertyKind::FbId | PropertyKind::CrtcId => {
+                DRM_MODE_PROP_OBJECT | DRM_MODE_PROP_ATOMIC
+            }
+            PropertyKind::CrtcX | PropertyKind::CrtcY => {
+                DRM_MODE_PROP_SIGNED_RANGE | DRM_MODE_PROP_ATOMIC
+            }
+            _ => DRM_MODE_PROP_RANGE | DRM_MODE_PROP_ATOMIC,
+        }
+    }
+
+    /// Returns the values of the property: the bounds of a range, the type of the object
+    /// referred to by an object property or the values of an enum.
+    fn values(&self) -> Vec<u64> {
+        match self {
+            PropertyKind::Type => alloc::vec![
+                DrmPlaneType::Overlay as u64,
+                DrmPlaneType::Primary as u64,
+                DrmPlaneType::Cursor as u64,
+            ],
+            PropertyKind::FbId => alloc::vec![DRM_MODE_OBJECT_FB as u64],
+            PropertyKind::CrtcId => alloc::vec![DRM_MODE_OBJECT_CRTC as u64],
+            PropertyKind::CrtcX | PropertyKind::CrtcY => {
+                alloc::vec![i32::MIN as i64 as u64, i32::MAX as u64]
+            }
+            _ => alloc::vec![0, u32::MAX as u64],
+        }
+    }
+
+    /// Returns the names of the values of an enum property.
+    fn enum_names(&self) -> &'static [&'static str] {
+        match self {
+            PropertyKind::Type => &["Overlay", "Primary", "Cursor"],
+            _ => &[],
+        }
+    }
+}
+
+/// Represents a property of the mode objects, which userspace refers to by its ID.
+struct Property {
+    sref: Weak<Self>,
+
+    kind: PropertyKind,
+    object_id: u32,
+}
+
+impl Property {
+    pub fn new(kind: PropertyKind, object_id: u32) -> Arc<Self> {
+        Arc::new_cyclic(|sref| Self {
+            sref: sref.clone(),
+
+            kind,
+            object_id,
+        })
+    }
+}
+
+impl ModeObject for Property {
+    fn id(&self) -> u32 {
+        self.object_id
+    }
+
+    fn object(&self) -> Arc<dyn ModeObject> {
+        self.sref.upgrade().unwrap()
+    }
+}
+
+/// The framebuffer and position of a plane, set through `DRM_IOCTL_MODE_SETPLANE` or its
+/// properties.
+#[derive(Default, Clone)]
+struct PlaneState {
+    crtc: Option<Arc<Crtc>>,
+    fb: Option<Arc<Framebuffer>>,
+
+    // Destination rectangle on the CRTC, in pixels.
+    crtc_x: i32,
+    crtc_y: i32,
+    crtc_w: u32,
+    crtc_h: u32,
+
+    // Source rectangle in the framebuffer, in 16.16 fixed point.
+    src_x: u32,
+    src_y: u32,
+    src_w: u32,
+    src_h: u32,
+}
+
+/// Represents an image source that is blended by a CRTC: the primary plane holds the main
+/// image, while overlay and cursor planes are composited on top of it.
+struct Plane {
+    sref: Weak<Self>,
+
+    plane_type: DrmPlaneType,
+    /// A vector containing all the possible CRTCs for this plane.
+    possible_crtcs: Vec<Arc<Crtc>>,
+    /// The pixel formats (fourcc codes) supported by the plane.
+    formats: Vec<u32>,
+    state: Mutex<PlaneState>,
+
+    object_id: u32,
+}
+
+impl Plane {
+    pub fn new(
+        plane_type: DrmPlaneType,
+        possible_crtcs: Vec<Arc<Crtc>>,
+        formats: Vec<u32>,
+        object_id: u32,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|sref| Self {
+            sref: sref.clone(),
+
+            plane_type,
+            possible_crtcs,
+            formats,
+            state: Mutex::new(PlaneState::default()),
+
+            object_id,
+        })
+    }
+
+    /// Sets the property `kind` to `value` in `state`, which is staged to be committed.
+    fn set_property(
+        &self,
+        drm: &Drm,
+        state: &mut PlaneState,
+        kind: PropertyKind,
+        value: u64,
+    ) -> fs::Result<()> {
+        let to_u32 = |value: u64| u32::try_from(value).map_err(|_| FileSystemError::InvalidInput);
+        // Signed properties are sign-extended to 64 bits.
+        let to_i32 =
+            |value: u64| i32::try_from(value as i64).map_err(|_| FileSystemError::InvalidInput);
+
+        match kind {
+            PropertyKind::Type => return Err(FileSystemError::InvalidInput),
+
+            PropertyKind::FbId if value == 0 => state.fb = None,
+            PropertyKind::FbId => {
+                let fb = drm
+                    .find_object(to_u32(value)?)
+                    .and_then(|object| object.as_framebuffer())
+                    .ok_or(FileSystemError::InvalidInput)?;
+
+                state.fb = Some(fb);
+            }
+
+            PropertyKind::CrtcId if value == 0 => state.crtc = None,
+            PropertyKind::CrtcId => {
+                let crtc = drm
+                    .find_object(to_u32(value)?)
+                    .and_then(|object| object.as_crtc())
+                    .ok_or(FileSystemError::InvalidInput)?;
+
+                if !self.possible_crtcs.iter().any(|e| e.id() == crtc.id()) {
+                    return Err(FileSystemError::InvalidInput);
+                }
+
+                state.crtc = Some(crtc);
+            }
+
+            PropertyKind::CrtcX => state.crtc_x = to_i32(value)?,
+            PropertyKind::CrtcY => state.crtc_y = to_i32(value)?,
+            PropertyKind::CrtcW => state.crtc_w = to_u32(value)?,
+            PropertyKind::CrtcH => state.crtc_h = to_u32(value)?,
+            PropertyKind::SrcX => state.src_x = to_u32(value)?,
+            PropertyKind::SrcY => state.src_y = to_u32(value)?,
+            PropertyKind::SrcW => state.src_w = to_u32(value)?,
+            PropertyKind::SrcH => state.src_h = to_u32(value)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl ModeObject for Plane {
+    fn id(&self) -> u32 {
+        self.object_id
+    }
+
+    fn object(&self) -> Arc<dyn ModeObject> {
+        self.sref.upgrade().unwrap()
+    }
+
+    fn properties(&self) -> Vec<(PropertyKind, u64)> {
+        let state = self.state.lock();
+
+        alloc::vec![
+            (PropertyKind::Type, self.plane_type as u64),
+            (
+                PropertyKind::FbId,
+                state.fb.as_ref().map_or(0, |fb| fb.id()) as u64
+            ),
+            (
+                PropertyKind::CrtcId,
+                state.crtc.as_ref().map_or(0, |crtc| crtc.id()) as u64
+            ),
+            (PropertyKind::CrtcX, state.crtc_x as i64 as u64),
+            (PropertyKind::CrtcY, state.crtc_y as i64 as u64),
+            (PropertyKind::CrtcW, state.crtc_w as u64),
+            (PropertyKind::CrtcH, state.crtc_h as u64),
+            (PropertyKind::SrcX, state.src_x as u64),
+            (PropertyKind::SrcY, state.src_y as u64),
+            (PropertyKind::SrcW, state.src_w as u64),
+            (PropertyKind::SrcH, state.src_h as u64),
+        ]
+    }
+}
+
 /// Represents a display connector; transmits the signal to the display, detects
 /// display connection, removal and exposes the display's supported modes.
 struct Connector {
@@ -249,14 +536,19 @@ struct Framebuffer {
     sref: Weak<Self>,
     object_id: u32,
     buffer_obj: BufferObject, // todo: this should be a reference not a clone.
+
+    /// The geometry the framebuffer was created with, reported back by `DRM_IOCTL_MODE_GETFB`.
+    /// The `fb_id` field is unused.
+    info: DrmModeFbCmd,
 }
 
 impl Framebuffer {
-    pub fn new(object_id: u32, buffer_obj: BufferObject) -> Arc<Self> {
+    pub fn new(object_id: u32, buffer_obj: BufferObject, info: DrmModeFbCmd) -> Arc<Self> {
         Arc::new_cyclic(|sref| Self {
             sref: sref.clone(),
             object_id,
             buffer_obj,
+            info,
         })
     }
 }
@@ -271,24 +563,35 @@ impl ModeObject for Framebuffer {
     }
 }
 
-fn copy_field<T>(buffer: *mut T, buffer_size: &mut usize, value: &[T]) {
-    // do not overflow the user buffer.
-    let mut copy_len = value.len();
+/// Copies `count` elements of the array at `buffer` out of userspace memory.
+fn read_field<T: Copy>(buffer: *const T, count: usize) -> fs::Result<Vec<T>> {
+    let mut result = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let mut value = MaybeUninit::<T>::uninit();
+        copy_from_user(&mut value, buffer.wrapping_add(i))?;
 
-    if *buffer_size > value.len() {
-        copy_len = *buffer_size;
+        // SAFETY: `copy_from_user` initialized the value.
+        result.push(unsafe { value.assume_init() });
     }
 
+    Ok(result)
+}
+
+fn copy_field<T>(buffer: *mut T, buffer_size: &mut usize, value: &[T]) -> fs::Result<()> {
+    // do not overflow the user buffer.
+    let copy_len = core::cmp::min(*buffer_size, value.len());
+
     // let userspace know exact length of driver value (which could be
     // larger than the userspace-supplied buffer).
     *buffer_size = value.len();
 
     // finally, try filling in the user buffer.
     if copy_len != 0 && !buffer.is_null() {
-        unsafe {
-            core::ptr::copy_nonoverlapping(value.as_ptr(), buffer, copy_len);
-        }
+        copy_slice_to_user(buffer, &value[..copy_len])?;
     }
+
+    Ok(())
 }
 
 static DRM_CARD_ID: AtomicUsize = AtomicUsize::new(0);
@@ -327,11 +630,22 @@ struct Drm {
     encoders: Mutex<Vec<Arc<Encoder>>>,
     connectors: Mutex<Vec<Arc<Connector>>>,
     framebuffers: Mutex<Vec<Arc<Framebuffer>>>,
+    planes: Mutex<Vec<Arc<Plane>>>,
+    properties: Mutex<Vec<Arc<Property>>>,
+
+    /// Serializes the updates of the plane states, so a commit is never interleaved with
+    /// another one.
+    modeset_lock: Mutex<()>,
+
+    /// Number of open file handles to the device.
+    clients: AtomicUsize,
+    /// Set once a client has set a CRTC, taking the display over from the kernel console.
+    owns_display: AtomicBool,
 }
 
 impl Drm {
     pub fn new(device: Arc<dyn DrmDevice>) -> Arc<Self> {
-        Arc::new_cyclic(|sref| Self {
+        let drm = Arc::new_cyclic(|sref| Self {
             sref: sref.clone(),
 
             inode: devfs::alloc_device_marker(),
@@ -349,7 +663,23 @@ impl Drm {
             encoders: Mutex::new(alloc::vec![]),
             connectors: Mutex::new(alloc::vec![]),
             framebuffers: Mutex::new(alloc::vec![]),
-        })
+            planes: Mutex::new(alloc::vec![]),
+            properties: Mutex::new(alloc::vec![]),
+
+            modeset_lock: Mutex::new(()),
+
+            clients: AtomicUsize::new(0),
+            owns_display: AtomicBool::new(false),
+        });
+
+        for kind in PropertyKind::ALL {
+            let property = Property::new(kind, drm.allocate_object_id());
+
+            drm.properties.lock().push(property.clone());
+            drm.install_object(property);
+        }
+
+        drm
     }
 
     /// Installs and initializes the CRTC identifier.
@@ -376,6 +706,12 @@ impl Drm {
         self.install_object(fb)
     }
 
+    /// Installs and initializes the plane identifier.
+    pub fn install_plane(&self, plane: Arc<Plane>) {
+        self.planes.lock().push(plane.clone());
+        self.install_object(plane)
+    }
+
     pub fn allocate_object_id(&self) -> u32 {
         self.id_alloc.alloc() as _
     }
@@ -398,6 +734,69 @@ impl Drm {
         self.buffers.lock().insert(handle, buffer);
         handle
     }
+
+    /// Returns the property object of `kind`.
+    fn property(&self, kind: PropertyKind) -> Arc<Property> {
+        self.properties
+            .lock()
+            .iter()
+            .find(|property| property.kind == kind)
+            .cloned()
+            .expect("drm: property not installed")
+    }
+
+    /// Checks the staged plane states with the driver and, unless `test_only` is set, applies
+    /// them all and commits the primary plane to the display once.
+    fn commit_planes(
+        &self,
+        staged: Vec<(Arc<Plane>, PlaneState)>,
+        test_only: bool,
+    ) -> fs::Result<()> {
+        for (plane, state) in staged.iter() {
+            // A plane is either enabled, showing a framebuffer on a CRTC, or disabled.
+            match (&state.fb, &state.crtc) {
+                (Some(_), Some(_)) => self.device.check_plane(plane, state)?,
+                (None, None) => {}
+                _ => return Err(FileSystemError::InvalidInput),
+            }
+        }
+
+        if test_only {
+            return Ok(());
+        }
+
+        let mut scanout = None;
+
+        for (plane, state) in staged {
+            if plane.plane_type == DrmPlaneType::Primary {
+                scanout = state.fb.clone();
+            }
+
+            *plane.state.lock() = state;
+        }
+
+        if let Some(fb) = scanout {
+            self.take_display();
+            self.device.commit(&fb.buffer_obj);
+        }
+
+        Ok(())
+    }
+
+    /// Stops the console from drawing over the scanout of the client.
+    fn take_display(&self) {
+        if !self.owns_display.swap(true, Ordering::SeqCst) {
+            rendy::suspend();
+        }
+    }
+
+    /// Hands the display back to the kernel console once the last client is gone, whether it
+    /// exited cleanly or crashed.
+    fn last_close(&self) {
+        if self.owns_display.swap(false, Ordering::SeqCst) {
+            rendy::reconfigure(self.device.restore_console());
+        }
+    }
 }
 
 impl INodeInterface for Drm {
@@ -406,7 +805,7 @@ impl INodeInterface for Drm {
     fn ioctl(&self, command: usize, arg: usize) -> fs::Result<usize> {
         match command {
             DRM_IOCTL_VERSION => {
-                let mut struc = unsafe { UserRef::<DrmVersion>::new(VirtAddr::new(arg as u64)) };
+                let mut struc = UserRef::<DrmVersion>::new(VirtAddr::new(arg as u64))?;
 
                 let (major, minor, patch_level) = self.device.driver_version();
                 let (name, desc, date) = self.device.driver_info();
@@ -415,15 +814,16 @@ impl INodeInterface for Drm {
                 struc.version_minor = minor as _;
                 struc.version_patch_level = patch_level as _;
 
-                copy_field::<u8>(struc.name, &mut struc.name_len, name.as_bytes());
-                copy_field::<u8>(struc.desc, &mut struc.desc_len, desc.as_bytes());
-                copy_field::<u8>(struc.date, &mut struc.date_len, date.as_bytes());
+                copy_field::<u8>(struc.name, &mut struc.name_len, name.as_bytes())?;
+                copy_field::<u8>(struc.desc, &mut struc.desc_len, desc.as_bytes())?;
+                copy_field::<u8>(struc.date, &mut struc.date_len, date.as_bytes())?;
 
+                struc.write_back()?;
                 Ok(0)
             }
 
             DRM_IOCTL_GET_CAP => {
-                let mut struc = unsafe { UserRef::<DrmGetCap>::new(VirtAddr::new(arg as u64)) };
+                let mut struc = UserRef::<DrmGetCap>::new(VirtAddr::new(arg as u64))?;
 
                 // NOTE: The user is responsible for zeroing out the structure.
                 match struc.capability {
@@ -439,12 +839,12 @@ impl INodeInterface for Drm {
                     }
                 }
 
+                struc.write_back()?;
                 Ok(0)
             }
 
             DRM_IOCTL_MODE_GETRESOURCES => {
-                let mut struc =
-                    unsafe { UserRef::<DrmModeCardRes>::new(VirtAddr::new(arg as u64)) };
+                let mut struc = UserRef::<DrmModeCardRes>::new(VirtAddr::new(arg as u64))?;
 
                 /// Copies the mode object IDs into the user provided buffer. For safety, checkout
                 /// the [`copy_field`] function.
@@ -452,17 +852,18 @@ impl INodeInterface for Drm {
                     obj: &Mutex<Vec<Arc<T>>>,
                     buffer: *mut u32,
                     buffer_size: &mut u32,
-                ) {
+                ) -> fs::Result<()> {
                     let objs = obj.lock();
-                    let mut count_objs = 0;
+                    let mut count_objs = *buffer_size as usize;
 
                     copy_field::<u32>(
                         buffer,
                         &mut count_objs,
                         objs.iter().map(|e| e.id()).collect::<Vec<_>>().as_slice(),
-                    );
+                    )?;
 
                     *buffer_size = count_objs as _;
+                    Ok(())
                 }
 
                 let crtc_id_ptr = struc.crtc_id_ptr as *mut u32;
@@ -470,10 +871,10 @@ impl INodeInterface for Drm {
                 let con_id_ptr = struc.connector_id_ptr as *mut u32;
                 let fb_id_ptr = struc.fb_id_ptr as *mut u32;
 
-                copy_mode_obj_id(&self.crtcs, crtc_id_ptr, &mut struc.count_crtcs);
-                copy_mode_obj_id(&self.encoders, encoder_id_ptr, &mut struc.count_encoders);
-                copy_mode_obj_id(&self.connectors, con_id_ptr, &mut struc.count_connectors);
-                copy_mode_obj_id(&self.framebuffers, fb_id_ptr, &mut struc.count_fbs);
+                copy_mode_obj_id(&self.crtcs, crtc_id_ptr, &mut struc.count_crtcs)?;
+                copy_mode_obj_id(&self.encoders, encoder_id_ptr, &mut struc.count_encoders)?;
+                copy_mode_obj_id(&self.connectors, con_id_ptr, &mut struc.count_connectors)?;
+                copy_mode_obj_id(&self.framebuffers, fb_id_ptr, &mut struc.count_fbs)?;
 
                 let (xmin, ymin) = self.device.min_dim();
 
@@ -485,11 +886,12 @@ impl INodeInterface for Drm {
                 struc.max_width = xmax as _;
                 struc.max_height = ymax as _;
 
+                struc.write_back()?;
                 Ok(0)
             }
 
             DRM_IOCTL_GET_CRTC => {
-                let struc = unsafe { UserRef::<DrmModeCrtc>::new(VirtAddr::new(arg as u64)) };
+                let struc = UserRef::<DrmModeCrtc>::new(VirtAddr::new(arg as u64))?;
                 let _object = self.find_object(struc.crtc_id).unwrap().as_crtc().unwrap();
 
                 log::warn!("drm::get_crtc: is a stub!");
@@ -497,7 +899,7 @@ impl INodeInterface for Drm {
             }
 
             DRM_IOCTL_SET_CRTC => {
-                let struc = unsafe { UserRef::<DrmModeCrtc>::new(VirtAddr::new(arg as u64)) };
+                let struc = UserRef::<DrmModeCrtc>::new(VirtAddr::new(arg as u64))?;
                 let _object = self.find_object(struc.crtc_id).unwrap().as_crtc().unwrap();
 
                 let object = self
@@ -506,6 +908,7 @@ impl INodeInterface for Drm {
                     .as_framebuffer()
                     .unwrap();
 
+                self.take_display();
                 self.device.commit(&object.buffer_obj);
                 log::warn!("drm::set_crtc: is a stub!");
 
@@ -513,8 +916,7 @@ impl INodeInterface for Drm {
             }
 
             DRM_IOCTL_GET_ENCODER => {
-                let mut struc =
-                    unsafe { UserRef::<DrmModeGetEncoder>::new(VirtAddr::new(arg as u64)) };
+                let mut struc = UserRef::<DrmModeGetEncoder>::new(VirtAddr::new(arg as u64))?;
 
                 let object = self
                     .find_object(struc.encoder_id)
@@ -539,12 +941,12 @@ impl INodeInterface for Drm {
                 struc.possible_clones = clone_mask;
                 struc.encoder_typ = 0; // todo: fill in the encoder typ.
 
+                struc.write_back()?;
                 Ok(0)
             }
 
             DRM_IOCTL_GET_CONNECTOR => {
-                let mut struc =
-                    unsafe { UserRef::<DrmModeGetConnector>::new(VirtAddr::new(arg as u64)) };
+                let mut struc = UserRef::<DrmModeGetConnector>::new(VirtAddr::new(arg as u64))?;
 
                 let object = self
                     .find_object(struc.connector_id)
@@ -554,7 +956,7 @@ impl INodeInterface for Drm {
 
                 // Fill in the array containing all of the possible encoders and its length.
                 let encoder_ids_ptr = struc.encoders_ptr as *mut u32;
-                let mut encoder_count = 0;
+                let mut encoder_count = struc.count_encoders as usize;
 
                 copy_field::<u32>(
                     encoder_ids_ptr,
@@ -565,7 +967,7 @@ impl INodeInterface for Drm {
                         .map(|e| e.id())
                         .collect::<Vec<_>>()
                         .as_slice(),
-                );
+                )?;
 
                 struc.count_encoders = encoder_count as _;
 
@@ -581,17 +983,17 @@ impl INodeInterface for Drm {
 
                 // Fill in the array containing all of the possible modes and its length.
                 let modes_ptr = struc.modes_ptr as *mut DrmModeInfo;
-                let mut modes_count = 0;
+                let mut modes_count = struc.count_modes as usize;
 
-                copy_field::<DrmModeInfo>(modes_ptr, &mut modes_count, object.modes.as_slice());
+                copy_field::<DrmModeInfo>(modes_ptr, &mut modes_count, object.modes.as_slice())?;
                 struc.count_modes = modes_count as _;
 
+                struc.write_back()?;
                 Ok(0)
             }
 
             DRM_IOCTL_MODE_CREATE_DUMB => {
-                let mut struc =
-                    unsafe { UserRef::<DrmModeCreateDumb>::new(VirtAddr::new(arg as u64)) };
+                let mut struc = UserRef::<DrmModeCreateDumb>::new(VirtAddr::new(arg as u64))?;
 
                 let (mut buffer, pitch) =
                     self.device
@@ -605,29 +1007,337 @@ impl INodeInterface for Drm {
                 struc.size = buffer.size as _;
                 struc.handle = self.create_handle(buffer);
 
+                struc.write_back()?;
                 Ok(0)
             }
 
             DRM_IOCTL_MODE_ADDFB => {
-                let mut struc = unsafe { UserRef::<DrmModeFbCmd>::new(VirtAddr::new(arg as u64)) };
+                let mut struc = UserRef::<DrmModeFbCmd>::new(VirtAddr::new(arg as u64))?;
 
                 let handle = self.find_handle(struc.handle).unwrap();
                 self.device
                     .framebuffer_create(&handle, struc.width, struc.height, struc.pitch);
 
-                let fb = Framebuffer::new(self.allocate_object_id(), handle);
+                let info = DrmModeFbCmd {
+                    fb_id: 0,
+                    width: struc.width,
+                    height: struc.height,
+                    pitch: struc.pitch,
+                    bpp: struc.bpp,
+                    depth: struc.depth,
+                    handle: struc.handle,
+                };
+
+                let fb = Framebuffer::new(self.allocate_object_id(), handle, info);
                 self.install_framebuffer(fb.clone());
 
                 struc.fb_id = fb.id();
+                struc.write_back()?;
+                Ok(0)
+            }
+
+            DRM_IOCTL_MODE_GETFB => {
+                let mut struc = UserRef::<DrmModeFbCmd>::new(VirtAddr::new(arg as u64))?;
+
+                let fb = self
+                    .find_object(struc.fb_id)
+                    .and_then(|object| object.as_framebuffer())
+                    .ok_or(FileSystemError::EntryNotFound)?;
+
+                struc.width = fb.info.width;
+                struc.height = fb.info.height;
+                struc.pitch = fb.info.pitch;
+                struc.bpp = fb.info.bpp;
+                struc.depth = fb.info.depth;
+
+                // Buffer handles are shared by every client of the device, so the one the
+                // framebuffer was created from is returned as is.
+                struc.handle = fb.info.handle;
+                struc.write_back()?;
                 Ok(0)
             }
 
             DRM_IOCTL_MODE_MAP_DUMB => {
-                let mut struc =
-                    unsafe { UserRef::<DrmModeMapDumb>::new(VirtAddr::new(arg as u64)) };
+                let mut struc = UserRef::<DrmModeMapDumb>::new(VirtAddr::new(arg as u64))?;
 
                 let handle = self.find_handle(struc.handle).unwrap();
                 struc.offset = handle.mapping as _;
+                struc.write_back()?;
+                Ok(0)
+            }
+
+            DRM_IOCTL_MODE_GETPLANERESOURCES => {
+                let mut struc = UserRef::<DrmModeGetPlaneRes>::new(VirtAddr::new(arg as u64))?;
+
+                let plane_id_ptr = struc.plane_id_ptr as *mut u32;
+                let mut count_planes = struc.count_planes as usize;
+
+                copy_field::<u32>(
+                    plane_id_ptr,
+                    &mut count_planes,
+                    self.planes
+                        .lock()
+                        .iter()
+                        .map(|e| e.id())
+                        .collect::<Vec<_>>()
+                        .as_slice(),
+                )?;
+
+                struc.count_planes = count_planes as _;
+                struc.write_back()?;
+                Ok(0)
+            }
+
+            DRM_IOCTL_MODE_GETPLANE => {
+                let mut struc = UserRef::<DrmModeGetPlane>::new(VirtAddr::new(arg as u64))?;
+
+                let object = self
+                    .find_object(struc.plane_id)
+                    .and_then(|object| object.as_plane())
+                    .ok_or(FileSystemError::EntryNotFound)?;
+
+                let state = object.state.lock().clone();
+
+                struc.crtc_id = state.crtc.map(|crtc| crtc.id()).unwrap_or(0);
+                struc.fb_id = state.fb.map(|fb| fb.id()).unwrap_or(0);
+
+                struc.possible_crtcs = object
+                    .possible_crtcs
+                    .iter()
+                    .fold(0, |mask, crtc| mask | (1 << crtc.index));
+
+                struc.gamma_size = 0;
+
+                // Fill in the array containing all of the supported formats and its length.
+                let formats_ptr = struc.format_type_ptr as *mut u32;
+                let mut formats_count = struc.count_format_types as usize;
+
+                copy_field::<u32>(formats_ptr, &mut formats_count, object.formats.as_slice())?;
+                struc.count_format_types = formats_count as _;
+
+                struc.write_back()?;
+                Ok(0)
+            }
+
+            DRM_IOCTL_MODE_SETPLANE => {
+                let struc = UserRef::<DrmModeSetPlane>::new(VirtAddr::new(arg as u64))?;
+
+                let object = self
+                    .find_object(struc.plane_id)
+                    .and_then(|object| object.as_plane())
+                    .ok_or(FileSystemError::EntryNotFound)?;
+
+                let _guard = self.modeset_lock.lock();
+
+                // A framebuffer ID of zero disables the plane.
+                if struc.fb_id == 0 {
+                    return self
+                        .commit_planes(alloc::vec![(object, PlaneState::default())], false)
+                        .map(|_| 0);
+                }
+
+                let crtc = self
+                    .find_object(struc.crtc_id)
+                    .and_then(|object| object.as_crtc())
+                    .ok_or(FileSystemError::EntryNotFound)?;
+
+                let fb = self
+                    .find_object(struc.fb_id)
+                    .and_then(|object| object.as_framebuffer())
+                    .ok_or(FileSystemError::EntryNotFound)?;
+
+                if !object.possible_crtcs.iter().any(|e| e.id() == crtc.id()) {
+                    return Err(FileSystemError::InvalidInput);
+                }
+
+                let state = PlaneState {
+                    crtc: Some(crtc),
+                    fb: Some(fb),
+
+                    crtc_x: struc.crtc_x,
+                    crtc_y: struc.crtc_y,
+                    crtc_w: struc.crtc_w,
+                    crtc_h: struc.crtc_h,
+
+                    src_x: struc.src_x,
+                    src_y: struc.src_y,
+                    src_w: struc.src_w,
+                    src_h: struc.src_h,
+                };
+
+                self.commit_planes(alloc::vec![(object, state)], false)?;
+                Ok(0)
+            }
+
+            DRM_IOCTL_MODE_GETPROPERTY => {
+                let mut struc = UserRef::<DrmModeGetProperty>::new(VirtAddr::new(arg as u64))?;
+
+                let object = self
+                    .find_object(struc.prop_id)
+                    .and_then(|object| object.as_property())
+                    .ok_or(FileSystemError::EntryNotFound)?;
+
+                let kind = object.kind;
+
+                struc.flags = kind.flags();
+                struc.name = [0; DRM_PROP_NAME_LEN];
+
+                for (dest, byte) in struc.name.iter_mut().zip(kind.name().bytes()) {
+                    *dest = byte as _;
+                }
+
+                // Fill in the array containing all of the values and its length.
+                let values_ptr = struc.values_ptr as *mut u64;
+                let mut values_count = struc.count_values as usize;
+
+                copy_field::<u64>(values_ptr, &mut values_count, kind.values().as_slice())?;
+                struc.count_values = values_count as _;
+
+                // Fill in the array containing the names of the enum values and its length.
+                let enums = kind
+                    .values()
+                    .iter()
+                    .zip(kind.enum_names())
+                    .map(|(value, name)| {
+                        let mut item = DrmModePropertyEnum {
+                            value: *value,
+                            name: [0; DRM_PROP_NAME_LEN],
+                        };
+
+                        for (dest, byte) in item.name.iter_mut().zip(name.bytes()) {
+                            *dest = byte as _;
+                        }
+
+                        item
+                    })
+                    .collect::<Vec<_>>();
+
+                let enums_ptr = struc.enum_blob_ptr as *mut DrmModePropertyEnum;
+                let mut enums_count = struc.count_enum_blobs as usize;
+
+                copy_field::<DrmModePropertyEnum>(enums_ptr, &mut enums_count, enums.as_slice())?;
+                struc.count_enum_blobs = enums_count as _;
+
+                struc.write_back()?;
+                Ok(0)
+            }
+
+            DRM_IOCTL_MODE_OBJ_GETPROPERTIES => {
+                let mut struc = UserRef::<DrmModeObjGetProperties>::new(VirtAddr::new(arg as u64))?;
+
+                let object = self
+                    .find_object(struc.obj_id)
+                    .ok_or(FileSystemError::EntryNotFound)?;
+
+                let properties = object.properties();
+
+                let props_ptr = struc.props_ptr as *mut u32;
+                let values_ptr = struc.prop_values_ptr as *mut u64;
+
+                let mut props_count = struc.count_props as usize;
+                let mut values_count = struc.count_props as usize;
+
+                copy_field::<u32>(
+                    props_ptr,
+                    &mut props_count,
+                    properties
+                        .iter()
+                        .map(|(kind, _)| self.property(*kind).id())
+                        .collect::<Vec<_>>()
+                        .as_slice(),
+                )?;
+
+                copy_field::<u64>(
+                    values_ptr,
+                    &mut values_count,
+                    properties
+                        .iter()
+                        .map(|(_, value)| *value)
+                        .collect::<Vec<_>>()
+                        .as_slice(),
+                )?;
+
+                struc.count_props = props_count as _;
+                struc.write_back()?;
+                Ok(0)
+            }
+
+            DRM_IOCTL_MODE_ATOMIC => {
+                let struc = UserRef::<DrmModeAtomicReq>::new(VirtAddr::new(arg as u64))?;
+
+                // Completion events are not supported.
+                if struc.flags & !DRM_MODE_ATOMIC_FLAGS != 0
+                    || struc.flags & DRM_MODE_PAGE_FLIP_EVENT != 0
+                    || struc.reserved != 0
+                {
+                    return Err(FileSystemError::InvalidInput);
+                }
+
+                let count_objs = struc.count_objs as usize;
+
+                // Every mode object has a handful of properties, so this bounds the size of
+                // the request before anything is allocated for it.
+                let max_objs = self.mode_objs.lock().len();
+                let max_props = max_objs * PropertyKind::ALL.len();
+
+                if count_objs > max_objs {
+                    return Err(FileSystemError::InvalidInput);
+                }
+
+                let objects = read_field::<u32>(struc.objs_ptr as *const u32, count_objs)?;
+                let counts = read_field::<u32>(struc.count_props_ptr as *const u32, count_objs)?;
+
+                let count_props = counts
+                    .iter()
+                    .try_fold(0usize, |total, &count| total.checked_add(count as usize))
+                    .filter(|&total| total <= max_props)
+                    .ok_or(FileSystemError::InvalidInput)?;
+
+                let props = read_field::<u32>(struc.props_ptr as *const u32, count_props)?;
+                let values = read_field::<u64>(struc.prop_values_ptr as *const u64, count_props)?;
+
+                let _guard = self.modeset_lock.lock();
+
+                // Stage the new state of every object, validating each property on the way, so
+                // that nothing is applied unless the whole request is valid.
+                let mut staged = Vec::<(Arc<Plane>, PlaneState)>::new();
+                let mut pairs = props.into_iter().zip(values);
+
+                for (object_id, count) in objects.into_iter().zip(counts) {
+                    let object = self
+                        .find_object(object_id)
+                        .ok_or(FileSystemError::InvalidInput)?;
+
+                    let known = object.properties();
+
+                    for (prop_id, value) in pairs.by_ref().take(count as usize) {
+                        let property = self
+                            .find_object(prop_id)
+                            .and_then(|object| object.as_property())
+                            .filter(|property| known.iter().any(|(kind, _)| *kind == property.kind))
+                            .ok_or(FileSystemError::InvalidInput)?;
+
+                        // Only planes have mutable properties.
+                        let plane = object.as_plane().ok_or(FileSystemError::InvalidInput)?;
+
+                        let index = match staged.iter().position(|(e, _)| e.id() == plane.id()) {
+                            Some(index) => index,
+                            None => {
+                                let state = plane.state.lock().clone();
+                                staged.push((plane.clone(), state));
+                                staged.len() - 1
+                            }
+                        };
+
+                        plane.set_property(self, &mut staged[index].1, property.kind, value)?;
+                    }
+                }
+
+                // The display is updated synchronously, so `DRM_MODE_ATOMIC_NONBLOCK` is done
+                // by the time the commit returns.
+                let test_only = struc.flags & DRM_MODE_ATOMIC_TEST_ONLY != 0;
+                self.commit_planes(staged, test_only)?;
+
                 Ok(0)
             }
 
@@ -645,20 +1355,38 @@ impl INodeInterface for Drm {
         }
     }
 
-    fn mmap(
-        &self,
-        offset: usize,
-        _size: usize,
-        _flags: aero_syscall::MMapFlags,
-    ) -> fs::Result<PhysFrame> {
+    fn open(&self, _handle: Arc<FileHandle>) -> fs::Result<Option<DirCacheItem>> {
+        self.clients.fetch_add(1, Ordering::SeqCst);
+        Ok(None)
+    }
+
+    fn close(&self, _flags: aero_syscall::OpenFlags) {
+        if self.clients.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.last_close();
+        }
+    }
+
+    fn mmap_region(&self, offset: usize, len: usize) -> fs::Result<MappableRegion> {
         let buffers = self.buffers.lock();
         let (_, handle) = buffers
             .iter()
-            .find(|(_, h)| offset <= h.mapping + h.size && offset >= h.mapping)
-            .unwrap();
+            .find(|(_, h)| offset.checked_sub(h.mapping).is_some_and(|o| o < h.size))
+            .ok_or(FileSystemError::InvalidInput)?;
 
         let index = (offset - handle.mapping) / Size4KiB::SIZE as usize;
-        Ok(handle.memory[index])
+        let count = len.div_ceil(Size4KiB::SIZE as usize);
+
+        // Dumb buffers are allocated frame by frame.
+        Ok(MappableRegion::scattered(
+            handle
+                .memory
+                .iter()
+                .skip(index)
+                .take(count)
+                .copied()
+                .collect(),
+            CacheMode::WriteBack,
+        ))
     }
 }
 
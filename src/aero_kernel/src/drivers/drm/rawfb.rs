@@ -15,14 +15,17 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use aero_syscall::Mode;
 use alloc::sync::Arc;
-use uapi::drm::DrmModeConStatus;
+use uapi::drm::{DrmModeConStatus, DrmPlaneType, DRM_FORMAT_XRGB8888};
 
-use crate::fs::{devfs, FileSystem};
+use crate::fs::{self, devfs, FileSystem, FileSystemError};
 
 use crate::mem::paging::*;
 
-use super::{make_dmt_modes, BufferObject, Connector, Crtc, Drm, DrmDevice, Encoder};
+use super::{
+    make_dmt_modes, BufferObject, Connector, Crtc, Drm, DrmDevice, Encoder, Plane, PlaneState,
+};
 use crate::rendy;
 
 struct RawFramebuffer {}
@@ -45,24 +48,45 @@ impl DrmDevice for RawFramebuffer {
     }
 
     fn commit(&self, buffer_obj: &BufferObject) {
-        crate::rendy::DEBUG_RENDY
-            .get()
-            .map(|e| {
-                let mut lock = e.lock_irq();
-                let fb = lock.get_framebuffer();
-
-                for (i, frame) in buffer_obj.memory.iter().enumerate() {
-                    unsafe {
-                        core::ptr::copy_nonoverlapping(
-                            frame.as_slice_mut::<u8>().as_mut_ptr(),
-                            (fb.as_mut_ptr().cast::<u8>())
-                                .offset(i as isize * Size4KiB::SIZE as isize),
-                            4096,
-                        )
-                    }
-                }
-            })
-            .unwrap();
+        // The display always scans out of the boot framebuffer, so the buffer object is copied
+        // into it.
+        let fb = rendy::boot_scanout().address.as_mut_ptr::<u8>();
+
+        for (i, frame) in buffer_obj.memory.iter().enumerate() {
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    frame.as_slice_mut::<u8>().as_mut_ptr(),
+                    fb.offset(i as isize * Size4KiB::SIZE as isize),
+                    4096,
+                )
+            }
+        }
+    }
+
+    fn check_plane(&self, plane: &Plane, state: &PlaneState) -> fs::Result<()> {
+        // There is no hardware composition, so only the primary plane can be shown and it
+        // always covers the whole display.
+        if plane.plane_type != DrmPlaneType::Primary {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        let (width, height) = self.max_dim();
+        let (width, height) = (width as u32, height as u32);
+
+        let full_screen = state.crtc_x == 0
+            && state.crtc_y == 0
+            && state.crtc_w == width
+            && state.crtc_h == height
+            && state.src_x == 0
+            && state.src_y == 0
+            && state.src_w >> 16 == width
+            && state.src_h >> 16 == height;
+
+        if !full_screen {
+            return Err(FileSystemError::InvalidInput);
+        }
+
+        Ok(())
     }
 
     fn framebuffer_create(
@@ -119,15 +143,23 @@ fn init() {
         rfb.allocate_object_id(),
     );
 
+    let plane = Plane::new(
+        DrmPlaneType::Primary,
+        alloc::vec![crtc.clone()],
+        alloc::vec![DRM_FORMAT_XRGB8888],
+        rfb.allocate_object_id(),
+    );
+
     let dri = devfs::DEV_FILESYSTEM
         .root_dir()
         .inode()
-        .mkdir("dri")
+        .mkdir("dri", Mode::from_bits_truncate(0o755))
         .expect("devfs: failed to create DRM directory");
 
     rfb.install_crtc(crtc);
     rfb.install_connector(connector);
     rfb.install_encoder(encoder);
+    rfb.install_plane(plane);
 
     devfs::install_device_at(dri, rfb).expect("ramfs: failed to install DRM device");
 }
@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use aero_syscall::KbdRepeat;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use spin::RwLock;
@@ -27,16 +28,71 @@ use crate::fs::devfs::{self, Device};
 use crate::fs::inode::{INodeInterface, PollFlags};
 use crate::utils::sync::{Mutex, WaitQueue};
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum KeyState {
+    Pressed,
+    /// The key is held down and repeats, at the typematic rate of the keyboard. Modifier keys do
+    /// not repeat.
+    Repeated,
+    Released,
+}
+
 pub trait KeyboardListener: Send + Sync {
-    fn on_key(&self, key: KeyCode, released: bool);
+    fn on_key(&self, key: KeyCode, state: KeyState);
 }
 
 static PS2_KEYBOARD_STATE: Mutex<Ps2KeyboardState> = Mutex::new(Ps2KeyboardState::new());
 static KEYBOARD_LISTENER: RwLock<Vec<Arc<dyn KeyboardListener>>> = RwLock::new(Vec::new());
 
+/// The repeat delays and periods supported by PS/2 keyboards in milliseconds, indexed by their
+/// encoding in the typematic byte.
+const TYPEMATIC_DELAYS: [u16; 4] = [250, 500, 750, 1000];
+const TYPEMATIC_PERIODS: [u16; 32] = [
+    33, 37, 42, 46, 50, 54, 58, 63, 67, 75, 83, 92, 100, 109, 116, 125, 133, 149, 167, 182, 200,
+    217, 232, 250, 270, 303, 333, 370, 400, 435, 470, 500,
+];
+
+/// The key repeat delay and period, as indices in [`TYPEMATIC_DELAYS`] and
+/// [`TYPEMATIC_PERIODS`].
+#[derive(Copy, Clone)]
+struct Typematic {
+    delay: usize,
+    period: usize,
+}
+
+impl Typematic {
+    /// Repeats after 500ms, 30 times per second.
+    const DEFAULT: Self = Self {
+        delay: 1,
+        period: 0,
+    };
+
+    /// Returns the index of the first of `values` that is at least `ms`, or of the last one.
+    fn closest(values: &[u16], ms: i32) -> usize {
+        values
+            .iter()
+            .position(|&value| i32::from(value) >= ms)
+            .unwrap_or(values.len() - 1)
+    }
+
+    fn as_byte(&self) -> u8 {
+        ((self.delay << 5) | self.period) as u8
+    }
+
+    fn as_repeat(&self) -> KbdRepeat {
+        KbdRepeat {
+            delay: TYPEMATIC_DELAYS[self.delay].into(),
+            period: TYPEMATIC_PERIODS[self.period].into(),
+        }
+    }
+}
+
 struct Ps2KeyboardState {
     special: bool,
     released: bool,
+    /// Bitmap of the keys that are held down, indexed by their key code.
+    held: u128,
+    typematic: Typematic,
 }
 
 impl Ps2KeyboardState {
@@ -45,6 +101,8 @@ impl Ps2KeyboardState {
         Self {
             special: false,
             released: false,
+            held: 0,
+            typematic: Typematic::DEFAULT,
         }
     }
 
@@ -179,6 +237,27 @@ pub enum KeyCode {
     KEY_COMPOSE = 127,
 }
 
+impl KeyCode {
+    /// Returns whether the key modifies the other keys (including the lock keys), in which case
+    /// it is not repeated while held down.
+    fn is_modifier(self) -> bool {
+        matches!(
+            self,
+            Self::KEY_LEFTSHIFT
+                | Self::KEY_RIGHTSHIFT
+                | Self::KEY_LEFTCTRL
+                | Self::KEY_RIGHTCTRL
+                | Self::KEY_LEFTALT
+                | Self::KEY_RIGHTALT
+                | Self::KEY_LEFTMETA
+                | Self::KEY_RIGHTMETA
+                | Self::KEY_CAPSLOCK
+                | Self::KEY_NUMLOCK
+                | Self::KEY_SCROLLLOCK
+        )
+    }
+}
+
 lazy_static::lazy_static! {
     static ref KEYBOARD: Arc<KeyboardDevice> = KeyboardDevice::new();
 }
@@ -216,8 +295,10 @@ impl Device for KeyboardDevice {
 }
 
 impl KeyboardListener for KeyboardDevice {
-    fn on_key(&self, keycode: KeyCode, released: bool) {
-        if released {
+    fn on_key(&self, keycode: KeyCode, state: KeyState) {
+        // Repeats are reported as presses of a key that is already down, as the keyboard sends
+        // them.
+        if state == KeyState::Released {
             self.buffer.lock_irq().push(0x80 | keycode as u8);
         } else {
             self.buffer.lock_irq().push(keycode as u8);
@@ -268,6 +349,15 @@ pub fn ps2_keyboard_init() {
             log::warn!("ps2: disable scanning failed, no ACK");
         }
 
+        io::outb(0x60, 0xF3); // command: set typematic rate and delay
+
+        if io::inb(0x60) != 0xFA {
+            log::warn!("ps2: failed to set the typematic rate, no ACK");
+        }
+
+        io::outb(0x60, lock.typematic.as_byte());
+        lock.flush();
+
         io::outb(0x60, 0xF4); // command: enable reporting
         lock.flush();
 
@@ -307,6 +397,36 @@ pub fn register_keyboard_listener(listener: Arc<dyn KeyboardListener>) {
     KEYBOARD_LISTENER.write().push(listener)
 }
 
+/// Sets the key repeat delay and period to the closest values the keyboard supports, leaving the
+/// ones that are not positive unchanged. `repeat` is updated with the settings in effect.
+pub fn set_key_repeat(repeat: &mut KbdRepeat) {
+    let mut state = PS2_KEYBOARD_STATE.lock_irq();
+
+    if repeat.delay > 0 || repeat.period > 0 {
+        if repeat.delay > 0 {
+            state.typematic.delay = Typematic::closest(&TYPEMATIC_DELAYS, repeat.delay);
+        }
+
+        if repeat.period > 0 {
+            state.typematic.period = Typematic::closest(&TYPEMATIC_PERIODS, repeat.period);
+        }
+
+        // The acknowledgements are dropped by the interrupt handler.
+        for byte in [0xF3, state.typematic.as_byte()] {
+            unsafe {
+                // Wait for the input buffer of the controller to be empty.
+                while io::inb(0x64) & 2 != 0 {
+                    core::hint::spin_loop();
+                }
+
+                io::outb(0x60, byte);
+            }
+        }
+    }
+
+    *repeat = state.typematic.as_repeat();
+}
+
 pub fn keyboard_irq_handler(_stack: &mut InterruptStack) {
     let scancode = unsafe { io::inb(0x60) };
 
@@ -314,6 +434,9 @@ pub fn keyboard_irq_handler(_stack: &mut InterruptStack) {
         0xE0 => PS2_KEYBOARD_STATE.lock().special = true,
         0xF0 => PS2_KEYBOARD_STATE.lock().released = true,
 
+        // Responses to the commands sent by `set_key_repeat`.
+        0xFA | 0xFE => {}
+
         _ => {
             let mut lock = PS2_KEYBOARD_STATE.lock();
             let released = lock.released;
@@ -433,11 +556,37 @@ pub fn keyboard_irq_handler(_stack: &mut InterruptStack) {
             lock.special = false;
             lock.released = false;
 
+            // While a key is held down, the keyboard repeats its make code.
+            let bit = 1u128 << keycode as u8;
+            let state = if released {
+                lock.held &= !bit;
+                KeyState::Released
+            } else if lock.held & bit != 0 {
+                if keycode.is_modifier() {
+                    return;
+                }
+
+                KeyState::Repeated
+            } else {
+                lock.held |= bit;
+                KeyState::Pressed
+            };
+
+            let held = |keys: &[KeyCode]| keys.iter().any(|key| lock.held & (1 << *key as u8) != 0);
+            let ctrl_alt_del = state == KeyState::Pressed
+                && matches!(keycode, KeyCode::KEY_DELETE | KeyCode::KEY_KPDOT)
+                && held(&[KeyCode::KEY_LEFTCTRL, KeyCode::KEY_RIGHTCTRL])
+                && held(&[KeyCode::KEY_LEFTALT, KeyCode::KEY_RIGHTALT]);
+
             core::mem::drop(lock);
 
+            if ctrl_alt_del {
+                crate::syscall::process::ctrl_alt_del();
+            }
+
             let listeners = KEYBOARD_LISTENER.read();
             for listener in listeners.iter() {
-                listener.on_key(keycode, released);
+                listener.on_key(keycode, state);
             }
         }
     }
@@ -22,6 +22,7 @@ use crate::acpi::{aml, fadt, get_acpi_table};
 use crate::mem::paging::PhysAddr;
 
 use crate::arch::io;
+use crate::timer;
 use crate::userland::scheduler;
 
 use super::pci::PciHeader;
@@ -50,10 +51,14 @@ impl lai::Host for LaiHost {
     }
 
     fn sleep(&self, ms: u64) {
-        scheduler::get_scheduler()
-            .inner
-            .sleep(Some(ms as usize / 1000))
-            .expect("lai: unexpected signal during sleep")
+        let deadline = timer::deadline_after(ms.saturating_mul(timer::NSEC_PER_MSEC));
+
+        while timer::now() < deadline {
+            scheduler::get_scheduler()
+                .inner
+                .sleep(Some(deadline))
+                .expect("lai: unexpected signal during sleep")
+        }
     }
 
     // Port I/O functions:
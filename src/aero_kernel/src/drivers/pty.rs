@@ -15,10 +15,10 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use aero_syscall as libc;
-use aero_syscall::{Termios, WinSize};
+use aero_syscall::{Mode, Termios, WinSize};
 
 use alloc::collections::BTreeMap;
 use alloc::string::ToString;
@@ -26,12 +26,13 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use spin::{Once, RwLock};
 
-use uapi::pty::TIOCGPTN;
+use uapi::pty::{TIOCGPTN, TIOCSPTLCK};
 
 use crate::arch::user_copy::UserRef;
+use crate::drivers::tty::line_discipline;
 use crate::fs::cache::*;
 use crate::fs::devfs::DEV_FILESYSTEM;
-use crate::fs::inode::{DirEntry, FileType, INodeInterface, PollFlags};
+use crate::fs::inode::{fetch_dir_entry, DirEntry, FileType, INodeInterface, PollFlags};
 use crate::fs::{self, cache, devfs, FileSystem, FileSystemError, Path, MOUNT_MANAGER};
 
 use crate::mem::paging::VirtAddr;
@@ -46,7 +47,9 @@ lazy_static::lazy_static! {
 }
 
 static PTS_FS: Once<Arc<PtsFs>> = Once::new();
-static PTY_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Permissions of the slave nodes in `/dev/pts`.
+const SLAVE_MODE: Mode = Mode::from_bits_truncate(0o620);
 
 #[derive(Debug, Ioctl)]
 pub enum TermiosCmd {
@@ -103,16 +106,25 @@ struct Master {
     window_size: Mutex<WinSize>,
     buffer: Mutex<Vec<u8>>,
     discipline: LineDiscipline,
+    /// Number of open file handles of the master. The slave is removed from `/dev/pts` once
+    /// the last one is closed.
+    handles: AtomicUsize,
+    /// Whether the slave cannot be opened, see `TIOCSPTLCK`. Unlike on Linux, slaves start
+    /// unlocked, so programs that do not call `unlockpt` keep working.
+    locked: AtomicBool,
 }
 
 impl Master {
-    pub fn new() -> Self {
+    pub fn new(id: u32) -> Self {
         Self {
-            id: PTY_ID.fetch_add(1, Ordering::SeqCst),
+            id,
             wq: WaitQueue::new(),
             window_size: Mutex::new(WinSize::default()),
             buffer: Mutex::new(Vec::new()),
             discipline: LineDiscipline::new(),
+            // The handle opened through `/dev/ptmx`.
+            handles: AtomicUsize::new(1),
+            locked: AtomicBool::new(false),
         }
     }
 
@@ -128,6 +140,22 @@ impl Master {
 }
 
 impl INodeInterface for Master {
+    fn open(&self, _handle: Arc<fs::file_table::FileHandle>) -> fs::Result<Option<DirCacheItem>> {
+        self.handles.fetch_add(1, Ordering::SeqCst);
+        Ok(None)
+    }
+
+    fn close(&self, _flags: aero_syscall::OpenFlags) {
+        if self.handles.fetch_sub(1, Ordering::SeqCst) == 1 {
+            PTS_FS.get().unwrap().remove_slave(self.id);
+
+            // The terminal hung up.
+            if let Some(foreground) = self.discipline.foreground() {
+                foreground.signal(libc::signal::SIGHUP);
+            }
+        }
+    }
+
     fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> fs::Result<usize> {
         let mut pty_buffer = self.buffer.lock_irq();
 
@@ -164,15 +192,21 @@ impl INodeInterface for Master {
     fn ioctl(&self, command: usize, arg: usize) -> fs::Result<usize> {
         match command {
             TIOCGPTN => {
-                let id = VirtAddr::new(arg as u64).read_mut::<u32>()?;
+                let mut id = UserRef::<u32>::new(VirtAddr::new(arg as u64))?;
                 *id = self.id;
+                id.write_back()?;
             }
 
             aero_syscall::TIOCSWINSZ => {
-                let winsize = VirtAddr::new(arg as u64).read_mut::<WinSize>()?;
+                let winsize = UserRef::<WinSize>::new(VirtAddr::new(arg as u64))?;
                 *self.window_size.lock_irq() = *winsize;
             }
 
+            TIOCSPTLCK => {
+                let lock = UserRef::<i32>::new(VirtAddr::new(arg as u64))?;
+                self.locked.store(*lock != 0, Ordering::SeqCst);
+            }
+
             _ => {
                 panic!("ptmx: unknown ioctl (command={command:#x})")
             }
@@ -236,14 +270,33 @@ impl INodeInterface for Slave {
     }
 
     fn stat(&self) -> fs::Result<aero_syscall::Stat> {
-        Ok(aero_syscall::Stat::default())
+        Ok(aero_syscall::Stat {
+            st_mode: SLAVE_MODE | Mode::S_IFCHR,
+            ..Default::default()
+        })
+    }
+
+    fn open(&self, _handle: Arc<fs::file_table::FileHandle>) -> fs::Result<Option<DirCacheItem>> {
+        if self.master.locked.load(Ordering::SeqCst) {
+            return Err(FileSystemError::Io);
+        }
+
+        Ok(None)
     }
 
     fn ioctl(&self, command: usize, arg: usize) -> fs::Result<usize> {
-        match TermiosCmd::from_command_arg(command, arg) {
-            TermiosCmd::GetWinSize(mut size) => *size = self.master.get_window_size(),
+        match TermiosCmd::from_command_arg(command, arg)? {
+            TermiosCmd::GetWinSize(mut size) => {
+                *size = self.master.get_window_size();
+                size.write_back()?;
+            }
+
             TermiosCmd::SetWinSize(size) => self.master.set_window_size(*size),
-            TermiosCmd::TcGets(mut termios) => *termios = self.master.discipline.termios(),
+            TermiosCmd::TcGets(mut termios) => {
+                *termios = self.master.discipline.termios();
+                termios.write_back()?;
+            }
+
             TermiosCmd::TcSetsf(termios) => self.master.discipline.set_termios(termios.clone()),
             TermiosCmd::TcSetsw(termios) => {
                 // TODO: Allow the output buffer to drain and then set the current serial port
@@ -253,9 +306,7 @@ impl INodeInterface for Slave {
 
             TermiosCmd::SetCtrlTerm => {
                 let current_task = scheduler::get_scheduler().current_task();
-                assert!(current_task.is_session_leader());
-
-                current_task.attach(self.sref());
+                current_task.attach(self.sref())?;
             }
 
             // FIXME: the following ioctls are not implemented.
@@ -285,29 +336,10 @@ impl INodeInterface for Slave {
     }
 
     fn write_at(&self, _offset: usize, buffer: &[u8]) -> fs::Result<usize> {
-        if self
-            .master
-            .discipline
-            .termios()
-            .c_oflag
-            .contains(aero_syscall::TermiosOFlag::ONLCR)
-        {
-            let mut master = self.master.buffer.lock_irq();
-
-            for b in buffer.iter() {
-                if *b == b'\n' {
-                    // ONLCR: Convert NL to CR + NL
-                    master.extend_from_slice(&[b'\r', b'\n']);
-                    continue;
-                }
-
-                master.push(*b);
-            }
-        } else {
-            let mut pty_buffer = self.master.buffer.lock_irq();
-            pty_buffer.extend_from_slice(buffer);
-        }
+        let termios = self.master.discipline.termios();
+        let output = line_discipline::process_output(&termios, buffer);
 
+        self.master.buffer.lock_irq().extend_from_slice(&output);
         self.master.wq.notify_all();
         Ok(buffer.len())
     }
@@ -341,12 +373,8 @@ impl devfs::Device for Ptmx {
 
 impl INodeInterface for Ptmx {
     fn open(&self, _handle: Arc<fs::file_table::FileHandle>) -> fs::Result<Option<DirCacheItem>> {
-        let master = Arc::new(Master::new());
-        let slave = Slave::new(master.clone());
-        let inode = DirEntry::from_inode(master, String::from("<pty>"));
-
-        PTS_FS.get().unwrap().insert_slave(slave);
-        Ok(Some(inode))
+        let master = PTS_FS.get().unwrap().create_pair();
+        Ok(Some(DirEntry::from_inode(master, String::from("<pty>"))))
     }
 }
 
@@ -399,13 +427,12 @@ impl INodeInterface for PtsINode {
     }
 
     fn lookup(&self, dir: DirCacheItem, name: &str) -> fs::Result<DirCacheItem> {
-        let id = name.parse::<u32>().unwrap();
-        let slaves = self.slaves.read();
+        let id = name
+            .parse::<u32>()
+            .map_err(|_| FileSystemError::EntryNotFound)?;
 
-        let (_, inode) = slaves
-            .iter()
-            .find(|(&e, _)| e == id)
-            .ok_or(FileSystemError::EntryNotFound)?;
+        let slaves = self.slaves.read();
+        let inode = slaves.get(&id).ok_or(FileSystemError::EntryNotFound)?;
 
         Ok(DirEntry::new(dir, inode.clone(), String::from(name)))
     }
@@ -436,14 +463,31 @@ impl PtsFs {
         this
     }
 
-    fn insert_slave(&self, slave: Arc<Slave>) {
+    /// Creates a pseudo-terminal pair, numbered with the lowest number that is not in use, and
+    /// adds its slave to the filesystem. Returns the master.
+    fn create_pair(&self) -> Arc<Master> {
         let icache = cache::icache();
 
         let pts_root = self.root_dir.inode().downcast_arc::<PtsINode>().unwrap();
-        pts_root.slaves.write().insert(
-            slave.master.id,
-            icache.make_item_no_cache(CachedINode::new(slave)),
-        );
+        let mut slaves = pts_root.slaves.write();
+
+        let id = (0..).find(|id| !slaves.contains_key(id)).unwrap();
+        let master = Arc::new(Master::new(id));
+        let slave = Slave::new(master.clone());
+
+        slaves.insert(id, icache.make_item_no_cache(CachedINode::new(slave)));
+        master
+    }
+
+    /// Removes the slave numbered `id` from the filesystem. Files that are already open keep a
+    /// reference to it.
+    fn remove_slave(&self, id: u32) {
+        let pts_root = self.root_dir.inode().downcast_arc::<PtsINode>().unwrap();
+        pts_root.slaves.write().remove(&id);
+
+        if let Some(entry) = fetch_dir_entry(&self.root_dir, id.to_string()) {
+            entry.drop_from_cache();
+        }
     }
 }
 
@@ -459,7 +503,7 @@ fn pty_init() {
     let fs = PTS_FS.call_once(PtsFs::new);
 
     let root = DEV_FILESYSTEM.root_dir().inode();
-    root.mkdir("pts").unwrap();
+    root.mkdir("pts", Mode::from_bits_truncate(0o755)).unwrap();
 
     let pts_dir = fs::lookup_path(Path::new("/dev/pts")).unwrap();
     MOUNT_MANAGER.mount(pts_dir, fs.clone()).unwrap();
@@ -20,7 +20,9 @@
 use aero_syscall::TIOCNOTTY;
 use alloc::sync::{Arc, Weak};
 
+use crate::fs::cache::DirCacheItem;
 use crate::fs::devfs::Device;
+use crate::fs::file_table::FileHandle;
 use crate::fs::inode::{FileType, INodeInterface, Metadata, PollFlags, PollTable};
 use crate::fs::{self, devfs, FileSystemError};
 use crate::userland::scheduler;
@@ -64,6 +66,12 @@ impl INodeInterface for Ctty {
         })
     }
 
+    fn open(&self, _handle: Arc<FileHandle>) -> fs::Result<Option<DirCacheItem>> {
+        // There is no terminal to open without a controlling terminal.
+        Self::controlling_terminal().map_err(|_| FileSystemError::NoDevice)?;
+        Ok(None)
+    }
+
     fn read_at(&self, offset: usize, buffer: &mut [u8]) -> fs::Result<usize> {
         Self::controlling_terminal()?.read_at(offset, buffer)
     }
@@ -84,7 +92,7 @@ impl INodeInterface for Ctty {
         match command {
             TIOCNOTTY => {
                 let current_task = scheduler::get_scheduler().current_task();
-                current_task.detach();
+                current_task.disown_terminal()?;
 
                 Ok(0)
             }
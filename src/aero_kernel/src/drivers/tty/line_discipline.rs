@@ -0,0 +1,313 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Line discipline shared by the terminal devices.
+//!
+//! [`LineDiscipline`] turns the bytes typed on a terminal into the input read from it, as
+//! configured by its [`Termios`]: line editing in canonical mode, echo, signal characters and
+//! newline translation. It does not touch the hardware; the device driver performs the returned
+//! [`Action`]s against its own output and wait queues.
+
+use aero_syscall::signal::{SIGINT, SIGQUIT, SIGTSTP};
+use aero_syscall::{
+    Termios, TermiosIFlag, TermiosLFlag, TermiosOFlag, VEOF, VEOL, VERASE, VINTR, VKILL, VQUIT,
+    VSUSP,
+};
+
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// What the device driver has to do after the line discipline processed its input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Writes the bytes to the terminal. `\x08` moves the cursor one column to the left, and may
+    /// blank that column.
+    Echo(Vec<u8>),
+    /// Moves the cursor of the terminal by the given number of columns.
+    MoveCursor(isize),
+    /// Sends the signal to the foreground process group of the terminal.
+    Signal(usize),
+    /// Input is ready to be read, so the readers should be woken up.
+    DeliverLine,
+}
+
+/// Erases the character before the cursor of the terminal.
+const ERASE: &[u8] = b"\x08 \x08";
+
+pub struct LineDiscipline {
+    termios: Termios,
+    /// The line being edited in canonical mode.
+    line: Vec<u8>,
+    /// Position of the cursor in `line`.
+    cursor: usize,
+    /// Input that is ready to be read. In canonical mode, every entry is a line and an empty
+    /// entry is an end of file.
+    ready: VecDeque<Vec<u8>>,
+}
+
+impl LineDiscipline {
+    pub fn new(termios: Termios) -> Self {
+        Self {
+            termios,
+            line: Vec::new(),
+            cursor: 0,
+            ready: VecDeque::new(),
+        }
+    }
+
+    pub fn termios(&self) -> &Termios {
+        &self.termios
+    }
+
+    pub fn set_termios(&mut self, termios: Termios) {
+        self.termios = termios;
+    }
+
+    /// Returns whether the special character `index` of the termios is `byte`. A special
+    /// character of zero is disabled.
+    fn is_special(&self, index: usize, byte: u8) -> bool {
+        let special = self.termios.c_cc[index];
+        special != 0 && special == byte
+    }
+
+    /// Returns how `byte` is echoed, with control characters shown as `^X` if `ECHOCTL` is set.
+    fn echo(&self, byte: u8) -> Vec<u8> {
+        let lflag = self.termios.c_lflag;
+
+        if lflag.contains(TermiosLFlag::ECHOCTL)
+            && (byte < 0x20 || byte == 0x7f)
+            && !matches!(byte, b'\t' | b'\n')
+        {
+            vec![b'^', byte ^ 0x40]
+        } else {
+            vec![byte]
+        }
+    }
+
+    /// Processes a byte typed on the terminal.
+    pub fn input_byte(&mut self, mut byte: u8) -> Vec<Action> {
+        let iflag = self.termios.c_iflag;
+        let lflag = self.termios.c_lflag;
+        let echo = lflag.contains(TermiosLFlag::ECHO);
+
+        let mut actions = vec![];
+
+        if byte == b'\r' {
+            if iflag.contains(TermiosIFlag::IGNCR) {
+                return actions;
+            }
+
+            if iflag.contains(TermiosIFlag::ICRNL) {
+                byte = b'\n';
+            }
+        } else if byte == b'\n' && iflag.contains(TermiosIFlag::INLCR) {
+            byte = b'\r';
+        }
+
+        if lflag.contains(TermiosLFlag::ISIG) {
+            let signal = [(VINTR, SIGINT), (VQUIT, SIGQUIT), (VSUSP, SIGTSTP)]
+                .into_iter()
+                .find_map(|(index, signal)| self.is_special(index, byte).then_some(signal));
+
+            if let Some(signal) = signal {
+                if !lflag.contains(TermiosLFlag::NOFLSH) {
+                    self.flush_input();
+                }
+
+                if echo {
+                    actions.push(Action::Echo(self.echo(byte)));
+                }
+
+                actions.push(Action::Signal(signal));
+                return actions;
+            }
+        }
+
+        if !lflag.contains(TermiosLFlag::ICANON) {
+            match self.ready.back_mut() {
+                Some(input) => input.push(byte),
+                None => self.ready.push_back(vec![byte]),
+            }
+
+            if echo {
+                actions.push(Action::Echo(self.echo(byte)));
+            }
+
+            actions.push(Action::DeliverLine);
+            return actions;
+        }
+
+        if self.is_special(VERASE, byte) {
+            if self.cursor == 0 {
+                return actions;
+            }
+
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+
+            let tail = &self.line[self.cursor..];
+
+            if echo && lflag.contains(TermiosLFlag::ECHOE) && tail.is_empty() {
+                actions.push(Action::Echo(ERASE.to_vec()));
+            } else if echo && lflag.contains(TermiosLFlag::ECHOE) {
+                // Shift the rest of the line to the left and blank its last column.
+                let mut erase = vec![b'\x08'];
+
+                erase.extend_from_slice(tail);
+                erase.push(b' ');
+
+                actions.push(Action::Echo(erase));
+                actions.push(Action::MoveCursor(-(tail.len() as isize) - 1));
+            } else if echo {
+                actions.push(Action::Echo(self.echo(byte)));
+            }
+        } else if self.is_special(VKILL, byte) {
+            if echo && lflag.intersects(TermiosLFlag::ECHOE | TermiosLFlag::ECHOKE) {
+                // Move to the end of the line, then erase all of it.
+                let mut erase = self.line[self.cursor..].to_vec();
+                erase.extend_from_slice(&ERASE.repeat(self.line.len()));
+
+                actions.push(Action::Echo(erase));
+            } else if echo {
+                actions.push(Action::Echo(self.echo(byte)));
+
+                if lflag.contains(TermiosLFlag::ECHOK) {
+                    actions.push(Action::Echo(vec![b'\n']));
+                }
+            }
+
+            self.line.clear();
+            self.cursor = 0;
+        } else if self.is_special(VEOF, byte) {
+            // The line is delivered without a newline. At the start of a line, this is an end of
+            // file, as the read returns zero bytes.
+            self.ready.push_back(core::mem::take(&mut self.line));
+            self.cursor = 0;
+
+            actions.push(Action::DeliverLine);
+        } else if byte == b'\n' || self.is_special(VEOL, byte) {
+            self.line.push(byte);
+            self.ready.push_back(core::mem::take(&mut self.line));
+            self.cursor = 0;
+
+            if echo || (byte == b'\n' && lflag.contains(TermiosLFlag::ECHONL)) {
+                actions.push(Action::Echo(vec![byte]));
+            }
+
+            actions.push(Action::DeliverLine);
+        } else {
+            self.line.insert(self.cursor, byte);
+            self.cursor += 1;
+
+            if echo {
+                // Characters typed in the middle of the line push the rest of it to the right.
+                let tail = &self.line[self.cursor..];
+                let mut bytes = self.echo(byte);
+                bytes.extend_from_slice(tail);
+
+                actions.push(Action::Echo(bytes));
+
+                if !tail.is_empty() {
+                    actions.push(Action::MoveCursor(-(tail.len() as isize)));
+                }
+            }
+        }
+
+        actions
+    }
+
+    /// Moves the cursor of the line being edited by `offset` characters, as long as it stays
+    /// inside of the line.
+    pub fn move_cursor(&mut self, offset: isize) -> Option<Action> {
+        if !self.termios.is_cooked() {
+            return None;
+        }
+
+        let cursor = self
+            .cursor
+            .checked_add_signed(offset)
+            .filter(|&cursor| cursor <= self.line.len())?;
+
+        self.cursor = cursor;
+        Some(Action::MoveCursor(offset))
+    }
+
+    /// Processes bytes written to the terminal.
+    pub fn output_bytes(&self, bytes: &[u8]) -> Vec<u8> {
+        process_output(&self.termios, bytes)
+    }
+
+    /// Returns whether there is input to read.
+    pub fn is_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    /// Reads the input that is ready into `buffer`, and returns the number of bytes read. In
+    /// canonical mode, at most one line is read, and zero is returned at the end of file.
+    pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+        let mut size = 0;
+
+        while let Some(input) = self.ready.front_mut() {
+            let count = input.len().min(buffer.len() - size);
+
+            buffer[size..size + count].copy_from_slice(&input[..count]);
+            input.drain(..count);
+            size += count;
+
+            if !input.is_empty() {
+                break;
+            }
+
+            self.ready.pop_front();
+
+            if self.termios.is_cooked() || size == buffer.len() {
+                break;
+            }
+        }
+
+        size
+    }
+
+    /// Discards the input that has not been read yet, including the line being edited.
+    pub fn flush_input(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+        self.ready.clear();
+    }
+}
+
+/// Processes bytes written to a terminal configured with `termios` (`OPOST`).
+pub fn process_output(termios: &Termios, bytes: &[u8]) -> Vec<u8> {
+    let oflag = termios.c_oflag;
+
+    if !oflag.contains(TermiosOFlag::OPOST) {
+        return bytes.to_vec();
+    }
+
+    let mut output = Vec::with_capacity(bytes.len());
+
+    for &byte in bytes {
+        match byte {
+            b'\n' if oflag.contains(TermiosOFlag::ONLCR) => output.extend_from_slice(b"\r\n"),
+            b'\r' if oflag.contains(TermiosOFlag::OCRNL) => output.push(b'\n'),
+            _ => output.push(byte),
+        }
+    }
+
+    output
+}
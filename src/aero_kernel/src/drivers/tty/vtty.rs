@@ -19,9 +19,13 @@
 
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+use aero_syscall::signal::{SignalHandler, SIGHUP, SIGTTOU};
+use aero_syscall::OpenFlags;
+
 use alloc::sync::{Arc, Weak};
-use alloc::vec::Vec;
+use spin::Once;
 
+use crate::arch::user_copy::UserRef;
 use crate::fs::inode::{self, PollFlags, PollTable};
 use crate::fs::{devfs, FileSystemError};
 use crate::{fs, rendy};
@@ -29,28 +33,44 @@ use crate::{fs, rendy};
 use crate::fs::inode::INodeInterface;
 use crate::mem::paging::VirtAddr;
 use crate::userland::scheduler;
+use crate::userland::task::sessions::SESSIONS;
 use crate::userland::task::Task;
 use crate::userland::terminal::TerminalDevice;
 use crate::utils::sync::{Mutex, WaitQueue};
 
+use super::line_discipline::{Action, LineDiscipline};
+
 #[cfg(target_arch = "x86_64")]
-use crate::drivers::keyboard::KeyCode;
-#[cfg(target_arch = "x86_64")]
-use crate::drivers::keyboard::KeyboardListener;
+use crate::drivers::keyboard::{self, KeyCode, KeyState, KeyboardListener};
 
 lazy_static::lazy_static! {
     static ref TTY: Arc<Tty> = Tty::new();
+}
 
-    static ref TERMIOS: Mutex<aero_syscall::Termios> = Mutex::new(aero_syscall::Termios {
+fn default_termios() -> aero_syscall::Termios {
+    // converts `^X` into `X`
+    let ctrl = |c| (c as u8 - 0x40);
+
+    let mut termios = aero_syscall::Termios {
         c_iflag: aero_syscall::TermiosIFlag::empty(),
         c_oflag: aero_syscall::TermiosOFlag::empty(),
         c_cflag: aero_syscall::TermiosCFlag::empty(),
-        c_lflag: aero_syscall::TermiosLFlag::ECHO | aero_syscall::TermiosLFlag::ICANON,
+        c_lflag: aero_syscall::TermiosLFlag::ECHO
+            | aero_syscall::TermiosLFlag::ECHOE
+            | aero_syscall::TermiosLFlag::ICANON,
         c_line: 0,
         c_cc: [0; 32],
         c_ispeed: 0,
         c_ospeed: 0,
-    });
+    };
+
+    // The backspace key sends `^H`.
+    termios.c_cc[aero_syscall::VERASE] = ctrl('H');
+    termios.c_cc[aero_syscall::VKILL] = ctrl('U');
+    termios.c_cc[aero_syscall::VEOF] = ctrl('D');
+    termios.c_cc[aero_syscall::VINTR] = ctrl('C');
+
+    termios
 }
 
 // From the linux kernel: https://github.com/torvalds/linux/blob/master/drivers/tty/vt/defkeymap.c_shipped
@@ -152,38 +172,6 @@ const CTRL_ALT_MAP: &[u16; 128] = &[
     0xf200, 0xf200, 0xf200, 0xf200, 0xf200, 0xf200, 0xf200, 0xf200,
 ];
 
-struct StdinBuffer {
-    back_buffer: Vec<u8>,
-    front_buffer: Vec<u8>, // more like a queue
-
-    cursor: usize,
-}
-
-impl StdinBuffer {
-    fn new() -> Self {
-        Self {
-            back_buffer: Vec::new(),
-            front_buffer: Vec::new(),
-
-            cursor: 0,
-        }
-    }
-
-    fn swap_buffer(&mut self) {
-        for c in self.back_buffer.drain(..) {
-            self.front_buffer.push(c);
-        }
-    }
-
-    fn is_complete(&self) -> bool {
-        !self.back_buffer.is_empty() || !self.front_buffer.is_empty()
-    }
-
-    fn advance_cursor(&mut self) {
-        self.cursor += 1;
-    }
-}
-
 struct TtyState {
     lshift: bool,
     rshift: bool,
@@ -199,10 +187,14 @@ struct Tty {
     state: Mutex<TtyState>,
     sref: Weak<Self>,
 
-    stdin: Mutex<StdinBuffer>,
+    discipline: Mutex<LineDiscipline>,
     block_queue: WaitQueue,
 
     connected: AtomicUsize,
+    listening: Once<()>,
+    /// Process group ID of the foreground process group, or 0 if the terminal is not the
+    /// controlling terminal of a session yet.
+    foreground_pgid: AtomicUsize,
 }
 
 impl Tty {
@@ -219,8 +211,10 @@ impl Tty {
                 caps: false,
             }),
             block_queue: WaitQueue::new(),
-            stdin: Mutex::new(StdinBuffer::new()),
+            discipline: Mutex::new(LineDiscipline::new(default_termios())),
             connected: AtomicUsize::new(0),
+            listening: Once::new(),
+            foreground_pgid: AtomicUsize::new(0),
             sref: sref.clone(),
         })
     }
@@ -229,48 +223,51 @@ impl Tty {
 impl INodeInterface for Tty {
     fn open(
         &self,
-        _handle: Arc<fs::file_table::FileHandle>,
+        handle: Arc<fs::file_table::FileHandle>,
     ) -> fs::Result<Option<fs::cache::DirCacheItem>> {
         let connected = self.connected.fetch_add(1, Ordering::SeqCst);
         if connected == 0 {
-            crate::drivers::keyboard::register_keyboard_listener(TTY.clone());
+            // The terminal can be reopened after the last handle to it was closed, so make
+            // sure the listener is only registered once.
+            self.listening
+                .call_once(|| crate::drivers::keyboard::register_keyboard_listener(TTY.clone()));
+        }
 
+        // A session leader without a controlling terminal acquires the terminal when it opens
+        // it, if no other session controls it.
+        if !handle.flags().contains(OpenFlags::O_NOCTTY) {
             let current_task = scheduler::get_scheduler().current_task();
-            current_task.attach(self.sref.upgrade().unwrap());
+            let _ = current_task.attach(self.sref.upgrade().unwrap());
         }
 
         Ok(None)
     }
 
-    fn close(&self, _flags: aero_syscall::OpenFlags) {
-        self.connected.fetch_sub(1, Ordering::SeqCst);
-    }
-
-    fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> fs::Result<usize> {
-        self.block_queue
-            .block_on(&self.stdin, |future| future.is_complete())?;
-
-        let mut stdin = self.stdin.lock_irq();
+    fn close(&self, _flags: OpenFlags) {
+        if self.connected.fetch_sub(1, Ordering::SeqCst) != 1 {
+            return;
+        }
 
-        // record the back buffer size before swapping
-        stdin.swap_buffer();
-        let back_len = stdin.front_buffer.len();
+        // The last handle was closed: hang up.
+        let foreground = self.foreground_pgid.load(Ordering::SeqCst);
 
-        if buffer.len() > stdin.front_buffer.len() {
-            for (i, c) in stdin.front_buffer.drain(..).enumerate() {
-                buffer[i] = c;
-            }
-        } else {
-            for (i, c) in stdin.front_buffer.drain(..buffer.len()).enumerate() {
-                buffer[i] = c;
-            }
+        if let Some(group) = SESSIONS.find_group_by_id(foreground) {
+            group.signal(SIGHUP);
         }
+    }
+
+    fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> fs::Result<usize> {
+        let mut discipline = self
+            .block_queue
+            .block_on_interruptible(&self.discipline, |discipline| discipline.is_ready())
+            .map_err(|_| FileSystemError::Interrupted)?;
 
-        Ok(core::cmp::min(buffer.len(), back_len))
+        Ok(discipline.read(buffer))
     }
 
     fn write_at(&self, _offset: usize, buffer: &[u8]) -> fs::Result<usize> {
-        let string = core::str::from_utf8(buffer).map_err(|_| FileSystemError::NotSupported)?;
+        let output = self.discipline.lock_irq().output_bytes(buffer);
+        let string = core::str::from_utf8(&output).map_err(|_| FileSystemError::NotSupported)?;
 
         crate::rendy::print!("{}", string);
 
@@ -287,7 +284,7 @@ impl INodeInterface for Tty {
         }
         let mut events = PollFlags::empty();
 
-        if self.stdin.lock_irq().is_complete() {
+        if self.discipline.lock_irq().is_ready() {
             events.insert(PollFlags::IN);
         }
 
@@ -297,8 +294,7 @@ impl INodeInterface for Tty {
     fn ioctl(&self, command: usize, arg: usize) -> fs::Result<usize> {
         match command {
             aero_syscall::TIOCGWINSZ => {
-                let winsize = VirtAddr::new(arg as u64);
-                let winsize = unsafe { &mut *(winsize.as_mut_ptr::<aero_syscall::WinSize>()) };
+                let mut winsize = UserRef::<aero_syscall::WinSize>::new(VirtAddr::new(arg as u64))?;
 
                 let (rows, cols) = rendy::get_rows_cols();
 
@@ -310,34 +306,88 @@ impl INodeInterface for Tty {
                 winsize.ws_xpixel = xpixel as u16;
                 winsize.ws_ypixel = ypixel as u16;
 
+                winsize.write_back()?;
                 Ok(0x00)
             }
 
             aero_syscall::TCGETS => {
-                let termios = VirtAddr::new(arg as u64);
-                let termios = unsafe { &mut *(termios.as_mut_ptr::<aero_syscall::Termios>()) };
+                let mut termios = UserRef::<aero_syscall::Termios>::new(VirtAddr::new(arg as u64))?;
 
-                let lock = TERMIOS.lock_irq();
-                let this = &*lock;
-
-                *termios = this.clone();
+                *termios = self.discipline.lock_irq().termios().clone();
+                termios.write_back()?;
                 Ok(0x00)
             }
 
             aero_syscall::TCSETSF => {
                 // Allow the output buffer to drain, discard pending input.
-                let mut stdin = self.stdin.lock_irq();
-                stdin.back_buffer.clear();
-                stdin.cursor = 0;
-                core::mem::drop(stdin);
+                let termios = UserRef::<aero_syscall::Termios>::new(VirtAddr::new(arg as u64))?;
+                let mut discipline = self.discipline.lock_irq();
+
+                discipline.flush_input();
+                discipline.set_termios(termios.take());
+                Ok(0x00)
+            }
+
+            aero_syscall::TCSETSW => {
+                // Allow the output buffer to drain, keep pending input.
+                //
+                // TODO: wait for output drain. Writes are printed before `write_at` returns, so
+                // there is no pending output for now.
+                let termios = UserRef::<aero_syscall::Termios>::new(VirtAddr::new(arg as u64))?;
+
+                self.discipline.lock_irq().set_termios(termios.take());
+                Ok(0x00)
+            }
 
-                let termios = VirtAddr::new(arg as u64);
-                let termios = unsafe { &*(termios.as_mut_ptr::<aero_syscall::Termios>()) };
+            aero_syscall::TIOCSCTTY => {
+                let current_task = scheduler::get_scheduler().current_task();
 
-                let mut lock = TERMIOS.lock_irq();
-                let this = &mut *lock;
+                current_task.attach(self.sref.upgrade().unwrap())?;
+                Ok(0x00)
+            }
 
-                *this = termios.clone();
+            aero_syscall::TIOCGPGRP => {
+                let mut pgrp = UserRef::<i32>::new(VirtAddr::new(arg as u64))?;
+
+                *pgrp = self.foreground_pgid.load(Ordering::SeqCst) as i32;
+                pgrp.write_back()?;
+                Ok(0x00)
+            }
+
+            aero_syscall::TIOCSPGRP => {
+                let pgrp = UserRef::<i32>::new(VirtAddr::new(arg as u64))?;
+                let current_task = scheduler::get_scheduler().current_task();
+                let foreground = self.foreground_pgid.load(Ordering::SeqCst);
+
+                // A background process group is stopped, unless it blocks or ignores `SIGTTOU`;
+                // which is what job control shells do to take the terminal back.
+                if foreground != 0 && current_task.group_id() != foreground {
+                    let signals = current_task.signals();
+                    let ignored = signals.is_blocked(SIGTTOU)
+                        || matches!(signals.entry(SIGTTOU).handler(), SignalHandler::Ignore);
+
+                    if !ignored {
+                        SESSIONS.find_group(&current_task).unwrap().signal(SIGTTOU);
+                        return Err(FileSystemError::Interrupted);
+                    }
+                }
+
+                let pgid = usize::try_from(*pgrp).map_err(|_| FileSystemError::InvalidInput)?;
+                let group = SESSIONS
+                    .find_group_in(current_task.session_id(), pgid)
+                    .ok_or(FileSystemError::NotPermitted)?;
+
+                self.foreground_pgid.store(group.id(), Ordering::SeqCst);
+                Ok(0x00)
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            aero_syscall::KDKBDREP => {
+                let mut repeat =
+                    UserRef::<aero_syscall::KbdRepeat>::new(VirtAddr::new(arg as u64))?;
+
+                keyboard::set_key_repeat(&mut repeat);
+                repeat.write_back()?;
                 Ok(0x00)
             }
 
@@ -361,8 +411,10 @@ impl devfs::Device for Tty {
 }
 
 impl TerminalDevice for Tty {
-    fn attach(&self, _task: Arc<Task>) {
-        // FIXME: We should handle foreground groups in TTY aswell
+    fn attach(&self, task: Arc<Task>) {
+        assert!(task.is_session_leader());
+        self.foreground_pgid
+            .store(task.group_id(), Ordering::SeqCst);
     }
 
     fn detach(&self, _task: Arc<Task>) {
@@ -371,119 +423,71 @@ impl TerminalDevice for Tty {
 }
 
 #[cfg(target_arch = "x86_64")]
-impl KeyboardListener for Tty {
-    fn on_key(&self, key: KeyCode, released: bool) {
-        let mut state = self.state.lock();
-        let termios = TERMIOS.lock_irq();
-
-        let push_str = |k: &str| {
-            // TODO: decckm
-            let mut stdin = self.stdin.lock_irq();
+impl Tty {
+    /// Returns the character produced by `key` with the modifiers that are held down, if it is
+    /// printable.
+    fn key_char(state: &TtyState, key: KeyCode) -> Option<u8> {
+        let mut shift = state.lshift || state.rshift;
+        let ctrl = state.lctrl || state.rctrl;
+
+        if state.caps {
+            shift = !shift;
+        }
 
-            for each in k.bytes() {
-                stdin.back_buffer.push(each);
-            }
+        let map = match (shift, ctrl, state.lalt, state.altgr) {
+            (false, false, false, false) => PLAIN_MAP,
+            (true, false, false, false) => SHIFT_MAP,
+            (false, true, false, false) => CTRL_MAP,
+            (false, false, true, false) => ALT_MAP,
+            (false, false, false, true) => ALTGR_MAP,
+            (true, true, false, false) => SHIFT_CTRL_MAP,
+            (false, true, true, false) => CTRL_ALT_MAP,
+            _ => PLAIN_MAP,
         };
 
-        let lchar = || {
-            let mut shift = state.lshift || state.rshift;
-            let ctrl = state.lctrl || state.rctrl;
-
-            if state.caps {
-                shift = !shift;
-            }
-
-            let map = match (shift, ctrl, state.lalt, state.altgr) {
-                (false, false, false, false) => PLAIN_MAP,
-                (true, false, false, false) => SHIFT_MAP,
-                (false, true, false, false) => CTRL_MAP,
-                (false, false, true, false) => ALT_MAP,
-                (false, false, false, true) => ALTGR_MAP,
-                (true, true, false, false) => SHIFT_CTRL_MAP,
-                (false, true, true, false) => CTRL_ALT_MAP,
-                _ => PLAIN_MAP,
-            };
-
-            let character =
-                unsafe { core::char::from_u32_unchecked((map[key as usize] & 0xff) as _) };
-
-            // Check if the character is actually printable printable.
-            if !(0x20..0x7e).contains(&(character as u32)) {
-                return;
-            }
-
-            {
-                let mut stdin = self.stdin.lock_irq();
-
-                stdin.back_buffer.push(character as u8);
-                stdin.advance_cursor();
-            }
-
-            if termios.c_lflag.contains(aero_syscall::TermiosLFlag::ECHO) {
-                rendy::print!("{}", character);
-            }
-        };
+        let character = (map[key as usize] & 0xff) as u8;
 
-        let backspace = || {
-            if termios.c_lflag.contains(aero_syscall::TermiosLFlag::ICANON) {
-                let mut stdin = self.stdin.lock_irq();
+        // Check if the character is actually printable.
+        (0x20..0x7e).contains(&character).then_some(character)
+    }
 
-                if stdin.back_buffer.pop().is_some()
-                    && termios.c_lflag.contains(aero_syscall::TermiosLFlag::ECHO)
-                {
-                    rendy::backspace();
-                    stdin.cursor -= 1;
+    /// Performs the actions returned by the line discipline.
+    fn perform(&self, actions: impl IntoIterator<Item = Action>) {
+        for action in actions {
+            match action {
+                Action::Echo(bytes) => {
+                    rendy::print!("{}", core::str::from_utf8(&bytes).unwrap_or_default())
                 }
-            } else {
-                push_str("\x08");
-            }
-        };
-
-        if !termios.c_lflag.contains(aero_syscall::TermiosLFlag::ICANON) && !released {
-            match key {
-                KeyCode::KEY_BACKSPACE if !released => backspace(),
-                KeyCode::KEY_CAPSLOCK if !released => state.caps = !state.caps,
-
-                KeyCode::KEY_LEFTSHIFT => state.lshift = !released,
-                KeyCode::KEY_RIGHTSHIFT => state.rshift = !released,
-
-                KeyCode::KEY_LEFTCTRL => state.lctrl = !released,
-                KeyCode::KEY_RIGHTCTRL => state.rctrl = !released,
 
-                KeyCode::KEY_LEFTALT => state.lalt = !released,
-                KeyCode::KEY_RIGHTALT => state.altgr = !released,
-                KeyCode::KEY_ENTER => push_str("\n"),
-
-                KeyCode::KEY_UP => push_str("\x1b[A"),
-                KeyCode::KEY_LEFT => push_str("\x1b[D"),
-                KeyCode::KEY_DOWN => push_str("\x1b[B"),
-                KeyCode::KEY_RIGHT => push_str("\x1b[C"),
+                Action::MoveCursor(offset) => {
+                    let (x, y) = rendy::get_cursor_position();
+                    rendy::set_cursor_position(x.saturating_add_signed(offset), y);
+                }
 
-                _ if !released => lchar(),
-                _ => {}
+                // FIXME: We should handle foreground groups in TTY aswell
+                Action::Signal(signal) => log::debug!("vtty: dropped signal {signal}"),
+                Action::DeliverLine => self.block_queue.notify_all(),
             }
-
-            self.stdin.lock_irq().cursor = 0;
-            self.block_queue.notify_all();
-            return;
         }
+    }
 
-        match key {
-            KeyCode::KEY_CAPSLOCK if !released => state.caps = !state.caps,
-            KeyCode::KEY_ENTER | KeyCode::KEY_KPENTER if !released => {
-                let mut stdin = self.stdin.lock_irq();
-
-                stdin.back_buffer.push(b'\n');
-                stdin.cursor = 0;
+    fn input(&self, bytes: &[u8]) {
+        let mut discipline = self.discipline.lock_irq();
 
-                if termios.c_lflag.contains(aero_syscall::TermiosLFlag::ECHO) {
-                    rendy::print!("\n");
-                }
+        for &byte in bytes {
+            self.perform(discipline.input_byte(byte));
+        }
+    }
+}
 
-                self.block_queue.notify_all();
-            }
+#[cfg(target_arch = "x86_64")]
+impl KeyboardListener for Tty {
+    fn on_key(&self, key: KeyCode, key_state: KeyState) {
+        let released = key_state == KeyState::Released;
+        let mut state = self.state.lock();
 
-            KeyCode::KEY_BACKSPACE if !released => backspace(),
+        match key {
+            KeyCode::KEY_CAPSLOCK if !released => state.caps = !state.caps,
 
             KeyCode::KEY_LEFTSHIFT => state.lshift = !released,
             KeyCode::KEY_RIGHTSHIFT => state.rshift = !released,
@@ -494,39 +498,33 @@ impl KeyboardListener for Tty {
             KeyCode::KEY_LEFTALT => state.lalt = !released,
             KeyCode::KEY_RIGHTALT => state.altgr = !released,
 
-            KeyCode::KEY_LEFT if !released => {
-                let mut stdin = self.stdin.lock_irq();
+            _ if released => {}
 
-                // We are at the start of the input so, we cannot shift
-                // the cursor to the left anymore.
-                if stdin.cursor == 0 {
-                    return;
-                }
+            KeyCode::KEY_ENTER | KeyCode::KEY_KPENTER => self.input(b"\n"),
+            KeyCode::KEY_BACKSPACE => self.input(b"\x08"),
 
-                let (x, y) = rendy::get_cursor_position();
-                rendy::set_cursor_position(x - 1, y);
+            // In canonical mode, the left and right keys move the cursor in the line being
+            // edited. Otherwise, they are sent as escape sequences like the up and down keys.
+            KeyCode::KEY_LEFT | KeyCode::KEY_RIGHT
+                if self.discipline.lock_irq().termios().is_cooked() =>
+            {
+                let offset = if key == KeyCode::KEY_LEFT { -1 } else { 1 };
+                let action = self.discipline.lock_irq().move_cursor(offset);
 
-                stdin.cursor -= 1;
+                self.perform(action);
             }
 
-            KeyCode::KEY_RIGHT if !released => {
-                let mut stdin = self.stdin.lock_irq();
-
-                // We are at the end of the input so, we cannot shift
-                // the cursor to the right anymore.
-                if stdin.cursor == stdin.back_buffer.len() {
-                    return;
+            // TODO: decckm
+            KeyCode::KEY_UP => self.input(b"\x1b[A"),
+            KeyCode::KEY_LEFT => self.input(b"\x1b[D"),
+            KeyCode::KEY_DOWN => self.input(b"\x1b[B"),
+            KeyCode::KEY_RIGHT => self.input(b"\x1b[C"),
+
+            _ => {
+                if let Some(character) = Self::key_char(&state, key) {
+                    self.input(&[character]);
                 }
-
-                let (x, y) = rendy::get_cursor_position();
-                rendy::set_cursor_position(x + 1, y);
-
-                stdin.advance_cursor();
             }
-
-            _ if !released => lchar(),
-
-            _ => {}
         }
     }
 }
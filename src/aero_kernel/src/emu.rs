@@ -15,7 +15,50 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+//! Support for running the test suite under QEMU in CI (the `ci` feature).
+//!
+//! The results are reported as single-line JSON records on the second serial port (COM2), which
+//! `make test` redirects to a file and checks with `build-support/test_report.py`:
+//!
+//! ```text
+//! {"event":"test_start","suite":"kernel","name":"aero_kernel::tests::timer::timer_sleep"}
+//! {"event":"test_ok","suite":"kernel","name":"aero_kernel::tests::timer::timer_sleep"}
+//! {"event":"test_fail","suite":"kernel","name":"...","message":"assertion failed: ..."}
+//! {"event":"run_summary","suite":"kernel","passed":41,"failed":0,"filtered":0}
+//! ```
+//!
+//! The userland tests write their records to `/dev/ci-events`, which forwards them to the same
+//! port. The run ends with [`finish`], or with [`panicked`] if the kernel panics.
+
+use core::fmt::{self, Write};
+
+use alloc::sync::Arc;
+use spin::Once;
+
+use crate::drivers::uart_16550::SerialPort;
+use crate::fs::devfs::{self, alloc_device_marker, Device};
+use crate::fs::inode::{INodeInterface, PollFlags, PollTable};
+use crate::fs::Result;
 use crate::utils::io;
+use crate::utils::sync::Mutex;
+
+/// I/O port of COM2, which carries the test events.
+const EVENTS_PORT: u16 = 0x2f8;
+
+static EVENTS: Once<Mutex<SerialPort>> = Once::new();
+static DEV_CI_EVENTS: Once<Arc<DevCiEvents>> = Once::new();
+
+static RUN: Mutex<Run> = Mutex::new(Run {
+    test: None,
+    passed: 0,
+});
+
+/// State of the kernel test run.
+struct Run {
+    /// Test currently running, reported as failed if the kernel panics.
+    test: Option<&'static str>,
+    passed: usize,
+}
 
 #[repr(u32)]
 pub enum ExitStatus {
@@ -40,3 +83,199 @@ pub fn exit_qemu(exit_status: ExitStatus) -> ! {
         }
     }
 }
+
+/// A record of the test event protocol.
+pub enum Event<'a> {
+    TestStart {
+        name: &'a str,
+    },
+    TestOk {
+        name: &'a str,
+    },
+    TestFail {
+        name: &'a str,
+        message: &'a dyn fmt::Display,
+    },
+    RunSummary {
+        passed: usize,
+        failed: usize,
+        filtered: usize,
+    },
+}
+
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The kernel only emits the events of the kernel tests.
+        let suite = r#""suite":"kernel""#;
+
+        match self {
+            Self::TestStart { name } => {
+                write!(
+                    f,
+                    r#"{{"event":"test_start",{suite},"name":"{}"}}"#,
+                    Json(name)
+                )
+            }
+
+            Self::TestOk { name } => {
+                write!(
+                    f,
+                    r#"{{"event":"test_ok",{suite},"name":"{}"}}"#,
+                    Json(name)
+                )
+            }
+
+            Self::TestFail { name, message } => {
+                write!(
+                    f,
+                    r#"{{"event":"test_fail",{suite},"name":"{}","message":""#,
+                    Json(name)
+                )?;
+                write!(JsonEscape(&mut *f), "{message}")?;
+                f.write_str(r#""}"#)
+            }
+
+            Self::RunSummary {
+                passed,
+                failed,
+                filtered,
+            } => write!(
+                f,
+                r#"{{"event":"run_summary",{suite},"passed":{passed},"failed":{failed},"filtered":{filtered}}}"#
+            ),
+        }
+    }
+}
+
+/// Displays a string escaped for use in a JSON string literal.
+struct Json<'a>(&'a str);
+
+impl fmt::Display for Json<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        JsonEscape(f).write_str(self.0)
+    }
+}
+
+/// Writer escaping everything written through it for use in a JSON string literal.
+struct JsonEscape<W: Write>(W);
+
+impl<W: Write> Write for JsonEscape<W> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for c in string.chars() {
+            match c {
+                '"' => self.0.write_str("\\\"")?,
+                '\\' => self.0.write_str("\\\\")?,
+                '\n' => self.0.write_str("\\n")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\t' => self.0.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(self.0, "\\u{:04x}", c as u32)?,
+                c => self.0.write_char(c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn events() -> &'static Mutex<SerialPort> {
+    EVENTS.call_once(|| Mutex::new(unsafe { SerialPort::new(EVENTS_PORT).init() }))
+}
+
+/// Writes `event` as a line to the events port.
+pub fn emit(event: Event) {
+    let _ = writeln!(events().lock_irq(), "{event}");
+}
+
+/// Reports the start of the kernel test `name`.
+pub fn test_start(name: &'static str) {
+    RUN.lock_irq().test = Some(name);
+    emit(Event::TestStart { name });
+}
+
+/// Reports that the running kernel test passed.
+pub fn test_ok() {
+    let mut run = RUN.lock_irq();
+
+    if let Some(name) = run.test.take() {
+        run.passed += 1;
+        emit(Event::TestOk { name });
+    }
+}
+
+/// Reports the failure of the running kernel test, or of the kernel itself outside of one, and
+/// ends the run. Called from the panic handler.
+pub fn panicked(message: &dyn fmt::Display) -> ! {
+    // The panicking code may hold the locks.
+    unsafe {
+        RUN.force_unlock();
+        events().force_unlock();
+    }
+
+    let run = RUN.lock();
+    let name = run.test.unwrap_or("<panic>");
+
+    emit(Event::TestFail { name, message });
+
+    // Outside of a kernel test, the kernel suite already reported its summary.
+    if run.test.is_some() {
+        emit(Event::RunSummary {
+            passed: run.passed,
+            failed: 1,
+            filtered: 0,
+        });
+    }
+
+    exit_qemu(ExitStatus::Failure)
+}
+
+/// Ends the test run with the QEMU exit code matching `success`.
+pub fn finish(success: bool) -> ! {
+    if success {
+        exit_qemu(ExitStatus::Success)
+    } else {
+        exit_qemu(ExitStatus::Failure)
+    }
+}
+
+/// Implementation of `/dev/ci-events`, through which the userland tests report their events.
+struct DevCiEvents(usize);
+
+impl DevCiEvents {
+    fn new() -> Arc<Self> {
+        Arc::new(Self(alloc_device_marker()))
+    }
+}
+
+impl Device for DevCiEvents {
+    fn device_marker(&self) -> usize {
+        self.0
+    }
+
+    fn device_name(&self) -> String {
+        String::from("ci-events")
+    }
+
+    fn inode(&self) -> Arc<dyn INodeInterface> {
+        DEV_CI_EVENTS.get().expect("device not initialized").clone()
+    }
+}
+
+impl INodeInterface for DevCiEvents {
+    fn write_at(&self, _offset: usize, buffer: &[u8]) -> Result<usize> {
+        let mut port = events().lock_irq();
+        buffer.iter().for_each(|byte| port.send_byte(*byte));
+
+        Ok(buffer.len())
+    }
+
+    fn poll(&self, _table: Option<&mut PollTable>) -> Result<PollFlags> {
+        Ok(PollFlags::OUT)
+    }
+}
+
+fn ci_events_init() {
+    let device = DEV_CI_EVENTS.call_once(DevCiEvents::new);
+    devfs::install_device(device.clone()).unwrap();
+}
+
+crate::module_init!(ci_events_init, ModuleType::Other);
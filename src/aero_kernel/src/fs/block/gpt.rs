@@ -124,9 +124,7 @@ impl Gpt {
         // Get the GPT header.
         let mut header = Box::<GptTableHeader>::new_uninit();
 
-        controller
-            .read_block(1, header.as_bytes_mut())
-            .expect("gpt: failed to read first sector");
+        controller.read_block(1, header.as_bytes_mut())?;
 
         // SAFETY: The buffer is initialized above.
         let header = unsafe { header.assume_init() };
@@ -140,12 +138,10 @@ impl Gpt {
 
         let mut entry_list = Box::<[GptEntry]>::new_uninit_slice(header.num_entries as usize);
 
-        controller
-            .read_block(
-                header.starting_lba as _,
-                MaybeUninit::slice_as_bytes_mut(&mut entry_list),
-            )
-            .expect("gpt: failed to read entry list");
+        controller.read_block(
+            header.starting_lba as _,
+            MaybeUninit::slice_as_bytes_mut(&mut entry_list),
+        )?;
 
         // SAFETY: The entries list is initialized above.
         let entries = unsafe { entry_list.assume_init() };
@@ -16,19 +16,26 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 mod gpt;
+pub mod queue;
 
 use gpt::Gpt;
+use queue::{Plug, STATS};
 
 use core::mem::MaybeUninit;
 use core::ops::{Deref, DerefMut};
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
-use crate::fs::devfs::install_device;
-use crate::fs::{FileSystem, Result};
+use uapi::block::{
+    HdGeometry, BLKBSZGET, BLKGETSIZE, BLKGETSIZE64, BLKROGET, BLKROSET, BLKSSZGET, HDIO_GETGEO,
+};
+
+use crate::arch::user_copy::{copy_to_user, UserRef};
+use crate::fs::devfs::{install_device, uninstall_device};
+use crate::fs::{FileSystem, FileSystemError, Result};
 
 use crate::fs::ext2::Ext2;
 use crate::mem::paging::*;
@@ -42,6 +49,13 @@ use super::inode::INodeInterface;
 type PageCacheKey = (usize, usize); // (owner ptr, index)
 pub type PageCacheItem = CacheArc<CacheItem<PageCacheKey, CachedPage>>;
 
+/// Number of pages read ahead of a sequential reader.
+const READAHEAD_PAGES: usize = 32;
+
+/// Unit of the sizes and offsets reported by the legacy ioctls, whatever the block size of the
+/// device is.
+const SECTOR_SIZE: usize = 512;
+
 struct DirtyMapping {
     addr_space: AddressSpace,
     addr: VirtAddr,
@@ -53,6 +67,8 @@ pub struct CachedPage {
     page: PhysFrame,
     dirty: AtomicBool,
     dirty_mappings: Mutex<Vec<DirtyMapping>>,
+    /// Set if the page was read ahead and has not been accessed yet.
+    readahead: AtomicBool,
 }
 
 impl CachedPage {
@@ -65,6 +81,7 @@ impl CachedPage {
                 .expect("page_cache: out of memory"),
             dirty: AtomicBool::new(false),
             dirty_mappings: Mutex::new(Vec::new()),
+            readahead: AtomicBool::new(false),
         };
         // TODO: temporary hack. i mean this is fine but is there a cleaner way to do this. this is
         // required since when the VM for the process umaps a page that contains a cached page, it
@@ -118,9 +135,17 @@ impl CachedPage {
 
         // Commit the changes made to the cache to the owner.
         let owner = self.device();
-        let offset_bytes = self.offset * Size4KiB::SIZE as usize;
-        owner.write_direct(offset_bytes, self.page);
+        owner.write_direct(self.offset_bytes(), self.page);
 
+        self.clean();
+    }
+
+    fn offset_bytes(&self) -> usize {
+        self.offset * Size4KiB::SIZE as usize
+    }
+
+    /// Marks the page clean after it has been written back to the owner.
+    fn clean(&self) {
         for mut mapping in self.dirty_mappings.lock_irq().drain(..) {
             let mut offset_table = mapping.addr_space.offset_page_table();
             offset_table
@@ -159,23 +184,157 @@ impl Cache<PageCacheKey, CachedPage> {
     /// * `device` - The device to get the page from.
     /// * `offset` - The offset in bytes to the data. This will be rounded down to the nearest page
     ///   boundary.
-    pub fn get_page(&self, device: &Weak<dyn CachedAccess>, offset: usize) -> PageCacheItem {
+    ///
+    /// Returns `None` if the page could not be read from the device.
+    pub fn get_page(
+        &self,
+        device: &Weak<dyn CachedAccess>,
+        offset: usize,
+    ) -> Option<PageCacheItem> {
         let cache_offset = offset / Size4KiB::SIZE as usize;
         let cache_key = CachedPage::make_key(device, cache_offset);
 
         if let Some(page) = PAGE_CACHE.get(cache_key) {
-            return page;
+            return Some(page);
         }
 
         let page = CachedPage::new(device.clone(), cache_offset);
         let device = device.upgrade().expect("page_cache: device dropped");
 
-        let aligned_offset = align_down(offset as u64, Size4KiB::SIZE) as usize;
-        device
-            .read_direct(aligned_offset, page.page())
-            .expect("page_cache: failed to read block");
+        device.read_direct(page.offset_bytes(), page.page())?;
+        Some(PAGE_CACHE.make_item_cached(page))
+    }
+
+    /// Same as [`Cache::get_page`], but reads the following pages ahead if `device` is being
+    /// read sequentially. The pages read ahead are placed in the page cache.
+    pub fn get_page_readahead(
+        &self,
+        device: &Weak<dyn CachedAccess>,
+        offset: usize,
+    ) -> Option<PageCacheItem> {
+        let cache_offset = offset / Size4KiB::SIZE as usize;
+        let owner = device.upgrade().expect("page_cache: device dropped");
+
+        let sequential = owner
+            .readahead()
+            .is_some_and(|readahead| readahead.access(cache_offset));
+
+        if let Some(page) = PAGE_CACHE.get(CachedPage::make_key(device, cache_offset)) {
+            if page.readahead.swap(false, Ordering::Relaxed) {
+                STATS.readahead_hits.fetch_add(1, Ordering::Relaxed);
+            }
+
+            return Some(page);
+        }
+
+        if !sequential {
+            return self.get_page(device, offset);
+        }
+
+        // Read the page together with the following pages that are not cached yet, so that the
+        // requests can be merged.
+        let pages = core::iter::once(cache_offset)
+            .chain(
+                (cache_offset + 1..cache_offset + READAHEAD_PAGES)
+                    .take_while(|&i| !PAGE_CACHE.contains(&CachedPage::make_key(device, i))),
+            )
+            .map(|i| CachedPage::new(device.clone(), i))
+            .collect::<Vec<_>>();
+
+        let requests = pages
+            .iter()
+            .map(|page| (page.offset_bytes(), page.page()))
+            .collect::<Vec<_>>();
+
+        let results = owner.read_direct_batch(&requests);
+
+        if !results[0] {
+            return None;
+        }
+
+        let mut pages = pages.into_iter().zip(results);
+        let (page, _) = pages.next().unwrap();
+
+        // Pages that could not be read (e.g. past the end of the device) are dropped. The
+        // others are placed in the page cache and left unused until they are accessed.
+        for (page, _) in pages.filter(|(_, ok)| *ok) {
+            STATS.readahead.fetch_add(1, Ordering::Relaxed);
+
+            page.readahead.store(true, Ordering::Relaxed);
+            drop(PAGE_CACHE.make_item_cached(page));
+        }
+
+        Some(PAGE_CACHE.make_item_cached(page))
+    }
+
+    /// Drops the pages of `device` from the page cache without writing them back, and returns
+    /// the number of dirty pages that were discarded.
+    fn invalidate(&self, device: &Weak<dyn CachedAccess>) -> usize {
+        let owner = device.as_ptr().addr();
+        let mut discarded = 0;
+
+        for page in self.items() {
+            let key = page.cache_key();
+
+            if key.0 != owner {
+                continue;
+            }
+
+            if page.is_dirty() {
+                page.clean();
+                discarded += 1;
+            }
+
+            self.remove(&key);
+        }
+
+        discarded
+    }
+}
+
+/// Writes all of the dirty pages in the page cache back to their devices.
+pub fn sync() {
+    let mut dirty = PAGE_CACHE
+        .items()
+        .into_iter()
+        .filter(|page| page.is_dirty())
+        .collect::<Vec<_>>();
+
+    // Write the dirty pages of each owner back in one batch, so that adjacent pages are merged.
+    dirty.sort_by_key(|page| page.cache_key());
+
+    for pages in dirty.chunk_by(|a, b| a.cache_key().0 == b.cache_key().0) {
+        let Some(owner) = pages[0].owner.upgrade() else {
+            continue;
+        };
+
+        let requests = pages
+            .iter()
+            .map(|page| (page.offset_bytes(), page.page()))
+            .collect::<Vec<_>>();
 
-        PAGE_CACHE.make_item_cached(page)
+        owner.write_direct_batch(&requests);
+        pages.iter().for_each(|page| page.clean());
+    }
+}
+
+/// Detects sequential access to a [`CachedAccess`] owner.
+pub struct Readahead {
+    /// Index of the page that a sequential reader accesses next.
+    next: AtomicUsize,
+}
+
+impl Readahead {
+    const fn new() -> Self {
+        Self {
+            next: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    /// Records an access to the page at `index` and returns whether it continues a sequential
+    /// read.
+    fn access(&self, index: usize) -> bool {
+        self.next.swap(index + 1, Ordering::Relaxed) == index
     }
 }
 
@@ -188,7 +347,9 @@ pub struct DirtyRef<T: Sized> {
 
 impl<T> DirtyRef<T> {
     pub fn new(device: &Weak<dyn CachedAccess>, offset: usize) -> Self {
-        let cache = PAGE_CACHE.get_page(device, offset);
+        let cache = PAGE_CACHE
+            .get_page(device, offset)
+            .expect("page_cache: failed to read block");
 
         let ptr_offset = offset % Size4KiB::SIZE as usize;
         let ptr = &cache.data_mut()[ptr_offset..ptr_offset + core::mem::size_of::<T>()];
@@ -221,11 +382,47 @@ unsafe impl<T> Send for DirtyRef<T> {}
 pub trait BlockDeviceInterface: Send + Sync {
     fn block_size(&self) -> usize;
 
+    /// Returns the capacity of the device, in blocks.
+    fn block_count(&self) -> usize;
+
     fn read_dma(&self, sector: usize, start: PhysAddr, size: usize) -> Option<usize>;
     fn write_dma(&self, sector: usize, start: PhysAddr, size: usize) -> Option<usize>;
 
     fn read_block(&self, sector: usize, dest: &mut [MaybeUninit<u8>]) -> Option<usize>;
     fn write_block(&self, sector: usize, buf: &[u8]) -> Option<usize>;
+
+    /// Returns the maximum number of bytes transferred by a single command.
+    fn max_transfer_size(&self) -> usize {
+        Size4KiB::SIZE as usize
+    }
+
+    /// Reads the consecutive pages starting at `sector` into `frames` with a single command,
+    /// if the device supports it. The size of the transfer is at most
+    /// [`BlockDeviceInterface::max_transfer_size`].
+    fn read_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        let sectors_per_page = Size4KiB::SIZE as usize / self.block_size();
+
+        for (i, frame) in frames.iter().enumerate() {
+            let sector = sector + i * sectors_per_page;
+            self.read_dma(sector, frame.start_address(), Size4KiB::SIZE as _)?;
+        }
+
+        Some(frames.len() * Size4KiB::SIZE as usize)
+    }
+
+    /// Writes `frames` to the consecutive pages starting at `sector` with a single command, if
+    /// the device supports it. The size of the transfer is at most
+    /// [`BlockDeviceInterface::max_transfer_size`].
+    fn write_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        let sectors_per_page = Size4KiB::SIZE as usize / self.block_size();
+
+        for (i, frame) in frames.iter().enumerate() {
+            let sector = sector + i * sectors_per_page;
+            self.write_dma(sector, frame.start_address(), Size4KiB::SIZE as _)?;
+        }
+
+        Some(frames.len() * Size4KiB::SIZE as usize)
+    }
 }
 
 pub trait CachedAccess: Send + Sync {
@@ -234,11 +431,38 @@ pub trait CachedAccess: Send + Sync {
     fn read_direct(&self, offset: usize, dest: PhysFrame) -> Option<usize>;
     fn write_direct(&self, offset: usize, src: PhysFrame) -> Option<usize>;
 
+    /// Reads each of the `(offset, frame)` pairs and returns whether each of the reads
+    /// succeeded.
+    fn read_direct_batch(&self, requests: &[(usize, PhysFrame)]) -> Vec<bool> {
+        requests
+            .iter()
+            .map(|&(offset, dest)| self.read_direct(offset, dest).is_some())
+            .collect()
+    }
+
+    /// Writes each of the `(offset, frame)` pairs and returns whether each of the writes
+    /// succeeded.
+    fn write_direct_batch(&self, requests: &[(usize, PhysFrame)]) -> Vec<bool> {
+        requests
+            .iter()
+            .map(|&(offset, src)| self.write_direct(offset, src).is_some())
+            .collect()
+    }
+
+    /// Returns the sequential access detector of the owner, if pages should be read ahead.
+    fn readahead(&self) -> Option<&Readahead> {
+        None
+    }
+
     fn read(&self, mut offset: usize, dest: &mut [MaybeUninit<u8>]) -> Option<usize> {
         let mut loc = 0;
 
         while loc < dest.len() {
-            let page = PAGE_CACHE.get_page(&self.sref(), offset);
+            let page = if self.readahead().is_some() {
+                PAGE_CACHE.get_page_readahead(&self.sref(), offset)?
+            } else {
+                PAGE_CACHE.get_page(&self.sref(), offset)?
+            };
 
             let page_offset = offset % Size4KiB::SIZE as usize;
             let size = core::cmp::min(Size4KiB::SIZE as usize - page_offset, dest.len() - loc);
@@ -268,7 +492,7 @@ pub trait CachedAccess: Send + Sync {
             // TODO: If it is not found in the page cache, then, when the write perfectly falls on
             // page size boundaries, the page is not even read from disk, but allocated and
             // immediately marked dirty.
-            let page = PAGE_CACHE.get_page(&self.sref(), offset);
+            let page = PAGE_CACHE.get_page(&self.sref(), offset)?;
 
             let page_offset = offset % Size4KiB::SIZE as usize;
             let size = core::cmp::min(Size4KiB::SIZE as usize - page_offset, buffer.len() - loc);
@@ -291,6 +515,11 @@ pub trait CachedAccess: Send + Sync {
 
 static BLOCK_DEVS: Mutex<BTreeMap<usize, Arc<BlockDevice>>> = Mutex::new(BTreeMap::new());
 
+/// Returns the installed block devices.
+pub fn block_devices() -> Vec<Arc<BlockDevice>> {
+    BLOCK_DEVS.lock().values().cloned().collect()
+}
+
 /// Installs the provided block `device` into the filesyetm.
 pub fn install_block_device(dev: Arc<BlockDevice>) -> Result<()> {
     let mut devs = BLOCK_DEVS.lock();
@@ -302,26 +531,127 @@ pub fn install_block_device(dev: Arc<BlockDevice>) -> Result<()> {
     Ok(())
 }
 
+/// Removes the block `device` and the block devices of its partitions from the filesystem,
+/// after the disk backing them was removed.
+///
+/// The pages of the devices are dropped from the page cache: dirty pages cannot be written
+/// back anymore and are discarded. Files that are already open keep referring to the devices,
+/// but all I/O on them fails.
+pub fn uninstall_block_device(device: &Arc<BlockDevice>) -> Result<()> {
+    let removed = {
+        let mut devs = BLOCK_DEVS.lock();
+
+        let ids = devs
+            .values()
+            .filter(|dev| dev.id == device.id || dev.parent == Some(device.id))
+            .map(|dev| dev.id)
+            .collect::<Vec<_>>();
+
+        ids.iter()
+            .filter_map(|id| devs.remove(id))
+            .collect::<Vec<_>>()
+    };
+
+    for dev in removed {
+        let discarded = PAGE_CACHE.invalidate(&dev.sref());
+
+        if discarded != 0 {
+            log::warn!(
+                "block: discarded {} dirty pages of {}",
+                discarded,
+                dev.name()
+            );
+        }
+
+        if dev.mounted.load(Ordering::SeqCst) {
+            log::error!(
+                "block: {} was removed while mounted, the filesystem on it is wedged and all I/O \
+                 on it fails with EIO",
+                dev.name()
+            );
+        }
+
+        uninstall_device(dev.clone())?;
+        log::debug!("block: uninstalled block device {}", dev.name());
+    }
+
+    Ok(())
+}
+
 pub struct BlockDevice {
     id: usize,
     name: String,
     dev: Arc<dyn BlockDeviceInterface>,
     sref: Weak<BlockDevice>,
+    readahead: Readahead,
+    /// The ID of the block device this is a partition of.
+    parent: Option<usize>,
+    /// The first sector of the partition on its parent device.
+    start: usize,
+    /// Set if writes to the device file are refused (`BLKROSET`).
+    read_only: AtomicBool,
+    /// Set if a filesystem on the device is mounted.
+    mounted: AtomicBool,
+    /// Set once the device was scanned for partitions.
+    scanned: AtomicBool,
 }
 
 impl BlockDevice {
     pub fn new(name: String, imp: Arc<dyn BlockDeviceInterface>) -> Arc<BlockDevice> {
+        Self::new_partition(name, imp, None, 0)
+    }
+
+    fn new_partition(
+        name: String,
+        imp: Arc<dyn BlockDeviceInterface>,
+        parent: Option<usize>,
+        start: usize,
+    ) -> Arc<BlockDevice> {
         Arc::new_cyclic(|sref| BlockDevice {
             id: alloc_device_marker(),
             name,
             dev: imp,
             sref: sref.clone(),
+            readahead: Readahead::new(),
+            parent,
+            start,
+            read_only: AtomicBool::new(false),
+            mounted: AtomicBool::new(false),
+            scanned: AtomicBool::new(false),
         })
     }
 
     pub fn name(&self) -> String {
         self.name.clone()
     }
+
+    /// Returns whether writes to the device file are refused. A partition is read-only if
+    /// either it or the disk it is on is.
+    pub fn is_read_only(&self) -> bool {
+        if self.read_only.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        self.parent
+            .and_then(|parent| BLOCK_DEVS.lock().get(&parent).cloned())
+            .is_some_and(|parent| parent.is_read_only())
+    }
+
+    /// Returns the legacy CHS geometry of the device. As with Linux, the disk is assumed to have
+    /// 255 heads and 63 sectors per track and the number of cylinders is derived from its size.
+    fn geometry(&self) -> HdGeometry {
+        const HEADS: usize = 255;
+        const SECTORS: usize = 63;
+
+        let sectors = self.block_count() * self.block_size() / SECTOR_SIZE;
+
+        HdGeometry {
+            heads: HEADS as u8,
+            sectors: SECTORS as u8,
+            cylinders: (sectors / (HEADS * SECTORS)).min(u16::MAX as usize) as u16,
+            start: (self.start * self.block_size() / SECTOR_SIZE) as u64,
+        }
+    }
 }
 
 impl BlockDeviceInterface for BlockDevice {
@@ -329,6 +659,10 @@ impl BlockDeviceInterface for BlockDevice {
         self.dev.block_size()
     }
 
+    fn block_count(&self) -> usize {
+        self.dev.block_count()
+    }
+
     fn read_dma(&self, sector: usize, start: PhysAddr, size: usize) -> Option<usize> {
         self.dev.read_dma(sector, start, size)
     }
@@ -344,6 +678,18 @@ impl BlockDeviceInterface for BlockDevice {
     fn write_block(&self, sector: usize, buf: &[u8]) -> Option<usize> {
         self.dev.write_block(sector, buf)
     }
+
+    fn max_transfer_size(&self) -> usize {
+        self.dev.max_transfer_size()
+    }
+
+    fn read_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        self.dev.read_frames(sector, frames)
+    }
+
+    fn write_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        self.dev.write_frames(sector, frames)
+    }
 }
 
 impl CachedAccess for BlockDevice {
@@ -352,23 +698,91 @@ impl CachedAccess for BlockDevice {
     }
 
     fn read_direct(&self, offset: usize, dest: PhysFrame) -> Option<usize> {
-        self.dev.read_dma(
-            offset / self.dev.block_size(),
-            dest.start_address(),
-            Size4KiB::SIZE as _,
-        )
+        self.read_direct_batch(&[(offset, dest)])[0].then_some(Size4KiB::SIZE as usize)
     }
 
     fn write_direct(&self, offset: usize, src: PhysFrame) -> Option<usize> {
-        self.dev.write_dma(
-            offset / self.dev.block_size(),
-            src.start_address(),
-            Size4KiB::SIZE as _,
-        )
+        self.write_direct_batch(&[(offset, src)])[0].then_some(Size4KiB::SIZE as usize)
+    }
+
+    fn read_direct_batch(&self, requests: &[(usize, PhysFrame)]) -> Vec<bool> {
+        let mut plug = Plug::new(&*self.dev);
+
+        for &(offset, dest) in requests {
+            plug.read(offset / self.dev.block_size(), dest);
+        }
+
+        plug.unplug()
+    }
+
+    fn write_direct_batch(&self, requests: &[(usize, PhysFrame)]) -> Vec<bool> {
+        let mut plug = Plug::new(&*self.dev);
+
+        for &(offset, src) in requests {
+            plug.write(offset / self.dev.block_size(), src);
+        }
+
+        plug.unplug()
+    }
+
+    fn readahead(&self) -> Option<&Readahead> {
+        Some(&self.readahead)
     }
 }
 
-impl INodeInterface for BlockDevice {}
+/// Reads and writes of the device file access the device directly, through the page cache.
+impl INodeInterface for BlockDevice {
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize> {
+        // SAFETY: `u8` and `MaybeUninit<u8>` have the same layout, and only initialized bytes
+        // are written to the buffer.
+        let buffer = unsafe { &mut *(core::ptr::from_mut(buffer) as *mut [MaybeUninit<u8>]) };
+        CachedAccess::read(self, offset, buffer).ok_or(FileSystemError::Io)
+    }
+
+    fn write_at(&self, offset: usize, buffer: &[u8]) -> Result<usize> {
+        if self.is_read_only() {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        CachedAccess::write(self, offset, buffer).ok_or(FileSystemError::Io)
+    }
+
+    fn ioctl(&self, command: usize, arg: usize) -> Result<usize> {
+        match command {
+            BLKGETSIZE64 => {
+                let size = (self.block_count() * self.block_size()) as u64;
+                copy_to_user(arg as *mut u64, &size)?;
+            }
+
+            // The size in 512-byte sectors, regardless of the block size of the device.
+            BLKGETSIZE => {
+                let sectors = (self.block_count() * self.block_size() / SECTOR_SIZE) as u64;
+                copy_to_user(arg as *mut u64, &sectors)?;
+            }
+
+            BLKSSZGET | BLKBSZGET => {
+                copy_to_user(arg as *mut i32, &(self.block_size() as i32))?;
+            }
+
+            BLKROGET => {
+                copy_to_user(arg as *mut i32, &(self.is_read_only() as i32))?;
+            }
+
+            BLKROSET => {
+                let read_only = UserRef::<i32>::new(VirtAddr::new(arg as u64))?;
+                self.read_only.store(*read_only != 0, Ordering::SeqCst);
+            }
+
+            HDIO_GETGEO => {
+                copy_to_user(arg as *mut HdGeometry, &self.geometry())?;
+            }
+
+            _ => return Err(FileSystemError::NoTty),
+        }
+
+        Ok(0)
+    }
+}
 
 impl Device for BlockDevice {
     fn device_marker(&self) -> usize {
@@ -398,6 +812,14 @@ impl PartitionBlockDevice {
             device,
         })
     }
+
+    /// Returns whether the `frames` pages starting at `sector` are inside of the partition.
+    fn contains_frames(&self, sector: usize, frames: usize) -> bool {
+        let sectors = frames * (Size4KiB::SIZE as usize / self.block_size());
+        sector
+            .checked_add(sectors)
+            .is_some_and(|end| end <= self.size)
+    }
 }
 
 impl BlockDeviceInterface for PartitionBlockDevice {
@@ -436,48 +858,85 @@ impl BlockDeviceInterface for PartitionBlockDevice {
     fn block_size(&self) -> usize {
         self.device.block_size()
     }
+
+    fn block_count(&self) -> usize {
+        self.size
+    }
+
+    fn max_transfer_size(&self) -> usize {
+        self.device.max_transfer_size()
+    }
+
+    fn read_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        if !self.contains_frames(sector, frames.len()) {
+            return None;
+        }
+
+        self.device.read_frames(self.offset + sector, frames)
+    }
+
+    fn write_frames(&self, sector: usize, frames: &[PhysFrame]) -> Option<usize> {
+        if !self.contains_frames(sector, frames.len()) {
+            return None;
+        }
+
+        self.device.write_frames(self.offset + sector, frames)
+    }
 }
 
-pub fn launch() -> Result<()> {
-    let mut blocks_copy = Vec::<Arc<BlockDevice>>::new();
+/// Scans the `block` device for a partition table and installs a block device for each of
+/// its partitions. Returns the installed block devices, or nothing if the device was already
+/// scanned.
+pub fn scan_partitions(block: &Arc<BlockDevice>) -> Result<Vec<Arc<BlockDevice>>> {
+    let mut partitions = Vec::new();
 
-    for (_, device) in BLOCK_DEVS.lock().iter() {
-        blocks_copy.push(device.clone());
+    if block.scanned.swap(true, Ordering::SeqCst) {
+        return Ok(partitions);
     }
 
-    for block in blocks_copy {
-        if let Some(gpt) = Gpt::new(&block) {
-            log::info!("block: found GPT on {}!", block.name());
+    let Some(gpt) = Gpt::new(block) else {
+        return Ok(partitions);
+    };
 
-            for (i, entry) in gpt
-                .entries()
-                .iter()
-                .enumerate()
-                .filter(|(_, e)| e.is_used())
-            {
-                let start = entry.start_lba() as usize;
-                let size = entry.size() as usize;
+    log::info!("block: found GPT on {}!", block.name());
 
-                log::info!(
-                    "gpt: found partition (name=`{}`, start={:#x}, size{:#x})!",
-                    entry.partition_name(),
-                    start,
-                    size
-                );
+    for (i, entry) in gpt
+        .entries()
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.is_used())
+    {
+        let start = entry.start_lba() as usize;
+        let size = entry.size() as usize;
 
-                let name = alloc::format!("{}p{}", block.name(), i);
-                let partition_device = PartitionBlockDevice::new(start, size, block.clone());
-                let device = BlockDevice::new(name, partition_device);
+        log::info!(
+            "gpt: found partition (name=`{}`, start={:#x}, size{:#x})!",
+            entry.partition_name(),
+            start,
+            size
+        );
 
-                install_block_device(device.clone())?;
+        let name = alloc::format!("{}p{}", block.name(), i);
+        let partition_device = PartitionBlockDevice::new(start, size, block.clone());
+        let device = BlockDevice::new_partition(name, partition_device, Some(block.id), start);
 
-                // Check what filesystem is on this partition and mount it.
-                if let Some(ext2) = Ext2::new(device.clone()) {
-                    log::info!("gpt: found ext2 filesystem on {}!", device.name());
+        install_block_device(device.clone())?;
+        partitions.push(device);
+    }
+
+    Ok(partitions)
+}
 
-                    super::ROOT_FS.call_once(|| ext2.clone());
-                    super::ROOT_DIR.call_once(|| ext2.root_dir());
-                }
+pub fn launch() -> Result<()> {
+    for block in block_devices().iter().filter(|dev| dev.parent.is_none()) {
+        for device in scan_partitions(block)? {
+            // Check what filesystem is on this partition and mount it.
+            if let Some(ext2) = Ext2::new(device.clone()) {
+                log::info!("gpt: found ext2 filesystem on {}!", device.name());
+
+                device.mounted.store(true, Ordering::SeqCst);
+                super::ROOT_FS.call_once(|| ext2.clone());
+                super::ROOT_DIR.call_once(|| ext2.root_dir());
             }
         }
     }
@@ -488,5 +947,8 @@ pub fn launch() -> Result<()> {
     super::procfs::init()?;
     log::info!("installed procfs");
 
+    super::tmpfs::init()?;
+    log::info!("installed tmpfs");
+
     Ok(())
 }
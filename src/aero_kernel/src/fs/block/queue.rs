@@ -0,0 +1,192 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Block I/O request queue.
+//!
+//! The page cache submits page-sized requests through a [`Plug`]. The requests are held back
+//! until the plug is unplugged, then sorted by sector (a single elevator sweep) and runs of
+//! adjacent requests going in the same direction are merged into one command, up to the maximum
+//! transfer size of the device. The drivers complete commands synchronously, so the result of
+//! a merged command is handed back to every request it was made of.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::mem::paging::*;
+
+use super::BlockDeviceInterface;
+
+/// Block layer counters, exposed through `/proc/blockstat`.
+pub struct BlockStats {
+    /// Page-sized requests submitted to the request queues.
+    pub requests: AtomicUsize,
+    /// Commands dispatched to the devices.
+    pub commands: AtomicUsize,
+    /// Requests merged into the command of an adjacent request.
+    pub merged: AtomicUsize,
+    /// Pages read ahead of a sequential reader.
+    pub readahead: AtomicUsize,
+    /// Pages read ahead that were used afterwards.
+    pub readahead_hits: AtomicUsize,
+}
+
+impl BlockStats {
+    const fn new() -> Self {
+        Self {
+            requests: AtomicUsize::new(0),
+            commands: AtomicUsize::new(0),
+            merged: AtomicUsize::new(0),
+            readahead: AtomicUsize::new(0),
+            readahead_hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// Renders the counters as `name value` lines.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        for (name, value) in [
+            ("requests", &self.requests),
+            ("commands", &self.commands),
+            ("merged", &self.merged),
+            ("readahead", &self.readahead),
+            ("readahead_hits", &self.readahead_hits),
+        ] {
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        out
+    }
+}
+
+pub static STATS: BlockStats = BlockStats::new();
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Direction {
+    Read,
+    Write,
+}
+
+struct Request {
+    sector: usize,
+    frame: PhysFrame,
+    direction: Direction,
+}
+
+/// A batch of page-sized block I/O requests to a device.
+pub struct Plug<'a> {
+    device: &'a dyn BlockDeviceInterface,
+    requests: Vec<Request>,
+}
+
+impl<'a> Plug<'a> {
+    pub fn new(device: &'a dyn BlockDeviceInterface) -> Self {
+        Self {
+            device,
+            requests: Vec::new(),
+        }
+    }
+
+    /// Queues a read of the page at `sector` into `frame`.
+    pub fn read(&mut self, sector: usize, frame: PhysFrame) {
+        self.submit(sector, frame, Direction::Read);
+    }
+
+    /// Queues a write of `frame` to the page at `sector`.
+    pub fn write(&mut self, sector: usize, frame: PhysFrame) {
+        self.submit(sector, frame, Direction::Write);
+    }
+
+    fn submit(&mut self, sector: usize, frame: PhysFrame, direction: Direction) {
+        STATS.requests.fetch_add(1, Ordering::Relaxed);
+
+        self.requests.push(Request {
+            sector,
+            frame,
+            direction,
+        });
+    }
+
+    /// Dispatches the queued requests and returns whether each of them succeeded, in the order
+    /// they were queued in.
+    pub fn unplug(self) -> Vec<bool> {
+        let page_size = Size4KiB::SIZE as usize;
+        let sectors_per_page = page_size / self.device.block_size();
+        let max_pages = core::cmp::max(self.device.max_transfer_size() / page_size, 1);
+
+        // The sort is stable, so requests to the same sector keep their order.
+        let mut order = (0..self.requests.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| self.requests[i].sector);
+
+        let mut results = alloc::vec![false; self.requests.len()];
+        let mut start = 0;
+
+        while start < order.len() {
+            let first = &self.requests[order[start]];
+            let mut end = start + 1;
+
+            while end < order.len() && end - start < max_pages {
+                let prev = &self.requests[order[end - 1]];
+                let next = &self.requests[order[end]];
+
+                if next.direction != first.direction
+                    || next.sector != prev.sector + sectors_per_page
+                {
+                    break;
+                }
+
+                end += 1;
+            }
+
+            let run = &order[start..end];
+            let frames = run
+                .iter()
+                .map(|&i| self.requests[i].frame)
+                .collect::<Vec<_>>();
+
+            STATS.merged.fetch_add(run.len() - 1, Ordering::Relaxed);
+
+            if self.dispatch(first.direction, first.sector, &frames) {
+                run.iter().for_each(|&i| results[i] = true);
+            } else if run.len() > 1 {
+                // Retry the requests one by one, so that a failing request (e.g. one past the
+                // end of the device) does not fail the requests it was merged with.
+                for &i in run {
+                    let request = &self.requests[i];
+                    results[i] = self.dispatch(request.direction, request.sector, &[request.frame]);
+                }
+            }
+
+            start = end;
+        }
+
+        results
+    }
+
+    fn dispatch(&self, direction: Direction, sector: usize, frames: &[PhysFrame]) -> bool {
+        STATS.commands.fetch_add(1, Ordering::Relaxed);
+
+        match direction {
+            Direction::Read => self.device.read_frames(sector, frames),
+            Direction::Write => self.device.write_frames(sector, frames),
+        }
+        .is_some()
+    }
+}
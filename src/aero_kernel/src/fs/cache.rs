@@ -276,6 +276,39 @@ impl<K: CacheKey, V: Cacheable<K>> Cache<K, V> {
         }
     }
 
+    /// Returns whether the item with the provided `key` is in the cache, without marking it as
+    /// used.
+    pub fn contains(&self, key: &K) -> bool {
+        let index = self.index.lock();
+
+        index
+            .used
+            .get(key)
+            .is_some_and(|item| item.strong_count() > 0)
+            || index.unused.contains(key)
+    }
+
+    /// Returns a snapshot of the items in the cache. Items added or removed afterwards are not
+    /// reflected in it.
+    pub fn items(&self) -> Vec<Arc<CacheItem<K, V>>> {
+        let index = self.index.lock();
+
+        index
+            .used
+            .values()
+            .filter_map(Weak::upgrade)
+            .chain(index.unused.iter().map(|(_, item)| item.clone()))
+            .collect()
+    }
+
+    /// Calls `f` on every item in the cache. The index is not locked while `f` runs, so items
+    /// added or removed in the meantime might be missed.
+    pub fn for_each<F: FnMut(&V)>(&self, mut f: F) {
+        for item in self.items() {
+            f(&item);
+        }
+    }
+
     /// Removes the item with the provided `key` from the cache.
     pub fn remove(&self, key: &K) {
         let mut index = self.index.lock();
@@ -25,18 +25,21 @@ use alloc::sync::Arc;
 
 use spin::{Once, RwLock};
 
+use crate::arch::user_copy::{copy_to_user, UserRef};
 use crate::fs::{lookup_path, Path};
 use crate::logger;
 use crate::mem::paging::*;
 use crate::rendy::RendyInfo;
 
 use super::cache::{DirCacheItem, INodeCacheItem};
-use super::inode::{INodeInterface, MMapPage, PollFlags, PollTable};
+use super::inode::{
+    fetch_dir_entry, INodeInterface, MMapPage, MappableRegion, PollFlags, PollTable,
+};
 use super::ramfs::RamFs;
 use super::{FileSystem, FileSystemError, Result, MOUNT_MANAGER};
 
 use aero_syscall::prelude::*;
-use aero_syscall::MMapFlags;
+use aero_syscall::OpenFlags;
 
 lazy_static::lazy_static! {
     pub static ref DEV_FILESYSTEM: Arc<DevFs> = DevFs::new();
@@ -89,6 +92,23 @@ pub fn install_device_at(at: INodeCacheItem, device: Arc<dyn Device>) -> Result<
     Ok(())
 }
 
+/// Removes the provided `device` from the device filesystem and the global [DEVICES] btree
+/// map. Files that are already open keep a reference to the device.
+pub fn uninstall_device(device: Arc<dyn Device>) -> Result<()> {
+    let root = DEV_FILESYSTEM.root_dir();
+    let device_name = device.device_name();
+
+    if let Some(entry) = fetch_dir_entry(&root, device_name.clone()) {
+        entry.drop_from_cache();
+    }
+
+    root.inode().unlink(&device_name)?;
+    DEVICES.write().remove(&device.device_marker());
+
+    log::debug!("uninstalled device `{}`", device_name);
+    Ok(())
+}
+
 /// Structure representing a device inode. This is internally used by ram-fs
 /// to create a new inode with the file type of `device` and its contents as a
 /// reference-counting pointer to the device itself.
@@ -117,8 +137,8 @@ impl INodeInterface for DevINode {
         self.0.inode().read_at(offset, buffer)
     }
 
-    fn mmap(&self, offset: usize, size: usize, flags: MMapFlags) -> Result<PhysFrame> {
-        self.0.inode().mmap(offset, size, flags)
+    fn mmap_region(&self, offset: usize, len: usize) -> Result<MappableRegion> {
+        self.0.inode().mmap_region(offset, len)
     }
 
     fn ioctl(&self, command: usize, arg: usize) -> Result<usize> {
@@ -133,6 +153,10 @@ impl INodeInterface for DevINode {
         self.0.inode().open(handle)
     }
 
+    fn close(&self, flags: OpenFlags) {
+        self.0.inode().close(flags)
+    }
+
     fn mmap_v2(&self, offset: usize) -> Result<MMapPage> {
         self.0.inode().mmap_v2(offset)
     }
@@ -323,91 +347,57 @@ impl INodeInterface for DevFb {
             .expect("/dev/fb: terminal not initialized")
     }
 
-    fn mmap(&self, offset: usize, size: usize, flags: MMapFlags) -> Result<PhysFrame> {
+    fn mmap_region(&self, offset: usize, len: usize) -> Result<MappableRegion> {
         let rinfo = crate::rendy::get_rendy_info();
 
         // Make sure we are in bounds.
-        if offset > rinfo.byte_len || offset + Size4KiB::SIZE as usize > rinfo.byte_len {
-            return Ok(PhysFrame::containing_address(PhysAddr::zero()));
+        if offset >= rinfo.byte_len {
+            return Err(FileSystemError::InvalidInput);
         }
 
-        crate::rendy::DEBUG_RENDY
-            .get()
-            .map(|e| unsafe {
-                let mut lock = e.lock_irq();
-
-                if flags.contains(MMapFlags::MAP_SHARED) {
-                    // This is a shared file mapping.
-                    let fb = lock.get_framebuffer();
-
-                    let fb_ptr = fb.as_ptr().cast::<u8>();
-                    let fb_ptr = fb_ptr.add(offset);
-
-                    let fb_phys_ptr = fb_ptr.sub(crate::PHYSICAL_MEMORY_OFFSET.as_u64() as usize);
-
-                    Ok(PhysFrame::containing_address(PhysAddr::new_unchecked(
-                        fb_phys_ptr as u64,
-                    )))
-                } else {
-                    let fb = lock.get_framebuffer();
-
-                    // This is a private file mapping.
-                    let private_cp: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
-                    private_cp.as_slice_mut()[..size].copy_from_slice(&fb[offset..offset + size]);
-
-                    Ok(private_cp)
-                }
-            })
-            .expect("/dev/fb: terminal not initialized")
-    }
-
-    fn mmap_v2(&self, offset: usize) -> Result<MMapPage> {
-        let rinfo = crate::rendy::get_rendy_info();
-
-        // Make sure we are in bounds.
-        if offset > rinfo.byte_len || offset + Size4KiB::SIZE as usize > rinfo.byte_len {
-            return Err(FileSystemError::NotSupported);
-        }
+        let len = len.min(rinfo.byte_len - offset);
 
         let mut lock = crate::rendy::DEBUG_RENDY.get().unwrap().lock_irq();
         let fb = lock.get_framebuffer();
 
-        let fb_ptr = fb.as_ptr().cast::<u8>();
-        let fb_ptr = unsafe { fb_ptr.add(offset) };
-        let fb_phys_ptr = unsafe { fb_ptr.sub(crate::PHYSICAL_MEMORY_OFFSET.as_u64() as usize) };
+        let fb_phys = fb.as_ptr() as u64 - crate::PHYSICAL_MEMORY_OFFSET.as_u64();
+        let start = PhysAddr::new(fb_phys + offset as u64);
 
-        Ok(MMapPage::Direct(PhysFrame::containing_address(unsafe {
-            PhysAddr::new_unchecked(fb_phys_ptr as u64)
-        })))
+        Ok(MappableRegion::contiguous(
+            PhysFrame::range(
+                PhysFrame::containing_address(start),
+                PhysFrame::containing_address((start + len as u64).align_up(Size4KiB::SIZE)),
+            ),
+            // Writes to the framebuffer are never read back, so let them be combined.
+            CacheMode::WriteCombining,
+        ))
     }
 
     fn ioctl(&self, command: usize, arg: usize) -> Result<usize> {
         match command {
             FBIOGET_VSCREENINFO => {
-                let struc = unsafe { &mut *(arg as *mut FramebufferVScreenInfo) };
+                let vinfo = self.vinfo.read().clone();
 
-                *struc = self.vinfo.read().clone();
+                copy_to_user(arg as *mut FramebufferVScreenInfo, &vinfo)?;
                 Ok(0x00)
             }
 
             FBIOPUT_VSCREENINFO => {
-                let struc = unsafe { &mut *(arg as *mut FramebufferVScreenInfo) };
+                let struc = UserRef::<FramebufferVScreenInfo>::new(VirtAddr::new(arg as _))?;
                 *self.vinfo.write() = struc.clone();
 
                 Ok(0x00)
             }
 
             FBIOGET_FSCREENINFO => {
-                let struc = unsafe { &mut *(arg as *mut FramebufferFScreenInfo) };
-
-                *struc = self.finfo.clone();
+                copy_to_user(arg as *mut FramebufferFScreenInfo, &self.finfo)?;
                 Ok(0x00)
             }
 
             // Device independent colormap information can be get and set using
             // the `FBIOGETCMAP` and `FBIOPUTCMAP` ioctls.
             FBIOPUTCMAP => {
-                let struc = VirtAddr::new(arg as _).read_mut::<FramebufferCmap>()?;
+                let struc = UserRef::<FramebufferCmap>::new(VirtAddr::new(arg as _))?;
                 log::debug!("fbdev: `FBIOPUTCMAP` is a stub! {struc:?}");
                 Ok(0)
             }
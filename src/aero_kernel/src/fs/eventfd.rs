@@ -61,7 +61,10 @@ impl INodeInterface for EventFd {
         // SAFETY: We have above verified that it is safe to dereference
         //         the value.
         let value = unsafe { &mut *(buffer.as_mut_ptr().cast::<u64>()) };
-        let mut count = self.wq.block_on(&self.count, |e| **e != 0)?;
+        let mut count = self
+            .wq
+            .block_on_interruptible(&self.count, |e| **e != 0)
+            .map_err(|_| FileSystemError::Interrupted)?;
 
         *value = *count;
         *count = 0; // reset the counter
@@ -19,13 +19,15 @@ use core::mem::MaybeUninit;
 
 use alloc::boxed::Box;
 use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
 use bit_field::BitField;
-use spin::RwLock;
 
 use crate::fs::block::{BlockDevice, CachedAccess};
+use crate::utils::sync::RwLock;
 
-use super::{disk, Ext2};
+use super::disk::{self, OnDisk};
+use super::Ext2;
 
 pub struct GroupDescriptors {
     descriptors: RwLock<Box<[disk::GroupDescriptor]>>,
@@ -84,6 +86,24 @@ impl GroupDescriptors {
         Some(index)
     }
 
+    /// Returns the number of free blocks in all of the block groups.
+    pub fn free_blocks(&self) -> usize {
+        self.descriptors
+            .read()
+            .iter()
+            .map(|e| e.free_blocks_count as usize)
+            .sum()
+    }
+
+    /// Returns the number of free inodes in all of the block groups.
+    pub fn free_inodes(&self) -> usize {
+        self.descriptors
+            .read()
+            .iter()
+            .map(|e| e.free_inodes_count as usize)
+            .sum()
+    }
+
     /// Returns the index of the block group which has free inode(s)
     /// available.
     pub fn find_free_inode(&self) -> Option<usize> {
@@ -97,32 +117,100 @@ impl GroupDescriptors {
         Some(index)
     }
 
-    pub fn find_inode(&self, id: usize) -> Option<Box<disk::INode>> {
-        let fs = self.ext2.upgrade()?;
-        let this = self.descriptors.read();
+    /// Returns the offset of the inode `id` on the disk.
+    fn inode_offset(&self, fs: &Ext2, id: usize) -> usize {
         let superblock = &fs.superblock;
 
         // There is one inode table per block group and can be located by
         // the `inode_table` offset in the group descriptor. Also there are
         // `inodes_per_group` inodes per table.
         let ino_per_group = superblock.inodes_per_group as usize;
+        let inode_size = superblock.inode_size as usize;
 
         let ino_block_group = (id - 1) / ino_per_group;
         let ino_table_index = (id - 1) % ino_per_group;
 
-        let group_descriptor = this[ino_block_group];
+        let group_descriptor = self.descriptors.read()[ino_block_group];
         let table_offset = group_descriptor.inode_table as usize * superblock.block_size();
 
-        let mut inode = Box::<disk::INode>::new_uninit();
+        table_offset + (ino_table_index * inode_size)
+    }
 
-        fs.block.read(
-            table_offset + (ino_table_index * core::mem::size_of::<disk::INode>()),
-            inode.as_bytes_mut(),
-        )?;
+    /// Returns the number of bytes of the extra fields that are stored after each inode.
+    fn extra_size(fs: &Ext2) -> usize {
+        (fs.superblock.inode_size as usize - core::mem::size_of::<disk::INode>())
+            .min(core::mem::size_of::<disk::INodeExtra>())
+    }
+
+    pub fn find_inode(&self, id: usize) -> Option<(Box<disk::INode>, disk::INodeExtra)> {
+        let fs = self.ext2.upgrade()?;
+        let offset = self.inode_offset(&fs, id);
+
+        let mut inode = Box::<disk::INode>::new_uninit();
+        fs.block.read(offset, inode.as_bytes_mut())?;
 
         // SAFETY: We have initialized the inode above.
         let inode = unsafe { inode.assume_init() };
-        Some(inode)
+
+        // The extra fields are stored right after the inode, if it is large enough.
+        let mut extra = MaybeUninit::<disk::INodeExtra>::zeroed();
+        let extra_size = Self::extra_size(&fs);
+
+        if extra_size != 0 {
+            fs.block.read(
+                offset + core::mem::size_of::<disk::INode>(),
+                &mut extra.as_bytes_mut()[..extra_size],
+            )?;
+        }
+
+        // SAFETY: All zeroes is a valid `INodeExtra` and we have read the rest above.
+        let mut extra = unsafe { extra.assume_init() };
+
+        // Do not look past the end of the inode.
+        if extra.extra_isize as usize > extra_size {
+            extra.extra_isize = extra_size as u16;
+        }
+
+        Some((inode, extra))
+    }
+
+    /// Writes the inode `id` back to its inode table.
+    pub fn write_inode(
+        &self,
+        id: usize,
+        inode: &disk::INode,
+        extra: &disk::INodeExtra,
+    ) -> Option<()> {
+        let fs = self.ext2.upgrade()?;
+        let offset = self.inode_offset(&fs, id);
+
+        fs.block.write(offset, inode.as_bytes())?;
+
+        let extra_size = Self::extra_size(&fs);
+
+        if extra_size != 0 {
+            fs.block.write(
+                offset + core::mem::size_of::<disk::INode>(),
+                &extra.as_bytes()[..extra_size],
+            )?;
+        }
+
+        Some(())
+    }
+
+    /// Writes the block group descriptors back to the disk.
+    pub fn sync(&self) -> Option<()> {
+        let fs = self.ext2.upgrade()?;
+        let descriptors = self.descriptors.read();
+
+        let bytes = descriptors
+            .iter()
+            .flat_map(|descriptor| descriptor.as_bytes())
+            .copied()
+            .collect::<Vec<_>>();
+
+        fs.block.write(fs.superblock.bgdt_block(), &bytes)?;
+        Some(())
     }
 
     /// Allocates a block pointer using the first fit allocation strategy.
@@ -20,12 +20,13 @@ mod group_desc;
 
 use core::mem::MaybeUninit;
 
+use aero_syscall::consts::FallocateFlags;
 use aero_syscall::socket::{MessageFlags, MessageHeader};
-use aero_syscall::{MMapFlags, SyscallError};
+use aero_syscall::{Mode, StatFs, SyscallError, EXT2_SUPER_MAGIC};
 use alloc::boxed::Box;
 use alloc::string::ToString;
 use alloc::sync::{Arc, Weak};
-use spin::RwLock;
+use alloc::vec;
 
 use crate::fs::block::{BlockDeviceInterface, DirtyRef};
 use crate::fs::cache::CachedINode;
@@ -34,6 +35,7 @@ use crate::mem::paging::*;
 
 use crate::socket::unix::UnixSocket;
 use crate::socket::SocketAddrRef;
+use crate::utils::sync::{BMutex, RwLock};
 
 use self::group_desc::GroupDescriptors;
 
@@ -50,10 +52,14 @@ pub struct INode {
     id: usize,
     fs: Weak<Ext2>,
     inode: RwLock<Box<disk::INode>>,
+    extra: RwLock<disk::INodeExtra>,
     // Forwards all of the inode operations to the proxy inode. Note that the
     // proxy inode is not saved on the disk. (e.g. This is useful for binding
     // a socket inode to a file).
     proxy: Option<Arc<dyn INodeInterface>>,
+    /// Serializes the writes and truncations, so that appends find the end of the file and
+    /// write there atomically.
+    io_lock: BMutex<()>,
 
     // TODO: Do not store this in the inode, but rather in a different
     // cache using the API provided by fs::cache (consider LRU only?).
@@ -75,14 +81,16 @@ impl INode {
             Some(inode)
         } else {
             let fs = ext2.upgrade()?;
-            let inode = fs.bgdt.find_inode(id)?;
+            let (inode, extra) = fs.bgdt.find_inode(id)?;
 
             Some(
                 icache.make_item_cached(CachedINode::new(Arc::new_cyclic(|sref| Self {
                     inode: RwLock::new(inode),
+                    extra: RwLock::new(extra),
                     id,
                     fs: ext2,
                     proxy,
+                    io_lock: BMutex::new(()),
 
                     sref: sref.clone(),
                 }))),
@@ -127,14 +135,17 @@ impl INode {
             }
 
             let block_index = self.get_block(block).unwrap() as usize;
+            let dest = &mut buffer[progress..progress + chunk];
 
-            filesystem
-                .block
-                .read(
-                    (block_index * block_size) + loc,
-                    &mut buffer[progress..progress + chunk],
-                )
-                .expect("inode: read failed");
+            // Blocks that were never written to are not allocated and read as zeroes.
+            if block_index == 0 {
+                dest.fill(MaybeUninit::new(0));
+            } else {
+                filesystem
+                    .block
+                    .read((block_index * block_size) + loc, dest)
+                    .ok_or(FileSystemError::Io)?;
+            }
 
             progress += chunk;
         }
@@ -163,7 +174,7 @@ impl INode {
             let mut block_index = self.get_block(block).unwrap() as usize;
 
             if block_index == 0 {
-                block_index = self.append_block().unwrap();
+                block_index = self.alloc_block(block)?;
             }
 
             filesystem
@@ -172,15 +183,70 @@ impl INode {
                     (block_index * block_size) + loc,
                     &buffer[progress..progress + chunk],
                 )
-                .expect("inode: write failed");
+                .ok_or(FileSystemError::Io)?;
 
             progress += chunk;
         }
-        self.inode.write().set_size(offset + count);
+        self.inode.write().set_size(size.max(offset + count));
 
         Ok(count)
     }
 
+    /// Allocates the data block at `index` in the file, which must not have one yet, and fills
+    /// it with zeroes. Returns the number of the allocated block.
+    fn alloc_block(&self, index: usize) -> super::Result<usize> {
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let block_size = fs.superblock.block_size();
+
+        if index >= Self::max_blocks(&fs) {
+            return Err(FileSystemError::FileTooLarge);
+        }
+
+        let alloc_zeroed = || -> super::Result<usize> {
+            let block = fs.bgdt.alloc_block_ptr().ok_or(FileSystemError::NoSpace)?;
+
+            fs.block
+                .write(block * block_size, &vec![0; block_size])
+                .ok_or(FileSystemError::Io)?;
+
+            Ok(block)
+        };
+
+        let new_block = alloc_zeroed()?;
+
+        if index < disk::INode::SINGLY_INDIRECT {
+            let mut inode = self.inode.write();
+
+            assert_eq!(inode.data_ptr[index], 0);
+            inode.data_ptr[index] = new_block as u32;
+
+            return Ok(new_block);
+        }
+
+        let mut block_ptrs = self.inode.read().data_ptr[disk::INode::SINGLY_INDIRECT] as usize;
+
+        if block_ptrs == 0 {
+            block_ptrs = alloc_zeroed()?;
+            self.inode.write().data_ptr[disk::INode::SINGLY_INDIRECT] = block_ptrs as u32;
+        }
+
+        let index = index - disk::INode::SINGLY_INDIRECT;
+        let offset = block_ptrs * block_size + index * core::mem::size_of::<u32>();
+
+        fs.block
+            .write(offset, &(new_block as u32).to_le_bytes())
+            .ok_or(FileSystemError::Io)?;
+
+        Ok(new_block)
+    }
+
+    /// Returns the number of data blocks a file can have.
+    ///
+    /// TODO: Allocating doubly and triply indirect blocks is not supported yet.
+    fn max_blocks(fs: &Ext2) -> usize {
+        disk::INode::SINGLY_INDIRECT + fs.superblock.entries_per_block()
+    }
+
     pub fn append_block(&self) -> Option<usize> {
         let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
         let block_size = fs.superblock.block_size();
@@ -280,6 +346,11 @@ impl INode {
         } else {
             // singly indirect block
             let block_ptrs = self.inode.read().data_ptr[12] as usize * block_size;
+
+            if block_ptrs == 0 {
+                return Some(0);
+            }
+
             let offset = block_ptrs + (block * core::mem::size_of::<u32>());
 
             let mut res = MaybeUninit::<u32>::uninit();
@@ -305,10 +376,38 @@ impl INode {
         entry.set_name(name);
     }
 
+    /// Removes the directory entry `name` and returns the ID of the inode it referred to.
+    pub fn remove_dirent(&self, name: &str) -> super::Result<usize> {
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let block_size = fs.superblock.block_size();
+
+        let mut entries = DirEntryIter::new(self.sref());
+        let (offset, id) = loop {
+            let entry = entries.next().ok_or(FileSystemError::EntryNotFound)?;
+
+            if entry.name() == name {
+                let offset = entries.offset() - entry.entry_size as usize;
+                break (offset, entry.inode as usize);
+            }
+        };
+
+        // The entry is kept with an inode of zero, so the entries after it do not move.
+        let block = self.get_block(offset / block_size).unwrap() as usize;
+        fs.block
+            .write(
+                block * block_size + offset % block_size,
+                &0u32.to_le_bytes(),
+            )
+            .expect("ext2: failed to remove the directory entry");
+
+        Ok(id)
+    }
+
     pub fn make_inode(
         &self,
         name: &str,
         typ: FileType,
+        permissions: Mode,
         proxy: Option<Arc<dyn INodeInterface>>,
     ) -> super::Result<INodeCacheItem> {
         if !self.metadata()?.is_directory() {
@@ -333,11 +432,13 @@ impl INode {
             **inode = disk::INode::default();
 
             inode.set_file_type(typ);
-            inode.set_permissions(0o755);
+            inode.set_permissions((permissions - Mode::S_IFMT).bits() as u16);
 
             inode.hl_count += 1;
         }
 
+        *ext2_inode.extra.write() = disk::INodeExtra::default();
+
         // FIXME: Fix the filetype!
         self.make_disk_dirent(&ext2_inode, 2, name);
         Ok(inode)
@@ -390,7 +491,7 @@ impl INodeInterface for INode {
 
     fn stat(&self) -> super::Result<aero_syscall::Stat> {
         use super::inode::FileType;
-        use aero_syscall::{Mode, Stat};
+        use aero_syscall::Stat;
 
         let inode = self.inode.read();
 
@@ -407,8 +508,7 @@ impl INodeInterface for INode {
             FileType::Symlink => mode.insert(Mode::S_IFLNK),
         }
 
-        // FIXME: read permission bits from the inode.
-        mode.insert(Mode::S_IRWXU | Mode::S_IRWXG | Mode::S_IRWXO);
+        mode.insert(Mode::from_bits_truncate(inode.permissions() as u32));
 
         Ok(Stat {
             st_ino: self.id as _,
@@ -424,6 +524,17 @@ impl INodeInterface for INode {
         })
     }
 
+    fn statx(&self) -> super::Result<aero_syscall::Statx> {
+        let mut statx = aero_syscall::Statx::from(self.stat()?);
+
+        if let Some(birth_time) = self.extra.read().birth_time() {
+            statx.stx_btime = birth_time.into();
+            statx.stx_mask.insert(aero_syscall::StatxMask::BTIME);
+        }
+
+        Ok(statx)
+    }
+
     fn dirent(&self, parent: DirCacheItem, index: usize) -> super::Result<Option<DirCacheItem>> {
         if let Some(entry) = DirEntryIter::new(self.sref()).nth(index) {
             Ok(self.make_dirent(parent, entry.name(), entry))
@@ -432,6 +543,25 @@ impl INodeInterface for INode {
         }
     }
 
+    fn dirent_at(
+        &self,
+        parent: DirCacheItem,
+        cursor: usize,
+    ) -> super::Result<Option<(DirCacheItem, usize)>> {
+        // The cursor is the byte offset of the entry in the directory, which does not change
+        // as entries are added or removed.
+        let mut entries = DirEntryIter::new(self.sref());
+        entries.seek(cursor);
+
+        let Some(entry) = entries.next() else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .make_dirent(parent, entry.name(), entry)
+            .map(|dirent| (dirent, entries.offset())))
+    }
+
     fn lookup(&self, parent: DirCacheItem, name: &str) -> super::Result<DirCacheItem> {
         let entry = DirEntryIter::new(self.sref())
             .find(|entry| entry.name() == name)
@@ -465,9 +595,25 @@ impl INodeInterface for INode {
             return Err(FileSystemError::NotSupported);
         }
 
+        let _guard = self.io_lock.lock();
         self.write(offset, usr_buffer)
     }
 
+    fn append(&self, usr_buffer: &[u8]) -> super::Result<(usize, usize)> {
+        if let Some(proxy) = self.proxy.as_ref() {
+            return proxy.append(usr_buffer);
+        }
+
+        if !self.metadata()?.is_file() {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        let _guard = self.io_lock.lock();
+        let offset = self.inode.read().size();
+
+        Ok((offset, self.write(offset, usr_buffer)?))
+    }
+
     fn rename(&self, old: DirCacheItem, dest: &str) -> super::Result<()> {
         assert!(self.metadata()?.is_directory());
 
@@ -475,9 +621,14 @@ impl INodeInterface for INode {
             return Err(FileSystemError::EntryExists);
         }
 
-        if let Some(_parent) = old.parent() {
-            // FIXME: Remove the directory entry from the parent
+        if let Some(parent) = old.parent() {
+            let parent = parent
+                .inode()
+                .downcast_arc::<INode>()
+                .ok_or(FileSystemError::CrossDevice)?;
+
             self.make_disk_dirent(&old.inode().downcast_arc().unwrap(), 2, dest);
+            parent.remove_dirent(&old.name())?;
             return Ok(());
         }
 
@@ -493,13 +644,49 @@ impl INodeInterface for INode {
             return Err(FileSystemError::NotSupported);
         }
 
-        let inode = self.make_inode(name, FileType::Symlink, None)?;
+        let inode = self.make_inode(
+            name,
+            FileType::Symlink,
+            Mode::from_bits_truncate(0o777),
+            None,
+        )?;
         inode.write_at(0, src.name().as_bytes())?;
 
         Ok(())
     }
 
+    fn unlink(&self, name: &str) -> super::Result<()> {
+        if !self.metadata()?.is_directory() {
+            return Err(FileSystemError::NotDirectory);
+        }
+
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let id = DirEntryIter::new(self.sref())
+            .find(|entry| entry.name() == name)
+            .map(|entry| entry.inode as usize)
+            .ok_or(FileSystemError::EntryNotFound)?;
+
+        let inode = fs
+            .find_inode(id, None)
+            .ok_or(FileSystemError::EntryNotFound)?;
+
+        if inode.metadata()?.is_directory() {
+            return Err(FileSystemError::IsDir);
+        }
+
+        self.remove_dirent(name)?;
+
+        let inode = inode.downcast_arc::<INode>().expect("ext2: invalid inode");
+        let mut inode = inode.inode.write();
+
+        // TODO: Free the blocks and the inode once the last link is gone and the file is no
+        // longer open.
+        inode.hl_count = inode.hl_count.saturating_sub(1);
+        Ok(())
+    }
+
     fn truncate(&self, size: usize) -> super::Result<()> {
+        let _guard = self.io_lock.lock();
         let inode = self.inode.read();
 
         if inode.size() > size {
@@ -513,29 +700,106 @@ impl INodeInterface for INode {
         Ok(())
     }
 
-    fn touch(&self, parent: DirCacheItem, name: &str) -> super::Result<DirCacheItem> {
+    fn fallocate(&self, flags: FallocateFlags, offset: usize, len: usize) -> super::Result<()> {
+        if !self.metadata()?.is_file() {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        let _guard = self.io_lock.lock();
+
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let block_size = fs.superblock.block_size();
+        let size = self.inode.read().size();
+        let end = offset + len;
+
+        if flags.contains(FallocateFlags::PUNCH_HOLE) {
+            // The blocks are zeroed rather than freed, as freeing blocks is not supported yet.
+            // Only the part of the range before the end of the file can hold data.
+            let end = end.min(size);
+            let zeroes = vec![0; block_size];
+            let mut position = offset;
+
+            while position < end {
+                let loc = position % block_size;
+                let chunk = (block_size - loc).min(end - position);
+                let block = self.get_block(position / block_size).unwrap() as usize;
+
+                if block != 0 {
+                    fs.block
+                        .write(block * block_size + loc, &zeroes[..chunk])
+                        .ok_or(FileSystemError::Io)?;
+                }
+
+                position += chunk;
+            }
+
+            return Ok(());
+        }
+
+        let first = offset / block_size;
+        let last = end.div_ceil(block_size);
+        let max_blocks = Self::max_blocks(&fs);
+
+        // Check that there is enough space up front, so that nothing is allocated if the request
+        // cannot be satisfied.
+        let allocated = (first..last.min(max_blocks))
+            .filter(|&index| self.get_block(index) != Some(0))
+            .count();
+
+        let mut missing = (last - first) - allocated;
+
+        if last > disk::INode::SINGLY_INDIRECT
+            && self.inode.read().data_ptr[disk::INode::SINGLY_INDIRECT] == 0
+        {
+            // The block holding the pointers of the singly indirect blocks.
+            missing += 1;
+        }
+
+        if missing > fs.bgdt.free_blocks() {
+            return Err(FileSystemError::NoSpace);
+        }
+
+        if last > max_blocks {
+            return Err(FileSystemError::FileTooLarge);
+        }
+
+        for index in first..last {
+            if self.get_block(index) == Some(0) {
+                self.alloc_block(index)?;
+            }
+        }
+
+        if !flags.contains(FallocateFlags::KEEP_SIZE) && end > size {
+            self.inode.write().set_size(end);
+        }
+
+        Ok(())
+    }
+
+    fn touch(&self, parent: DirCacheItem, name: &str, mode: Mode) -> super::Result<DirCacheItem> {
         if !self.metadata()?.is_directory() {
             return Err(FileSystemError::NotDirectory);
         }
 
-        let inode = self.make_inode(name, FileType::File, None)?;
+        let inode = self.make_inode(name, FileType::File, mode, None)?;
         Ok(inode::DirEntry::new(parent, inode, name.to_string()))
     }
 
-    fn mkdir(&self, name: &str) -> super::Result<INodeCacheItem> {
+    fn mkdir(&self, name: &str, mode: Mode) -> super::Result<INodeCacheItem> {
         if !self.metadata()?.is_directory() {
             return Err(FileSystemError::NotDirectory);
         }
 
-        self.make_inode(name, FileType::Directory, None)
+        self.make_inode(name, FileType::Directory, mode, None)
     }
 
     fn make_local_socket_inode(
         &self,
         name: &str,
         inode: Arc<dyn INodeInterface>,
+        mode: Mode,
     ) -> super::Result<INodeCacheItem> {
-        self.make_inode(name, FileType::Socket, Some(inode))
+        self.make_inode(name, FileType::Socket, mode, Some(inode))
     }
 
     fn resolve_link(&self) -> super::Result<PathBuf> {
@@ -586,27 +850,13 @@ impl INodeInterface for INode {
         Ok(())
     }
 
-    fn mmap(&self, offset: usize, size: usize, flags: MMapFlags) -> super::Result<PhysFrame> {
-        assert!(self.proxy.is_none());
-
-        // TODO: support shared file mappings.
-        // assert!(!flags.contains(MMapFlags::MAP_SHARED));
-
-        let private_cp: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
-        private_cp.as_slice_mut().fill(0);
-
-        let buffer = &mut private_cp.as_slice_mut()[..size];
-        self.read_at(offset, buffer)?;
-
-        Ok(private_cp)
-    }
-
     // TODO: cleanup
     fn mmap_v2(&self, offset: usize) -> super::Result<MMapPage> {
-        Ok(MMapPage::PageCache(PAGE_CACHE.get_page(
-            &(self.sref.clone() as Weak<dyn CachedAccess>),
-            offset,
-        )))
+        let page = PAGE_CACHE
+            .get_page(&(self.sref.clone() as Weak<dyn CachedAccess>), offset)
+            .ok_or(FileSystemError::Io)?;
+
+        Ok(MMapPage::PageCache(page))
     }
 
     fn listen(&self, backlog: usize) -> Result<(), SyscallError> {
@@ -673,6 +923,8 @@ pub struct DirEntryIter<'a> {
     offset: usize,
 
     current_block: Box<[MaybeUninit<u8>]>,
+    /// Offset of the block in `current_block`, if one has been read.
+    current_block_offset: Option<usize>,
     block_size: usize,
     _phantom: core::marker::PhantomData<&'a disk::DirEntry>,
 }
@@ -686,17 +938,33 @@ impl<'a> DirEntryIter<'a> {
             inode,
             offset: 0,
             current_block: buf,
+            current_block_offset: None,
             block_size,
 
             _phantom: core::marker::PhantomData,
         }
     }
-}
 
-impl<'a> Iterator for DirEntryIter<'a> {
-    type Item = &'a mut disk::DirEntry;
+    /// Returns the offset of the next entry in the directory.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Moves the iterator to the first entry at or after `offset`. The entries of the block
+    /// are walked from its start, as `offset` might not point to the start of an entry.
+    pub fn seek(&mut self, offset: usize) {
+        self.offset = offset - (offset % self.block_size);
+
+        while self.offset < offset {
+            match self.entry() {
+                Some(entry) if entry.entry_size != 0 => self.offset += entry.entry_size as usize,
+                _ => return,
+            }
+        }
+    }
+
+    /// Returns the entry at the current offset, used or not.
+    fn entry(&mut self) -> Option<&'a mut disk::DirEntry> {
         // Read 1 block at a time.
         //
         // XXX: A directory entry cannot span between multiple data blocks.
@@ -707,10 +975,14 @@ impl<'a> Iterator for DirEntryIter<'a> {
         }
 
         let block_offset = self.offset % self.block_size;
-        if block_offset == 0 {
+        let block_start = self.offset - block_offset;
+
+        if self.current_block_offset != Some(block_start) {
             self.inode
-                .read(self.offset, &mut self.current_block)
+                .read(block_start, &mut self.current_block)
                 .unwrap();
+
+            self.current_block_offset = Some(block_start);
         }
 
         // SAFETY: We have initialized the current block above.
@@ -722,15 +994,32 @@ impl<'a> Iterator for DirEntryIter<'a> {
                 .cast::<disk::DirEntry>()
         };
 
-        if !entry.is_used() {
-            return None;
-        }
-
-        self.offset += entry.entry_size as usize;
         Some(entry)
     }
 }
 
+impl<'a> Iterator for DirEntryIter<'a> {
+    type Item = &'a mut disk::DirEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = self.entry()?;
+
+            // A zero sized entry is corrupted and would never advance the iterator.
+            if entry.entry_size == 0 {
+                return None;
+            }
+
+            self.offset += entry.entry_size as usize;
+
+            // Deleted entries are kept with an inode of zero to cover the space they occupied.
+            if entry.is_used() {
+                return Some(entry);
+            }
+        }
+    }
+}
+
 pub struct Ext2 {
     superblock: Box<SuperBlock>,
     bgdt: GroupDescriptors,
@@ -740,8 +1029,6 @@ pub struct Ext2 {
 }
 
 impl Ext2 {
-    const ROOT_INODE_ID: usize = 2;
-
     pub fn new(block: Arc<BlockDevice>) -> Option<Arc<Self>> {
         let mut superblock = Box::<SuperBlock>::new_uninit();
         block.read_block(2, superblock.as_bytes_mut())?;
@@ -760,10 +1047,7 @@ impl Ext2 {
         );
 
         assert_eq!(superblock.revision(), Revision::Revision1);
-        assert_eq!(
-            superblock.inode_size as usize,
-            core::mem::size_of::<disk::INode>()
-        );
+        assert!(superblock.inode_size as usize >= core::mem::size_of::<disk::INode>());
 
         Some(Arc::new_cyclic(|sref| Self {
             bgdt: GroupDescriptors::new(sref.clone(), &block, &superblock)
@@ -787,9 +1071,51 @@ impl Ext2 {
 impl FileSystem for Ext2 {
     fn root_dir(&self) -> DirCacheItem {
         let inode = self
-            .find_inode(Ext2::ROOT_INODE_ID, None)
+            .find_inode(disk::ROOT_INODE, None)
             .expect("ext2: invalid filesystem (root inode not found)");
 
         inode::DirEntry::new_root(inode, String::from("/"))
     }
+
+    /// Writes the inodes in the inode cache and the block group descriptors back to the disk.
+    ///
+    /// TODO: The free block and inode counts of the superblock are not kept up to date.
+    fn sync(&self) -> super::Result<()> {
+        for item in cache::icache().items() {
+            let Some(inode) = item.downcast_arc::<INode>() else {
+                continue;
+            };
+
+            if !inode.fs.ptr_eq(&self.sref) {
+                continue;
+            }
+
+            self.bgdt
+                .write_inode(inode.id, &inode.inode.read(), &inode.extra.read())
+                .ok_or(FileSystemError::Io)?;
+        }
+
+        self.bgdt.sync().ok_or(FileSystemError::Io)
+    }
+
+    /// The free counts are taken from the block group descriptors, as the ones in the superblock
+    /// are not kept up to date.
+    fn stat(&self) -> super::Result<StatFs> {
+        let superblock = &self.superblock;
+        let block_size = superblock.block_size() as u64;
+        let free_blocks = self.bgdt.free_blocks() as u64;
+
+        Ok(StatFs {
+            f_type: EXT2_SUPER_MAGIC,
+            f_bsize: block_size,
+            f_blocks: superblock.blocks_count as u64,
+            f_bfree: free_blocks,
+            f_bavail: free_blocks.saturating_sub(superblock.r_blocks_count as u64),
+            f_files: superblock.inodes_count as u64,
+            f_ffree: self.bgdt.free_inodes() as u64,
+            f_namelen: super::NAME_MAX as u64,
+            f_frsize: block_size,
+            ..Default::default()
+        })
+    }
 }
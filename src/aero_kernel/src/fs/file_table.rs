@@ -17,6 +17,8 @@
 
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+use aero_syscall::prelude::InotifyMask;
+use aero_syscall::process::RLimit;
 use aero_syscall::{OpenFlags, SysDirEntry};
 
 use alloc::sync::Arc;
@@ -30,6 +32,9 @@ use super::cache::{DirCacheItem, INodeCacheItem};
 use super::inode::FileType;
 use super::FileSystemError;
 
+/// Maximum number of file descriptors of a file table.
+pub const MAX_FILES: usize = 256;
+
 #[derive(Debug, Copy, Clone)]
 pub enum DuplicateHint {
     Exact(usize),
@@ -79,6 +84,29 @@ impl FileHandle {
         *self.flags.write() = flags;
     }
 
+    /// Returns [`true`] if the handle was opened with `O_PATH`. Such handles only refer to a
+    /// location in the filesystem tree and cannot be used for I/O.
+    #[inline]
+    pub fn is_path(&self) -> bool {
+        self.flags().contains(OpenFlags::O_PATH)
+    }
+
+    /// Notifies the backing inode that this handle is being closed.
+    pub fn close(&self) {
+        // `O_PATH` handles never opened the inode in the first place.
+        if !self.is_path() {
+            self.inode.inode().close(self.flags());
+
+            let mask = if self.is_writable() {
+                InotifyMask::CLOSE_WRITE
+            } else {
+                InotifyMask::CLOSE_NOWRITE
+            };
+
+            super::inotify::notify(&self.inode, mask);
+        }
+    }
+
     pub fn read(&self, buffer: &mut [u8]) -> super::Result<usize> {
         let offset = self.offset.load(Ordering::SeqCst);
         let new_offset = self.inode.inode().read_at(offset, buffer)?;
@@ -88,8 +116,24 @@ impl FileHandle {
     }
 
     pub fn write(&self, buffer: &[u8]) -> super::Result<usize> {
+        let inode = self.inode.inode();
+
+        // The filesystem may have been remounted read-only after the file was opened.
+        super::check_writable(&inode)?;
+
+        // The offset is ignored by appends, and only moved to the end of the file afterwards.
+        // Pipes and devices have no end of file, so the flag does not apply to them.
+        if self.flags().contains(OpenFlags::O_APPEND)
+            && inode.metadata().is_ok_and(|meta| meta.is_file())
+        {
+            let (offset, written) = inode.append(buffer)?;
+
+            self.offset.store(offset + written, Ordering::SeqCst);
+            return Ok(written);
+        }
+
         let offset = self.offset.load(Ordering::SeqCst);
-        let new_offset = self.inode.inode().write_at(offset, buffer)?;
+        let new_offset = inode.write_at(offset, buffer)?;
 
         self.offset.fetch_add(new_offset, Ordering::SeqCst);
         Ok(new_offset)
@@ -125,6 +169,23 @@ impl FileHandle {
             }
 
             Ok(self.offset.load(Ordering::SeqCst))
+        } else if meta.is_directory() {
+            // The offset of a directory is an opaque cursor returned by `dirent_at`, which can
+            // only be saved (`telldir`) and restored (`seekdir`).
+            let offset = match whence {
+                aero_syscall::SeekWhence::SeekSet => off,
+                aero_syscall::SeekWhence::SeekCur => {
+                    self.offset.load(Ordering::SeqCst) as isize + off
+                }
+                aero_syscall::SeekWhence::SeekEnd => return Err(FileSystemError::InvalidPath),
+            };
+
+            if offset < 0 {
+                return Err(FileSystemError::InvalidPath);
+            }
+
+            self.offset.store(offset as usize, Ordering::SeqCst);
+            Ok(offset as usize)
         } else {
             Err(FileSystemError::IsPipe)
         }
@@ -138,8 +199,10 @@ impl FileHandle {
         self.inode.inode()
     }
 
+    /// Creates a handle for the descriptor `dupfd` that shares the file and offset of this one.
+    /// The close-on-exec flag belongs to the descriptor, so it is only set if `flags` has it.
     pub fn duplicate(&self, dupfd: usize, flags: OpenFlags) -> super::Result<Arc<FileHandle>> {
-        let flags = *self.flags.read() | flags;
+        let flags = (self.flags() - OpenFlags::O_CLOEXEC) | flags;
         let new = Arc::new(Self {
             fd: dupfd,
             inode: self.inode.clone(),
@@ -147,7 +210,9 @@ impl FileHandle {
             flags: RwLock::new(flags),
         });
 
-        new.inode.inode().open(new.clone())?;
+        if !new.is_path() {
+            new.inode.inode().open(new.clone())?;
+        }
 
         Ok(new)
     }
@@ -156,7 +221,7 @@ impl FileHandle {
         let inode = self
             .inode
             .inode()
-            .dirent(self.inode.clone(), self.offset.load(Ordering::SeqCst))?;
+            .dirent_at(self.inode.clone(), self.offset.load(Ordering::SeqCst))?;
 
         // We are allowed to chop off the name of the entry though not the header
         // itself.
@@ -164,7 +229,7 @@ impl FileHandle {
             return Err(FileSystemError::TooSmall);
         }
 
-        if let Some(entry) = inode {
+        if let Some((entry, next)) = inode {
             let mut reclen = core::mem::size_of::<SysDirEntry>() + entry.name().len();
 
             if reclen > buffer.len() {
@@ -179,7 +244,7 @@ impl FileHandle {
             let sysd = unsafe { &mut *(buffer.as_mut_ptr().cast::<SysDirEntry>()) };
 
             sysd.inode = entry.inode().metadata()?.id();
-            sysd.offset = reclen;
+            sysd.offset = next;
             sysd.reclen = reclen;
             sysd.file_type = file_type as usize;
 
@@ -190,7 +255,7 @@ impl FileHandle {
                     .copy_from(entry.name().as_ptr(), name_size);
             }
 
-            self.offset.fetch_add(1, Ordering::SeqCst);
+            self.offset.store(next, Ordering::SeqCst);
             Ok(reclen)
         } else {
             // nothing to read
@@ -199,19 +264,56 @@ impl FileHandle {
     }
 }
 
-#[repr(transparent)]
-pub struct FileTable(pub RwLock<Vec<Option<Arc<FileHandle>>>>);
+pub struct FileTable {
+    pub files: RwLock<Vec<Option<Arc<FileHandle>>>>,
+    /// The `RLIMIT_NOFILE` limits. File descriptors are numbered below the soft limit.
+    nofile: RwLock<RLimit>,
+}
 
 impl FileTable {
     pub fn new() -> Self {
         let mut table = Vec::new();
-        table.resize(256, None);
+        table.resize(MAX_FILES, None);
+
+        Self {
+            files: RwLock::new(table),
+            nofile: RwLock::new(RLimit {
+                rlim_cur: MAX_FILES as u64,
+                rlim_max: MAX_FILES as u64,
+            }),
+        }
+    }
+
+    /// Returns the `RLIMIT_NOFILE` limits of the table.
+    pub fn nofile_limit(&self) -> RLimit {
+        *self.nofile.read()
+    }
+
+    /// Replaces the `RLIMIT_NOFILE` limits of the table. The hard limit can only be lowered.
+    /// File descriptors that are already open above the new soft limit stay open.
+    pub fn set_nofile_limit(&self, limit: RLimit) -> Result<(), aero_syscall::SyscallError> {
+        let mut nofile = self.nofile.write();
+
+        if limit.rlim_cur > limit.rlim_max {
+            return Err(aero_syscall::SyscallError::EINVAL);
+        }
+
+        if limit.rlim_max > nofile.rlim_max {
+            return Err(aero_syscall::SyscallError::EPERM);
+        }
 
-        Self(RwLock::new(table))
+        *nofile = limit;
+        Ok(())
+    }
+
+    /// Returns the number of file descriptors that can be opened, which is the soft
+    /// `RLIMIT_NOFILE` limit.
+    fn max_files(&self) -> usize {
+        self.nofile.read().rlim_cur as usize
     }
 
     pub fn get_handle(&self, fd: usize) -> Option<Arc<FileHandle>> {
-        let files = self.0.read();
+        let files = self.files.read();
 
         if let Some(Some(handle)) = &files.get(fd) {
             return Some(handle.clone());
@@ -220,8 +322,13 @@ impl FileTable {
         None
     }
 
+    /// Returns the open file handles, ordered by file descriptor.
+    pub fn handles(&self) -> Vec<Arc<FileHandle>> {
+        self.files.read().iter().flatten().cloned().collect()
+    }
+
     pub fn log(&self) {
-        let files = self.0.read();
+        let files = self.files.read();
 
         for handle in files.iter().flatten() {
             log::debug!(
@@ -233,14 +340,12 @@ impl FileTable {
     }
 
     pub fn close_on_exec(&self) {
-        let mut files = self.0.write();
+        let mut files = self.files.write();
 
         for file in files.iter_mut() {
             if let Some(handle) = file {
-                let flags = *handle.flags.read();
-
-                if flags.contains(OpenFlags::O_CLOEXEC) {
-                    handle.inode().close(flags);
+                if handle.flags().contains(OpenFlags::O_CLOEXEC) {
+                    handle.close();
                     *file = None;
                 }
             }
@@ -250,71 +355,77 @@ impl FileTable {
     /// Duplicates the provided file descriptor based on the provided duplicate
     /// descriptor hint. Check out the documentation for [`DuplicateHint`] for more
     /// information.
+    ///
+    /// The table is locked for the whole operation, so another thread never observes the
+    /// descriptor of [`DuplicateHint::Exact`] closed before the duplicate is installed.
     pub fn duplicate(
         &self,
         fd: usize,
         hint: DuplicateHint,
         flags: OpenFlags,
     ) -> Result<usize, aero_syscall::SyscallError> {
-        let handle = self
-            .get_handle(fd)
-            .ok_or(aero_syscall::SyscallError::EINVAL)?;
+        let mut files = self.files.write();
+
+        let handle = files
+            .get(fd)
+            .cloned()
+            .flatten()
+            .ok_or(aero_syscall::SyscallError::EBADFD)?;
 
-        let find_from = |files: &mut Vec<Option<Arc<FileHandle>>>, start: usize| {
-            let array = &mut files[start..];
+        let max_files = self.max_files();
 
+        let mut find_from = |start: usize| {
             // Loop over the current file descriptor table and find the first
             // available file descriptor.
-            for (i, file) in array.iter_mut().enumerate() {
-                if file.is_none() {
-                    *file = Some(handle.duplicate(i, flags)?);
-                    return Ok(start + i);
-                }
+            let end = files.len().min(max_files);
+
+            if let Some(fd) = (start..end).find(|&fd| files[fd].is_none()) {
+                files[fd] = Some(handle.duplicate(fd, flags)?);
+                return Ok(fd);
             }
 
             // We ran out of file descriptors. Grow the FD table and insert the FD.
-            let fd = files.len();
+            let fd = files.len().max(start);
+
+            if fd >= max_files {
+                return Err(aero_syscall::SyscallError::EMFILE);
+            }
+
+            files.resize(fd, None);
             files.push(Some(handle.duplicate(fd, flags)?));
             Ok(fd)
         };
 
         match hint {
             DuplicateHint::Exact(new_fd) => {
-                let mut files = self.0.write();
-
-                // Ensure the file descriptor is available.
-                if files[new_fd].is_none() {
-                    files[new_fd] = Some(handle.duplicate(new_fd, flags)?);
-                    Ok(0)
-                } else {
-                    // If the file descriptor is not available, then we close the
-                    // old one and set its handle to the new duplicate handle.
-                    let handle = handle.duplicate(new_fd, flags)?;
-                    let old = files[new_fd].take().unwrap();
-
-                    old.inode.inode().close(*old.flags.read());
-                    files[new_fd] = Some(handle);
-
-                    Ok(0)
+                if new_fd >= max_files {
+                    return Err(aero_syscall::SyscallError::EBADFD);
                 }
-            }
 
-            DuplicateHint::Any => {
-                let mut files = self.0.write();
-                find_from(&mut files, 0)
-            }
+                if new_fd >= files.len() {
+                    files.resize(new_fd + 1, None);
+                }
+
+                // If the file descriptor is already in use, the old file is closed and
+                // replaced by the duplicate.
+                let new = handle.duplicate(new_fd, flags)?;
 
-            DuplicateHint::GreatorOrEqual(hint_fd) => {
-                let mut files = self.0.write();
-                find_from(&mut files, hint_fd)
+                if let Some(old) = files[new_fd].replace(new) {
+                    old.close();
+                }
+
+                Ok(new_fd)
             }
+
+            DuplicateHint::Any => find_from(0),
+            DuplicateHint::GreatorOrEqual(hint_fd) => find_from(hint_fd),
         }
     }
 
     pub fn deep_clone(&self) -> Self {
-        let files = self.0.read();
+        let files = self.files.read();
 
-        for handle in files.iter().flatten() {
+        for handle in files.iter().flatten().filter(|handle| !handle.is_path()) {
             handle
                 .inode
                 .inode()
@@ -322,7 +433,10 @@ impl FileTable {
                 .expect("FileTable::clone: failed to open file");
         }
 
-        Self(RwLock::new(files.clone()))
+        Self {
+            files: RwLock::new(files.clone()),
+            nofile: RwLock::new(self.nofile_limit()),
+        }
     }
 
     pub fn debug_open_file(&self, dirent: DirCacheItem, flags: OpenFlags) -> super::Result<usize> {
@@ -331,32 +445,43 @@ impl FileTable {
     }
 
     pub fn open_file(&self, dentry: DirCacheItem, mut flags: OpenFlags) -> super::Result<usize> {
-        let mut files = self.0.write();
+        let mut files = self.files.write();
 
         // Remove all of the unnecessary flags.
         flags.remove(OpenFlags::O_CREAT);
         flags.remove(OpenFlags::O_DIRECTORY);
 
-        // Check if a file handle was removed, if so re-use the file handle.
-        if let Some((i, f)) = files.iter_mut().enumerate().find(|e| e.1.is_none()) {
-            let mut handle = Arc::new(FileHandle::new(i, dentry, flags));
+        // `O_PATH` handles only hold a reference to the directory entry, so the inode is not
+        // notified about the open.
+        let open_inode = |handle: Arc<FileHandle>| -> super::Result<Arc<FileHandle>> {
+            if handle.is_path() {
+                return Ok(handle);
+            }
 
             if let Some(inode) = handle.inode.inode().open(handle.clone())? {
                 // TODO: should open be called on the inner file as well???
-                handle = Arc::new(FileHandle::new(i, inode, flags))
+                return Ok(Arc::new(FileHandle::new(handle.fd, inode, flags)));
             }
 
+            Ok(handle)
+        };
+
+        let max_files = self.max_files();
+
+        // Check if a file handle was removed, if so re-use the file handle.
+        if let Some((i, f)) = files
+            .iter_mut()
+            .enumerate()
+            .take(max_files)
+            .find(|e| e.1.is_none())
+        {
+            let handle = open_inode(Arc::new(FileHandle::new(i, dentry, flags)))?;
             *f = Some(handle);
 
             Ok(i)
-        } else if files.len() < 256 {
+        } else if files.len() < max_files {
             let fd = files.len();
-            let mut handle = Arc::new(FileHandle::new(fd, dentry, flags));
-
-            if let Some(inode) = handle.inode.inode().open(handle.clone())? {
-                // TODO: should open be called on the inner file as well???
-                handle = Arc::new(FileHandle::new(fd, inode, flags))
-            }
+            let handle = open_inode(Arc::new(FileHandle::new(fd, dentry, flags)))?;
 
             files.push(Some(handle));
 
@@ -374,11 +499,11 @@ impl FileTable {
         // crate::unwind::unwind_stack_trace();
         // log::warn!("closing filedescriptor {fd} ---- END");
 
-        let mut files = self.0.write();
+        let mut files = self.files.write();
 
         if let Some(file) = files.get_mut(fd) {
             if let Some(handle) = file {
-                handle.inode.inode().close(*handle.flags.read());
+                handle.close();
                 *file = None;
 
                 return true;
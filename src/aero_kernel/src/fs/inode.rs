@@ -17,9 +17,10 @@
 
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+use aero_syscall::consts::{FallocateFlags, SealFlags};
 use aero_syscall::prelude::{EPollEventFlags, PollEventFlags};
 use aero_syscall::socket::{MessageFlags, MessageHeader};
-use aero_syscall::{MMapFlags, OpenFlags, SyscallError};
+use aero_syscall::{Mode, OpenFlags, SyscallError};
 
 use alloc::sync::{Arc, Weak};
 
@@ -27,7 +28,7 @@ use alloc::vec::Vec;
 use intrusive_collections::UnsafeRef;
 use spin::Once;
 
-use crate::mem::paging::{PhysFrame, VirtAddr};
+use crate::mem::paging::{CacheMode, PhysFrame, PhysFrameRange, VirtAddr};
 use crate::socket::unix::UnixSocket;
 use crate::socket::{SocketAddr, SocketAddrRef};
 use crate::userland::scheduler;
@@ -115,6 +116,50 @@ pub enum MMapPage {
     PageCache(PageCacheItem),
 }
 
+/// Frames backing a range of a device, returned by [`INodeInterface::mmap_region`].
+pub struct MappableRegion {
+    frames: RegionFrames,
+    cache: CacheMode,
+}
+
+enum RegionFrames {
+    Contiguous(PhysFrameRange),
+    Scattered(Vec<PhysFrame>),
+}
+
+impl MappableRegion {
+    /// Creates a region backed by the physically contiguous `frames`.
+    pub fn contiguous(frames: PhysFrameRange, cache: CacheMode) -> Self {
+        Self {
+            frames: RegionFrames::Contiguous(frames),
+            cache,
+        }
+    }
+
+    /// Creates a region backed by `frames`, in order.
+    pub fn scattered(frames: Vec<PhysFrame>, cache: CacheMode) -> Self {
+        Self {
+            frames: RegionFrames::Scattered(frames),
+            cache,
+        }
+    }
+
+    /// Returns the frames backing the consecutive pages of the region.
+    pub fn frames(&self) -> impl Iterator<Item = PhysFrame> + '_ {
+        let (range, scattered) = match &self.frames {
+            RegionFrames::Contiguous(range) => (Some(*range), &[][..]),
+            RegionFrames::Scattered(frames) => (None, &frames[..]),
+        };
+
+        range.into_iter().flatten().chain(scattered.iter().copied())
+    }
+
+    /// Returns the memory type the region has to be mapped with.
+    pub fn cache(&self) -> CacheMode {
+        self.cache
+    }
+}
+
 /// An inode describes a file. An inode structure holds metadata of the
 /// inode which includes its type, size, the number of links referring to it,
 /// and the list of blocks holding the file's content. For example device files,
@@ -146,8 +191,18 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
-    /// Creates a new directory with the provided `name` in the filesystem.
-    fn mkdir(&self, _name: &str) -> Result<INodeCacheItem> {
+    /// Writes `buffer` at the end of the file, for the handles opened with `O_APPEND`. Returns
+    /// the offset it was written at and the number of bytes written.
+    ///
+    /// File systems have to find the end of the file and write there atomically with respect to
+    /// the other writes and truncations of the inode, which the default implementation does not.
+    fn append(&self, buffer: &[u8]) -> Result<(usize, usize)> {
+        let offset = self.metadata()?.size;
+        Ok((offset, self.write_at(offset, buffer)?))
+    }
+
+    /// Creates a new directory with the provided `name` and permissions in the filesystem.
+    fn mkdir(&self, _name: &str, _mode: Mode) -> Result<INodeCacheItem> {
         Err(FileSystemError::NotSupported)
     }
 
@@ -155,8 +210,8 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
-    /// Creates a new file with the provided `name` in the filesystem.
-    fn touch(&self, _parent: DirCacheItem, _name: &str) -> Result<DirCacheItem> {
+    /// Creates a new file with the provided `name` and permissions in the filesystem.
+    fn touch(&self, _parent: DirCacheItem, _name: &str, _mode: Mode) -> Result<DirCacheItem> {
         Err(FileSystemError::NotSupported)
     }
 
@@ -164,6 +219,12 @@ pub trait INodeInterface: Send + Sync {
         Ok(aero_syscall::Stat::default())
     }
 
+    /// Returns the extended status of the inode. By default, only the fields of [`Self::stat`]
+    /// are filled in.
+    fn statx(&self) -> Result<aero_syscall::Statx> {
+        Ok(self.stat()?.into())
+    }
+
     fn shutdown(&self, _how: usize) -> Result<()> {
         Err(FileSystemError::NotSupported)
     }
@@ -187,6 +248,7 @@ pub trait INodeInterface: Send + Sync {
         &self,
         _name: &str,
         _inode: Arc<dyn INodeInterface>,
+        _mode: Mode,
     ) -> Result<INodeCacheItem> {
         Err(FileSystemError::NotSupported)
     }
@@ -216,6 +278,21 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
+    /// Returns the first directory entry at or after the directory offset `cursor` and the
+    /// cursor of the entry after it. The cursor of an entry must not change while the entry
+    /// exists, so that a directory stream can be resumed at it (`seekdir`).
+    ///
+    /// Defaults to the entry index as the cursor.
+    fn dirent_at(
+        &self,
+        parent: DirCacheItem,
+        cursor: usize,
+    ) -> Result<Option<(DirCacheItem, usize)>> {
+        Ok(self
+            .dirent(parent, cursor)?
+            .map(|entry| (entry, cursor + 1)))
+    }
+
     /// Returns a weak reference to the filesystem that this inode belongs to.
     fn weak_filesystem(&self) -> Option<Weak<dyn FileSystem>> {
         None
@@ -229,6 +306,13 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
+    /// Allocates the disk space of the `len` bytes at `offset`, so that writing to them does not
+    /// fail with `ENOSPC`. With [`FallocateFlags::PUNCH_HOLE`], the range is deallocated instead
+    /// and reads as zeroes.
+    fn fallocate(&self, _flags: FallocateFlags, _offset: usize, _len: usize) -> Result<()> {
+        Err(FileSystemError::NotSupported)
+    }
+
     /// ## Safety
     ///
     /// The caller is responsible for removing the inode from the cache.
@@ -236,7 +320,13 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
-    fn mmap(&self, _offset: usize, _size: usize, _flags: MMapFlags) -> Result<PhysFrame> {
+    /// Returns the frames backing the device memory from the page-aligned `offset`, which are
+    /// mapped all at once by the page fault handler instead of a page per fault. `len` is the
+    /// number of bytes left in the mapping; the returned region may be shorter, in which case the
+    /// rest is asked for on the next fault past its end.
+    ///
+    /// Files backed by the page cache implement [`INodeInterface::mmap_v2`] instead.
+    fn mmap_region(&self, _offset: usize, _len: usize) -> Result<MappableRegion> {
         Err(FileSystemError::NotSupported)
     }
 
@@ -248,6 +338,23 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
+    /// Called on `fork` for each mapping of the file. Returns the inode that backs the copy of
+    /// the mapping in the child, or [`None`] if the child shares this one.
+    fn fork_mapping(&self) -> Option<Arc<dyn INodeInterface>> {
+        None
+    }
+
+    /// Returns the seals of the file, see [`INodeInterface::add_seals`].
+    fn seals(&self) -> Result<SealFlags> {
+        Err(FileSystemError::InvalidInput)
+    }
+
+    /// Adds `seals` to the file, restricting the ways in which it can be modified from then on.
+    /// Seals cannot be removed, and only files created to be sealed support them.
+    fn add_seals(&self, _seals: SealFlags) -> Result<()> {
+        Err(FileSystemError::InvalidInput)
+    }
+
     // Socket operations:
     fn bind(&self, _address: SocketAddrRef, _length: usize) -> Result<()> {
         Err(FileSystemError::NotSocket)
@@ -297,6 +404,10 @@ pub trait INodeInterface: Send + Sync {
     fn symlink(&self, _target: &Path) -> Result<()> {
         Err(FileSystemError::NotSupported)
     }
+
+    /// Appends the lines specific to this kind of file to `/proc/<pid>/fdinfo/<fd>` (e.g. the
+    /// fill level of a pipe).
+    fn fdinfo(&self, _out: &mut String) {}
 }
 
 /// Structure representing the crucial, characteristics of an inode. The metadata
@@ -506,10 +617,11 @@ impl DirEntry {
         parent: DirCacheItem,
         name: String,
         inode: Arc<dyn INodeInterface>,
+        mode: Mode,
     ) -> Result<DirCacheItem> {
         let inode = parent
             .inode()
-            .make_local_socket_inode(name.as_str(), inode)?;
+            .make_local_socket_inode(name.as_str(), inode, mode)?;
 
         Ok(cache::dcache().make_item_no_cache(Self {
             data: BMutex::new(DirProtectedData {
@@ -547,6 +659,15 @@ impl DirEntry {
         self.data.lock().parent.clone()
     }
 
+    /// Returns whether both directory entries belong to the same filesystem. Entries without a
+    /// filesystem reference are never considered to be on the same filesystem.
+    pub fn is_same_filesystem(&self, other: &DirEntry) -> bool {
+        match (self.filesystem.get(), other.filesystem.get()) {
+            (Some(this), Some(other)) => Weak::ptr_eq(this, other),
+            _ => false,
+        }
+    }
+
     /// Drops the directory entry from the cache.
     pub fn drop_from_cache(&self) {
         cache::dcache().remove(&self.cache_key());
@@ -0,0 +1,417 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! File change notifications, with the interface of Linux's inotify.
+//!
+//! Every watch is registered in [`WATCHES`] under the inode it watches, so the VFS can report an
+//! event on an inode without knowing about the instances watching it. The events are queued on
+//! the instance, which hands them out as `inotify_event` records when it is read.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use aero_syscall::prelude::{InotifyEvent, InotifyMask};
+use aero_syscall::{OpenFlags, SyscallError};
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use spin::Once;
+
+use crate::utils::sync::{Mutex, WaitQueue};
+
+use super::cache::{DirCacheItem, INodeCacheItem};
+use super::file_table::FileHandle;
+use super::inode::{INodeInterface, PollFlags, PollTable};
+use super::FileSystemError;
+
+/// Maximum number of events queued on an instance (`max_queued_events` on Linux). Any further
+/// event is replaced with a single [`InotifyMask::Q_OVERFLOW`] event.
+const MAX_QUEUED_EVENTS: usize = 16384;
+
+/// The watches of every instance, indexed by the key of the watched inode.
+static WATCHES: Mutex<BTreeMap<usize, Vec<WatchRef>>> = Mutex::new(BTreeMap::new());
+
+static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Clone)]
+struct WatchRef {
+    instance: Weak<Inotify>,
+    wd: i32,
+}
+
+/// Returns the key that identifies `inode` in [`WATCHES`]. Watches hold a reference to the inode,
+/// so the key is not reused while it is watched.
+fn key(inode: &INodeCacheItem) -> usize {
+    Arc::as_ptr(inode.inner()).cast::<()>() as usize
+}
+
+fn unregister(key: usize, instance: &Weak<Inotify>, wd: i32) {
+    let mut watches = WATCHES.lock_irq();
+
+    if let Some(refs) = watches.get_mut(&key) {
+        refs.retain(|watch| watch.wd != wd || !Weak::ptr_eq(&watch.instance, instance));
+
+        if refs.is_empty() {
+            watches.remove(&key);
+        }
+    }
+}
+
+/// Returns a new cookie, used to pair the [`InotifyMask::MOVED_FROM`] and
+/// [`InotifyMask::MOVED_TO`] events of a rename.
+pub fn next_cookie() -> u32 {
+    NEXT_COOKIE.fetch_add(1, Ordering::Relaxed)
+}
+
+fn notify_inode(inode: &INodeCacheItem, mask: InotifyMask, cookie: u32, name: Option<&str>) {
+    let refs = match WATCHES.lock_irq().get(&key(inode)) {
+        Some(refs) => refs.clone(),
+        None => return,
+    };
+
+    for watch in refs {
+        if let Some(instance) = watch.instance.upgrade() {
+            instance.report(watch.wd, mask, cookie, name);
+        }
+    }
+}
+
+/// Reports `mask` on the file `entry` to the watches of the file and of its parent directory.
+pub fn notify(entry: &DirCacheItem, mask: InotifyMask) {
+    // Avoid looking up the metadata of the file when nothing is watched.
+    if WATCHES.lock_irq().is_empty() {
+        return;
+    }
+
+    let inode = entry.inode();
+    let mut mask = mask;
+
+    if inode
+        .metadata()
+        .is_ok_and(|metadata| metadata.is_directory())
+    {
+        mask.insert(InotifyMask::ISDIR);
+    }
+
+    notify_inode(&inode, mask, 0, None);
+
+    if let Some(parent) = entry.parent() {
+        notify_inode(&parent.inode(), mask, 0, Some(&entry.name()));
+    }
+}
+
+/// Reports `mask` on the entry `name` of the directory `dir`, such as its creation or removal.
+pub fn notify_entry(dir: &INodeCacheItem, name: &str, mask: InotifyMask, cookie: u32) {
+    notify_inode(dir, mask, cookie, Some(name));
+}
+
+#[derive(PartialEq)]
+struct Event {
+    wd: i32,
+    mask: InotifyMask,
+    cookie: u32,
+    name: Option<String>,
+}
+
+impl Event {
+    fn overflow() -> Self {
+        Self {
+            wd: -1,
+            mask: InotifyMask::Q_OVERFLOW,
+            cookie: 0,
+            name: None,
+        }
+    }
+
+    /// Returns the size of the name, which is NUL-terminated and padded to the alignment of the
+    /// records.
+    fn name_len(&self) -> usize {
+        self.name.as_ref().map_or(0, |name| {
+            (name.len() + 1).next_multiple_of(core::mem::size_of::<InotifyEvent>())
+        })
+    }
+
+    fn size(&self) -> usize {
+        core::mem::size_of::<InotifyEvent>() + self.name_len()
+    }
+
+    /// Writes the record of the event to `buffer`, which must be [`Event::size`] bytes long.
+    fn write(&self, buffer: &mut [u8]) {
+        let header = InotifyEvent {
+            wd: self.wd,
+            mask: self.mask,
+            cookie: self.cookie,
+            len: self.name_len() as u32,
+        };
+
+        let (head, name) = buffer.split_at_mut(core::mem::size_of::<InotifyEvent>());
+
+        // SAFETY: `head` is exactly as large as the header.
+        unsafe {
+            head.as_mut_ptr()
+                .cast::<InotifyEvent>()
+                .write_unaligned(header)
+        }
+
+        let bytes = self.name.as_ref().map_or(&[][..], |name| name.as_bytes());
+
+        name[..bytes.len()].copy_from_slice(bytes);
+        name[bytes.len()..].fill(0);
+    }
+}
+
+struct Watch {
+    inode: INodeCacheItem,
+    mask: InotifyMask,
+}
+
+struct Inner {
+    events: VecDeque<Event>,
+    watches: BTreeMap<i32, Watch>,
+    next_wd: i32,
+}
+
+impl Inner {
+    fn queue(&mut self, event: Event) {
+        // Identical events are merged when they are queued back to back.
+        if self.events.back() == Some(&event) {
+            return;
+        }
+
+        if self.events.len() >= MAX_QUEUED_EVENTS {
+            let overflow = Event::overflow();
+
+            if self.events.back() != Some(&overflow) {
+                self.events.push_back(overflow);
+            }
+
+            return;
+        }
+
+        self.events.push_back(event);
+    }
+}
+
+pub struct Inotify {
+    inner: Mutex<Inner>,
+    wq: WaitQueue,
+    handle: Once<Arc<FileHandle>>,
+    sref: Weak<Self>,
+}
+
+impl Inotify {
+    pub fn new() -> Arc<Self> {
+        Arc::new_cyclic(|sref| Self {
+            inner: Mutex::new(Inner {
+                events: VecDeque::new(),
+                watches: BTreeMap::new(),
+                next_wd: 1,
+            }),
+            wq: WaitQueue::new(),
+            handle: Once::new(),
+            sref: sref.clone(),
+        })
+    }
+
+    fn is_nonblock(&self) -> bool {
+        let handle = self
+            .handle
+            .get()
+            .expect("inotify: file handle is not initialized");
+        handle.flags().contains(OpenFlags::O_NONBLOCK)
+    }
+
+    /// Watches `inode` for the events in `mask` and returns the watch descriptor. If the inode is
+    /// already watched, its watch is updated instead.
+    ///
+    /// ## Errors
+    /// * `EINVAL`: `mask` contains no events, or both `IN_MASK_ADD` and `IN_MASK_CREATE`.
+    /// * `EEXIST`: `mask` contains `IN_MASK_CREATE` and the inode is already watched.
+    pub fn add_watch(&self, inode: INodeCacheItem, mask: InotifyMask) -> Result<i32, SyscallError> {
+        let events = mask & (InotifyMask::ALL_EVENTS | InotifyMask::ONESHOT);
+
+        if (mask & InotifyMask::ALL_EVENTS).is_empty()
+            || mask.contains(InotifyMask::MASK_ADD | InotifyMask::MASK_CREATE)
+        {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let key = key(&inode);
+        let mut inner = self.inner.lock_irq();
+
+        let existing = inner
+            .watches
+            .iter_mut()
+            .find(|(_, watch)| self::key(&watch.inode) == key);
+
+        if let Some((wd, watch)) = existing {
+            if mask.contains(InotifyMask::MASK_CREATE) {
+                return Err(SyscallError::EEXIST);
+            }
+
+            if mask.contains(InotifyMask::MASK_ADD) {
+                watch.mask |= events;
+            } else {
+                watch.mask = events;
+            }
+
+            return Ok(*wd);
+        }
+
+        let wd = inner.next_wd;
+        inner.next_wd += 1;
+        inner.watches.insert(
+            wd,
+            Watch {
+                inode,
+                mask: events,
+            },
+        );
+
+        WATCHES.lock_irq().entry(key).or_default().push(WatchRef {
+            instance: self.sref.clone(),
+            wd,
+        });
+
+        Ok(wd)
+    }
+
+    /// Removes the watch `wd`, which queues an [`InotifyMask::IGNORED`] event for it.
+    pub fn remove_watch(&self, wd: i32) -> Result<(), SyscallError> {
+        let mut inner = self.inner.lock_irq();
+        self.remove_watch_locked(&mut inner, wd)
+            .ok_or(SyscallError::EINVAL)?;
+
+        core::mem::drop(inner);
+        self.wq.notify_all();
+        Ok(())
+    }
+
+    fn remove_watch_locked(&self, inner: &mut Inner, wd: i32) -> Option<()> {
+        let watch = inner.watches.remove(&wd)?;
+        unregister(key(&watch.inode), &self.sref, wd);
+
+        inner.queue(Event {
+            wd,
+            mask: InotifyMask::IGNORED,
+            cookie: 0,
+            name: None,
+        });
+
+        Some(())
+    }
+
+    fn report(&self, wd: i32, mask: InotifyMask, cookie: u32, name: Option<&str>) {
+        let mut inner = self.inner.lock_irq();
+
+        let Some(watch) = inner.watches.get(&wd) else {
+            return;
+        };
+
+        let events = mask & watch.mask & InotifyMask::ALL_EVENTS;
+
+        if events.is_empty() {
+            return;
+        }
+
+        let oneshot = watch.mask.contains(InotifyMask::ONESHOT);
+
+        inner.queue(Event {
+            wd,
+            mask: events | (mask & InotifyMask::ISDIR),
+            cookie,
+            name: name.map(ToString::to_string),
+        });
+
+        if oneshot {
+            self.remove_watch_locked(&mut inner, wd);
+        }
+
+        core::mem::drop(inner);
+        self.wq.notify_all();
+    }
+}
+
+impl INodeInterface for Inotify {
+    fn open(&self, handle: Arc<FileHandle>) -> super::Result<Option<DirCacheItem>> {
+        self.handle.call_once(|| handle);
+        Ok(None)
+    }
+
+    fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> super::Result<usize> {
+        let mut inner = if self.is_nonblock() {
+            let inner = self.inner.lock_irq();
+
+            if inner.events.is_empty() {
+                return Err(FileSystemError::WouldBlock);
+            }
+
+            inner
+        } else {
+            self.wq
+                .block_on_interruptible(&self.inner, |inner| !inner.events.is_empty())
+                .map_err(|_| FileSystemError::Interrupted)?
+        };
+
+        let mut written = 0;
+
+        while let Some(event) = inner.events.front() {
+            let size = event.size();
+
+            if written + size > buffer.len() {
+                break;
+            }
+
+            event.write(&mut buffer[written..written + size]);
+            written += size;
+
+            inner.events.pop_front();
+        }
+
+        // The buffer is too small for the next event.
+        if written == 0 {
+            return Err(FileSystemError::InvalidInput);
+        }
+
+        Ok(written)
+    }
+
+    fn poll(&self, table: Option<&mut PollTable>) -> super::Result<PollFlags> {
+        let inner = self.inner.lock_irq();
+
+        if let Some(table) = table {
+            table.insert(&self.wq);
+        }
+
+        if inner.events.is_empty() {
+            Ok(PollFlags::empty())
+        } else {
+            Ok(PollFlags::IN)
+        }
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        let inner = self.inner.lock_irq();
+
+        for (wd, watch) in inner.watches.iter() {
+            unregister(key(&watch.inode), &self.sref, *wd);
+        }
+    }
+}
@@ -0,0 +1,594 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! A minimal `io_uring` implementation.
+//!
+//! The submission and completion queues live in a [`MemFd`] that userspace maps at the
+//! `IORING_OFF_*` offsets of the ring file descriptor. There is no submission thread: requests
+//! are issued by `io_uring_enter`. A request that would block is kept in the kernel and retried
+//! by the following `io_uring_enter` calls, which also wait for it to become ready if
+//! completions were requested.
+//!
+//! Only reads, writes, accepts, sends and receives are supported.
+
+use aero_syscall::io_uring::*;
+use aero_syscall::socket::{IoVec, MessageFlags, MessageHeader};
+use aero_syscall::{OpenFlags, SocketAddrUnix, SyscallError};
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use num_traits::FromPrimitive;
+
+use crate::arch::user_copy::copy_slice_to_user;
+use crate::mem::paging::*;
+use crate::userland::scheduler;
+use crate::utils::sync::{Mutex, WaitQueue};
+
+use super::file_table::FileHandle;
+use super::inode::{DirEntry, INodeInterface, MMapPage, PollFlags, PollTable};
+use super::memfd::MemFd;
+use super::{FileSystemError, Result};
+
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
+
+/// The largest amount of data that a single read or receive request transfers. Longer requests
+/// complete with a short count.
+const MAX_TRANSFER: usize = 64 * PAGE_SIZE;
+
+/// Allocates the kernel buffer that a read or receive request of `len` bytes is done into. The
+/// data is only copied to the user buffer afterwards, since the user buffer may be unmapped while
+/// the request sleeps.
+fn bounce_buffer(len: u32) -> Vec<u8> {
+    alloc::vec![0; (len as usize).min(MAX_TRANSFER)]
+}
+
+// Offsets of the fields of the submission queue ring.
+const SQ_HEAD: usize = 0;
+const SQ_TAIL: usize = 4;
+const SQ_RING_MASK: usize = 8;
+const SQ_RING_ENTRIES: usize = 12;
+const SQ_FLAGS: usize = 16;
+const SQ_DROPPED: usize = 20;
+const SQ_ARRAY: usize = 64;
+
+// Offsets of the fields of the completion queue ring, which follows the submission queue ring.
+const CQ_HEAD: usize = 0;
+const CQ_TAIL: usize = 4;
+const CQ_RING_MASK: usize = 8;
+const CQ_RING_ENTRIES: usize = 12;
+const CQ_OVERFLOW: usize = 16;
+const CQ_FLAGS: usize = 20;
+const CQ_CQES: usize = 64;
+
+type SyscallResult<T> = core::result::Result<T, SyscallError>;
+
+/// A request taken from the submission queue.
+struct Request {
+    sqe: Sqe,
+    op: IoUringOp,
+    /// The file is looked up when the request is submitted, so closing the file descriptor
+    /// afterwards does not affect the request.
+    file: Arc<FileHandle>,
+}
+
+impl Request {
+    fn new(sqe: Sqe) -> SyscallResult<Self> {
+        let op = IoUringOp::from_u8(sqe.opcode).ok_or(SyscallError::EINVAL)?;
+
+        // Linking, draining and fixed files are not supported.
+        if sqe.flags != 0 {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let file = scheduler::current_thread()
+            .file_table
+            .get_handle(sqe.fd as usize)
+            .ok_or(SyscallError::EBADF)?;
+
+        Ok(Self { sqe, op, file })
+    }
+
+    /// Returns whether the request can be executed without blocking. If `table` is provided, the
+    /// current task is added to the wait queues of the file.
+    fn is_ready(&self, table: Option<&mut PollTable>) -> bool {
+        let needed = match self.op {
+            IoUringOp::Read | IoUringOp::Recv | IoUringOp::Accept => PollFlags::IN,
+            IoUringOp::Write | IoUringOp::Send => PollFlags::OUT,
+        };
+
+        match self.file.inode().poll(table) {
+            Ok(ready) => ready.intersects(needed | PollFlags::ERR),
+            // Files that cannot be polled, such as regular files, never block.
+            Err(_) => true,
+        }
+    }
+
+    fn execute(&self) -> SyscallResult<usize> {
+        let sqe = &self.sqe;
+
+        match self.op {
+            IoUringOp::Read => {
+                crate::utils::validate_slice_mut(sqe.addr as *mut u8, sqe.len as _)?;
+                let mut buffer = bounce_buffer(sqe.len);
+
+                let size = if sqe.off == IORING_OFFSET_CURRENT {
+                    self.file.read(&mut buffer)?
+                } else {
+                    self.file.inode().read_at(sqe.off as usize, &mut buffer)?
+                };
+
+                copy_slice_to_user(sqe.addr as *mut u8, &buffer[..size])?;
+                Ok(size)
+            }
+
+            IoUringOp::Write => {
+                let buffer = crate::utils::validate_slice(sqe.addr as *const u8, sqe.len as _)?;
+
+                if sqe.off == IORING_OFFSET_CURRENT {
+                    Ok(self.file.write(buffer)?)
+                } else {
+                    let inode = self.file.inode();
+
+                    super::check_writable(&inode)?;
+                    Ok(inode.write_at(sqe.off as usize, buffer)?)
+                }
+            }
+
+            IoUringOp::Recv | IoUringOp::Send => {
+                let flags =
+                    MessageFlags::from_bits(sqe.op_flags as usize).ok_or(SyscallError::EINVAL)?;
+
+                if self.op == IoUringOp::Recv {
+                    crate::utils::validate_slice_mut(sqe.addr as *mut u8, sqe.len as _)?;
+                    let mut buffer = bounce_buffer(sqe.len);
+
+                    let mut iovecs = [IoVec::from_slice_mut(&mut buffer)];
+                    let mut header = MessageHeader::new::<SocketAddrUnix>(None, &mut iovecs);
+                    let size = self.file.inode().recv(&mut header, flags)?;
+
+                    copy_slice_to_user(sqe.addr as *mut u8, &buffer[..size.min(buffer.len())])?;
+                    Ok(size)
+                } else {
+                    let buffer = crate::utils::validate_slice(sqe.addr as *const u8, sqe.len as _)?;
+
+                    let mut iovecs = [IoVec::from_slice(buffer)];
+                    let mut header = MessageHeader::new::<SocketAddrUnix>(None, &mut iovecs);
+                    Ok(self.file.inode().send(&mut header, flags)?)
+                }
+            }
+
+            IoUringOp::Accept => {
+                // Like `accept`, the flags of the new socket are ignored.
+                let address = if sqe.addr != 0 && sqe.off != 0 {
+                    Some((
+                        VirtAddr::new(sqe.addr),
+                        crate::utils::validate_mut_ptr(sqe.off as *mut u32)?,
+                    ))
+                } else {
+                    None
+                };
+
+                let connection = self.file.inode().accept(address)?;
+                let entry = DirEntry::from_inode(connection, String::from("<socket>"));
+
+                Ok(scheduler::current_thread()
+                    .file_table
+                    .open_file(entry, OpenFlags::O_RDWR)?)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Requests that could not be executed without blocking.
+    pending: VecDeque<Request>,
+    /// Completions that did not fit in the completion queue.
+    overflow: VecDeque<Cqe>,
+}
+
+pub struct IoUring {
+    memory: Arc<MemFd>,
+
+    sq_entries: u32,
+    cq_entries: u32,
+
+    /// Offset of the completion queue ring in the memory.
+    cq_base: usize,
+    /// Size of the rings, which are followed by the submission queue entries in the memory.
+    rings_size: usize,
+    sqes_size: usize,
+
+    state: Mutex<State>,
+    /// Woken up when a completion is posted.
+    completions: WaitQueue,
+}
+
+impl IoUring {
+    /// Creates a ring with at least `entries` submission queue entries and fills `params` with
+    /// its layout.
+    pub fn new(entries: usize, params: &mut IoUringParams) -> SyscallResult<Arc<Self>> {
+        if entries == 0 || entries > IORING_MAX_ENTRIES {
+            return Err(SyscallError::EINVAL);
+        }
+
+        if !SetupFlags::all().contains(params.flags) {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let sq_entries = entries.next_power_of_two();
+        let cq_entries = if params.flags.contains(SetupFlags::CQSIZE) {
+            let cq_entries = params.cq_entries as usize;
+
+            if cq_entries < sq_entries || cq_entries > IORING_MAX_CQ_ENTRIES {
+                return Err(SyscallError::EINVAL);
+            }
+
+            cq_entries.next_power_of_two()
+        } else {
+            2 * sq_entries
+        };
+
+        let cq_base = align_up((SQ_ARRAY + sq_entries * size_of::<u32>()) as u64, 64) as usize;
+        let cqes_end = cq_base + CQ_CQES + cq_entries * size_of::<Cqe>();
+
+        let rings_size = align_up(cqes_end as u64, PAGE_SIZE as u64) as usize;
+        let sqes_size = align_up((sq_entries * size_of::<Sqe>()) as u64, PAGE_SIZE as u64) as usize;
+
+        let memory = MemFd::new();
+        memory.truncate(rings_size + sqes_size)?;
+
+        let this = Arc::new(Self {
+            memory,
+
+            sq_entries: sq_entries as u32,
+            cq_entries: cq_entries as u32,
+
+            cq_base,
+            rings_size,
+            sqes_size,
+
+            state: Mutex::new(State::default()),
+            completions: WaitQueue::new(),
+        });
+
+        let sq_mask = this.sq_entries - 1;
+        let cq_mask = this.cq_entries - 1;
+
+        this.sq(SQ_RING_MASK).store(sq_mask, Ordering::Relaxed);
+        this.sq(SQ_RING_ENTRIES)
+            .store(this.sq_entries, Ordering::Relaxed);
+        this.cq(CQ_RING_MASK).store(cq_mask, Ordering::Relaxed);
+        this.cq(CQ_RING_ENTRIES)
+            .store(this.cq_entries, Ordering::Relaxed);
+
+        params.sq_entries = this.sq_entries;
+        params.cq_entries = this.cq_entries;
+        params.features = Features::SINGLE_MMAP | Features::NODROP;
+
+        params.sq_off = SqRingOffsets {
+            head: SQ_HEAD as u32,
+            tail: SQ_TAIL as u32,
+            ring_mask: SQ_RING_MASK as u32,
+            ring_entries: SQ_RING_ENTRIES as u32,
+            flags: SQ_FLAGS as u32,
+            dropped: SQ_DROPPED as u32,
+            array: SQ_ARRAY as u32,
+            ..Default::default()
+        };
+
+        params.cq_off = CqRingOffsets {
+            head: (cq_base + CQ_HEAD) as u32,
+            tail: (cq_base + CQ_TAIL) as u32,
+            ring_mask: (cq_base + CQ_RING_MASK) as u32,
+            ring_entries: (cq_base + CQ_RING_ENTRIES) as u32,
+            overflow: (cq_base + CQ_OVERFLOW) as u32,
+            cqes: (cq_base + CQ_CQES) as u32,
+            flags: (cq_base + CQ_FLAGS) as u32,
+            ..Default::default()
+        };
+
+        Ok(this)
+    }
+
+    /// Returns a pointer to the value at `offset` in the memory of the ring. The value must not
+    /// cross a page boundary.
+    fn ptr<T>(&self, offset: usize) -> *mut T {
+        debug_assert!(offset % PAGE_SIZE + size_of::<T>() <= PAGE_SIZE);
+
+        // The memory is sized when the ring is created, so every offset in it has a page.
+        let Ok(MMapPage::Direct(frame)) = self.memory.mmap_v2(offset) else {
+            unreachable!("io_uring: {offset:#x} is outside of the ring")
+        };
+
+        (frame.start_address().as_hhdm_virt() + offset % PAGE_SIZE).as_mut_ptr()
+    }
+
+    fn sq(&self, field: usize) -> &AtomicU32 {
+        // SAFETY: The field is aligned and the memory lives as long as the ring.
+        unsafe { &*self.ptr::<AtomicU32>(field) }
+    }
+
+    fn cq(&self, field: usize) -> &AtomicU32 {
+        // SAFETY: The field is aligned and the memory lives as long as the ring.
+        unsafe { &*self.ptr::<AtomicU32>(self.cq_base + field) }
+    }
+
+    /// Returns the number of completions that userspace has not consumed yet.
+    fn ready_completions(&self) -> u32 {
+        let tail = self.cq(CQ_TAIL).load(Ordering::Relaxed);
+        tail.wrapping_sub(self.cq(CQ_HEAD).load(Ordering::Acquire))
+    }
+
+    /// Moves the overflowed completions to the completion queue, as long as there is room.
+    fn flush_overflow(&self, state: &mut State) {
+        while self.ready_completions() < self.cq_entries {
+            let Some(cqe) = state.overflow.pop_front() else {
+                break;
+            };
+
+            let tail = self.cq(CQ_TAIL).load(Ordering::Relaxed);
+            let index = (tail & (self.cq_entries - 1)) as usize;
+
+            // SAFETY: The index is masked, so the entry is inside of the completion queue.
+            unsafe {
+                self.ptr::<Cqe>(self.cq_base + CQ_CQES + index * size_of::<Cqe>())
+                    .write_volatile(cqe);
+            }
+
+            self.cq(CQ_TAIL)
+                .store(tail.wrapping_add(1), Ordering::Release);
+        }
+
+        let flags = self.sq(SQ_FLAGS);
+        let overflow = SqRingFlags::CQ_OVERFLOW.bits();
+
+        if state.overflow.is_empty() {
+            flags.fetch_and(!overflow, Ordering::Relaxed);
+        } else {
+            flags.fetch_or(overflow, Ordering::Relaxed);
+        }
+    }
+
+    fn complete(&self, user_data: u64, result: SyscallResult<usize>) {
+        let res = match result {
+            Ok(value) => value as i32,
+            Err(err) => -(err as i32),
+        };
+
+        {
+            let mut state = self.state.lock();
+
+            state.overflow.push_back(Cqe {
+                user_data,
+                res,
+                flags: 0,
+            });
+
+            self.flush_overflow(&mut state);
+        }
+
+        self.completions.notify_all();
+    }
+
+    /// Executes the request if it would not block and keeps it pending otherwise.
+    fn attempt(&self, request: Request) {
+        if request.is_ready(None) {
+            self.complete(request.sqe.user_data, request.execute());
+        } else if request.file.flags().contains(OpenFlags::O_NONBLOCK) {
+            self.complete(request.sqe.user_data, Err(SyscallError::EAGAIN));
+        } else {
+            self.state.lock().pending.push_back(request);
+        }
+    }
+
+    /// Retries the pending requests and flushes the overflowed completions.
+    fn reap(&self) {
+        let pending = core::mem::take(&mut self.state.lock().pending);
+
+        for request in pending {
+            self.attempt(request);
+        }
+
+        self.flush_overflow(&mut *self.state.lock());
+    }
+
+    /// Takes up to `count` entries from the submission queue and issues them. Returns the number
+    /// of entries that were consumed.
+    fn submit(&self, count: usize) -> usize {
+        let mut sqes = Vec::new();
+
+        // Copy the entries out first, as issuing them may block.
+        let count = {
+            let _guard = self.state.lock();
+
+            let head = self.sq(SQ_HEAD).load(Ordering::Relaxed);
+            let tail = self.sq(SQ_TAIL).load(Ordering::Acquire);
+            let count = count
+                .min(tail.wrapping_sub(head) as usize)
+                .min(self.sq_entries as usize);
+
+            for i in 0..count as u32 {
+                let slot = (head.wrapping_add(i) & (self.sq_entries - 1)) as usize;
+
+                // SAFETY: The slot is masked, so it is inside of the index array.
+                let index = unsafe { self.ptr::<u32>(SQ_ARRAY + slot * size_of::<u32>()).read() };
+
+                if index >= self.sq_entries {
+                    self.sq(SQ_DROPPED).fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+
+                // SAFETY: The index was checked above.
+                let sqe = unsafe {
+                    self.ptr::<Sqe>(self.rings_size + index as usize * size_of::<Sqe>())
+                        .read_volatile()
+                };
+
+                sqes.push(sqe);
+            }
+
+            self.sq(SQ_HEAD)
+                .store(head.wrapping_add(count as u32), Ordering::Release);
+
+            count
+        };
+
+        for sqe in sqes {
+            match Request::new(sqe) {
+                Ok(request) => self.attempt(request),
+                Err(err) => self.complete(sqe.user_data, Err(err)),
+            }
+        }
+
+        count
+    }
+
+    /// Blocks until at least `min_complete` completions are available to userspace, or until no
+    /// pending request is left to complete.
+    fn wait(&self, min_complete: usize) -> SyscallResult<()> {
+        let min_complete = min_complete.min(self.cq_entries as usize) as u32;
+
+        loop {
+            self.reap();
+
+            if self.ready_completions() >= min_complete {
+                return Ok(());
+            }
+
+            let pending = core::mem::take(&mut self.state.lock().pending);
+
+            // Nothing is in flight, so waiting would never finish.
+            if pending.is_empty() {
+                return Ok(());
+            }
+
+            let mut table = PollTable::default();
+            table.insert(&self.completions);
+
+            let mut ready = false;
+
+            for request in pending.iter() {
+                ready |= request.is_ready(Some(&mut table));
+            }
+
+            // Put the requests back in front of the ones that were added in the meantime.
+            {
+                let mut state = self.state.lock();
+                let newer = core::mem::replace(&mut state.pending, pending);
+
+                state.pending.extend(newer);
+            }
+
+            if !ready && self.ready_completions() < min_complete {
+                scheduler::get_scheduler().inner.await_io()?;
+            }
+        }
+    }
+
+    /// Submits `to_submit` requests and, with [`EnterFlags::GETEVENTS`], waits for
+    /// `min_complete` completions. Returns the number of submitted requests.
+    pub fn enter(
+        &self,
+        to_submit: usize,
+        min_complete: usize,
+        flags: EnterFlags,
+    ) -> SyscallResult<usize> {
+        let submitted = self.submit(to_submit);
+
+        if flags.contains(EnterFlags::GETEVENTS) {
+            self.wait(min_complete)?;
+        } else {
+            self.reap();
+        }
+
+        Ok(submitted)
+    }
+
+    pub fn register(&self, opcode: RegisterOp, arg: usize, nr_args: usize) -> SyscallResult<usize> {
+        match opcode {
+            RegisterOp::Probe => {
+                let probe = crate::utils::validate_mut_ptr(arg as *mut Probe)?;
+                let ops = crate::utils::validate_slice_mut(
+                    (arg + size_of::<Probe>()) as *mut ProbeOp,
+                    nr_args.min(u8::MAX as usize),
+                )?;
+
+                probe.last_op = IoUringOp::LAST as u8;
+                probe.ops_len = ops.len().min(IoUringOp::LAST as usize + 1) as u8;
+
+                for (i, op) in ops.iter_mut().take(probe.ops_len as usize).enumerate() {
+                    op.op = i as u8;
+                    op.flags = if IoUringOp::from_usize(i).is_some() {
+                        IO_URING_OP_SUPPORTED
+                    } else {
+                        0
+                    };
+                }
+
+                Ok(0)
+            }
+        }
+    }
+
+    /// Translates an `mmap` offset of the ring to an offset in its memory.
+    fn memory_offset(&self, offset: usize) -> Option<usize> {
+        if offset >= IORING_OFF_SQES {
+            let offset = offset - IORING_OFF_SQES;
+            return (offset < self.sqes_size).then_some(self.rings_size + offset);
+        }
+
+        // Both rings are in the same mapping (`IORING_FEAT_SINGLE_MMAP`).
+        let offset = if offset >= IORING_OFF_CQ_RING {
+            offset - IORING_OFF_CQ_RING
+        } else {
+            offset - IORING_OFF_SQ_RING
+        };
+
+        (offset < self.rings_size).then_some(offset)
+    }
+}
+
+impl INodeInterface for IoUring {
+    fn mmap_v2(&self, offset: usize) -> Result<MMapPage> {
+        let offset = self
+            .memory_offset(offset)
+            .ok_or(FileSystemError::NotSupported)?;
+
+        self.memory.mmap_v2(offset)
+    }
+
+    fn poll(&self, table: Option<&mut PollTable>) -> Result<PollFlags> {
+        if let Some(table) = table {
+            table.insert(&self.completions);
+        }
+
+        let mut flags = PollFlags::OUT;
+
+        if self.ready_completions() > 0 {
+            flags.insert(PollFlags::IN);
+        }
+
+        Ok(flags)
+    }
+}
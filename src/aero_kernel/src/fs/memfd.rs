@@ -0,0 +1,298 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Anonymous memory-backed files, created with `memfd_create`.
+//!
+//! The contents live in frames that are allocated on first access and shared by every shared
+//! mapping of the file, which makes them usable as shared memory between processes.
+//!
+//! Files created with `MFD_ALLOW_SEALING` can be sealed with `fcntl(F_ADD_SEALS)`, which lets a
+//! process hand one to another that does not trust it without the contents changing under it.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use aero_syscall::consts::SealFlags;
+use aero_syscall::Mode;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+use crate::mem::paging::*;
+use crate::userland::scheduler;
+use crate::utils::sync::Mutex;
+
+use super::cache::INodeCacheItem;
+use super::inode::{FileType, INodeInterface, MMapPage, Metadata};
+use super::{FileSystemError, Result};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
+
+/// Contents of a file kept in anonymous memory, also used by [`tmpfs`](super::tmpfs).
+pub(super) struct Contents {
+    pub(super) size: usize,
+    /// Frames of the pages that have been written or mapped, indexed by page.
+    pages: BTreeMap<usize, PhysFrame>,
+}
+
+impl Contents {
+    pub(super) const fn new() -> Self {
+        Self {
+            size: 0,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of pages holding data.
+    pub(super) fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns the number of pages of `offset..offset + len` that are not allocated yet.
+    pub(super) fn missing_pages(&self, offset: usize, len: usize) -> usize {
+        if len == 0 {
+            return 0;
+        }
+
+        (offset / PAGE_SIZE..(offset + len).div_ceil(PAGE_SIZE))
+            .filter(|index| !self.pages.contains_key(index))
+            .count()
+    }
+
+    /// Returns the frame of the page at `index`, allocating a zeroed one if it has not been
+    /// accessed yet.
+    pub(super) fn page(&mut self, index: usize) -> Result<PhysFrame> {
+        if let Some(frame) = self.pages.get(&index) {
+            return Ok(*frame);
+        }
+
+        let frame = FRAME_ALLOCATOR
+            .alloc_zeroed(PAGE_SIZE)
+            .map(PhysFrame::containing_address)
+            .ok_or(FileSystemError::OutOfMemory)?;
+
+        // The file holds a reference to the frame, so it is not deallocated when the last
+        // mapping of the page is unmapped.
+        frame.start_address().as_vm_frame().unwrap().inc_ref_count();
+
+        self.pages.insert(index, frame);
+        Ok(frame)
+    }
+
+    /// Reads the contents at `offset` into `buffer`. The pages that were never written read
+    /// back as zeroes, without being allocated.
+    pub(super) fn read(&self, offset: usize, buffer: &mut [u8]) -> usize {
+        let size = buffer.len().min(self.size.saturating_sub(offset));
+        let mut loc = 0;
+
+        while loc < size {
+            let page_offset = (offset + loc) % PAGE_SIZE;
+            let chunk = (PAGE_SIZE - page_offset).min(size - loc);
+            let target = &mut buffer[loc..loc + chunk];
+
+            match self.pages.get(&((offset + loc) / PAGE_SIZE)) {
+                Some(frame) => {
+                    target.copy_from_slice(&frame.as_slice_mut()[page_offset..page_offset + chunk])
+                }
+                None => target.fill(0),
+            }
+
+            loc += chunk;
+        }
+
+        size
+    }
+
+    /// Writes `buffer` at `offset`, extending the file if needed.
+    pub(super) fn write(&mut self, offset: usize, buffer: &[u8]) -> Result<usize> {
+        let mut loc = 0;
+
+        while loc < buffer.len() {
+            let page_offset = (offset + loc) % PAGE_SIZE;
+            let chunk = (PAGE_SIZE - page_offset).min(buffer.len() - loc);
+
+            let frame = self.page((offset + loc) / PAGE_SIZE)?;
+            frame.as_slice_mut()[page_offset..page_offset + chunk]
+                .copy_from_slice(&buffer[loc..loc + chunk]);
+
+            loc += chunk;
+        }
+
+        self.size = self.size.max(offset + buffer.len());
+        Ok(buffer.len())
+    }
+
+    /// Sets the size of the file to `size`. Returns the number of pages released.
+    pub(super) fn truncate(&mut self, size: usize) -> usize {
+        // Release the pages past the new end and zero the tail of the last page, so that the
+        // file reads back zeroes if it is extended again.
+        let released = self.pages.split_off(&size.div_ceil(PAGE_SIZE));
+        let count = released.len();
+
+        for (_, frame) in released {
+            Self::release(frame);
+        }
+
+        if size % PAGE_SIZE != 0 {
+            if let Some(frame) = self.pages.get(&(size / PAGE_SIZE)) {
+                frame.as_slice_mut::<u8>()[size % PAGE_SIZE..].fill(0);
+            }
+        }
+
+        self.size = size;
+        count
+    }
+
+    /// Drops the reference of the file to `frame`.
+    fn release(frame: PhysFrame) {
+        let vm_frame = frame.start_address().as_vm_frame().unwrap();
+        vm_frame.dec_ref_count();
+
+        if vm_frame.ref_count() == 0 {
+            FRAME_ALLOCATOR.deallocate_frame(frame);
+        }
+    }
+}
+
+impl Drop for Contents {
+    fn drop(&mut self) {
+        for (_, frame) in core::mem::take(&mut self.pages) {
+            Self::release(frame);
+        }
+    }
+}
+
+pub struct MemFd {
+    id: usize,
+    contents: Mutex<Contents>,
+    /// Kept apart from the contents, which are locked while handling page faults on the file.
+    seals: Mutex<SealFlags>,
+}
+
+impl MemFd {
+    /// Creates an empty file that cannot be sealed.
+    pub fn new() -> Arc<Self> {
+        Self::with_seals(SealFlags::SEAL)
+    }
+
+    /// Creates an empty file that can be sealed.
+    pub fn sealable() -> Arc<Self> {
+        Self::with_seals(SealFlags::empty())
+    }
+
+    fn with_seals(seals: SealFlags) -> Arc<Self> {
+        Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            contents: Mutex::new(Contents::new()),
+            seals: Mutex::new(seals),
+        })
+    }
+}
+
+/// Returns whether `inode` is mapped shared and writable (or could be made writable with
+/// `mprotect`) in any address space.
+pub fn is_writably_mapped(inode: &INodeCacheItem) -> bool {
+    let mut mapped = false;
+
+    scheduler::get_scheduler().for_each_task(|task| {
+        task.vm().for_each_mapping(|map| {
+            mapped |= map.is_shared_writable()
+                && map
+                    .file
+                    .as_ref()
+                    .is_some_and(|file| Arc::ptr_eq(&*file.file().inode(), &**inode));
+        });
+    });
+
+    mapped
+}
+
+impl INodeInterface for MemFd {
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            id: self.id,
+            file_type: FileType::File,
+            size: self.contents.lock().size,
+            children_len: 0,
+        })
+    }
+
+    fn stat(&self) -> Result<aero_syscall::Stat> {
+        Ok(aero_syscall::Stat {
+            st_size: self.contents.lock().size as _,
+            st_mode: Mode::S_IFREG | Mode::S_IRUSR | Mode::S_IWUSR,
+            ..Default::default()
+        })
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize> {
+        Ok(self.contents.lock().read(offset, buffer))
+    }
+
+    fn write_at(&self, offset: usize, buffer: &[u8]) -> Result<usize> {
+        let seals = *self.seals.lock();
+        let mut contents = self.contents.lock();
+
+        if seals.intersects(SealFlags::WRITE | SealFlags::FUTURE_WRITE)
+            || (seals.contains(SealFlags::GROW) && offset + buffer.len() > contents.size)
+        {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        contents.write(offset, buffer)
+    }
+
+    fn truncate(&self, size: usize) -> Result<()> {
+        let seals = *self.seals.lock();
+        let mut contents = self.contents.lock();
+
+        if (seals.contains(SealFlags::SHRINK) && size < contents.size)
+            || (seals.contains(SealFlags::GROW) && size > contents.size)
+        {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        contents.truncate(size);
+        Ok(())
+    }
+
+    fn mmap_v2(&self, offset: usize) -> Result<MMapPage> {
+        let mut contents = self.contents.lock();
+
+        // Accessing a page past the end of the file is an error (`SIGBUS` on Linux).
+        if offset >= contents.size {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        Ok(MMapPage::Direct(contents.page(offset / PAGE_SIZE)?))
+    }
+
+    fn seals(&self) -> Result<SealFlags> {
+        Ok(*self.seals.lock())
+    }
+
+    fn add_seals(&self, seals: SealFlags) -> Result<()> {
+        let mut current = self.seals.lock();
+
+        if current.contains(SealFlags::SEAL) {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        current.insert(seals);
+        Ok(())
+    }
+}
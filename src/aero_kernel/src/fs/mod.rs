@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use core::cell::Cell;
 use core::mem;
 
 pub mod path;
@@ -22,16 +23,21 @@ pub mod path;
 // TODO: Do not re-export this.
 pub use path::Path;
 
-use aero_syscall::SyscallError;
-use alloc::collections::BTreeMap;
-use alloc::sync::Arc;
+use aero_syscall::prelude::InotifyMask;
+use aero_syscall::{Mode, ResolveFlags, StatFs, SyscallError};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
 use crate::fs::cache::DirCacheImpl;
+use crate::fs::inotify;
+use crate::mem::paging::{PageSize, Size4KiB};
 use crate::userland::scheduler;
 use crate::utils::sync::Mutex;
 use spin::Once;
 
-use self::cache::{Cacheable, DirCacheItem};
+use self::cache::{Cacheable, DirCacheItem, INodeCacheItem};
+use self::inode::FileType;
 
 pub mod block;
 pub mod cache;
@@ -41,9 +47,21 @@ pub mod eventfd;
 pub mod ext2;
 pub mod file_table;
 pub mod inode;
+pub mod inotify;
+pub mod io_uring;
+pub mod memfd;
+pub mod mqueue;
 pub mod pipe;
 pub mod procfs;
 pub mod ramfs;
+pub mod tmpfs;
+
+/// Maximum length of a path, in bytes, including the terminating null byte.
+pub const PATH_MAX: usize = 4096;
+/// Maximum length of a path component, in bytes.
+pub const NAME_MAX: usize = 255;
+/// Maximum number of symbolic links followed while looking up a path.
+const MAX_SYMLINKS: usize = 40;
 
 static ROOT_FS: Once<Arc<dyn FileSystem>> = Once::new();
 static ROOT_DIR: Once<DirCacheItem> = Once::new();
@@ -63,17 +81,23 @@ struct MountPoint {
     origin_entry: DirCacheItem,
 }
 
-#[repr(transparent)]
-pub struct MountManager(Mutex<BTreeMap<MountKey, MountPoint>>);
+pub struct MountManager {
+    mounts: Mutex<BTreeMap<MountKey, MountPoint>>,
+    /// Addresses of the filesystems that are mounted read-only, including the root filesystem.
+    read_only: Mutex<BTreeSet<usize>>,
+}
 
 impl MountManager {
     #[inline]
     fn new() -> Self {
-        Self(Mutex::new(BTreeMap::new()))
+        Self {
+            mounts: Mutex::new(BTreeMap::new()),
+            read_only: Mutex::new(BTreeSet::new()),
+        }
     }
 
     pub fn mount(&self, directory: DirCacheItem, filesystem: Arc<dyn FileSystem>) -> Result<()> {
-        let mut this = self.0.lock();
+        let mut this = self.mounts.lock();
         let mount_key = directory.cache_key();
 
         if this.contains_key(&mount_key) {
@@ -103,8 +127,61 @@ impl MountManager {
         Ok(())
     }
 
+    /// Returns whether `dir` is the root directory of a mounted filesystem.
+    fn is_mount_root(&self, dir: &DirCacheItem) -> bool {
+        let this = self.mounts.lock();
+
+        this.values()
+            .any(|mount_point| Arc::ptr_eq(&*mount_point.root_entry, &**dir))
+    }
+
+    /// Changes whether the filesystem `dir` is the root of is mounted read-only. Fails with
+    /// `InvalidInput` if `dir` is not the root of a mount.
+    pub fn remount(&self, dir: &DirCacheItem, read_only: bool) -> Result<()> {
+        if !Arc::ptr_eq(&**dir, &**root_dir()) && !self.is_mount_root(dir) {
+            return Err(FileSystemError::InvalidInput);
+        }
+
+        let filesystem = dir
+            .inode()
+            .weak_filesystem()
+            .ok_or(FileSystemError::InvalidInput)?;
+
+        let key = filesystem_key(&filesystem);
+
+        if !read_only {
+            self.read_only.lock().remove(&key);
+            return Ok(());
+        }
+
+        self.read_only.lock().insert(key);
+
+        // Write back what the filesystem keeps in memory, as it is not modified from now on.
+        if let Some(filesystem) = filesystem.upgrade() {
+            filesystem.sync()?;
+        }
+
+        block::sync();
+        Ok(())
+    }
+
+    /// Returns whether `inode` is on a filesystem that is mounted read-only.
+    pub fn is_read_only(&self, inode: &INodeCacheItem) -> bool {
+        inode
+            .weak_filesystem()
+            .is_some_and(|filesystem| self.read_only.lock().contains(&filesystem_key(&filesystem)))
+    }
+
+    /// Removes all of the mounts and returns their filesystems.
+    fn unmount_all(&self) -> Vec<Arc<dyn FileSystem>> {
+        mem::take(&mut *self.mounts.lock())
+            .into_values()
+            .map(|mount_point| mount_point.filesystem)
+            .collect()
+    }
+
     fn find_mount(&self, dir: &DirCacheItem) -> Result<MountPoint> {
-        let this = self.0.lock();
+        let this = self.mounts.lock();
         let cache_key = dir.cache_key();
 
         if let Some(mount_point) = this.get(&cache_key) {
@@ -115,10 +192,59 @@ impl MountManager {
     }
 }
 
+/// Returns the address of the filesystem. The vtable of the `dyn` pointer is left out, as it may
+/// differ between codegen units.
+fn filesystem_key(filesystem: &Weak<dyn FileSystem>) -> usize {
+    filesystem.as_ptr().cast::<()>() as usize
+}
+
+/// Returns `ReadOnly` if `inode` is on a filesystem that is mounted read-only. Device nodes and
+/// sockets can still be written to, as that does not modify the filesystem.
+pub fn check_writable(inode: &INodeCacheItem) -> Result<()> {
+    if !MOUNT_MANAGER.is_read_only(inode) {
+        return Ok(());
+    }
+
+    match inode.metadata()?.file_type() {
+        FileType::Device | FileType::Socket => Ok(()),
+        _ => Err(FileSystemError::ReadOnly),
+    }
+}
+
 pub trait FileSystem: Send + Sync {
     fn root_dir(&self) -> DirCacheItem {
         todo!()
     }
+
+    /// Writes the metadata the filesystem keeps in memory back to its device. The data is written
+    /// through the page cache, which has to be synced afterwards.
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns information about the filesystem (`statfs`). Filesystems that are not backed by
+    /// storage report no blocks.
+    fn stat(&self) -> Result<StatFs> {
+        Ok(empty_statfs())
+    }
+}
+
+fn empty_statfs() -> StatFs {
+    StatFs {
+        f_bsize: Size4KiB::SIZE,
+        f_frsize: Size4KiB::SIZE,
+        f_namelen: NAME_MAX as u64,
+        ..Default::default()
+    }
+}
+
+/// Returns information about the filesystem that `inode` is on. Inodes that are not on a
+/// filesystem, such as pipes and sockets, report no blocks.
+pub fn statfs(inode: &INodeCacheItem) -> Result<StatFs> {
+    match inode.weak_filesystem().and_then(|fs| fs.upgrade()) {
+        Some(filesystem) => filesystem.stat(),
+        None => Ok(empty_statfs()),
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -138,6 +264,20 @@ pub enum FileSystemError {
     NotConnected,
     WouldBlock,
     NoTty,
+    Fault,
+    CrossDevice,
+    Loop,
+    InvalidInput,
+    OutOfMemory,
+    Io,
+    AddressInUse,
+    NotPermitted,
+    NameTooLong,
+    NoDevice,
+    NoSpace,
+    NotEmpty,
+    FileTooLarge,
+    ReadOnly,
 }
 
 impl From<FileSystemError> for SyscallError {
@@ -158,6 +298,20 @@ impl From<FileSystemError> for SyscallError {
             FileSystemError::NotConnected => Self::ENOTCONN,
             FileSystemError::WouldBlock => Self::EAGAIN,
             FileSystemError::NoTty => Self::ENOTTY,
+            FileSystemError::Fault => Self::EFAULT,
+            FileSystemError::CrossDevice => Self::EXDEV,
+            FileSystemError::Loop => Self::ELOOP,
+            FileSystemError::InvalidInput => Self::EINVAL,
+            FileSystemError::OutOfMemory => Self::ENOMEM,
+            FileSystemError::Io => Self::EIO,
+            FileSystemError::AddressInUse => Self::EADDRINUSE,
+            FileSystemError::NotPermitted => Self::EPERM,
+            FileSystemError::NameTooLong => Self::ENAMETOOLONG,
+            FileSystemError::NoDevice => Self::ENXIO,
+            FileSystemError::NoSpace => Self::ENOSPC,
+            FileSystemError::NotEmpty => Self::ENOTEMPTY,
+            FileSystemError::FileTooLarge => Self::EFBIG,
+            FileSystemError::ReadOnly => Self::EROFS,
         }
     }
 }
@@ -165,101 +319,208 @@ impl From<FileSystemError> for SyscallError {
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum LookupMode {
     None,
-    /// Creates the file if it does not exist.
-    Create,
+    /// Creates the file with the given mode if it does not exist. The mode is used as is, so
+    /// the umask must already have been applied to it.
+    Create(Mode),
 }
 
 pub fn lookup_path_with(
-    mut cwd: DirCacheItem,
+    cwd: DirCacheItem,
     path: &Path,
     mode: LookupMode,
     resolve_last: bool,
 ) -> Result<DirCacheItem> {
-    let components_len = path.components().count();
-
-    // Iterate and resolve each component. For example `a`, `b`, and `c` in `a/b/c`.
-    for (i, component) in path.components().enumerate() {
-        match component {
-            // Handle some special cases that might occur in a relative path.
-            "." => continue,
-            ".." => {
-                let current = cwd.data.lock();
-
-                if let Some(parent) = current.parent.clone() {
-                    core::mem::drop(current); // drop the data lock.
-                    cwd = parent;
+    lookup_path_resolve(cwd, path, mode, resolve_last, ResolveFlags::empty())
+}
+
+/// Same as [`lookup_path_with`], but with the path resolution restricted by `resolve`. See
+/// `openat2(2)` for the meaning of each of the flags.
+///
+/// ## Errors
+/// * [`FileSystemError::NameTooLong`]: The path, or the target of a symbolic link, is longer than
+///   [`PATH_MAX`], or one of their components is longer than [`NAME_MAX`].
+/// * [`FileSystemError::Loop`]: More than [`MAX_SYMLINKS`] symbolic links were followed.
+pub fn lookup_path_resolve(
+    cwd: DirCacheItem,
+    path: &Path,
+    mode: LookupMode,
+    resolve_last: bool,
+    resolve: ResolveFlags,
+) -> Result<DirCacheItem> {
+    if resolve.contains(ResolveFlags::BENEATH) && path.is_absolute() {
+        return Err(FileSystemError::CrossDevice);
+    }
+
+    let walker = PathWalker {
+        start: cwd.clone(),
+        resolve,
+        symlinks: Cell::new(0),
+    };
+
+    walker.walk(cwd, path, mode, resolve_last)
+}
+
+struct PathWalker {
+    /// The directory the lookup started at. With `RESOLVE_BENEATH`, the lookup is not allowed to
+    /// leave this directory.
+    start: DirCacheItem,
+    resolve: ResolveFlags,
+    /// Number of symbolic links followed so far, including the ones in the targets of other
+    /// symbolic links. It bounds the lookup of a path with a cycle of symbolic links.
+    symlinks: Cell<usize>,
+}
+
+impl PathWalker {
+    fn walk(
+        &self,
+        mut cwd: DirCacheItem,
+        path: &Path,
+        mode: LookupMode,
+        resolve_last: bool,
+    ) -> Result<DirCacheItem> {
+        if path.as_str().len() >= PATH_MAX {
+            return Err(FileSystemError::NameTooLong);
+        }
+
+        let components_len = path.components().count();
+
+        // Iterate and resolve each component. For example `a`, `b`, and `c` in `a/b/c`.
+        for (i, component) in path.components().enumerate() {
+            match component {
+                // Handle some special cases that might occur in a relative path.
+                "." => continue,
+                ".." => {
+                    if self.resolve.contains(ResolveFlags::BENEATH)
+                        && Arc::ptr_eq(&*cwd, &*self.start)
+                    {
+                        return Err(FileSystemError::CrossDevice);
+                    }
+
+                    // The parent of a mount's root directory lives on the filesystem it is
+                    // mounted on.
+                    if self.resolve.contains(ResolveFlags::NO_XDEV)
+                        && MOUNT_MANAGER.is_mount_root(&cwd)
+                    {
+                        return Err(FileSystemError::CrossDevice);
+                    }
+
+                    let current = cwd.data.lock();
+
+                    if let Some(parent) = current.parent.clone() {
+                        core::mem::drop(current); // drop the data lock.
+                        cwd = parent;
+                    }
+
+                    // Else the entry does not have a parent, ie. the current entry is the root
+                    // and we can't go any further :^)
                 }
 
-                // Else the entry does not have a parent, ie. the current entry is the root and
-                // we can't go any further :^)
-            }
+                _ if component.len() > NAME_MAX => return Err(FileSystemError::NameTooLong),
+
+                _ => {
+                    // After we have resolved all of the special cases that might occur in a
+                    // path, now we have to resolve the directory entry itself. For example `a`
+                    // in `./a/`.
+                    let cache_entry = inode::fetch_dir_entry(&cwd, String::from(component));
+                    let parent = cwd.clone();
+                    let is_last = i == components_len - 1;
+
+                    if let Some(entry) = cache_entry {
+                        cwd = entry;
+                    } else {
+                        match (cwd.inode().lookup(cwd.clone(), component), mode) {
+                            (Ok(entry), _) => cwd = entry,
+
+                            (Err(FileSystemError::EntryNotFound), LookupMode::Create(mode)) => {
+                                check_writable(&cwd.inode())?;
+
+                                if is_last {
+                                    cwd = cwd.inode().touch(cwd.clone(), component, mode)?;
+                                    inotify::notify_entry(
+                                        &parent.inode(),
+                                        component,
+                                        InotifyMask::CREATE,
+                                        0,
+                                    );
+                                } else {
+                                    // todo: fix this shit
+                                    let dir_mode = scheduler::current_thread()
+                                        .creation_mode(Mode::from_bits_truncate(0o777));
+
+                                    cwd.inode().mkdir(component, dir_mode)?;
+                                    inotify::notify_entry(
+                                        &parent.inode(),
+                                        component,
+                                        InotifyMask::CREATE | InotifyMask::ISDIR,
+                                        0,
+                                    );
+
+                                    cwd = match self.walk(
+                                        cwd.clone(),
+                                        Path::new(component),
+                                        LookupMode::None,
+                                        resolve_last,
+                                    ) {
+                                        Ok(x) => x,
+                                        Err(e) => {
+                                            dbg!(component, cwd.absolute_path());
+                                            return Err(dbg!(e));
+                                        }
+                                    };
+                                }
+                            }
 
-            _ => {
-                // After we have resolved all of the special cases that might occur in a path, now
-                // we have to resolve the directory entry itself. For example `a` in `./a/`.
-                let cache_entry = inode::fetch_dir_entry(&cwd, String::from(component));
-                let parent = cwd.clone();
-
-                if let Some(entry) = cache_entry {
-                    cwd = entry;
-                } else {
-                    match cwd.inode().lookup(cwd.clone(), component) {
-                        Ok(entry) => cwd = entry,
-
-                        Err(err)
-                            if err == FileSystemError::EntryNotFound
-                                && mode == LookupMode::Create =>
+                            (Err(err), _) => return Err(err),
+                        }
+                    }
+
+                    let inode = cwd.inode();
+                    let metadata = inode.metadata()?;
+
+                    // Symbolic links in the middle of the path are always followed, only the
+                    // trailing component depends on `resolve_last`.
+                    if metadata.is_symlink() && (resolve_last || !is_last) {
+                        if self.resolve.contains(ResolveFlags::NO_SYMLINKS)
+                            || self.symlinks.get() == MAX_SYMLINKS
                         {
-                            if i == components_len - 1 {
-                                cwd = cwd.inode().touch(cwd.clone(), component)?;
-                            } else {
-                                // todo: fix this shit
-                                cwd.inode().mkdir(component)?;
-                                cwd = match lookup_path_with(
-                                    cwd.clone(),
-                                    Path::new(component),
-                                    LookupMode::None,
-                                    resolve_last,
-                                ) {
-                                    Ok(x) => x,
-                                    Err(e) => {
-                                        dbg!(component, cwd.absolute_path());
-                                        return Err(dbg!(e));
-                                    }
-                                };
-                            }
+                            return Err(FileSystemError::Loop);
                         }
 
-                        Err(err) => return Err(err),
-                    }
-                }
+                        self.symlinks.set(self.symlinks.get() + 1);
 
-                let inode = cwd.inode();
-                let metadata = inode.metadata()?;
+                        let resolved_path = inode.resolve_link()?;
 
-                if metadata.is_symlink() && resolve_last {
-                    let resolved_path = inode.resolve_link()?;
+                        let start = if resolved_path.is_absolute() {
+                            let root = root_dir();
 
-                    cwd = lookup_path_with(
-                        if resolved_path.is_absolute() {
-                            root_dir().clone()
+                            if self.resolve.contains(ResolveFlags::BENEATH)
+                                || (self.resolve.contains(ResolveFlags::NO_XDEV)
+                                    && !root.is_same_filesystem(&cwd))
+                            {
+                                return Err(FileSystemError::CrossDevice);
+                            }
+
+                            root.clone()
                         } else {
                             parent
-                        },
-                        resolved_path.as_ref(),
-                        LookupMode::None,
-                        resolve_last,
-                    )?;
-                } else if metadata.is_directory() {
-                    if let Ok(mount_point) = MOUNT_MANAGER.find_mount(&cwd) {
-                        cwd = mount_point.root_entry;
+                        };
+
+                        cwd = self.walk(start, resolved_path.as_ref(), LookupMode::None, true)?;
+                    } else if metadata.is_directory() {
+                        if let Ok(mount_point) = MOUNT_MANAGER.find_mount(&cwd) {
+                            if self.resolve.contains(ResolveFlags::NO_XDEV) {
+                                return Err(FileSystemError::CrossDevice);
+                            }
+
+                            cwd = mount_point.root_entry;
+                        }
                     }
                 }
             }
         }
-    }
 
-    Ok(cwd)
+        Ok(cwd)
+    }
 }
 
 pub fn lookup_path(path: &Path) -> Result<DirCacheItem> {
@@ -281,3 +542,23 @@ pub fn init() -> Result<()> {
     cache::init();
     Ok(())
 }
+
+/// Syncs and unmounts all of the filesystems, and writes all of the dirty pages back to their
+/// devices, before the system is powered off or restarted.
+pub fn shutdown() {
+    let mut filesystems = MOUNT_MANAGER.unmount_all();
+    filesystems.extend(ROOT_FS.get().cloned());
+
+    // The inodes are written back from the inode cache, so it is only cleared afterwards.
+    for filesystem in filesystems {
+        if let Err(err) = filesystem.sync() {
+            log::error!("fs: failed to sync a filesystem: {err:?}");
+        }
+    }
+
+    cache::dcache().log();
+
+    cache::clear_inode_cache();
+    cache::clear_dir_cache();
+    block::sync();
+}
@@ -0,0 +1,220 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! POSIX message queues, opened by name with `mq_open`.
+//!
+//! The queues are registered in a global table until their name is removed with `mq_unlink`, and
+//! live on as long as a file descriptor refers to them.
+
+use core::cmp::Reverse;
+
+use aero_syscall::{MqAttr, SyscallError, MQ_PRIO_MAX};
+use alloc::collections::{BTreeMap, BinaryHeap};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::inode::{INodeInterface, PollFlags, PollTable};
+use crate::ipc;
+use crate::utils::sync::{Mutex, WaitQueue};
+
+/// Limits of the queues created without attributes.
+const DEFAULT_MAXMSG: usize = 10;
+const DEFAULT_MSGSIZE: usize = 8192;
+
+/// Upper bounds of the limits a queue can be created with.
+const MAXMSG_MAX: usize = 65536;
+const MSGSIZE_MAX: usize = 16 * 1024 * 1024;
+
+static QUEUES: Mutex<BTreeMap<String, Arc<MessageQueue>>> = Mutex::new(BTreeMap::new());
+
+struct Inner {
+    /// The messages ordered by priority. Messages of the same priority are ordered by their
+    /// sequence number, so they are received in the order they were sent.
+    messages: BinaryHeap<(u32, Reverse<u64>, Vec<u8>)>,
+    next_seq: u64,
+}
+
+pub struct MessageQueue {
+    inner: Mutex<Inner>,
+    wq: WaitQueue,
+    maxmsg: usize,
+    msgsize: usize,
+}
+
+impl MessageQueue {
+    fn new(maxmsg: usize, msgsize: usize) -> Arc<Self> {
+        Arc::new(Self {
+            inner: Mutex::new(Inner {
+                messages: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+            wq: WaitQueue::new(),
+            maxmsg,
+            msgsize,
+        })
+    }
+
+    /// Adds `message` to the queue, blocking while the queue is full unless `nonblock` is set.
+    ///
+    /// ## Errors
+    /// * `EMSGSIZE`: `message` is larger than the message size of the queue.
+    /// * `EINVAL`: `priority` is not below [`MQ_PRIO_MAX`].
+    /// * `EAGAIN`: The queue is full and `nonblock` is set.
+    pub fn send(&self, message: &[u8], priority: u32, nonblock: bool) -> Result<(), SyscallError> {
+        if message.len() > self.msgsize {
+            return Err(SyscallError::EMSGSIZE);
+        }
+
+        if priority >= MQ_PRIO_MAX {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let mut inner = if nonblock {
+            let inner = self.inner.lock_irq();
+
+            if inner.messages.len() >= self.maxmsg {
+                return Err(SyscallError::EAGAIN);
+            }
+
+            inner
+        } else {
+            self.wq
+                .block_on(&self.inner, |inner| inner.messages.len() < self.maxmsg)?
+        };
+
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        inner
+            .messages
+            .push((priority, Reverse(seq), message.to_vec()));
+
+        core::mem::drop(inner);
+        self.wq.notify_all();
+        Ok(())
+    }
+
+    /// Removes the oldest message of the highest priority from the queue, blocking while the
+    /// queue is empty unless `nonblock` is set. Returns the message and its priority.
+    ///
+    /// ## Errors
+    /// * `EMSGSIZE`: `max_size` is smaller than the message size of the queue.
+    /// * `EAGAIN`: The queue is empty and `nonblock` is set.
+    pub fn receive(&self, max_size: usize, nonblock: bool) -> Result<(Vec<u8>, u32), SyscallError> {
+        if max_size < self.msgsize {
+            return Err(SyscallError::EMSGSIZE);
+        }
+
+        let mut inner = if nonblock {
+            let inner = self.inner.lock_irq();
+
+            if inner.messages.is_empty() {
+                return Err(SyscallError::EAGAIN);
+            }
+
+            inner
+        } else {
+            self.wq
+                .block_on(&self.inner, |inner| !inner.messages.is_empty())?
+        };
+
+        let (priority, _, message) = inner.messages.pop().unwrap();
+
+        core::mem::drop(inner);
+        self.wq.notify_all();
+        Ok((message, priority))
+    }
+}
+
+impl INodeInterface for MessageQueue {
+    fn poll(&self, table: Option<&mut PollTable>) -> super::Result<PollFlags> {
+        let inner = self.inner.lock_irq();
+        let mut events = PollFlags::empty();
+
+        if let Some(table) = table {
+            table.insert(&self.wq);
+        }
+
+        if !inner.messages.is_empty() {
+            events.insert(PollFlags::IN);
+        }
+
+        if inner.messages.len() < self.maxmsg {
+            events.insert(PollFlags::OUT);
+        }
+
+        Ok(events)
+    }
+}
+
+/// Returns the queue `name`. If it does not exist and `create` is set, it is created with the
+/// limits in `attr`, or the default ones if it is `None`.
+///
+/// ## Errors
+/// * `ENOENT`: The queue does not exist and `create` is not set.
+/// * `EEXIST`: The queue exists and both `create` and `exclusive` are set.
+/// * `EINVAL`: The queue is created and a limit in `attr` is out of range.
+pub fn open(
+    name: &str,
+    create: bool,
+    exclusive: bool,
+    attr: Option<&MqAttr>,
+) -> Result<Arc<MessageQueue>, SyscallError> {
+    let name = ipc::object_name(name)?;
+    let mut queues = QUEUES.lock_irq();
+
+    if let Some(queue) = queues.get(name) {
+        if create && exclusive {
+            return Err(SyscallError::EEXIST);
+        }
+
+        return Ok(queue.clone());
+    }
+
+    if !create {
+        return Err(SyscallError::ENOENT);
+    }
+
+    let (maxmsg, msgsize) = match attr {
+        Some(attr) => (attr.mq_maxmsg as usize, attr.mq_msgsize as usize),
+        None => (DEFAULT_MAXMSG, DEFAULT_MSGSIZE),
+    };
+
+    // Negative limits are out of range as well, as they wrap around.
+    if !(1..=MAXMSG_MAX).contains(&maxmsg) || !(1..=MSGSIZE_MAX).contains(&msgsize) {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let queue = MessageQueue::new(maxmsg, msgsize);
+    queues.insert(String::from(name), queue.clone());
+
+    Ok(queue)
+}
+
+/// Removes the name of the queue `name`.
+///
+/// ## Errors
+/// * `ENOENT`: The queue does not exist.
+pub fn unlink(name: &str) -> Result<(), SyscallError> {
+    let name = ipc::object_name(name)?;
+
+    QUEUES
+        .lock_irq()
+        .remove(name)
+        .map(|_| ())
+        .ok_or(SyscallError::ENOENT)
+}
@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use core::fmt::Write;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 use aero_syscall::OpenFlags;
@@ -37,6 +38,8 @@ pub struct Pipe {
 
     /// The number of writers currently connected to the pipe.
     num_writers: AtomicUsize,
+    /// The number of readers currently connected to the pipe.
+    num_readers: AtomicUsize,
 
     handle: Once<Arc<FileHandle>>,
 }
@@ -50,6 +53,7 @@ impl Pipe {
             writers: WaitQueue::new(),
 
             num_writers: AtomicUsize::new(0),
+            num_readers: AtomicUsize::new(0),
 
             handle: Once::new(),
         })
@@ -67,6 +71,8 @@ impl INodeInterface for Pipe {
         if handle.flags().contains(OpenFlags::O_WRONLY) {
             self.num_writers.fetch_add(1, Ordering::SeqCst);
             self.handle.call_once(|| handle);
+        } else {
+            self.num_readers.fetch_add(1, Ordering::SeqCst);
         }
 
         Ok(None)
@@ -81,6 +87,8 @@ impl INodeInterface for Pipe {
             if active_writers == 0 {
                 self.readers.notify_all();
             }
+        } else {
+            self.num_readers.fetch_sub(1, Ordering::SeqCst);
         }
     }
 
@@ -92,9 +100,12 @@ impl INodeInterface for Pipe {
             return Err(FileSystemError::WouldBlock);
         }
 
-        let mut buffer = self.readers.block_on(&self.queue, |lock| {
-            lock.has_data() || self.active_writers() == 0
-        })?;
+        let mut buffer = self
+            .readers
+            .block_on_interruptible(&self.queue, |lock| {
+                lock.has_data() || self.active_writers() == 0
+            })
+            .map_err(|_| FileSystemError::Interrupted)?;
 
         let read = buffer.read_data(buf);
 
@@ -127,4 +138,14 @@ impl INodeInterface for Pipe {
 
         Ok(flags)
     }
+
+    fn fdinfo(&self, out: &mut String) {
+        let _ = writeln!(out, "pipe_size:\t{}", self.queue.lock_irq().data.len());
+        let _ = writeln!(
+            out,
+            "pipe_readers:\t{}",
+            self.num_readers.load(Ordering::SeqCst)
+        );
+        let _ = writeln!(out, "pipe_writers:\t{}", self.active_writers());
+    }
 }
@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use core::fmt::Write;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 use alloc::borrow::ToOwned;
@@ -25,10 +26,19 @@ use alloc::sync::{Arc, Weak};
 use spin::{Once, RwLock};
 
 use crate::fs;
+use crate::fs::file_table::FileHandle;
 use crate::fs::inode::FileType;
 
+use crate::arch::task::USERLAND_STACK_TOP;
 use crate::arch::tls;
+use crate::drivers::block::zram;
+use crate::mem::paging::VirtAddr;
+use crate::net;
+use crate::syscall::ipc;
 use crate::userland::scheduler;
+use crate::userland::task::{StopReason, Task, TaskId, TaskState};
+use crate::userland::vm::{Mapping, Vm, VmFlag};
+use crate::utils::sync::Mutex;
 
 use super::cache::*;
 use super::{cache, FileSystem, Path, MOUNT_MANAGER};
@@ -39,12 +49,6 @@ use super::FileSystemError;
 // TODO: put this mf in prelude
 use alloc::vec;
 
-fn push_string_if_some(map: &mut serde_json::Value, key: &str, value: Option<String>) {
-    if let Some(value) = value {
-        map[key] = serde_json::Value::String(value);
-    }
-}
-
 fn get_cmdline_cached() -> &'static str {
     static CACHED: Once<String> = Once::new();
 
@@ -58,45 +62,53 @@ fn get_cmdline_cached() -> &'static str {
     })
 }
 
-fn get_cpuinfo_cached() -> &'static str {
-    static CACHED: Once<String> = Once::new();
-
-    CACHED.call_once(|| {
-        use serde_json::*;
-
-        let mut data = json!({ "processors": [] });
-
-        if let Some(processors) = data
-            .get_mut("processors")
-            .and_then(|processors| processors.as_array_mut())
-        {
-            let mut cpu_info = vec![];
-
-            #[cfg(target_arch = "x86_64")]
-            tls::for_cpu_info_cached(|info| {
-                let mut processor = json!({});
-
-                processor["id"] = Value::Number(Number::from(info.cpuid));
-                processor["fpu"] = Value::Bool(info.fpu);
-
-                push_string_if_some(&mut processor, "brand", info.brand.clone());
-                push_string_if_some(&mut processor, "vendor", info.vendor.clone());
+/// Renders `/proc/cpuinfo` in the format of Linux, with one block per online CPU. It is not
+/// cached as CPUs may come online after it was first read.
+fn render_cpuinfo() -> String {
+    let mut out = String::new();
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        let khz = crate::arch::time::tsc_khz();
+        let mut cpus = vec![];
+
+        tls::for_cpu_info_cached(|info| {
+            let mut block = String::new();
+
+            writeln!(block, "processor\t: {}", info.cpuid).unwrap();
+            writeln!(
+                block,
+                "vendor_id\t: {}",
+                info.vendor.as_deref().unwrap_or("unknown")
+            )
+            .unwrap();
+            writeln!(block, "cpu family\t: {}", info.family).unwrap();
+            writeln!(block, "model\t\t: {}", info.model).unwrap();
+            writeln!(
+                block,
+                "model name\t: {}",
+                info.brand.as_deref().unwrap_or("unknown")
+            )
+            .unwrap();
+            writeln!(block, "stepping\t: {}", info.stepping).unwrap();
+            writeln!(block, "cpu MHz\t\t: {}.{:03}", khz / 1000, khz % 1000).unwrap();
+            writeln!(block, "apicid\t\t: {}", info.apic_id).unwrap();
+            writeln!(block, "fpu\t\t: {}", if info.fpu { "yes" } else { "no" }).unwrap();
+            writeln!(block, "flags\t\t: {}", info.features.join(" ")).unwrap();
 
-                processor["features"] = Value::Array(
-                    info.features
-                        .iter()
-                        .map(|feature| Value::String(feature.to_string()))
-                        .collect(),
-                );
+            cpus.push((info.cpuid, block));
+        });
 
-                cpu_info.push(processor);
-            });
+        // CPUs are listed in the order they came online.
+        cpus.sort_by_key(|(cpuid, _)| *cpuid);
 
-            *processors = cpu_info;
+        for (_, block) in cpus {
+            out.push_str(&block);
+            out.push('\n');
         }
+    }
 
-        data.to_string()
-    })
+    out
 }
 
 #[derive(Default)]
@@ -113,7 +125,39 @@ struct ProcINode {
 enum FileContents {
     CpuInfo,
     CmdLine,
-    SelfMaps,
+    /// `/proc/blockstat`, the block layer request merging and readahead counters.
+    BlockStat,
+    /// `/proc/zram`, the statistics of the `zram0` device. Only created if the device exists.
+    Zram,
+    /// `/proc/<pid>/maps`, where [`None`] refers to the process that opens the file.
+    Maps(Option<TaskId>),
+    /// `/proc/<pid>/stat`, where [`None`] refers to the process that reads the file.
+    Stat(Option<TaskId>),
+    /// `/proc/<pid>/status`, where [`None`] refers to the process that reads the file.
+    Status(Option<TaskId>),
+    /// `/proc/<pid>/memory.max`, the memory limit of the process. [`None`] refers to the
+    /// process that accesses the file.
+    MemoryMax(Option<TaskId>),
+    /// `/proc/<pid>/fd`, which links to the files opened by the process. [`None`] refers to the
+    /// process that looks up the file.
+    Fds(Option<TaskId>),
+    /// `/proc/<pid>/fdinfo`, which describes the files opened by the process. [`None`] refers
+    /// to the process that looks up the file.
+    FdInfo(Option<TaskId>),
+    /// `/proc/<pid>/fdinfo/<fd>`, where [`None`] refers to the process that reads the file.
+    FdInfoFile(Option<TaskId>, usize),
+    /// `/proc/<pid>/exe`, which opens the executable of the process. [`None`] refers to the
+    /// process that opens the file.
+    Exe(Option<TaskId>),
+    /// `/proc/<pid>/ipc`, the messages queued for the process by `sys_ipc_send`. [`None`]
+    /// refers to the process that reads the file.
+    Ipc(Option<TaskId>),
+    /// `/proc/sys/ipc_trace`, whether the IPC messages are logged (`0` or `1`).
+    IpcTrace,
+    /// `/proc/net/dev`, the network interfaces.
+    NetDev,
+    /// The root directory, which also contains a directory for every process.
+    Root,
 
     None,
 }
@@ -152,13 +196,28 @@ impl LockedProcINode {
         file_type: FileType,
         contents: FileContents,
     ) -> fs::Result<INodeCacheItem> {
-        let icache = cache::icache();
         let mut this = self.0.write();
 
         if this.children.contains_key(name) || ["", ".", ".."].contains(&name) {
             return Err(FileSystemError::EntryExists);
         }
 
+        let inode_cached = Self::alloc_child(&this, file_type, contents);
+
+        this.children
+            .insert(String::from(name), inode_cached.clone());
+
+        Ok(inode_cached)
+    }
+
+    /// Allocates a new inode with `this` as its parent, without linking it into `this`
+    /// directory.
+    fn alloc_child(
+        this: &ProcINode,
+        file_type: FileType,
+        contents: FileContents,
+    ) -> INodeCacheItem {
+        let icache = cache::icache();
         let filesystem = this.filesystem.upgrade().unwrap();
 
         let inode = filesystem.allocate_inode(file_type, contents);
@@ -175,10 +234,73 @@ impl LockedProcINode {
                 file_type,
             );
 
-        this.children
-            .insert(String::from(name), inode_cached.clone());
+        inode_cached
+    }
 
-        Ok(inode_cached)
+    /// Creates the `/proc/<pid>` directory of the process with the given `pid`.
+    fn make_process_dir(this: &ProcINode, pid: TaskId) -> fs::Result<INodeCacheItem> {
+        let dir = Self::alloc_child(this, FileType::Directory, FileContents::None);
+        let dir_inode = dir.inner().downcast_arc::<LockedProcINode>().unwrap();
+
+        dir_inode.make_inode("maps", FileType::File, FileContents::Maps(Some(pid)))?;
+        dir_inode.make_inode("stat", FileType::File, FileContents::Stat(Some(pid)))?;
+        dir_inode.make_inode("status", FileType::File, FileContents::Status(Some(pid)))?;
+        dir_inode.make_inode(
+            "memory.max",
+            FileType::File,
+            FileContents::MemoryMax(Some(pid)),
+        )?;
+        dir_inode.make_inode("fd", FileType::Directory, FileContents::Fds(Some(pid)))?;
+        dir_inode.make_inode(
+            "fdinfo",
+            FileType::Directory,
+            FileContents::FdInfo(Some(pid)),
+        )?;
+        dir_inode.make_inode("exe", FileType::File, FileContents::Exe(Some(pid)))?;
+        dir_inode.make_inode("ipc", FileType::File, FileContents::Ipc(Some(pid)))?;
+        Ok(dir)
+    }
+
+    /// Returns the entry at `index` of the directory contents of `this` that are not linked into
+    /// it. The open files and the processes are listed without caching their entries, as both
+    /// come and go.
+    fn dynamic_dirent(this: &ProcINode, index: usize) -> fs::Result<Option<DirCacheItem>> {
+        let entry = match this.contents {
+            FileContents::Fds(pid) => {
+                let handles = find_task(pid)?.file_table.handles();
+
+                handles.get(index).map(|handle| {
+                    let inode = handle.inode.inode().inner().clone();
+                    DirEntry::from_inode(inode, handle.fd.to_string())
+                })
+            }
+
+            FileContents::FdInfo(pid) => {
+                let handles = find_task(pid)?.file_table.handles();
+
+                handles.get(index).map(|handle| {
+                    let contents = FileContents::FdInfoFile(pid, handle.fd);
+                    let child = Self::alloc_child(this, FileType::File, contents);
+
+                    DirEntry::from_inode(child.inner().clone(), handle.fd.to_string())
+                })
+            }
+
+            FileContents::Root => {
+                let mut pids = vec![];
+                scheduler::get_scheduler().for_each_task(|task| pids.push(task.pid().as_usize()));
+                pids.sort_unstable();
+
+                pids.get(index).map(|pid| {
+                    let child = Self::alloc_child(this, FileType::Directory, FileContents::None);
+                    DirEntry::from_inode(child.inner().clone(), pid.to_string())
+                })
+            }
+
+            _ => None,
+        };
+
+        Ok(entry)
     }
 }
 
@@ -187,44 +309,162 @@ impl INodeInterface for LockedProcINode {
         let this = self.0.read();
 
         let data = match &this.contents {
-            FileContents::CpuInfo => Ok(get_cpuinfo_cached().to_owned()),
+            FileContents::CpuInfo => Ok(render_cpuinfo()),
             FileContents::CmdLine => Ok(get_cmdline_cached().to_owned()),
-
-            FileContents::SelfMaps => {
-                let current_thread = scheduler::current_thread();
-                let mut result = serde_json::json!({ "maps": [] });
-                let maps = result.get_mut("maps").unwrap().as_array_mut().unwrap();
-
-                current_thread.vm().for_each_mapping(|map| {
-                    maps.push(serde_json::json!({
-                        "start": map.start_addr.as_u64(),
-                        "end": map.end_addr.as_u64(),
-                        // "flags": map.flags.bits(),
-                        // do we need to tell if is shared?
-                        "protection": map.protection().bits(),
-                    }));
-                });
-
-                Ok(result.to_string())
+            FileContents::BlockStat => Ok(fs::block::queue::STATS.render()),
+            FileContents::Zram => Ok(zram::get().unwrap().stats().render()),
+            FileContents::Stat(pid) => Ok(render_stat(&find_task(*pid)?)),
+            FileContents::Status(pid) => Ok(render_status(&find_task(*pid)?)),
+            FileContents::MemoryMax(pid) => Ok(match find_task(*pid)?.vm().memory_limit() {
+                Some(limit) => alloc::format!("{limit}\n"),
+                None => String::from("max\n"),
+            }),
+            FileContents::Ipc(pid) => Ok(find_task(*pid)?.message_queue.render()),
+            FileContents::IpcTrace => Ok(alloc::format!("{}\n", ipc::is_tracing() as u8)),
+            FileContents::NetDev => Ok(render_net_dev()),
+            FileContents::FdInfoFile(pid, fd) => {
+                let handle = find_task(*pid)?
+                    .file_table
+                    .get_handle(*fd)
+                    .ok_or(FileSystemError::EntryNotFound)?;
+
+                Ok(render_fdinfo(&handle))
             }
 
             _ => Err(FileSystemError::NotSupported),
         }?;
 
-        let count = core::cmp::min(buffer.len(), data.len() - offset);
-        buffer[..count].copy_from_slice(&data.as_bytes()[offset..offset + count]);
+        // The contents are generated on every read and may have shrunk since the last one.
+        let data = data.as_bytes().get(offset..).unwrap_or_default();
+
+        let count = core::cmp::min(buffer.len(), data.len());
+        buffer[..count].copy_from_slice(&data[..count]);
 
         Ok(count)
     }
 
+    fn write_at(&self, _offset: usize, buffer: &[u8]) -> fs::Result<usize> {
+        let this = self.0.read();
+
+        let value = core::str::from_utf8(buffer)
+            .map_err(|_| FileSystemError::InvalidInput)?
+            .trim();
+
+        if let FileContents::IpcTrace = this.contents {
+            match value {
+                "0" => ipc::set_trace(false),
+                "1" => ipc::set_trace(true),
+                _ => return Err(FileSystemError::InvalidInput),
+            }
+
+            return Ok(buffer.len());
+        }
+
+        let FileContents::MemoryMax(pid) = this.contents else {
+            return Err(FileSystemError::NotSupported);
+        };
+
+        // Either a number of bytes or `max` to remove the limit.
+
+        let limit = match value {
+            "max" => None,
+            bytes => Some(
+                bytes
+                    .parse::<usize>()
+                    .map_err(|_| FileSystemError::InvalidInput)?,
+            ),
+        };
+
+        find_task(pid)?.vm().set_memory_limit(limit);
+        Ok(buffer.len())
+    }
+
     fn lookup(&self, dir: DirCacheItem, name: &str) -> fs::Result<DirCacheItem> {
         let this = self.0.read();
-        let child = this
-            .children
-            .get(name)
-            .ok_or(FileSystemError::EntryNotFound)?;
 
-        Ok(DirEntry::new(dir, child.clone(), String::from(name)))
+        if let Some(child) = this.children.get(name) {
+            return Ok(DirEntry::new(dir, child.clone(), String::from(name)));
+        }
+
+        // Process directories are created on demand instead of being linked into the root
+        // directory, as processes come and go.
+        if let FileContents::Root = this.contents {
+            let pid = name
+                .parse::<usize>()
+                .map_err(|_| FileSystemError::EntryNotFound)?;
+
+            let task = scheduler::get_scheduler()
+                .find_task(TaskId::new(pid))
+                .ok_or(FileSystemError::EntryNotFound)?;
+
+            let child = Self::make_process_dir(&this, task.pid())?;
+            return Ok(DirEntry::new(dir, child, String::from(name)));
+        }
+
+        // `/proc/<pid>/fd/<fd>` resolves to the directory entry of the open file itself, so
+        // opening it opens the same file (e.g. a memfd) again. The entries are not cached as
+        // file descriptors get reused.
+        //
+        // TODO: Only allow the owner of the process to look up its files.
+        if let FileContents::Fds(pid) = this.contents {
+            let fd = name
+                .parse::<usize>()
+                .map_err(|_| FileSystemError::EntryNotFound)?;
+
+            let handle = find_task(pid)?
+                .file_table
+                .get_handle(fd)
+                .ok_or(FileSystemError::EntryNotFound)?;
+
+            return Ok(handle.inode.clone());
+        }
+
+        // TODO: Only allow the owner of the process (or root) to read the state of its files,
+        // once tasks have credentials.
+        if let FileContents::FdInfo(pid) = this.contents {
+            let fd = name
+                .parse::<usize>()
+                .map_err(|_| FileSystemError::EntryNotFound)?;
+
+            find_task(pid)?
+                .file_table
+                .get_handle(fd)
+                .ok_or(FileSystemError::EntryNotFound)?;
+
+            let contents = FileContents::FdInfoFile(pid, fd);
+            let child = Self::alloc_child(&this, FileType::File, contents);
+
+            return Ok(DirEntry::from_inode(
+                child.inner().clone(),
+                String::from(name),
+            ));
+        }
+
+        Err(FileSystemError::EntryNotFound)
+    }
+
+    fn open(&self, _handle: Arc<FileHandle>) -> fs::Result<Option<DirCacheItem>> {
+        let this = self.0.read();
+
+        match this.contents {
+            FileContents::Maps(pid) => {
+                let task = find_task(pid)?;
+
+                // Every open file description gets its own reading position.
+                let maps = Arc::new(MapsFile::new(task.vm().clone()));
+                Ok(Some(DirEntry::from_inode(maps, String::from("maps"))))
+            }
+
+            // Kernel tasks do not have an executable.
+            FileContents::Exe(pid) => find_task(pid)?
+                .executable
+                .lock()
+                .clone()
+                .map(Some)
+                .ok_or(FileSystemError::EntryNotFound),
+
+            _ => Ok(None),
+        }
     }
 
     fn metadata(&self) -> fs::Result<Metadata> {
@@ -263,11 +503,13 @@ impl INodeInterface for LockedProcINode {
             }
 
             // Subtract two because of the "." and ".." entries.
-            _ => this
+            _ if index - 2 < this.children.len() => this
                 .children
                 .iter()
                 .nth(index - 2)
                 .map(|(name, inode)| DirEntry::new(parent, inode.clone(), name.clone())),
+
+            _ => Self::dynamic_dirent(&this, index - 2 - this.children.len())?,
         })
     }
 
@@ -276,6 +518,253 @@ impl INodeInterface for LockedProcINode {
     }
 }
 
+/// Returns the task with the provided `pid`, or the current task if it is [`None`].
+fn find_task(pid: Option<TaskId>) -> fs::Result<Arc<Task>> {
+    if let Some(pid) = pid {
+        scheduler::get_scheduler()
+            .find_task(pid)
+            .ok_or(FileSystemError::EntryNotFound)
+    } else {
+        Ok(scheduler::current_thread())
+    }
+}
+
+/// Renders the `/proc/<pid>/fdinfo/<fd>` file of the open file `handle`.
+fn render_fdinfo(handle: &FileHandle) -> String {
+    let inode = handle.inode.inode();
+
+    let mut out = alloc::format!(
+        "pos:\t{}\nflags:\t0{:o}\nino:\t{}\npath:\t{}\n",
+        handle.offset.load(Ordering::SeqCst),
+        handle.flags().bits(),
+        inode.metadata().map_or(0, |m| m.id),
+        handle.inode.absolute_path(),
+    );
+
+    inode.fdinfo(&mut out);
+    out
+}
+
+/// Clock ticks per second used for the CPU times in `/proc/<pid>/stat` (`USER_HZ`).
+pub const USER_HZ: u64 = 100;
+
+/// Returns the file name of the executable of `task`.
+fn task_comm(task: &Task) -> String {
+    task.path()
+        .map(|path| path.as_str().rsplit('/').next().unwrap_or("").to_owned())
+        .unwrap_or_else(|| String::from("kernel"))
+}
+
+/// Returns the state of `task` as a `ps(1)` state code.
+fn task_state(task: &Task) -> char {
+    match (task.state(), task.stop_reason()) {
+        (_, Some(StopReason::Signal(_))) => 'T',
+        (_, Some(StopReason::Trace(_))) => 't',
+        (TaskState::Runnable, None) => 'R',
+        (TaskState::AwaitingIo, None) => 'S',
+        (TaskState::Zombie, None) => 'Z',
+    }
+}
+
+/// Renders the `/proc/<pid>/stat` line of `task`. Fields that are not tracked are zero.
+fn render_stat(task: &Task) -> String {
+    let comm = task_comm(task);
+    let state = task_state(task);
+
+    let ticks = |us: u64| us * USER_HZ / 1_000_000;
+    let (utime, stime) = task.sched().cpu_times();
+    let (cutime, cstime) = task.sched().children_cpu_times();
+    let nice = task.sched().nice();
+    let (minflt, cminflt) = task.sched().page_faults();
+
+    // pid (comm) state ppid pgrp session tty_nr tpgid flags minflt cminflt majflt cmajflt
+    // utime stime cutime cstime priority nice num_threads itrealvalue starttime vsize rss
+    alloc::format!(
+        "{} ({comm}) {state} {} {} {} 0 -1 0 {minflt} {cminflt} 0 0 {} {} {} {} {} {nice} 1 0 0 0 0\n",
+        task.pid().as_usize(),
+        task.parent_pid().as_usize(),
+        task.group_id(),
+        task.session_id(),
+        ticks(utime),
+        ticks(stime),
+        ticks(cutime),
+        ticks(cstime),
+        20 + nice,
+    )
+}
+
+/// Renders the `/proc/<pid>/status` file of `task`, a subset of the Linux fields.
+fn render_status(task: &Task) -> String {
+    let vm = task.vm();
+
+    let mut out = alloc::format!(
+        "Name:\t{}\nState:\t{}\nPid:\t{}\nPPid:\t{}\nVmRSS:\t{} kB\n",
+        task_comm(task),
+        task_state(task),
+        task.pid().as_usize(),
+        task.parent_pid().as_usize(),
+        vm.resident_size() / 1024,
+    );
+
+    if let Some(limit) = vm.memory_limit() {
+        let _ = writeln!(out, "VmLimit:\t{} kB", limit / 1024);
+    }
+
+    out
+}
+
+/// Renders the `/proc/net/dev` file in the Linux format. The traffic of the interfaces is not
+/// accounted yet, so all of the counters are zero.
+fn render_net_dev() -> String {
+    let mut out = String::from(
+        "Inter-|   Receive                                                |  Transmit\n \
+         face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs \
+         drop fifo colls carrier compressed\n",
+    );
+
+    for device in net::devices() {
+        let _ = write!(out, "{:>6}:", device.name());
+
+        for _ in 0..16 {
+            out.push_str(" 0");
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Renders a VM area in the format of a `/proc/<pid>/maps` line.
+fn render_mapping(out: &mut String, map: &Mapping) {
+    let protection = map.protection();
+    let flag = |flag: VmFlag, c: char| if protection.contains(flag) { c } else { '-' };
+
+    let (offset, inode, name) = if let Some(file) = map.file.as_ref() {
+        let inode = file.file().inode().metadata().map_or(0, |m| m.id);
+        let name = file.file().absolute_path().to_string();
+
+        (file.offset(), inode, Some(name))
+    } else if (map.start_addr..map.end_addr).contains(&(USERLAND_STACK_TOP - 1u64)) {
+        (0, 0, Some(String::from("[stack]")))
+    } else {
+        (0, 0, None)
+    };
+
+    let start = out.len();
+
+    let _ = write!(
+        out,
+        "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 {}",
+        map.start_addr.as_u64(),
+        map.end_addr.as_u64(),
+        flag(VmFlag::READ, 'r'),
+        flag(VmFlag::WRITE, 'w'),
+        flag(VmFlag::EXEC, 'x'),
+        if map.is_shared() { 's' } else { 'p' },
+        offset,
+        inode,
+    );
+
+    if let Some(name) = name {
+        // Pad the pathname into its own column, like Linux does.
+        let width = out.len() - start;
+        out.extend(core::iter::repeat(' ').take(MAPS_NAME_COLUMN.saturating_sub(width) + 1));
+        out.push_str(&name);
+    }
+
+    out.push('\n');
+}
+
+const MAPS_NAME_COLUMN: usize = 73;
+
+/// An open `/proc/<pid>/maps` file.
+///
+/// The VM areas are rendered lazily and the reading position is remembered as an address, so
+/// areas that are unmapped in between `read()` calls make the output continue from the next area
+/// instead of skipping or repeating lines.
+struct MapsFile {
+    vm: Arc<Vm>,
+    cursor: Mutex<MapsCursor>,
+}
+
+struct MapsCursor {
+    /// Offset within the file of the first byte in `pending`.
+    position: usize,
+    /// Rendered text that has not been read yet.
+    pending: String,
+    /// End address of the last rendered VM area.
+    next_addr: VirtAddr,
+}
+
+impl MapsCursor {
+    fn new() -> Self {
+        Self {
+            position: 0,
+            pending: String::new(),
+            next_addr: VirtAddr::zero(),
+        }
+    }
+}
+
+impl MapsFile {
+    fn new(vm: Arc<Vm>) -> Self {
+        Self {
+            vm,
+            cursor: Mutex::new(MapsCursor::new()),
+        }
+    }
+}
+
+impl INodeInterface for MapsFile {
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> fs::Result<usize> {
+        let mut cursor = self.cursor.lock();
+
+        // Seeking backwards starts over from the first VM area.
+        if offset < cursor.position {
+            *cursor = MapsCursor::new();
+        }
+
+        let wanted = offset + buffer.len();
+
+        // The VM area list is only accessed under its lock, so each line is rendered from a
+        // consistent snapshot of the area.
+        self.vm.for_each_mapping(|map| {
+            if map.end_addr <= cursor.next_addr || cursor.position + cursor.pending.len() >= wanted
+            {
+                return;
+            }
+
+            render_mapping(&mut cursor.pending, map);
+            cursor.next_addr = map.end_addr;
+        });
+
+        let start = offset - cursor.position;
+
+        if start >= cursor.pending.len() {
+            return Ok(0);
+        }
+
+        let count = core::cmp::min(buffer.len(), cursor.pending.len() - start);
+        buffer[..count].copy_from_slice(&cursor.pending.as_bytes()[start..start + count]);
+
+        // Discard the text that has been read.
+        cursor.pending.drain(..start + count);
+        cursor.position = offset + count;
+
+        Ok(count)
+    }
+
+    fn metadata(&self) -> fs::Result<Metadata> {
+        Ok(Metadata {
+            id: 0,
+            file_type: FileType::File,
+            size: 0,
+            children_len: 0,
+        })
+    }
+}
+
 struct ProcFs {
     root_inode: INodeCacheItem,
     root_dir: DirCacheItem,
@@ -286,7 +775,10 @@ impl ProcFs {
     pub fn new() -> fs::Result<Arc<Self>> {
         let icache = cache::icache();
 
-        let root_node = Arc::new(LockedProcINode::new(ProcINode::default()));
+        let root_node = Arc::new(LockedProcINode::new(ProcINode {
+            contents: FileContents::Root,
+            ..Default::default()
+        }));
         let root_cached = icache.make_item_no_cache(CachedINode::new(root_node));
 
         let root_dir = DirEntry::new_root(root_cached.clone(), String::from("/"));
@@ -315,11 +807,33 @@ impl ProcFs {
 
         inode.make_inode("cpuinfo", FileType::File, FileContents::CpuInfo)?;
         inode.make_inode("cmdline", FileType::File, FileContents::CmdLine)?;
+        inode.make_inode("blockstat", FileType::File, FileContents::BlockStat)?;
+
+        if zram::get().is_some() {
+            inode.make_inode("zram", FileType::File, FileContents::Zram)?;
+        }
 
         let proc_self = inode.make_inode("self", FileType::Directory, FileContents::None)?;
         let proc_self = proc_self.downcast_arc::<LockedProcINode>().unwrap();
 
-        proc_self.make_inode("maps", FileType::File, FileContents::SelfMaps)?;
+        proc_self.make_inode("maps", FileType::File, FileContents::Maps(None))?;
+        proc_self.make_inode("stat", FileType::File, FileContents::Stat(None))?;
+        proc_self.make_inode("status", FileType::File, FileContents::Status(None))?;
+        proc_self.make_inode("memory.max", FileType::File, FileContents::MemoryMax(None))?;
+        proc_self.make_inode("fd", FileType::Directory, FileContents::Fds(None))?;
+        proc_self.make_inode("fdinfo", FileType::Directory, FileContents::FdInfo(None))?;
+        proc_self.make_inode("exe", FileType::File, FileContents::Exe(None))?;
+        proc_self.make_inode("ipc", FileType::File, FileContents::Ipc(None))?;
+
+        let proc_sys = inode.make_inode("sys", FileType::Directory, FileContents::None)?;
+        let proc_sys = proc_sys.downcast_arc::<LockedProcINode>().unwrap();
+
+        proc_sys.make_inode("ipc_trace", FileType::File, FileContents::IpcTrace)?;
+
+        let proc_net = inode.make_inode("net", FileType::Directory, FileContents::None)?;
+        let proc_net = proc_net.downcast_arc::<LockedProcINode>().unwrap();
+
+        proc_net.make_inode("dev", FileType::File, FileContents::NetDev)?;
 
         Ok(ramfs)
     }
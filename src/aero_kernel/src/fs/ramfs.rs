@@ -17,23 +17,23 @@
 
 use core::sync::atomic::{AtomicUsize, Ordering};
 
-use aero_syscall::MMapFlags;
+use aero_syscall::{Mode, OpenFlags};
 use alloc::collections::BTreeMap;
 use alloc::string::ToString;
 use alloc::sync::{Arc, Weak};
 
 use alloc::vec::Vec;
-use spin::RwLock;
 
 use crate::mem::paging::*;
-use crate::utils::sync::Mutex;
+use crate::utils::sync::{Mutex, RwLock};
 
 use super::cache::{
     self, CacheWeak, CachedINode, DirCacheItem, INodeCacheItem, INodeCacheWeakItem,
 };
 use super::devfs::DevINode;
 use super::inode::{
-    DirEntry, FileContents, FileType, INodeInterface, MMapPage, Metadata, PollFlags, PollTable,
+    DirEntry, FileContents, FileType, INodeInterface, MMapPage, MappableRegion, Metadata,
+    PollFlags, PollTable,
 };
 use super::{FileSystem, FileSystemError, Result};
 
@@ -45,6 +45,7 @@ pub struct RamINode {
     children: BTreeMap<String, INodeCacheItem>,
     filesystem: Weak<RamFs>,
     file_type: FileType,
+    permissions: Mode,
     contents: FileContents,
 }
 
@@ -62,6 +63,7 @@ impl LockedRamINode {
         node: &INodeCacheWeakItem,
         filesystem: &Weak<RamFs>,
         file_type: FileType,
+        permissions: Mode,
     ) {
         let mut this = self.0.write();
 
@@ -69,12 +71,14 @@ impl LockedRamINode {
         this.node = node.clone();
         this.filesystem = filesystem.clone();
         this.file_type = file_type;
+        this.permissions = permissions - Mode::S_IFMT;
     }
 
     fn make_inode(
         &self,
         name: &str,
         file_type: FileType,
+        permissions: Mode,
         contents: FileContents,
     ) -> Result<INodeCacheItem> {
         let icache = cache::icache();
@@ -101,6 +105,7 @@ impl LockedRamINode {
                 &inode_cached.downgrade(),
                 &this.filesystem,
                 file_type,
+                permissions,
             );
 
         this.children
@@ -116,6 +121,15 @@ impl INodeInterface for LockedRamINode {
 
         let this = self.0.read();
 
+        stat.st_mode = this.permissions;
+        stat.st_mode.insert(match this.file_type {
+            FileType::File => Mode::S_IFREG,
+            FileType::Directory => Mode::S_IFDIR,
+            FileType::Device => Mode::S_IFCHR,
+            FileType::Socket => Mode::S_IFSOCK,
+            FileType::Symlink => Mode::S_IFLNK,
+        });
+
         match &this.contents {
             FileContents::Content(contents) => {
                 stat.st_size = contents.lock().len() as _;
@@ -131,12 +145,13 @@ impl INodeInterface for LockedRamINode {
         Ok(stat)
     }
 
-    fn touch(&self, parent: DirCacheItem, name: &str) -> Result<DirCacheItem> {
+    fn touch(&self, parent: DirCacheItem, name: &str, mode: Mode) -> Result<DirCacheItem> {
         Ok(DirEntry::new(
             parent,
             self.make_inode(
                 name,
                 FileType::File,
+                mode,
                 FileContents::Content(Mutex::new(Vec::new())),
             )?,
             String::from(name),
@@ -144,8 +159,8 @@ impl INodeInterface for LockedRamINode {
     }
 
     #[inline]
-    fn mkdir(&self, name: &str) -> Result<INodeCacheItem> {
-        self.make_inode(name, FileType::Directory, FileContents::None)
+    fn mkdir(&self, name: &str, mode: Mode) -> Result<INodeCacheItem> {
+        self.make_inode(name, FileType::Directory, mode, FileContents::None)
     }
 
     #[inline]
@@ -153,13 +168,19 @@ impl INodeInterface for LockedRamINode {
         self.make_inode(
             name,
             FileType::Device,
+            Mode::from_bits_truncate(0o666),
             FileContents::Device(DevINode::new(marker)?),
         )
     }
 
     #[inline]
     fn make_ramfs_inode(&self, name: &str, buffer: &'static [u8]) -> Result<INodeCacheItem> {
-        self.make_inode(name, FileType::File, FileContents::StaticContent(buffer))
+        self.make_inode(
+            name,
+            FileType::File,
+            Mode::from_bits_truncate(0o444),
+            FileContents::StaticContent(buffer),
+        )
     }
 
     #[inline]
@@ -167,8 +188,9 @@ impl INodeInterface for LockedRamINode {
         &self,
         name: &str,
         inode: Arc<dyn INodeInterface>,
+        mode: Mode,
     ) -> Result<INodeCacheItem> {
-        self.make_inode(name, FileType::Socket, FileContents::Socket(inode))
+        self.make_inode(name, FileType::Socket, mode, FileContents::Socket(inode))
     }
 
     fn write_at(&self, offset: usize, buffer: &[u8]) -> Result<usize> {
@@ -200,6 +222,23 @@ impl INodeInterface for LockedRamINode {
         }
     }
 
+    fn append(&self, buffer: &[u8]) -> Result<(usize, usize)> {
+        let this = self.0.read();
+
+        match &this.contents {
+            // Truncations lock the contents as well, so the end of the file cannot move.
+            FileContents::Content(vec) => {
+                let mut vec = vec.lock();
+                let offset = vec.len();
+
+                vec.extend_from_slice(buffer);
+                Ok((offset, buffer.len()))
+            }
+
+            _ => Err(FileSystemError::NotSupported),
+        }
+    }
+
     fn dirent(&self, parent: DirCacheItem, index: usize) -> Result<Option<DirCacheItem>> {
         let this = self.0.read();
 
@@ -317,6 +356,17 @@ impl INodeInterface for LockedRamINode {
         }
     }
 
+    fn close(&self, flags: OpenFlags) {
+        let this = self.0.read();
+
+        if let FileContents::Device(device) = &this.contents {
+            let device = device.clone();
+            drop(this);
+
+            device.close(flags)
+        }
+    }
+
     fn metadata(&self) -> Result<Metadata> {
         let this = self.0.read();
 
@@ -347,7 +397,7 @@ impl INodeInterface for LockedRamINode {
         }
     }
 
-    fn mmap(&self, offset: usize, size: usize, flags: MMapFlags) -> Result<PhysFrame> {
+    fn mmap_region(&self, offset: usize, len: usize) -> Result<MappableRegion> {
         let this = self.0.read();
 
         match &this.contents {
@@ -355,31 +405,9 @@ impl INodeInterface for LockedRamINode {
                 let device = dev.clone();
                 drop(this);
 
-                device.mmap(offset, size, flags)
-            }
-
-            FileContents::StaticContent(contents) => {
-                // TODO: Support shared static content ramfs file mappings.
-                assert!(!flags.contains(MMapFlags::MAP_SHARED));
-
-                let private_cp: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
-                private_cp.as_slice_mut()[..size].copy_from_slice(&contents[offset..offset + size]);
-
-                Ok(private_cp)
-            }
-
-            FileContents::Content(contents) => {
-                // TODO: Support shared content ramfs file mappings.
-                assert!(!flags.contains(MMapFlags::MAP_SHARED));
-
-                let private_cp: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
-                private_cp.as_slice_mut()[..size]
-                    .copy_from_slice(&contents.lock()[offset..offset + size]);
-
-                Ok(private_cp)
+                device.mmap_region(offset, len)
             }
 
-            // TODO: Support other memory mapping ramfs files:
             _ => Err(FileSystemError::NotSupported),
         }
     }
@@ -505,6 +533,7 @@ impl RamFs {
                 &root_cached.downgrade(),
                 &Arc::downgrade(&ramfs),
                 FileType::Directory,
+                Mode::from_bits_truncate(0o755),
             );
 
         ramfs
@@ -519,6 +548,8 @@ impl RamFs {
             id: self.next_id.fetch_add(1, Ordering::SeqCst),
             contents,
             file_type,
+            // Set along with the rest of the node in `init`.
+            permissions: Mode::empty(),
         }))
     }
 }
@@ -0,0 +1,743 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Filesystem kept in anonymous memory, mounted at `/tmp` and `/run`.
+//!
+//! The contents of the regular files live in frames that are allocated when a page is first
+//! written and freed as soon as the last reference to the file goes away, such as when it is
+//! unlinked. Shared mappings of a file map its frames directly.
+//!
+//! The size of a filesystem is fixed when it is created. It bounds both the number of pages
+//! holding file contents and the size of each file, and writes past it fail with `ENOSPC`.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use aero_syscall::{Mode, OpenFlags, StatFs, TMPFS_MAGIC};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use spin::Once;
+
+use crate::mem::paging::*;
+use crate::utils::sync::{Mutex, RwLock, RwLockWriteGuard};
+
+use super::cache::{
+    self, CacheWeak, CachedINode, DirCacheItem, INodeCacheItem, INodeCacheWeakItem,
+};
+use super::devfs::DevINode;
+use super::file_table::FileHandle;
+use super::inode::{
+    DirEntry, FileType, INodeInterface, MMapPage, MappableRegion, Metadata, PollFlags, PollTable,
+};
+use super::memfd::Contents;
+use super::path::PathBuf;
+use super::{FileSystem, FileSystemError, Path, Result, MOUNT_MANAGER};
+
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
+
+struct Directory {
+    /// Empty for the root directory, whose parent is itself.
+    parent: INodeCacheWeakItem,
+    /// Entries by cookie, the offset of the entry in the directory stream. Cookies are not
+    /// reused, so the cookie of an entry stays valid while it exists. `0` and `1` are taken by
+    /// the `.` and `..` entries.
+    entries: BTreeMap<usize, (String, INodeCacheItem)>,
+    cookies: BTreeMap<String, usize>,
+    next_cookie: usize,
+}
+
+impl Directory {
+    fn new(parent: INodeCacheWeakItem) -> Self {
+        Self {
+            parent,
+            entries: BTreeMap::new(),
+            cookies: BTreeMap::new(),
+            next_cookie: 2,
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&INodeCacheItem> {
+        self.cookies.get(name).map(|cookie| &self.entries[cookie].1)
+    }
+
+    fn insert(&mut self, name: &str, inode: INodeCacheItem) -> Result<()> {
+        if ["", ".", ".."].contains(&name) || self.cookies.contains_key(name) {
+            return Err(FileSystemError::EntryExists);
+        }
+
+        let cookie = self.next_cookie;
+        self.next_cookie += 1;
+
+        self.cookies.insert(String::from(name), cookie);
+        self.entries.insert(cookie, (String::from(name), inode));
+        Ok(())
+    }
+
+    /// Removes the entry `name`, if it still refers to `inode`.
+    fn remove(&mut self, name: &str, inode: &INodeCacheItem) -> Result<INodeCacheItem> {
+        match self.get(name) {
+            Some(entry) if Arc::ptr_eq(&**entry, &**inode) => {}
+            _ => return Err(FileSystemError::EntryNotFound),
+        }
+
+        let cookie = self.cookies.remove(name).unwrap();
+        Ok(self.entries.remove(&cookie).unwrap().1)
+    }
+}
+
+enum Node {
+    File(Contents),
+    Directory(Directory),
+    Symlink(String),
+    Device(Arc<DevINode>),
+    Socket(Arc<dyn INodeInterface>),
+}
+
+impl Node {
+    fn file_type(&self) -> FileType {
+        match self {
+            Self::File(_) => FileType::File,
+            Self::Directory(_) => FileType::Directory,
+            Self::Symlink(_) => FileType::Symlink,
+            Self::Device(_) => FileType::Device,
+            Self::Socket(_) => FileType::Socket,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::File(contents) => contents.size,
+            Self::Symlink(target) => target.len(),
+            _ => 0,
+        }
+    }
+
+    fn file_mut(&mut self) -> Result<&mut Contents> {
+        match self {
+            Self::File(contents) => Ok(contents),
+            Self::Directory(_) => Err(FileSystemError::IsDir),
+            _ => Err(FileSystemError::NotSupported),
+        }
+    }
+
+    fn directory(&self) -> Result<&Directory> {
+        match self {
+            Self::Directory(directory) => Ok(directory),
+            _ => Err(FileSystemError::NotDirectory),
+        }
+    }
+
+    fn directory_mut(&mut self) -> Result<&mut Directory> {
+        match self {
+            Self::Directory(directory) => Ok(directory),
+            _ => Err(FileSystemError::NotDirectory),
+        }
+    }
+}
+
+pub struct TmpINode {
+    id: usize,
+    filesystem: Weak<Tmpfs>,
+    /// The cache item of the inode itself, for the `.` entry.
+    node: Once<INodeCacheWeakItem>,
+    permissions: Mode,
+    data: RwLock<Node>,
+}
+
+impl TmpINode {
+    fn new(filesystem: &Weak<Tmpfs>, id: usize, permissions: Mode, data: Node) -> INodeCacheItem {
+        let inode = Arc::new(Self {
+            id,
+            filesystem: filesystem.clone(),
+            node: Once::new(),
+            permissions: permissions - Mode::S_IFMT,
+            data: RwLock::new(data),
+        });
+
+        let cached = cache::icache().make_item_no_cache(CachedINode::new(inode.clone()));
+        inode.node.call_once(|| cached.downgrade());
+
+        cached
+    }
+
+    fn filesystem(&self) -> Arc<Tmpfs> {
+        self.filesystem
+            .upgrade()
+            .expect("tmpfs: the filesystem was dropped")
+    }
+
+    fn node(&self) -> INodeCacheItem {
+        // UNWRAP: The inode is only reachable through its cache item.
+        self.node.get().and_then(CacheWeak::upgrade).unwrap()
+    }
+
+    /// Creates an inode holding `data` and links it in this directory.
+    fn make_inode(&self, name: &str, permissions: Mode, data: Node) -> Result<INodeCacheItem> {
+        let filesystem = self.filesystem();
+        let mut this = self.data.write();
+        let directory = this.directory_mut()?;
+
+        if directory.get(name).is_some() {
+            return Err(FileSystemError::EntryExists);
+        }
+
+        let inode = TmpINode::new(
+            &self.filesystem,
+            filesystem.next_id.fetch_add(1, Ordering::SeqCst),
+            permissions,
+            data,
+        );
+
+        directory.insert(name, inode.clone())?;
+        Ok(inode)
+    }
+
+    fn child(&self, name: &str) -> Result<INodeCacheItem> {
+        let this = self.data.read();
+
+        this.directory()?
+            .get(name)
+            .cloned()
+            .ok_or(FileSystemError::EntryNotFound)
+    }
+
+    /// Returns the parent directory, or `None` for the root directory.
+    fn parent(&self) -> Option<Arc<TmpINode>> {
+        let parent = self.data.read().directory().ok()?.parent.upgrade()?;
+        parent.downcast_arc::<TmpINode>()
+    }
+
+    /// Returns whether this inode is `ancestor` or lies beneath it.
+    fn is_beneath(&self, ancestor: &TmpINode) -> bool {
+        if core::ptr::eq(self, ancestor) {
+            return true;
+        }
+
+        let mut current = self.parent();
+
+        while let Some(inode) = current {
+            if core::ptr::eq(&*inode, ancestor) {
+                return true;
+            }
+
+            current = inode.parent();
+        }
+
+        false
+    }
+
+    /// Returns the device that the operations on a device node are forwarded to.
+    fn device(&self) -> Option<Arc<DevINode>> {
+        match &*self.data.read() {
+            Node::Device(device) => Some(device.clone()),
+            _ => None,
+        }
+    }
+
+    /// Locks the nodes of `self` and `other` for writing. They are always locked in the same
+    /// order, so that two operations locking the same pair of inodes cannot deadlock.
+    fn lock_pair<'a>(
+        &'a self,
+        other: &'a TmpINode,
+    ) -> (RwLockWriteGuard<'a, Node>, RwLockWriteGuard<'a, Node>) {
+        if (self as *const Self) < (other as *const Self) {
+            let this = self.data.write();
+            (this, other.data.write())
+        } else {
+            let other = other.data.write();
+            (self.data.write(), other)
+        }
+    }
+}
+
+impl INodeInterface for TmpINode {
+    fn metadata(&self) -> Result<Metadata> {
+        let this = self.data.read();
+
+        Ok(Metadata {
+            id: self.id,
+            file_type: this.file_type(),
+            size: this.size(),
+            children_len: this
+                .directory()
+                .map_or(0, |directory| directory.entries.len()),
+        })
+    }
+
+    fn stat(&self) -> Result<aero_syscall::Stat> {
+        let this = self.data.read();
+
+        let mut mode = self.permissions;
+        mode.insert(match this.file_type() {
+            FileType::File => Mode::S_IFREG,
+            FileType::Directory => Mode::S_IFDIR,
+            FileType::Device => Mode::S_IFCHR,
+            FileType::Socket => Mode::S_IFSOCK,
+            FileType::Symlink => Mode::S_IFLNK,
+        });
+
+        let blocks = match &*this {
+            Node::File(contents) => contents.page_count() * (PAGE_SIZE / 512),
+            _ => 0,
+        };
+
+        Ok(aero_syscall::Stat {
+            st_ino: self.id as _,
+            st_mode: mode,
+            st_size: this.size() as _,
+            st_blksize: PAGE_SIZE as _,
+            st_blocks: blocks as _,
+            ..Default::default()
+        })
+    }
+
+    fn touch(&self, parent: DirCacheItem, name: &str, mode: Mode) -> Result<DirCacheItem> {
+        let inode = self.make_inode(name, mode, Node::File(Contents::new()))?;
+        Ok(DirEntry::new(parent, inode, String::from(name)))
+    }
+
+    fn mkdir(&self, name: &str, mode: Mode) -> Result<INodeCacheItem> {
+        let directory = Directory::new(self.node().downgrade());
+        self.make_inode(name, mode, Node::Directory(directory))
+    }
+
+    fn make_dev_inode(&self, name: &str, marker: usize) -> Result<INodeCacheItem> {
+        self.make_inode(
+            name,
+            Mode::from_bits_truncate(0o666),
+            Node::Device(DevINode::new(marker)?),
+        )
+    }
+
+    fn make_local_socket_inode(
+        &self,
+        name: &str,
+        inode: Arc<dyn INodeInterface>,
+        mode: Mode,
+    ) -> Result<INodeCacheItem> {
+        self.make_inode(name, mode, Node::Socket(inode))
+    }
+
+    fn lookup(&self, dir: DirCacheItem, name: &str) -> Result<DirCacheItem> {
+        Ok(DirEntry::new(dir, self.child(name)?, String::from(name)))
+    }
+
+    fn dirent_at(
+        &self,
+        parent: DirCacheItem,
+        cursor: usize,
+    ) -> Result<Option<(DirCacheItem, usize)>> {
+        let (name, inode, next) = {
+            let this = self.data.read();
+            let directory = this.directory()?;
+
+            match cursor {
+                0 => (String::from("."), self.node(), 1),
+                1 => {
+                    let parent = directory.parent.upgrade().unwrap_or_else(|| self.node());
+                    (String::from(".."), parent, 2)
+                }
+
+                _ => match directory.entries.range(cursor..).next() {
+                    Some((cookie, (name, inode))) => (name.clone(), inode.clone(), cookie + 1),
+                    None => return Ok(None),
+                },
+            }
+        };
+
+        Ok(Some((DirEntry::new(parent, inode, name), next)))
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        let child = self.child(name)?;
+
+        if child.metadata()?.is_directory() {
+            return Err(FileSystemError::IsDir);
+        }
+
+        // The contents are freed along with the inode, once it is no longer open or mapped.
+        self.data.write().directory_mut()?.remove(name, &child)?;
+        Ok(())
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        let child = self.child(name)?;
+        let child_inode = child.downcast_arc::<TmpINode>().unwrap();
+
+        let (mut this, child_data) = self.lock_pair(&child_inode);
+
+        if !child_data.directory()?.entries.is_empty() {
+            return Err(FileSystemError::NotEmpty);
+        }
+
+        this.directory_mut()?.remove(name, &child)?;
+        Ok(())
+    }
+
+    fn rename(&self, src: DirCacheItem, dest: &str) -> Result<()> {
+        let filesystem = self.filesystem();
+
+        let inode = src.inode();
+        let parent = src.parent().ok_or(FileSystemError::Busy)?.inode();
+
+        let old_parent = parent
+            .downcast_arc::<TmpINode>()
+            .filter(|parent| Weak::ptr_eq(&parent.filesystem, &self.filesystem))
+            .ok_or(FileSystemError::CrossDevice)?;
+
+        let moved = inode
+            .downcast_arc::<TmpINode>()
+            .ok_or(FileSystemError::CrossDevice)?;
+        let is_directory = inode.metadata()?.is_directory();
+
+        // Renames take two directory locks and walk the tree, which another rename could be
+        // changing at the same time.
+        let _guard = filesystem.rename_lock.lock();
+
+        // Moving a directory beneath itself would detach it from the tree.
+        if is_directory && self.is_beneath(&moved) {
+            return Err(FileSystemError::InvalidInput);
+        }
+
+        let name = src.name();
+
+        if core::ptr::eq(self, &*old_parent) {
+            let mut this = self.data.write();
+            let directory = this.directory_mut()?;
+
+            if directory.get(dest).is_some() {
+                return Err(FileSystemError::EntryExists);
+            }
+
+            let inode = directory.remove(&name, &inode)?;
+            directory.insert(dest, inode)?;
+        } else {
+            let (mut this, mut old) = self.lock_pair(&old_parent);
+            let directory = this.directory_mut()?;
+
+            if directory.get(dest).is_some() {
+                return Err(FileSystemError::EntryExists);
+            }
+
+            let inode = old.directory_mut()?.remove(&name, &inode)?;
+            directory.insert(dest, inode)?;
+        }
+
+        if is_directory {
+            moved.data.write().directory_mut()?.parent = self.node().downgrade();
+        }
+
+        Ok(())
+    }
+
+    fn link(&self, name: &str, src: DirCacheItem) -> Result<()> {
+        let src = src.inode();
+
+        let same_filesystem = src
+            .downcast_arc::<TmpINode>()
+            .is_some_and(|inode| Weak::ptr_eq(&inode.filesystem, &self.filesystem));
+
+        if !same_filesystem {
+            return Err(FileSystemError::CrossDevice);
+        }
+
+        if src.metadata()?.is_directory() {
+            return Err(FileSystemError::IsDir);
+        }
+
+        self.data.write().directory_mut()?.insert(name, src)
+    }
+
+    fn symlink(&self, target: &Path) -> Result<()> {
+        let mut this = self.data.write();
+
+        // The lookup creates the link as an empty file, which becomes the link here.
+        match &*this {
+            Node::File(contents) if contents.size == 0 => {}
+            _ => return Err(FileSystemError::NotSupported),
+        }
+
+        *this = Node::Symlink(String::from(target.as_str()));
+        Ok(())
+    }
+
+    fn resolve_link(&self) -> Result<PathBuf> {
+        match &*self.data.read() {
+            Node::Symlink(target) => Ok(PathBuf::from(target.as_str())),
+            _ => Err(FileSystemError::NotSupported),
+        }
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize> {
+        if let Some(device) = self.device() {
+            return device.read_at(offset, buffer);
+        }
+
+        match &*self.data.read() {
+            Node::File(contents) => Ok(contents.read(offset, buffer)),
+            Node::Directory(_) => Err(FileSystemError::IsDir),
+            _ => Err(FileSystemError::NotSupported),
+        }
+    }
+
+    fn write_at(&self, offset: usize, buffer: &[u8]) -> Result<usize> {
+        if let Some(device) = self.device() {
+            return device.write_at(offset, buffer);
+        }
+
+        let mut this = self.data.write();
+        self.filesystem().write(this.file_mut()?, offset, buffer)
+    }
+
+    fn append(&self, buffer: &[u8]) -> Result<(usize, usize)> {
+        let mut this = self.data.write();
+        let contents = this.file_mut()?;
+        let offset = contents.size;
+
+        Ok((offset, self.filesystem().write(contents, offset, buffer)?))
+    }
+
+    fn truncate(&self, size: usize) -> Result<()> {
+        let filesystem = self.filesystem();
+
+        if size > filesystem.size {
+            return Err(FileSystemError::NoSpace);
+        }
+
+        let released = self.data.write().file_mut()?.truncate(size);
+        filesystem.unreserve(released);
+
+        Ok(())
+    }
+
+    fn mmap_v2(&self, offset: usize) -> Result<MMapPage> {
+        if let Some(device) = self.device() {
+            return device.mmap_v2(offset);
+        }
+
+        let filesystem = self.filesystem();
+        let mut this = self.data.write();
+        let contents = this.file_mut()?;
+
+        // Accessing a page past the end of the file is an error (`SIGBUS` on Linux).
+        if offset >= contents.size {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        let missing = contents.missing_pages(offset, 1);
+        filesystem.reserve(missing)?;
+
+        let frame = contents
+            .page(offset / PAGE_SIZE)
+            .inspect_err(|_| filesystem.unreserve(missing))?;
+
+        Ok(MMapPage::Direct(frame))
+    }
+
+    fn mmap_region(&self, offset: usize, len: usize) -> Result<MappableRegion> {
+        match self.device() {
+            Some(device) => device.mmap_region(offset, len),
+            None => Err(FileSystemError::NotSupported),
+        }
+    }
+
+    fn open(&self, handle: Arc<FileHandle>) -> Result<Option<DirCacheItem>> {
+        match self.device() {
+            Some(device) => device.open(handle),
+            None => Ok(None),
+        }
+    }
+
+    fn close(&self, flags: OpenFlags) {
+        if let Some(device) = self.device() {
+            device.close(flags);
+        }
+    }
+
+    fn ioctl(&self, command: usize, arg: usize) -> Result<usize> {
+        match self.device() {
+            Some(device) => device.ioctl(command, arg),
+            None => Err(FileSystemError::NotSupported),
+        }
+    }
+
+    fn poll(&self, table: Option<&mut PollTable>) -> Result<PollFlags> {
+        match self.device() {
+            Some(device) => device.poll(table),
+            None => Err(FileSystemError::NotSupported),
+        }
+    }
+
+    fn as_unix_socket(&self) -> Result<Arc<dyn INodeInterface>> {
+        match &*self.data.read() {
+            Node::Socket(socket) => Ok(socket.clone()),
+            _ => Err(FileSystemError::NotSocket),
+        }
+    }
+
+    fn weak_filesystem(&self) -> Option<Weak<dyn FileSystem>> {
+        Some(self.filesystem.clone())
+    }
+}
+
+impl Drop for TmpINode {
+    fn drop(&mut self) {
+        // The frames themselves are released by the contents.
+        if let Node::File(contents) = &*self.data.read() {
+            if let Some(filesystem) = self.filesystem.upgrade() {
+                filesystem.unreserve(contents.page_count());
+            }
+        }
+    }
+}
+
+pub struct Tmpfs {
+    root_dir: DirCacheItem,
+    next_id: AtomicUsize,
+    rename_lock: Mutex<()>,
+
+    /// Size of the filesystem, in bytes.
+    size: usize,
+    /// Number of pages holding file contents.
+    used: AtomicUsize,
+}
+
+impl Tmpfs {
+    /// Creates an empty filesystem of `size` bytes, whose root directory has the permissions
+    /// `mode`.
+    pub fn new(size: usize, mode: Mode) -> Arc<Self> {
+        Arc::new_cyclic(|this| {
+            let root = TmpINode::new(
+                this,
+                0,
+                mode,
+                Node::Directory(Directory::new(CacheWeak::new())),
+            );
+            let root_dir = DirEntry::new_root(root, String::from("/"));
+
+            let filesystem: Weak<dyn FileSystem> = this.clone();
+            root_dir.filesystem.call_once(|| filesystem);
+
+            Self {
+                root_dir,
+                next_id: AtomicUsize::new(1),
+                rename_lock: Mutex::new(()),
+
+                size,
+                used: AtomicUsize::new(0),
+            }
+        })
+    }
+
+    /// Charges `count` pages of file contents to the filesystem.
+    fn reserve(&self, count: usize) -> Result<()> {
+        let limit = self.size / PAGE_SIZE;
+
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                used.checked_add(count).filter(|&used| used <= limit)
+            })
+            .map(|_| ())
+            .map_err(|_| FileSystemError::NoSpace)
+    }
+
+    fn unreserve(&self, count: usize) {
+        self.used.fetch_sub(count, Ordering::SeqCst);
+    }
+
+    /// Writes `buffer` at `offset` in `contents`, charging the pages it allocates.
+    fn write(&self, contents: &mut Contents, offset: usize, buffer: &[u8]) -> Result<usize> {
+        let end = offset
+            .checked_add(buffer.len())
+            .ok_or(FileSystemError::InvalidInput)?;
+
+        // A file cannot be larger than the filesystem, even if it is sparse.
+        if end > self.size {
+            return Err(FileSystemError::NoSpace);
+        }
+
+        let missing = contents.missing_pages(offset, buffer.len());
+        self.reserve(missing)?;
+
+        let pages = contents.page_count();
+        let result = contents.write(offset, buffer);
+
+        // The write can fail half-way if the kernel runs out of memory.
+        self.unreserve(missing - (contents.page_count() - pages));
+        result
+    }
+}
+
+impl FileSystem for Tmpfs {
+    fn root_dir(&self) -> DirCacheItem {
+        self.root_dir.clone()
+    }
+
+    fn stat(&self) -> Result<StatFs> {
+        let blocks = (self.size / PAGE_SIZE) as u64;
+        let free = blocks.saturating_sub(self.used.load(Ordering::SeqCst) as u64);
+
+        Ok(StatFs {
+            f_type: TMPFS_MAGIC,
+            f_bsize: PAGE_SIZE as u64,
+            f_blocks: blocks,
+            f_bfree: free,
+            f_bavail: free,
+            f_namelen: super::NAME_MAX as u64,
+            f_frsize: PAGE_SIZE as u64,
+            ..Default::default()
+        })
+    }
+}
+
+/// Mounts `/tmp`, and `/run` where programs keep the files that must not outlive the boot (such
+/// as sockets and lock files).
+pub fn init() -> Result<()> {
+    // Like on Linux, each of them can take up to half of the memory.
+    let size = FRAME_ALLOCATOR.total_memory() / 2;
+
+    mount(
+        Path::new("/tmp"),
+        Tmpfs::new(size, Mode::from_bits_truncate(0o1777)),
+    )?;
+    mount(
+        Path::new("/run"),
+        Tmpfs::new(size, Mode::from_bits_truncate(0o755)),
+    )?;
+
+    Ok(())
+}
+
+fn mount(path: &Path, filesystem: Arc<Tmpfs>) -> Result<()> {
+    let dir = match super::lookup_path(path) {
+        Ok(dir) => dir,
+
+        // Disk images made before `/run` was added to them lack the directory.
+        Err(FileSystemError::EntryNotFound) => {
+            let (parent, name) = path.parent_and_basename();
+
+            super::lookup_path(parent)?
+                .inode()
+                .mkdir(name, Mode::from_bits_truncate(0o755))?;
+
+            super::lookup_path(path)?
+        }
+
+        Err(error) => return Err(error),
+    };
+
+    MOUNT_MANAGER.mount(dir, filesystem)
+}
@@ -0,0 +1,53 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Named IPC objects shared between processes.
+
+pub mod msg;
+pub mod sem;
+pub mod semaphore;
+pub mod shm;
+
+use aero_syscall::SyscallError;
+
+/// Maximum length of the name of an IPC object, excluding the leading slash.
+const NAME_MAX: usize = 255;
+
+/// Returns the name of an IPC object without its leading slash.
+///
+/// ## Errors
+/// * `EINVAL`: `name` does not start with a slash, is only a slash or contains another slash.
+/// * `ENAMETOOLONG`: `name` is longer than [`NAME_MAX`].
+pub fn object_name(name: &str) -> Result<&str, SyscallError> {
+    let name = name.strip_prefix('/').ok_or(SyscallError::EINVAL)?;
+
+    if name.is_empty() || name.contains('/') {
+        return Err(SyscallError::EINVAL);
+    }
+
+    if name.len() > NAME_MAX {
+        return Err(SyscallError::ENAMETOOLONG);
+    }
+
+    Ok(name)
+}
+
+/// Returns the current `CLOCK_REALTIME` time in seconds, as recorded in the attributes of System V
+/// IPC objects.
+fn now() -> i64 {
+    crate::arch::time::get_realtime_clock().tv_sec as i64
+}
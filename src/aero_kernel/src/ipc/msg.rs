@@ -0,0 +1,312 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! System V message queues, created with `msgget`.
+//!
+//! A queue is registered in a global table until it is removed with `msgctl(IPC_RMID)`, which
+//! discards its messages and fails the pending `msgsnd` and `msgrcv` calls with `EIDRM`.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use aero_syscall::{
+    IpcPerm, MsqidDs, SyscallError, IPC_CREAT, IPC_EXCL, IPC_NOWAIT, IPC_PRIVATE, IPC_SET,
+    IPC_STAT, MSGMAX, MSGMNB, MSG_EXCEPT, MSG_NOERROR,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+use spin::Once;
+
+use crate::userland::task::TaskId;
+use crate::utils::sync::{Mutex, WaitQueue};
+
+use super::now;
+
+static MSG_TABLE: Once<Mutex<HashMap<i32, Arc<MsgQueue>>>> = Once::new();
+static NEXT_ID: AtomicI32 = AtomicI32::new(0);
+
+/// Returns the table of message queues, indexed by their identifier; initializing it if
+/// necessary.
+fn msg_table() -> &'static Mutex<HashMap<i32, Arc<MsgQueue>>> {
+    MSG_TABLE.call_once(|| Mutex::new(HashMap::new()))
+}
+
+struct Inner {
+    /// The messages with their type, in the order they were sent.
+    messages: VecDeque<(i64, Vec<u8>)>,
+    /// Number of bytes of text in the queue.
+    bytes: usize,
+    perm: MsqidDs,
+    removed: bool,
+}
+
+impl Inner {
+    /// Returns the index of the first message selected by `mtype`, see [`MsgQueue::receive`].
+    fn find(&self, mtype: i64, except: bool) -> Option<usize> {
+        let mut messages = self.messages.iter().map(|(ty, _)| *ty).enumerate();
+
+        match mtype {
+            0 => messages.next().map(|(index, _)| index),
+            1.. => messages
+                .find(|(_, ty)| (*ty == mtype) != except)
+                .map(|(index, _)| index),
+            _ => messages
+                .filter(|(_, ty)| ty.unsigned_abs() <= mtype.unsigned_abs())
+                .min_by_key(|(_, ty)| *ty)
+                .map(|(index, _)| index),
+        }
+    }
+}
+
+pub struct MsgQueue {
+    key: i32,
+    inner: Mutex<Inner>,
+    wq: WaitQueue,
+}
+
+impl MsgQueue {
+    fn new(key: i32, mode: u32) -> Arc<Self> {
+        Arc::new(Self {
+            key,
+            inner: Mutex::new(Inner {
+                messages: VecDeque::new(),
+                bytes: 0,
+                perm: MsqidDs {
+                    msg_perm: IpcPerm {
+                        key,
+                        mode: mode & 0o777,
+                        ..Default::default()
+                    },
+                    msg_ctime: now(),
+                    msg_qbytes: MSGMNB as u64,
+                    ..Default::default()
+                },
+                removed: false,
+            }),
+            wq: WaitQueue::new(),
+        })
+    }
+
+    /// Adds a message of type `mtype` with the text `text` to the queue, blocking while the queue
+    /// is full unless [`IPC_NOWAIT`] is set in `flags`.
+    ///
+    /// ## Errors
+    /// * `EINVAL`: `mtype` is not positive or `text` is larger than [`MSGMAX`].
+    /// * `EAGAIN`: The queue is full and [`IPC_NOWAIT`] is set.
+    /// * `EIDRM`: The queue was removed.
+    pub fn send(
+        &self,
+        mtype: i64,
+        text: &[u8],
+        flags: usize,
+        pid: TaskId,
+    ) -> Result<(), SyscallError> {
+        if mtype <= 0 || text.len() > MSGMAX {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let fits = |inner: &Inner| inner.bytes + text.len() <= inner.perm.msg_qbytes as usize;
+
+        let mut inner = if flags & IPC_NOWAIT != 0 {
+            let inner = self.inner.lock_irq();
+
+            if !inner.removed && !fits(&inner) {
+                return Err(SyscallError::EAGAIN);
+            }
+
+            inner
+        } else {
+            self.wq
+                .block_on(&self.inner, |inner| inner.removed || fits(inner))?
+        };
+
+        if inner.removed {
+            return Err(SyscallError::EIDRM);
+        }
+
+        inner.messages.push_back((mtype, text.to_vec()));
+        inner.bytes += text.len();
+        inner.perm.msg_stime = now();
+        inner.perm.msg_lspid = pid.as_usize() as i32;
+
+        core::mem::drop(inner);
+        self.wq.notify_all();
+        Ok(())
+    }
+
+    /// Removes the first message selected by `mtype` from the queue, blocking while there is no
+    /// such message unless [`IPC_NOWAIT`] is set in `flags`. Returns the type of the message and
+    /// its text, truncated to `max_size` bytes.
+    ///
+    /// An `mtype` of zero selects any message and a positive one a message of that type, or of
+    /// any other type with [`MSG_EXCEPT`]. A negative one selects a message of the lowest type that
+    /// is at most its absolute value.
+    ///
+    /// ## Errors
+    /// * `E2BIG`: The text of the message is larger than `max_size` and [`MSG_NOERROR`] is not set,
+    ///   in which case the message stays in the queue.
+    /// * `ENOMSG`: There is no such message and [`IPC_NOWAIT`] is set.
+    /// * `EIDRM`: The queue was removed.
+    pub fn receive(
+        &self,
+        max_size: usize,
+        mtype: i64,
+        flags: usize,
+        pid: TaskId,
+    ) -> Result<(i64, usize), SyscallError> {
+        let except = flags & MSG_EXCEPT != 0;
+
+        let mut inner = if flags & IPC_NOWAIT != 0 {
+            let inner = self.inner.lock_irq();
+
+            if !inner.removed && inner.find(mtype, except).is_none() {
+                return Err(SyscallError::ENOMSG);
+            }
+
+            inner
+        } else {
+            self.wq.block_on(&self.inner, |inner| {
+                inner.removed || inner.find(mtype, except).is_some()
+            })?
+        };
+
+        if inner.removed {
+            return Err(SyscallError::EIDRM);
+        }
+
+        let index = inner.find(mtype, except).unwrap();
+
+        if inner.messages[index].1.len() > max_size && flags & MSG_NOERROR == 0 {
+            return Err(SyscallError::E2BIG);
+        }
+
+        let (mtype, mut text) = inner.messages.remove(index).unwrap();
+
+        inner.bytes -= text.len();
+        inner.perm.msg_rtime = now();
+        inner.perm.msg_lrpid = pid.as_usize() as i32;
+
+        core::mem::drop(inner);
+        self.wq.notify_all();
+
+        text.truncate(max_size);
+        Ok((mtype, text))
+    }
+
+    /// Performs the `IPC_STAT` or `IPC_SET` control operation.
+    ///
+    /// Permissions are not checked, so `IPC_SET` only updates the ownership and mode of the queue
+    /// and its maximum number of bytes.
+    pub fn control(&self, command: usize, buffer: &mut MsqidDs) -> Result<(), SyscallError> {
+        let mut inner = self.inner.lock_irq();
+
+        match command {
+            IPC_STAT => {
+                *buffer = inner.perm;
+                buffer.msg_cbytes = inner.bytes as u64;
+                buffer.msg_qnum = inner.messages.len() as u64;
+            }
+
+            IPC_SET => {
+                if buffer.msg_qbytes == 0 {
+                    return Err(SyscallError::EINVAL);
+                }
+
+                let perm = &mut inner.perm;
+                perm.msg_perm.uid = buffer.msg_perm.uid;
+                perm.msg_perm.gid = buffer.msg_perm.gid;
+                perm.msg_perm.mode = (perm.msg_perm.mode & !0o777) | (buffer.msg_perm.mode & 0o777);
+                perm.msg_qbytes = buffer.msg_qbytes;
+                perm.msg_ctime = now();
+
+                // Blocked senders may fit in the queue now.
+                core::mem::drop(inner);
+                self.wq.notify_all();
+            }
+
+            _ => return Err(SyscallError::EINVAL),
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the identifier of the queue with the key `key`. If it does not exist and
+/// [`IPC_CREAT`] is set in `flags`, it is created with the mode from the low bits of `flags`. A
+/// key of [`IPC_PRIVATE`] always creates a new queue.
+///
+/// ## Errors
+/// * `ENOENT`: The queue does not exist and [`IPC_CREAT`] is not set.
+/// * `EEXIST`: The queue exists and both [`IPC_CREAT`] and [`IPC_EXCL`] are set.
+pub fn get(key: i32, flags: usize) -> Result<i32, SyscallError> {
+    let mut table = msg_table().lock_irq();
+
+    if key != IPC_PRIVATE {
+        if let Some((id, _)) = table.iter().find(|(_, queue)| queue.key == key) {
+            if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                return Err(SyscallError::EEXIST);
+            }
+
+            return Ok(*id);
+        }
+
+        if flags & IPC_CREAT == 0 {
+            return Err(SyscallError::ENOENT);
+        }
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    table.insert(id, MsgQueue::new(key, flags as u32));
+
+    Ok(id)
+}
+
+/// Returns the queue with the identifier `id`.
+///
+/// ## Errors
+/// * `EINVAL`: The queue does not exist or has been removed.
+pub fn lookup(id: usize) -> Result<Arc<MsgQueue>, SyscallError> {
+    let id = i32::try_from(id).map_err(|_| SyscallError::EINVAL)?;
+
+    msg_table()
+        .lock_irq()
+        .get(&id)
+        .cloned()
+        .ok_or(SyscallError::EINVAL)
+}
+
+/// Removes the queue with the identifier `id`, waking up the tasks blocked on it.
+///
+/// ## Errors
+/// * `EINVAL`: The queue does not exist or has already been removed.
+pub fn remove(id: usize) -> Result<(), SyscallError> {
+    let id = i32::try_from(id).map_err(|_| SyscallError::EINVAL)?;
+    let queue = msg_table()
+        .lock_irq()
+        .remove(&id)
+        .ok_or(SyscallError::EINVAL)?;
+
+    let mut inner = queue.inner.lock_irq();
+    inner.messages.clear();
+    inner.bytes = 0;
+    inner.removed = true;
+
+    core::mem::drop(inner);
+    queue.wq.notify_all();
+    Ok(())
+}
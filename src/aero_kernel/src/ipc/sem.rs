@@ -0,0 +1,427 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! System V semaphore sets, created with `semget` and operated on with `semop`.
+//!
+//! A set is registered in a global table until it is removed with `semctl(IPC_RMID)`, which fails
+//! the pending `semop` calls with `EIDRM`. Unlike the POSIX semaphores of [`super::semaphore`],
+//! all the operations of a `semop` call are performed atomically.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use aero_syscall::{
+    IpcPerm, SemBuf, SemidDs, SyscallError, GETNCNT, GETPID, GETVAL, GETZCNT, IPC_CREAT, IPC_EXCL,
+    IPC_NOWAIT, IPC_PRIVATE, IPC_SET, IPC_STAT, SEMMSL, SEMVMX, SEM_UNDO,
+};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+use spin::Once;
+
+use crate::userland::task::TaskId;
+use crate::utils::sync::{Mutex, WaitQueue};
+
+use super::now;
+
+static SEM_TABLE: Once<Mutex<HashMap<i32, Arc<SemSet>>>> = Once::new();
+static NEXT_ID: AtomicI32 = AtomicI32::new(0);
+
+/// Returns the table of semaphore sets, indexed by their identifier; initializing it if
+/// necessary.
+fn sem_table() -> &'static Mutex<HashMap<i32, Arc<SemSet>>> {
+    SEM_TABLE.call_once(|| Mutex::new(HashMap::new()))
+}
+
+#[derive(Default, Copy, Clone)]
+struct Sem {
+    value: u16,
+    /// PID of the process that last operated on the semaphore.
+    pid: i32,
+    /// Number of tasks waiting for the value to increase.
+    ncnt: usize,
+    /// Number of tasks waiting for the value to become zero.
+    zcnt: usize,
+}
+
+struct Inner {
+    sems: Vec<Sem>,
+    /// Adjustments of the semaphores made by the operations performed with [`SEM_UNDO`], by PID.
+    /// They are applied when the process exits.
+    undo: BTreeMap<usize, Vec<i32>>,
+    perm: SemidDs,
+    removed: bool,
+}
+
+impl Inner {
+    /// Performs `ops` on behalf of the process `pid` if none of them blocks. Otherwise, nothing is
+    /// changed and the index of the first operation that blocks is returned.
+    fn apply(&mut self, ops: &[SemBuf], pid: usize) -> Result<Option<usize>, SyscallError> {
+        let mut values = self.sems.iter().map(|sem| sem.value).collect::<Vec<_>>();
+
+        for (index, op) in ops.iter().enumerate() {
+            let value = &mut values[op.sem_num as usize];
+
+            match op.sem_op {
+                0 if *value != 0 => return Ok(Some(index)),
+                0 => {}
+
+                1.. => {
+                    *value = value
+                        .checked_add(op.sem_op as u16)
+                        .filter(|value| *value <= SEMVMX)
+                        .ok_or(SyscallError::ERANGE)?;
+                }
+
+                _ => match value.checked_sub(op.sem_op.unsigned_abs()) {
+                    Some(result) => *value = result,
+                    None => return Ok(Some(index)),
+                },
+            }
+        }
+
+        let undo = ops.iter().filter(|op| op.sem_flg as usize & SEM_UNDO != 0);
+        let mut adjustments = self.undo.get(&pid).cloned();
+
+        for op in undo {
+            let adjustments = adjustments.get_or_insert_with(|| alloc::vec![0; self.sems.len()]);
+            let adjustment = &mut adjustments[op.sem_num as usize];
+
+            *adjustment -= op.sem_op as i32;
+
+            if adjustment.unsigned_abs() > SEMVMX as u32 {
+                return Err(SyscallError::ERANGE);
+            }
+        }
+
+        for op in ops {
+            self.sems[op.sem_num as usize].pid = pid as i32;
+        }
+
+        for (sem, value) in self.sems.iter_mut().zip(values) {
+            sem.value = value;
+        }
+
+        if let Some(adjustments) = adjustments {
+            self.undo.insert(pid, adjustments);
+        }
+
+        self.perm.sem_otime = now();
+        Ok(None)
+    }
+
+    /// Returns the number of tasks waiting on the semaphore of `op`, which is blocked.
+    fn waiters(&mut self, op: &SemBuf) -> &mut usize {
+        let sem = &mut self.sems[op.sem_num as usize];
+
+        if op.sem_op == 0 {
+            &mut sem.zcnt
+        } else {
+            &mut sem.ncnt
+        }
+    }
+}
+
+pub struct SemSet {
+    key: i32,
+    nsems: usize,
+    inner: Mutex<Inner>,
+    wq: WaitQueue,
+}
+
+impl SemSet {
+    fn new(key: i32, nsems: usize, mode: u32) -> Arc<Self> {
+        Arc::new(Self {
+            key,
+            nsems,
+            inner: Mutex::new(Inner {
+                sems: alloc::vec![Sem::default(); nsems],
+                undo: BTreeMap::new(),
+                perm: SemidDs {
+                    sem_perm: IpcPerm {
+                        key,
+                        mode: mode & 0o777,
+                        ..Default::default()
+                    },
+                    sem_ctime: now(),
+                    sem_nsems: nsems as u64,
+                    ..Default::default()
+                },
+                removed: false,
+            }),
+            wq: WaitQueue::new(),
+        })
+    }
+
+    #[inline]
+    pub fn nsems(&self) -> usize {
+        self.nsems
+    }
+
+    /// Performs the operations `ops` atomically, blocking until all of them can be performed. See
+    /// [`SemBuf::sem_op`] for the meaning of each operation.
+    ///
+    /// ## Errors
+    /// * `EFBIG`: An operation refers to a semaphore that is not in the set.
+    /// * `EAGAIN`: An operation would block and has [`IPC_NOWAIT`] set.
+    /// * `ERANGE`: A value or an undo adjustment would be larger than [`SEMVMX`].
+    /// * `EIDRM`: The set was removed.
+    pub fn operate(&self, ops: &[SemBuf], pid: TaskId) -> Result<(), SyscallError> {
+        if ops.iter().any(|op| op.sem_num as usize >= self.nsems) {
+            return Err(SyscallError::EFBIG);
+        }
+
+        let pid = pid.as_usize();
+        let mut inner = self.inner.lock_irq();
+
+        if inner.removed {
+            return Err(SyscallError::EIDRM);
+        }
+
+        let Some(index) = inner.apply(ops, pid)? else {
+            core::mem::drop(inner);
+            self.wq.notify_all();
+            return Ok(());
+        };
+
+        let op = ops[index];
+
+        if op.sem_flg as usize & IPC_NOWAIT != 0 {
+            return Err(SyscallError::EAGAIN);
+        }
+
+        // The task counts as waiting on the semaphore of the operation that blocked.
+        *inner.waiters(&op) += 1;
+        core::mem::drop(inner);
+
+        let mut result = Ok(());
+        let wait = self.wq.block_on(&self.inner, |inner| {
+            if inner.removed {
+                result = Err(SyscallError::EIDRM);
+                return true;
+            }
+
+            match inner.apply(ops, pid) {
+                Ok(blocked) => blocked.is_none(),
+                Err(error) => {
+                    result = Err(error);
+                    true
+                }
+            }
+        });
+
+        let mut inner = match wait {
+            Ok(inner) => inner,
+            Err(error) => {
+                result = Err(error.into());
+                self.inner.lock_irq()
+            }
+        };
+
+        *inner.waiters(&op) -= 1;
+        core::mem::drop(inner);
+
+        if result.is_ok() {
+            self.wq.notify_all();
+        }
+
+        result
+    }
+
+    /// Performs the `GETVAL`, `GETPID`, `GETNCNT` or `GETZCNT` control operation on the semaphore
+    /// `semnum`.
+    pub fn query(&self, semnum: usize, command: usize) -> Result<usize, SyscallError> {
+        let inner = self.inner.lock_irq();
+        let sem = inner.sems.get(semnum).ok_or(SyscallError::EINVAL)?;
+
+        match command {
+            GETVAL => Ok(sem.value as usize),
+            GETPID => Ok(sem.pid as usize),
+            GETNCNT => Ok(sem.ncnt),
+            GETZCNT => Ok(sem.zcnt),
+            _ => Err(SyscallError::EINVAL),
+        }
+    }
+
+    /// Returns the values of the semaphores, for the `GETALL` control operation.
+    pub fn values(&self) -> Vec<u16> {
+        let inner = self.inner.lock_irq();
+        inner.sems.iter().map(|sem| sem.value).collect()
+    }
+
+    /// Sets the values of the semaphores starting at `first`, for the `SETVAL` and `SETALL`
+    /// control operations. The undo adjustments of those semaphores are discarded.
+    ///
+    /// ## Errors
+    /// * `EINVAL`: The semaphores are not in the set.
+    /// * `ERANGE`: A value is larger than [`SEMVMX`].
+    pub fn set_values(
+        &self,
+        first: usize,
+        values: &[u16],
+        pid: TaskId,
+    ) -> Result<(), SyscallError> {
+        let range = first..first + values.len();
+
+        if range.end > self.nsems {
+            return Err(SyscallError::EINVAL);
+        }
+
+        if values.iter().any(|value| *value > SEMVMX) {
+            return Err(SyscallError::ERANGE);
+        }
+
+        let mut inner = self.inner.lock_irq();
+
+        for (sem, value) in inner.sems[range.clone()].iter_mut().zip(values) {
+            sem.value = *value;
+            sem.pid = pid.as_usize() as i32;
+        }
+
+        for adjustments in inner.undo.values_mut() {
+            adjustments[range.clone()].fill(0);
+        }
+
+        inner.perm.sem_ctime = now();
+
+        core::mem::drop(inner);
+        self.wq.notify_all();
+        Ok(())
+    }
+
+    /// Performs the `IPC_STAT` or `IPC_SET` control operation.
+    ///
+    /// Permissions are not checked, so `IPC_SET` only updates the ownership and mode of the set.
+    pub fn control(&self, command: usize, buffer: &mut SemidDs) -> Result<(), SyscallError> {
+        let mut inner = self.inner.lock_irq();
+
+        match command {
+            IPC_STAT => *buffer = inner.perm,
+
+            IPC_SET => {
+                let perm = &mut inner.perm;
+                perm.sem_perm.uid = buffer.sem_perm.uid;
+                perm.sem_perm.gid = buffer.sem_perm.gid;
+                perm.sem_perm.mode = (perm.sem_perm.mode & !0o777) | (buffer.sem_perm.mode & 0o777);
+                perm.sem_ctime = now();
+            }
+
+            _ => return Err(SyscallError::EINVAL),
+        }
+
+        Ok(())
+    }
+
+    /// Applies the undo adjustments of the process `pid`, clamping the values to the range of a
+    /// semaphore.
+    fn undo(&self, pid: usize) {
+        let mut inner = self.inner.lock_irq();
+
+        let Some(adjustments) = inner.undo.remove(&pid) else {
+            return;
+        };
+
+        for (sem, adjustment) in inner.sems.iter_mut().zip(adjustments) {
+            if adjustment != 0 {
+                sem.value = (sem.value as i32 + adjustment).clamp(0, SEMVMX as i32) as u16;
+                sem.pid = pid as i32;
+            }
+        }
+
+        core::mem::drop(inner);
+        self.wq.notify_all();
+    }
+}
+
+/// Returns the identifier of the set with the key `key`. If it does not exist and [`IPC_CREAT`]
+/// is set in `flags`, a set of `nsems` semaphores is created, with the mode from the low bits of
+/// `flags`. A key of [`IPC_PRIVATE`] always creates a new set.
+///
+/// ## Errors
+/// * `ENOENT`: The set does not exist and [`IPC_CREAT`] is not set.
+/// * `EEXIST`: The set exists and both [`IPC_CREAT`] and [`IPC_EXCL`] are set.
+/// * `EINVAL`: The set exists and has less than `nsems` semaphores, or it is created and `nsems` is
+///   zero or larger than [`SEMMSL`].
+pub fn get(key: i32, nsems: usize, flags: usize) -> Result<i32, SyscallError> {
+    let mut table = sem_table().lock_irq();
+
+    if key != IPC_PRIVATE {
+        if let Some((id, set)) = table.iter().find(|(_, set)| set.key == key) {
+            if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                return Err(SyscallError::EEXIST);
+            }
+
+            if nsems > set.nsems {
+                return Err(SyscallError::EINVAL);
+            }
+
+            return Ok(*id);
+        }
+
+        if flags & IPC_CREAT == 0 {
+            return Err(SyscallError::ENOENT);
+        }
+    }
+
+    if nsems == 0 || nsems > SEMMSL {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    table.insert(id, SemSet::new(key, nsems, flags as u32));
+
+    Ok(id)
+}
+
+/// Returns the set with the identifier `id`.
+///
+/// ## Errors
+/// * `EINVAL`: The set does not exist or has been removed.
+pub fn lookup(id: usize) -> Result<Arc<SemSet>, SyscallError> {
+    let id = i32::try_from(id).map_err(|_| SyscallError::EINVAL)?;
+
+    sem_table()
+        .lock_irq()
+        .get(&id)
+        .cloned()
+        .ok_or(SyscallError::EINVAL)
+}
+
+/// Removes the set with the identifier `id`, waking up the tasks blocked on it.
+///
+/// ## Errors
+/// * `EINVAL`: The set does not exist or has already been removed.
+pub fn remove(id: usize) -> Result<(), SyscallError> {
+    let id = i32::try_from(id).map_err(|_| SyscallError::EINVAL)?;
+    let set = sem_table()
+        .lock_irq()
+        .remove(&id)
+        .ok_or(SyscallError::EINVAL)?;
+
+    set.inner.lock_irq().removed = true;
+    set.wq.notify_all();
+    Ok(())
+}
+
+/// Applies the undo adjustments of the exiting process `pid` to every set.
+pub fn exit(pid: TaskId) {
+    let sets = sem_table().lock_irq().values().cloned().collect::<Vec<_>>();
+
+    for set in sets {
+        set.undo(pid.as_usize());
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Named POSIX semaphores, opened by name with `sem_open`.
+//!
+//! The semaphores are registered in a global table until their name is removed with
+//! `sem_unlink`, and live on as long as a file descriptor refers to them.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use aero_syscall::{SyscallError, SEM_VALUE_MAX};
+use alloc::string::String;
+use alloc::sync::Arc;
+use hashbrown::HashMap;
+use spin::Once;
+
+use crate::fs::inode::INodeInterface;
+use crate::timer;
+use crate::userland::scheduler;
+use crate::utils::sync::{Mutex, WaitQueue};
+
+static SEMAPHORES: Once<Mutex<HashMap<String, Arc<Semaphore>>>> = Once::new();
+
+/// Returns the table of named semaphores; initializing it if necessary.
+fn semaphores() -> &'static Mutex<HashMap<String, Arc<Semaphore>>> {
+    SEMAPHORES.call_once(|| Mutex::new(HashMap::new()))
+}
+
+pub struct Semaphore {
+    count: AtomicI32,
+    wq: WaitQueue,
+}
+
+impl Semaphore {
+    fn new(value: i32) -> Arc<Self> {
+        Arc::new(Self {
+            count: AtomicI32::new(value),
+            wq: WaitQueue::new(),
+        })
+    }
+
+    /// Decrements the count if it is positive, and returns whether it was.
+    fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count > 0).then_some(count - 1)
+            })
+            .is_ok()
+    }
+
+    /// Increments the count and wakes up one of the waiters.
+    ///
+    /// ## Errors
+    /// * `EOVERFLOW`: The count is already [`SEM_VALUE_MAX`].
+    pub fn post(&self) -> Result<(), SyscallError> {
+        self.count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < SEM_VALUE_MAX as i32).then_some(count + 1)
+            })
+            .map_err(|_| SyscallError::EOVERFLOW)?;
+
+        self.wq.notify();
+        Ok(())
+    }
+
+    /// Decrements the count without blocking.
+    ///
+    /// ## Errors
+    /// * `EAGAIN`: The count is zero.
+    pub fn try_wait(&self) -> Result<(), SyscallError> {
+        if self.try_acquire() {
+            Ok(())
+        } else {
+            Err(SyscallError::EAGAIN)
+        }
+    }
+
+    /// Decrements the count, blocking while it is zero. If a `deadline` is given, on the monotonic
+    /// clock of the [timers](crate::timer), gives up once it passed.
+    ///
+    /// ## Errors
+    /// * `ETIMEDOUT`: The deadline passed before the count could be decremented.
+    /// * `EINTR`: A signal interrupted the wait.
+    pub fn wait(&self, deadline: Option<u64>) -> Result<(), SyscallError> {
+        if self.try_acquire() {
+            return Ok(());
+        }
+
+        let scheduler = scheduler::get_scheduler();
+        let task = scheduler.current_task();
+
+        let timer = deadline.map(|deadline| {
+            let task = task.clone();
+            timer::add_timer(deadline, move || task.wake_up())
+        });
+
+        // Queue the task before testing the count, so that a post between the test and going to
+        // sleep is not lost.
+        self.wq.insert(task.clone());
+
+        let result = loop {
+            if self.try_acquire() {
+                break Ok(());
+            }
+
+            if deadline.is_some_and(|deadline| timer::now() >= deadline) {
+                break Err(SyscallError::ETIMEDOUT);
+            }
+
+            if let Err(error) = scheduler.inner.await_io() {
+                break Err(error.into());
+            }
+        };
+
+        self.wq.remove(&task);
+
+        if let Some(timer) = timer {
+            timer::cancel(timer);
+        }
+
+        // A post only wakes up the first waiter, which may have been this task while it was
+        // already awake. Pass the wake up on if the count is still positive.
+        if self.count.load(Ordering::SeqCst) > 0 {
+            self.wq.notify();
+        }
+
+        result
+    }
+}
+
+impl INodeInterface for Semaphore {}
+
+/// Returns the semaphore `name`. If it does not exist and `create` is set, it is created with
+/// the initial `value`.
+///
+/// ## Errors
+/// * `ENOENT`: The semaphore does not exist and `create` is not set.
+/// * `EEXIST`: The semaphore exists and both `create` and `exclusive` are set.
+/// * `EINVAL`: The semaphore is created and `value` is larger than [`SEM_VALUE_MAX`].
+pub fn open(
+    name: &str,
+    create: bool,
+    exclusive: bool,
+    value: usize,
+) -> Result<Arc<Semaphore>, SyscallError> {
+    let name = super::object_name(name)?;
+    let mut semaphores = semaphores().lock_irq();
+
+    if let Some(semaphore) = semaphores.get(name) {
+        if create && exclusive {
+            return Err(SyscallError::EEXIST);
+        }
+
+        return Ok(semaphore.clone());
+    }
+
+    if !create {
+        return Err(SyscallError::ENOENT);
+    }
+
+    if value > SEM_VALUE_MAX as usize {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let semaphore = Semaphore::new(value as i32);
+    semaphores.insert(String::from(name), semaphore.clone());
+
+    Ok(semaphore)
+}
+
+/// Removes the name of the semaphore `name`.
+///
+/// ## Errors
+/// * `ENOENT`: The semaphore does not exist.
+pub fn unlink(name: &str) -> Result<(), SyscallError> {
+    let name = super::object_name(name)?;
+
+    semaphores()
+        .lock_irq()
+        .remove(name)
+        .map(|_| ())
+        .ok_or(SyscallError::ENOENT)
+}
@@ -0,0 +1,285 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! System V shared memory segments, created with `shmget` and attached with `shmat`.
+//!
+//! A segment is registered in a global table until it is removed with `shmctl(IPC_RMID)`, and
+//! its frames are freed once it is no longer attached to any address space.
+
+use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+use aero_syscall::{
+    IpcPerm, ShmidDs, SyscallError, IPC_CREAT, IPC_EXCL, IPC_PRIVATE, IPC_SET, IPC_STAT,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+use spin::Once;
+
+use crate::fs::inode::{INodeInterface, MMapPage};
+use crate::fs::{FileSystemError, Result as FsResult};
+use crate::mem::paging::*;
+use crate::userland::task::TaskId;
+use crate::utils::sync::Mutex;
+
+use super::now;
+
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
+
+/// Maximum size of a segment, in bytes.
+const SHMMAX: usize = 64 * 1024 * 1024;
+
+/// Set in the mode of a segment once it has been removed with `shmctl(IPC_RMID)`.
+const SHM_DEST: u32 = 0o1000;
+
+static SHMEM_TABLE: Once<Mutex<HashMap<i32, Arc<ShmSegment>>>> = Once::new();
+static NEXT_ID: AtomicI32 = AtomicI32::new(0);
+
+/// Returns the table of shared memory segments, indexed by their identifier; initializing it if
+/// necessary.
+fn shmem_table() -> &'static Mutex<HashMap<i32, Arc<ShmSegment>>> {
+    SHMEM_TABLE.call_once(|| Mutex::new(HashMap::new()))
+}
+
+pub struct ShmSegment {
+    key: i32,
+    size: usize,
+    frames: Vec<PhysFrame>,
+    perm: Mutex<ShmidDs>,
+    attachments: AtomicUsize,
+}
+
+impl ShmSegment {
+    /// Creates a segment of `size` bytes, backed by zeroed frames.
+    fn new(key: i32, size: usize, mode: u32, creator: TaskId) -> FsResult<Arc<Self>> {
+        let mut frames = Vec::with_capacity(size.div_ceil(PAGE_SIZE));
+
+        for _ in 0..size.div_ceil(PAGE_SIZE) {
+            let Some(frame) = FRAME_ALLOCATOR
+                .alloc_zeroed(PAGE_SIZE)
+                .map(PhysFrame::containing_address)
+            else {
+                frames.into_iter().for_each(Self::release);
+                return Err(FileSystemError::OutOfMemory);
+            };
+
+            // The segment holds a reference to the frame, so it is not deallocated when the last
+            // mapping of the page is unmapped.
+            frame.start_address().as_vm_frame().unwrap().inc_ref_count();
+            frames.push(frame);
+        }
+
+        Ok(Arc::new(Self {
+            key,
+            size,
+            frames,
+            perm: Mutex::new(ShmidDs {
+                shm_perm: IpcPerm {
+                    key,
+                    mode: mode & 0o777,
+                    ..Default::default()
+                },
+                shm_segsz: size,
+                shm_ctime: now(),
+                shm_cpid: creator.as_usize() as i32,
+                ..Default::default()
+            }),
+            attachments: AtomicUsize::new(0),
+        }))
+    }
+
+    /// Drops the reference of the segment to `frame`.
+    fn release(frame: PhysFrame) {
+        let vm_frame = frame.start_address().as_vm_frame().unwrap();
+        vm_frame.dec_ref_count();
+
+        if vm_frame.ref_count() == 0 {
+            FRAME_ALLOCATOR.deallocate_frame(frame);
+        }
+    }
+
+    #[inline]
+    pub fn key(&self) -> i32 {
+        self.key
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Records that the segment was attached by the process `pid`.
+    pub fn attached(&self, pid: TaskId) {
+        let mut perm = self.perm.lock_irq();
+        perm.shm_atime = now();
+        perm.shm_lpid = pid.as_usize() as i32;
+    }
+
+    /// Records that the segment was detached by the process `pid`.
+    pub fn detached(&self, pid: TaskId) {
+        let mut perm = self.perm.lock_irq();
+        perm.shm_dtime = now();
+        perm.shm_lpid = pid.as_usize() as i32;
+    }
+
+    /// Performs the `IPC_STAT` or `IPC_SET` control operation.
+    ///
+    /// Permissions are not checked, so `IPC_SET` only updates the ownership and mode of the
+    /// segment.
+    pub fn control(&self, command: usize, buffer: &mut ShmidDs) -> Result<(), SyscallError> {
+        let mut perm = self.perm.lock_irq();
+
+        match command {
+            IPC_STAT => {
+                *buffer = *perm;
+                buffer.shm_nattch = self.attachments.load(Ordering::SeqCst) as u64;
+            }
+
+            IPC_SET => {
+                perm.shm_perm.uid = buffer.shm_perm.uid;
+                perm.shm_perm.gid = buffer.shm_perm.gid;
+                perm.shm_perm.mode = (perm.shm_perm.mode & !0o777) | (buffer.shm_perm.mode & 0o777);
+                perm.shm_ctime = now();
+            }
+
+            _ => return Err(SyscallError::EINVAL),
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        for frame in core::mem::take(&mut self.frames) {
+            Self::release(frame);
+        }
+    }
+}
+
+/// An attachment of a segment to an address space, backing the shared mapping created by
+/// `shmat`.
+///
+/// Every attachment is counted in `shm_nattch`. The child of a `fork` gets its own attachment
+/// for each one it inherits, see [`INodeInterface::fork_mapping`].
+pub struct ShmAttachment {
+    segment: Arc<ShmSegment>,
+}
+
+impl ShmAttachment {
+    pub fn new(segment: Arc<ShmSegment>) -> Arc<Self> {
+        segment.attachments.fetch_add(1, Ordering::SeqCst);
+        Arc::new(Self { segment })
+    }
+
+    #[inline]
+    pub fn segment(&self) -> &Arc<ShmSegment> {
+        &self.segment
+    }
+}
+
+impl INodeInterface for ShmAttachment {
+    fn mmap_v2(&self, offset: usize) -> FsResult<MMapPage> {
+        self.segment
+            .frames
+            .get(offset / PAGE_SIZE)
+            .map(|frame| MMapPage::Direct(*frame))
+            .ok_or(FileSystemError::NotSupported)
+    }
+
+    fn fork_mapping(&self) -> Option<Arc<dyn INodeInterface>> {
+        Some(ShmAttachment::new(self.segment.clone()))
+    }
+}
+
+impl Drop for ShmAttachment {
+    fn drop(&mut self) {
+        self.segment.attachments.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Returns the identifier of the segment with the key `key`. If it does not exist and
+/// [`IPC_CREAT`] is set in `flags`, a segment of `size` bytes is created, with the mode from the
+/// low bits of `flags`. A key of [`IPC_PRIVATE`] always creates a new segment.
+///
+/// ## Errors
+/// * `ENOENT`: The segment does not exist and [`IPC_CREAT`] is not set.
+/// * `EEXIST`: The segment exists and both [`IPC_CREAT`] and [`IPC_EXCL`] are set.
+/// * `EINVAL`: The segment exists and is smaller than `size`, or it is created and `size` is zero
+///   or larger than [`SHMMAX`].
+/// * `ENOMEM`: There is not enough memory to create the segment.
+pub fn get(key: i32, size: usize, flags: usize, creator: TaskId) -> Result<i32, SyscallError> {
+    let mut table = shmem_table().lock_irq();
+
+    if key != IPC_PRIVATE {
+        if let Some((id, segment)) = table.iter().find(|(_, segment)| segment.key == key) {
+            if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                return Err(SyscallError::EEXIST);
+            }
+
+            if size > segment.size {
+                return Err(SyscallError::EINVAL);
+            }
+
+            return Ok(*id);
+        }
+
+        if flags & IPC_CREAT == 0 {
+            return Err(SyscallError::ENOENT);
+        }
+    }
+
+    if size == 0 || size > SHMMAX {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let segment = ShmSegment::new(key, size, flags as u32, creator)?;
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    table.insert(id, segment);
+
+    Ok(id)
+}
+
+/// Returns the segment with the identifier `id`.
+///
+/// ## Errors
+/// * `EINVAL`: The segment does not exist or has been removed.
+pub fn lookup(id: usize) -> Result<Arc<ShmSegment>, SyscallError> {
+    let id = i32::try_from(id).map_err(|_| SyscallError::EINVAL)?;
+
+    shmem_table()
+        .lock_irq()
+        .get(&id)
+        .cloned()
+        .ok_or(SyscallError::EINVAL)
+}
+
+/// Removes the segment with the identifier `id` from the table, so that it can no longer be
+/// found or attached. It is destroyed once it is detached from every address space.
+///
+/// ## Errors
+/// * `EINVAL`: The segment does not exist or has already been removed.
+pub fn remove(id: usize) -> Result<(), SyscallError> {
+    let id = i32::try_from(id).map_err(|_| SyscallError::EINVAL)?;
+    let segment = shmem_table()
+        .lock_irq()
+        .remove(&id)
+        .ok_or(SyscallError::EINVAL)?;
+
+    segment.perm.lock_irq().shm_perm.mode |= SHM_DEST;
+    Ok(())
+}
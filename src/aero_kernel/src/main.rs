@@ -94,6 +94,7 @@ mod drivers;
 #[cfg(feature = "ci")]
 mod emu;
 mod fs;
+mod ipc;
 mod logger;
 mod mem;
 mod modules;
@@ -103,9 +104,11 @@ mod socket;
 mod syscall;
 #[cfg(test)]
 mod tests;
+mod timer;
 mod unwind;
 mod userland;
 mod utils;
+mod version;
 
 use self::mem::alloc::LockedHeap;
 use self::mem::paging::VirtAddr;
@@ -156,6 +159,7 @@ fn aero_main() -> ! {
     log::info!("loaded filesystem");
 
     crate::arch::time::init();
+    timer::init();
     log::info!("loaded timer");
 
     userland::scheduler::init();
@@ -168,7 +172,7 @@ fn aero_main() -> ! {
 
     // Now that all of the essential initialization is done we are going to schedule
     // the kernel main thread.
-    let init = Task::new_kernel(kernel_main_thread, true);
+    let init = Task::new_init(kernel_main_thread);
     let kdbg = Task::new_kernel(kernel_dbg_thread, true);
     scheduler::get_scheduler().register_task(init);
     scheduler::get_scheduler().register_task(kdbg);
@@ -262,6 +266,9 @@ fn kernel_dbg_thread() {
                         .wake_up();
                 }
 
+                #[cfg(feature = "kasan")]
+                "heapdump" => mem::kasan::dump(),
+
                 _ => log::warn!("kdbg: unknown command {name:?}"),
             }
         }
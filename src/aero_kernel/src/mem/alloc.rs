@@ -80,7 +80,10 @@ impl Allocator {
         }
 
         let _guard = IrqGuard::new();
-        if layout.size() <= 1024 {
+        let size = align_up(layout.size() as _, layout.align() as _);
+
+        // Objects are handed back to the slab they were allocated from in `alloc`.
+        if self.zones.iter().any(|slab| size as usize <= slab.size()) {
             let slab_header = SlabHeader::from_object(ptr);
             slab_header.as_slab().dealloc(ptr);
         }
@@ -210,11 +213,16 @@ unsafe impl GlobalAlloc for LockedHeap {
         // SAFETY: We we need to be careful to not cause a deadlock as the interrupt
         // handlers utilize the heap and might interrupt an in-progress allocation. So, we
         // lock the interrupts during the allocation.
+        #[cfg(feature = "kasan")]
+        let ptr = super::kasan::alloc(layout, |layout| self.0.alloc(layout));
+
+        #[cfg(not(feature = "kasan"))]
+        let ptr = self.0.alloc(layout);
 
         #[cfg(feature = "kmemleak")]
         kmemleak::MEM_LEAK_CATCHER.track_caller(ptr, layout);
 
-        self.0.alloc(layout)
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
@@ -224,7 +232,11 @@ unsafe impl GlobalAlloc for LockedHeap {
         #[cfg(feature = "kmemleak")]
         kmemleak::MEM_LEAK_CATCHER.unref(ptr);
 
-        self.0.dealloc(ptr, layout)
+        #[cfg(feature = "kasan")]
+        super::kasan::dealloc(ptr, layout, |ptr, layout| self.0.dealloc(ptr, layout));
+
+        #[cfg(not(feature = "kasan"))]
+        self.0.dealloc(ptr, layout);
     }
 
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
@@ -258,7 +270,11 @@ fn alloc_error_handler(layout: alloc::Layout) -> ! {
 /// Initialize the heap at the [HEAP_START].
 pub fn init_heap() {
     vmalloc::init();
+    super::kstack::init();
 
     #[cfg(feature = "kmemleak")]
     kmemleak::MEM_LEAK_CATCHER.init();
+
+    #[cfg(feature = "kasan")]
+    super::kasan::init();
 }
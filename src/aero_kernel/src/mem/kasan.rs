@@ -0,0 +1,455 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Heap sanitizer (`kasan`), a lightweight take on the kernel address sanitizer.
+//!
+//! Every allocation is surrounded by redzones filled with [`REDZONE_BYTE`]:
+//!
+//! ```text
+//! +--------+---------+-----------------+---------+
+//! | header | redzone | allocation data | redzone |
+//! +--------+---------+-----------------+---------+
+//! ```
+//!
+//! The redzones are checked when the allocation is freed, which catches small out of bounds
+//! writes. Freed memory is filled with [`POISON_BYTE`] and kept in a quarantine for a while
+//! before it is handed back to the allocator, so a use after free reads the poison instead of
+//! another allocation. The poison is checked again when the memory leaves the quarantine, which
+//! catches writes after free.
+//!
+//! The outstanding allocations are accounted to their call site, the backtrace of the
+//! allocation. `#[track_caller]` does not propagate through the global allocator, so the call
+//! site is identified by its return addresses instead. The call sites with the most outstanding
+//! bytes are logged with [`dump`] (`heapdump` in the kernel debugger).
+
+use core::alloc::Layout;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::string::ToString;
+
+use crate::mem::paging::align_up;
+use crate::unwind;
+use crate::utils::sync::IrqGuard;
+
+/// Size of each redzone.
+const REDZONE_SIZE: usize = 16;
+/// Fills the redzones.
+pub const REDZONE_BYTE: u8 = 0xcc;
+/// Fills freed memory.
+pub const POISON_BYTE: u8 = 0x6b;
+
+const LIVE_MAGIC: u32 = 0x6b61_736e;
+const FREED_MAGIC: u32 = 0x6672_6565;
+
+/// Number of freed allocations kept in the quarantine.
+const QUARANTINE_LEN: usize = 512;
+/// Maximum number of call sites. Allocations from call sites found after the table is full are
+/// not accounted.
+const MAX_SITES: usize = 1024;
+/// Number of return addresses identifying a call site.
+const SITE_DEPTH: usize = 8;
+/// Number of call sites logged by [`dump`].
+const DUMP_SITES: usize = 16;
+
+/// Marks an allocation that is not accounted to a call site.
+const NO_SITE: u16 = u16::MAX;
+
+static QUARANTINE: spin::Mutex<Quarantine> = spin::Mutex::new(Quarantine::new());
+static SITES: spin::Mutex<Sites> = spin::Mutex::new(Sites::new());
+
+/// Call sites are only recorded once the backtraces can be captured.
+static TRACK_SITES: AtomicBool = AtomicBool::new(false);
+
+#[repr(C)]
+struct Header {
+    magic: u32,
+    site: u16,
+    size: usize,
+}
+
+/// A detected heap corruption.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Corruption {
+    /// The memory before the allocation was overwritten.
+    LeftRedzone,
+    /// The memory after the allocation was overwritten.
+    RightRedzone,
+    /// The allocation has already been freed.
+    DoubleFree,
+    /// The header of the allocation was overwritten or the pointer was not allocated.
+    BadHeader,
+    /// The allocation was written to after it had been freed.
+    UseAfterFree,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LeftRedzone => "heap buffer underflow",
+            Self::RightRedzone => "heap buffer overflow",
+            Self::DoubleFree => "double free",
+            Self::BadHeader => "invalid free or corrupted header",
+            Self::UseAfterFree => "write after free",
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Site {
+    frames: [usize; SITE_DEPTH],
+    count: usize,
+    bytes: usize,
+}
+
+impl Site {
+    const EMPTY: Self = Self {
+        frames: [0; SITE_DEPTH],
+        count: 0,
+        bytes: 0,
+    };
+}
+
+struct Sites {
+    sites: [Site; MAX_SITES],
+}
+
+impl Sites {
+    const fn new() -> Self {
+        Self {
+            sites: [Site::EMPTY; MAX_SITES],
+        }
+    }
+
+    /// Returns the index of the call site with the provided backtrace, adding it if needed.
+    fn find_or_insert(&mut self, frames: &[usize; SITE_DEPTH]) -> Option<u16> {
+        let hash = frames
+            .iter()
+            .fold(0usize, |hash, &frame| hash.rotate_left(5) ^ frame);
+
+        for probe in 0..MAX_SITES {
+            let index = (hash + probe) % MAX_SITES;
+            let site = &mut self.sites[index];
+
+            if site.frames[0] == 0 {
+                site.frames = *frames;
+                return Some(index as u16);
+            }
+
+            if site.frames == *frames {
+                return Some(index as u16);
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Quarantined {
+    ptr: usize,
+    size: usize,
+    align: usize,
+}
+
+struct Quarantine {
+    blocks: [Quarantined; QUARANTINE_LEN],
+    next: usize,
+}
+
+impl Quarantine {
+    const fn new() -> Self {
+        Self {
+            blocks: [Quarantined {
+                ptr: 0,
+                size: 0,
+                align: 0,
+            }; QUARANTINE_LEN],
+            next: 0,
+        }
+    }
+
+    /// Puts the block in the quarantine and returns the block that has been in the quarantine
+    /// for the longest time, if it is full.
+    fn push(&mut self, block: Quarantined) -> Option<Quarantined> {
+        let evicted = core::mem::replace(&mut self.blocks[self.next], block);
+        self.next = (self.next + 1) % QUARANTINE_LEN;
+
+        (evicted.ptr != 0).then_some(evicted)
+    }
+}
+
+/// Returns the layout of the underlying allocation and the offset of the allocation data in it.
+fn inner_layout(layout: Layout) -> (Layout, usize) {
+    let align = layout.align().max(core::mem::align_of::<Header>());
+    let offset = align_up(
+        (core::mem::size_of::<Header>() + REDZONE_SIZE) as u64,
+        align as u64,
+    ) as usize;
+
+    let size = offset + layout.size() + REDZONE_SIZE;
+
+    // SAFETY: The alignment is a power of two and the size cannot overflow for a valid layout
+    // and does not either for the small amount added.
+    (
+        unsafe { Layout::from_size_align_unchecked(size, align) },
+        offset,
+    )
+}
+
+fn record_site(size: usize) -> u16 {
+    if !TRACK_SITES.load(Ordering::Relaxed) {
+        return NO_SITE;
+    }
+
+    let mut frames = [0; SITE_DEPTH];
+    unwind::capture_stack_trace(&mut frames);
+
+    let mut sites = SITES.lock();
+    let Some(index) = sites.find_or_insert(&frames) else {
+        return NO_SITE;
+    };
+
+    let site = &mut sites.sites[index as usize];
+    site.count += 1;
+    site.bytes += size;
+
+    index
+}
+
+fn release_site(index: u16, size: usize) {
+    if index == NO_SITE {
+        return;
+    }
+
+    let mut sites = SITES.lock();
+    let site = &mut sites.sites[index as usize];
+
+    site.count -= 1;
+    site.bytes -= size;
+}
+
+fn report(corruption: Corruption, ptr: *const u8, header: &Header) -> ! {
+    let frames = match header.site {
+        NO_SITE => [0; SITE_DEPTH],
+        site => SITES.lock().sites[site as usize].frames,
+    };
+
+    log::error!(
+        "kasan: {corruption} on {ptr:p} (size={} bytes)",
+        header.size
+    );
+
+    log::error!("kasan: allocated at:");
+    unwind::log_stack_trace(frames.split(|&frame| frame == 0).next().unwrap());
+
+    panic!("kasan: {corruption} on {ptr:p}");
+}
+
+/// Allocates `layout` with `alloc`, surrounded by redzones.
+pub fn alloc(layout: Layout, alloc: impl FnOnce(Layout) -> *mut u8) -> *mut u8 {
+    // Interrupt handlers allocate as well.
+    let _guard = IrqGuard::new();
+
+    let (inner, offset) = inner_layout(layout);
+    let base = alloc(inner);
+
+    if base.is_null() {
+        return base;
+    }
+
+    let site = record_site(layout.size());
+
+    // SAFETY: The underlying allocation is large enough for the header, the redzones and the
+    // allocation data.
+    unsafe {
+        base.cast::<Header>().write(Header {
+            magic: LIVE_MAGIC,
+            site,
+            size: layout.size(),
+        });
+
+        let header_size = core::mem::size_of::<Header>();
+        let ptr = base.add(offset);
+
+        base.add(header_size)
+            .write_bytes(REDZONE_BYTE, offset - header_size);
+        ptr.add(layout.size())
+            .write_bytes(REDZONE_BYTE, REDZONE_SIZE);
+
+        ptr
+    }
+}
+
+/// Checks the header and the redzones of the allocation at `ptr` with the provided layout.
+///
+/// ## Safety
+///
+/// `ptr` must have been returned by [`alloc`] with the same `layout`.
+pub unsafe fn check(ptr: *mut u8, layout: Layout) -> Result<(), Corruption> {
+    let (_, offset) = inner_layout(layout);
+    let base = ptr.sub(offset);
+    let header = &*base.cast::<Header>();
+
+    match header.magic {
+        LIVE_MAGIC if header.size == layout.size() => {}
+        FREED_MAGIC => return Err(Corruption::DoubleFree),
+        _ => return Err(Corruption::BadHeader),
+    }
+
+    let header_size = core::mem::size_of::<Header>();
+    let left = core::slice::from_raw_parts(base.add(header_size), offset - header_size);
+    let right = core::slice::from_raw_parts(ptr.add(layout.size()), REDZONE_SIZE);
+
+    if left.iter().any(|&byte| byte != REDZONE_BYTE) {
+        Err(Corruption::LeftRedzone)
+    } else if right.iter().any(|&byte| byte != REDZONE_BYTE) {
+        Err(Corruption::RightRedzone)
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks the allocation at `ptr`, poisons it and puts it in the quarantine. The allocation
+/// that leaves the quarantine is checked for writes after free and freed with `dealloc`.
+///
+/// ## Safety
+///
+/// `ptr` must have been returned by [`alloc`] with the same `layout`.
+pub unsafe fn dealloc(ptr: *mut u8, layout: Layout, dealloc: impl Fn(*mut u8, Layout)) {
+    let _guard = IrqGuard::new();
+
+    let (inner, offset) = inner_layout(layout);
+    let base = ptr.sub(offset);
+
+    if let Err(corruption) = check(ptr, layout) {
+        report(corruption, ptr, &*base.cast::<Header>());
+    }
+
+    let header = &mut *base.cast::<Header>();
+
+    release_site(header.site, header.size);
+
+    header.magic = FREED_MAGIC;
+
+    let header_size = core::mem::size_of::<Header>();
+    base.add(header_size)
+        .write_bytes(POISON_BYTE, inner.size() - header_size);
+
+    let evicted = QUARANTINE.lock().push(Quarantined {
+        ptr: base as usize,
+        size: inner.size(),
+        align: inner.align(),
+    });
+
+    if let Some(block) = evicted {
+        let base = block.ptr as *mut u8;
+        let header = &*base.cast::<Header>();
+
+        let header_size = core::mem::size_of::<Header>();
+        let data = core::slice::from_raw_parts(base.add(header_size), block.size - header_size);
+
+        if data.iter().any(|&byte| byte != POISON_BYTE) {
+            report(Corruption::UseAfterFree, base.add(header_size), header);
+        }
+
+        dealloc(
+            base,
+            Layout::from_size_align_unchecked(block.size, block.align),
+        );
+    }
+}
+
+/// Starts accounting allocations to their call sites. Called once backtraces can be captured.
+pub fn init() {
+    TRACK_SITES.store(true, Ordering::SeqCst);
+}
+
+/// Logs the call sites with the most outstanding allocated bytes.
+pub fn dump() {
+    let mut top = [Site::EMPTY; DUMP_SITES];
+    let mut total_count = 0;
+    let mut total_bytes = 0;
+
+    // Collect the call sites first, as logging allocates.
+    {
+        let _guard = IrqGuard::new();
+        let sites = SITES.lock();
+
+        for site in sites.sites.iter().filter(|site| site.count != 0) {
+            total_count += site.count;
+            total_bytes += site.bytes;
+
+            // Replace the call site with the least bytes if this one has more.
+            let min = top.iter_mut().min_by_key(|site| site.bytes).unwrap();
+
+            if site.bytes > min.bytes {
+                *min = *site;
+            }
+        }
+    }
+
+    top.sort_unstable_by(|a, b| b.bytes.cmp(&a.bytes));
+
+    log::info!("kasan: {total_count} outstanding allocations ({total_bytes} bytes)");
+
+    for site in top.iter().filter(|site| site.count != 0) {
+        // Skip the frames of the allocator itself.
+        let caller = site
+            .frames
+            .iter()
+            .filter(|&&frame| frame != 0)
+            .map(|&frame| (frame, unwind::resolve_symbol(frame)))
+            .find(|(_, symbol)| {
+                let Some(symbol) = symbol else {
+                    return true;
+                };
+
+                let name = symbol.to_string();
+                ![
+                    "alloc::",
+                    "core::",
+                    "__rust",
+                    "aero_kernel::mem::kasan::",
+                    "<aero_kernel::mem::alloc::",
+                    "<alloc::",
+                ]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+            });
+
+        match caller {
+            Some((rip, Some(symbol))) => log::info!(
+                "{:>10} bytes in {:>6} allocations at 0x{rip:016x} - {symbol:#}",
+                site.bytes,
+                site.count
+            ),
+
+            Some((rip, None)) => log::info!(
+                "{:>10} bytes in {:>6} allocations at 0x{rip:016x} - <unknown>",
+                site.bytes,
+                site.count
+            ),
+
+            None => log::info!(
+                "{:>10} bytes in {:>6} allocations at <unknown>",
+                site.bytes,
+                site.count
+            ),
+        }
+    }
+}
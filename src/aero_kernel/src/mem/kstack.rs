@@ -0,0 +1,292 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+//! Kernel stacks are allocated from a dedicated area of the kernel address space, starting
+//! at [`KSTACK_START`]. The area is split into equally sized slots, each holding an unmapped
+//! guard page followed by the stack itself:
+//!
+//! ```text
+//! | guard page | stack (KERNEL_STACK_SIZE) | guard page | stack (KERNEL_STACK_SIZE) | ...
+//! ```
+//!
+//! Since stacks grow downwards, overflowing a stack hits its guard page and faults instead of
+//! silently corrupting whatever is mapped below it.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+
+use crate::utils::sync::Mutex;
+
+use super::paging::*;
+use super::AddressSpace;
+
+/// Size of each kernel stack, excluding its guard page. Debug builds have much larger stack
+/// frames, so they get a larger stack.
+pub const KERNEL_STACK_SIZE: usize = if cfg!(debug_assertions) {
+    32 * Size4KiB::SIZE as usize
+} else {
+    16 * Size4KiB::SIZE as usize
+};
+
+const KSTACK_SLOT_SIZE: usize = KERNEL_STACK_SIZE + Size4KiB::SIZE as usize;
+const KSTACK_MAX_SLOTS: usize = 65536;
+
+const KSTACK_START: VirtAddr = VirtAddr::new(0xfffff90000000000);
+const KSTACK_END: VirtAddr = VirtAddr::new(KSTACK_START.as_u64() + KSTACK_AREA_SIZE as u64);
+const KSTACK_AREA_SIZE: usize = KSTACK_MAX_SLOTS * KSTACK_SLOT_SIZE;
+
+/// The lowest part of every stack is painted with [`STACK_PAINT`] in debug builds. Once the
+/// topmost painted word is overwritten, the stack usage has crossed the warning threshold.
+const STACK_PAINT_SIZE: usize = KERNEL_STACK_SIZE / 4;
+const STACK_PAINT: u64 = 0xdeadbeefdeadbeef;
+
+static KERNEL_STACKS: Mutex<KernelStackSlots> = Mutex::new(KernelStackSlots::new());
+
+/// Incremented every time a stack is unmapped.
+static UNMAP_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// The value of [`UNMAP_GENERATION`] when this CPU last flushed its TLB in [`flush_stale`].
+#[cpu_local]
+static mut FLUSHED_GENERATION: usize = 0;
+
+struct KernelStackSlots {
+    /// Index of the first slot that has never been handed out.
+    next: usize,
+    /// Slots that have been handed out before and were freed since.
+    free: Vec<usize>,
+}
+
+impl KernelStackSlots {
+    const fn new() -> Self {
+        Self {
+            next: 0,
+            free: Vec::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(slot) = self.free.pop() {
+            return Some(slot);
+        }
+
+        if self.next == KSTACK_MAX_SLOTS {
+            return None;
+        }
+
+        self.next += 1;
+        Some(self.next - 1)
+    }
+}
+
+pub struct KernelStack {
+    slot: usize,
+    /// Set once the stack usage warning has been logged for this stack.
+    warned: bool,
+}
+
+impl KernelStack {
+    /// Allocates and maps a new kernel stack.
+    pub fn alloc() -> Result<Self, MapToError<Size4KiB>> {
+        let slot = KERNEL_STACKS
+            .lock_irq()
+            .alloc()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+
+        let mut this = Self {
+            slot,
+            warned: false,
+        };
+
+        let mut mapped = 0;
+
+        if let Err(err) = this.map(&mut mapped) {
+            // Unmap the pages that were mapped before the failure and give the slot back.
+            this.release(mapped);
+            return Err(err);
+        }
+
+        if cfg!(debug_assertions) {
+            this.painted().fill(STACK_PAINT);
+        }
+
+        Ok(this)
+    }
+
+    /// Unmaps the stack and frees its memory.
+    pub fn dealloc(self) {
+        self.release(KERNEL_STACK_SIZE / Size4KiB::SIZE as usize);
+    }
+
+    /// Maps the pages of the stack to newly allocated frames, counting the pages that were
+    /// mapped in `mapped`.
+    fn map(&self, mapped: &mut usize) -> Result<(), MapToError<Size4KiB>> {
+        let mut address_space = AddressSpace::this();
+        let mut offset_table = address_space.offset_page_table();
+
+        for page in self.pages() {
+            let frame: PhysFrame<Size4KiB> = FRAME_ALLOCATOR
+                .alloc_zeroed(Size4KiB::SIZE as usize)
+                .map(PhysFrame::containing_address)
+                .ok_or(MapToError::FrameAllocationFailed)?;
+
+            let flags =
+                PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+
+            match unsafe { offset_table.map_to(page, frame, flags) } {
+                Ok(flush) => flush.flush(),
+                Err(err) => {
+                    FRAME_ALLOCATOR.deallocate_frame(frame);
+                    return Err(err);
+                }
+            }
+
+            *mapped += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Unmaps the first `count` pages of the stack, which frees their frames, and releases its
+    /// slot.
+    fn release(self, count: usize) {
+        let mut address_space = AddressSpace::this();
+        let mut offset_table = address_space.offset_page_table();
+
+        for page in self.pages().take(count) {
+            // unmap the page at the address which in turn will deallocate
+            // the frame (refcnt == 0).
+            offset_table.unmap(page).unwrap().1.flush();
+        }
+
+        // The other CPUs may still have the stack in their TLB. This runs with interrupts
+        // disabled, so instead of waiting for them to flush it, they do so in `flush_stale`
+        // before they switch to another task, which is the only way they can reach the stack
+        // once the slot is reused.
+        UNMAP_GENERATION.fetch_add(1, Ordering::SeqCst);
+        KERNEL_STACKS.lock_irq().free.push(self.slot);
+    }
+
+    /// Returns the pages of the stack, excluding its guard page.
+    fn pages(&self) -> PageRange {
+        let start_page: Page = Page::containing_address(self.bottom());
+        let end_page = Page::containing_address(self.top());
+
+        Page::range(start_page, end_page)
+    }
+
+    /// Returns the lowest address of the stack.
+    pub fn bottom(&self) -> VirtAddr {
+        slot_address(self.slot) + Size4KiB::SIZE
+    }
+
+    /// Returns the address right above the stack, which is the initial stack pointer.
+    pub fn top(&self) -> VirtAddr {
+        self.bottom() + KERNEL_STACK_SIZE
+    }
+
+    /// Returns true if `addr` lies in the guard page of this stack.
+    pub fn guard_page_contains(&self, addr: VirtAddr) -> bool {
+        (slot_address(self.slot)..self.bottom()).contains(&addr)
+    }
+
+    /// Returns the maximum number of bytes that have been used on this stack, the first time
+    /// it crosses the warning threshold. Always returns `None` in release builds.
+    pub fn check_usage(&mut self) -> Option<usize> {
+        if !cfg!(debug_assertions) || self.warned {
+            return None;
+        }
+
+        let painted = self.painted();
+
+        if painted.last() == Some(&STACK_PAINT) {
+            return None;
+        }
+
+        let unused = painted
+            .iter()
+            .take_while(|word| **word == STACK_PAINT)
+            .count()
+            * 8;
+
+        self.warned = true;
+        Some(KERNEL_STACK_SIZE - unused)
+    }
+
+    fn painted(&mut self) -> &mut [u64] {
+        // SAFETY: The painted area is at the bottom of the stack, which is mapped.
+        unsafe { core::slice::from_raw_parts_mut(self.bottom().as_mut_ptr(), STACK_PAINT_SIZE / 8) }
+    }
+}
+
+fn slot_address(slot: usize) -> VirtAddr {
+    KSTACK_START + slot * KSTACK_SLOT_SIZE
+}
+
+/// Returns true if `addr` lies in the guard page of any kernel stack.
+pub fn is_guard_page(addr: VirtAddr) -> bool {
+    if !(KSTACK_START..KSTACK_END).contains(&addr) {
+        return false;
+    }
+
+    (addr - KSTACK_START) as usize % KSTACK_SLOT_SIZE < Size4KiB::SIZE as usize
+}
+
+/// Flushes the TLB of the current CPU if a stack was unmapped since it last did. Must be called
+/// before switching to a task, as its stack may be in a slot that was reused since.
+pub fn flush_stale() {
+    let generation = UNMAP_GENERATION.load(Ordering::SeqCst);
+
+    // SAFETY: The CPU-local generation is only accessed by the current CPU. If a context switch
+    // interrupts this one, the TLB is at worst flushed twice.
+    unsafe {
+        if *FLUSHED_GENERATION == generation {
+            return;
+        }
+
+        // Reloading CR3 flushes every translation that is not global, which includes the
+        // kernel stacks.
+        core::arch::asm!(
+            "mov cr3, {}",
+            in(reg) crate::arch::controlregs::read_cr3_raw(),
+            options(nostack)
+        );
+
+        *FLUSHED_GENERATION = generation;
+    }
+}
+
+pub fn init() {
+    // With 5-level paging, the whole kernel half is covered by a single top-level entry that
+    // is already in use.
+    if level_5_paging_enabled() {
+        return;
+    }
+
+    // Address spaces copy the top-level entries of the kernel half when they are created, so the
+    // table covering the kernel stacks has to exist before any of them is. Otherwise, stacks
+    // allocated later would not be mapped in the older address spaces.
+    let mut address_space = AddressSpace::this();
+    let entry = &mut address_space.page_table()[KSTACK_START.p4_index()];
+
+    if entry.is_unused() {
+        let frame = FRAME_ALLOCATOR
+            .alloc_zeroed(Size4KiB::SIZE as usize)
+            .expect("kstack: physical memory exhausted");
+
+        entry.set_addr(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+    }
+}
@@ -16,6 +16,9 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 pub mod alloc;
+#[cfg(feature = "kasan")]
+pub mod kasan;
+pub mod kstack;
 pub mod paging;
 pub mod pti;
 mod slab;
@@ -56,13 +56,18 @@ pub struct PhysAddr(u64);
 pub enum ReadErr {
     Null,
     NotAligned,
+    /// The address is not a mapped userspace address.
+    Fault,
 }
 
 impl From<ReadErr> for FileSystemError {
-    fn from(_: ReadErr) -> Self {
-        // `FileSystemError::NotSupported` will be converted to `EINVAL` on
-        // syscall error conversion.
-        FileSystemError::NotSupported
+    fn from(value: ReadErr) -> Self {
+        match value {
+            ReadErr::Fault => FileSystemError::Fault,
+            // `FileSystemError::NotSupported` will be converted to `EINVAL` on
+            // syscall error conversion.
+            ReadErr::Null | ReadErr::NotAligned => FileSystemError::NotSupported,
+        }
     }
 }
 
@@ -71,6 +76,7 @@ impl From<ReadErr> for aero_syscall::SyscallError {
         match value {
             ReadErr::Null => Self::EINVAL,
             ReadErr::NotAligned => Self::EACCES,
+            ReadErr::Fault => Self::EFAULT,
         }
     }
 }
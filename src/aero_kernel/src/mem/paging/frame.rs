@@ -87,6 +87,7 @@ impl LockedFrameAllocator {
                 Bitmap::empty(bstrap_ref),
             ],
             free: [0; 10],
+            total: 0,
 
             base: PhysAddr::zero(),
             end: PhysAddr::zero(),
@@ -118,6 +119,30 @@ impl LockedFrameAllocator {
 
         Some(addr)
     }
+
+    /// Returns the size of the usable memory, in bytes.
+    pub fn total_memory(&self) -> usize {
+        self.0.lock_irq().total as usize
+    }
+
+    /// Returns the size of the memory that is not allocated, in bytes.
+    pub fn free_memory(&self) -> usize {
+        let allocator = self.0.lock_irq();
+
+        allocator
+            .free
+            .iter()
+            .zip(BUDDY_SIZE)
+            .map(|(&count, size)| count * size as usize)
+            .sum()
+    }
+
+    /// Returns whether the block of `size_bytes` at `addr` is free as a whole.
+    #[cfg(test)]
+    pub fn is_free(&self, addr: PhysAddr, size_bytes: usize) -> bool {
+        let order = order_from_size(size_bytes as u64);
+        self.0.lock_irq().is_free(addr, order)
+    }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for LockedFrameAllocator {
@@ -302,6 +327,8 @@ static VM_FRAMES: Once<Vec<VmFrame>> = Once::new();
 pub struct GlobalFrameAllocator {
     buddies: [Bitmap<BootAllocRef>; 10],
     free: [usize; 10],
+    /// Size of the usable memory inserted in the allocator, in bytes.
+    total: u64,
 
     base: PhysAddr,
     end: PhysAddr,
@@ -381,6 +408,7 @@ impl GlobalFrameAllocator {
                 Bitmap::empty(bref),
             ],
             free: [0; 10],
+            total: 0,
         };
 
         let size = this.end - this.base;
@@ -458,6 +486,8 @@ impl GlobalFrameAllocator {
         let mut remaining = end - base;
         let mut current = base;
 
+        self.total += remaining;
+
         while remaining > 0 {
             let order = self.find_order(current, remaining);
             let size = BUDDY_SIZE[order];
@@ -1128,7 +1128,14 @@ impl<'a> Translate for OffsetPageTable<'a> {
 }
 
 impl<'a> OffsetPageTable<'a> {
-    pub fn copy_page_range(&mut self, src: &mut OffsetPageTable, range: RangeInclusive<VirtAddr>) {
+    /// Copies the page table entries in `range` from `src` into this page table, removing the
+    /// writable flag from both of them. The `clear` flags are only removed from the copies.
+    pub fn copy_page_range(
+        &mut self,
+        src: &mut OffsetPageTable,
+        range: RangeInclusive<VirtAddr>,
+        clear: PageTableFlags,
+    ) {
         let mut map_to = |src: &mut OffsetPageTable, addr, frame, flags| match frame {
             MappedFrame::Size4KiB(frame) => {
                 let page = Page::<Size4KiB>::containing_address(addr);
@@ -1137,7 +1144,7 @@ impl<'a> OffsetPageTable<'a> {
                     self.map_to_with_table_flags(
                         page,
                         frame,
-                        flags,
+                        flags & !clear,
                         PageTableFlags::PRESENT
                             | PageTableFlags::USER_ACCESSIBLE
                             | PageTableFlags::WRITABLE,
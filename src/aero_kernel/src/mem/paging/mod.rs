@@ -60,6 +60,40 @@ bitflags::bitflags! {
     }
 }
 
+/// Layout of the page attribute table, programmed into the `IA32_PAT` MSR of every CPU. The
+/// memory type of a page is selected by the `WRITE_THROUGH` (bit 0 of the index), `NO_CACHE`
+/// (bit 1) and PAT (bit 2) flags of its entry:
+///
+/// | Index | 0  | 1  | 2   | 3  | 4  | 5  | 6   | 7  |
+/// |-------|----|----|-----|----|----|----|-----|----|
+/// | Type  | WB | WC | UC- | UC | WB | WC | UC- | UC |
+///
+/// This is the default layout with write-through replaced by write-combining, which leaves the
+/// entries set up by the bootloader (write-combining at index 5 for the framebuffer) unchanged.
+pub const PAT_LAYOUT: u64 = 0x0007_0106_0007_0106;
+
+/// Memory type of a mapping, see [`PAT_LAYOUT`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Normal cacheable memory.
+    WriteBack,
+    /// Writes are buffered and combined before reaching memory. For framebuffers.
+    WriteCombining,
+    /// Every access goes to the device. For memory-mapped registers.
+    Uncached,
+}
+
+impl CacheMode {
+    /// Returns the page table flags selecting the memory type.
+    pub fn page_table_flags(self) -> PageTableFlags {
+        match self {
+            Self::WriteBack => PageTableFlags::empty(),
+            Self::WriteCombining => PageTableFlags::WRITE_THROUGH,
+            Self::Uncached => PageTableFlags::WRITE_THROUGH | PageTableFlags::NO_CACHE,
+        }
+    }
+}
+
 /// Returns true if level 5 paging is supported by the CPU and is enabled in Cr4.
 #[cfg(target_arch = "x86_64")]
 pub fn level_5_paging_enabled() -> bool {
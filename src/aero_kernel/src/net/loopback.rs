@@ -16,41 +16,86 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 //! Loopback device.
+//!
+//! Packets sent through the loopback device are queued and then received by the loopback thread,
+//! as if they came from the wire.
 
-use alloc::boxed::Box;
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use crabnet::data_link::MacAddr;
 use crabnet::network::Ipv4Addr;
 
-use crate::utils::dma::DmaAllocator;
+use crate::utils::sync::{Mutex, WaitQueue};
 
-use super::{NetworkDevice, NetworkDriver, RecvPacket};
+use super::{NetworkDevice, NetworkDriver, RawPacket, RecvPacket};
 
-pub struct Loopback;
+pub struct Loopback {
+    queue: Mutex<VecDeque<RawPacket>>,
+    wq: WaitQueue,
+    /// ID of the packet at the front of the queue.
+    next_id: AtomicUsize,
+}
+
+impl Loopback {
+    fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            wq: WaitQueue::new(),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+}
 
 impl NetworkDriver for Loopback {
-    fn send(&self, _packet: Box<[u8], DmaAllocator>) {
-        todo!()
+    fn send(&self, packet: RawPacket) {
+        self.queue.lock_irq().push_back(packet);
+        self.wq.notify_all();
     }
 
     fn recv(&self) -> RecvPacket {
-        todo!()
+        let queue = self
+            .wq
+            .block_on(&self.queue, |queue| !queue.is_empty())
+            .unwrap();
+
+        let packet = queue.front().unwrap();
+
+        // SAFETY: The loopback thread is the only receiver and the packet is only removed from the
+        // queue in `recv_end()`, so the packet stays alive while it is being processed.
+        let packet = unsafe { core::slice::from_raw_parts(packet.as_ptr(), packet.len()) };
+
+        RecvPacket {
+            packet,
+            id: self.next_id.load(Ordering::SeqCst),
+        }
     }
 
-    fn recv_end(&self, _packet_id: usize) {
-        todo!()
+    fn recv_end(&self, packet_id: usize) {
+        assert_eq!(packet_id, self.next_id.fetch_add(1, Ordering::SeqCst));
+        self.queue.lock_irq().pop_front();
     }
 
     #[inline]
     fn mac(&self) -> MacAddr {
-        // TODO: What should this really be?
         MacAddr::NULL
     }
+
+    #[inline]
+    fn mtu(&self) -> usize {
+        65536
+    }
+
+    #[inline]
+    fn is_loopback(&self) -> bool {
+        true
+    }
 }
 
 lazy_static::lazy_static! {
     pub static ref LOOPBACK: Arc<NetworkDevice> = {
-        let device = Arc::new(NetworkDevice::new(Arc::new(Loopback)));
+        let device = Arc::new(NetworkDevice::new(Arc::new(Loopback::new())));
 
         device.set_ip(Ipv4Addr::LOOPBACK);
         device.set_subnet_mask(Ipv4Addr::new(255, 0, 0, 0));
@@ -15,11 +15,13 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use aero_syscall::netlink::InterfaceFlags;
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use crabnet::transport::TcpOptions;
-use spin::RwLock;
+use spin::{Once, RwLock};
 
 pub mod arp;
 pub mod loopback;
@@ -39,12 +41,20 @@ pub trait NetworkDriver: Send + Sync {
     fn recv(&self) -> RecvPacket;
     fn recv_end(&self, packet_id: usize);
     fn mac(&self) -> MacAddr;
+
+    /// Returns the maximum size of a packet that can be sent through the device.
+    fn mtu(&self) -> usize {
+        1500
+    }
+
+    fn is_loopback(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Default)]
 struct Metadata {
     ip: Ipv4Addr,
-    #[allow(dead_code)]
     subnet_mask: Ipv4Addr,
     default_gateway: Ipv4Addr,
 }
@@ -55,6 +65,7 @@ struct Metadata {
 pub struct NetworkDevice {
     driver: Arc<dyn NetworkDriver>,
     metadata: RwLock<Metadata>,
+    name: Once<String>,
 }
 
 impl NetworkDevice {
@@ -72,6 +83,7 @@ impl NetworkDevice {
         Self {
             driver,
             metadata: RwLock::new(metadata),
+            name: Once::new(),
         }
     }
 
@@ -80,7 +92,7 @@ impl NetworkDevice {
     }
 
     pub fn set_subnet_mask(&self, mask: Ipv4Addr) {
-        self.metadata.write().ip = mask;
+        self.metadata.write().subnet_mask = mask;
     }
 
     pub fn ip(&self) -> Ipv4Addr {
@@ -95,6 +107,21 @@ impl NetworkDevice {
     pub fn default_gateway(&self) -> Ipv4Addr {
         self.metadata.read().default_gateway
     }
+
+    /// Returns the name of the interface (e.g. `lo` or `eth0`).
+    pub fn name(&self) -> &str {
+        self.name.get().expect("net: device was not registered")
+    }
+
+    pub fn flags(&self) -> InterfaceFlags {
+        let flags = InterfaceFlags::UP | InterfaceFlags::RUNNING;
+
+        if self.is_loopback() {
+            flags | InterfaceFlags::LOOPBACK
+        } else {
+            flags | InterfaceFlags::BROADCAST | InterfaceFlags::MULTICAST
+        }
+    }
 }
 
 impl core::ops::Deref for NetworkDevice {
@@ -111,25 +138,18 @@ pub struct RecvPacket<'a> {
     pub id: usize,
 }
 
-impl<'a> Drop for RecvPacket<'a> {
-    fn drop(&mut self) {
-        default_device().recv_end(self.id)
-    }
-}
-
 static DEVICES: RwLock<Vec<Arc<NetworkDevice>>> = RwLock::new(Vec::new());
 static DEFAULT_DEVICE: RwLock<Option<Arc<NetworkDevice>>> = RwLock::new(None);
 
-fn packet_processor_thread() {
+fn process_packets(device: Arc<NetworkDevice>) -> ! {
     use crabnet::data_link::{Arp, Eth, EthType};
     use crabnet::network::{Ipv4, Ipv4Type};
     use crabnet::transport::{Tcp, Udp};
     use crabnet::PacketParser;
 
-    let device = default_device();
-
     loop {
         let packet = device.recv();
+        let id = packet.id;
 
         let mut parser = PacketParser::new(packet.packet);
         let eth = parser.next::<Eth>();
@@ -144,7 +164,7 @@ fn packet_processor_thread() {
                         let size = ip.payload_len() as usize - core::mem::size_of::<Udp>();
 
                         let payload = &parser.payload()[..size];
-                        udp::on_packet(udp, payload);
+                        udp::on_packet(ip.src_ip(), udp, payload);
                     }
 
                     Ipv4Type::Tcp => {
@@ -162,12 +182,30 @@ fn packet_processor_thread() {
                 arp::do_recv(parser.next::<Arp>());
             }
         }
+
+        device.recv_end(id);
     }
 }
 
+fn packet_processor_thread() {
+    process_packets(default_device())
+}
+
+/// Receives the packets sent to the loopback device. The packets are processed here, instead of
+/// in [`NetworkDriver::send`], as sending a reply from the receive path would otherwise recurse.
+fn loopback_thread() {
+    process_packets(loopback::LOOPBACK.clone())
+}
+
 pub fn add_device(device: NetworkDevice) {
     let device = Arc::new(device);
-    DEVICES.write().push(device.clone());
+
+    let mut devices = DEVICES.write();
+    let index = devices.iter().filter(|e| !e.is_loopback()).count();
+
+    device.name.call_once(|| alloc::format!("eth{index}"));
+    devices.push(device.clone());
+    core::mem::drop(devices);
 
     let mut default_device = DEFAULT_DEVICE.write();
     if default_device.is_none() {
@@ -177,6 +215,24 @@ pub fn add_device(device: NetworkDevice) {
     scheduler::get_scheduler().register_task(Task::new_kernel(packet_processor_thread, true));
 }
 
+/// Returns all of the registered network devices. The index of a device in the list, plus one, is
+/// its interface index.
+pub fn devices() -> Vec<Arc<NetworkDevice>> {
+    DEVICES.read().clone()
+}
+
+/// Returns the device that packets sent to `ip` are routed through. Packets sent to the loopback
+/// network (`127.0.0.0/8`) never leave the host.
+pub fn route(ip: Ipv4Addr) -> Arc<NetworkDevice> {
+    let lo = loopback::LOOPBACK.clone();
+
+    if ip.is_same_subnet(lo.ip(), lo.subnet_mask()) {
+        lo
+    } else {
+        default_device()
+    }
+}
+
 pub fn has_default_device() -> bool {
     DEFAULT_DEVICE.read().as_ref().is_some()
 }
@@ -191,12 +247,18 @@ pub fn default_device() -> Arc<NetworkDevice> {
 
 // Initialize the networking stack.
 pub fn init() {
+    let lo = loopback::LOOPBACK.clone();
+    lo.name.call_once(|| "lo".into());
+
+    // The loopback device always comes first, like on Linux.
+    DEVICES.write().insert(0, lo);
+    scheduler::get_scheduler().register_task(Task::new_kernel(loopback_thread, true));
+
     if !has_default_device() {
         // No network devices are avaliable.
         return;
     }
 
-    DEVICES.write().push(loopback::LOOPBACK.clone());
     arp::init();
     log::info!("net::arp: initialized cache");
 }
@@ -220,13 +282,18 @@ pub mod shim {
     // TODO(andypython): Can all of the packet send impls be refactored?
     impl<T: Protocol, U: Protocol> PacketSend for Stacked<Stacked<Stacked<Eth, Ipv4>, T>, U> {
         fn send(mut self) {
-            let device = net::default_device();
-
             let eth = &mut self.upper.upper.upper;
             let ip = &self.upper.upper.lower;
 
             let mut dest_ip = ip.dest_ip();
 
+            let device = net::route(dest_ip);
+            if device.is_loopback() {
+                // No need to resolve the MAC address, the packet never leaves the host.
+                device.send(self.into_boxed_bytes_in(DmaAllocator));
+                return;
+            }
+
             if !dest_ip.is_broadcast() && !dest_ip.is_same_subnet(device.ip(), device.subnet_mask())
             {
                 dest_ip = device.default_gateway();
@@ -247,13 +314,18 @@ pub mod shim {
         for Stacked<Stacked<Stacked<Stacked<Eth, Ipv4>, T>, U>, S>
     {
         fn send(mut self) {
-            let device = net::default_device();
-
             let eth = &mut self.upper.upper.upper.upper;
             let ip = &self.upper.upper.upper.lower;
 
             let mut dest_ip = ip.dest_ip();
 
+            let device = net::route(dest_ip);
+            if device.is_loopback() {
+                // No need to resolve the MAC address, the packet never leaves the host.
+                device.send(self.into_boxed_bytes_in(DmaAllocator));
+                return;
+            }
+
             if !dest_ip.is_broadcast() && !dest_ip.is_same_subnet(device.ip(), device.subnet_mask())
             {
                 dest_ip = device.default_gateway();
@@ -22,13 +22,13 @@ use spin::RwLock;
 use crabnet::network::Ipv4Addr;
 use crabnet::transport::Udp;
 
-pub fn on_packet(udp: &Udp, payload: &[u8]) {
+pub fn on_packet(src: Ipv4Addr, udp: &Udp, payload: &[u8]) {
     let dest_port = udp.dst_port();
 
     let handlers = HANDLERS.read();
 
     if let Some(handler) = handlers.get(&dest_port) {
-        handler.recv(udp, payload);
+        handler.recv(src, udp, payload);
     } else {
         log::warn!("udp: no handler registered for port {}", dest_port);
     }
@@ -37,7 +37,7 @@ pub fn on_packet(udp: &Udp, payload: &[u8]) {
 static HANDLERS: RwLock<BTreeMap<u16, Arc<dyn UdpHandler>>> = RwLock::new(BTreeMap::new());
 
 pub trait UdpHandler: Send + Sync {
-    fn recv(&self, udp: &Udp, payload: &[u8]);
+    fn recv(&self, src: Ipv4Addr, udp: &Udp, payload: &[u8]);
 }
 
 pub fn alloc_ephemeral_port(socket: Arc<dyn UdpHandler>) -> Option<u16> {
@@ -15,6 +15,8 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+pub mod psf;
+
 use core::fmt::Write;
 
 use core::fmt;
@@ -30,14 +32,22 @@ use vte::ansi::{Handler, NamedColor, Timeout};
 
 use crate::cmdline::CommandLine;
 use crate::mem;
-use crate::mem::paging::align_up;
+use crate::mem::paging::{align_up, VirtAddr};
 
 use crate::utils::sync::Mutex;
 
 use vte::ansi::{Attr, Processor};
 
-static FONT: &[[u8; FONT_HEIGHT]; FONT_GLYPHS] =
-    unsafe { &core::mem::transmute(*include_bytes!("../../font.bin")) };
+use self::psf::Font;
+
+/// The VGA font, used unless a font is loaded with the `font` command line option.
+static DEFAULT_FONT: &[u8] = include_bytes!("../../font.bin");
+const DEFAULT_FONT_HEIGHT: usize = 16;
+
+/// Returns the built-in VGA font.
+pub fn default_font() -> Font<'static> {
+    Font::raw(DEFAULT_FONT, DEFAULT_FONT_HEIGHT)
+}
 
 // This is an example of how the rendered screen will look like:
 //
@@ -53,10 +63,6 @@ static FONT: &[[u8; FONT_HEIGHT]; FONT_GLYPHS] =
 // -----------------------------------------------------|
 // ```
 
-const FONT_WIDTH: usize = 8;
-const FONT_HEIGHT: usize = 16;
-const FONT_GLYPHS: usize = 256;
-
 const DEFAULT_MARGIN: usize = 64 / 2;
 const TAB_SIZE: usize = 4;
 
@@ -70,11 +76,35 @@ const DEFAULT_TEXT_FOREGROUND: u32 = 0xaaaaaa;
 
 pub const DEFAULT_THEME_BACKGROUND: u32 = 0x50000000;
 
+bitflags::bitflags! {
+    /// Rendering attributes of a character, set with SGR escape sequences.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct CharAttributes: u8 {
+        /// The glyph is drawn twice, one pixel apart.
+        const BOLD = 1 << 0;
+        /// The foreground color is drawn at half its brightness.
+        const DIM  = 1 << 1;
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
-struct Character {
-    char: char,
-    fg: u32,
-    bg: u32,
+pub struct Character {
+    pub char: char,
+    pub fg: u32,
+    /// [`DEFAULT_TEXT_BACKGROUND`] draws the background canvas behind the glyph.
+    pub bg: u32,
+    pub attrs: CharAttributes,
+}
+
+impl Character {
+    fn blank(fg: u32, bg: u32) -> Self {
+        Self {
+            char: ' ',
+            fg,
+            bg,
+            attrs: CharAttributes::empty(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -280,6 +310,7 @@ impl Index<usize> for ColorList {
 pub struct Inner<'this> {
     buffer: &'this mut [u32],
     info: RendyInfo,
+    font: Font<'static>,
 
     x_pos: usize,
     y_pos: usize,
@@ -291,6 +322,7 @@ pub struct Inner<'this> {
     cols: usize,
 
     color: ColorCode,
+    attrs: CharAttributes,
     theme_background: u32,
 
     queue: Box<[QueueCharacter]>,
@@ -305,8 +337,12 @@ pub struct Inner<'this> {
 
     cursor_visibility: bool,
     auto_flush: bool,
+    /// Set while a DRM client owns the display. Output is still recorded in the grid, but
+    /// nothing is drawn until the console is given the display back.
+    suspended: bool,
 
     color_list: ColorList,
+    background: Option<Image>,
 }
 
 impl<'a> Inner<'a> {
@@ -426,16 +462,19 @@ impl<'a> Inner<'a> {
         });
     }
 
-    fn generate_canvas(&mut self, image: Option<Image>) {
+    fn generate_canvas(&mut self) {
         let width = self.info.horizontal_resolution;
         let height = self.info.vertical_resolution;
 
-        if let Some(image) = image {
-            let frame_width = width / 2 - (FONT_WIDTH * self.cols) / 2;
-            let frame_height = height / 2 - (FONT_HEIGHT * self.rows) / 2;
+        if let Some(image) = self.background.take() {
+            let font_width = self.font.width();
+            let font_height = self.font.height();
+
+            let frame_width = width / 2 - (font_width * self.cols) / 2;
+            let frame_height = height / 2 - (font_height * self.rows) / 2;
 
-            let frame_width_end = frame_width + FONT_WIDTH * self.cols;
-            let frame_height_end = frame_height + FONT_HEIGHT * self.rows;
+            let frame_width_end = frame_width + font_width * self.cols;
+            let frame_height_end = frame_height + font_height * self.rows;
 
             let fheight = frame_height - MARGIN_GRADIENT;
             let fheight_end = frame_height_end + MARGIN_GRADIENT;
@@ -454,6 +493,8 @@ impl<'a> Inner<'a> {
                 frame_height,
                 frame_height_end,
             );
+
+            self.background = Some(image);
         } else {
             for y in 0..height {
                 for x in 0..width {
@@ -504,11 +545,7 @@ impl<'a> Inner<'a> {
     }
 
     fn clear(&mut self, mv: bool) {
-        let char = Character {
-            char: ' ',
-            fg: self.color.get_foreground(),
-            bg: self.color.get_background(),
-        };
+        let char = Character::blank(self.color.get_foreground(), self.color.get_background());
 
         for i in 0..self.rows * self.cols {
             self.push_to_queue(&char, i % self.cols, i / self.cols);
@@ -546,51 +583,41 @@ impl<'a> Inner<'a> {
     }
 
     fn plot_char(&mut self, x: usize, y: usize, char: Character) {
-        let ch = match char.char {
-            ch if ch.is_ascii() => ch,
-            _ => '?',
-        };
-
         if x >= self.cols || y >= self.rows {
             return;
         }
 
-        let x = self.offset_x + x * FONT_WIDTH;
-        let y = self.offset_y + y * FONT_HEIGHT;
-        let glyph = &FONT[ch as usize];
-
-        // naming: fx, fy for font coordinates and gx, gy for glyph coordinates
-        for (gy, glyph) in glyph.iter().enumerate().take(FONT_HEIGHT) {
-            let fb_line = unsafe {
-                self.buffer
-                    .as_mut_ptr()
-                    .add(x + (y + gy) * (self.info.stride / 4))
-            };
-
-            let canvas_line = unsafe {
-                self.bg_canvas
-                    .as_mut_ptr()
-                    .add(x + (y + gy) * self.info.horizontal_resolution)
-            };
-
-            for gx in 0..FONT_WIDTH {
-                let draw = *glyph & (1 << (FONT_WIDTH - gx - 1)) != 0;
-                let color = if draw {
-                    char.fg
-                } else if char.bg == u32::MAX {
-                    unsafe { *canvas_line.add(gx) }
-                } else {
-                    char.bg
-                };
+        let width = self.font.width();
+        let height = self.font.height();
 
-                unsafe {
-                    *fb_line.add(gx) = color;
-                }
+        let x = self.offset_x + x * width;
+        let y = self.offset_y + y * height;
+        let stride = self.info.stride / DWORD_SIZE;
+
+        if char.bg == DEFAULT_TEXT_BACKGROUND {
+            for gy in 0..height {
+                let fb_line = x + (y + gy) * stride;
+                let canvas_line = x + (y + gy) * self.info.horizontal_resolution;
+
+                self.buffer[fb_line..fb_line + width]
+                    .copy_from_slice(&self.bg_canvas[canvas_line..canvas_line + width]);
             }
         }
+
+        rasterize(
+            &self.font,
+            &char,
+            &mut self.buffer[x + y * stride..],
+            stride,
+        );
     }
 
     fn double_buffer_flush(&mut self) {
+        if self.suspended {
+            self.commit_queue();
+            return;
+        }
+
         if self.cursor_visibility {
             self.draw_cursor();
         }
@@ -623,11 +650,94 @@ impl<'a> Inner<'a> {
         self.queue_cursor = 0;
     }
 
+    /// Moves the queued characters into the grid without drawing them.
+    fn commit_queue(&mut self) {
+        for i in 0..self.queue_cursor {
+            let queue = &self.queue[i];
+            let offset = queue.y * self.cols + queue.x;
+
+            if self.map[offset].take().is_some() {
+                self.grid[offset] = queue.char;
+            }
+        }
+
+        self.old_x_pos = self.x_pos;
+        self.old_y_pos = self.y_pos;
+
+        self.queue_cursor = 0;
+    }
+
+    /// Redraws the background and every character of the grid.
+    fn repaint(&mut self) {
+        self.commit_queue();
+        self.generate_canvas();
+
+        for i in 0..self.rows * self.cols {
+            self.plot_char(i % self.cols, i / self.cols, self.grid[i]);
+        }
+
+        self.double_buffer_flush();
+    }
+
+    /// Switches to `buffer`, rebuilding the character grid if the geometry of the display
+    /// changed. The most recent output is kept and the screen is repainted.
+    fn reconfigure(&mut self, buffer: &'a mut [u32], info: RendyInfo) {
+        self.commit_queue();
+
+        let same_geometry = info.horizontal_resolution == self.info.horizontal_resolution
+            && info.vertical_resolution == self.info.vertical_resolution
+            && info.stride == self.info.stride;
+
+        self.buffer = buffer;
+        self.info = info;
+        self.suspended = false;
+
+        if same_geometry {
+            self.repaint();
+            return;
+        }
+
+        let geometry = Geometry::new(&info, &self.font);
+        let blank = Character::blank(DEFAULT_TEXT_FOREGROUND, DEFAULT_TEXT_BACKGROUND);
+
+        let mut grid = mem::alloc_boxed_buffer::<Character>(geometry.rows * geometry.cols);
+        grid.fill(blank);
+
+        // If the console got shorter, drop the rows at the top so that the cursor line stays
+        // visible.
+        let skip = (self.y_pos + 1).saturating_sub(geometry.rows);
+
+        for y in 0..geometry.rows.min(self.rows - skip) {
+            for x in 0..geometry.cols.min(self.cols) {
+                grid[y * geometry.cols + x] = self.grid[(y + skip) * self.cols + x];
+            }
+        }
+
+        self.grid = grid;
+        self.queue = mem::alloc_boxed_buffer::<QueueCharacter>(geometry.rows * geometry.cols);
+        self.map = mem::alloc_boxed_buffer::<Option<NonNull<QueueCharacter>>>(
+            geometry.rows * geometry.cols,
+        );
+        self.bg_canvas =
+            mem::alloc_boxed_buffer::<u32>(info.horizontal_resolution * info.vertical_resolution);
+
+        self.rows = geometry.rows;
+        self.cols = geometry.cols;
+        self.offset_x = geometry.offset_x;
+        self.offset_y = geometry.offset_y;
+
+        self.x_pos = self.x_pos.min(self.cols - 1);
+        self.y_pos = (self.y_pos - skip).min(self.rows - 1);
+
+        self.repaint();
+    }
+
     fn raw_put_char(&mut self, char: char) {
         let char = Character {
             char,
             fg: self.color.get_foreground(),
             bg: self.color.get_background(),
+            attrs: self.attrs,
         };
 
         self.push_to_queue(&char, self.x_pos, self.y_pos);
@@ -697,11 +807,7 @@ impl<'a> Inner<'a> {
         }
 
         // Clear the last line of the screen.
-        let empty = Character {
-            char: ' ',
-            fg: self.color.get_foreground(),
-            bg: self.color.get_background(),
-        };
+        let empty = Character::blank(self.color.get_foreground(), self.color.get_background());
 
         for i in ((self.rows - 1) * self.cols)..self.rows * self.cols {
             self.push_to_queue(&empty, i % self.cols, i / self.cols);
@@ -717,6 +823,58 @@ impl<'a> Inner<'a> {
     }
 }
 
+/// Draws `char` with `font` into `pixels`, whose lines are `stride` pixels apart. The background
+/// of a character with the [`DEFAULT_TEXT_BACKGROUND`] is left as is.
+pub fn rasterize(font: &Font, char: &Character, pixels: &mut [u32], stride: usize) {
+    let glyph = font.glyph_index(char.char);
+    let bold = char.attrs.contains(CharAttributes::BOLD);
+
+    let fg = if char.attrs.contains(CharAttributes::DIM) {
+        (char.fg >> 1) & 0x7f7f7f
+    } else {
+        char.fg
+    };
+
+    for gy in 0..font.height() {
+        let line = &mut pixels[gy * stride..gy * stride + font.width()];
+
+        for (gx, pixel) in line.iter_mut().enumerate() {
+            let set =
+                font.is_set(glyph, gx, gy) || (bold && gx > 0 && font.is_set(glyph, gx - 1, gy));
+
+            if set {
+                *pixel = fg;
+            } else if char.bg != DEFAULT_TEXT_BACKGROUND {
+                *pixel = char.bg;
+            }
+        }
+    }
+}
+
+/// Layout of the character grid on a display.
+struct Geometry {
+    rows: usize,
+    cols: usize,
+    offset_x: usize,
+    offset_y: usize,
+}
+
+impl Geometry {
+    fn new(info: &RendyInfo, font: &Font) -> Self {
+        let width = info
+            .horizontal_resolution
+            .saturating_sub(DEFAULT_MARGIN * 2);
+        let height = info.vertical_resolution.saturating_sub(DEFAULT_MARGIN * 2);
+
+        Self {
+            rows: core::cmp::max(height / font.height(), 1),
+            cols: core::cmp::max(width / font.width(), 1),
+            offset_x: DEFAULT_MARGIN + (width % font.width()) / 2,
+            offset_y: DEFAULT_MARGIN + (height % font.height()) / 2,
+        }
+    }
+}
+
 pub struct DebugRendy<'a> {
     inner: Inner<'a>,
     performer: Processor<RendySync>,
@@ -729,11 +887,23 @@ impl<'this> DebugRendy<'this> {
         let width = info.horizontal_resolution;
         let height = info.vertical_resolution;
 
-        let offset_x = DEFAULT_MARGIN + ((width - DEFAULT_MARGIN * 2) % FONT_WIDTH) / 2;
-        let offset_y = DEFAULT_MARGIN + ((height - DEFAULT_MARGIN * 2) % FONT_HEIGHT) / 2;
+        let font = cmdline
+            .font
+            .and_then(|data| match Font::parse(data) {
+                Ok(font) => Some(font),
+                Err(err) => {
+                    log::warn!("rendy: invalid font ({err:?}), using the default font");
+                    None
+                }
+            })
+            .unwrap_or_else(default_font);
 
-        let cols = (width - DEFAULT_MARGIN * 2) / FONT_WIDTH;
-        let rows = (height - DEFAULT_MARGIN * 2) / FONT_HEIGHT;
+        let Geometry {
+            rows,
+            cols,
+            offset_x,
+            offset_y,
+        } = Geometry::new(&info, &font);
 
         let grid = mem::alloc_boxed_buffer::<Character>(rows * cols);
         let queue = mem::alloc_boxed_buffer::<QueueCharacter>(rows * cols);
@@ -744,6 +914,7 @@ impl<'this> DebugRendy<'this> {
             inner: Inner {
                 buffer,
                 info,
+                font,
 
                 x_pos: 0,
                 y_pos: 0,
@@ -756,6 +927,7 @@ impl<'this> DebugRendy<'this> {
 
                 theme_background: cmdline.theme_background,
                 color: ColorCode::new(DEFAULT_TEXT_FOREGROUND, DEFAULT_TEXT_BACKGROUND),
+                attrs: CharAttributes::empty(),
 
                 queue,
                 grid,
@@ -769,15 +941,15 @@ impl<'this> DebugRendy<'this> {
 
                 cursor_visibility: true,
                 auto_flush: true,
+                suspended: false,
 
                 color_list: ColorList::new(),
+                background: cmdline.term_background.map(parse_bmp_image),
             },
             performer: Processor::new(),
         };
 
-        let image = cmdline.term_background.map(parse_bmp_image);
-
-        this.generate_canvas(image);
+        this.generate_canvas();
         this.clear(true);
         this.double_buffer_flush();
 
@@ -823,11 +995,7 @@ impl<'a> vte::ansi::Handler for Inner<'a> {
     }
 
     fn backspace(&mut self) {
-        let empty = Character {
-            char: ' ',
-            fg: self.color.get_foreground(),
-            bg: self.color.get_background(),
-        };
+        let empty = Character::blank(self.color.get_foreground(), self.color.get_background());
 
         if self.x_pos == 0 {
             self.y_pos -= 1;
@@ -843,11 +1011,12 @@ impl<'a> vte::ansi::Handler for Inner<'a> {
     fn terminal_attribute(&mut self, attr: Attr) {
         match attr {
             Attr::Reset => {
-                self.color = ColorCode::new(DEFAULT_TEXT_FOREGROUND, DEFAULT_TEXT_BACKGROUND)
+                self.color = ColorCode::new(DEFAULT_TEXT_FOREGROUND, DEFAULT_TEXT_BACKGROUND);
+                self.attrs = CharAttributes::empty();
             }
 
-            // Attr::Bold => todo!(),
-            // Attr::Dim => todo!(),
+            Attr::Bold => self.attrs.insert(CharAttributes::BOLD),
+            Attr::Dim => self.attrs.insert(CharAttributes::DIM),
             // Attr::Italic => todo!(),
             // Attr::Underline => todo!(),
             // Attr::DoubleUnderline => todo!(),
@@ -859,8 +1028,10 @@ impl<'a> vte::ansi::Handler for Inner<'a> {
             // Attr::Reverse => todo!(),
             // Attr::Hidden => todo!(),
             // Attr::Strike => todo!(),
-            // Attr::CancelBold => todo!(),
-            // Attr::CancelBoldDim => todo!(),
+            Attr::CancelBold => self.attrs.remove(CharAttributes::BOLD),
+            Attr::CancelBoldDim => self
+                .attrs
+                .remove(CharAttributes::BOLD | CharAttributes::DIM),
             // Attr::CancelItalic => todo!(),
             // Attr::CancelUnderline => todo!(),
             // Attr::CancelBlink => todo!(),
@@ -888,6 +1059,22 @@ unsafe impl<'this> Sync for DebugRendy<'this> {}
 
 pub static DEBUG_RENDY: Once<Mutex<DebugRendy>> = Once::new();
 
+/// The framebuffer set up by the bootloader.
+static BOOT_SCANOUT: Once<Scanout> = Once::new();
+
+/// A framebuffer the terminal can draw to.
+#[derive(Debug, Clone, Copy)]
+pub struct Scanout {
+    pub address: VirtAddr,
+    pub info: RendyInfo,
+}
+
+impl Scanout {
+    fn buffer(&self) -> &'static mut [u32] {
+        unsafe { core::slice::from_raw_parts_mut(self.address.as_mut_ptr(), self.info.byte_len) }
+    }
+}
+
 pub macro print {
     ($($arg:tt)*) => ($crate::rendy::_print(format_args!($($arg)*))),
 }
@@ -1019,6 +1206,57 @@ pub unsafe fn force_unlock() {
     }
 }
 
+/// Returns the framebuffer set up by the bootloader.
+///
+/// # Panics
+/// This function was called before the terminal was initialized.
+pub fn boot_scanout() -> Scanout {
+    *BOOT_SCANOUT
+        .get()
+        .expect("boot_scanout: invoked before the terminal was initialized")
+}
+
+/// Stops drawing to the display, as a DRM client has taken it over. The output is still
+/// recorded and shows up once the terminal is given the display back with [`reconfigure`] or
+/// [`resume`].
+pub fn suspend() {
+    if let Some(l) = DEBUG_RENDY.get() {
+        l.lock_irq().suspended = true;
+    }
+}
+
+/// Resumes drawing to the current framebuffer and repaints the screen.
+pub fn resume() {
+    if let Some(l) = DEBUG_RENDY.get() {
+        let mut this = l.lock_irq();
+
+        if this.suspended {
+            this.suspended = false;
+            this.repaint();
+        }
+    }
+}
+
+/// Switches the terminal to `scanout`, rebuilding the character grid for its resolution, and
+/// resumes drawing. Falls back to the boot framebuffer if the pixel format of `scanout` is not
+/// supported.
+pub fn reconfigure(scanout: Scanout) {
+    let scanout = if scanout.info.bits_per_pixel == 32 {
+        scanout
+    } else {
+        log::warn!(
+            "rendy: unsupported scanout format ({} bpp), using the boot framebuffer",
+            scanout.info.bits_per_pixel
+        );
+
+        boot_scanout()
+    };
+
+    if let Some(l) = DEBUG_RENDY.get() {
+        l.lock_irq().reconfigure(scanout.buffer(), scanout.info);
+    }
+}
+
 pub fn init(fb_info: Framebuffer, cmdline: &CommandLine) {
     let stride = fb_info.pitch() as usize;
     let height = fb_info.height() as usize;
@@ -1043,14 +1281,12 @@ pub fn init(fb_info: Framebuffer, cmdline: &CommandLine) {
         blue_mask_size: fb_info.blue_mask_size(),
     };
 
-    let framebuffer = unsafe {
-        core::slice::from_raw_parts_mut::<u32>(
-            fb_info.addr().cast::<u32>(),
-            framebuffer_info.byte_len,
-        )
-    };
+    let scanout = BOOT_SCANOUT.call_once(|| Scanout {
+        address: VirtAddr::new(fb_info.addr() as u64),
+        info: framebuffer_info,
+    });
 
-    let rendy = DebugRendy::new(framebuffer, framebuffer_info, cmdline);
+    let rendy = DebugRendy::new(scanout.buffer(), framebuffer_info, cmdline);
 
     DEBUG_RENDY.call_once(|| Mutex::new(rendy));
 }
@@ -0,0 +1,252 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! PC Screen Font (PSF) parser.
+//!
+//! Both versions of the format are supported, along with the unicode table that maps characters
+//! to glyphs. Characters without a glyph are drawn with the replacement glyph of the font.
+//!
+//! **Notes**: <https://www.win.tue.nl/~aeb/linux/kbd/font-formats-1.html>
+
+use alloc::collections::BTreeMap;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE512: u8 = 0x01;
+const PSF1_MODEHASTAB: u8 = 0x02;
+const PSF1_MODESEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xffff;
+const PSF1_STARTSEQ: u16 = 0xfffe;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_STARTSEQ: u8 = 0xfe;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PsfError {
+    /// The data does not start with the magic of either PSF version.
+    InvalidMagic,
+    /// The header describes glyphs of an invalid size.
+    InvalidHeader,
+    /// The data ends before the last glyph.
+    Truncated,
+}
+
+pub struct Font<'a> {
+    glyphs: &'a [u8],
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    width: usize,
+    height: usize,
+    /// Glyphs of the characters listed in the unicode table of the font. Without a table, the
+    /// ASCII characters are the index of their glyph.
+    unicode: Option<BTreeMap<char, usize>>,
+    replacement: usize,
+}
+
+impl<'a> Font<'a> {
+    /// Parses a PSF1 or PSF2 font.
+    pub fn parse(data: &'a [u8]) -> Result<Self, PsfError> {
+        if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)
+        } else if data.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(data)
+        } else {
+            Err(PsfError::InvalidMagic)
+        }
+    }
+
+    /// Creates a font from the bitmaps of its glyphs, `height` bytes each, which are 8 pixels
+    /// wide. The glyphs of the ASCII characters are at the index of the character.
+    pub fn raw(glyphs: &'a [u8], height: usize) -> Self {
+        Self::new(glyphs, glyphs.len() / height, height, 8, height, None)
+    }
+
+    fn new(
+        glyphs: &'a [u8],
+        glyph_count: usize,
+        bytes_per_glyph: usize,
+        width: usize,
+        height: usize,
+        unicode: Option<BTreeMap<char, usize>>,
+    ) -> Self {
+        let mut font = Self {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+            unicode,
+            replacement: 0,
+        };
+
+        font.replacement = font
+            .lookup('\u{fffd}')
+            .or_else(|| font.lookup('?'))
+            .unwrap_or(0);
+
+        font
+    }
+
+    fn parse_psf1(data: &'a [u8]) -> Result<Self, PsfError> {
+        let (mode, height) = match data.get(2..4) {
+            Some(&[mode, height]) => (mode, height as usize),
+            _ => return Err(PsfError::Truncated),
+        };
+
+        if height == 0 {
+            return Err(PsfError::InvalidHeader);
+        }
+
+        let glyph_count = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+        let glyphs_end = 4 + glyph_count * height;
+        let glyphs = data.get(4..glyphs_end).ok_or(PsfError::Truncated)?;
+
+        let unicode = (mode & (PSF1_MODEHASTAB | PSF1_MODESEQ) != 0)
+            .then(|| psf1_unicode_table(&data[glyphs_end..], glyph_count));
+
+        Ok(Self::new(glyphs, glyph_count, height, 8, height, unicode))
+    }
+
+    fn parse_psf2(data: &'a [u8]) -> Result<Self, PsfError> {
+        let header = data.get(..PSF2_HEADER_SIZE).ok_or(PsfError::Truncated)?;
+        let field = |index: usize| {
+            let bytes = &header[index * 4..index * 4 + 4];
+            u32::from_le_bytes(bytes.try_into().unwrap()) as usize
+        };
+
+        let header_size = field(2);
+        let flags = field(3) as u32;
+        let glyph_count = field(4);
+        let bytes_per_glyph = field(5);
+        let height = field(6);
+        let width = field(7);
+
+        if header_size < PSF2_HEADER_SIZE
+            || width == 0
+            || height == 0
+            || bytes_per_glyph < height * width.div_ceil(8)
+        {
+            return Err(PsfError::InvalidHeader);
+        }
+
+        let glyphs_end = glyph_count
+            .checked_mul(bytes_per_glyph)
+            .and_then(|size| size.checked_add(header_size))
+            .ok_or(PsfError::InvalidHeader)?;
+
+        let glyphs = data
+            .get(header_size..glyphs_end)
+            .ok_or(PsfError::Truncated)?;
+
+        let unicode = (flags & PSF2_HAS_UNICODE_TABLE != 0)
+            .then(|| psf2_unicode_table(&data[glyphs_end..], glyph_count));
+
+        Ok(Self::new(
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+            unicode,
+        ))
+    }
+
+    /// Returns the width of the glyphs in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the glyphs in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn lookup(&self, char: char) -> Option<usize> {
+        match &self.unicode {
+            Some(unicode) => unicode.get(&char).copied(),
+            None if char.is_ascii() && (char as usize) < self.glyph_count => Some(char as usize),
+            None => None,
+        }
+    }
+
+    /// Returns the index of the glyph of `char`, or of the replacement glyph if the font has
+    /// none for it.
+    pub fn glyph_index(&self, char: char) -> usize {
+        self.lookup(char).unwrap_or(self.replacement)
+    }
+
+    /// Returns whether the pixel at `(x, y)` of the glyph `index` is set.
+    pub fn is_set(&self, index: usize, x: usize, y: usize) -> bool {
+        let offset = index * self.bytes_per_glyph + y * self.width.div_ceil(8) + x / 8;
+        self.glyphs[offset] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+/// Parses the PSF1 unicode table, made of a list of UCS-2 characters for every glyph. Sequences
+/// of combining characters are not supported and are skipped.
+fn psf1_unicode_table(table: &[u8], glyph_count: usize) -> BTreeMap<char, usize> {
+    let mut unicode = BTreeMap::new();
+    let mut glyph = 0;
+    let mut sequence = false;
+
+    for value in table.as_chunks::<2>().0 {
+        match u16::from_le_bytes(*value) {
+            PSF1_SEPARATOR => {
+                glyph += 1;
+                sequence = false;
+
+                if glyph == glyph_count {
+                    break;
+                }
+            }
+
+            PSF1_STARTSEQ => sequence = true,
+
+            value if !sequence => {
+                if let Some(char) = char::from_u32(value as u32) {
+                    unicode.entry(char).or_insert(glyph);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    unicode
+}
+
+/// Parses the PSF2 unicode table, made of a list of UTF-8 characters for every glyph. Sequences
+/// of combining characters are not supported and are skipped.
+fn psf2_unicode_table(table: &[u8], glyph_count: usize) -> BTreeMap<char, usize> {
+    let mut unicode = BTreeMap::new();
+
+    for (glyph, entry) in table
+        .split(|&byte| byte == PSF2_SEPARATOR)
+        .take(glyph_count)
+        .enumerate()
+    {
+        let chars = entry.split(|&byte| byte == PSF2_STARTSEQ).next().unwrap();
+
+        for char in core::str::from_utf8(chars).unwrap_or_default().chars() {
+            unicode.entry(char).or_insert(glyph);
+        }
+    }
+
+    unicode
+}
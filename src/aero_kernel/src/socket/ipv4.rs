@@ -40,12 +40,13 @@ impl INodeInterface for Ipv4Socket {
     fn ioctl(&self, command: usize, arg: usize) -> Result<usize> {
         match command {
             SIOCGIFINDEX => {
-                let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+                let mut ifreq = UserRef::<IfReq>::new(VirtAddr::new(arg as _))?;
 
                 let name = ifreq.name().unwrap();
                 assert!(name == "eth0");
 
                 ifreq.data.ifindex = 1; // FIXME: Fill the actual interface index
+                ifreq.write_back()?;
                 Ok(0)
             }
 
@@ -22,17 +22,120 @@ pub mod netlink;
 pub mod udp;
 pub mod unix;
 
+use core::mem::MaybeUninit;
+
 use aero_syscall::netlink::sockaddr_nl;
-use aero_syscall::prelude::IfReq;
+use aero_syscall::prelude::{IfReq, SockAddrStorage};
+use aero_syscall::socket::MessageHeader;
 use aero_syscall::*;
 
+use alloc::vec::Vec;
+
+use crate::arch::user_copy::{copy_from_user, copy_slice_from_user, copy_slice_to_user};
+use crate::fs::{self, FileSystemError};
 use crate::mem::paging::VirtAddr;
 
 #[derive(Debug)]
 pub enum SocketAddr {
     Inet(SocketAddrInet),
     Netlink(sockaddr_nl),
-    Unix(SocketAddrUnix),
+    /// A unix socket address along with its length, which delimits abstract names.
+    Unix(SocketAddrUnix, usize),
+}
+
+impl SocketAddr {
+    /// Copies the socket address of `length` bytes at `address` from userland. The length must
+    /// cover the whole address structure of the family, except for unix socket addresses which
+    /// end with their name.
+    pub fn from_user(address: VirtAddr, length: usize) -> Result<Self> {
+        if length < core::mem::size_of::<u32>() {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let mut family = MaybeUninit::<u32>::uninit();
+        copy_from_user(&mut family, address.as_ptr())?;
+
+        // SAFETY: We have initialized the family via `copy_from_user` above.
+        match unsafe { family.assume_init() } {
+            AF_UNIX => {
+                let mut unix = SocketAddrUnix::default();
+                let path = unix
+                    .path
+                    .get_mut(..length - SocketAddrUnix::PATH_OFFSET)
+                    .ok_or(SyscallError::EINVAL)?;
+
+                copy_slice_from_user(path, (address + SocketAddrUnix::PATH_OFFSET).as_ptr())?;
+                Ok(SocketAddr::Unix(unix, length))
+            }
+
+            AF_INET => Ok(SocketAddr::Inet(copy_addr(address, length)?)),
+            AF_NETLINK => Ok(SocketAddr::Netlink(copy_addr(address, length)?)),
+
+            _ => Err(SyscallError::EINVAL),
+        }
+    }
+}
+
+/// Copies the address structure at `address` from userland, if `length` covers all of it.
+fn copy_addr<T>(address: VirtAddr, length: usize) -> Result<T> {
+    if length < core::mem::size_of::<T>() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let mut value = MaybeUninit::<T>::uninit();
+    copy_from_user(&mut value, address.as_ptr())?;
+
+    // SAFETY: We have initialized the address via `copy_from_user` above, and every bit pattern
+    // is a valid socket address.
+    Ok(unsafe { value.assume_init() })
+}
+
+/// Copies the address buffer of `header` (`msg_name`) from userland, if there is one.
+///
+/// ## Errors
+/// * `InvalidInput`: The buffer is larger than a [`SocketAddrUnix`], the largest address.
+/// * `Fault`: The buffer is not mapped.
+pub fn message_name(header: &MessageHeader) -> fs::Result<Option<Vec<u8>>> {
+    let Some((name, length)) = header.name() else {
+        return Ok(None);
+    };
+
+    if length > core::mem::size_of::<SocketAddrUnix>() {
+        return Err(FileSystemError::InvalidInput);
+    }
+
+    let mut buffer = alloc::vec![0; length];
+    copy_slice_from_user(&mut buffer, name)?;
+
+    Ok(Some(buffer))
+}
+
+/// Copies `address` out to the address buffer of `header` (`msg_name`), if there is one, and sets
+/// `msg_namelen` to `length`, the size of the address. Like on Linux, an address that does not fit
+/// is truncated to the size of the buffer.
+pub fn set_message_name<T>(
+    header: &mut MessageHeader,
+    address: &T,
+    length: usize,
+) -> fs::Result<()> {
+    let Some((name, capacity)) = header.name() else {
+        return Ok(());
+    };
+
+    // SAFETY: `address` is valid for `size_of::<T>()` bytes, and socket addresses have no
+    // padding.
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            (address as *const T).cast::<u8>(),
+            core::mem::size_of::<T>(),
+        )
+    };
+
+    let size = length.min(capacity).min(bytes.len());
+    copy_slice_to_user(name, &bytes[..size])?;
+
+    header.set_name_len(length as u32);
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -43,24 +146,35 @@ pub enum SocketAddrRef<'a> {
     Netlink,
 }
 
-impl<'a> SocketAddrRef<'a> {
-    pub fn from_family(address: VirtAddr, family: u32) -> Result<Self> {
-        match family {
-            AF_UNIX => Ok(SocketAddrRef::Unix(address.read_mut::<SocketAddrUnix>()?)),
-            AF_INET => Ok(SocketAddrRef::INet(address.read_mut::<SocketAddrInet>()?)),
-            AF_NETLINK => Ok(SocketAddrRef::Netlink),
+const_assert!(core::mem::size_of::<SockAddrStorage>() >= core::mem::size_of::<SocketAddrInet>());
+const_assert!(core::mem::align_of::<SockAddrStorage>() >= core::mem::align_of::<SocketAddrInet>());
 
-            _ => Err(SyscallError::EINVAL),
+impl<'a> From<&'a SocketAddr> for SocketAddrRef<'a> {
+    fn from(address: &'a SocketAddr) -> Self {
+        match address {
+            SocketAddr::Inet(address) => SocketAddrRef::INet(address),
+            SocketAddr::Netlink(_) => SocketAddrRef::Netlink,
+            SocketAddr::Unix(address, _) => SocketAddrRef::Unix(address),
         }
     }
+}
 
-    pub fn from_ifreq(ifreq: &IfReq) -> Result<Self> {
-        // SAFETY???
-        let family = unsafe { ifreq.data.addr.sa_family };
-        SocketAddrRef::from_family(
-            VirtAddr::new(&unsafe { ifreq.data.addr } as *const _ as _),
-            family,
-        )
+impl<'a> SocketAddrRef<'a> {
+    /// Returns the internet socket address of the interface request. Other families are not
+    /// supported.
+    pub fn from_ifreq(ifreq: &'a IfReq) -> Result<Self> {
+        // SAFETY: Any bit pattern is a valid address.
+        let address = unsafe { &ifreq.data.addr };
+
+        match address.sa_family {
+            // SAFETY: The storage is large and aligned enough for an internet socket address,
+            // which is valid for any bit pattern.
+            AF_INET => Ok(SocketAddrRef::INet(unsafe {
+                &*(address as *const SockAddrStorage).cast::<SocketAddrInet>()
+            })),
+
+            _ => Err(SyscallError::EINVAL),
+        }
     }
 
     /// Converts the socket address into a unix socket address. Returns [`None`] if
@@ -32,15 +32,17 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use crabnet::network::Ipv4Addr;
 
-use crate::fs;
 use crate::fs::inode::{FileType, INodeInterface, Metadata, PollFlags, PollTable};
 use crate::utils::sync::{Mutex, WaitQueue};
+use crate::{fs, net};
 
-use super::SocketAddrRef;
+use super::{set_message_name, SocketAddrRef};
 
 // TODO(andypython): can we use crabnet to construct netlink packets(?)
 struct NetlinkBuilder {
     buffer: Vec<u8>,
+    /// Offset of the header of the message that is being built.
+    message_start: usize,
 }
 
 impl NetlinkBuilder {
@@ -57,54 +59,52 @@ impl NetlinkBuilder {
         //     }
         // });
 
-        Self { buffer: Vec::new() }
+        Self {
+            buffer: Vec::new(),
+            message_start: 0,
+        }
     }
 
+    /// Starts a new message in the buffer. Multiple messages can be sent in a single datagram.
     fn header(&mut self, header: &netlink::nlmsghdr) {
-        self.buffer.extend_from_slice(unsafe {
-            core::slice::from_raw_parts(
-                &header as *const _ as *const u8,
-                core::mem::size_of::<netlink::nlmsghdr>(),
-            )
-        });
+        self.finish_message();
+        self.message_start = self.buffer.len();
 
+        self.buffer.extend_from_slice(as_bytes(header));
         self.buffer_align();
     }
 
-    fn message(&mut self, message: &netlink::rtmsg) {
-        self.buffer.extend_from_slice(unsafe {
-            core::slice::from_raw_parts(
-                &message as *const _ as *const u8,
-                core::mem::size_of::<netlink::rtmsg>(),
-            )
-        });
-
+    fn message<T>(&mut self, message: &T) {
+        self.buffer.extend_from_slice(as_bytes(message));
         self.buffer_align();
     }
 
     fn rtattr<T>(&mut self, ty: RtAttrType, data: T) {
-        let rta_len = netlink::rta_length(core::mem::size_of::<T>() as u32);
+        self.attr(ty as u16, as_bytes(&data));
+    }
 
-        let attr = netlink::rtattr {
-            rta_len: rta_len.try_into().unwrap(),
-            rta_type: ty,
-        };
+    /// Appends an attribute with the given type, for the attribute types that are not a
+    /// [`RtAttrType`] (e.g. `IFLA_*`).
+    fn attr(&mut self, ty: u16, data: &[u8]) {
+        let rta_len: u16 = netlink::rta_length(data.len() as u32).try_into().unwrap();
 
-        let padding = rta_len as usize - core::mem::size_of::<T>();
+        // The layout of `rtattr`.
+        self.buffer.extend_from_slice(&rta_len.to_ne_bytes());
+        self.buffer.extend_from_slice(&ty.to_ne_bytes());
+        self.buffer.extend_from_slice(data);
+        self.buffer_align();
+    }
 
-        self.buffer.extend_from_slice(unsafe {
-            core::slice::from_raw_parts(
-                &attr as *const _ as *const u8,
-                core::mem::size_of::<netlink::rtattr>(),
-            )
-        });
+    /// Sets the length of the message that is being built.
+    fn finish_message(&mut self) {
+        if self.buffer.len() == self.message_start {
+            return;
+        }
 
-        self.buffer.extend_from_slice(unsafe {
-            core::slice::from_raw_parts(&data as *const _ as *const u8, core::mem::size_of::<T>())
-        });
+        let msg_len = (self.buffer.len() - self.message_start) as u32;
+        let header = &mut self.buffer[self.message_start..];
 
-        self.buffer.resize(self.buffer.len() + padding, 0);
-        self.buffer_align();
+        header[..4].copy_from_slice(&msg_len.to_ne_bytes());
     }
 
     /// Aligns the buffer to the netlink message alignment.
@@ -115,14 +115,16 @@ impl NetlinkBuilder {
         self.buffer.resize(aligned_len as usize, 0);
     }
 
-    fn build(self) -> Vec<u8> {
-        let mut buffer = self.buffer;
-
-        let msg_len = buffer.len();
-        let msg_hdr = unsafe { &mut *buffer.as_mut_ptr().cast::<netlink::nlmsghdr>() };
+    fn build(mut self) -> Vec<u8> {
+        self.finish_message();
+        self.buffer
+    }
+}
 
-        msg_hdr.nlmsg_len = msg_len as u32;
-        buffer
+fn as_bytes<T>(value: &T) -> &[u8] {
+    // SAFETY: The netlink structures are plain old data.
+    unsafe {
+        core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
     }
 }
 
@@ -196,6 +198,59 @@ impl NetLinkSocket {
 
         self.send_route_packet(header);
     }
+
+    fn get_link(&self, header: &netlink::nlmsghdr, payload: &[u8]) {
+        // Either a `rtgenmsg` or an `ifinfomsg`, both of which start with the family.
+        let family = payload.first().copied().unwrap_or_default() as u32;
+        assert!(family == AF_UNSPEC || family == AF_NETLINK);
+
+        let mut builder = NetlinkBuilder::new();
+
+        for (index, device) in net::devices().iter().enumerate() {
+            builder.header(&netlink::nlmsghdr {
+                nlmsg_type: MessageType::RtmNewLink,
+                nlmsg_flags: MessageFlags::MULTI,
+                nlmsg_seq: header.nlmsg_seq,
+                nlmsg_pid: 0,
+                nlmsg_len: 0,
+            });
+
+            let ifi_type = if device.is_loopback() {
+                netlink::ARPHRD_LOOPBACK
+            } else {
+                netlink::ARPHRD_ETHER
+            };
+
+            builder.message(&netlink::ifinfomsg {
+                ifi_family: AF_UNSPEC as u8,
+                __ifi_pad: 0,
+                ifi_type,
+                ifi_index: index as i32 + 1,
+                ifi_flags: device.flags(),
+                ifi_change: u32::MAX,
+            });
+
+            let mut name = device.name().as_bytes().to_vec();
+            name.push(b'\0');
+
+            builder.attr(netlink::IFLA_IFNAME, &name);
+            builder.attr(netlink::IFLA_MTU, &(device.mtu() as u32).to_ne_bytes());
+            builder.attr(netlink::IFLA_ADDRESS, &device.mac().0);
+        }
+
+        builder.header(&netlink::nlmsghdr {
+            nlmsg_type: MessageType::Done,
+            nlmsg_flags: MessageFlags::MULTI,
+            nlmsg_seq: header.nlmsg_seq,
+            nlmsg_pid: 0,
+            nlmsg_len: 0,
+        });
+
+        builder.message(&0i32);
+
+        self.recv_queue.lock().push(builder.build());
+        self.recv_wq.notify();
+    }
 }
 
 impl INodeInterface for NetLinkSocket {
@@ -227,14 +282,14 @@ impl INodeInterface for NetLinkSocket {
         // FIXME(andypython): All of the message header and iovec logic should be moved to
         // syscall::net::recvmsg() instead.
 
-        if let Some(addr) = message_hdr.name_mut::<netlink::sockaddr_nl>() {
-            *addr = netlink::sockaddr_nl {
-                nl_family: AF_NETLINK,
-                nl_pad: 0,
-                nl_pid: 0,
-                nl_groups: 0,
-            };
-        }
+        let address = netlink::sockaddr_nl {
+            nl_family: AF_NETLINK,
+            nl_pad: 0,
+            nl_pid: 0,
+            nl_groups: 0,
+        };
+
+        set_message_name(message_hdr, &address, core::mem::size_of_val(&address))?;
 
         let mut queue = self
             .recv_wq
@@ -299,6 +354,7 @@ impl INodeInterface for NetLinkSocket {
                 }
 
                 MessageType::RtmGetRoute => self.get_route(header, payload),
+                MessageType::RtmGetLink => self.get_link(header, payload),
 
                 ty => unimplemented!("netlink::send: unknown message type {ty:?}"),
             }
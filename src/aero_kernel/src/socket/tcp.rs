@@ -96,21 +96,24 @@ impl TcpSocket {
             .is_some_and(|handle| handle.flags().contains(OpenFlags::O_NONBLOCK))
     }
 
-    pub fn do_recv(&self, buf: &mut [u8]) -> Result<usize, FileSystemError> {
+    pub fn do_recv(&self, buf: &mut [u8], non_block: bool) -> Result<usize, FileSystemError> {
         let mut tcp = self.tcp.lock_irq();
         let socket = tcp.as_mut().ok_or(FileSystemError::NotConnected)?;
 
         match socket.recv(buf) {
             Ok(bytes_read) => Ok(bytes_read),
 
-            Err(TcpError::WouldBlock) if self.non_blocking() => Err(FileSystemError::WouldBlock),
+            Err(TcpError::WouldBlock) if non_block => Err(FileSystemError::WouldBlock),
             Err(TcpError::WouldBlock) => {
                 drop(tcp);
 
-                let mut socket = self.wq.block_on(&self.tcp, |tcp| {
-                    tcp.as_ref()
-                        .map_or(true, |socket| !socket.recv_queue.is_empty())
-                })?;
+                let mut socket = self
+                    .wq
+                    .block_on_interruptible(&self.tcp, |tcp| {
+                        tcp.as_ref()
+                            .map_or(true, |socket| !socket.recv_queue.is_empty())
+                    })
+                    .map_err(|_| FileSystemError::Interrupted)?;
 
                 if let Some(socket) = socket.as_mut() {
                     Ok(socket.recv(buf).unwrap())
@@ -143,13 +146,15 @@ impl INodeInterface for TcpSocket {
             let addr = address.as_inet().ok_or(FileSystemError::NotSupported)?;
             self.peer.call_once(|| addr.clone());
 
+            // TODO: TCP sockets cannot listen yet, so there is nothing to connect to on the
+            // loopback device.
             if addr.addr() == Ipv4Addr::LOOPBACK.0 {
                 return Err(FileSystemError::NotSupported);
             }
 
+            let device = Arc::new(DeviceShim(net::route(Ipv4Addr::from(addr.addr()))));
             let addr = Address::new(port, addr.port(), addr.addr().into());
 
-            let device = Arc::new(DeviceShim(net::default_device()));
             let socket = crabnet_tcp::Socket::connect(device, addr);
 
             *tcp = Some(socket);
@@ -174,7 +179,7 @@ impl INodeInterface for TcpSocket {
 
     #[inline]
     fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, FileSystemError> {
-        self.do_recv(buf)
+        self.do_recv(buf, self.non_blocking())
     }
 
     #[inline]
@@ -230,15 +235,34 @@ impl INodeInterface for TcpSocket {
         }
     }
 
-    fn recv(&self, message_hdr: &mut MessageHeader, _flags: MessageFlags) -> fs::Result<usize> {
-        Ok(message_hdr
-            .iovecs_mut()
-            .iter_mut()
-            .map(|iovec| {
-                let iovec = iovec.as_slice_mut();
-                self.do_recv(iovec).unwrap()
-            })
-            .sum::<usize>())
+    fn recv(&self, message_hdr: &mut MessageHeader, flags: MessageFlags) -> fs::Result<usize> {
+        // TODO: Support `MSG_PEEK`, which requires crabnet_tcp to leave the data queued.
+        if flags.contains(MessageFlags::PEEK) {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        let non_block = self.non_blocking() || flags.contains(MessageFlags::DONTWAIT);
+        let mut copied = 0;
+
+        for iovec in message_hdr.iovecs_mut() {
+            let iovec = iovec.as_slice_mut();
+
+            // Only wait for the first bytes; the following I/O vectors get the data that is
+            // already queued.
+            let count = match self.do_recv(iovec, non_block || copied > 0) {
+                Ok(count) => count,
+                Err(FileSystemError::WouldBlock) if copied > 0 => break,
+                Err(err) => return Err(err),
+            };
+
+            copied += count;
+
+            if count < iovec.len() {
+                break;
+            }
+        }
+
+        Ok(copied)
     }
 
     fn poll(&self, table: Option<&mut PollTable>) -> fs::Result<PollFlags> {
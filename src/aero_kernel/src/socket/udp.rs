@@ -17,7 +17,8 @@
 
 use aero_syscall::prelude::{IfReq, SIOCGIFHWADDR, SIOCSIFADDR, SIOCSIFNETMASK};
 use aero_syscall::socket::{MessageFlags, MessageHeader};
-use aero_syscall::{OpenFlags, SocketAddrInet};
+use aero_syscall::{InAddr, OpenFlags, SocketAddrInet, AF_INET};
+use alloc::collections::VecDeque;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use spin::Once;
@@ -32,7 +33,7 @@ use crate::net::udp::{self, UdpHandler};
 use crate::net::{self};
 use crate::utils::sync::{Mutex, WaitQueue};
 
-use super::SocketAddrRef;
+use super::{message_name, set_message_name, SocketAddrRef};
 
 use crabnet::data_link::{Eth, EthType, MacAddr};
 use crabnet::network::{Ipv4, Ipv4Addr, Ipv4Type};
@@ -46,12 +47,19 @@ enum SocketState {
     Connected(SocketAddrInet),
 }
 
+#[derive(Clone)]
+struct Datagram {
+    /// Address of the sending socket.
+    sender: SocketAddrInet,
+    data: Vec<u8>,
+}
+
 #[derive(Default)]
 struct UdpSocketInner {
     /// The address that the socket has been bound to.
     address: Option<SocketAddrInet>,
     state: SocketState,
-    incoming: Vec<Vec<u8>>,
+    incoming: VecDeque<Datagram>,
 }
 
 pub struct UdpSocket {
@@ -143,10 +151,15 @@ impl INodeInterface for UdpSocket {
     }
 
     fn send(&self, message_hdr: &mut MessageHeader, _flags: MessageFlags) -> fs::Result<usize> {
-        let name = message_hdr
-            .name_mut::<SocketAddrInet>()
-            .cloned()
-            .unwrap_or_else(|| self.dest());
+        let name = match message_name(message_hdr)? {
+            Some(name) if name.len() >= core::mem::size_of::<SocketAddrInet>() => {
+                // SAFETY: The buffer is large enough, and any bit pattern is a valid address.
+                unsafe { name.as_ptr().cast::<SocketAddrInet>().read_unaligned() }
+            }
+
+            Some(_) => return Err(FileSystemError::InvalidInput),
+            None => self.dest(),
+        };
 
         let dest_port = name.port.to_native();
         let dest_ip = Ipv4Addr::from(name.addr());
@@ -167,17 +180,15 @@ impl INodeInterface for UdpSocket {
             .copied()
             .collect::<Vec<_>>();
 
-        // FIXME: loopback
-        if dest_ip == Ipv4Addr::LOOPBACK {
-            log::debug!("looback moments :)");
-            return Err(FileSystemError::NotSupported);
-            // return Ok(data.len());
-        }
-
         use crate::net::shim::PacketSend;
 
+        let ipv4 = if net::route(dest_ip).is_loopback() {
+            Ipv4::new(Ipv4Addr::LOOPBACK, dest_ip, Ipv4Type::Udp)
+        } else {
+            Ipv4::new(Ipv4Addr::BROADCAST, Ipv4Addr::BROADCAST, Ipv4Type::Udp)
+        };
+
         let eth = Eth::new(MacAddr::NULL, MacAddr::NULL, EthType::Ip);
-        let ipv4 = Ipv4::new(Ipv4Addr::BROADCAST, Ipv4Addr::BROADCAST, Ipv4Type::Udp);
         let udp = Udp::new(src_port, dest_port);
         let packet = eth / ipv4 / udp / data.as_slice();
 
@@ -185,34 +196,52 @@ impl INodeInterface for UdpSocket {
         Ok(data.len())
     }
 
-    fn recv(&self, message_hdr: &mut MessageHeader, _flags: MessageFlags) -> fs::Result<usize> {
-        // assert!(flags.is_empty());
+    fn recv(&self, message_hdr: &mut MessageHeader, flags: MessageFlags) -> fs::Result<usize> {
+        let non_block = self.is_non_block() || flags.contains(MessageFlags::DONTWAIT);
 
-        if self.inner.lock_irq().incoming.is_empty() && self.is_non_block() {
+        if self.inner.lock_irq().incoming.is_empty() && non_block {
             return Err(FileSystemError::WouldBlock);
         }
 
-        let mut this = self.wq.block_on(&self.inner, |e| !e.incoming.is_empty())?;
-        let packet = this.incoming.pop().expect("recv: someone was greedy");
-
-        let mut data = packet.as_slice().to_vec();
-
-        Ok(message_hdr
-            .iovecs_mut()
-            .iter_mut()
-            .map(|iovec| {
-                let iovec = iovec.as_slice_mut();
-                let size = core::cmp::min(iovec.len(), data.len());
-                iovec[..size].copy_from_slice(&data.drain(..size).collect::<Vec<_>>());
-                size
-            })
-            .sum::<usize>())
+        let mut this = self
+            .wq
+            .block_on_interruptible(&self.inner, |e| !e.incoming.is_empty())
+            .map_err(|_| FileSystemError::Interrupted)?;
+
+        let datagram = if flags.contains(MessageFlags::PEEK) {
+            this.incoming.front().cloned()
+        } else {
+            this.incoming.pop_front()
+        }
+        .expect("recv: someone was greedy");
+
+        core::mem::drop(this);
+
+        let copied = message_hdr.scatter(&datagram.data);
+
+        // The part of the datagram that does not fit is discarded.
+        if copied < datagram.data.len() {
+            message_hdr.flags |= MessageFlags::TRUNC.bits() as i32;
+        }
+
+        set_message_name(
+            message_hdr,
+            &datagram.sender,
+            core::mem::size_of::<SocketAddrInet>(),
+        )?;
+
+        // With `MSG_TRUNC`, the size of the whole datagram is returned.
+        if flags.contains(MessageFlags::TRUNC) {
+            return Ok(datagram.data.len());
+        }
+
+        Ok(copied)
     }
 
     fn ioctl(&self, command: usize, arg: usize) -> fs::Result<usize> {
         match command {
             SIOCGIFHWADDR => {
-                let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+                let mut ifreq = UserRef::<IfReq>::new(VirtAddr::new(arg as _))?;
 
                 let name = ifreq.name().ok_or(FileSystemError::InvalidPath)?;
                 assert!(name == "eth0");
@@ -226,11 +255,13 @@ impl INodeInterface for UdpSocket {
 
                 let mac_addr = net::default_device().mac();
                 hwaddr.copy_from_slice(mac_addr.0.as_slice());
+
+                ifreq.write_back()?;
                 Ok(0)
             }
 
             SIOCSIFADDR => {
-                let ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+                let ifreq = UserRef::<IfReq>::new(VirtAddr::new(arg as _))?;
                 let socket = SocketAddrRef::from_ifreq(&ifreq)
                     .map_err(|_| FileSystemError::NotSupported)?
                     .as_inet()
@@ -247,7 +278,7 @@ impl INodeInterface for UdpSocket {
             }
 
             SIOCSIFNETMASK => {
-                let ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+                let ifreq = UserRef::<IfReq>::new(VirtAddr::new(arg as _))?;
                 let socket = SocketAddrRef::from_ifreq(&ifreq)
                     .map_err(|_| FileSystemError::NotSupported)?
                     .as_inet()
@@ -284,8 +315,21 @@ impl INodeInterface for UdpSocket {
 }
 
 impl UdpHandler for UdpSocket {
-    fn recv(&self, _udp: &Udp, payload: &[u8]) {
-        self.inner.lock_irq().incoming.push(payload.to_vec());
+    fn recv(&self, src: Ipv4Addr, udp: &Udp, payload: &[u8]) {
+        let sender = SocketAddrInet {
+            family: AF_INET,
+            port: udp.src_port().into(),
+            sin_addr: InAddr {
+                addr: u32::from_le_bytes(src.0),
+            },
+            padding: [0; 8],
+        };
+
+        self.inner.lock_irq().incoming.push_back(Datagram {
+            sender,
+            data: payload.to_vec(),
+        });
+
         self.wq.notify_all();
     }
 }
@@ -15,11 +15,15 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::{OpenFlags, SocketAddrUnix, SyscallError, AF_UNIX};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 
+use aero_syscall::{Mode, OpenFlags, SocketAddrUnix, SyscallError, UnixSocketName, AF_UNIX};
+
+use aero_syscall::prelude::InotifyMask;
 use aero_syscall::socket::{MessageFlags, MessageHeader};
 
-use alloc::collections::VecDeque;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use spin::Once;
@@ -33,39 +37,112 @@ use crate::fs::inode::{DirEntry, FileType, INodeInterface, Metadata, PollFlags,
 use crate::fs::{FileSystemError, Path};
 
 use crate::mem::paging::VirtAddr;
+use crate::userland::scheduler;
 use crate::utils::sync::{Mutex, WaitQueue};
 
-use super::SocketAddrRef;
+use super::{message_name, set_message_name, SocketAddrRef};
+
+/// Maximum number of datagrams queued on a `SOCK_DGRAM` socket. Senders block (or fail with
+/// `EAGAIN`) until the receiver catches up.
+const DGRAM_QUEUE_LEN: usize = 64;
+
+/// Sockets bound to a name in the abstract namespace. A name is released when the last file
+/// descriptor of its socket is closed.
+static ABSTRACT_NAMES: Mutex<BTreeMap<Vec<u8>, Weak<UnixSocket>>> = Mutex::new(BTreeMap::new());
+
+/// Counter used to generate the names assigned by autobind.
+static NEXT_AUTOBIND: AtomicU32 = AtomicU32::new(0);
+
+/// The name a unix socket is bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum UnixAddress {
+    /// The path of the socket file.
+    Path(String),
+    /// A name in the abstract namespace, which is not backed by a file.
+    Abstract(Vec<u8>),
+}
+
+impl UnixAddress {
+    /// Parses `address`, which is `length` bytes long. Returns [`None`] if it is unnamed.
+    fn parse(address: &SocketAddrUnix, length: usize) -> fs::Result<Option<Self>> {
+        match address.name(length) {
+            UnixSocketName::Unnamed => Ok(None),
+            UnixSocketName::Path(path) => core::str::from_utf8(path)
+                .map(|path| Some(Self::Path(String::from(path))))
+                .map_err(|_| FileSystemError::InvalidPath),
+            UnixSocketName::Abstract(name) => Ok(Some(Self::Abstract(name.to_vec()))),
+        }
+    }
+
+    /// Parses the destination address passed to `sendto`, which may be shorter than
+    /// [`SocketAddrUnix`].
+    fn from_name(name: &[u8]) -> fs::Result<Self> {
+        let (family, path) = name
+            .split_first_chunk::<4>()
+            .ok_or(FileSystemError::InvalidPath)?;
 
-fn path_from_unix_sock(address: &SocketAddrUnix) -> fs::Result<&Path> {
-    // The abstract namespace socket allows the creation of a socket
-    // connection which does not require a path to be created.
-    let abstract_namespaced = address.path[0] == 0;
-    assert!(!abstract_namespaced);
-    assert!(address.path[1] != 0, "unnamed UNIX socket");
+        if u32::from_ne_bytes(*family) != AF_UNIX {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        let mut address = SocketAddrUnix::default();
+        let size = core::cmp::min(path.len(), address.path.len());
+        address.path[..size].copy_from_slice(&path[..size]);
+
+        Self::parse(&address, name.len())?.ok_or(FileSystemError::InvalidPath)
+    }
+
+    /// Returns `address` as a [`SocketAddrUnix`] along with its length, an abstract name being
+    /// reported with a leading NUL byte.
+    fn to_sockaddr(address: Option<&Self>) -> (SocketAddrUnix, usize) {
+        let name = match address {
+            None => UnixSocketName::Unnamed,
+            Some(Self::Path(path)) => UnixSocketName::Path(path.as_bytes()),
+            Some(Self::Abstract(name)) => UnixSocketName::Abstract(name),
+        };
 
-    let path_len = address
-        .path
-        .iter()
-        .position(|&c| c == 0)
-        .unwrap_or(address.path.len());
+        SocketAddrUnix::new(name).expect("unix: bound name does not fit in an address")
+    }
 
-    let path_str = core::str::from_utf8(&address.path[..path_len])
-        .ok()
-        .ok_or(FileSystemError::InvalidPath)?;
+    /// Looks up the socket bound to this address.
+    fn lookup(&self) -> fs::Result<Arc<UnixSocket>> {
+        match self {
+            Self::Path(path) => fs::lookup_path(Path::new(path))?
+                .inode()
+                .as_unix_socket()?
+                .downcast_arc::<UnixSocket>()
+                .ok_or(FileSystemError::NotSocket),
+
+            Self::Abstract(name) => ABSTRACT_NAMES
+                .lock_irq()
+                .get(name)
+                .and_then(Weak::upgrade)
+                .ok_or(FileSystemError::ConnectionRefused),
+        }
+    }
+}
 
-    Ok(Path::new(path_str))
+/// Returns the name of `address` as shown in `/proc/<pid>/fdinfo`, abstract names being prefixed
+/// with `@`.
+fn address_name(address: Option<&UnixAddress>) -> String {
+    match address {
+        None => String::from("(unnamed)"),
+        Some(UnixAddress::Path(path)) => path.clone(),
+        Some(UnixAddress::Abstract(name)) => alloc::format!("@{}", String::from_utf8_lossy(name)),
+    }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Message {
     data: Vec<u8>,
-    // TODO: Keep track of the sender of the message here?
+    /// Address of the sending socket. Only recorded for datagrams; [`None`] if the sender
+    /// was not bound.
+    sender: Option<UnixAddress>,
 }
 
 impl Message {
     pub fn new(data: Vec<u8>) -> Self {
-        Self { data }
+        Self { data, sender: None }
     }
 }
 
@@ -79,6 +156,11 @@ impl MessageQueue {
         self.messages.is_empty()
     }
 
+    /// Returns the number of bytes queued.
+    pub fn bytes(&self) -> usize {
+        self.messages.iter().map(|message| message.data.len()).sum()
+    }
+
     pub fn read(&mut self, buffer: &mut [u8]) -> usize {
         if let Some(message) = self.messages.front_mut() {
             let message_len = message.data.len();
@@ -98,10 +180,65 @@ impl MessageQueue {
         }
     }
 
+    /// Copies up to `size` bytes from the front of the queue. Stream sockets do not preserve
+    /// message boundaries, so the copy continues into the following messages. The bytes are
+    /// removed from the queue unless `peek` is set.
+    pub fn read_stream(&mut self, size: usize, peek: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        for message in self.messages.iter() {
+            let count = core::cmp::min(size - data.len(), message.data.len());
+            data.extend_from_slice(&message.data[..count]);
+
+            if data.len() == size {
+                break;
+            }
+        }
+
+        if !peek {
+            self.consume(data.len());
+        }
+
+        data
+    }
+
+    /// Removes `size` bytes from the front of the queue.
+    fn consume(&mut self, mut size: usize) {
+        while let Some(message) = self.messages.front_mut() {
+            if message.data.len() > size {
+                message.data.drain(..size);
+                return;
+            }
+
+            size -= message.data.len();
+            self.messages.pop_front();
+        }
+    }
+
     pub fn write(&mut self, buffer: &[u8]) {
         let message = Message::new(buffer.to_vec());
         self.messages.push_back(message);
     }
+
+    /// Returns `true` if no more datagrams can be queued.
+    pub fn is_full(&self) -> bool {
+        self.messages.len() >= DGRAM_QUEUE_LEN
+    }
+
+    /// Queues `message` as a whole, preserving its boundaries.
+    pub fn push(&mut self, message: Message) {
+        self.messages.push_back(message);
+    }
+
+    /// Removes the first message from the queue and returns it, or [`None`] if it is empty.
+    pub fn pop(&mut self) -> Option<Message> {
+        self.messages.pop_front()
+    }
+
+    /// Returns a copy of the first message in the queue, leaving it queued.
+    pub fn peek(&self) -> Option<Message> {
+        self.messages.front().cloned()
+    }
 }
 
 pub struct AcceptQueue {
@@ -124,6 +261,11 @@ impl AcceptQueue {
         self.sockets.is_empty()
     }
 
+    /// Returns the number of pending connections.
+    pub fn len(&self) -> usize {
+        self.sockets.len()
+    }
+
     /// Adds the given socket to the queue. Returns `EAGAIN` if the
     /// queue is full.
     pub fn push(&mut self, socket: Arc<UnixSocket>) -> Result<(), SyscallError> {
@@ -163,7 +305,8 @@ enum UnixSocketState {
     /// The socket is listening for new connections.
     Listening(AcceptQueue),
 
-    /// The socket has connected to a peer.
+    /// The socket has connected to a peer. For datagram sockets, this is the default
+    /// destination set by `connect` and the peer is not connected back.
     Connected(Arc<UnixSocket>),
 }
 
@@ -184,7 +327,7 @@ impl UnixSocketState {
 #[derive(Default)]
 struct UnixSocketInner {
     /// The address that the socket has been bound to.
-    address: Option<SocketAddrUnix>,
+    address: Option<UnixAddress>,
 
     state: UnixSocketState,
 }
@@ -195,10 +338,24 @@ pub struct UnixSocket {
     wq: WaitQueue,
     weak: Weak<UnixSocket>,
     handle: Once<Arc<FileHandle>>,
+    /// Number of open file descriptors referring to the socket.
+    open_handles: AtomicUsize,
+    /// Whether this is a `SOCK_DGRAM` socket.
+    datagram: bool,
 }
 
 impl UnixSocket {
+    /// Creates a new `SOCK_STREAM` socket.
     pub fn new() -> Arc<Self> {
+        Self::with_type(false)
+    }
+
+    /// Creates a new `SOCK_DGRAM` socket.
+    pub fn new_datagram() -> Arc<Self> {
+        Self::with_type(true)
+    }
+
+    fn with_type(datagram: bool) -> Arc<Self> {
         Arc::new_cyclic(|weak| Self {
             inner: Mutex::new(UnixSocketInner::default()),
 
@@ -206,6 +363,8 @@ impl UnixSocket {
             wq: WaitQueue::new(),
             weak: weak.clone(),
             handle: Once::new(),
+            open_handles: AtomicUsize::new(0),
+            datagram,
         })
     }
 
@@ -236,6 +395,107 @@ impl UnixSocket {
             .flags()
             .contains(OpenFlags::O_NONBLOCK)
     }
+
+    fn peer(&self) -> Option<Arc<UnixSocket>> {
+        match &self.inner.lock_irq().state {
+            UnixSocketState::Connected(peer) => Some(peer.clone()),
+            _ => None,
+        }
+    }
+
+    /// Looks up the datagram socket bound to `address`.
+    fn lookup_datagram(address: &UnixAddress) -> fs::Result<Arc<UnixSocket>> {
+        let target = address.lookup()?;
+
+        if !target.datagram {
+            return Err(FileSystemError::ConnectionRefused);
+        }
+
+        Ok(target)
+    }
+
+    /// Registers the socket under `name` in the abstract namespace.
+    fn bind_abstract(&self, name: Vec<u8>) -> fs::Result<UnixAddress> {
+        let mut names = ABSTRACT_NAMES.lock_irq();
+
+        if names
+            .get(&name)
+            .is_some_and(|socket| socket.strong_count() > 0)
+        {
+            return Err(FileSystemError::AddressInUse);
+        }
+
+        names.insert(name.clone(), self.weak.clone());
+        Ok(UnixAddress::Abstract(name))
+    }
+
+    /// Binds the socket to a unique abstract name of five hexadecimal digits, as Linux does when
+    /// binding to an unnamed address.
+    fn autobind(&self) -> fs::Result<UnixAddress> {
+        for _ in 0..=0xfffff {
+            let id = NEXT_AUTOBIND.fetch_add(1, Ordering::SeqCst) & 0xfffff;
+            let name = alloc::format!("{id:05x}").into_bytes();
+
+            match self.bind_abstract(name) {
+                Err(FileSystemError::AddressInUse) => continue,
+                result => return result,
+            }
+        }
+
+        Err(FileSystemError::AddressInUse)
+    }
+
+    /// Queues `data` as a single datagram on `target`, blocking while its queue is full.
+    fn send_datagram(
+        &self,
+        target: &UnixSocket,
+        data: &[u8],
+        non_block: bool,
+    ) -> fs::Result<usize> {
+        let sender = self.inner.lock_irq().address.clone();
+
+        if target.buffer.lock_irq().is_full() && non_block {
+            return Err(FileSystemError::WouldBlock);
+        }
+
+        let mut buffer = target
+            .wq
+            .block_on_interruptible(&target.buffer, |e| !e.is_full())
+            .map_err(|_| FileSystemError::Interrupted)?;
+        buffer.push(Message {
+            data: data.to_vec(),
+            sender,
+        });
+
+        core::mem::drop(buffer);
+        target.wq.notify_all();
+
+        Ok(data.len())
+    }
+
+    /// Removes the next datagram from the receive queue, blocking while it is empty. If `peek`
+    /// is set, the datagram is left in the queue.
+    fn recv_datagram(&self, non_block: bool, peek: bool) -> fs::Result<Message> {
+        if self.buffer.lock_irq().is_empty() && non_block {
+            return Err(FileSystemError::WouldBlock);
+        }
+
+        let mut buffer = self
+            .wq
+            .block_on_interruptible(&self.buffer, |e| !e.is_empty())
+            .map_err(|_| FileSystemError::Interrupted)?;
+
+        if peek {
+            return Ok(buffer.peek().expect("unix: datagram queue is empty"));
+        }
+
+        let message = buffer.pop().expect("unix: datagram queue is empty");
+        core::mem::drop(buffer);
+
+        // Wake up the senders waiting for space in the queue.
+        self.wq.notify_all();
+        Ok(message)
+    }
 }
 
 impl INodeInterface for UnixSocket {
@@ -250,21 +510,60 @@ impl INodeInterface for UnixSocket {
 
     fn open(&self, handle: Arc<FileHandle>) -> fs::Result<Option<DirCacheItem>> {
         self.handle.call_once(|| handle);
+        self.open_handles.fetch_add(1, Ordering::SeqCst);
         Ok(None)
     }
 
+    fn close(&self, _flags: OpenFlags) {
+        if self.open_handles.fetch_sub(1, Ordering::SeqCst) != 1 {
+            return;
+        }
+
+        // Unlike a socket file, an abstract name disappears with the socket. Sockets returned by
+        // `accept` share the name of the listener without owning it.
+        let address = self.inner.lock_irq().address.clone();
+
+        if let Some(UnixAddress::Abstract(name)) = address {
+            let mut names = ABSTRACT_NAMES.lock_irq();
+
+            if names
+                .get(&name)
+                .is_some_and(|socket| socket.ptr_eq(&self.weak))
+            {
+                names.remove(&name);
+            }
+        }
+    }
+
     fn read_at(&self, _offset: usize, user_buffer: &mut [u8]) -> fs::Result<usize> {
+        if self.datagram {
+            // The part of the datagram that does not fit is discarded.
+            let message = self.recv_datagram(self.is_non_block(), false)?;
+            let size = core::cmp::min(user_buffer.len(), message.data.len());
+
+            user_buffer[..size].copy_from_slice(&message.data[..size]);
+            return Ok(size);
+        }
+
         if self.buffer.lock_irq().is_empty() && self.is_non_block() {
             return Err(FileSystemError::WouldBlock);
         }
 
-        let mut buffer = self.wq.block_on(&self.buffer, |e| !e.is_empty())?;
+        let mut buffer = self
+            .wq
+            .block_on_interruptible(&self.buffer, |e| !e.is_empty())
+            .map_err(|_| FileSystemError::Interrupted)?;
 
         let read = buffer.read(user_buffer);
         Ok(read)
     }
 
     fn write_at(&self, _offset: usize, buffer: &[u8]) -> fs::Result<usize> {
+        if self.datagram {
+            let peer = self.peer().ok_or(FileSystemError::NotConnected)?;
+            return self.send_datagram(&peer, buffer, self.is_non_block());
+        }
+
         let inner = self.inner.lock_irq();
         let peer = match inner.state {
             UnixSocketState::Connected(ref peer) => peer,
@@ -278,6 +577,10 @@ impl INodeInterface for UnixSocket {
     }
 
     fn listen(&self, backlog: usize) -> Result<(), SyscallError> {
+        if self.datagram {
+            return Err(SyscallError::EOPNOTSUPP);
+        }
+
         let mut inner = self.inner.lock_irq();
         let is_bound = inner.address.is_some();
 
@@ -297,33 +600,54 @@ impl INodeInterface for UnixSocket {
         }
     }
 
-    fn bind(&self, address: SocketAddrRef, _length: usize) -> fs::Result<()> {
+    fn bind(&self, address: SocketAddrRef, length: usize) -> fs::Result<()> {
         let address = address.as_unix().ok_or(FileSystemError::NotSupported)?;
-        let path = path_from_unix_sock(address)?;
 
-        if fs::lookup_path(path).is_ok() {
-            return Err(FileSystemError::EntryExists);
+        if self.inner.lock_irq().address.is_some() {
+            return Err(FileSystemError::InvalidInput);
         }
 
-        let (parent, name) = path.parent_and_basename();
-        DirEntry::from_socket_inode(fs::lookup_path(parent)?, String::from(name), self.sref())?;
+        let address = match UnixAddress::parse(address, length)? {
+            Some(UnixAddress::Path(path)) => {
+                let path = Path::new(&path);
 
-        let mut inner = self.inner.lock_irq();
-        inner.address = Some(address.clone());
+                if fs::lookup_path(path).is_ok() {
+                    return Err(FileSystemError::EntryExists);
+                }
+
+                let (parent, name) = path.parent_and_basename();
+                let mode = scheduler::current_thread()
+                    .creation_mode(Mode::S_IRWXU | Mode::S_IRWXG | Mode::S_IRWXO);
+
+                let parent = fs::lookup_path(parent)?;
+                fs::check_writable(&parent.inode())?;
+
+                DirEntry::from_socket_inode(parent.clone(), String::from(name), self.sref(), mode)?;
+                fs::inotify::notify_entry(&parent.inode(), name, InotifyMask::CREATE, 0);
+
+                UnixAddress::Path(String::from(path.as_str()))
+            }
+
+            Some(UnixAddress::Abstract(name)) => self.bind_abstract(name)?,
+            None => self.autobind()?,
+        };
 
+        self.inner.lock_irq().address = Some(address);
         Ok(())
     }
 
-    fn connect(&self, address: SocketAddrRef, _length: usize) -> fs::Result<()> {
+    fn connect(&self, address: SocketAddrRef, length: usize) -> fs::Result<()> {
         let address = address.as_unix().ok_or(FileSystemError::NotSupported)?;
-        let path = path_from_unix_sock(address)?;
-        let socket = fs::lookup_path(path)?;
+        let address = UnixAddress::parse(address, length)?.ok_or(FileSystemError::InvalidPath)?;
 
-        let target = socket
-            .inode()
-            .as_unix_socket()?
-            .downcast_arc::<UnixSocket>()
-            .ok_or(FileSystemError::NotSocket)?;
+        if self.datagram {
+            // Connecting a datagram socket only sets its default destination.
+            let target = Self::lookup_datagram(&address)?;
+            self.inner.lock_irq().state = UnixSocketState::Connected(target);
+            return Ok(());
+        }
+
+        let target = address.lookup()?;
 
         let mut itarget = target.inner.lock_irq();
 
@@ -341,9 +665,12 @@ impl INodeInterface for UnixSocket {
     }
 
     fn accept(&self, address: Option<(VirtAddr, &mut u32)>) -> fs::Result<Arc<UnixSocket>> {
-        let mut inner = self.wq.block_on(&self.inner, |e| {
-            e.state.queue().is_some_and(|x| !x.is_empty())
-        })?;
+        let mut inner = self
+            .wq
+            .block_on_interruptible(&self.inner, |e| {
+                e.state.queue().is_some_and(|x| !x.is_empty())
+            })
+            .map_err(|_| FileSystemError::Interrupted)?;
 
         let queue = inner
             .state
@@ -366,17 +693,13 @@ impl INodeInterface for UnixSocket {
 
         // THIS SHOULD NOT BE DONE HERE
         if let Some((address, length)) = address {
-            let mut address = unsafe { UserRef::new(address) };
+            let mut address = UserRef::<SocketAddrUnix>::new(address)?;
+            let (peer_address, peer_length) =
+                UnixAddress::to_sockaddr(peer.inner.lock_irq().address.as_ref());
 
-            if let Some(paddr) = inner.address.as_ref() {
-                *address = paddr.clone();
-            } else {
-                *address = SocketAddrUnix::default();
-                address.family = AF_UNIX;
-            }
-
-            // THIS IS WRONG use addr.name_len() + offset_of!(sockaddr_un, path)
-            *length = core::mem::size_of::<SocketAddrUnix>() as u32;
+            *address = peer_address;
+            address.write_back()?;
+            *length = peer_length as u32;
         }
 
         peer.wq.notify_all();
@@ -384,33 +707,49 @@ impl INodeInterface for UnixSocket {
     }
 
     fn recv(&self, header: &mut MessageHeader, flags: MessageFlags) -> fs::Result<usize> {
-        // assert!(flags.is_empty());
+        let non_block = self.is_non_block() || flags.contains(MessageFlags::DONTWAIT);
+        let peek = flags.contains(MessageFlags::PEEK);
 
-        let inner = self.inner.lock_irq();
+        if self.datagram {
+            let message = self.recv_datagram(non_block, peek)?;
+            let copied = header.scatter(&message.data);
 
-        let peer = match &inner.state {
-            UnixSocketState::Connected(peer) => peer,
-            _ => return Err(FileSystemError::NotConnected),
-        };
+            // The part of the datagram that does not fit is discarded.
+            if copied < message.data.len() {
+                header.flags |= MessageFlags::TRUNC.bits() as i32;
+            }
 
-        if self.buffer.lock_irq().is_empty() && self.is_non_block() {
-            return Err(FileSystemError::WouldBlock);
+            let (sender, name_len) = UnixAddress::to_sockaddr(message.sender.as_ref());
+            set_message_name(header, &sender, name_len)?;
+
+            // With `MSG_TRUNC`, the size of the whole datagram is returned.
+            if flags.contains(MessageFlags::TRUNC) {
+                return Ok(message.data.len());
+            }
+
+            return Ok(copied);
         }
 
-        let mut buffer = self.wq.block_on(&self.buffer, |e| !e.is_empty())?;
+        let peer = self.peer().ok_or(FileSystemError::NotConnected)?;
 
-        if let Some(addr) = header.name_mut::<SocketAddrUnix>() {
-            *addr = peer.inner.lock_irq().address.as_ref().cloned().unwrap();
+        if self.buffer.lock_irq().is_empty() && non_block {
+            return Err(FileSystemError::WouldBlock);
         }
 
-        Ok(header
-            .iovecs_mut()
-            .iter_mut()
-            .map(|iovec| buffer.read(iovec.as_slice_mut()))
-            .sum::<usize>())
+        let data = self
+            .wq
+            .block_on_interruptible(&self.buffer, |e| !e.is_empty())
+            .map_err(|_| FileSystemError::Interrupted)?
+            .read_stream(header.iovecs_len(), peek);
+
+        let (peer_address, name_len) =
+            UnixAddress::to_sockaddr(peer.inner.lock_irq().address.as_ref());
+        set_message_name(header, &peer_address, name_len)?;
+
+        Ok(header.scatter(&data))
     }
 
-    fn send(&self, header: &mut MessageHeader, _flags: MessageFlags) -> fs::Result<usize> {
+    fn send(&self, header: &mut MessageHeader, flags: MessageFlags) -> fs::Result<usize> {
         // FIXME(andyython): figure out the message header stuff...
         let data = header
             .iovecs()
@@ -419,6 +758,16 @@ impl INodeInterface for UnixSocket {
             .copied()
             .collect::<Vec<_>>();
 
+        if self.datagram {
+            let target = match message_name(header)? {
+                Some(name) => Self::lookup_datagram(&UnixAddress::from_name(&name)?)?,
+                None => self.peer().ok_or(FileSystemError::NotConnected)?,
+            };
+
+            let non_block = self.is_non_block() || flags.contains(MessageFlags::DONTWAIT);
+            return self.send_datagram(&target, &data, non_block);
+        }
+
         self.write_at(0, &data)
     }
 
@@ -453,12 +802,9 @@ impl INodeInterface for UnixSocket {
 
     fn get_sockname(&self) -> fs::Result<super::SocketAddr> {
         let inner = self.inner.lock_irq();
-        let address = inner.address.as_ref().cloned().unwrap_or(SocketAddrUnix {
-            family: AF_UNIX,
-            path: [0; 108],
-        });
+        let (address, length) = UnixAddress::to_sockaddr(inner.address.as_ref());
 
-        Ok(super::SocketAddr::Unix(address.clone()))
+        Ok(super::SocketAddr::Unix(address, length))
     }
 
     fn get_peername(&self) -> fs::Result<super::SocketAddr> {
@@ -469,11 +815,37 @@ impl INodeInterface for UnixSocket {
         };
 
         let peer = peer.inner.lock_irq();
-        let address = peer.address.as_ref().cloned().unwrap_or(SocketAddrUnix {
-            family: AF_UNIX,
-            path: [0; 108],
-        });
+        let (address, length) = UnixAddress::to_sockaddr(peer.address.as_ref());
+
+        Ok(super::SocketAddr::Unix(address, length))
+    }
+
+    fn fdinfo(&self, out: &mut String) {
+        let peer = self.peer();
+        let inner = self.inner.lock_irq();
+
+        let kind = if self.datagram { "dgram" } else { "stream" };
+        let state = match &inner.state {
+            UnixSocketState::Disconnected => "disconnected",
+            UnixSocketState::Listening(_) => "listening",
+            UnixSocketState::Connected(_) => "connected",
+        };
+
+        let _ = writeln!(out, "type:\tunix {kind}");
+        let _ = writeln!(out, "state:\t{state}");
+        let _ = writeln!(out, "local:\t{}", address_name(inner.address.as_ref()));
+
+        if let UnixSocketState::Listening(queue) = &inner.state {
+            let _ = writeln!(out, "accept_queue:\t{}/{}", queue.len(), queue.backlog);
+        }
+
+        core::mem::drop(inner);
+
+        if let Some(peer) = peer {
+            let peer = peer.inner.lock_irq();
+            let _ = writeln!(out, "peer:\t{}", address_name(peer.address.as_ref()));
+        }
 
-        Ok(super::SocketAddr::Unix(address.clone()))
+        let _ = writeln!(out, "rx_queue:\t{}", self.buffer.lock_irq().bytes());
     }
 }
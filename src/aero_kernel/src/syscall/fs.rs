@@ -17,20 +17,32 @@
 
 use core::fmt;
 
+use aero_syscall::io_uring::{self, IoUringParams};
+use aero_syscall::perf::{PerfEventAttr, PerfEventFlags, PERF_ATTR_SIZE_VER0};
 use aero_syscall::prelude::*;
 use aero_syscall::signal::SigProcMask;
-use aero_syscall::{AtFlags, OpenFlags, Stat, TimeSpec, AT_FDCWD};
+use aero_syscall::{
+    AtFlags, Mode, MqAttr, OpenFlags, OpenHow, ResolveFlags, Stat, StatFs, Statx, StatxMask,
+    TimeSpec, AT_FDCWD, W_OK,
+};
 use alloc::sync::{Arc, Weak};
+use num_traits::FromPrimitive;
 
-use crate::fs::cache::{self, DirCacheImpl};
+use crate::arch::perf::PerfEvent;
+use crate::arch::user_copy::{copy_slice_to_user, copy_to_user};
+use crate::fs::cache::{self, DirCacheImpl, DirCacheItem};
 use crate::fs::epoll::EPoll;
 use crate::fs::eventfd::EventFd;
 use crate::fs::file_table::{DuplicateHint, FileHandle};
 use crate::fs::inode::{DirEntry, PollTable};
+use crate::fs::inotify::{self, Inotify};
+use crate::fs::io_uring::IoUring;
+use crate::fs::memfd::{self, MemFd};
+use crate::fs::mqueue::{self, MessageQueue};
 use crate::fs::pipe::Pipe;
 use crate::fs::{self, LookupMode};
-use crate::syscall::SysArg;
-use crate::userland::scheduler;
+use crate::mem::paging::ReadErr;
+use crate::userland::{scheduler, signals};
 
 use crate::fs::Path;
 
@@ -48,6 +60,21 @@ impl FileDescriptor {
             .get_handle(self.0)
             .ok_or(SyscallError::EBADFD)
     }
+
+    /// Returns the file handle associated with this file descriptor if it can be used for I/O.
+    ///
+    /// ## Errors
+    /// * `EBADFD`: The file descriptor is not a valid open file descriptor.
+    /// * `EBADF`: The file descriptor was opened with `O_PATH`.
+    pub fn io_handle(&self) -> aero_syscall::Result<Arc<FileHandle>> {
+        let handle = self.handle()?;
+
+        if handle.is_path() {
+            return Err(SyscallError::EBADF);
+        }
+
+        Ok(handle)
+    }
 }
 
 impl fmt::Display for FileDescriptor {
@@ -63,8 +90,8 @@ impl fmt::Display for FileDescriptor {
 }
 
 impl super::SysArg for FileDescriptor {
-    fn from_usize(value: usize) -> Self {
-        Self(value)
+    fn from_usize(value: usize) -> Result<Self, ReadErr> {
+        Ok(Self(value))
     }
 }
 
@@ -74,7 +101,58 @@ impl From<FileDescriptor> for usize {
     }
 }
 
-#[syscall]
+/// Directory file descriptor argument of the `*at` family of system calls.
+///
+/// Relative paths are resolved against the directory referred to by the file descriptor, or
+/// against the current working directory of the calling task if the value is [`AT_FDCWD`].
+#[derive(Debug, Copy, Clone)]
+pub struct DirFd(isize);
+
+impl DirFd {
+    /// Returns the directory entry that `path` should be looked up from.
+    ///
+    /// ## Errors
+    /// * `EBADFD`: The directory file descriptor is not a valid open file descriptor.
+    /// * `ENOTDIR`: `path` is relative and non-empty, and the file descriptor does not refer to a
+    ///   directory.
+    pub fn at(&self, path: &Path) -> aero_syscall::Result<DirCacheItem> {
+        if path.is_absolute() {
+            return Ok(fs::root_dir().clone());
+        }
+
+        if self.0 == AT_FDCWD {
+            return Ok(scheduler::current_thread().cwd_dirent());
+        }
+
+        let ent = FileDescriptor(self.0 as usize).handle()?.inode.clone();
+
+        // An empty path refers to the file descriptor itself (`AT_EMPTY_PATH`), which is not
+        // required to be a directory.
+        if !path.is_empty() && !ent.inode().metadata()?.is_directory() {
+            return Err(SyscallError::ENOTDIR);
+        }
+
+        Ok(ent)
+    }
+}
+
+impl fmt::Display for DirFd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == AT_FDCWD {
+            write!(f, "AT_FDCWD")
+        } else {
+            fmt::Display::fmt(&FileDescriptor(self.0 as usize), f)
+        }
+    }
+}
+
+impl super::SysArg for DirFd {
+    fn from_usize(value: usize) -> Result<Self, ReadErr> {
+        Ok(Self(value as isize))
+    }
+}
+
+#[syscall(number(SYS_WRITE))]
 pub fn write(fd: FileDescriptor, buffer: &[u8]) -> Result<usize, SyscallError> {
     // FIXME(heck for xeyes): fnctl should update the open flags!
     //
@@ -82,64 +160,124 @@ pub fn write(fd: FileDescriptor, buffer: &[u8]) -> Result<usize, SyscallError> {
     //     .flags
     //     .intersects(OpenFlags::O_WRONLY | OpenFlags::O_RDWR)
     // {
-    Ok(fd.handle()?.write(buffer)?)
+    let handle = fd.io_handle()?;
+    let written = handle.write(buffer).map_err(signals::restartable)?;
+
+    if written > 0 {
+        inotify::notify(&handle.inode, InotifyMask::MODIFY);
+    }
+
+    Ok(written)
     // } else {
     //     Err(SyscallError::EACCES)
     // }
 }
 
-#[syscall]
+#[syscall(number(SYS_READ))]
 pub fn read(fd: FileDescriptor, buffer: &mut [u8]) -> Result<usize, SyscallError> {
     // if handle
     //     .flags
     //     .read()
     //     .intersects(OpenFlags::O_RDONLY | OpenFlags::O_RDWR)
     // {
-    Ok(fd.handle()?.read(buffer)?)
+    let handle = fd.io_handle()?;
+    let read = handle.read(buffer).map_err(signals::restartable)?;
+
+    if read > 0 {
+        inotify::notify(&handle.inode, InotifyMask::ACCESS);
+    }
+
+    Ok(read)
     // } else {
     //     Err(SyscallError::EACCES)
     // }
 }
 
-#[syscall]
-pub fn open(fd: usize, path: &Path, flags: usize, _mode: usize) -> Result<usize, SyscallError> {
-    let current_thread = scheduler::current_thread();
-    let at = match fd as isize {
-        AT_FDCWD if !path.is_absolute() => current_thread.cwd_dirent(),
-        _ if !path.is_absolute() => {
-            let ent = FileDescriptor::from_usize(fd).handle()?.inode.clone();
-            assert!(ent.inode().metadata()?.is_directory());
-            ent
-        }
-        _ => fs::root_dir().clone(),
-    };
+/// Flags that are honoured when `O_PATH` is specified; all other flags are ignored.
+const O_PATH_MASK: OpenFlags = OpenFlags::from_bits_truncate(
+    OpenFlags::O_PATH.bits()
+        | OpenFlags::O_CLOEXEC.bits()
+        | OpenFlags::O_DIRECTORY.bits()
+        | OpenFlags::O_NOFOLLOW.bits(),
+);
+
+#[syscall(number(SYS_OPEN))]
+pub fn open(fd: DirFd, path: &Path, flags: usize, mode: usize) -> Result<usize, SyscallError> {
+    let flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+    let mode = Mode::from_bits_truncate(mode as u32);
+
+    do_open(fd, path, flags, mode, ResolveFlags::empty())
+}
+
+#[syscall(number(SYS_OPENAT2))]
+pub fn openat2(fd: DirFd, path: &Path, how: &OpenHow, size: usize) -> Result<usize, SyscallError> {
+    if size < core::mem::size_of::<OpenHow>() {
+        return Err(SyscallError::EINVAL);
+    }
 
-    let mut flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+    let flags = OpenFlags::from_bits(how.flags as usize).ok_or(SyscallError::EINVAL)?;
+    let resolve = ResolveFlags::from_bits(how.resolve).ok_or(SyscallError::EINVAL)?;
+    let mode = Mode::from_bits_truncate(how.mode as u32);
 
-    if !flags.intersects(OpenFlags::O_RDONLY | OpenFlags::O_RDWR | OpenFlags::O_WRONLY) {
+    do_open(fd, path, flags, mode, resolve)
+}
+
+fn do_open(
+    fd: DirFd,
+    path: &Path,
+    mut flags: OpenFlags,
+    mode: Mode,
+    resolve: ResolveFlags,
+) -> Result<usize, SyscallError> {
+    let current_thread = scheduler::current_thread();
+    let at = fd.at(path)?;
+
+    if flags.contains(OpenFlags::O_PATH) {
+        flags &= O_PATH_MASK;
+    } else if !flags.intersects(OpenFlags::O_RDONLY | OpenFlags::O_RDWR | OpenFlags::O_WRONLY) {
         flags.insert(OpenFlags::O_RDONLY);
     }
 
     let mut lookup_mode = LookupMode::None;
 
     if flags.contains(OpenFlags::O_CREAT) {
-        lookup_mode = LookupMode::Create;
+        lookup_mode = LookupMode::Create(current_thread.creation_mode(mode));
     }
 
-    let inode = fs::lookup_path_with(at, path, lookup_mode, true)?;
+    let resolve_last = !flags.contains(OpenFlags::O_NOFOLLOW);
+    let inode = fs::lookup_path_resolve(at, path, lookup_mode, resolve_last, resolve)?;
 
-    if flags.contains(OpenFlags::O_DIRECTORY) && !inode.inode().metadata()?.is_directory() {
+    let metadata = inode.inode().metadata()?;
+
+    if flags.contains(OpenFlags::O_DIRECTORY) && !metadata.is_directory() {
         return Err(SyscallError::ENOTDIR);
     }
 
+    // Without `O_PATH`, `O_NOFOLLOW` fails if the trailing component is a symbolic link.
+    if metadata.is_symlink() && !flags.contains(OpenFlags::O_PATH) {
+        return Err(SyscallError::ELOOP);
+    }
+
+    let writes = OpenFlags::O_WRONLY | OpenFlags::O_RDWR | OpenFlags::O_TRUNC | OpenFlags::O_CREAT;
+
+    if flags.intersects(writes) {
+        fs::check_writable(&inode.inode())?;
+    }
+
     if flags.contains(OpenFlags::O_TRUNC) {
         inode.inode().truncate(0)?;
     }
 
-    Ok(current_thread.file_table.open_file(inode.clone(), flags)?)
+    let fd = current_thread.file_table.open_file(inode.clone(), flags)?;
+
+    if !flags.contains(OpenFlags::O_PATH) {
+        inotify::notify(&inode, InotifyMask::OPEN);
+    }
+
+    Ok(fd)
 }
 
-#[syscall]
+#[syscall(number(SYS_DUP))]
 pub fn dup(fd: FileDescriptor, flags: usize) -> Result<usize, SyscallError> {
     let task = scheduler::get_scheduler().current_task();
     let flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)? & OpenFlags::O_CLOEXEC;
@@ -148,21 +286,44 @@ pub fn dup(fd: FileDescriptor, flags: usize) -> Result<usize, SyscallError> {
         .duplicate(fd.into(), DuplicateHint::Any, flags)
 }
 
-#[syscall]
-pub fn dup2(fd: FileDescriptor, new_fd: usize, flags: usize) -> Result<usize, SyscallError> {
-    let task = scheduler::get_scheduler().current_task();
-    let flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)? & OpenFlags::O_CLOEXEC;
+/// Duplicates `fd` onto `new_fd`, closing the file `new_fd` referred to first. The close-on-exec
+/// flag of `new_fd` is cleared.
+#[syscall(number(SYS_DUP2))]
+pub fn dup2(fd: FileDescriptor, new_fd: usize) -> Result<usize, SyscallError> {
+    // Duplicating a file descriptor onto itself does nothing, as long as it is valid.
+    if fd.0 == new_fd {
+        fd.handle()?;
+        return Ok(new_fd);
+    }
 
-    task.file_table
+    scheduler::current_thread().file_table.duplicate(
+        fd.into(),
+        DuplicateHint::Exact(new_fd),
+        OpenFlags::empty(),
+    )
+}
+
+/// Same as [`dup2`], except that `O_CLOEXEC` can be passed in `flags` to set the close-on-exec
+/// flag of `new_fd`, and that `fd` and `new_fd` must be different.
+#[syscall(number(SYS_DUP3))]
+pub fn dup3(fd: FileDescriptor, new_fd: usize, flags: usize) -> Result<usize, SyscallError> {
+    let flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    if fd.0 == new_fd || !(flags - OpenFlags::O_CLOEXEC).is_empty() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    scheduler::current_thread()
+        .file_table
         .duplicate(fd.into(), DuplicateHint::Exact(new_fd), flags)
 }
 
-#[syscall]
+#[syscall(number(SYS_GETDENTS))]
 pub fn getdents(fd: FileDescriptor, buffer: &mut [u8]) -> Result<usize, SyscallError> {
-    Ok(fd.handle()?.get_dents(buffer)?)
+    Ok(fd.io_handle()?.get_dents(buffer)?)
 }
 
-#[syscall]
+#[syscall(number(SYS_CLOSE))]
 pub fn close(fd: FileDescriptor) -> Result<usize, SyscallError> {
     let res = scheduler::get_scheduler()
         .current_task()
@@ -177,20 +338,16 @@ pub fn close(fd: FileDescriptor) -> Result<usize, SyscallError> {
     }
 }
 
-#[syscall]
-pub fn chdir(fd: usize, path: &Path) -> Result<usize, SyscallError> {
+#[syscall(number(SYS_CHDIR))]
+pub fn chdir(fd: DirFd, path: &Path) -> Result<usize, SyscallError> {
     let current_thread = scheduler::current_thread();
-    let at = match fd as isize {
-        AT_FDCWD if !path.is_absolute() => current_thread.cwd_dirent(),
-        _ if !path.is_absolute() => {
-            let ent = FileDescriptor::from_usize(fd).handle()?.inode.clone();
-            assert!(ent.inode().metadata()?.is_directory());
-            ent
-        }
-        _ => fs::root_dir().clone(),
-    };
+    let at = fd.at(path)?;
 
     if path.is_empty() {
+        if !at.inode().metadata()?.is_directory() {
+            return Err(SyscallError::ENOTDIR);
+        }
+
         current_thread.set_cwd(at);
         return Ok(0);
     }
@@ -204,8 +361,8 @@ pub fn chdir(fd: usize, path: &Path) -> Result<usize, SyscallError> {
     Ok(0)
 }
 
-#[syscall]
-pub fn mkdirat(dfd: usize, path: &Path) -> Result<usize, SyscallError> {
+#[syscall(number(SYS_MKDIR_AT))]
+pub fn mkdirat(dfd: usize, path: &Path, mode: usize) -> Result<usize, SyscallError> {
     // NOTE: If the pathname given in pathname is relative, then it is interpreted
     // relative to the directory referred to by the file descriptor (rather than relative
     // to the current working directory of the calling task, as is done by mkdir() for a
@@ -241,27 +398,41 @@ pub fn mkdirat(dfd: usize, path: &Path) -> Result<usize, SyscallError> {
         return Err(SyscallError::EEXIST);
     }
 
-    parent_inode.mkdir(child)?;
+    fs::check_writable(&parent_inode)?;
+
+    let mode = scheduler::current_thread().creation_mode(Mode::from_bits_truncate(mode as u32));
+
+    parent_inode.mkdir(child, mode)?;
+    inotify::notify_entry(
+        &parent_inode,
+        child,
+        InotifyMask::CREATE | InotifyMask::ISDIR,
+        0,
+    );
+
     Ok(0x00)
 }
 
-#[syscall]
+#[syscall(number(SYS_RMDIR))]
 pub fn rmdir(path: &Path) -> Result<usize, SyscallError> {
-    let (_, child) = path.parent_and_basename();
-    let inode = fs::lookup_path(path)?;
+    let entry = fs::lookup_path(path)?;
 
-    if !inode.inode().metadata()?.is_directory() {
+    if !entry.inode().metadata()?.is_directory() {
         // ENOTDIR: A component used as a directory in pathname, is not in fact,
         // a directory.
         return Err(SyscallError::ENOTDIR);
     }
 
-    inode.inode().rmdir(child)?;
-    inode.drop_from_cache();
+    // The directory is removed from its parent. The root directory cannot be removed.
+    let parent = entry.parent().ok_or(SyscallError::EBUSY)?;
+    fs::check_writable(&parent.inode())?;
+
+    parent.inode().rmdir(&entry.name())?;
+    entry.drop_from_cache();
     Ok(0x00)
 }
 
-#[syscall]
+#[syscall(number(SYS_GETCWD))]
 pub fn getcwd(buffer: &mut [u8]) -> Result<usize, SyscallError> {
     let cwd = scheduler::current_thread().get_cwd();
     log::debug!("getcwd: {}", cwd);
@@ -274,9 +445,9 @@ pub fn getcwd(buffer: &mut [u8]) -> Result<usize, SyscallError> {
     Ok(cwd.len())
 }
 
-#[syscall]
+#[syscall(number(SYS_IOCTL))]
 pub fn ioctl(fd: FileDescriptor, command: usize, argument: usize) -> Result<usize, SyscallError> {
-    let handle = fd.handle()?;
+    let handle = fd.io_handle()?;
 
     match command {
         // Sets the close-on-exec file descriptor flag. This is equivalent
@@ -298,13 +469,13 @@ pub fn ioctl(fd: FileDescriptor, command: usize, argument: usize) -> Result<usiz
     }
 }
 
-#[syscall]
+#[syscall(number(SYS_SEEK))]
 pub fn seek(fd: FileDescriptor, offset: usize, whence: usize) -> Result<usize, SyscallError> {
-    let handle = fd.handle()?;
+    let handle = fd.io_handle()?;
     Ok(handle.seek(offset as isize, aero_syscall::SeekWhence::from(whence))?)
 }
 
-#[syscall]
+#[syscall(number(SYS_PIPE))]
 pub fn pipe(fds: &mut [i32; 2], flags: usize) -> Result<usize, SyscallError> {
     let flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
     let pipe = Pipe::new();
@@ -336,41 +507,59 @@ pub fn pipe(fds: &mut [i32; 2], flags: usize) -> Result<usize, SyscallError> {
     Ok(0x00)
 }
 
-#[syscall]
-pub fn unlink(_fd: usize, _path: &Path, _flags: usize) -> Result<usize, SyscallError> {
-    // let _flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
-    // let name = path.container();
+/// Removes the directory entry `path`, or the empty directory `path` if `flags` contains
+/// `AT_REMOVEDIR`.
+#[syscall(number(SYS_UNLINK))]
+pub fn unlink(fd: DirFd, path: &Path, flags: usize) -> Result<usize, SyscallError> {
+    let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+    let at = fd.at(path)?;
 
-    // if fd as isize == aero_syscall::AT_FDCWD {
-    //     let file = fs::lookup_path(path)?;
+    let entry = fs::lookup_path_with(at, path, LookupMode::None, false)?;
+    // The root directory cannot be removed.
+    let parent = entry.parent().ok_or(SyscallError::EBUSY)?;
+    fs::check_writable(&parent.inode())?;
 
-    //     if let Some(dir) = file.parent() {
-    //         let metadata = file.inode().metadata()?;
+    let name = entry.name();
+    let is_directory = entry.inode().metadata()?.is_directory();
 
-    //         if metadata.is_file() {
-    //             dir.inode().unlink(name.as_str())?;
-    //             file.drop_from_cache();
-    //         }
-    //     }
-    // } else {
-    //     unimplemented!()
-    // }
+    if flags.contains(AtFlags::REMOVEDIR) {
+        if !is_directory {
+            return Err(SyscallError::ENOTDIR);
+        }
 
-    Ok(0x00)
+        parent.inode().rmdir(&name)?;
+    } else {
+        if is_directory {
+            return Err(SyscallError::EISDIR);
+        }
+
+        parent.inode().unlink(&name)?;
+    }
+
+    entry.drop_from_cache();
+
+    let mut mask = InotifyMask::DELETE;
+
+    if is_directory {
+        mask.insert(InotifyMask::ISDIR);
+    }
+
+    inotify::notify_entry(&parent.inode(), &name, mask, 0);
+    Ok(0)
 }
 
-#[syscall]
-pub fn access(fd: usize, path: &Path, _mode: usize, flags: usize) -> Result<usize, SyscallError> {
-    let at = match fd as isize {
-        AT_FDCWD if !path.is_absolute() => scheduler::current_thread().cwd_dirent(),
-        _ if !path.is_absolute() => FileDescriptor::from_usize(fd).handle()?.inode.clone(),
-        _ => fs::root_dir().clone(),
-    };
+#[syscall(number(SYS_ACCESS))]
+pub fn access(fd: DirFd, path: &Path, mode: usize, flags: usize) -> Result<usize, SyscallError> {
+    let at = fd.at(path)?;
 
     let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
     let resolve_last = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
-    let _ = fs::lookup_path_with(at, path, LookupMode::None, resolve_last)?;
+    let entry = fs::lookup_path_with(at, path, LookupMode::None, resolve_last)?;
+
+    if mode & W_OK != 0 {
+        fs::check_writable(&entry.inode())?;
+    }
 
     Ok(0)
 }
@@ -383,7 +572,7 @@ const SETFL_MASK: OpenFlags = OpenFlags::from_bits_truncate(
         | OpenFlags::O_NOATIME.bits(),
 );
 
-#[syscall]
+#[syscall(number(SYS_FCNTL))]
 pub fn fcntl(fd: FileDescriptor, command: usize, arg: usize) -> Result<usize, SyscallError> {
     let handle = fd.handle()?;
 
@@ -400,13 +589,13 @@ pub fn fcntl(fd: FileDescriptor, command: usize, arg: usize) -> Result<usize, Sy
         aero_syscall::prelude::F_DUPFD => scheduler::current_thread().file_table.duplicate(
             fd.into(),
             DuplicateHint::GreatorOrEqual(arg),
-            handle.flags(),
+            OpenFlags::empty(),
         ),
 
         aero_syscall::prelude::F_DUPFD_CLOEXEC => scheduler::current_thread().file_table.duplicate(
             fd.into(),
             DuplicateHint::GreatorOrEqual(arg),
-            handle.flags() | OpenFlags::O_CLOEXEC,
+            OpenFlags::O_CLOEXEC,
         ),
 
         // Get the value of file descriptor flags.
@@ -447,6 +636,27 @@ pub fn fcntl(fd: FileDescriptor, command: usize, arg: usize) -> Result<usize, Sy
             Ok(0)
         }
 
+        // Add seals to a file created with `MFD_ALLOW_SEALING`:
+        aero_syscall::prelude::F_ADD_SEALS => {
+            let seals = SealFlags::from_bits(arg).ok_or(SyscallError::EINVAL)?;
+
+            if !handle.is_writable() {
+                return Err(SyscallError::EPERM);
+            }
+
+            let inode = handle.inode();
+
+            // The contents could still be changed through an existing writable mapping.
+            if seals.contains(SealFlags::WRITE) && memfd::is_writably_mapped(&inode) {
+                return Err(SyscallError::EBUSY);
+            }
+
+            inode.add_seals(seals)?;
+            Ok(0)
+        }
+
+        aero_syscall::prelude::F_GET_SEALS => Ok(handle.inode().seals()?.bits()),
+
         aero_syscall::prelude::F_SETLKW | aero_syscall::prelude::F_SETLK => {
             log::warn!("fcntl: F_SETLKW,F_SETLK are a stub!");
             Ok(0)
@@ -459,13 +669,9 @@ pub fn fcntl(fd: FileDescriptor, command: usize, arg: usize) -> Result<usize, Sy
     }
 }
 
-#[syscall]
-pub fn fstat(fd: usize, path: &Path, flags: usize, stat: &mut Stat) -> Result<usize, SyscallError> {
-    let at = match fd as isize {
-        AT_FDCWD if !path.is_absolute() => scheduler::current_thread().cwd_dirent(),
-        _ if !path.is_absolute() => FileDescriptor::from_usize(fd).handle()?.inode.clone(),
-        _ => fs::root_dir().clone(),
-    };
+#[syscall(number(SYS_FSTAT))]
+pub fn fstat(fd: DirFd, path: &Path, flags: usize, stat: &mut Stat) -> Result<usize, SyscallError> {
+    let at = fd.at(path)?;
 
     // TODO: derive(SysArg) for bitflags.
     let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
@@ -486,14 +692,70 @@ pub fn fstat(fd: usize, path: &Path, flags: usize, stat: &mut Stat) -> Result<us
     Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_STATX))]
+pub fn statx(
+    fd: DirFd,
+    path: &Path,
+    flags: usize,
+    mask: usize,
+    statx: &mut Statx,
+) -> Result<usize, SyscallError> {
+    let at = fd.at(path)?;
+
+    let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+    let mask = StatxMask::from_bits_truncate(mask as u32);
+
+    if flags.intersects(AtFlags::EACCESS | AtFlags::REMOVEDIR)
+        || flags.contains(AtFlags::STATX_SYNC_TYPE)
+        || mask.contains(StatxMask::RESERVED)
+    {
+        return Err(SyscallError::EINVAL);
+    }
+
+    // All of the filesystems are local so, the cached attributes are always up to date and
+    // `AT_STATX_SYNC_AS_STAT`, `AT_STATX_FORCE_SYNC` and `AT_STATX_DONT_SYNC` behave the same.
+    //
+    // Like Linux, the fields that are cheap to get are filled in even if they were not requested
+    // in `mask`, and `stx_mask` tells which ones are valid.
+    let inode = if path.is_empty() {
+        if !flags.contains(AtFlags::EMPTY_PATH) {
+            return Err(SyscallError::ENOENT);
+        }
+
+        at.inode()
+    } else {
+        let resolve_last = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
+        fs::lookup_path_with(at, path, LookupMode::None, resolve_last)?.inode()
+    };
+
+    *statx = inode.statx()?;
+    Ok(0)
+}
+
+#[syscall(number(SYS_STAT))]
 pub fn stat(path: &Path, stat: &mut Stat) -> Result<usize, SyscallError> {
     let file = fs::lookup_path(path)?;
     *stat = file.inode().stat()?;
     Ok(0)
 }
 
-#[syscall]
+/// Returns information about the filesystem that the file at `path` is on. An empty `path` refers
+/// to the file descriptor `fd` itself (`fstatfs`).
+#[syscall(number(SYS_STATFS))]
+pub fn statfs(fd: DirFd, path: &Path, statfs: &mut StatFs) -> Result<usize, SyscallError> {
+    let at = fd.at(path)?;
+
+    let inode = if path.is_empty() {
+        at.inode()
+    } else {
+        fs::lookup_path_with(at, path, LookupMode::None, true)?.inode()
+    };
+
+    *statfs = fs::statfs(&inode)?;
+    Ok(0)
+}
+
+#[syscall(number(SYS_READ_LINK))]
 pub fn read_link(path: &Path, buffer: &mut [u8]) -> Result<usize, SyscallError> {
     // XXX: lookup_path with automatically resolve the link.
     let cwd = if !path.is_absolute() {
@@ -523,7 +785,7 @@ pub fn read_link(path: &Path, buffer: &mut [u8]) -> Result<usize, SyscallError>
 }
 
 /// Returns a file descriptor referring to the new epoll instance.
-#[syscall]
+#[syscall(number(SYS_EPOLL_CREATE))]
 pub fn epoll_create(flags: usize) -> Result<usize, SyscallError> {
     let _flags = EPollFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
@@ -539,7 +801,7 @@ pub fn epoll_create(flags: usize) -> Result<usize, SyscallError> {
 /// Used to add, modify, or remove entries in the interest list of the
 /// epoll instance referred to by the file descriptor. It requests that
 /// the operation be performed for the target file descriptor.
-#[syscall]
+#[syscall(number(SYS_EPOLL_CTL))]
 pub fn epoll_ctl(
     epfd: FileDescriptor,
     mode: usize,
@@ -573,7 +835,7 @@ pub fn epoll_ctl(
     }
 }
 
-#[syscall]
+#[syscall(number(SYS_EPOLL_PWAIT))]
 pub fn epoll_pwait(
     epfd: FileDescriptor,
     event: &mut [EPollEvent],
@@ -603,7 +865,7 @@ pub fn epoll_pwait(
     Ok(result)
 }
 
-#[syscall]
+#[syscall(number(SYS_EVENT_FD))]
 pub fn event_fd(_initval: usize, flags: usize) -> Result<usize, SyscallError> {
     let flags = EventFdFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
     assert!(!flags.contains(EventFdFlags::SEMAPHORE)); // todo: implement event fd semaphore support.
@@ -618,9 +880,374 @@ pub fn event_fd(_initval: usize, flags: usize) -> Result<usize, SyscallError> {
         .open_file(entry, OpenFlags::O_RDWR)?)
 }
 
+/// Creates an anonymous file that lives in memory and returns a file descriptor referring to
+/// it. The file is empty and is sized with `ftruncate`. The `name` is only used for debugging.
+///
+/// With `MFD_ALLOW_SEALING`, the file can be sealed with `fcntl(F_ADD_SEALS)`.
+#[syscall(number(SYS_MEMFD_CREATE))]
+pub fn memfd_create(name: &str, flags: usize) -> Result<usize, SyscallError> {
+    let flags = MemFdFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    let mut open_flags = OpenFlags::O_RDWR;
+    if flags.contains(MemFdFlags::CLOEXEC) {
+        open_flags.insert(OpenFlags::O_CLOEXEC);
+    }
+
+    let file = if flags.contains(MemFdFlags::ALLOW_SEALING) {
+        MemFd::sealable()
+    } else {
+        MemFd::new()
+    };
+
+    let entry = DirEntry::from_inode(file, alloc::format!("memfd:{name}"));
+    let current_task = scheduler::get_scheduler().current_task();
+
+    Ok(current_task.file_table.open_file(entry, open_flags)?)
+}
+
+/// Truncates or extends the file referred to by `fd` to `length` bytes.
+#[syscall(number(SYS_FTRUNCATE))]
+pub fn ftruncate(fd: FileDescriptor, length: usize) -> Result<usize, SyscallError> {
+    let handle = fd.io_handle()?;
+
+    if !handle.is_writable() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    fs::check_writable(&handle.inode())?;
+    handle.inode().truncate(length)?;
+    inotify::notify(&handle.inode, InotifyMask::MODIFY);
+
+    Ok(0)
+}
+
+/// Allocates the disk space of the `len` bytes at `offset` in the file referred to by `fd`, or
+/// deallocates it with `FALLOC_FL_PUNCH_HOLE`.
+#[syscall(number(SYS_FALLOCATE))]
+pub fn fallocate(
+    fd: FileDescriptor,
+    flags: usize,
+    offset: usize,
+    len: usize,
+) -> Result<usize, SyscallError> {
+    let flags = FallocateFlags::from_bits(flags).ok_or(SyscallError::EOPNOTSUPP)?;
+
+    // Punching a hole never changes the size of the file, which has to be requested explicitly.
+    if flags.contains(FallocateFlags::PUNCH_HOLE) && !flags.contains(FallocateFlags::KEEP_SIZE) {
+        return Err(SyscallError::EOPNOTSUPP);
+    }
+
+    if (offset as isize) < 0 || (len as isize) <= 0 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    if !offset
+        .checked_add(len)
+        .is_some_and(|end| end <= isize::MAX as usize)
+    {
+        return Err(SyscallError::EFBIG);
+    }
+
+    let handle = fd.io_handle()?;
+
+    if !handle.is_writable() {
+        return Err(SyscallError::EBADF);
+    }
+
+    let metadata = handle.inode().metadata()?;
+
+    if metadata.is_directory() {
+        return Err(SyscallError::EISDIR);
+    } else if !metadata.is_file() {
+        return Err(SyscallError::ENODEV);
+    }
+
+    fs::check_writable(&handle.inode())?;
+
+    match handle.inode().fallocate(flags, offset, len) {
+        Ok(()) => {}
+        Err(fs::FileSystemError::NotSupported) => return Err(SyscallError::EOPNOTSUPP),
+        Err(err) => return Err(err.into()),
+    }
+
+    inotify::notify(&handle.inode, InotifyMask::MODIFY);
+    Ok(0)
+}
+
+/// Changes the flags of the mount at `target`. Only remounting an existing mount is supported,
+/// which makes it read-only with `MS_RDONLY` and writable without it.
+#[syscall(number(SYS_MOUNT))]
+pub fn mount(target: &Path, flags: usize) -> Result<usize, SyscallError> {
+    let flags = MountFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    // TODO: Mount new filesystems.
+    if !flags.contains(MountFlags::REMOUNT) {
+        return Err(SyscallError::EOPNOTSUPP);
+    }
+
+    let dir = fs::lookup_path(target)?;
+    fs::MOUNT_MANAGER.remount(&dir, flags.contains(MountFlags::RDONLY))?;
+
+    Ok(0)
+}
+
+/// Creates an `io_uring` instance with at least `entries` submission queue entries and returns a
+/// file descriptor referring to it. The layout of the rings is returned in `params`.
+#[syscall(number(SYS_IO_URING_SETUP))]
+pub fn io_uring_setup(entries: usize, params: &mut IoUringParams) -> Result<usize, SyscallError> {
+    let ring = IoUring::new(entries, params)?;
+    let entry = DirEntry::from_inode(ring, String::from("[io_uring]"));
+
+    Ok(scheduler::current_thread()
+        .file_table
+        .open_file(entry, OpenFlags::O_RDWR | OpenFlags::O_CLOEXEC)?)
+}
+
+#[syscall(number(SYS_IO_URING_ENTER))]
+pub fn io_uring_enter(
+    fd: FileDescriptor,
+    to_submit: usize,
+    min_complete: usize,
+    flags: usize,
+    sigmask: usize,
+    _sigsz: usize,
+) -> Result<usize, SyscallError> {
+    let flags = io_uring::EnterFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    // TODO: Replace the signal mask while waiting for completions.
+    if sigmask != 0 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let ring = fd
+        .handle()?
+        .inode()
+        .downcast_arc::<IoUring>()
+        .ok_or(SyscallError::EOPNOTSUPP)?;
+
+    ring.enter(to_submit, min_complete, flags)
+}
+
+#[syscall(number(SYS_IO_URING_REGISTER))]
+pub fn io_uring_register(
+    fd: FileDescriptor,
+    opcode: usize,
+    arg: usize,
+    nr_args: usize,
+) -> Result<usize, SyscallError> {
+    let opcode = io_uring::RegisterOp::from_usize(opcode).ok_or(SyscallError::EINVAL)?;
+    let ring = fd
+        .handle()?
+        .inode()
+        .downcast_arc::<IoUring>()
+        .ok_or(SyscallError::EOPNOTSUPP)?;
+
+    ring.register(opcode, arg, nr_args)
+}
+
+/// Creates an inotify instance and returns a file descriptor referring to it. Reading from it
+/// returns the events reported on the files it watches.
+#[syscall(number(SYS_INOTIFY_INIT))]
+pub fn inotify_init(flags: usize) -> Result<usize, SyscallError> {
+    let flags = InotifyFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    let mut open_flags = OpenFlags::O_RDONLY;
+
+    if flags.contains(InotifyFlags::CLOEXEC) {
+        open_flags.insert(OpenFlags::O_CLOEXEC);
+    }
+
+    if flags.contains(InotifyFlags::NONBLOCK) {
+        open_flags.insert(OpenFlags::O_NONBLOCK);
+    }
+
+    let entry = DirEntry::from_inode(Inotify::new(), String::from("anon_inode:inotify"));
+    let current_task = scheduler::get_scheduler().current_task();
+
+    Ok(current_task.file_table.open_file(entry, open_flags)?)
+}
+
+/// Returns the inotify instance referred to by `fd`.
+///
+/// ## Errors
+/// * `EINVAL`: The file descriptor does not refer to an inotify instance.
+fn inotify_instance(fd: FileDescriptor) -> Result<Arc<Inotify>, SyscallError> {
+    fd.handle()?
+        .inode()
+        .downcast_arc::<Inotify>()
+        .ok_or(SyscallError::EINVAL)
+}
+
+/// Watches the file at `path` for the events in `mask` and returns the watch descriptor.
+#[syscall(number(SYS_INOTIFY_ADD_WATCH))]
+pub fn inotify_add_watch(
+    fd: FileDescriptor,
+    path: &Path,
+    mask: usize,
+) -> Result<usize, SyscallError> {
+    let mask = InotifyMask::from_bits(mask as u32).ok_or(SyscallError::EINVAL)?;
+    let instance = inotify_instance(fd)?;
+
+    let at = DirFd(AT_FDCWD).at(path)?;
+    let resolve_last = !mask.contains(InotifyMask::DONT_FOLLOW);
+    let entry = fs::lookup_path_with(at, path, LookupMode::None, resolve_last)?;
+
+    if mask.contains(InotifyMask::ONLYDIR) && !entry.inode().metadata()?.is_directory() {
+        return Err(SyscallError::ENOTDIR);
+    }
+
+    Ok(instance.add_watch(entry.inode(), mask)? as usize)
+}
+
+/// Removes the watch `wd` from the inotify instance referred to by `fd`.
+#[syscall(number(SYS_INOTIFY_RM_WATCH))]
+pub fn inotify_rm_watch(fd: FileDescriptor, wd: usize) -> Result<usize, SyscallError> {
+    inotify_instance(fd)?.remove_watch(wd as i32)?;
+    Ok(0)
+}
+
+/// Opens the message queue `name` and returns a file descriptor referring to it. With
+/// `O_CREAT`, a missing queue is created with the limits in `attr`, which may be NULL.
+///
+/// Permissions are not checked, so `mode` is ignored.
+#[syscall(number(SYS_MQ_OPEN))]
+pub fn mq_open(name: &str, flags: usize, _mode: usize, attr: usize) -> Result<usize, SyscallError> {
+    let flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    let attr = if attr != 0 {
+        Some(crate::utils::validate_ptr(attr as *const MqAttr)?)
+    } else {
+        None
+    };
+
+    let queue = mqueue::open(
+        name,
+        flags.contains(OpenFlags::O_CREAT),
+        flags.contains(OpenFlags::O_EXCL),
+        attr,
+    )?;
+
+    let open_flags = flags & (OpenFlags::O_ACCMODE | OpenFlags::O_NONBLOCK | OpenFlags::O_CLOEXEC);
+    let entry = DirEntry::from_inode(queue, alloc::format!("mqueue:{name}"));
+
+    Ok(scheduler::current_thread()
+        .file_table
+        .open_file(entry, open_flags)?)
+}
+
+/// Returns the message queue referred to by `fd` and whether it was opened with `O_NONBLOCK`.
+///
+/// ## Errors
+/// * `EBADF`: The file descriptor does not refer to a message queue, or was not opened for writing
+///   (if `write` is set) or reading.
+fn mqueue_instance(
+    fd: FileDescriptor,
+    write: bool,
+) -> Result<(Arc<MessageQueue>, bool), SyscallError> {
+    let handle = fd.handle()?;
+    let queue = handle
+        .inode()
+        .downcast_arc::<MessageQueue>()
+        .ok_or(SyscallError::EBADF)?;
+
+    let permitted = if write {
+        handle.is_writable()
+    } else {
+        handle.is_readable()
+    };
+
+    if !permitted {
+        return Err(SyscallError::EBADF);
+    }
+
+    Ok((queue, handle.flags().contains(OpenFlags::O_NONBLOCK)))
+}
+
+/// Adds `message` with the given `priority` to the message queue `fd`.
+#[syscall(number(SYS_MQ_SEND))]
+pub fn mq_send(fd: FileDescriptor, message: &[u8], priority: usize) -> Result<usize, SyscallError> {
+    let (queue, nonblock) = mqueue_instance(fd, true)?;
+    let priority = u32::try_from(priority).map_err(|_| SyscallError::EINVAL)?;
+
+    queue.send(message, priority, nonblock)?;
+    Ok(0)
+}
+
+/// Removes the oldest message of the highest priority from the message queue `fd` into `buffer`
+/// and returns its size. The priority of the message is stored in `priority` unless it is NULL.
+#[syscall(number(SYS_MQ_RECEIVE))]
+pub fn mq_receive(
+    fd: FileDescriptor,
+    buffer: &mut [u8],
+    priority: usize,
+) -> Result<usize, SyscallError> {
+    let (queue, nonblock) = mqueue_instance(fd, false)?;
+
+    if priority != 0 {
+        crate::utils::validate_mut_ptr(priority as *mut u32)?;
+    }
+
+    // The message is copied out after it is received since the buffers may be unmapped while the
+    // task is blocked.
+    let (message, message_priority) = queue.receive(buffer.len(), nonblock)?;
+    copy_slice_to_user(buffer.as_mut_ptr(), &message)?;
+
+    if priority != 0 {
+        copy_to_user(priority as *mut u32, &message_priority)?;
+    }
+
+    Ok(message.len())
+}
+
+/// Removes the name of the message queue `name`. The queue is destroyed once every file
+/// descriptor referring to it is closed.
+#[syscall(number(SYS_MQ_UNLINK))]
+pub fn mq_unlink(name: &str) -> Result<usize, SyscallError> {
+    mqueue::unlink(name)?;
+    Ok(0)
+}
+
+/// Opens a counter for the event described by `attr` and returns a file descriptor referring to
+/// it. Reading from the file descriptor returns the 64-bit value of the counter.
+///
+/// Only the calling thread can be measured, so `pid` must be 0, `cpu` must be -1 and events
+/// cannot be grouped.
+#[syscall(number(SYS_PERF_EVENT_OPEN))]
+pub fn perf_event_open(
+    attr: &PerfEventAttr,
+    pid: usize,
+    cpu: usize,
+    group_fd: usize,
+    flags: usize,
+) -> Result<usize, SyscallError> {
+    let flags = PerfEventFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    if attr.size != 0 && attr.size < PERF_ATTR_SIZE_VER0 {
+        return Err(SyscallError::E2BIG);
+    }
+
+    if pid != 0 || cpu as isize != -1 || group_fd as isize != -1 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let mut open_flags = OpenFlags::O_RDONLY;
+
+    if flags.contains(PerfEventFlags::FD_CLOEXEC) {
+        open_flags.insert(OpenFlags::O_CLOEXEC);
+    }
+
+    let entry = DirEntry::from_inode(
+        PerfEvent::new(attr)?,
+        String::from("anon_inode:[perf_event]"),
+    );
+    let current_task = scheduler::get_scheduler().current_task();
+
+    Ok(current_task.file_table.open_file(entry, open_flags)?)
+}
+
 /// Creates a new link (also known as a hard link) to an existing
 /// file.
-#[syscall]
+#[syscall(number(SYS_LINK))]
 pub fn link(src_path: &Path, dest_path: &Path) -> Result<usize, SyscallError> {
     let src = fs::lookup_path(src_path)?;
     let (dest_dir, dest_name) = dest_path.parent_and_basename();
@@ -640,7 +1267,10 @@ pub fn link(src_path: &Path, dest_path: &Path) -> Result<usize, SyscallError> {
         return Err(SyscallError::EINVAL);
     }
 
+    fs::check_writable(&dest_dir)?;
     dest_dir.link(dest_name, src)?;
+    inotify::notify_entry(&dest_dir, dest_name, InotifyMask::CREATE, 0);
+
     Ok(0)
 }
 
@@ -711,7 +1341,7 @@ fn do_poll(fds: &mut [PollFd], timeout: Option<&TimeSpec>) -> Result<usize, Sysc
     }
 }
 
-#[syscall]
+#[syscall(number(SYS_POLL))]
 pub fn poll(fds: &mut [PollFd], timeout: usize, sigmask: usize) -> Result<usize, SyscallError> {
     // Nothing to poll on.
     if fds.is_empty() {
@@ -740,7 +1370,7 @@ pub fn poll(fds: &mut [PollFd], timeout: usize, sigmask: usize) -> Result<usize,
     Ok(n)
 }
 
-#[syscall]
+#[syscall(number(SYS_RENAME))]
 pub fn rename(src: &Path, dest: &Path) -> Result<usize, SyscallError> {
     let src = fs::lookup_path(src)?;
     let (dest, name) = {
@@ -748,33 +1378,55 @@ pub fn rename(src: &Path, dest: &Path) -> Result<usize, SyscallError> {
         (fs::lookup_path(dir)?, name)
     };
 
+    let old_parent = src.parent();
+    let old_name = src.name();
+
+    if let Some(old_parent) = &old_parent {
+        fs::check_writable(&old_parent.inode())?;
+    }
+
+    fs::check_writable(&dest.inode())?;
     dest.inode().rename(src.clone(), name)?;
 
     cache::dcache().rehash(src.clone(), || {
         src.set_name(name);
-        src.set_parent(dest);
+        src.set_parent(dest.clone());
     });
+
+    let mut mask = InotifyMask::empty();
+
+    if src.inode().metadata()?.is_directory() {
+        mask.insert(InotifyMask::ISDIR);
+    }
+
+    let cookie = inotify::next_cookie();
+
+    if let Some(old_parent) = old_parent {
+        inotify::notify_entry(
+            &old_parent.inode(),
+            &old_name,
+            mask | InotifyMask::MOVED_FROM,
+            cookie,
+        );
+    }
+
+    inotify::notify_entry(&dest.inode(), name, mask | InotifyMask::MOVED_TO, cookie);
+
     Ok(0)
 }
 
-#[syscall]
-pub fn symlink(link_dirfd: usize, target: &Path, linkpath: &Path) -> Result<usize, SyscallError> {
-    // TODO(andypython): the following code is reused in a couple of places. Isolate this inside the
-    // syscall parsing for FileDescriptor with an argument as a generic specifing the allowance
-    // of this value.
-    //
+#[syscall(number(SYS_SYMLINK_AT))]
+pub fn symlink(link_dirfd: DirFd, target: &Path, linkpath: &Path) -> Result<usize, SyscallError> {
     // If the pathname given in `linkpath` is relative, then it is interpreted relative to the
     // directory referred to by the file descriptor `link_dirfd`.
-    let at = match link_dirfd as isize {
-        AT_FDCWD if !linkpath.is_absolute() => scheduler::current_thread().cwd_dirent(),
-        _ if !linkpath.is_absolute() => FileDescriptor::from_usize(link_dirfd)
-            .handle()?
-            .inode
-            .clone(),
-        _ => fs::root_dir().clone(),
-    };
+    let at = link_dirfd.at(linkpath)?;
+
+    // The permissions of a symbolic link are ignored, so the umask does not apply to them.
+    let mode = Mode::from_bits_truncate(0o777);
+
+    let ent = fs::lookup_path_with(at, linkpath, LookupMode::Create(mode), false)?;
 
-    let ent = fs::lookup_path_with(at, linkpath, LookupMode::Create, false)?;
+    fs::check_writable(&ent.inode())?;
     ent.inode().symlink(target)?;
 
     Ok(0)
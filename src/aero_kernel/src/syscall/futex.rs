@@ -24,6 +24,7 @@ use spin::Once;
 use crate::mem::paging::{PhysAddr, Translate, VirtAddr};
 use crate::mem::AddressSpace;
 use crate::userland::scheduler;
+use crate::userland::task::Task;
 use crate::utils::sync::{Mutex, WaitQueue};
 
 pub struct FutexContainer {
@@ -42,7 +43,7 @@ impl FutexContainer {
     fn validate_futex_ptr(ptr: VirtAddr) -> Result<(), SyscallError> {
         let raw = ptr.as_u64() as usize;
 
-        if raw == 0 || (raw & (core::mem::size_of::<u32>() - 1)) == 0 {
+        if raw == 0 || (raw & (core::mem::size_of::<u32>() - 1)) != 0 {
             Err(SyscallError::EINVAL)
         } else {
             Ok(())
@@ -57,16 +58,24 @@ impl FutexContainer {
         offset_table.translate_addr(ptr)
     }
 
-    /// Returns the futex at the given key; allocating it if it doesn't exist.
-    fn get_alloc(&self, key: PhysAddr) -> Arc<WaitQueue> {
-        let mut container = self.futexes.lock();
+    /// Inserts `task` into the futex at the given key; allocating it if it doesn't exist.
+    fn insert(&self, key: PhysAddr, task: Arc<Task>) -> Arc<WaitQueue> {
+        let mut container = self.futexes.lock_irq();
+        let futex = container
+            .entry(key)
+            .or_insert_with(|| Arc::new(WaitQueue::new()));
 
-        if let Some(futex) = container.get(&key) {
-            futex.clone()
-        } else {
-            let futex = Arc::new(WaitQueue::new());
-            container.insert(key, futex.clone());
-            futex
+        futex.insert(task);
+        futex.clone()
+    }
+
+    /// Removes `task` from the futex at the given key; freeing the futex if it is empty.
+    fn remove(&self, key: PhysAddr, futex: &WaitQueue, task: &Task) {
+        let mut container = self.futexes.lock_irq();
+        futex.remove(task);
+
+        if futex.is_empty() {
+            container.remove(&key);
         }
     }
 
@@ -87,33 +96,33 @@ impl FutexContainer {
         Self::validate_futex_ptr(uaddr)?;
 
         let key = Self::addr_as_futex_key(uaddr).ok_or(SyscallError::EINVAL)?;
-        let value = uaddr.read_mut::<AtomicU32>()?;
-
-        if value.load(Ordering::SeqCst) == expected {
-            let futex = self.get_alloc(key);
-
-            let scheduler = scheduler::get_scheduler();
-            let current_task = scheduler.current_task();
+        let value = crate::utils::validate_ptr(uaddr.as_ptr::<AtomicU32>())?;
 
-            futex.insert(current_task.clone());
-            scheduler.inner.await_io()?;
-            futex.remove(&current_task);
+        let scheduler = scheduler::get_scheduler();
+        let current_task = scheduler.current_task();
 
-            if futex.is_empty() {
-                self.futexes.lock().remove(&key);
-            }
+        // Queue the task before testing the futex word, so that a wake up between the test
+        // and going to sleep is not lost.
+        let futex = self.insert(key, current_task.clone());
 
-            Ok(())
+        let result = if value.load(Ordering::SeqCst) == expected {
+            scheduler.inner.await_io().map_err(SyscallError::from)
         } else {
             Err(SyscallError::EAGAIN)
-        }
+        };
+
+        self.remove(key, &futex, &current_task);
+        result
     }
 
     fn wake(&self, uaddr: VirtAddr) -> Result<(), SyscallError> {
         Self::validate_futex_ptr(uaddr)?;
 
         let key = Self::addr_as_futex_key(uaddr).ok_or(SyscallError::EINVAL)?;
-        let futex = self.get(key).ok_or(SyscallError::EINVAL)?;
+        // Nobody is waiting on the futex.
+        let Some(futex) = self.get(key) else {
+            return Ok(());
+        };
 
         futex.notify_all();
 
@@ -129,7 +138,12 @@ fn get_futex_container() -> &'static FutexContainer {
     FUTEX_CONTAINER.call_once(FutexContainer::new)
 }
 
-#[syscall]
+/// Wakes up the tasks waiting on the futex word at `ptr`, on behalf of the kernel.
+pub fn wake_up(ptr: VirtAddr) -> Result<(), SyscallError> {
+    get_futex_container().wake(ptr)
+}
+
+#[syscall(number(SYS_FUTEX_WAIT))]
 pub fn wait(ptr: usize, expected: usize, timeout: &TimeSpec) -> Result<usize, SyscallError> {
     let ptr = VirtAddr::new(ptr as u64);
 
@@ -139,7 +153,7 @@ pub fn wait(ptr: usize, expected: usize, timeout: &TimeSpec) -> Result<usize, Sy
     Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_FUTEX_WAKE))]
 pub fn wake(ptr: usize) -> Result<usize, SyscallError> {
     let ptr = VirtAddr::new(ptr as u64);
 
@@ -15,22 +15,62 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use crate::arch::user_copy::{copy_slice_to_user, copy_to_user, UserRef};
+use crate::fs::file_table::FileHandle;
+use crate::fs::inode::DirEntry;
+use crate::ipc::semaphore::{self, Semaphore};
+use crate::ipc::shm::{self, ShmAttachment};
+use crate::ipc::{msg, sem};
+use crate::mem::paging::{PageSize, Size4KiB, VirtAddr};
+use crate::syscall::fs::FileDescriptor;
+use crate::syscall::time;
 use crate::userland::scheduler::get_scheduler;
 use crate::userland::task::TaskId;
 
 use crate::utils::sync::{Mutex, WaitQueue};
 
-use aero_syscall::SyscallError;
+use aero_syscall::{
+    MMapFlags, MMapProt, MsqidDs, OpenFlags, SemBuf, SemidDs, ShmidDs, SyscallError, TimeSpec,
+    GETALL, IPC_RMID, IPC_SET, IPC_STAT, SEMOPM, SETALL, SETVAL, SHM_RDONLY, SHM_RND,
+};
 use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use spin::Once;
 
 // TODO: Make this reassignable in case we want to handle the root node's death, so
 // someone else can take over (e.g. system server but after it's restarted)
 static IPC_ROOT_NODE: Once<usize> = Once::new();
 
+/// Number of bytes of a message shown by [`MessageQueue::render`].
+const PREVIEW_LEN: usize = 16;
+
+static IPC_TRACE: Once<AtomicBool> = Once::new();
+/// Sequence number of the next message sent, to match the trace of a send with the receive.
+static NEXT_SEQUENCE: AtomicUsize = AtomicUsize::new(1);
+
+/// Returns whether the IPC messages are logged, see [`set_trace`].
+pub fn is_tracing() -> bool {
+    IPC_TRACE
+        .call_once(|| AtomicBool::new(crate::cmdline::get().is_some_and(|c| c.ipc_trace)))
+        .load(Ordering::Relaxed)
+}
+
+/// Enables or disables logging every message sent and received, with its sequence number,
+/// source, destination and length. It is initially enabled with `ipctrace=1` on the kernel
+/// command line.
+pub fn set_trace(enabled: bool) {
+    is_tracing();
+    IPC_TRACE.get().unwrap().store(enabled, Ordering::Relaxed);
+}
+
 struct Message {
     from: usize,
+    /// Returned by [`send`] to the sender, see [`NEXT_SEQUENCE`].
+    sequence: usize,
     data: Vec<u8>,
 }
 
@@ -46,6 +86,35 @@ impl MessageQueue {
             blockqueue: WaitQueue::new(),
         }
     }
+
+    /// Renders the queued messages, one per line, with their sequence number, sender, length
+    /// and first [`PREVIEW_LEN`] bytes in hexadecimal.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        for message in self.queue.lock_irq().iter() {
+            write!(
+                out,
+                "#{} from {} len {}:",
+                message.sequence,
+                message.from,
+                message.data.len()
+            )
+            .unwrap();
+
+            for byte in message.data.iter().take(PREVIEW_LEN) {
+                write!(out, " {byte:02x}").unwrap();
+            }
+
+            if message.data.len() > PREVIEW_LEN {
+                out.push_str(" ...");
+            }
+
+            out.push('\n');
+        }
+
+        out
+    }
 }
 
 fn handle_receive(
@@ -57,31 +126,54 @@ fn handle_receive(
 
     *pid_ptr = msg.from;
 
+    if is_tracing() {
+        log::info!(
+            "ipc: #{} recv {} -> {} ({} bytes)",
+            msg.sequence,
+            msg.from,
+            get_scheduler().current_task().pid().as_usize(),
+            msg.data.len()
+        );
+    }
+
     Ok(msg.data.len())
 }
 
-#[syscall]
+/// Sends `payload` to the process `pid` and returns the sequence number of the message, which
+/// identifies it in the IPC trace (see [`set_trace`]).
+#[syscall(number(SYS_IPC_SEND))]
 pub fn send(pid: usize, payload: &[u8]) -> Result<usize, SyscallError> {
     let target = get_scheduler()
         .find_task(TaskId::new(pid))
         .ok_or(SyscallError::EINVAL)?;
 
+    let from = get_scheduler().current_task().pid().as_usize();
+    let sequence = NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+
+    if is_tracing() {
+        log::info!(
+            "ipc: #{sequence} send {from} -> {pid} ({} bytes)",
+            payload.len()
+        );
+    }
+
     let message_queue = &target.message_queue;
     let mut queue = message_queue.queue.lock();
 
     // Push the message to the message queue of the provided task.
     queue.push_back(Message {
-        from: get_scheduler().current_task().pid().as_usize(),
+        from,
+        sequence,
         data: payload.to_vec(),
     });
 
     // Notify the task that it has a new message if its awaiting for one!
     message_queue.blockqueue.notify_all();
 
-    Ok(0)
+    Ok(sequence)
 }
 
-#[syscall]
+#[syscall(number(SYS_IPC_RECV))]
 pub fn recv(pid_ptr: &mut usize, output: &mut [u8], block: usize) -> Result<usize, SyscallError> {
     let current = get_scheduler().current_task();
 
@@ -118,7 +210,7 @@ pub fn recv(pid_ptr: &mut usize, output: &mut [u8], block: usize) -> Result<usiz
     }
 }
 
-#[syscall]
+#[syscall(number(SYS_IPC_DISCOVER_ROOT))]
 pub fn discover_root() -> Result<usize, SyscallError> {
     match IPC_ROOT_NODE.get() {
         Some(pid) => Ok(*pid),
@@ -126,7 +218,7 @@ pub fn discover_root() -> Result<usize, SyscallError> {
     }
 }
 
-#[syscall]
+#[syscall(number(SYS_IPC_BECOME_ROOT))]
 pub fn become_root() -> Result<usize, SyscallError> {
     if IPC_ROOT_NODE.is_completed() {
         Err(SyscallError::EINVAL)
@@ -136,3 +228,322 @@ pub fn become_root() -> Result<usize, SyscallError> {
         Ok(0)
     }
 }
+
+/// Opens the named semaphore `name` and returns a file descriptor referring to it. With `O_CREAT`,
+/// a missing semaphore is created with the initial `value`.
+///
+/// Permissions are not checked, so `mode` is ignored.
+#[syscall(number(SYS_SEM_OPEN))]
+pub fn sem_open(
+    name: &str,
+    flags: usize,
+    _mode: usize,
+    value: usize,
+) -> Result<usize, SyscallError> {
+    let flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    let semaphore = semaphore::open(
+        name,
+        flags.contains(OpenFlags::O_CREAT),
+        flags.contains(OpenFlags::O_EXCL),
+        value,
+    )?;
+
+    let entry = DirEntry::from_inode(semaphore, alloc::format!("sem:{name}"));
+
+    Ok(get_scheduler()
+        .current_task()
+        .file_table
+        .open_file(entry, OpenFlags::O_RDWR | (flags & OpenFlags::O_CLOEXEC))?)
+}
+
+/// Returns the semaphore referred to by `fd`.
+///
+/// ## Errors
+/// * `EINVAL`: The file descriptor does not refer to a semaphore.
+fn semaphore_instance(fd: FileDescriptor) -> Result<Arc<Semaphore>, SyscallError> {
+    fd.handle()?
+        .inode()
+        .downcast_arc::<Semaphore>()
+        .ok_or(SyscallError::EINVAL)
+}
+
+#[syscall(number(SYS_SEM_POST))]
+pub fn sem_post(fd: FileDescriptor) -> Result<usize, SyscallError> {
+    semaphore_instance(fd)?.post()?;
+    Ok(0)
+}
+
+#[syscall(number(SYS_SEM_WAIT))]
+pub fn sem_wait(fd: FileDescriptor) -> Result<usize, SyscallError> {
+    semaphore_instance(fd)?.wait(None)?;
+    Ok(0)
+}
+
+#[syscall(number(SYS_SEM_TRYWAIT))]
+pub fn sem_trywait(fd: FileDescriptor) -> Result<usize, SyscallError> {
+    semaphore_instance(fd)?.try_wait()?;
+    Ok(0)
+}
+
+/// Same as [`sem_wait`], except that it fails with `ETIMEDOUT` once the absolute `CLOCK_REALTIME`
+/// time `deadline` has passed.
+#[syscall(number(SYS_SEM_TIMEDWAIT))]
+pub fn sem_timedwait(fd: FileDescriptor, deadline: &TimeSpec) -> Result<usize, SyscallError> {
+    let semaphore = semaphore_instance(fd)?;
+
+    // The deadline is not validated if the semaphore can be decremented right away.
+    if semaphore.try_wait().is_ok() {
+        return Ok(0);
+    }
+
+    semaphore.wait(Some(time::realtime_deadline(deadline)?))?;
+    Ok(0)
+}
+
+/// Removes the name of the semaphore `name`. The semaphore is destroyed once every file
+/// descriptor referring to it is closed.
+#[syscall(number(SYS_SEM_UNLINK))]
+pub fn sem_unlink(name: &str) -> Result<usize, SyscallError> {
+    semaphore::unlink(name)?;
+    Ok(0)
+}
+
+/// Returns the identifier of the shared memory segment with the key `key`, creating it with
+/// `IPC_CREAT` if necessary.
+#[syscall(number(SYS_SHMGET))]
+pub fn shmget(key: usize, size: usize, flags: usize) -> Result<usize, SyscallError> {
+    let pid = get_scheduler().current_task().pid();
+    Ok(shm::get(key as i32, size, flags, pid)? as usize)
+}
+
+/// Attaches the shared memory segment `id` at `address`, or at an address picked by the kernel if
+/// it is null, and returns the address of the attachment.
+#[syscall(number(SYS_SHMAT))]
+pub fn shmat(id: usize, address: usize, flags: usize) -> Result<usize, SyscallError> {
+    let segment = shm::lookup(id)?;
+
+    let address = if flags & SHM_RND != 0 {
+        VirtAddr::new(address as u64).align_down(Size4KiB::SIZE)
+    } else {
+        VirtAddr::new(address as u64)
+    };
+
+    if !address.is_aligned(Size4KiB::SIZE) {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let (open_flags, protection) = if flags & SHM_RDONLY != 0 {
+        (OpenFlags::O_RDONLY, MMapProt::PROT_READ)
+    } else {
+        (
+            OpenFlags::O_RDWR,
+            MMapProt::PROT_READ | MMapProt::PROT_WRITE,
+        )
+    };
+
+    let mut map_flags = MMapFlags::MAP_SHARED;
+
+    if address != VirtAddr::zero() {
+        map_flags.insert(MMapFlags::MAP_FIXED);
+    }
+
+    let entry = DirEntry::from_inode(
+        ShmAttachment::new(segment.clone()),
+        alloc::format!("SYSV{:08x}", segment.key()),
+    );
+
+    // The mapping keeps the attachment alive, so the handle is not installed in the file table.
+    let handle = Arc::new(FileHandle::new(0, entry, open_flags));
+
+    let task = get_scheduler().current_task();
+    let address = task
+        .vm()
+        .mmap(
+            address,
+            segment.size(),
+            protection,
+            map_flags,
+            0,
+            Some(handle),
+        )
+        .ok_or(SyscallError::ENOMEM)?;
+
+    segment.attached(task.pid());
+    Ok(address.as_u64() as usize)
+}
+
+/// Detaches the shared memory segment attached at `address`.
+#[syscall(number(SYS_SHMDT))]
+pub fn shmdt(address: usize) -> Result<usize, SyscallError> {
+    let address = VirtAddr::new(address as u64);
+    let task = get_scheduler().current_task();
+
+    let mut attachment = None;
+
+    task.vm().for_each_mapping(|mapping| {
+        if mapping.start_addr != address {
+            return;
+        }
+
+        if let Some(file) = &mapping.file {
+            attachment = file
+                .file()
+                .inode()
+                .downcast_arc::<ShmAttachment>()
+                .map(|attachment| (attachment, mapping.end_addr - mapping.start_addr));
+        }
+    });
+
+    let (attachment, size) = attachment.ok_or(SyscallError::EINVAL)?;
+
+    attachment.segment().detached(task.pid());
+    task.vm().munmap(address, size as usize);
+
+    Ok(0)
+}
+
+/// Performs the control operation `command` on the shared memory segment `id`. With `IPC_RMID`,
+/// the segment is destroyed once it is detached from every address space.
+#[syscall(number(SYS_SHMCTL))]
+pub fn shmctl(id: usize, command: usize, buffer: usize) -> Result<usize, SyscallError> {
+    if command == IPC_RMID {
+        shm::remove(id)?;
+        return Ok(0);
+    }
+
+    let segment = shm::lookup(id)?;
+    let mut buffer = UserRef::<ShmidDs>::new(VirtAddr::new(buffer as u64))?;
+
+    segment.control(command, &mut buffer)?;
+    buffer.write_back()?;
+    Ok(0)
+}
+
+/// Returns the identifier of the message queue with the key `key`, creating it with `IPC_CREAT` if
+/// necessary.
+#[syscall(number(SYS_MSGGET))]
+pub fn msgget(key: usize, flags: usize) -> Result<usize, SyscallError> {
+    Ok(msg::get(key as i32, flags)? as usize)
+}
+
+/// Sends the message at `message`, a type followed by `size` bytes of text, to the message queue
+/// `id`.
+#[syscall(number(SYS_MSGSND))]
+pub fn msgsnd(id: usize, message: usize, size: usize, flags: usize) -> Result<usize, SyscallError> {
+    let queue = msg::lookup(id)?;
+
+    let mtype = *crate::utils::validate_ptr(message as *const i64)?;
+    let text = crate::utils::validate_slice((message + 8) as *const u8, size)?;
+
+    queue.send(mtype, text, flags, get_scheduler().current_task().pid())?;
+    Ok(0)
+}
+
+/// Receives a message selected by `mtype` from the message queue `id` into `message`, a type
+/// followed by `size` bytes of text, and returns the size of its text.
+#[syscall(number(SYS_MSGRCV))]
+pub fn msgrcv(
+    id: usize,
+    message: usize,
+    size: usize,
+    mtype: usize,
+    flags: usize,
+) -> Result<usize, SyscallError> {
+    let queue = msg::lookup(id)?;
+
+    // The buffer is checked before blocking, but the message is only copied out after it is
+    // received since the buffer may be unmapped in the meantime.
+    crate::utils::validate_mut_ptr(message as *mut i64)?;
+    crate::utils::validate_slice_mut((message + 8) as *mut u8, size)?;
+
+    let (received_type, text) = queue.receive(
+        size,
+        mtype as i64,
+        flags,
+        get_scheduler().current_task().pid(),
+    )?;
+
+    copy_to_user(message as *mut i64, &received_type)?;
+    copy_slice_to_user((message + 8) as *mut u8, &text)?;
+    Ok(text.len())
+}
+
+/// Performs the control operation `command` on the message queue `id`. With `IPC_RMID`, the
+/// queue is destroyed and the tasks blocked on it fail with `EIDRM`.
+#[syscall(number(SYS_MSGCTL))]
+pub fn msgctl(id: usize, command: usize, buffer: usize) -> Result<usize, SyscallError> {
+    if command == IPC_RMID {
+        msg::remove(id)?;
+        return Ok(0);
+    }
+
+    let queue = msg::lookup(id)?;
+    let mut buffer = UserRef::<MsqidDs>::new(VirtAddr::new(buffer as u64))?;
+
+    queue.control(command, &mut buffer)?;
+    buffer.write_back()?;
+    Ok(0)
+}
+
+#[syscall(number(SYS_SEMGET))]
+pub fn semget(key: usize, nsems: usize, flags: usize) -> Result<usize, SyscallError> {
+    Ok(sem::get(key as i32, nsems, flags)? as usize)
+}
+
+/// Performs the `count` operations at `ops` atomically on the semaphore set `id`.
+#[syscall(number(SYS_SEMOP))]
+pub fn semop(id: usize, ops: usize, count: usize) -> Result<usize, SyscallError> {
+    if count == 0 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    if count > SEMOPM {
+        return Err(SyscallError::E2BIG);
+    }
+
+    let set = sem::lookup(id)?;
+    let ops = crate::utils::validate_slice(ops as *const SemBuf, count)?;
+
+    set.operate(ops, get_scheduler().current_task().pid())?;
+    Ok(0)
+}
+
+/// Performs the control operation `command` on the semaphore `semnum` of the set `id`. `arg` is
+/// the value of `union semun`. With `IPC_RMID`, the set is destroyed and the tasks blocked on it
+/// fail with `EIDRM`.
+#[syscall(number(SYS_SEMCTL))]
+pub fn semctl(id: usize, semnum: usize, command: usize, arg: usize) -> Result<usize, SyscallError> {
+    if command == IPC_RMID {
+        sem::remove(id)?;
+        return Ok(0);
+    }
+
+    let set = sem::lookup(id)?;
+    let pid = get_scheduler().current_task().pid();
+
+    match command {
+        IPC_STAT | IPC_SET => {
+            let mut buffer = UserRef::<SemidDs>::new(VirtAddr::new(arg as u64))?;
+            set.control(command, &mut buffer)?;
+            buffer.write_back()?;
+        }
+
+        GETALL => copy_slice_to_user(arg as *mut u16, &set.values())?,
+
+        SETVAL => {
+            // Only the `int` member of the union is passed.
+            let value = u16::try_from(arg as i32).map_err(|_| SyscallError::ERANGE)?;
+            set.set_values(semnum, &[value], pid)?;
+        }
+
+        SETALL => {
+            let values = crate::utils::validate_slice(arg as *const u16, set.nsems())?;
+            set.set_values(0, values, pid)?;
+        }
+
+        _ => return set.query(semnum, command),
+    }
+
+    Ok(0)
+}
@@ -18,20 +18,25 @@
 //! System Calls are used to call a kernel service from userland.
 
 use core::fmt::Display;
-use core::mem::MaybeUninit;
+use core::mem::{size_of, MaybeUninit};
 
 use aero_syscall::prelude::*;
 
 mod fs;
-mod futex;
+pub mod futex;
 pub mod ipc;
 mod net;
-mod process;
+pub mod process;
 pub mod time;
 
 use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 
+use spin::{Mutex, Once};
+
+use crate::extern_sym;
+use crate::mem::paging::ReadErr;
 use crate::utils::StackHelper;
 
 #[derive(Default)]
@@ -94,13 +99,15 @@ pub fn exec_args_from_slice(args: usize, size: usize) -> ExecArgs {
     ExecArgs { inner: result }
 }
 
-pub trait SysArg: Display {
-    fn from_usize(value: usize) -> Self;
+/// Conversion from a raw syscall argument. Fails if the argument refers to userspace memory
+/// that is not accessible.
+pub trait SysArg: Display + Sized {
+    fn from_usize(value: usize) -> Result<Self, ReadErr>;
 }
 
 impl SysArg for usize {
-    fn from_usize(value: usize) -> Self {
-        value
+    fn from_usize(value: usize) -> Result<Self, ReadErr> {
+        Ok(value)
     }
 }
 
@@ -170,6 +177,53 @@ impl SysLog {
     }
 }
 
+/// A syscall handler registered with `#[syscall(number(SYS_*))]`. The entries are collected in
+/// the `.syscalls` section by the linker.
+pub struct SyscallEntry {
+    pub number: usize,
+    pub name: &'static str,
+    /// The number of argument registers the handler decodes its arguments from.
+    pub args: usize,
+    pub handler: fn(&[usize; 6]) -> Result<usize, SyscallError>,
+}
+
+fn entries() -> &'static [SyscallEntry] {
+    let start = extern_sym!(__syscalls_start).cast::<SyscallEntry>();
+    let end = extern_sym!(__syscalls_end).cast::<SyscallEntry>();
+
+    let len = (end.addr() - start.addr()) / size_of::<SyscallEntry>();
+    unsafe { core::slice::from_raw_parts(start, len) }
+}
+
+/// The registered syscalls, indexed by their number.
+static TABLE: Once<Vec<Option<&'static SyscallEntry>>> = Once::new();
+
+/// Returns the handler registered for the syscall `number`.
+pub fn lookup(number: usize) -> Option<&'static SyscallEntry> {
+    let table = TABLE.call_once(|| {
+        let entries = entries();
+        let len = entries
+            .iter()
+            .map(|entry| entry.number + 1)
+            .max()
+            .unwrap_or(0);
+        let mut table = alloc::vec![None; len];
+
+        for entry in entries {
+            if let Some(other) = table[entry.number].replace(entry) {
+                panic!(
+                    "syscall: {} and {} are both registered as {:#x}",
+                    other.name, entry.name, entry.number
+                );
+            }
+        }
+
+        table
+    });
+
+    table.get(number).copied().flatten()
+}
+
 pub fn generic_do_syscall(
     a: usize,
     b: usize,
@@ -179,98 +233,20 @@ pub fn generic_do_syscall(
     f: usize,
     g: usize,
 ) -> usize {
-    let result = match a {
-        SYS_EXIT => process::exit(b),
-        SYS_SHUTDOWN => process::shutdown(),
-        SYS_FORK => process::fork(),
-        SYS_MMAP => process::mmap(b, c, d, e, f, g),
-        SYS_MUNMAP => process::munmap(b, c),
-        SYS_MPROTECT => process::mprotect(b, c, d),
-        SYS_EXEC => process::exec(b, c, d, e, f, g),
-        SYS_LOG => process::log(b, c),
-        SYS_UNAME => process::uname(b),
-        SYS_WAITPID => process::waitpid(b, c, d),
-        SYS_GETPID => process::getpid(),
-        SYS_GETPPID => process::getppid(),
-        SYS_GETTID => process::gettid(),
-        SYS_GETHOSTNAME => process::gethostname(b, c),
-        SYS_SETHOSTNAME => process::sethostname(b, c),
-        SYS_INFO => process::info(b),
-        SYS_SIGACTION => process::sigaction(b, c, d, e),
-        SYS_SIGPROCMASK => process::sigprocmask(b, c, d),
-        SYS_CLONE => process::clone(b, c),
-        SYS_KILL => process::kill(b, c),
-        SYS_BACKTRACE => process::backtrace(),
-        SYS_TRACE => process::trace(),
-        SYS_SETPGID => process::setpgid(b, c),
-        SYS_SETSID => process::setsid(),
-        SYS_GETPGID => process::getpgid(b),
-
-        SYS_READ => fs::read(b, c, d),
-        SYS_OPEN => fs::open(b, c, d, e, f),
-        SYS_CLOSE => fs::close(b),
-        SYS_WRITE => fs::write(b, c, d),
-        SYS_GETDENTS => fs::getdents(b, c, d),
-        SYS_GETCWD => fs::getcwd(b, c),
-        SYS_CHDIR => fs::chdir(b, c, d),
-        SYS_MKDIR_AT => fs::mkdirat(b, c, d),
-        SYS_RMDIR => fs::rmdir(b, c),
-        SYS_IOCTL => fs::ioctl(b, c, d),
-        SYS_SEEK => fs::seek(b, c, d),
-        SYS_ACCESS => fs::access(b, c, d, e, f),
-        SYS_PIPE => fs::pipe(b, c),
-        SYS_UNLINK => fs::unlink(b, c, d, e),
-        SYS_DUP => fs::dup(b, c),
-        SYS_DUP2 => fs::dup2(b, c, d),
-        SYS_FCNTL => fs::fcntl(b, c, d),
-        SYS_STAT => fs::stat(b, c, d),
-        SYS_FSTAT => fs::fstat(b, c, d, e, f),
-        SYS_READ_LINK => fs::read_link(b, c, d, e),
-        SYS_EVENT_FD => fs::event_fd(b, c),
-        SYS_LINK => fs::link(b, c, d, e),
-        SYS_POLL => fs::poll(b, c, d, e),
-        SYS_RENAME => fs::rename(b, c, d, e),
-        SYS_SYMLINK_AT => fs::symlink(b, c, d, e, f),
-
-        // epoll calls:
-        SYS_EPOLL_CREATE => fs::epoll_create(b),
-        SYS_EPOLL_CTL => fs::epoll_ctl(b, c, d, e),
-        SYS_EPOLL_PWAIT => fs::epoll_pwait(b, c, d, e, f),
-
-        SYS_SOCKET => net::socket(b, c, d),
-        SYS_BIND => net::bind(b, c, d),
-        SYS_CONNECT => net::connect(b, c, d),
-        SYS_LISTEN => net::listen(b, c),
-        SYS_ACCEPT => net::accept(b, c, d),
-        SYS_SOCK_RECV => net::sock_recv(b, c, d),
-        SYS_SOCK_SEND => net::sock_send(b, c, d),
-        SYS_SOCKET_PAIR => net::socket_pair(b, c, d, e),
-        SYS_SOCK_SHUTDOWN => net::shutdown(b, c),
-        SYS_GETPEERNAME => net::get_peername(b, c, d),
-        SYS_GETSOCKNAME => net::get_sockname(b, c, d),
-        SYS_SETSOCKOPT => net::setopt(a, b, c, d, e),
-
-        SYS_GETTIME => time::gettime(b, c),
-        SYS_SLEEP => time::sleep(b),
-
-        SYS_SETITIMER => time::setitimer(b, c, d),
-        SYS_GETITIMER => time::getitimer(b, c),
-
-        SYS_IPC_SEND => ipc::send(b, c, d),
-        SYS_IPC_RECV => ipc::recv(b, c, d, e),
-        SYS_IPC_DISCOVER_ROOT => ipc::discover_root(),
-        SYS_IPC_BECOME_ROOT => ipc::become_root(),
-
-        SYS_FUTEX_WAIT => futex::wait(b, c, d),
-        SYS_FUTEX_WAKE => futex::wake(b),
+    let result = match lookup(a) {
+        Some(entry) => (entry.handler)(&[b, c, d, e, f, g]),
 
         // Syscall aliases (this should be handled in aero_syscall)
-        SYS_MKDIR => fs::mkdirat(aero_syscall::AT_FDCWD as _, b, c),
+        None if a == SYS_MKDIR => fs::mkdirat(aero_syscall::AT_FDCWD as _, b, c, d),
+
+        None => {
+            // Only log the first attempt, programs probing for a syscall may retry it often.
+            static LOGGED: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
 
-        SYS_DEBUG => tag_memory(b, c, d, e),
+            if LOGGED.lock().insert(a) {
+                log::error!("invalid syscall: {:#x}", a);
+            }
 
-        _ => {
-            log::error!("invalid syscall: {:#x}", a);
             Err(SyscallError::ENOSYS)
         }
     };
@@ -278,7 +254,7 @@ pub fn generic_do_syscall(
     aero_syscall::syscall_result_as_usize(result)
 }
 
-#[syscall]
+#[syscall(number(SYS_DEBUG))]
 pub fn tag_memory(ptr: *const u8, size: usize, tag: &str) -> Result<usize, SyscallError> {
     use crate::userland::scheduler;
     use alloc::string::ToString;
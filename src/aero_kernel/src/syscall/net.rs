@@ -16,12 +16,12 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 use aero_syscall::netlink::sockaddr_nl;
-use aero_syscall::socket::{MessageFlags, MessageHeader, SocketOptionLevel};
+use aero_syscall::socket::{ControlMessages, MessageFlags, MessageHeader, SocketOptionLevel};
 use aero_syscall::*;
 use alloc::sync::Arc;
 use num_traits::cast::FromPrimitive;
 
-use crate::arch::user_copy::UserRef;
+use crate::arch::user_copy::{copy_slice_from_user, copy_to_user};
 
 use crate::fs::cache::DirCacheItem;
 use crate::fs::inode::{DirEntry, INodeInterface};
@@ -34,18 +34,11 @@ use crate::socket::udp::UdpSocket;
 use crate::socket::unix::*;
 use crate::socket::{SocketAddr, SocketAddrRef};
 
-use crate::userland::scheduler;
+use crate::userland::{scheduler, signals};
 
 use crate::syscall::fs::FileDescriptor;
 
-/// Creates a [`SocketAddr`] from the provided userland socket structure address. This
-/// is done by looking at the family field present in every socket address structure.
-fn socket_addr_from_addr<'sys>(address: VirtAddr) -> Result<SocketAddrRef<'sys>> {
-    let family = *address.read_mut::<u32>()?;
-    SocketAddrRef::from_family(address, family)
-}
-
-#[syscall]
+#[syscall(number(SYS_SOCK_SHUTDOWN))]
 pub fn shutdown(fd: usize, how: usize) -> Result<usize> {
     let file_table = &scheduler::get_scheduler().current_task().file_table;
     let socket = file_table.get_handle(fd).ok_or(SyscallError::EINVAL)?;
@@ -55,21 +48,22 @@ pub fn shutdown(fd: usize, how: usize) -> Result<usize> {
 }
 
 /// Connects the socket to the specified address.
-#[syscall]
+#[syscall(number(SYS_CONNECT))]
 pub fn connect(fd: usize, address: usize, length: usize) -> Result<usize> {
-    let address = socket_addr_from_addr(VirtAddr::new(address as u64))?;
+    let address = SocketAddr::from_user(VirtAddr::new(address as u64), length)?;
     let file = scheduler::get_scheduler()
         .current_task()
         .file_table
         .get_handle(fd)
         .ok_or(SyscallError::EINVAL)?;
 
-    file.inode().connect(address, length)?;
+    file.inode()
+        .connect(SocketAddrRef::from(&address), length)?;
     Ok(0)
 }
 
 /// Accept a connection on a socket.
-#[syscall]
+#[syscall(number(SYS_ACCEPT))]
 pub fn accept(fd: usize, address: usize, length: usize) -> Result<usize> {
     let file_table = scheduler::get_scheduler().current_task().file_table.clone();
     let socket = file_table.get_handle(fd).ok_or(SyscallError::EINVAL)?;
@@ -77,13 +71,16 @@ pub fn accept(fd: usize, address: usize, length: usize) -> Result<usize> {
     let address = if address != 0 && length != 0 {
         Some((
             VirtAddr::new(address as u64),
-            VirtAddr::new(length as u64).read_mut::<u32>()?,
+            crate::utils::validate_mut_ptr(length as *mut u32)?,
         ))
     } else {
         None
     };
 
-    let connection_sock = socket.inode().accept(address)?;
+    let connection_sock = socket
+        .inode()
+        .accept(address)
+        .map_err(signals::restartable)?;
     let handle = file_table.open_file(
         DirEntry::from_inode(connection_sock, String::from("<socket>")),
         OpenFlags::O_RDWR,
@@ -92,9 +89,25 @@ pub fn accept(fd: usize, address: usize, length: usize) -> Result<usize> {
     Ok(handle)
 }
 
-#[syscall]
+/// The largest control buffer accepted by `sendmsg`, like the default `optmem_max` of Linux.
+const MAX_CONTROL_LEN: usize = 20480;
+
+#[syscall(number(SYS_SOCK_SEND))]
 pub fn sock_send(fd: usize, header: &mut MessageHeader, flags: usize) -> Result<usize> {
-    dbg!(header.control());
+    if let Some((control, length)) = header.control() {
+        if length > MAX_CONTROL_LEN {
+            return Err(SyscallError::ENOBUFS);
+        }
+
+        let mut buffer = alloc::vec![0; length];
+        copy_slice_from_user(&mut buffer, control)?;
+
+        // TODO: Pass file descriptors (`SCM_RIGHTS`) and credentials over UNIX sockets.
+        if let Some((message, _)) = ControlMessages::new(&buffer).next() {
+            log::warn!("sock_send: control messages are not supported: {message:?}");
+            return Err(SyscallError::EOPNOTSUPP);
+        }
+    }
 
     let flags = MessageFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
@@ -104,10 +117,13 @@ pub fn sock_send(fd: usize, header: &mut MessageHeader, flags: usize) -> Result<
         .get_handle(fd)
         .ok_or(SyscallError::EINVAL)?;
 
-    Ok(socket.inode().send(header, flags)?)
+    Ok(socket
+        .inode()
+        .send(header, flags)
+        .map_err(signals::restartable)?)
 }
 
-#[syscall]
+#[syscall(number(SYS_SOCK_RECV))]
 pub fn sock_recv(sockfd: usize, header: &mut MessageHeader, flags: usize) -> Result<usize> {
     let flags = MessageFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
@@ -117,10 +133,17 @@ pub fn sock_recv(sockfd: usize, header: &mut MessageHeader, flags: usize) -> Res
         .get_handle(sockfd)
         .ok_or(SyscallError::EINVAL)?;
 
-    Ok(socket.inode().recv(header, flags)?)
+    // No control messages are received yet.
+    header.flags = 0;
+    header.set_control_len(0);
+
+    Ok(socket
+        .inode()
+        .recv(header, flags)
+        .map_err(signals::restartable)?)
 }
 
-#[syscall]
+#[syscall(number(SYS_SETSOCKOPT))]
 pub fn setopt(fd: FileDescriptor, layer: usize, number: usize, buf: &[u8]) -> Result<usize> {
     let layer = SocketOptionLevel::from_usize(layer).ok_or(SyscallError::EINVAL)?;
 
@@ -143,7 +166,7 @@ pub fn setopt(fd: FileDescriptor, layer: usize, number: usize, buf: &[u8]) -> Re
 
 /// Marks the socket as a passive socket (i.e. as a socket that will be used to accept incoming
 /// connection requests).
-#[syscall]
+#[syscall(number(SYS_LISTEN))]
 pub fn listen(fd: FileDescriptor, backlog: usize) -> Result<usize> {
     fd.handle()?.inode().listen(backlog)?;
     Ok(0)
@@ -154,7 +177,13 @@ fn create_socket(domain: usize, socket_type: usize, protocol: usize) -> Result<D
     let protocol = IpProtocol::from_usize(protocol).ok_or(SyscallError::EINVAL)?;
 
     let (name, socket) = match domain as u32 {
-        AF_UNIX => ("unix", UnixSocket::new() as Arc<dyn INodeInterface>),
+        AF_UNIX => match typ {
+            SocketType::Dgram => (
+                "unix",
+                UnixSocket::new_datagram() as Arc<dyn INodeInterface>,
+            ),
+            _ => ("unix", UnixSocket::new() as Arc<dyn INodeInterface>),
+        },
         AF_INET => match (typ, protocol) {
             (SocketType::Dgram, IpProtocol::Default | IpProtocol::Udp) => {
                 ("udp", UdpSocket::new() as Arc<dyn INodeInterface>)
@@ -193,7 +222,7 @@ fn create_socket(domain: usize, socket_type: usize, protocol: usize) -> Result<D
     Ok(entry)
 }
 
-#[syscall]
+#[syscall(number(SYS_SOCKET))]
 pub fn socket(domain: usize, socket_type: usize, protocol: usize) -> Result<usize> {
     let entry = create_socket(domain, socket_type, protocol)?;
 
@@ -211,9 +240,9 @@ pub fn socket(domain: usize, socket_type: usize, protocol: usize) -> Result<usiz
     Ok(fd)
 }
 
-#[syscall]
+#[syscall(number(SYS_BIND))]
 pub fn bind(fd: usize, address: usize, length: usize) -> Result<usize> {
-    let address = socket_addr_from_addr(VirtAddr::new(address as u64))?;
+    let address = SocketAddr::from_user(VirtAddr::new(address as u64), length)?;
 
     let current_task = scheduler::get_scheduler().current_task();
     let file = current_task.file_table.get_handle(fd);
@@ -221,7 +250,7 @@ pub fn bind(fd: usize, address: usize, length: usize) -> Result<usize> {
     match file {
         Some(handle) => {
             if handle.inode().metadata()?.is_socket() {
-                handle.inode().bind(address, length)?;
+                handle.inode().bind(SocketAddrRef::from(&address), length)?;
 
                 Ok(0)
             } else {
@@ -233,7 +262,7 @@ pub fn bind(fd: usize, address: usize, length: usize) -> Result<usize> {
 }
 
 // TODO(andypython): bindgen the abi-bits from mlibc and use those types instead.
-#[syscall]
+#[syscall(number(SYS_GETPEERNAME))]
 pub fn get_peername(fd: usize, addr: usize, len: &mut u32) -> Result<usize> {
     let thread = scheduler::current_thread();
     let file = thread
@@ -253,26 +282,24 @@ pub fn get_peername(fd: usize, addr: usize, len: &mut u32) -> Result<usize> {
             let size = core::mem::size_of::<SocketAddrInet>() as u32;
             assert!(*len >= size);
 
-            let mut target = unsafe { UserRef::<SocketAddrInet>::new(VirtAddr::new(addr as u64)) };
-            *target = peer;
+            copy_to_user(addr as *mut SocketAddrInet, &peer)?;
             *len = size;
         }
 
         SocketAddr::Netlink(peer) => unimplemented!("{:?}", peer),
-        SocketAddr::Unix(peer) => {
+        SocketAddr::Unix(peer, length) => {
             let size = core::mem::size_of::<SocketAddrUnix>() as u32;
             assert!(*len >= size);
 
-            let mut target = unsafe { &mut *(addr as *mut SocketAddrUnix) };
-            *len = peer.path_len() as u32 + core::mem::offset_of!(SocketAddrUnix, path) as u32;
-            *target = peer;
+            copy_to_user(addr as *mut SocketAddrUnix, &peer)?;
+            *len = length as u32;
         }
     }
 
     Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_GETSOCKNAME))]
 pub fn get_sockname(fd: usize, addr: usize, len: &mut u32) -> Result<usize> {
     let thread = scheduler::current_thread();
     let file = thread
@@ -293,8 +320,7 @@ pub fn get_sockname(fd: usize, addr: usize, len: &mut u32) -> Result<usize> {
             let size = core::mem::size_of::<SocketAddrInet>() as u32;
             assert!(*len >= size);
 
-            let mut target = unsafe { UserRef::<SocketAddrInet>::new(VirtAddr::new(addr as u64)) };
-            *target = name;
+            copy_to_user(addr as *mut SocketAddrInet, &name)?;
             *len = size;
         }
 
@@ -302,18 +328,16 @@ pub fn get_sockname(fd: usize, addr: usize, len: &mut u32) -> Result<usize> {
             let size = core::mem::size_of::<sockaddr_nl>() as u32;
             assert!(*len >= size);
 
-            let mut target = unsafe { UserRef::<sockaddr_nl>::new(VirtAddr::new(addr as u64)) };
-            *target = name;
+            copy_to_user(addr as *mut sockaddr_nl, &name)?;
             *len = size;
         }
 
-        SocketAddr::Unix(name) => {
+        SocketAddr::Unix(name, length) => {
             let size = core::mem::size_of::<SocketAddrUnix>() as u32;
             assert!(*len >= size);
 
-            let mut target = unsafe { UserRef::<SocketAddrUnix>::new(VirtAddr::new(addr as u64)) };
-            *len = name.path_len() as u32 + core::mem::offset_of!(SocketAddrUnix, path) as u32;
-            *target = name;
+            copy_to_user(addr as *mut SocketAddrUnix, &name)?;
+            *len = length as u32;
         }
     }
 
@@ -324,7 +348,7 @@ pub fn get_sockname(fd: usize, addr: usize, len: &mut u32) -> Result<usize> {
 /// specified type, under the protocol optionally specified by the protocol
 /// argument. The two sockets shall be identical. The file descriptors used
 /// in referencing the created sockets shall be returned in fds[0] and fds[1].
-#[syscall]
+#[syscall(number(SYS_SOCKET_PAIR))]
 pub fn socket_pair(
     domain: usize,
     type_and_flags: usize,
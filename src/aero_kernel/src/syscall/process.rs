@@ -15,62 +15,83 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::signal::{SigAction, SigProcMask};
+use aero_syscall::consts::SealFlags;
+use aero_syscall::prelude::{
+    _SC_ARG_MAX, _SC_CLK_TCK, _SC_NPROCESSORS_CONF, _SC_NPROCESSORS_ONLN, _SC_OPEN_MAX,
+    _SC_PAGESIZE, PRIO_PGRP, PRIO_PROCESS, RLIMIT_NOFILE, RLIM_INFINITY, RLIM_NLIMITS,
+};
+use aero_syscall::process::{CpuSet, RLimit, NGROUPS_MAX};
+use aero_syscall::signal::{SigAction, SigInfo, SigProcMask, SIGINT, SIGKILL, SIGTERM};
 use aero_syscall::*;
+use num_traits::cast::FromPrimitive;
 use spin::{Mutex, Once};
 
-use crate::acpi::aml;
-use crate::fs;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::arch::user_copy::{copy_from_user, copy_to_user};
 use crate::fs::Path;
+use crate::{arch, fs, timer};
 
-use crate::mem::paging::VirtAddr;
+use crate::mem::paging::{PageSize, Size4KiB, VirtAddr, FRAME_ALLOCATOR};
 use crate::userland::scheduler::{self, ExitStatus};
-use crate::userland::signals::SignalEntry;
+use crate::userland::signals::{self, SignalEntry, SIGNAL_COUNT};
 use crate::userland::task::sessions::SESSIONS;
-use crate::userland::task::TaskId;
-use crate::utils::sync::IrqGuard;
+use crate::userland::task::{Task, TaskId};
+
+/// Maximum length of the host and domain names, excluding the NUL terminator.
+const UTS_NAME_MAX: usize = 64;
 
 static HOSTNAME: Once<Mutex<String>> = Once::new();
+static DOMAINNAME: Once<Mutex<String>> = Once::new();
 
 fn hostname() -> &'static Mutex<String> {
     HOSTNAME.call_once(|| Mutex::new(String::from("aero")))
 }
 
-#[syscall(no_return)]
-pub fn exit(status: usize) -> Result<usize> {
-    #[cfg(all(test, feature = "ci"))]
-    crate::emu::exit_qemu(crate::emu::ExitStatus::Success);
+fn domainname() -> &'static Mutex<String> {
+    DOMAINNAME.call_once(|| Mutex::new(String::from("(none)")))
+}
 
-    #[cfg(not(feature = "ci"))]
-    {
-        let current_task = scheduler::get_scheduler().current_task();
-        let pid = current_task.pid().as_usize();
-        let path = current_task.path();
+/// Validates a host or domain name passed to `sethostname` or `setdomainname`.
+fn parse_uts_name(name: &[u8]) -> Result<&str> {
+    if name.len() > UTS_NAME_MAX || name.iter().any(|&c| c == 0 || c.is_ascii_whitespace()) {
+        return Err(SyscallError::EINVAL);
+    }
 
-        log::trace!("exiting the process (pid={pid}, path={path:?}) with status: {status}");
+    core::str::from_utf8(name).map_err(|_| SyscallError::EINVAL)
+}
 
-        crate::unwind::unwind_stack_trace();
-        scheduler::get_scheduler().exit(ExitStatus::Normal(status as isize));
-    }
+#[syscall(no_return, number(SYS_EXIT))]
+pub fn exit(status: usize) -> Result<usize> {
+    let current_task = scheduler::get_scheduler().current_task();
+    let pid = current_task.pid().as_usize();
+    let path = current_task.path();
+
+    log::trace!("exiting the process (pid={pid}, path={path:?}) with status: {status}");
+
+    crate::unwind::unwind_stack_trace();
+    scheduler::get_scheduler().exit(ExitStatus::Normal(status as isize));
 }
 
-#[syscall]
+#[syscall(number(SYS_UNAME))]
 pub fn uname(buffer: &mut Utsname) -> Result<usize> {
-    fn init_array(fixed: &mut [u8; 65], init: &'static str) {
-        let init_bytes = init.as_bytes();
-        let len = init.len();
+    fn init_array(fixed: &mut [u8; 65], init: &str) {
+        // Leave room for the NUL terminator.
+        let len = init.len().min(fixed.len() - 1);
 
-        fixed[..len].copy_from_slice(init_bytes);
+        fixed[..len].copy_from_slice(&init.as_bytes()[..len]);
         fixed[len..].fill(0);
     }
 
     init_array(&mut buffer.sysname, "Aero");
-    init_array(&mut buffer.nodename, "unknown");
-    init_array(&mut buffer.version, env!("CARGO_PKG_VERSION"));
-    init_array(
-        &mut buffer.release,
-        concat!(env!("CARGO_PKG_VERSION"), "-aero"),
-    );
+    init_array(&mut buffer.nodename, &hostname().lock());
+    init_array(&mut buffer.domainname, &domainname().lock());
+    init_array(&mut buffer.version, &crate::version::version());
+    init_array(&mut buffer.release, crate::version::RELEASE);
 
     #[cfg(target_arch = "x86_64")]
     init_array(&mut buffer.machine, "x86_64");
@@ -81,7 +102,7 @@ pub fn uname(buffer: &mut Utsname) -> Result<usize> {
     Ok(0x00)
 }
 
-#[syscall]
+#[syscall(number(SYS_FORK))]
 pub fn fork() -> Result<usize> {
     let scheduler = scheduler::get_scheduler();
     let forked = scheduler.current_task().fork();
@@ -90,7 +111,7 @@ pub fn fork() -> Result<usize> {
     Ok(forked.pid().as_usize())
 }
 
-#[syscall]
+#[syscall(number(SYS_CLONE))]
 pub fn clone(entry: usize, stack: usize) -> Result<usize> {
     let scheduler = scheduler::get_scheduler();
     let cloned = scheduler.current_task().clone_process(entry, stack);
@@ -99,7 +120,7 @@ pub fn clone(entry: usize, stack: usize) -> Result<usize> {
     Ok(cloned.pid().as_usize())
 }
 
-#[syscall]
+#[syscall(number(SYS_KILL))]
 pub fn kill(pid: usize, signal: usize) -> Result<usize> {
     // If pid is positive, then signal is sent to the process with that pid.
     if pid > 0 {
@@ -116,7 +137,101 @@ pub fn kill(pid: usize, signal: usize) -> Result<usize> {
     }
 }
 
-#[syscall(no_return)]
+#[syscall(number(SYS_PTRACE))]
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> Result<usize> {
+    let request = PtraceRequest::from_usize(request).ok_or(SyscallError::EINVAL)?;
+    let current_task = scheduler::get_scheduler().current_task();
+
+    match request {
+        PtraceRequest::TraceMe => {
+            current_task.ptrace_traceme()?;
+            return Ok(0);
+        }
+
+        PtraceRequest::Attach => {
+            let tracee = scheduler::get_scheduler()
+                .find_task(TaskId::new(pid))
+                .ok_or(SyscallError::ESRCH)?;
+
+            current_task.ptrace_attach(&tracee)?;
+            return Ok(0);
+        }
+
+        _ => {}
+    }
+
+    let tracee = current_task.tracee(pid)?;
+
+    match request {
+        PtraceRequest::PeekText | PtraceRequest::PeekData => {
+            let word = tracee.ptrace_peek(addr)?;
+            copy_to_user(data as *mut u64, &word)?;
+        }
+
+        PtraceRequest::PokeText | PtraceRequest::PokeData => {
+            tracee.ptrace_poke(addr, data as u64)?;
+        }
+
+        PtraceRequest::GetRegs => {
+            let regs = tracee.ptrace_get_regs()?;
+            copy_to_user(data as *mut PtraceRegs, &regs)?;
+        }
+
+        PtraceRequest::SetRegs => {
+            let mut regs = MaybeUninit::uninit();
+            copy_from_user(&mut regs, data as *const PtraceRegs)?;
+
+            // SAFETY: `copy_from_user` initialized the registers.
+            tracee.ptrace_set_regs(unsafe { regs.assume_init_ref() })?;
+        }
+
+        PtraceRequest::Cont | PtraceRequest::Syscall | PtraceRequest::Detach => {
+            if data >= SIGNAL_COUNT {
+                return Err(SyscallError::EIO);
+            }
+
+            if request == PtraceRequest::Detach {
+                tracee.ptrace_detach();
+            } else {
+                tracee.ptrace_resume(request == PtraceRequest::Syscall)?;
+            }
+
+            // A non-zero `data` is a signal to deliver to the tracee.
+            if data != 0 {
+                tracee.signal(data);
+            }
+        }
+
+        PtraceRequest::GetSigInfo => {
+            let info = tracee.ptrace_get_siginfo()?;
+            copy_to_user(data as *mut SigInfo, &info)?;
+        }
+
+        PtraceRequest::SetWatchpoint => {
+            let mut watchpoint = MaybeUninit::uninit();
+            copy_from_user(&mut watchpoint, data as *const PtraceWatchpoint)?;
+
+            // SAFETY: `copy_from_user` initialized the watchpoint.
+            tracee.ptrace_set_watchpoint(addr, unsafe { watchpoint.assume_init_ref() })?;
+        }
+
+        PtraceRequest::GetWatchpoints => {
+            let (watchpoints, triggered) = tracee.ptrace_get_watchpoints()?;
+            copy_to_user(
+                data as *mut [PtraceWatchpoint; PTRACE_WATCHPOINT_COUNT],
+                &watchpoints,
+            )?;
+
+            return Ok(triggered as usize);
+        }
+
+        PtraceRequest::TraceMe | PtraceRequest::Attach => unreachable!(),
+    }
+
+    Ok(0)
+}
+
+#[syscall(no_return, number(SYS_EXEC))]
 pub fn exec(path: &Path, args: usize, argc: usize, envs: usize, envc: usize) -> Result<usize> {
     let executable = fs::lookup_path(path)?;
 
@@ -145,22 +260,24 @@ pub fn exec(path: &Path, args: usize, argc: usize, envs: usize, envc: usize) ->
     unreachable!()
 }
 
-#[syscall]
+#[syscall(number(SYS_LOG))]
 pub fn log(msg: &str) -> Result<usize> {
     log::debug!("{}", msg);
 
     Ok(0x00)
 }
 
-#[syscall]
+#[syscall(number(SYS_WAITPID))]
 pub fn waitpid(pid: usize, status: &mut u32, flags: usize) -> Result<usize> {
     let flags = WaitPidFlags::from_bits_truncate(flags);
     let current_task = scheduler::get_scheduler().current_task();
 
-    Ok(current_task.waitpid(pid as isize, status, flags)?)
+    current_task
+        .waitpid(pid as isize, status, flags)
+        .map_err(signals::restartable)
 }
 
-#[syscall]
+#[syscall(number(SYS_MMAP))]
 pub fn mmap(
     address: usize,
     size: usize,
@@ -176,13 +293,30 @@ pub fn mmap(
     let mut file = None;
 
     if fd as isize != -1 {
-        file = Some(
-            scheduler::get_scheduler()
-                .current_task()
-                .file_table
-                .get_handle(fd)
-                .ok_or(SyscallError::EBADF)?,
-        );
+        let handle = scheduler::get_scheduler()
+            .current_task()
+            .file_table
+            .get_handle(fd)
+            .ok_or(SyscallError::EBADF)?;
+
+        // `O_PATH` file descriptors cannot be mapped.
+        if handle.is_path() {
+            return Err(SyscallError::EBADF);
+        }
+
+        let write_sealed = handle
+            .inode()
+            .seals()
+            .is_ok_and(|seals| seals.intersects(SealFlags::WRITE | SealFlags::FUTURE_WRITE));
+
+        if write_sealed
+            && flags.contains(MMapFlags::MAP_SHARED)
+            && protection.contains(MMapProt::PROT_WRITE)
+        {
+            return Err(SyscallError::EPERM);
+        }
+
+        file = Some(handle);
     }
 
     if let Some(alloc) = scheduler::get_scheduler()
@@ -196,7 +330,7 @@ pub fn mmap(
     }
 }
 
-#[syscall]
+#[syscall(number(SYS_MUNMAP))]
 pub fn munmap(address: usize, size: usize) -> Result<usize> {
     let address = VirtAddr::new(address as u64);
 
@@ -211,7 +345,7 @@ pub fn munmap(address: usize, size: usize) -> Result<usize> {
     }
 }
 
-#[syscall]
+#[syscall(number(SYS_MPROTECT))]
 pub fn mprotect(ptr: usize, size: usize, prot: usize) -> Result<usize> {
     let ptr = VirtAddr::new(ptr as _);
     let prot = MMapProt::from_bits(prot).ok_or(SyscallError::EINVAL)?;
@@ -222,7 +356,7 @@ pub fn mprotect(ptr: usize, size: usize, prot: usize) -> Result<usize> {
     Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_BACKTRACE))]
 pub fn backtrace() -> Result<usize> {
     crate::unwind::unwind_stack_trace();
     Ok(0)
@@ -232,18 +366,18 @@ pub fn backtrace() -> Result<usize> {
 ///
 /// When the tracer is enabled for a process, it also applies to any child processes spawned by the
 /// process.
-#[syscall]
+#[syscall(number(SYS_TRACE))]
 pub fn trace() -> Result<usize> {
     scheduler::get_scheduler().current_task().enable_systrace();
     Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_GETPID))]
 pub fn getpid() -> Result<usize> {
     Ok(scheduler::get_scheduler().current_task().pid().as_usize())
 }
 
-#[syscall]
+#[syscall(number(SYS_GETPPID))]
 pub fn getppid() -> Result<usize> {
     Ok(scheduler::get_scheduler()
         .current_task()
@@ -251,17 +385,28 @@ pub fn getppid() -> Result<usize> {
         .as_usize())
 }
 
-#[syscall]
+#[syscall(number(SYS_GETTID))]
 pub fn gettid() -> Result<usize> {
     Ok(scheduler::get_scheduler().current_task().tid().as_usize())
 }
 
-#[syscall]
+/// Sets the address of the thread ID that is zeroed when the calling thread exits, after which
+/// a waiter on it is woken up with a futex wake. Returns the thread ID of the caller.
+#[syscall(number(SYS_SET_TID_ADDRESS))]
+pub fn set_tid_address(address: usize) -> Result<usize> {
+    let task = scheduler::get_scheduler().current_task();
+
+    task.set_clear_child_tid(address);
+    Ok(task.tid().as_usize())
+}
+
+#[syscall(number(SYS_GETHOSTNAME))]
 pub fn gethostname(buffer: &mut [u8]) -> Result<usize> {
     let hostname = hostname().lock();
     let bytes = hostname.as_bytes();
 
-    if bytes.len() > buffer.len() {
+    // Leave room for the NUL terminator.
+    if bytes.len() >= buffer.len() {
         Err(SyscallError::ENAMETOOLONG)
     } else {
         buffer[0..bytes.len()].copy_from_slice(bytes);
@@ -271,26 +416,32 @@ pub fn gethostname(buffer: &mut [u8]) -> Result<usize> {
     }
 }
 
-#[syscall]
+#[syscall(number(SYS_INFO))]
 pub fn info(struc: &mut SysInfo) -> Result<usize> {
     struc.uptime = crate::arch::time::get_uptime_ticks() as i64;
 
+    struc.totalram = FRAME_ALLOCATOR.total_memory() as u64;
+    struc.freeram = FRAME_ALLOCATOR.free_memory() as u64;
+    struc.mem_unit = 1;
+
     Ok(0x00)
 }
 
-#[syscall]
+// TODO: Changing the host and domain names requires euid 0 (or `CAP_SYS_ADMIN`) once
+// credentials are implemented.
+#[syscall(number(SYS_SETHOSTNAME))]
 pub fn sethostname(name: &[u8]) -> Result<usize> {
-    match core::str::from_utf8(name) {
-        Ok(name) => {
-            *hostname().lock() = name.into();
+    *hostname().lock() = parse_uts_name(name)?.into();
+    Ok(0)
+}
 
-            Ok(0)
-        }
-        Err(_) => Err(SyscallError::EINVAL),
-    }
+#[syscall(number(SYS_SETDOMAINNAME))]
+pub fn setdomainname(name: &[u8]) -> Result<usize> {
+    *domainname().lock() = parse_uts_name(name)?.into();
+    Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_SIGPROCMASK))]
 pub fn sigprocmask(how: usize, set: *const u64, old_set: *mut u64) -> Result<usize> {
     let set = if set.is_null() {
         None
@@ -314,7 +465,7 @@ pub fn sigprocmask(how: usize, set: *const u64, old_set: *mut u64) -> Result<usi
     Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_SIGACTION))]
 pub fn sigaction(
     sig: usize,
     sigact: *mut SigAction,
@@ -348,20 +499,109 @@ pub fn sigaction(
     Ok(0)
 }
 
-#[syscall(no_return)]
+#[syscall(no_return, number(SYS_SHUTDOWN))]
 pub fn shutdown() -> Result<usize> {
-    fs::cache::dcache().log();
+    reboot_system(RebootCmd::PowerOff)
+}
+
+// TODO: Rebooting requires `CAP_SYS_BOOT` once credentials are implemented.
+#[syscall(number(SYS_REBOOT))]
+pub fn reboot(magic1: usize, magic2: usize, cmd: usize, _arg: usize) -> Result<usize> {
+    // The arguments are C `int`s, which may have been sign-extended.
+    if magic1 as u32 != REBOOT_MAGIC1 || magic2 as u32 != REBOOT_MAGIC2 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let cmd = RebootCmd::from_u32(cmd as u32).ok_or(SyscallError::EINVAL)?;
+
+    match cmd {
+        RebootCmd::CadOn | RebootCmd::CadOff => {
+            CTRL_ALT_DEL_RESTARTS.store(cmd == RebootCmd::CadOn, Ordering::SeqCst);
+            Ok(0)
+        }
+
+        _ => reboot_system(cmd),
+    }
+}
+
+/// Whether Ctrl-Alt-Del restarts the system right away, rather than sending `SIGINT` to init.
+static CTRL_ALT_DEL_RESTARTS: AtomicBool = AtomicBool::new(true);
+
+/// Called by the keyboard driver when Ctrl-Alt-Del is pressed.
+pub fn ctrl_alt_del() {
+    if CTRL_ALT_DEL_RESTARTS.load(Ordering::SeqCst) {
+        // Like on Linux, the processes are not terminated and the filesystems are not synced.
+        log::info!("reboot: Ctrl-Alt-Del");
+        arch::power::reset();
+    }
+
+    if let Some(init) = scheduler::get_scheduler().find_task(TaskId::INIT) {
+        init.signal(SIGINT);
+    }
+}
+
+/// Time given to the other processes to exit after `SIGTERM` before they are killed, in
+/// seconds.
+const REBOOT_GRACE_PERIOD: usize = 5;
+
+/// Terminates the other processes, syncs the filesystems and then restarts, powers off or halts
+/// the system.
+fn reboot_system(cmd: RebootCmd) -> ! {
+    let pid = scheduler::current_thread().pid();
+
+    signal_other_processes(pid, SIGTERM);
+    wait_for_other_processes(pid, REBOOT_GRACE_PERIOD);
+
+    signal_other_processes(pid, SIGKILL);
+    wait_for_other_processes(pid, 1);
 
-    fs::cache::clear_inode_cache();
-    fs::cache::clear_dir_cache();
+    fs::shutdown();
 
-    let _guard = IrqGuard::new();
-    aml::get_subsystem().enter_state(aml::SleepState::S5);
+    log::info!("reboot: {cmd:?}");
 
-    unreachable!("aml: failed to shutdown (enter state S5)")
+    match cmd {
+        RebootCmd::Restart => arch::power::reset(),
+        RebootCmd::Halt => arch::power::halt_system(),
+        RebootCmd::PowerOff => arch::power::power_off(),
+
+        RebootCmd::CadOn | RebootCmd::CadOff => unreachable!("reboot: {cmd:?} does not reboot"),
+    }
 }
 
-#[syscall]
+/// Returns the live userland tasks that are not part of the process `pid` or of init.
+fn other_processes(pid: TaskId) -> Vec<Arc<Task>> {
+    let mut tasks = Vec::new();
+
+    scheduler::get_scheduler().for_each_task(|task| {
+        let other = task.pid() != pid && task.pid() != TaskId::INIT;
+
+        if other && task.arch_task().is_user() && !task.has_exited() {
+            tasks.push(task.clone());
+        }
+    });
+
+    tasks
+}
+
+fn signal_other_processes(pid: TaskId, signal: usize) {
+    for task in other_processes(pid) {
+        task.signal(signal);
+    }
+}
+
+/// Sleeps until all of the other processes have exited, for at most `timeout` seconds.
+fn wait_for_other_processes(pid: TaskId, timeout: usize) {
+    let deadline = arch::time::get_uptime_ticks() + timeout;
+
+    while !other_processes(pid).is_empty() && arch::time::get_uptime_ticks() < deadline {
+        // Interrupted by a signal (e.g. `SIGCHLD` from the exiting children) is fine.
+        let _ = scheduler::get_scheduler()
+            .inner
+            .sleep(Some(timer::deadline_after(timer::NSEC_PER_SEC)));
+    }
+}
+
+#[syscall(number(SYS_GETPGID))]
 pub fn getpgid(pid: usize) -> Result<usize> {
     let current_task = scheduler::current_thread();
 
@@ -378,7 +618,7 @@ pub fn getpgid(pid: usize) -> Result<usize> {
     Ok(group.id())
 }
 
-#[syscall]
+#[syscall(number(SYS_SETPGID))]
 pub fn setpgid(pid: usize, pgid: usize) -> Result<usize> {
     let current_task = scheduler::current_thread();
     let task = if pid == 0 || pid == current_task.pid().as_usize() {
@@ -410,13 +650,273 @@ pub fn setpgid(pid: usize, pgid: usize) -> Result<usize> {
     Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_SETSID))]
 pub fn setsid() -> Result<usize> {
     let current_task = scheduler::get_scheduler().current_task();
     if current_task.is_group_leader() {
         return Err(SyscallError::EPERM);
     }
 
+    // The new session has no controlling terminal.
+    let _ = current_task.disown_terminal();
+
     SESSIONS.isolate(&current_task);
     Ok(0)
 }
+
+/// Returns the tasks selected by the `which` and `who` arguments of `getpriority` and
+/// `setpriority`.
+fn priority_targets(which: usize, who: usize) -> Result<Vec<Arc<Task>>> {
+    let current_task = scheduler::current_thread();
+
+    let targets = match which {
+        PRIO_PROCESS if who == 0 => alloc::vec![current_task],
+        PRIO_PROCESS => alloc::vec![scheduler::get_scheduler()
+            .find_task(TaskId::new(who))
+            .ok_or(SyscallError::ESRCH)?],
+
+        PRIO_PGRP => {
+            let group = if who == 0 {
+                current_task.group_id()
+            } else {
+                who
+            };
+            let mut targets = Vec::new();
+
+            scheduler::get_scheduler().for_each_task(|task| {
+                if task.group_id() == group {
+                    targets.push(task.clone());
+                }
+            });
+
+            targets
+        }
+
+        // There are no users yet.
+        _ => return Err(SyscallError::EINVAL),
+    };
+
+    if targets.is_empty() {
+        return Err(SyscallError::ESRCH);
+    }
+
+    Ok(targets)
+}
+
+/// Returns the lowest nice value of the selected tasks, as `20 - nice` so that the result is
+/// never negative (like Linux, the C library converts it back).
+#[syscall(number(SYS_GETPRIORITY))]
+pub fn getpriority(which: usize, who: usize) -> Result<usize> {
+    let nice = priority_targets(which, who)?
+        .iter()
+        .map(|task| task.sched().nice())
+        .min()
+        .unwrap();
+
+    Ok((20 - nice) as usize)
+}
+
+#[syscall(number(SYS_SETPRIORITY))]
+pub fn setpriority(which: usize, who: usize, nice: usize) -> Result<usize> {
+    let nice = nice as isize;
+
+    // TODO: Lowering the nice value of a task requires euid 0 (or `CAP_SYS_NICE`) once
+    // credentials are implemented. Until then, every task is privileged.
+    for task in priority_targets(which, who)? {
+        task.sched().set_nice(nice);
+    }
+
+    Ok(0)
+}
+
+/// Stores the set of CPUs the task `pid` may run on. Affinity cannot be changed yet, so this is
+/// the set of online CPUs.
+#[syscall(number(SYS_SCHED_GETAFFINITY))]
+pub fn sched_getaffinity(pid: usize, set: &mut CpuSet) -> Result<usize> {
+    if pid != 0 {
+        scheduler::get_scheduler()
+            .find_task(TaskId::new(pid))
+            .ok_or(SyscallError::ESRCH)?;
+    }
+
+    *set = CpuSet::new();
+
+    #[cfg(target_arch = "x86_64")]
+    crate::arch::tls::for_cpu_info_cached(|info| set.insert(info.cpuid));
+
+    Ok(core::mem::size_of::<CpuSet>())
+}
+
+/// Issues a memory barrier on every running thread selected by `cmd` and returns once they all
+/// executed it, or returns the set of supported commands if `cmd` is empty.
+///
+/// Registration is not tracked, so the expedited commands also work for processes that did not
+/// register for them. Every command interrupts all of the online CPUs.
+#[syscall(number(SYS_MEMBARRIER))]
+pub fn membarrier(cmd: usize, flags: usize, _cpu_id: usize) -> Result<usize> {
+    use aero_syscall::process::MembarrierCmd;
+
+    let cmd = MembarrierCmd::from_bits(cmd).ok_or(SyscallError::EINVAL)?;
+
+    if flags != 0 || cmd.bits().count_ones() > 1 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    if cmd.is_empty() {
+        return Ok(MembarrierCmd::all().bits());
+    }
+
+    if cmd.intersects(
+        MembarrierCmd::GLOBAL | MembarrierCmd::GLOBAL_EXPEDITED | MembarrierCmd::PRIVATE_EXPEDITED,
+    ) {
+        #[cfg(target_arch = "x86_64")]
+        crate::arch::apic::fence_all();
+
+        #[cfg(not(target_arch = "x86_64"))]
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+    }
+
+    Ok(0)
+}
+
+/// Compares the kernel objects of type `typ` used by two processes, returning `0` if they are the
+/// same object, `1` if the object of `pid1` orders before the one of `pid2` and `2` otherwise.
+/// The ordering is arbitrary but stable while the objects are alive. Like Linux, negative return
+/// values are errors, so the result is not signed like the one of `memcmp`.
+#[syscall(number(SYS_KCMP))]
+pub fn kcmp(pid1: usize, pid2: usize, typ: usize, idx1: usize, idx2: usize) -> Result<usize> {
+    let typ = KcmpType::from_usize(typ).ok_or(SyscallError::EINVAL)?;
+    let scheduler = scheduler::get_scheduler();
+
+    let task1 = scheduler
+        .find_task(TaskId::new(pid1))
+        .ok_or(SyscallError::ESRCH)?;
+    let task2 = scheduler
+        .find_task(TaskId::new(pid2))
+        .ok_or(SyscallError::ESRCH)?;
+
+    // TODO: Require `CAP_SYS_PTRACE`, or that the caller owns both processes, once credentials
+    // are implemented. Until then, every task is privileged.
+
+    let file = |task: &Task, fd: usize| -> Result<usize> {
+        let handle = task.file_table.get_handle(fd).ok_or(SyscallError::EBADF)?;
+        let inode = handle.inode();
+
+        Ok(Arc::as_ptr(&***inode).cast::<()>() as usize)
+    };
+
+    let (object1, object2) = match typ {
+        KcmpType::File => (file(&task1, idx1)?, file(&task2, idx2)?),
+        KcmpType::Vm => (
+            Arc::as_ptr(task1.vm()) as usize,
+            Arc::as_ptr(task2.vm()) as usize,
+        ),
+        // There is no `chroot` yet, so every process has the same filesystem root.
+        KcmpType::Fs => (0, 0),
+    };
+
+    Ok(match object1.cmp(&object2) {
+        core::cmp::Ordering::Equal => 0,
+        core::cmp::Ordering::Less => 1,
+        core::cmp::Ordering::Greater => 2,
+    })
+}
+
+/// Maximum size of the arguments and environment of a new program, reported as `_SC_ARG_MAX`.
+const ARG_MAX: usize = 128 * 1024;
+
+/// Returns the value of the system configuration variable `name`.
+#[syscall(number(SYS_SYSCONF))]
+pub fn sysconf(name: usize) -> Result<usize> {
+    match name {
+        _SC_PAGESIZE => Ok(Size4KiB::SIZE as usize),
+        _SC_CLK_TCK => Ok(fs::procfs::USER_HZ as usize),
+        _SC_OPEN_MAX => {
+            let limit = scheduler::current_thread().file_table.nofile_limit();
+            Ok(limit.rlim_cur as usize)
+        }
+        _SC_ARG_MAX => Ok(ARG_MAX),
+
+        // Every CPU that is present is brought online at boot.
+        _SC_NPROCESSORS_CONF | _SC_NPROCESSORS_ONLN => {
+            let mut count = 0;
+
+            #[cfg(target_arch = "x86_64")]
+            crate::arch::tls::for_cpu_info_cached(|_| count += 1);
+
+            Ok(count)
+        }
+
+        _ => Err(SyscallError::EINVAL),
+    }
+}
+
+/// Stores the limits of `resource` of the calling process into `limit`. Only `RLIMIT_NOFILE` is
+/// enforced, every other resource is unlimited.
+#[syscall(number(SYS_GETRLIMIT))]
+pub fn getrlimit(resource: usize, limit: &mut RLimit) -> Result<usize> {
+    *limit = match resource {
+        RLIMIT_NOFILE => scheduler::current_thread().file_table.nofile_limit(),
+        _ if resource < RLIM_NLIMITS => RLimit {
+            rlim_cur: RLIM_INFINITY,
+            rlim_max: RLIM_INFINITY,
+        },
+        _ => return Err(SyscallError::EINVAL),
+    };
+
+    Ok(0)
+}
+
+/// Sets the limits of `resource` of the calling process. Only `RLIMIT_NOFILE` can be changed.
+#[syscall(number(SYS_SETRLIMIT))]
+pub fn setrlimit(resource: usize, limit: &RLimit) -> Result<usize> {
+    if resource != RLIMIT_NOFILE {
+        return Err(SyscallError::EINVAL);
+    }
+
+    scheduler::current_thread()
+        .file_table
+        .set_nofile_limit(*limit)?;
+
+    Ok(0)
+}
+
+/// Sets the file mode creation mask of the calling process to `mask & 0o777` and returns the
+/// previous mask. This call always succeeds.
+#[syscall(number(SYS_UMASK))]
+pub fn umask(mask: usize) -> Result<usize> {
+    let mask = Mode::from_bits_truncate(mask as u32);
+    Ok(scheduler::current_thread().set_umask(mask).bits() as usize)
+}
+
+/// Stores the supplementary group IDs of the calling process into `groups` and returns their
+/// number. An empty `groups` only returns the number.
+#[syscall(number(SYS_GETGROUPS))]
+pub fn getgroups(groups: &mut [u32]) -> Result<usize> {
+    let credentials = scheduler::current_thread().credentials();
+    let current = credentials.supplementary_groups();
+
+    if groups.is_empty() {
+        return Ok(current.len());
+    }
+
+    groups
+        .get_mut(..current.len())
+        .ok_or(SyscallError::EINVAL)?
+        .copy_from_slice(current);
+
+    Ok(current.len())
+}
+
+/// Replaces the supplementary group IDs of the calling process.
+#[syscall(number(SYS_SETGROUPS))]
+pub fn setgroups(groups: &[u32]) -> Result<usize> {
+    if groups.len() > NGROUPS_MAX {
+        return Err(SyscallError::EINVAL);
+    }
+
+    // TODO: Require `CAP_SETGID` once credentials are implemented. Until then, every task is
+    // privileged.
+    scheduler::current_thread().set_supplementary_groups(groups);
+    Ok(0)
+}
@@ -15,28 +15,41 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::time::{ITimerVal, ITIMER_REAL};
+use aero_syscall::process::{RUsage, Tms, RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD};
+use aero_syscall::signal::SIGALRM;
+use aero_syscall::time::{ITimerVal, TimeVal, ITIMER_REAL};
 use aero_syscall::{SyscallError, TimeSpec};
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
+use crate::fs::procfs::USER_HZ;
+use crate::timer::{self, TimerId};
 use crate::userland::scheduler;
-use crate::userland::task::Task;
+use crate::userland::task::{Task, TaskState};
 use crate::utils::sync::{IrqGuard, Mutex};
 
 const CLOCK_TYPE_REALTIME: usize = 0;
 const CLOCK_TYPE_MONOTONIC: usize = 1;
 
-#[syscall]
+#[syscall(number(SYS_SLEEP))]
 pub fn sleep(timespec: &TimeSpec) -> Result<usize, SyscallError> {
-    let duration = (timespec.tv_nsec as usize).div_ceil(1000000000) + timespec.tv_sec as usize;
+    if timespec.tv_sec < 0 || !(0..1_000_000_000).contains(&timespec.tv_nsec) {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let duration = (timespec.tv_sec as u64)
+        .saturating_mul(timer::NSEC_PER_SEC)
+        .saturating_add(timespec.tv_nsec as u64);
+    let deadline = timer::deadline_after(duration);
 
-    scheduler::get_scheduler().inner.sleep(Some(duration))?;
+    while timer::now() < deadline {
+        scheduler::get_scheduler().inner.sleep(Some(deadline))?;
+    }
 
     Ok(0x00)
 }
 
-#[syscall]
+#[syscall(number(SYS_GETTIME))]
 pub fn gettime(clock: usize, timespec: &mut TimeSpec) -> Result<usize, SyscallError> {
     match clock {
         CLOCK_TYPE_REALTIME => {
@@ -64,13 +77,138 @@ pub fn gettime(clock: usize, timespec: &mut TimeSpec) -> Result<usize, SyscallEr
 
 static TIMERS: Mutex<Vec<Arc<Task>>> = Mutex::new(Vec::new());
 
-pub fn check_timers() {
-    // for task in TIMERS.lock_irq().iter() {
-    //     task.signal(aero_syscall::signal::SIGALRM);
-    // }
+struct Alarm {
+    /// The process leader that `SIGALRM` is sent to.
+    task: Weak<Task>,
+    /// Deadline of the alarm, on the monotonic clock of the timers.
+    deadline: u64,
+    timer: TimerId,
+}
+
+/// Pending alarms, at most one per process.
+static ALARMS: Mutex<Vec<Alarm>> = Mutex::new(Vec::new());
+
+/// Converts the absolute `CLOCK_REALTIME` time `deadline` to a deadline on the monotonic clock of
+/// the [timers](crate::timer).
+///
+/// ## Errors
+/// * `EINVAL`: The nanoseconds of `deadline` are out of range.
+pub fn realtime_deadline(deadline: &TimeSpec) -> Result<u64, SyscallError> {
+    if !(0..1_000_000_000).contains(&deadline.tv_nsec) {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let now = crate::arch::time::get_realtime_clock();
+    let remaining = (deadline.tv_sec as i128 - now.tv_sec as i128) * 1_000_000_000
+        + (deadline.tv_nsec - now.tv_nsec) as i128;
+
+    Ok(timer::deadline_after(
+        remaining.clamp(0, u64::MAX as i128) as u64
+    ))
+}
+
+/// Sends `SIGALRM` to the process of the alarm that expired at `deadline`.
+fn alarm_expired(task: Weak<Task>, deadline: u64) {
+    ALARMS
+        .lock_irq()
+        .retain(|alarm| !(Weak::ptr_eq(&alarm.task, &task) && alarm.deadline == deadline));
+
+    if let Some(task) = task.upgrade() {
+        if task.state() != TaskState::Zombie {
+            task.signal(SIGALRM);
+        }
+    }
+}
+
+#[syscall(number(SYS_ALARM))]
+pub fn alarm(seconds: usize) -> Result<usize, SyscallError> {
+    let task = scheduler::get_scheduler().current_task().process_leader();
+    let now = timer::now();
+
+    let mut alarms = ALARMS.lock_irq();
+
+    // Remove the previous alarm of the process, and any alarm of a process that is gone.
+    let mut remaining = 0;
+
+    alarms.retain(|alarm| match alarm.task.upgrade() {
+        Some(owner) if Arc::ptr_eq(&owner, &task) => {
+            timer::cancel(alarm.timer);
+
+            // Round up, so that an alarm that is about to go off is still reported.
+            remaining = alarm
+                .deadline
+                .saturating_sub(now)
+                .div_ceil(timer::NSEC_PER_SEC)
+                .max(1) as usize;
+            false
+        }
+
+        Some(_) => true,
+        None => {
+            timer::cancel(alarm.timer);
+            false
+        }
+    });
+
+    if seconds != 0 {
+        let deadline = now.saturating_add((seconds as u64).saturating_mul(timer::NSEC_PER_SEC));
+        let weak = Arc::downgrade(&task);
+
+        alarms.push(Alarm {
+            task: weak.clone(),
+            deadline,
+            timer: timer::add_timer(deadline, move || alarm_expired(weak, deadline)),
+        });
+    }
+
+    Ok(remaining)
+}
+
+#[syscall(number(SYS_TIMES))]
+pub fn times(tms: &mut Tms) -> Result<usize, SyscallError> {
+    let ticks = |us: u64| (us * USER_HZ / 1_000_000) as i64;
+
+    let task = scheduler::get_scheduler().current_task();
+    let (utime, stime) = task.sched().cpu_times();
+    let (cutime, cstime) = task.sched().children_cpu_times();
+
+    *tms = Tms {
+        tms_utime: ticks(utime),
+        tms_stime: ticks(stime),
+        tms_cutime: ticks(cutime),
+        tms_cstime: ticks(cstime),
+    };
+
+    Ok(crate::arch::time::get_uptime_ms() * USER_HZ as usize / 1000)
+}
+
+#[syscall(number(SYS_GETRUSAGE))]
+pub fn getrusage(who: usize, usage: &mut RUsage) -> Result<usize, SyscallError> {
+    let timeval = |us: u64| TimeVal {
+        tv_sec: (us / 1_000_000) as i64,
+        tv_usec: (us % 1_000_000) as i64,
+    };
+
+    let task = scheduler::get_scheduler().current_task();
+    let (minflt, cminflt) = task.sched().page_faults();
+
+    let ((utime, stime), minflt) = match who as isize {
+        RUSAGE_SELF | RUSAGE_THREAD => (task.sched().cpu_times(), minflt),
+        RUSAGE_CHILDREN => (task.sched().children_cpu_times(), cminflt),
+        _ => return Err(SyscallError::EINVAL),
+    };
+
+    *usage = RUsage {
+        ru_utime: timeval(utime),
+        ru_stime: timeval(stime),
+        ru_minflt: minflt as i64,
+        ..Default::default()
+    };
+
+    Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_SETITIMER))]
 pub fn setitimer(
     which: usize,
     _new_value: &ITimerVal,
@@ -93,7 +231,7 @@ pub fn setitimer(
     Ok(0)
 }
 
-#[syscall]
+#[syscall(number(SYS_GETITIMER))]
 pub fn getitimer(_which: usize, _curr_value: &mut ITimerVal) -> Result<usize, SyscallError> {
     Ok(0)
 }
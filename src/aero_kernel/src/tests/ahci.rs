@@ -0,0 +1,69 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! AHCI port hot-plug state machine tests.
+
+use crate::drivers::block::ahci::{PortAction, PortEvent, PortState};
+
+#[test]
+fn ahci_port_hotplug() {
+    let (state, action) = PortState::Empty.next(PortEvent::Connected);
+    assert_eq!((state, action), (PortState::Attaching, PortAction::Attach));
+
+    let (state, action) = state.next(PortEvent::Attached);
+    assert_eq!((state, action), (PortState::Active, PortAction::None));
+
+    // The link bouncing while the disk stays connected does not reinstall it.
+    let (state, action) = state.next(PortEvent::Connected);
+    assert_eq!((state, action), (PortState::Active, PortAction::None));
+
+    let (state, action) = state.next(PortEvent::Disconnected);
+    assert_eq!((state, action), (PortState::Empty, PortAction::Detach));
+
+    let (state, action) = state.next(PortEvent::Disconnected);
+    assert_eq!((state, action), (PortState::Empty, PortAction::None));
+}
+
+#[test]
+fn ahci_port_attach_failure() {
+    let (state, _) = PortState::Empty.next(PortEvent::Connected);
+
+    // E.g. a port multiplier was connected.
+    let (state, action) = state.next(PortEvent::AttachFailed);
+    assert_eq!((state, action), (PortState::Failed, PortAction::None));
+
+    // Nothing was installed, so there is nothing to remove.
+    let (state, action) = state.next(PortEvent::Disconnected);
+    assert_eq!((state, action), (PortState::Empty, PortAction::None));
+
+    // A failed port is retried on the next connect change.
+    let (state, _) = PortState::Failed.next(PortEvent::Connected);
+    assert_eq!(state, PortState::Attaching);
+
+    // The device was removed while the port was being initialized.
+    let (state, action) = state.next(PortEvent::Disconnected);
+    assert_eq!((state, action), (PortState::Empty, PortAction::None));
+}
+
+#[test]
+fn ahci_port_unexpected_events() {
+    for state in [PortState::Empty, PortState::Active, PortState::Failed] {
+        for event in [PortEvent::Attached, PortEvent::AttachFailed] {
+            assert_eq!(state.next(event), (state, PortAction::None));
+        }
+    }
+}
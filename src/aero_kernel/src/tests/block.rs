@@ -0,0 +1,78 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Block request merging and readahead tests.
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::Ordering;
+
+use crate::fs::block::queue::STATS;
+use crate::fs::block::{self, BlockDeviceInterface, CachedAccess};
+use crate::mem::paging::*;
+
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
+
+/// Offset of the data read by the tests, past the partition table.
+const START: usize = 1 << 20;
+/// Size of the sequential read (4 MiB).
+const SIZE: usize = 4 << 20;
+
+fn commands() -> usize {
+    STATS.commands.load(Ordering::Relaxed)
+}
+
+#[test]
+fn sequential_read_merges_requests() {
+    // The first block device is a whole disk, which nothing else reads through the page cache.
+    let Some(device) = block::block_devices().into_iter().next() else {
+        log::warn!("block: no block device, skipping");
+        return;
+    };
+
+    if device.max_transfer_size() < 8 * PAGE_SIZE {
+        log::warn!("block: {} cannot merge requests, skipping", device.name());
+        return;
+    }
+
+    // Without the page cache, every page is a command of its own.
+    let frame: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
+    let before = commands();
+
+    for offset in (START + SIZE..START + SIZE + 64 * PAGE_SIZE).step_by(PAGE_SIZE) {
+        device.read_direct(offset, frame).unwrap();
+    }
+
+    assert_eq!(commands() - before, 64);
+    FRAME_ALLOCATOR.deallocate_frame(frame);
+
+    // Read 4 MiB a page at a time, like a file read would.
+    let mut buffer = [MaybeUninit::<u8>::uninit(); PAGE_SIZE];
+    let hits = STATS.readahead_hits.load(Ordering::Relaxed);
+    let before = commands();
+
+    for offset in (START..START + SIZE).step_by(PAGE_SIZE) {
+        assert_eq!(device.read(offset, &mut buffer), Some(PAGE_SIZE));
+    }
+
+    let pages = SIZE / PAGE_SIZE;
+    let commands = commands() - before;
+
+    log::debug!("block: read {pages} pages with {commands} commands");
+
+    assert!(commands * 8 <= pages);
+    assert!(STATS.readahead_hits.load(Ordering::Relaxed) - hits >= pages / 2);
+}
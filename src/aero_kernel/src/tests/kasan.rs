@@ -0,0 +1,65 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Heap sanitizer tests.
+
+use core::alloc::Layout;
+
+use alloc::boxed::Box;
+use alloc::vec;
+
+use crate::mem::kasan::{self, Corruption, POISON_BYTE, REDZONE_BYTE};
+
+#[test]
+fn kasan_heap_overflow() {
+    let buffer: Box<[u8]> = vec![0; 32].into_boxed_slice();
+    let layout = Layout::for_value(&*buffer);
+    let ptr = Box::into_raw(buffer).cast::<u8>();
+
+    // SAFETY: `ptr` was allocated with `layout` by the global allocator.
+    unsafe {
+        assert_eq!(kasan::check(ptr, layout), Ok(()));
+
+        // Write one byte past the end of the buffer, into the redzone.
+        ptr.add(32).write_volatile(0);
+        assert_eq!(kasan::check(ptr, layout), Err(Corruption::RightRedzone));
+
+        // Restore the redzone, as freeing the buffer would panic otherwise.
+        ptr.add(32).write_volatile(REDZONE_BYTE);
+        assert_eq!(kasan::check(ptr, layout), Ok(()));
+
+        drop(Box::from_raw(core::ptr::slice_from_raw_parts_mut(ptr, 32)));
+    }
+}
+
+#[test]
+fn kasan_use_after_free() {
+    let vec = vec![0u8; 64];
+    let ptr = vec.as_ptr();
+
+    drop(vec);
+
+    // The freed memory is poisoned and stays in the quarantine, instead of being handed out
+    // to the next allocation.
+    let other = vec![0u8; 64];
+    assert_ne!(other.as_ptr(), ptr);
+
+    for i in 0..64 {
+        // SAFETY: The memory is in the quarantine, so it is still mapped.
+        assert_eq!(unsafe { ptr.add(i).read_volatile() }, POISON_BYTE);
+    }
+}
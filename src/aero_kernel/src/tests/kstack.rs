@@ -0,0 +1,64 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+//! Kernel stack guard page tests.
+
+use crate::mem::kstack::{self, KernelStack, KERNEL_STACK_SIZE};
+use crate::mem::paging::*;
+use crate::mem::AddressSpace;
+
+#[test]
+fn kstack_guard_page() {
+    let stack = KernelStack::alloc().unwrap();
+
+    assert_eq!(stack.top() - stack.bottom(), KERNEL_STACK_SIZE as u64);
+
+    let guard = stack.bottom() - Size4KiB::SIZE;
+
+    assert!(stack.guard_page_contains(guard));
+    assert!(stack.guard_page_contains(stack.bottom() - 1u64));
+    assert!(!stack.guard_page_contains(stack.bottom()));
+
+    assert!(kstack::is_guard_page(guard));
+    assert!(!kstack::is_guard_page(stack.bottom()));
+    assert!(!kstack::is_guard_page(stack.top() - 1u64));
+
+    // The guard page must not be mapped, while the whole stack is.
+    let mut address_space = AddressSpace::this();
+    let offset_table = address_space.offset_page_table();
+
+    assert!(offset_table.translate_addr(guard).is_none());
+    assert!(offset_table.translate_addr(stack.bottom()).is_some());
+    assert!(offset_table.translate_addr(stack.top() - 1u64).is_some());
+
+    stack.dealloc();
+}
+
+/// Recurses until the kernel stack of the test thread overflows. This test never passes: the
+/// guard page has to turn the overflow into the kernel stack overflow panic.
+#[cfg(feature = "kstack-overflow-test")]
+#[test]
+fn kstack_overflow() {
+    #[allow(unconditional_recursion)]
+    #[inline(never)]
+    fn recurse(depth: usize) -> usize {
+        let frame = core::hint::black_box([depth; 64]);
+        recurse(depth + 1) + frame[0]
+    }
+
+    recurse(0);
+    unreachable!("kernel stack overflow was not detected");
+}
@@ -0,0 +1,60 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Lock dependency tracker tests.
+
+use crate::utils::lockdep;
+use crate::utils::sync::Mutex;
+
+static A: Mutex<()> = Mutex::new(());
+static B: Mutex<()> = Mutex::new(());
+
+#[test]
+fn lock_order_inversion() {
+    let cycles = lockdep::cycles();
+
+    {
+        let _a = A.lock();
+        let _b = B.lock();
+    }
+
+    assert_eq!(lockdep::cycles(), cycles);
+
+    // Taking the locks in the opposite order closes the cycle. Nothing deadlocks, as both
+    // orders run on the same CPU one after the other.
+    {
+        let _b = B.lock();
+        let _a = A.lock();
+    }
+
+    assert_eq!(lockdep::cycles(), cycles + 1);
+
+    // The cycle is only reported once.
+    for _ in 0..2 {
+        {
+            let _a = A.lock();
+            let _b = B.lock();
+        }
+
+        {
+            let _b = B.lock();
+            let _a = A.lock();
+        }
+    }
+
+    assert_eq!(lockdep::cycles(), cycles + 1);
+}
@@ -0,0 +1,173 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Physical frame allocator and slab allocator tests.
+
+use core::alloc::Layout;
+
+use alloc::vec::Vec;
+use hashbrown::HashSet;
+
+use crate::mem::paging::*;
+use crate::userland::task::Task;
+use crate::utils::sync::IrqGuard;
+
+/// Largest buddy order handed out by the frame allocator (2 MiB).
+const MAX_ORDER: usize = 9;
+
+const fn order_size(order: usize) -> usize {
+    (Size4KiB::SIZE as usize) << order
+}
+
+#[test]
+fn alloc_single_frame() {
+    let frame: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
+    let bytes = frame
+        .start_address()
+        .as_hhdm_virt()
+        .as_bytes_mut(Size4KiB::SIZE as usize);
+
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = i as u8 ^ 0xa5;
+    }
+
+    for (i, byte) in bytes.iter().enumerate() {
+        assert_eq!(*byte, i as u8 ^ 0xa5);
+    }
+
+    FRAME_ALLOCATOR.deallocate_frame(frame);
+}
+
+#[test]
+fn alloc_many_frames() {
+    let mut frames = Vec::new();
+    let mut seen = HashSet::new();
+
+    for _ in 0..256 {
+        let frame: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
+
+        assert!(seen.insert(frame.start_address()), "frame handed out twice");
+        frames.push(frame);
+    }
+
+    for frame in frames {
+        FRAME_ALLOCATOR.deallocate_frame(frame);
+    }
+}
+
+#[test]
+fn free_memory_accounting() {
+    // Nothing else may allocate while the free memory is compared.
+    let _guard = IrqGuard::new();
+    let before = FRAME_ALLOCATOR.free_memory();
+
+    assert!(before <= FRAME_ALLOCATOR.total_memory());
+
+    let frame: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
+    assert_eq!(
+        FRAME_ALLOCATOR.free_memory(),
+        before - Size4KiB::SIZE as usize
+    );
+
+    FRAME_ALLOCATOR.deallocate_frame(frame);
+    assert_eq!(FRAME_ALLOCATOR.free_memory(), before);
+}
+
+#[test]
+fn alloc_slab_object() {
+    let layout = Layout::new::<Task>();
+    let mut objects = Vec::with_capacity(10000);
+    let mut seen = HashSet::new();
+
+    for _ in 0..10000 {
+        // SAFETY: `Task` is not zero-sized.
+        let ptr = unsafe { alloc::alloc::alloc(layout) };
+
+        assert!(!ptr.is_null());
+        assert!(seen.insert(ptr as usize), "slab object handed out twice");
+
+        objects.push(ptr);
+    }
+
+    for ptr in objects {
+        // SAFETY: `ptr` was allocated above with the same layout.
+        unsafe { alloc::alloc::dealloc(ptr, layout) };
+    }
+}
+
+#[test]
+fn oom_returns_none() {
+    // Nothing else may allocate while the memory is exhausted.
+    let _guard = IrqGuard::new();
+
+    // The allocated blocks are chained through their first word, so exhausting the memory does
+    // not require any heap allocations.
+    let mut head: Option<(PhysAddr, usize)> = None;
+
+    for order in (0..=MAX_ORDER).rev() {
+        let size = order_size(order);
+
+        while let Some(addr) = FRAME_ALLOCATOR.alloc(size) {
+            // SAFETY: The block was just allocated and is large enough for the link.
+            unsafe {
+                addr.as_hhdm_virt()
+                    .as_mut_ptr::<Option<(PhysAddr, usize)>>()
+                    .write(head)
+            };
+            head = Some((addr, size));
+        }
+    }
+
+    let frame: Option<PhysFrame<Size4KiB>> = FRAME_ALLOCATOR.allocate_frame();
+    assert!(frame.is_none());
+
+    let frame: Option<PhysFrame<Size2MiB>> = FRAME_ALLOCATOR.allocate_frame();
+    assert!(frame.is_none());
+
+    while let Some((addr, size)) = head {
+        // SAFETY: The link was written above, when the block was allocated.
+        head = unsafe {
+            addr.as_hhdm_virt()
+                .as_ptr::<Option<(PhysAddr, usize)>>()
+                .read()
+        };
+        FRAME_ALLOCATOR.dealloc(addr, size);
+    }
+
+    let frame: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
+    FRAME_ALLOCATOR.deallocate_frame(frame);
+}
+
+#[test]
+fn coalesce_buddy() {
+    let base = FRAME_ALLOCATOR.alloc(order_size(MAX_ORDER)).unwrap();
+    assert!(!FRAME_ALLOCATOR.is_free(base, order_size(MAX_ORDER)));
+
+    // Free the first frame and then the buddies of increasing order. Each one completes the
+    // block starting at `base` of the next order.
+    FRAME_ALLOCATOR.dealloc(base, order_size(0));
+    assert!(FRAME_ALLOCATOR.is_free(base, order_size(0)));
+
+    for order in 0..MAX_ORDER {
+        let buddy = base + order_size(order) as u64;
+        FRAME_ALLOCATOR.dealloc(buddy, order_size(order));
+
+        assert!(!FRAME_ALLOCATOR.is_free(base, order_size(order)));
+        assert!(!FRAME_ALLOCATOR.is_free(buddy, order_size(order)));
+        assert!(FRAME_ALLOCATOR.is_free(base, order_size(order + 1)));
+    }
+}
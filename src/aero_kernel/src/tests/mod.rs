@@ -0,0 +1,126 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+#[cfg(target_arch = "x86_64")]
+mod ahci;
+mod block;
+#[cfg(feature = "kasan")]
+mod kasan;
+mod kstack;
+mod mem;
+mod regex;
+mod rendy;
+mod syscall;
+mod timer;
+mod tty;
+mod zram;
+
+#[cfg(feature = "lockdep")]
+mod lockdep;
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "ci")]
+use crate::emu;
+use crate::utils::regex::Regex;
+
+pub struct Test {
+    pub test_fn: fn(),
+    pub path: &'static str,
+}
+
+pub(crate) fn test_runner(tests: &[&Test]) {
+    crate::rendy::clear_screen(true);
+    crate::logger::set_rendy_debug(true);
+
+    let cmdline = crate::cmdline::get();
+
+    if cmdline.is_some_and(|cmdline| cmdline.test_list) {
+        for test in tests {
+            log::info!("{}: test", test.path);
+        }
+
+        log::info!("");
+        log::info!("{} tests", tests.len());
+        stop();
+    }
+
+    // Only the tests whose path matches the filter are run.
+    let filter = cmdline
+        .and_then(|cmdline| cmdline.test_filter)
+        .map(|filter| {
+            Regex::new(filter)
+                .unwrap_or_else(|error| panic!("invalid test filter {filter:?}: {error:?}"))
+        });
+
+    let selected = tests
+        .iter()
+        .filter(|test| {
+            filter
+                .as_ref()
+                .is_none_or(|filter| filter.is_match(test.path))
+        })
+        .collect::<Vec<_>>();
+
+    log::info!("running {} tests", selected.len());
+
+    let mut passed = 0usize;
+
+    for test in selected {
+        #[cfg(feature = "ci")]
+        emu::test_start(test.path);
+
+        (test.test_fn)();
+        log::info!("test {} ... ok", test.path);
+
+        #[cfg(feature = "ci")]
+        emu::test_ok();
+
+        passed += 1;
+    }
+
+    log::info!("");
+    log::info!(
+        "test result: ok. {} passed; 0 failed; 0 ignored; 0 measured; {} filtered out",
+        passed,
+        tests.len() - passed
+    );
+
+    // A failing test panics, so the run only gets here if all of them passed.
+    #[cfg(feature = "ci")]
+    emu::emit(emu::Event::RunSummary {
+        passed,
+        failed: 0,
+        filtered: tests.len() - passed,
+    });
+
+    // A filtered run is about the kernel tests, so the userland tests are skipped.
+    if filter.is_some() {
+        stop();
+    }
+}
+
+/// Ends the test run without running the userland tests.
+fn stop() -> ! {
+    #[cfg(feature = "ci")]
+    emu::finish(true);
+
+    #[cfg(not(feature = "ci"))]
+    loop {
+        unsafe { crate::arch::interrupts::halt() }
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Test filter regular expression tests.
+
+use crate::utils::regex::{Error, Regex};
+
+fn is_match(pattern: &str, text: &str) -> bool {
+    Regex::new(pattern).unwrap().is_match(text)
+}
+
+#[test]
+fn regex_match() {
+    // The pattern matches anywhere in the text, unless it is anchored.
+    assert!(is_match("zram", "aero_kernel::tests::zram::read"));
+    assert!(!is_match("^zram", "aero_kernel::tests::zram::read"));
+    assert!(is_match(
+        "zram::(read|write)$",
+        "aero_kernel::tests::zram::write"
+    ));
+    assert!(!is_match(
+        "zram::(read|write)$",
+        "aero_kernel::tests::zram::writes"
+    ));
+
+    assert!(is_match("^ab+c$", "abbc"));
+    assert!(!is_match("^ab?c$", "abbc"));
+    assert!(is_match("^[a-c_]+$", "ab_c"));
+    assert!(!is_match("^[^0-9]+$", "a1c"));
+    assert!(is_match("^\\w+\\s\\d$", "ab_c 4"));
+    assert!(!is_match("^a\\.b$", "axb"));
+
+    // Repeating something that matches the empty string terminates.
+    assert!(is_match("^(a|)*b$", "aab"));
+    assert!(!is_match("^(ab)*$", "aba"));
+}
+
+#[test]
+fn regex_syntax_errors() {
+    assert_eq!(Regex::new("(a").unwrap_err(), Error::UnmatchedParen);
+    assert_eq!(Regex::new("a)").unwrap_err(), Error::UnmatchedParen);
+    assert_eq!(Regex::new("[a").unwrap_err(), Error::UnclosedClass);
+    assert_eq!(Regex::new("a|*").unwrap_err(), Error::NothingToRepeat);
+    assert_eq!(Regex::new("a\\").unwrap_err(), Error::TrailingBackslash);
+}
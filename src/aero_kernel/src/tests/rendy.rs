@@ -0,0 +1,152 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Debug renderer tests.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::rendy::psf::{Font, PsfError};
+use crate::rendy::{self, CharAttributes, Character};
+
+/// Renders every line of `lines` with the default font into a buffer and returns it along with
+/// its stride.
+fn render(lines: &[(&str, CharAttributes)]) -> (Vec<u32>, usize) {
+    let font = rendy::default_font();
+    let cols = lines
+        .iter()
+        .map(|(text, _)| text.chars().count())
+        .max()
+        .unwrap();
+    let stride = cols * font.width();
+    let mut pixels = vec![0x123456; stride * font.height() * lines.len()];
+
+    for (row, (text, attrs)) in lines.iter().enumerate() {
+        for (col, char) in text.chars().enumerate() {
+            let char = Character {
+                char,
+                fg: 0xc5c8c6,
+                bg: 0x1d1f21,
+                attrs: *attrs,
+            };
+
+            let offset = row * font.height() * stride + col * font.width();
+            rendy::rasterize(&font, &char, &mut pixels[offset..], stride);
+        }
+    }
+
+    (pixels, stride)
+}
+
+fn checksum(pixels: &[u32]) -> u64 {
+    // FNV-1a
+    pixels.iter().fold(0xcbf29ce484222325, |hash, &pixel| {
+        (hash ^ pixel as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[test]
+fn rendy_rasterize() {
+    let (pixels, _) = render(&[
+        ("Aero 0.1", CharAttributes::empty()),
+        ("Aero 0.1", CharAttributes::BOLD),
+        ("Aero 0.1", CharAttributes::DIM),
+    ]);
+
+    assert_eq!(checksum(&pixels), 0xe81fae967b7cae5d);
+}
+
+#[test]
+fn rendy_replacement_glyph() {
+    // Characters without a glyph are drawn like `?` instead of indexing out of the font.
+    let (pixels, stride) = render(&[("?é\u{1f980}", CharAttributes::empty())]);
+    let font = rendy::default_font();
+
+    for line in pixels.chunks(stride).take(font.height()) {
+        let (question, rest) = line.split_at(font.width());
+
+        assert_eq!(question, &rest[..font.width()]);
+        assert_eq!(question, &rest[font.width()..]);
+    }
+}
+
+/// Builds a PSF2 font with 10x2 glyphs for `?`, `é` and U+FFFD. The glyph of `é` has its first
+/// line set.
+fn psf2_font() -> Vec<u8> {
+    let header = [0x864ab572u32, 0, 32, 1, 3, 4, 2, 10];
+    let mut font: Vec<u8> = header
+        .iter()
+        .flat_map(|field| field.to_le_bytes())
+        .collect();
+
+    font.extend_from_slice(&[0, 0, 0, 0]);
+    font.extend_from_slice(&[0xff, 0xc0, 0, 0]);
+    font.extend_from_slice(&[0, 0, 0, 0]);
+
+    font.extend_from_slice(b"?\xff");
+    font.extend_from_slice("é".as_bytes());
+    font.push(0xfe);
+    font.extend_from_slice("e\u{301}".as_bytes());
+    font.push(0xff);
+    font.extend_from_slice("\u{fffd}".as_bytes());
+    font.push(0xff);
+
+    font
+}
+
+#[test]
+fn psf2_unicode_table() {
+    let data = psf2_font();
+    let font = Font::parse(&data).unwrap();
+
+    assert_eq!((font.width(), font.height()), (10, 2));
+    assert_eq!(font.glyph_index('?'), 0);
+    assert_eq!(font.glyph_index('é'), 1);
+    assert_eq!(font.glyph_index('e'), 2);
+
+    assert!(font.is_set(1, 0, 0) && font.is_set(1, 9, 0));
+    assert!(!font.is_set(1, 0, 1));
+
+    assert_eq!(Font::parse(&data[..40]).err(), Some(PsfError::Truncated));
+    assert_eq!(Font::parse(&data[4..]).err(), Some(PsfError::InvalidMagic));
+}
+
+#[test]
+fn psf1_unicode_table() {
+    // A PSF1 font with 256 glyphs of 8x1 and a unicode table.
+    let mut data = vec![0x36, 0x04, 0x02, 1];
+
+    data.extend(0..=255u8);
+
+    for glyph in 0..=255u8 {
+        match glyph {
+            b'?' => data.extend_from_slice(&u16::from(b'?').to_le_bytes()),
+            0x82 => data.extend_from_slice(&u16::from(b'\xe9').to_le_bytes()),
+            _ => {}
+        }
+
+        data.extend_from_slice(&[0xff, 0xff]);
+    }
+
+    let font = Font::parse(&data).unwrap();
+
+    assert_eq!((font.width(), font.height()), (8, 1));
+    assert_eq!(font.glyph_index('é'), 0x82);
+    assert_eq!(font.glyph_index('A'), b'?' as usize);
+
+    assert_eq!(Font::parse(&data[..200]).err(), Some(PsfError::Truncated));
+}
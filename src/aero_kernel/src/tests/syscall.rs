@@ -0,0 +1,61 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Syscall table tests.
+
+use aero_syscall::prelude::*;
+
+use crate::syscall;
+
+/// Asserts that the syscall `number` is handled by `name`, which decodes its arguments from the
+/// first `args` registers.
+fn assert_entry(number: usize, name: &str, args: usize) {
+    let entry = syscall::lookup(number).unwrap();
+
+    assert_eq!(entry.number, number);
+    assert_eq!(entry.name, name);
+    assert_eq!(entry.args, args);
+}
+
+#[test]
+fn syscall_table_decoding() {
+    assert_entry(SYS_GETPID, "getpid", 0);
+    assert_entry(SYS_EXIT, "exit", 1);
+
+    // Slices and strings take a pointer and a length register.
+    assert_entry(SYS_READ, "read", 3);
+    assert_entry(SYS_OPEN, "open", 5);
+    assert_entry(SYS_IPC_SEND, "send", 3);
+
+    assert_entry(SYS_MMAP, "mmap", 6);
+
+    // Used to be passed the syscall number as its first argument.
+    assert_entry(SYS_SETSOCKOPT, "setopt", 5);
+}
+
+#[test]
+fn syscall_table_unknown() {
+    // Aliases are not in the table.
+    assert!(syscall::lookup(SYS_MKDIR).is_none());
+    assert!(syscall::lookup(usize::MAX).is_none());
+
+    let result = syscall::generic_do_syscall(usize::MAX, 0, 0, 0, 0, 0, 0);
+    assert_eq!(
+        aero_syscall::isize_as_syscall_result(result as isize),
+        Err(SyscallError::ENOSYS)
+    );
+}
@@ -0,0 +1,158 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Kernel timer tests.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Once;
+
+use crate::timer::{self, TimerId, NSEC_PER_MSEC};
+use crate::userland::scheduler;
+
+/// Sleeps until `deadline` has passed.
+fn sleep_until(deadline: u64) {
+    while timer::now() < deadline {
+        let _ = scheduler::get_scheduler().inner.sleep(Some(deadline));
+    }
+}
+
+#[test]
+fn timer_never_fires_early() {
+    const TIMERS: u64 = 64;
+
+    let fired = Arc::new(AtomicUsize::new(0));
+    let early = Arc::new(AtomicUsize::new(0));
+    let start = timer::now();
+
+    for i in 0..TIMERS {
+        let deadline = start + i * NSEC_PER_MSEC / 4;
+        let (fired, early) = (fired.clone(), early.clone());
+
+        timer::add_timer(deadline, move || {
+            if timer::now() < deadline {
+                early.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fired.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    sleep_until(start + TIMERS * NSEC_PER_MSEC / 4 + 10 * NSEC_PER_MSEC);
+
+    assert_eq!(fired.load(Ordering::SeqCst), TIMERS as usize);
+    assert_eq!(early.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn timer_sleep() {
+    let start = timer::now();
+    let deadline = start + 3 * NSEC_PER_MSEC;
+
+    sleep_until(deadline);
+    assert!(timer::now() >= deadline);
+}
+
+#[test]
+fn timer_cancel() {
+    let fired = Arc::new(AtomicBool::new(false));
+    let id = timer::add_timer(timer::deadline_after(5 * NSEC_PER_MSEC), {
+        let fired = fired.clone();
+        move || fired.store(true, Ordering::SeqCst)
+    });
+
+    assert!(timer::cancel(id));
+    assert!(!timer::cancel(id));
+
+    sleep_until(timer::deadline_after(10 * NSEC_PER_MSEC));
+    assert!(!fired.load(Ordering::SeqCst));
+}
+
+/// A callback cancelling its own timer must not wait for itself to complete.
+#[test]
+fn timer_cancel_while_firing() {
+    let id = Arc::new(Once::<TimerId>::new());
+    let cancelled = Arc::new(AtomicUsize::new(0));
+    let fired = Arc::new(AtomicBool::new(false));
+
+    let timer = timer::add_timer(timer::deadline_after(10 * NSEC_PER_MSEC), {
+        let (id, cancelled, fired) = (id.clone(), cancelled.clone(), fired.clone());
+
+        move || {
+            if timer::cancel(*id.get().unwrap()) {
+                cancelled.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fired.store(true, Ordering::SeqCst);
+        }
+    });
+
+    id.call_once(|| timer);
+    sleep_until(timer::deadline_after(20 * NSEC_PER_MSEC));
+
+    assert!(fired.load(Ordering::SeqCst));
+    assert_eq!(cancelled.load(Ordering::SeqCst), 0);
+    assert!(!timer::cancel(timer));
+}
+
+/// Adds thousands of timers in a random order and cancels a third of them. The others have to
+/// fire exactly once, in the order of their deadlines.
+#[test]
+fn timer_stress() {
+    const TIMERS: usize = 10_000;
+    const SPREAD: u64 = 50 * NSEC_PER_MSEC;
+
+    let fired = Arc::new(AtomicUsize::new(0));
+    let out_of_order = Arc::new(AtomicUsize::new(0));
+    let last_deadline = Arc::new(AtomicU64::new(0));
+
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let start = timer::now();
+    let mut ids = Vec::with_capacity(TIMERS);
+
+    for _ in 0..TIMERS {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+
+        // Leave time for all of the timers to be added before the first one fires.
+        let deadline = start + 20 * NSEC_PER_MSEC + seed % SPREAD;
+        let (fired, out_of_order, last_deadline) =
+            (fired.clone(), out_of_order.clone(), last_deadline.clone());
+
+        ids.push(timer::add_timer(deadline, move || {
+            if last_deadline.swap(deadline, Ordering::SeqCst) > deadline {
+                out_of_order.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fired.fetch_add(1, Ordering::SeqCst);
+        }));
+    }
+
+    let cancelled = ids
+        .iter()
+        .step_by(3)
+        .filter(|id| timer::cancel(**id))
+        .count();
+
+    sleep_until(start + 20 * NSEC_PER_MSEC + SPREAD + 10 * NSEC_PER_MSEC);
+
+    assert_eq!(fired.load(Ordering::SeqCst), TIMERS - cancelled);
+    assert_eq!(out_of_order.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,218 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Line discipline tests.
+
+use aero_syscall::signal::SIGINT;
+use aero_syscall::{
+    Termios, TermiosCFlag, TermiosIFlag, TermiosLFlag, TermiosOFlag, VEOF, VERASE, VINTR, VKILL,
+};
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::drivers::tty::line_discipline::{self, Action, LineDiscipline};
+
+const ERASE: u8 = 0x7f;
+const KILL: u8 = b'U' - 0x40;
+const EOF: u8 = b'D' - 0x40;
+const INTR: u8 = b'C' - 0x40;
+
+fn termios(iflag: TermiosIFlag, oflag: TermiosOFlag, lflag: TermiosLFlag) -> Termios {
+    let mut termios = Termios {
+        c_iflag: iflag,
+        c_oflag: oflag,
+        c_cflag: TermiosCFlag::CREAD,
+        c_lflag: lflag,
+        c_line: 0,
+        c_cc: [0; 32],
+        c_ispeed: 0,
+        c_ospeed: 0,
+    };
+
+    termios.c_cc[VERASE] = ERASE;
+    termios.c_cc[VKILL] = KILL;
+    termios.c_cc[VEOF] = EOF;
+    termios.c_cc[VINTR] = INTR;
+    termios
+}
+
+fn canonical() -> LineDiscipline {
+    LineDiscipline::new(termios(
+        TermiosIFlag::ICRNL,
+        TermiosOFlag::OPOST | TermiosOFlag::ONLCR,
+        TermiosLFlag::ICANON | TermiosLFlag::ECHO | TermiosLFlag::ECHOE | TermiosLFlag::ISIG,
+    ))
+}
+
+/// Feeds `input` to the line discipline and returns the actions, merging consecutive echoes.
+fn input(discipline: &mut LineDiscipline, input: &[u8]) -> Vec<Action> {
+    let mut actions: Vec<Action> = vec![];
+
+    for &byte in input {
+        for action in discipline.input_byte(byte) {
+            match (actions.last_mut(), action) {
+                (Some(Action::Echo(echo)), Action::Echo(bytes)) => echo.extend_from_slice(&bytes),
+                (_, action) => actions.push(action),
+            }
+        }
+    }
+
+    actions
+}
+
+fn read(discipline: &mut LineDiscipline) -> Vec<u8> {
+    let mut buffer = [0; 64];
+    let size = discipline.read(&mut buffer);
+
+    buffer[..size].to_vec()
+}
+
+#[test]
+fn ldisc_canonical_erase() {
+    let mut discipline = canonical();
+
+    let actions = input(&mut discipline, &[b'a', b'b', ERASE, ERASE, ERASE, ERASE]);
+
+    // Erasing past the start of the line does nothing.
+    assert_eq!(actions, [Action::Echo(b"ab\x08 \x08\x08 \x08".to_vec())]);
+    assert!(!discipline.is_ready());
+
+    input(&mut discipline, b"cd\n");
+    assert!(discipline.is_ready());
+    assert_eq!(read(&mut discipline), b"cd\n");
+    assert!(!discipline.is_ready());
+}
+
+#[test]
+fn ldisc_canonical_kill() {
+    let mut discipline = canonical();
+
+    let actions = input(&mut discipline, &[b'a', b'b', b'c', KILL]);
+    let mut echo = b"abc".to_vec();
+    echo.extend_from_slice(&b"\x08 \x08".repeat(3));
+
+    assert_eq!(actions, [Action::Echo(echo)]);
+
+    let actions = input(&mut discipline, b"xy\n");
+
+    assert_eq!(
+        actions,
+        [Action::Echo(b"xy\n".to_vec()), Action::DeliverLine]
+    );
+    assert_eq!(read(&mut discipline), b"xy\n");
+}
+
+#[test]
+fn ldisc_canonical_cursor() {
+    let mut discipline = canonical();
+
+    input(&mut discipline, b"ac");
+    assert_eq!(discipline.move_cursor(-1), Some(Action::MoveCursor(-1)));
+    assert_eq!(discipline.move_cursor(-2), None);
+
+    // Typing in the middle of the line redraws the rest of it.
+    let actions = input(&mut discipline, b"b");
+    assert_eq!(
+        actions,
+        [Action::Echo(b"bc".to_vec()), Action::MoveCursor(-1)]
+    );
+
+    input(&mut discipline, &[ERASE, b'B', b'\n']);
+    assert_eq!(read(&mut discipline), b"aBc\n");
+}
+
+#[test]
+fn ldisc_canonical_eof() {
+    let mut discipline = canonical();
+
+    // At the start of a line, the end of file character makes the read return zero bytes.
+    assert_eq!(input(&mut discipline, &[EOF]), [Action::DeliverLine]);
+    assert!(discipline.is_ready());
+    assert_eq!(read(&mut discipline), b"");
+    assert!(!discipline.is_ready());
+
+    // Otherwise, it delivers the line without a newline.
+    input(&mut discipline, &[b'h', b'i', EOF, EOF]);
+    assert_eq!(read(&mut discipline), b"hi");
+    assert_eq!(read(&mut discipline), b"");
+
+    // A read returns at most one line.
+    input(&mut discipline, b"one\ntwo\n");
+    assert_eq!(read(&mut discipline), b"one\n");
+    assert_eq!(read(&mut discipline), b"two\n");
+}
+
+#[test]
+fn ldisc_signals() {
+    let mut discipline = canonical();
+
+    let actions = input(&mut discipline, &[b'a', INTR]);
+
+    assert_eq!(
+        actions,
+        [Action::Echo(vec![b'a', INTR]), Action::Signal(SIGINT)]
+    );
+
+    // The pending input is discarded.
+    input(&mut discipline, b"b\n");
+    assert_eq!(read(&mut discipline), b"b\n");
+}
+
+#[test]
+fn ldisc_newline_translation() {
+    let mut discipline = canonical();
+
+    // ICRNL
+    let actions = input(&mut discipline, b"a\r");
+    assert_eq!(
+        actions,
+        [Action::Echo(b"a\n".to_vec()), Action::DeliverLine]
+    );
+    assert_eq!(read(&mut discipline), b"a\n");
+
+    // ONLCR
+    assert_eq!(discipline.output_bytes(b"a\nb\n"), b"a\r\nb\r\n");
+
+    let mut termios = discipline.termios().clone();
+    termios.c_iflag.remove(TermiosIFlag::ICRNL);
+    termios.c_oflag.remove(TermiosOFlag::OPOST);
+    discipline.set_termios(termios.clone());
+
+    input(&mut discipline, b"a\r\n");
+    assert_eq!(read(&mut discipline), b"a\r\n");
+
+    // ONLCR is only applied if OPOST is set.
+    assert_eq!(discipline.output_bytes(b"a\n"), b"a\n");
+    assert_eq!(line_discipline::process_output(&termios, b"a\n"), b"a\n");
+}
+
+#[test]
+fn ldisc_raw() {
+    let mut discipline = LineDiscipline::new(termios(
+        TermiosIFlag::empty(),
+        TermiosOFlag::empty(),
+        TermiosLFlag::empty(),
+    ));
+
+    // Every byte is delivered right away, including the special characters.
+    assert_eq!(input(&mut discipline, b"a"), [Action::DeliverLine]);
+    input(&mut discipline, &[ERASE, EOF, b'\r']);
+
+    assert_eq!(read(&mut discipline), [b'a', ERASE, EOF, b'\r']);
+    assert!(!discipline.is_ready());
+}
@@ -0,0 +1,183 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Compressed RAM block device tests.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::drivers::block::zram::{lz4, Zram};
+use crate::fs::block::BlockDeviceInterface;
+use crate::mem::paging::*;
+
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
+/// Size of the devices created by the tests (4 MiB).
+const SIZE: usize = 4 << 20;
+
+/// Fills `page` with its index followed by repeated text, which compresses well.
+fn compressible_page(index: usize, page: &mut [u8]) {
+    for (i, byte) in page.iter_mut().enumerate() {
+        *byte = b"zram page "[i % 10].wrapping_add(index as u8 % 16);
+    }
+
+    page[..8].copy_from_slice(&index.to_le_bytes());
+}
+
+fn random_bytes(seed: &mut u64, buffer: &mut [u8]) {
+    for byte in buffer {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *byte = *seed as u8;
+    }
+}
+
+#[test]
+fn zram_compressible_round_trip() {
+    let zram = Zram::new(SIZE);
+    let mut page = vec![0; PAGE_SIZE];
+
+    for index in 0..SIZE / PAGE_SIZE {
+        compressible_page(index, &mut page);
+        assert_eq!(zram.write(index * PAGE_SIZE, &page), Some(PAGE_SIZE));
+    }
+
+    let mut expected = vec![0; PAGE_SIZE];
+
+    for index in 0..SIZE / PAGE_SIZE {
+        compressible_page(index, &mut expected);
+        assert_eq!(zram.read(index * PAGE_SIZE, &mut page), Some(PAGE_SIZE));
+        assert_eq!(page, expected);
+    }
+
+    let stats = zram.stats();
+    log::debug!("zram: compressible {stats:?}");
+
+    assert_eq!(stats.orig_data_size, SIZE);
+    assert_eq!(stats.same_pages, 0);
+    assert!(stats.mem_used_total < SIZE / 8);
+}
+
+#[test]
+fn zram_incompressible_round_trip() {
+    let zram = Zram::new(SIZE);
+    let pages = 64;
+
+    let mut data = vec![0; pages * PAGE_SIZE];
+    random_bytes(&mut 0x2545f4914f6cdd1d, &mut data);
+
+    // Write through the block interface, a sector at a time for the first page.
+    for sector in 0..PAGE_SIZE / 512 {
+        let offset = sector * 512;
+        assert_eq!(
+            zram.write_block(sector, &data[offset..offset + 512]),
+            Some(512)
+        );
+    }
+
+    assert_eq!(
+        zram.write(PAGE_SIZE, &data[PAGE_SIZE..]),
+        Some(data.len() - PAGE_SIZE)
+    );
+
+    let mut buffer = vec![0; data.len()];
+    assert_eq!(zram.read(0, &mut buffer), Some(data.len()));
+    assert_eq!(buffer, data);
+
+    let stats = zram.stats();
+    log::debug!("zram: incompressible {stats:?}");
+
+    // Pages that do not compress are stored as-is.
+    assert_eq!(stats.orig_data_size, data.len());
+    assert_eq!(stats.compr_data_size, data.len());
+}
+
+#[test]
+fn zram_same_filled_pages() {
+    let zram = Zram::new(SIZE);
+
+    let zeroes = vec![0; PAGE_SIZE];
+    let filled = vec![0xaa; PAGE_SIZE];
+
+    for index in 0..16 {
+        let page = if index % 2 == 0 { &zeroes } else { &filled };
+        zram.write(index * PAGE_SIZE, page).unwrap();
+    }
+
+    let stats = zram.stats();
+
+    assert_eq!(stats.same_pages, 16);
+    assert_eq!(stats.compr_data_size, 0);
+
+    // A write across a page boundary updates both pages and keeps the rest of them.
+    zram.write(PAGE_SIZE - 3, b"across").unwrap();
+
+    let mut buffer = vec![0; 2 * PAGE_SIZE];
+    zram.read(0, &mut buffer).unwrap();
+
+    assert_eq!(&buffer[PAGE_SIZE - 3..PAGE_SIZE + 3], b"across");
+    assert!(buffer[..PAGE_SIZE - 3].iter().all(|&byte| byte == 0));
+    assert!(buffer[PAGE_SIZE + 3..].iter().all(|&byte| byte == 0xaa));
+    assert_eq!(zram.stats().same_pages, 14);
+
+    // Pages that have never been written read as zeroes.
+    let mut page = vec![0xff; PAGE_SIZE];
+    zram.read(SIZE - PAGE_SIZE, &mut page).unwrap();
+    assert_eq!(page, zeroes);
+
+    // Accesses past the end of the device fail.
+    assert_eq!(zram.read(SIZE - 1, &mut page), None);
+    assert_eq!(zram.write(SIZE, &page[..1]), None);
+}
+
+#[test]
+fn lz4_round_trip() {
+    let mut seed = 0x9e3779b97f4a7c15;
+    let mut inputs: Vec<Vec<u8>> = vec![vec![], vec![7], b"short input".to_vec()];
+
+    // Runs longer than what fits in a token, with literals in between.
+    let mut runs = vec![0; 3000];
+    runs[1000..1017].fill(1);
+    runs[2000] = 2;
+    inputs.push(runs);
+
+    let mut random = vec![0; PAGE_SIZE];
+    random_bytes(&mut seed, &mut random);
+    inputs.push(random);
+
+    let mut page = vec![0; PAGE_SIZE];
+    compressible_page(3, &mut page);
+    inputs.push(page);
+
+    for input in inputs {
+        let compressed = lz4::compress(&input);
+        let mut output = vec![0; PAGE_SIZE];
+
+        assert_eq!(lz4::decompress(&compressed, &mut output), Some(input.len()));
+        assert_eq!(&output[..input.len()], &input[..]);
+    }
+
+    // Malformed input is rejected rather than read or written out of bounds.
+    let mut output = [0; 16];
+
+    assert_eq!(lz4::decompress(&[], &mut output), None);
+    assert_eq!(
+        lz4::decompress(&[0x10, b'a', 0x05, 0x00], &mut output),
+        None
+    );
+    assert_eq!(lz4::decompress(&[0xf0, 0xff], &mut output), None);
+}
@@ -0,0 +1,234 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Kernel timers, which run a callback once an absolute deadline on the monotonic clock (see
+//! [`now`]) has passed.
+//!
+//! The pending timers are kept in a min-heap ordered by deadline, so adding one costs
+//! `O(log n)`. Cancelling a timer only removes its callback; its heap entry is discarded once it
+//! reaches the top, or when cancelled entries make up most of the heap.
+//!
+//! The local APIC timer is armed for the nearest deadline when the CPU supports the TSC-deadline
+//! mode. Otherwise, it ticks every [`FALLBACK_PERIOD_US`] microseconds and the expired timers are
+//! looked for on each tick.
+//!
+//! Callbacks run in interrupt context, with the timer table unlocked: they may add or cancel
+//! timers, but must not block.
+
+use core::cmp::Reverse;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use hashbrown::HashMap;
+use spin::Once;
+
+use crate::arch::interrupts::{self, InterruptStack};
+use crate::utils::sync::Mutex;
+
+/// Period of the timer interrupt when the TSC-deadline mode is not supported.
+const FALLBACK_PERIOD_US: usize = 1000;
+
+pub const NSEC_PER_MSEC: u64 = 1_000_000;
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// Identifies a timer returned by [`add_timer`], to [`cancel`] it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId(u64);
+
+struct Timers {
+    /// Deadlines of the timers, the nearest first. Cancelled timers are left behind until they
+    /// are popped or [`Timers::compact`] runs.
+    heap: BinaryHeap<Reverse<(u64, TimerId)>>,
+    /// Callbacks of the pending timers.
+    callbacks: HashMap<TimerId, Callback>,
+    /// Deadline the local APIC timer is armed for, in the TSC-deadline mode.
+    armed: Option<u64>,
+}
+
+impl Timers {
+    /// Discards the cancelled timers at the top of the heap and returns the nearest deadline.
+    fn next_deadline(&mut self) -> Option<u64> {
+        while let Some(Reverse((deadline, id))) = self.heap.peek() {
+            if self.callbacks.contains_key(id) {
+                return Some(*deadline);
+            }
+
+            self.heap.pop();
+        }
+
+        None
+    }
+
+    /// Removes the callback of the nearest timer whose deadline is at most `now`.
+    fn pop_expired(&mut self, now: u64) -> Option<Callback> {
+        while self.next_deadline()? <= now {
+            let Reverse((_, id)) = self.heap.pop().unwrap();
+
+            if let Some(callback) = self.callbacks.remove(&id) {
+                return Some(callback);
+            }
+        }
+
+        None
+    }
+
+    /// Drops the heap entries of the cancelled timers once they outnumber the pending ones, so
+    /// that timers which are always cancelled before their deadline do not pile up.
+    fn compact(&mut self) {
+        if self.heap.len() > 2 * self.callbacks.len() + 64 {
+            let callbacks = &self.callbacks;
+            self.heap
+                .retain(|Reverse((_, id))| callbacks.contains_key(id));
+        }
+    }
+
+    /// Arms the local APIC timer for the nearest deadline, if it is not already.
+    fn rearm(&mut self) {
+        if !TSC_DEADLINE.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let Some(deadline) = self.next_deadline() else {
+            return;
+        };
+
+        if self.armed.is_some_and(|armed| armed <= deadline) {
+            return;
+        }
+
+        self.armed = Some(deadline);
+
+        #[cfg(target_arch = "x86_64")]
+        crate::arch::apic::get_local_apic().timer_tsc_deadline(
+            *TIMER_VECTOR.get().unwrap(),
+            crate::arch::time::tsc_at(deadline),
+        );
+    }
+}
+
+static TIMERS: Once<Mutex<Timers>> = Once::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+static TIMER_VECTOR: Once<u8> = Once::new();
+/// Whether the local APIC timer is used in the TSC-deadline mode.
+static TSC_DEADLINE: AtomicBool = AtomicBool::new(false);
+
+/// Returns the table of timers; initializing it if necessary.
+fn timers() -> &'static Mutex<Timers> {
+    TIMERS.call_once(|| {
+        Mutex::new(Timers {
+            heap: BinaryHeap::new(),
+            callbacks: HashMap::new(),
+            armed: None,
+        })
+    })
+}
+
+/// Returns the current time of the monotonic clock the deadlines are expressed in, in
+/// nanoseconds.
+#[inline]
+pub fn now() -> u64 {
+    crate::arch::time::get_uptime_ns()
+}
+
+/// Returns the deadline `ns` nanoseconds from now.
+#[inline]
+pub fn deadline_after(ns: u64) -> u64 {
+    now().saturating_add(ns)
+}
+
+/// Runs `callback` once [`now`] reaches `deadline`, never before. A deadline that already
+/// passed fires on the next timer interrupt.
+///
+/// This function can be called both from process and interrupt context, including from another
+/// timer callback.
+pub fn add_timer(deadline: u64, callback: impl FnOnce() + Send + 'static) -> TimerId {
+    let id = TimerId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let mut timers = timers().lock_irq();
+
+    timers.heap.push(Reverse((deadline, id)));
+    timers.callbacks.insert(id, Box::new(callback));
+    timers.rearm();
+
+    id
+}
+
+/// Cancels the timer `id`. Returns `false` if its callback already ran or is running, in which
+/// case it is not waited for, so that a callback can cancel its own timer.
+pub fn cancel(id: TimerId) -> bool {
+    let mut timers = timers().lock_irq();
+    let callback = timers.callbacks.remove(&id);
+
+    timers.compact();
+    core::mem::drop(timers);
+
+    // The callback may own the last reference to a task, which should not be dropped with the
+    // table locked.
+    callback.is_some()
+}
+
+/// Runs the callbacks of the expired timers and arms the local APIC timer for the next one.
+fn run_expired() {
+    // Timers added by the callbacks with a deadline that passed in the meantime are run on the
+    // next interrupt, so that a callback re-adding itself cannot keep us here.
+    let now = now();
+
+    loop {
+        let Some(callback) = timers().lock_irq().pop_expired(now) else {
+            break;
+        };
+
+        callback();
+    }
+
+    let mut timers = timers().lock_irq();
+    timers.armed = None;
+    timers.rearm();
+}
+
+fn timer_irq_handler(stack: &mut InterruptStack) {
+    #[cfg(target_arch = "x86_64")]
+    crate::arch::interrupts::INTERRUPT_CONTROLLER.eoi();
+
+    run_expired();
+
+    // Preemption is left for last, as the interrupted task may not run again for a while.
+    crate::userland::scheduler::preempt_if_requested(stack);
+}
+
+/// Sets up the timer interrupt. The local APIC timer has to be calibrated before.
+pub fn init() {
+    let vector = interrupts::allocate_vector();
+    interrupts::register_handler(vector, timer_irq_handler);
+    TIMER_VECTOR.call_once(|| vector);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        use crate::arch::apic;
+
+        if apic::has_tsc_deadline() {
+            TSC_DEADLINE.store(true, Ordering::Relaxed);
+            timers().lock_irq().rearm();
+        } else {
+            log::warn!("timer: TSC-deadline mode is not supported, falling back to periodic ticks");
+            apic::get_local_apic().timer_periodic(vector, FALLBACK_PERIOD_US);
+        }
+    }
+}
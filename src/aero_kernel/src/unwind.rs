@@ -20,7 +20,7 @@ use core::panic::PanicInfo;
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use xmas_elf::sections::{SectionData, ShType};
-use xmas_elf::symbol_table::Entry;
+use xmas_elf::symbol_table::{Entry, Entry64};
 use xmas_elf::ElfFile;
 
 use crate::mem::paging::{Translate, VirtAddr};
@@ -53,9 +53,11 @@ pub fn prepare_panic() {
     }
 
     // Clear the screen if the rendy is initialized and enable
-    // rendy debug in logger.
+    // rendy debug in logger. Take the display back from any DRM
+    // client, so that the panic message is visible.
     if rendy::is_initialized() {
         rendy::clear_screen(true);
+        rendy::resume();
         logger::set_rendy_debug(true);
     }
 }
@@ -70,13 +72,7 @@ impl UnwindInfo {
     }
 }
 
-pub fn unwind_stack_trace() {
-    let _guard = IrqGuard::new();
-
-    let mut address_space = AddressSpace::this();
-    let offset_table = address_space.offset_page_table();
-
-    let kernel_elf = &UNWIND_INFO.get().unwrap().kernel_elf;
+fn kernel_symbol_table(kernel_elf: &ElfFile<'static>) -> Option<&'static [Entry64]> {
     let mut symbol_table = None;
 
     for section in kernel_elf.section_iter() {
@@ -91,7 +87,110 @@ pub fn unwind_stack_trace() {
         }
     }
 
-    let symbol_table = symbol_table.unwrap();
+    symbol_table
+}
+
+fn symbol_name(
+    kernel_elf: &ElfFile<'static>,
+    symbol_table: &[Entry64],
+    rip: usize,
+) -> Option<rustc_demangle::Demangle<'static>> {
+    let mut name = None;
+
+    for data in symbol_table {
+        let st_value = data.value() as usize;
+        let st_size = data.size() as usize;
+
+        if rip >= st_value && rip < (st_value + st_size) {
+            let mangled_name = data.get_name(kernel_elf).unwrap_or("<unknown>");
+            let demangled_name = rustc_demangle::demangle(mangled_name);
+
+            name = Some(demangled_name);
+        }
+    }
+
+    name
+}
+
+/// Returns the demangled name of the kernel symbol containing `rip`.
+pub fn resolve_symbol(rip: usize) -> Option<rustc_demangle::Demangle<'static>> {
+    let kernel_elf = &UNWIND_INFO.get()?.kernel_elf;
+    let symbol_table = kernel_symbol_table(kernel_elf)?;
+
+    symbol_name(kernel_elf, symbol_table, rip)
+}
+
+/// Walks the frame pointer chain of the current stack and stores the return addresses into
+/// `frames`. Returns the number of frames stored.
+pub fn capture_stack_trace(frames: &mut [usize]) -> usize {
+    let mut address_space = AddressSpace::this();
+    let offset_table = address_space.offset_page_table();
+
+    let mut rbp: usize;
+
+    unsafe {
+        asm!("mov {}, rbp", out(reg) rbp);
+    }
+
+    let mut len = 0;
+
+    while len < frames.len() && rbp != 0 {
+        let Some(rip_rbp) = rbp.checked_add(core::mem::size_of::<usize>()) else {
+            break;
+        };
+
+        let rip_rbp = VirtAddr::new(rip_rbp as u64);
+
+        if offset_table.translate_addr(rip_rbp).is_none() || !rip_rbp.is_canonical() {
+            break;
+        }
+
+        let rip = unsafe { *(rip_rbp.as_ptr::<usize>()) };
+
+        if rip == 0 {
+            break;
+        }
+
+        unsafe {
+            rbp = *(rbp as *const usize);
+        }
+
+        frames[len] = rip;
+        len += 1;
+    }
+
+    len
+}
+
+/// Logs a stack trace captured with [`capture_stack_trace`] at the warning level.
+pub fn log_stack_trace(frames: &[usize]) {
+    let Some(unwind_info) = UNWIND_INFO.get() else {
+        for (depth, rip) in frames.iter().enumerate() {
+            log::warn!("{depth:>2}: 0x{rip:016x} - <unknown>");
+        }
+
+        return;
+    };
+
+    let kernel_elf = &unwind_info.kernel_elf;
+    let symbol_table = kernel_symbol_table(kernel_elf).unwrap_or(&[]);
+
+    for (depth, &rip) in frames.iter().enumerate() {
+        match symbol_name(kernel_elf, symbol_table, rip) {
+            Some(name) => log::warn!("{depth:>2}: 0x{rip:016x} - {name}"),
+            None => log::warn!("{depth:>2}: 0x{rip:016x} - <unknown>"),
+        }
+    }
+}
+
+pub fn unwind_stack_trace() {
+    let _guard = IrqGuard::new();
+
+    let mut address_space = AddressSpace::this();
+    let offset_table = address_space.offset_page_table();
+
+    let kernel_elf = &UNWIND_INFO.get().unwrap().kernel_elf;
+    let symbol_table = kernel_symbol_table(kernel_elf).unwrap();
     let mut rbp: usize;
 
     unsafe {
@@ -129,21 +228,7 @@ pub fn unwind_stack_trace() {
                 rbp = *(rbp as *const usize);
             }
 
-            let mut name = None;
-
-            for data in symbol_table {
-                let st_value = data.value() as usize;
-                let st_size = data.size() as usize;
-
-                if rip >= st_value && rip < (st_value + st_size) {
-                    let mangled_name = data.get_name(kernel_elf).unwrap_or("<unknown>");
-                    let demangled_name = rustc_demangle::demangle(mangled_name);
-
-                    name = Some(demangled_name);
-                }
-            }
-
-            if let Some(name) = name {
+            if let Some(name) = symbol_name(kernel_elf, symbol_table, rip) {
                 log::trace!("{:>2}: 0x{:016x} - {}", depth, rip, name);
             } else if scheduler::is_initialized() {
                 if let Some((region, tag)) = scheduler::current_thread()
@@ -203,7 +288,7 @@ fn rust_begin_unwind(info: &PanicInfo) -> ! {
     unwind_stack_trace();
 
     #[cfg(feature = "ci")]
-    emu::exit_qemu(emu::ExitStatus::Success);
+    emu::panicked(&message);
 
     #[cfg(not(feature = "ci"))]
     unsafe {
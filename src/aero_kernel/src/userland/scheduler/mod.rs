@@ -18,11 +18,14 @@
 #[cfg(feature = "round-robin")]
 pub mod round_robin;
 
+use core::sync::atomic::{AtomicBool, AtomicI8, AtomicU64, Ordering};
+
 use alloc::sync::Arc;
 
-use crate::arch::interrupts::{self, InterruptStack};
+use crate::arch::interrupts::InterruptStack;
 use crate::fs::cache::DirCacheItem;
 use crate::syscall::ExecArgs;
+use crate::timer;
 use crate::utils::sync::Mutex;
 
 use spin::Once;
@@ -50,7 +53,11 @@ pub trait SchedulerInterface: Send + Sync {
     fn wake_up(&self, task: Arc<Task>);
 
     fn await_io(&self) -> SignalResult<()>;
-    fn sleep(&self, duration: Option<usize>) -> SignalResult<()>;
+
+    /// Blocks the current task until it is woken up or, if a `deadline` on the monotonic clock
+    /// of the [timers](crate::timer) is given, it passes. The task may be woken up early, so
+    /// callers have to check the deadline themselves.
+    fn sleep(&self, deadline: Option<u64>) -> SignalResult<()>;
 
     /// Yields execution to another task.
     fn preempt(&self);
@@ -59,6 +66,144 @@ pub trait SchedulerInterface: Send + Sync {
     fn exit(&self, status: ExitStatus) -> !;
 }
 
+/// Lowest (most favourable) nice value.
+pub const NICE_MIN: isize = -20;
+/// Highest (least favourable) nice value.
+pub const NICE_MAX: isize = 19;
+
+/// Scheduling weight of a nice 0 task.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// Scheduling weight of each nice value, starting at [`NICE_MIN`]. Each step is about 1.25x,
+/// which makes a task lose about 10% of the CPU time to a competing task one nice level lower
+/// (the same table as Linux's CFS).
+#[rustfmt::skip]
+const NICE_TO_WEIGHT: [u64; 40] = [
+    /* -20 */ 88761, 71755, 56483, 46273, 36291,
+    /* -15 */ 29154, 23254, 18705, 14949, 11916,
+    /* -10 */ 9548, 7620, 6100, 4904, 3906,
+    /*  -5 */ 3121, 2501, 1991, 1586, 1277,
+    /*   0 */ 1024, 820, 655, 526, 423,
+    /*   5 */ 335, 272, 215, 172, 137,
+    /*  10 */ 110, 87, 70, 56, 45,
+    /*  15 */ 36, 29, 23, 18, 15,
+];
+
+/// Per-task scheduling state and CPU time accounting.
+#[derive(Default)]
+pub struct SchedEntity {
+    nice: AtomicI8,
+    /// Consumed CPU time in nanoseconds, scaled by the inverse of the task's weight. The runnable
+    /// task with the lowest virtual runtime is picked to run next.
+    vruntime: AtomicU64,
+    /// CPU time spent in userland, in microseconds.
+    utime: AtomicU64,
+    /// CPU time spent in the kernel, in microseconds.
+    stime: AtomicU64,
+    /// CPU time spent in userland by the children that have been waited for, in microseconds.
+    cutime: AtomicU64,
+    /// CPU time spent in the kernel by the children that have been waited for, in microseconds.
+    cstime: AtomicU64,
+    /// Number of page faults resolved for the task.
+    minflt: AtomicU64,
+    /// Number of page faults resolved for the children that have been waited for.
+    cminflt: AtomicU64,
+}
+
+impl SchedEntity {
+    /// Returns the scheduling state of a child forked from the task that owns `self`. The
+    /// nice value is inherited.
+    pub fn fork(&self) -> Self {
+        Self::with_nice(self.nice())
+    }
+
+    fn with_nice(nice: isize) -> Self {
+        Self {
+            nice: AtomicI8::new(nice as i8),
+            ..Default::default()
+        }
+    }
+
+    pub fn nice(&self) -> isize {
+        self.nice.load(Ordering::Relaxed) as isize
+    }
+
+    /// Sets the nice value, clamped to the [`NICE_MIN`]..=[`NICE_MAX`] range.
+    pub fn set_nice(&self, nice: isize) {
+        let nice = nice.clamp(NICE_MIN, NICE_MAX);
+        self.nice.store(nice as i8, Ordering::Relaxed);
+    }
+
+    fn weight(&self) -> u64 {
+        NICE_TO_WEIGHT[(self.nice() - NICE_MIN) as usize]
+    }
+
+    pub fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    /// Moves the virtual runtime forward to at least `min`, so that a task that has been
+    /// sleeping or was just created does not monopolize the CPU to catch up.
+    fn place(&self, min: u64) {
+        self.vruntime.fetch_max(min, Ordering::Relaxed);
+    }
+
+    /// Charges `us` microseconds of CPU time to the task.
+    fn charge(&self, us: u64, user: bool) {
+        let vruntime = us * 1000 * NICE_0_WEIGHT / self.weight();
+        self.vruntime.fetch_add(vruntime, Ordering::Relaxed);
+
+        if user {
+            self.utime.fetch_add(us, Ordering::Relaxed);
+        } else {
+            self.stime.fetch_add(us, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the CPU time spent in userland and in the kernel, in microseconds.
+    pub fn cpu_times(&self) -> (u64, u64) {
+        (
+            self.utime.load(Ordering::Relaxed),
+            self.stime.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Returns the CPU time spent in userland and in the kernel by the children that have been
+    /// waited for, in microseconds.
+    pub fn children_cpu_times(&self) -> (u64, u64) {
+        (
+            self.cutime.load(Ordering::Relaxed),
+            self.cstime.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Records a page fault resolved for the task.
+    pub fn count_page_fault(&self) {
+        self.minflt.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of page faults resolved for the task and for the children that have
+    /// been waited for.
+    pub fn page_faults(&self) -> (u64, u64) {
+        (
+            self.minflt.load(Ordering::Relaxed),
+            self.cminflt.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Adds the CPU times and page faults of the reaped `child`, including the ones of its own
+    /// children, to the children ones.
+    pub fn reap(&self, child: &SchedEntity) {
+        let (utime, stime) = child.cpu_times();
+        let (cutime, cstime) = child.children_cpu_times();
+        let (minflt, cminflt) = child.page_faults();
+
+        self.cutime.fetch_add(utime + cutime, Ordering::Relaxed);
+        self.cstime.fetch_add(stime + cstime, Ordering::Relaxed);
+        self.cminflt.fetch_add(minflt + cminflt, Ordering::Relaxed);
+    }
+}
+
 struct TaskContainer(Mutex<hashbrown::HashMap<TaskId, Arc<Task>>>);
 
 impl TaskContainer {
@@ -122,13 +267,27 @@ impl Scheduler {
         self.inner.current_task()
     }
 
+    /// Exits the current task. It stays in the process table as a zombie until it is released.
     pub fn exit(&self, status: ExitStatus) -> ! {
-        let current_task = self.inner.current_task();
-        SESSIONS.remove_task(&current_task);
-        self.tasks.remove_task(&current_task);
+        if let Some(task) = self.inner.current_task_optional() {
+            task.clear_child_tid();
+
+            // The userland tests run as init, so the test run is over once it exits.
+            #[cfg(all(test, feature = "ci"))]
+            if task.tid() == TaskId::INIT {
+                crate::emu::finish(matches!(status, ExitStatus::Normal(0)));
+            }
+        }
+
         self.inner.exit(status)
     }
 
+    /// Removes the exited `task` from the process table and from its session.
+    pub fn release(&self, task: &Arc<Task>) {
+        SESSIONS.remove_task(task);
+        self.tasks.remove_task(task);
+    }
+
     pub fn log_ptable(&self) {
         self.tasks.0.lock().iter().for_each(|(pid, task)| {
             let path: String = task
@@ -137,9 +296,10 @@ impl Scheduler {
                 .unwrap_or("<unknown>".into());
 
             log::info!(
-                "task(pid={pid:?}, path={:?}, state={:?})",
+                "task(pid={pid:?}, path={:?}, state={:?}, nice={})",
                 path,
-                task.state()
+                task.state(),
+                task.sched().nice()
             )
         });
     }
@@ -172,29 +332,42 @@ pub fn is_initialized() -> bool {
     SCHEDULER.get().is_some()
 }
 
-static SCHEDULER_VECTOR: Once<u8> = Once::new();
 const SCHEDULER_TIMER_US: usize = 5000;
 
-fn scheduler_irq_handler(_stack: &mut InterruptStack) {
-    #[cfg(target_arch = "x86_64")]
-    {
-        crate::arch::apic::get_local_apic()
-            .timer_oneshot(*SCHEDULER_VECTOR.get().unwrap(), SCHEDULER_TIMER_US);
+/// Set by the scheduler tick, for the timer interrupt to preempt the current task.
+static PREEMPT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Requests preemption in [`SCHEDULER_TIMER_US`] microseconds, and so on.
+fn schedule_tick() {
+    timer::add_timer(
+        timer::deadline_after(SCHEDULER_TIMER_US as u64 * 1000),
+        || {
+            PREEMPT_REQUESTED.store(true, Ordering::Relaxed);
+            schedule_tick();
+        },
+    );
+}
 
-        crate::arch::interrupts::INTERRUPT_CONTROLLER.eoi();
+/// Called at the end of the timer interrupt, preempts the current task if the scheduler tick
+/// expired.
+pub fn preempt_if_requested(stack: &mut InterruptStack) {
+    if !PREEMPT_REQUESTED.swap(false, Ordering::Relaxed) {
+        return;
     }
 
-    self::get_scheduler().inner.preempt();
+    let scheduler = self::get_scheduler();
+
+    // The whole tick is charged to the task that was interrupted by it.
+    if let Some(task) = scheduler.inner.current_task_optional() {
+        task.sched()
+            .charge(SCHEDULER_TIMER_US as u64, stack.iret.is_user());
+    }
+
+    scheduler.inner.preempt();
 }
 
-/// Initialize the scheduler and set up the scheduler interrupt.
+/// Initialize the scheduler and start the scheduler tick.
 pub fn init() {
     SCHEDULER.call_once(Scheduler::new).inner.init();
-
-    let scheduler_vector = interrupts::allocate_vector();
-    interrupts::register_handler(scheduler_vector, scheduler_irq_handler);
-
-    #[cfg(target_arch = "x86_64")]
-    crate::arch::apic::get_local_apic().timer_oneshot(scheduler_vector, SCHEDULER_TIMER_US);
-    SCHEDULER_VECTOR.call_once(|| scheduler_vector);
+    schedule_tick();
 }
@@ -19,9 +19,9 @@ use alloc::sync::Arc;
 
 use intrusive_collections::LinkedList;
 
-use crate::arch;
 use crate::userland::signals::{SignalError, SignalResult};
 use crate::userland::task::{SchedTaskAdapter, Task, TaskState};
+use crate::{arch, timer};
 
 use crate::utils::sync::{IrqGuard, WaitQueue};
 use crate::utils::PerCpu;
@@ -37,11 +37,13 @@ struct TaskQueue {
     idle_task: Arc<Task>,
     preempt_task: Arc<Task>,
     current_task: Option<Arc<Task>>,
+    /// Virtual runtime of the last task that was picked to run. Tasks that become runnable are
+    /// placed no earlier than this.
+    min_vruntime: u64,
 
     runnable: LinkedList<SchedTaskAdapter>,
     dead: LinkedList<SchedTaskAdapter>,
     awaiting: LinkedList<SchedTaskAdapter>,
-    deadline_awaiting: LinkedList<SchedTaskAdapter>,
 
     dead_wq: WaitQueue,
 }
@@ -53,11 +55,11 @@ impl TaskQueue {
             idle_task: Task::new_idle(),
             preempt_task: Task::new_kernel(preempter, false),
             current_task: None,
+            min_vruntime: 0,
 
             runnable: LinkedList::new(SchedTaskAdapter::new()),
             dead: LinkedList::new(SchedTaskAdapter::new()),
             awaiting: LinkedList::new(SchedTaskAdapter::new()),
-            deadline_awaiting: LinkedList::new(SchedTaskAdapter::new()),
 
             dead_wq: WaitQueue::new(),
         }
@@ -67,23 +69,37 @@ impl TaskQueue {
         debug_assert!(!task.link.is_linked()); // Make sure the task is not already linked
 
         task.update_state(TaskState::Runnable);
+        task.sched().place(self.min_vruntime);
         self.runnable.push_back(task);
     }
 
-    fn push_dead(&mut self, task: Arc<Task>) {
-        debug_assert_eq!(task.state(), TaskState::Runnable);
-        debug_assert!(!task.link.is_linked()); // Make sure the task is not already linked
+    /// Removes the runnable task with the lowest virtual runtime from the queue.
+    fn pop_runnable(&mut self) -> Option<Arc<Task>> {
+        let mut cursor = self.runnable.front_mut();
+        let mut min = None;
 
-        self.dead.push_back(task);
+        while let Some(task) = cursor.get() {
+            let vruntime = task.sched().vruntime();
+
+            if min.map_or(true, |(_, min)| vruntime < min) {
+                min = Some((task as *const Task, vruntime));
+            }
+
+            cursor.move_next();
+        }
+
+        let (task, vruntime) = min?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+
+        // SAFETY: The task is linked into the runnable queue.
+        unsafe { self.runnable.cursor_mut_from_ptr(task) }.remove()
     }
 
-    fn push_deadline_awaiting(&mut self, task: Arc<Task>, duration: usize) {
+    fn push_dead(&mut self, task: Arc<Task>) {
+        debug_assert_eq!(task.state(), TaskState::Runnable);
         debug_assert!(!task.link.is_linked()); // Make sure the task is not already linked
 
-        task.update_state(TaskState::AwaitingIo);
-        task.set_sleep_duration(crate::arch::time::get_uptime_ticks() + duration);
-
-        self.deadline_awaiting.push_back(task);
+        self.dead.push_back(task);
     }
 
     fn push_awaiting(&mut self, task: Arc<Task>) {
@@ -98,6 +114,11 @@ impl TaskQueue {
 /// system timer fires, the next task in the queue is switched to, and the
 /// preempted task is put back into the queue.
 ///
+/// The next task is the runnable task that has received the least CPU time so far, weighted by
+/// its nice value (see [`SchedEntity`](super::SchedEntity)). Tasks with the same nice value are
+/// therefore scheduled round robin, while a task with a lower nice value gets a proportionally
+/// larger share of the CPU.
+///
 /// ## Notes
 /// * <https://en.wikipedia.org/wiki/Round-robin_scheduling>
 pub struct RoundRobin {
@@ -128,60 +149,23 @@ impl RoundRobin {
         }
     }
 
-    fn schedule_check_deadline(&self) {
-        let _guard = IrqGuard::new();
-        let queue = self.queue.get_mut();
-
-        let time = crate::arch::time::get_uptime_ticks();
-
-        let mut cursor = queue.deadline_awaiting.front_mut();
-
-        while let Some(task) = cursor.get() {
-            if task.load_sleep_duration() <= time {
-                let ptr = cursor.remove().unwrap();
-
-                assert!(!ptr.link.is_linked());
-
-                ptr.update_state(TaskState::Runnable);
-                ptr.set_sleep_duration(0);
-
-                queue.runnable.push_back(ptr);
-            } else {
-                cursor.move_next();
-            }
-        }
-    }
-
     fn schedule_next_task(&self) {
         let guard = IrqGuard::new();
         let queue = self.queue.get_mut();
 
-        self.schedule_check_deadline();
-
-        // Switch to the next runnable task in the runnable queue, and put
-        // the preempted task back into the runnable queue.
-        if let Some(task) = queue.runnable.pop_front() {
-            if let Some(current_task) = queue.current_task.clone() {
-                if !current_task.link.is_linked() && current_task.pid() != task.pid() {
-                    queue.push_runnable(current_task);
-                }
+        // Put the preempted task back into the runnable queue, so that it competes with the
+        // other runnable tasks, and switch to the one that is the most behind.
+        if let Some(current_task) = queue.current_task.clone() {
+            if !current_task.link.is_linked() && current_task.state() == TaskState::Runnable {
+                queue.push_runnable(current_task);
             }
+        }
 
+        if let Some(task) = queue.pop_runnable() {
             queue.current_task = Some(task.clone());
             core::mem::drop(guard);
             arch::task::arch_task_spinup(queue.preempt_task.arch_task_mut(), task.arch_task());
         } else {
-            if let Some(current) = queue.current_task.as_ref() {
-                if current.state() == TaskState::Runnable {
-                    core::mem::drop(guard);
-                    arch::task::arch_task_spinup(
-                        queue.preempt_task.arch_task_mut(),
-                        current.arch_task(),
-                    );
-                    return;
-                }
-            }
-
             queue.current_task = None;
             core::mem::drop(guard);
             arch::task::arch_task_spinup(
@@ -223,7 +207,7 @@ impl SchedulerInterface for RoundRobin {
         }
     }
 
-    fn sleep(&self, duration: Option<usize>) -> SignalResult<()> {
+    fn sleep(&self, deadline: Option<u64>) -> SignalResult<()> {
         let _guard = IrqGuard::new();
         let queue = self.queue.get_mut();
 
@@ -238,14 +222,18 @@ impl SchedulerInterface for RoundRobin {
             return Ok(());
         }
 
-        if let Some(duration) = duration {
-            queue.push_deadline_awaiting(task, duration);
-        } else {
-            queue.push_awaiting(task);
-        }
+        let timer = deadline.map(|deadline| {
+            let task = task.clone();
+            timer::add_timer(deadline, move || task.wake_up())
+        });
 
+        queue.push_awaiting(task);
         self.preempt();
 
+        if let Some(timer) = timer {
+            timer::cancel(timer);
+        }
+
         let task = queue
             .current_task
             .as_ref()
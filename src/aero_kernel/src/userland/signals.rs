@@ -27,6 +27,8 @@ use bit_field::BitField;
 use aero_syscall::SyscallError;
 
 use super::scheduler::{self, ExitStatus};
+use super::task::StopReason;
+use crate::arch::interrupts::InterruptStack;
 use crate::fs::FileSystemError;
 use crate::utils::sync::{Mutex, MutexGuard};
 
@@ -38,6 +40,8 @@ mod default {
     pub enum Action {
         Ignore,
         Handle(fn(usize)),
+        /// Stop the task until it receives a `SIGCONT`.
+        Stop,
     }
 
     /// Some of the default actions for the signals.
@@ -60,11 +64,11 @@ mod default {
         Action::Handle(terminate),        // SIGTERM
         Action::Ignore,                   // UNUSED
         Action::Ignore,                   // SIGCHLD
-        Action::Handle(terminate),        // SIGCONT
-        Action::Handle(stop),             // SIGSTOP
-        Action::Handle(stop),             // SIGTSTP
-        Action::Ignore,                   // UNUSED
-        Action::Ignore,                   // UNUSED
+        Action::Ignore,                   // SIGCONT (continued when generated)
+        Action::Stop,                     // SIGSTOP
+        Action::Stop,                     // SIGTSTP
+        Action::Stop,                     // SIGTTIN
+        Action::Stop,                     // SIGTTOU
         Action::Ignore,                   // UNUSED
         Action::Ignore,                   // UNUSED
         Action::Ignore,                   // UNUSED
@@ -87,10 +91,6 @@ mod default {
         unimplemented!()
     }
 
-    fn stop(_signal: usize) {
-        unimplemented!()
-    }
-
     /// Get the default action for the provided `signal`.
     pub fn action(signal: usize) -> Action {
         DEFAULT_ACTIONS[signal]
@@ -136,6 +136,15 @@ impl From<SignalError> for SyscallError {
     }
 }
 
+/// Marks a system call interrupted by a signal as restartable (see
+/// [`SyscallError::ERESTARTSYS`]).
+pub fn restartable<E: Into<SyscallError>>(error: E) -> SyscallError {
+    match error.into() {
+        SyscallError::EINTR => SyscallError::ERESTARTSYS,
+        error => error,
+    }
+}
+
 #[derive(Default, Copy, Clone, Debug)]
 pub struct SignalEntry {
     handler: SignalHandler,
@@ -185,12 +194,15 @@ impl SignalEntry {
     }
 }
 
-const SIGNAL_COUNT: usize = 35;
+pub const SIGNAL_COUNT: usize = 35;
 
 #[derive(Copy, Clone)]
 pub struct Entries {
     entries: [SignalEntry; SIGNAL_COUNT],
     pending_mask: u64,
+    /// Information about the pending signals, passed to the handlers installed with
+    /// `SA_SIGINFO`. Only the latest one is kept, as signals are not queued.
+    pending_info: [Option<SigInfo>; SIGNAL_COUNT],
 }
 
 impl Default for Entries {
@@ -198,6 +210,7 @@ impl Default for Entries {
         Entries {
             entries: [SignalEntry::default(); SIGNAL_COUNT],
             pending_mask: 0,
+            pending_info: [None; SIGNAL_COUNT],
         }
     }
 }
@@ -231,6 +244,11 @@ impl Entries {
     pub fn set_pending(&mut self, signal: u64) {
         self.pending_mask.set_bit(signal as usize, true);
     }
+
+    /// Takes the information about the pending `signal`.
+    pub fn take_pending_info(&mut self, signal: usize) -> Option<SigInfo> {
+        self.pending_info[signal].take()
+    }
 }
 
 pub struct Signals {
@@ -270,6 +288,11 @@ impl Signals {
         self.entries.lock_irq()
     }
 
+    /// Returns the action installed for the provided `signal`.
+    pub fn entry(&self, signal: usize) -> SignalEntry {
+        self.entries()[signal]
+    }
+
     pub fn thread_pending(&self) -> u64 {
         self.thread_pending_mask.load(Ordering::SeqCst)
     }
@@ -315,10 +338,11 @@ impl Signals {
         self.blocked_mask().get_bit(signal)
     }
 
-    pub fn trigger(&self, signal: usize, this_thread: bool) -> TriggerResult {
+    pub fn trigger(&self, info: SigInfo, this_thread: bool) -> TriggerResult {
+        let signal = info.si_signo as usize;
         assert!(signal < SIGNAL_COUNT);
 
-        let sigs = self.entries();
+        let mut sigs = self.entries();
         let handler = sigs[signal].handler();
 
         if match handler {
@@ -329,12 +353,16 @@ impl Signals {
 
                 match action {
                     default::Action::Ignore => false,
-                    default::Action::Handle(_) => true,
+                    default::Action::Handle(_) | default::Action::Stop => true,
                 }
             }
 
             SignalHandler::Handle(_) => true,
         } {
+            if !this_thread {
+                sigs.pending_info[signal] = Some(info);
+            }
+
             core::mem::drop(sigs); // drop the lock
             self.set_pending(signal as u64, this_thread);
 
@@ -423,7 +451,7 @@ impl Signals {
     }
 }
 
-pub fn check_for_signals() -> Option<(usize, SignalEntry)> {
+pub fn check_for_signals(frame: &mut InterruptStack) -> Option<(usize, SignalEntry, SigInfo)> {
     let task = scheduler::get_scheduler().current_task();
     let signals = task.signals();
 
@@ -435,24 +463,37 @@ pub fn check_for_signals() -> Option<(usize, SignalEntry)> {
     // Check if a SIGKILL is pending, and if so, kill the task.
     if signals.is_pending(SIGKILL as u64) {
         signals.clear_pending(SIGKILL as u64);
-        scheduler::get_scheduler().exit(ExitStatus::Normal(1));
+        scheduler::get_scheduler().exit(ExitStatus::Signal(SIGKILL));
     }
 
     for i in 0..SIGNAL_COUNT {
         if !signals.is_blocked(i) && signals.is_pending(i as u64) {
             signals.clear_pending(i as u64);
 
-            let entries = signals.entries();
+            let mut entries = signals.entries();
             let entry = entries[i];
+            let info = entries
+                .take_pending_info(i)
+                .unwrap_or_else(|| SigInfo::new(i, SI_USER, 0));
 
             match entry.handler() {
                 SignalHandler::Default => {
                     drop(entries);
-                    default::handle_default(i);
+
+                    if matches!(default::action(i), default::Action::Stop) {
+                        // A tracee stops for its tracer instead of its parent.
+                        if task.is_traced() {
+                            task.ptrace_signal_stop(i, frame);
+                        } else {
+                            task.stop(StopReason::Signal(i), frame);
+                        }
+                    } else {
+                        default::handle_default(i);
+                    }
                 }
 
                 SignalHandler::Handle(_) => {
-                    return Some((i, entry));
+                    return Some((i, entry, info));
                 }
 
                 SignalHandler::Ignore => {
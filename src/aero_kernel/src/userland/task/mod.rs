@@ -15,24 +15,31 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+pub mod ptrace;
 pub mod sessions;
 
-use aero_syscall::WaitPidFlags;
+use aero_syscall::process::WaitStatus;
+use aero_syscall::signal::*;
+use aero_syscall::{Mode, SyscallError, WaitPidFlags};
 use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
 use hashbrown::HashMap;
 use spin::{Once, RwLock};
 
 use core::cell::UnsafeCell;
 use core::ops::Range;
-use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering};
 
 use crate::fs::cache::{DirCacheImpl, DirCacheItem};
 use crate::fs::path::PathBuf;
-use crate::fs::{self, FileSystem};
+use crate::fs::{self, FileSystem, FileSystemError};
 use crate::mem::paging::*;
 
+use crate::arch::interrupts::InterruptStack;
 use crate::arch::task::ArchTask;
+use crate::arch::user_copy::copy_to_user;
 use crate::fs::file_table::FileTable;
 use crate::syscall::ipc::MessageQueue;
 use crate::syscall::ExecArgs;
@@ -42,23 +49,30 @@ use crate::userland::signals::Signals;
 
 use intrusive_collections::{intrusive_adapter, LinkedList, LinkedListLink};
 
-use super::scheduler::{self, ExitStatus};
-use super::signals::{SignalResult, TriggerResult};
+use super::scheduler::{self, ExitStatus, SchedEntity};
+use super::signals::TriggerResult;
 use super::terminal::TerminalDevice;
 use super::vm::Vm;
 
+use self::ptrace::Ptrace;
+use self::sessions::SESSIONS;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[repr(transparent)]
 pub struct TaskId(usize);
 
 impl TaskId {
+    /// The task that runs the kernel main thread and then executes `/usr/bin/init`. Orphaned
+    /// processes are adopted by it.
+    pub const INIT: Self = Self::new(1);
+
     pub const fn new(pid: usize) -> Self {
         Self(pid)
     }
 
     /// Allocates a new task ID.
     fn allocate() -> Self {
-        static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
+        static NEXT_PID: AtomicUsize = AtomicUsize::new(TaskId::INIT.0 + 1);
 
         Self::new(NEXT_PID.fetch_add(1, Ordering::AcqRel))
     }
@@ -86,6 +100,60 @@ impl From<u8> for TaskState {
     }
 }
 
+/// The reason a task was stopped.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StopReason {
+    /// Stopped by a job control signal (`SIGSTOP`, `SIGTSTP`, `SIGTTIN` or `SIGTTOU`) and resumed
+    /// by `SIGCONT`. Reported to the parent by `waitpid` with `WUNTRACED`.
+    Signal(usize),
+    /// Stopped for the tracer, either on signal delivery or on syscall entry and exit. Only the
+    /// tracer can resume the task.
+    Trace(usize),
+}
+
+struct Stop {
+    reason: StopReason,
+    /// The user register frame of the stopped task. It lives on the task's kernel stack and
+    /// stays valid for as long as the task is stopped.
+    frame: NonNull<InterruptStack>,
+    /// Whether the stop has already been reported by `waitpid`.
+    reported: bool,
+}
+
+// SAFETY: The frame is only accessed while the owning task is stopped.
+unsafe impl Send for Stop {}
+
+enum Waited {
+    Exited(TaskId, ExitStatus),
+    Stopped(TaskId, usize),
+}
+
+/// The state of a task that is waited for, other than a zombie.
+enum ChildState {
+    /// Stopped by the signal, and the stop is yet to be reported.
+    Stopped(usize),
+    /// A tracee that exited, and whose exit is reported to its tracer before its parent.
+    Exited(Arc<Task>),
+    Alive,
+    /// Not a child or a tracee of the waiter, or already reaped.
+    Gone,
+}
+
+/// Identity of a process, used for permission checks.
+///
+/// User and group IDs are not tracked yet: every process runs as root.
+#[derive(Debug, Default, Clone)]
+pub struct Credentials {
+    /// Supplementary group IDs, sorted.
+    supplementary_groups: Vec<u32>,
+}
+
+impl Credentials {
+    pub fn supplementary_groups(&self) -> &[u32] {
+        &self.supplementary_groups
+    }
+}
+
 struct Cwd {
     inode: DirCacheItem,
     filesystem: Arc<dyn FileSystem>,
@@ -135,50 +203,95 @@ impl Zombies {
         self.block.notify_all();
     }
 
-    fn waitpid(
+    /// Waits for one of the children in `pids` to exit or stop. With `any`, every zombie is
+    /// waited for, including the ones that were adopted after `pids` was collected. The CPU
+    /// times of the reaped child are added to the children CPU times of `waiter`. Fails with
+    /// `ECHILD` once there is nothing left to wait for.
+    fn waitpid<F>(
         &self,
+        waiter: &SchedEntity,
         pids: &[usize],
+        any: bool,
         status: &mut u32,
         flags: WaitPidFlags,
-    ) -> SignalResult<usize> {
+        mut child: F,
+    ) -> Result<usize, SyscallError>
+    where
+        F: FnMut(usize) -> ChildState,
+    {
         let mut captured = None;
+        let mut reaped = None;
+        let mut tracee = None;
+        let mut alive = false;
 
-        self.block.block_on(&self.list, |l| {
-            let mut cursor = l.front_mut();
+        self.block.block_on_interruptible(&self.list, |l| {
+            alive = false;
 
-            while let Some(t) = cursor.get() {
-                for pid in pids {
-                    if t.pid().as_usize() == *pid {
-                        captured = Some((t.pid(), t.exit_status().clone()));
-                        cursor.remove();
+            for pid in pids {
+                match child(*pid) {
+                    ChildState::Stopped(signal) => {
+                        captured = Some(Waited::Stopped(TaskId::new(*pid), signal));
+                        return true;
+                    }
 
+                    ChildState::Exited(task) => {
+                        captured = Some(Waited::Exited(task.pid(), task.exit_status().clone()));
+                        tracee = Some(task);
                         return true;
                     }
-                }
 
-                cursor.move_next();
+                    ChildState::Alive => alive = true,
+                    ChildState::Gone => {}
+                }
             }
 
-            if flags.contains(WaitPidFlags::WNOHANG) {
-                return true;
+            let mut cursor = l.front_mut();
+
+            while let Some(t) = cursor.get() {
+                if any || pids.contains(&t.pid().as_usize()) {
+                    captured = Some(Waited::Exited(t.pid(), t.exit_status().clone()));
+                    waiter.reap(t.sched());
+                    reaped = cursor.remove();
+
+                    return true;
+                }
+
+                cursor.move_next();
             }
 
-            false
+            !alive || flags.contains(WaitPidFlags::WNOHANG)
         })?;
 
-        if let Some((tid, exit_status)) = captured {
-            // mlibc/abis/linux/wait.h (`W_EXITCODE`)
-            match exit_status {
-                ExitStatus::Normal(code) => {
-                    *status = (code as u32) << 8;
-                }
+        if let Some(zombie) = reaped {
+            zombie.release();
+        }
 
-                ExitStatus::Signal(signal) => {
-                    *status = signal as u32;
+        if let Some(tracee) = tracee {
+            tracee.ptrace_exit_reported();
+        }
+
+        if let Some(waited) = captured {
+            let (tid, waited) = match waited {
+                Waited::Exited(tid, ExitStatus::Normal(code)) => {
+                    (tid, WaitStatus::Exited(code as u8))
                 }
-            }
 
+                // Core dumps are not supported.
+                Waited::Exited(tid, ExitStatus::Signal(signal)) => (
+                    tid,
+                    WaitStatus::Signaled {
+                        signal,
+                        core_dumped: false,
+                    },
+                ),
+
+                Waited::Stopped(tid, signal) => (tid, WaitStatus::Stopped(signal)),
+            };
+
+            *status = waited.into_raw();
             Ok(tid.as_usize())
+        } else if !alive {
+            Err(SyscallError::ECHILD)
         } else {
             // If `WNOHANG` was specified in flags and there were no children in a waitable
             // state, then waipid() returns 0 immediately.
@@ -188,6 +301,9 @@ impl Zombies {
     }
 }
 
+/// File mode creation mask of the init process.
+const DEFAULT_UMASK: u32 = 0o022;
+
 pub struct Task {
     sref: Weak<Task>,
 
@@ -205,7 +321,7 @@ pub struct Task {
 
     zombies: Zombies,
 
-    sleep_duration: AtomicUsize,
+    sched: SchedEntity,
     signals: Signals,
 
     pub executable: Mutex<Option<DirCacheItem>>,
@@ -220,12 +336,24 @@ pub struct Task {
     pub message_queue: MessageQueue,
 
     cwd: RwLock<Option<Cwd>>,
+    /// File mode creation mask. Only the one of the process leader is used.
+    umask: AtomicU32,
+    /// Only the credentials of the process leader are used.
+    credentials: RwLock<Credentials>,
 
     pub(super) exit_status: Once<ExitStatus>,
 
     controlling_terminal: Mutex<Option<Arc<dyn TerminalDevice>>>,
     systrace: AtomicBool,
 
+    stop: Mutex<Option<Stop>>,
+    stop_queue: WaitQueue,
+    ptrace: Ptrace,
+
+    /// Address of the thread ID that is cleared when the task exits, set with
+    /// `set_tid_address`. Zero if there is none.
+    clear_child_tid: AtomicUsize,
+
     // for debugging only. may remove in the future.
     pub mem_tags: Mutex<HashMap<Range<usize>, String>>,
 }
@@ -261,7 +389,7 @@ impl Task {
 
             pending_io: AtomicBool::new(false),
 
-            sleep_duration: AtomicUsize::new(0),
+            sched: SchedEntity::default(),
             exit_status: Once::new(),
 
             children: Mutex::new(Default::default()),
@@ -269,9 +397,15 @@ impl Task {
 
             signals: Signals::new(),
             cwd: RwLock::new(None),
+            umask: AtomicU32::new(DEFAULT_UMASK),
+            credentials: RwLock::new(Credentials::default()),
 
             systrace: AtomicBool::new(false),
+            stop: Mutex::new(None),
+            stop_queue: WaitQueue::new(),
+            ptrace: Ptrace::new(),
             controlling_terminal: Mutex::new(None),
+            clear_child_tid: AtomicUsize::new(0),
 
             mem_tags: Mutex::new(HashMap::new()),
         })
@@ -279,8 +413,16 @@ impl Task {
 
     /// Allocates a new kernel task pointing at the provided entry point function.
     pub fn new_kernel(entry_point: fn(), enable_interrupts: bool) -> Arc<Self> {
-        let pid = TaskId::allocate();
+        Self::new_kernel_with_id(TaskId::allocate(), entry_point, enable_interrupts)
+    }
 
+    /// Creates the kernel task with the [`TaskId::INIT`] ID, which later executes the init
+    /// process.
+    pub fn new_init(entry_point: fn()) -> Arc<Self> {
+        Self::new_kernel_with_id(TaskId::INIT, entry_point, true)
+    }
+
+    fn new_kernel_with_id(pid: TaskId, entry_point: fn(), enable_interrupts: bool) -> Arc<Self> {
         Arc::new_cyclic(|sref| Self {
             sref: sref.clone(),
             zombies: Zombies::new(),
@@ -302,7 +444,7 @@ impl Task {
             link: Default::default(),
             clink: Default::default(),
 
-            sleep_duration: AtomicUsize::new(0),
+            sched: SchedEntity::default(),
             exit_status: Once::new(),
 
             executable: Mutex::new(None),
@@ -313,9 +455,15 @@ impl Task {
 
             signals: Signals::new(),
             cwd: RwLock::new(None),
+            umask: AtomicU32::new(DEFAULT_UMASK),
+            credentials: RwLock::new(Credentials::default()),
 
             systrace: AtomicBool::new(false),
+            stop: Mutex::new(None),
+            stop_queue: WaitQueue::new(),
+            ptrace: Ptrace::new(),
             controlling_terminal: Mutex::new(None),
+            clear_child_tid: AtomicUsize::new(0),
 
             mem_tags: Mutex::new(HashMap::new()),
         })
@@ -333,6 +481,10 @@ impl Task {
         &self.signals
     }
 
+    pub fn sched(&self) -> &SchedEntity {
+        &self.sched
+    }
+
     pub fn clone_process(&self, entry: usize, stack: usize) -> Arc<Task> {
         let arch_task = UnsafeCell::new(
             self.arch_task_mut()
@@ -355,7 +507,7 @@ impl Task {
             link: Default::default(),
             clink: Default::default(),
 
-            sleep_duration: AtomicUsize::new(0),
+            sched: self.sched.fork(),
             exit_status: Once::new(),
 
             tid: pid,
@@ -371,15 +523,21 @@ impl Task {
             parent: Mutex::new(None),
 
             cwd: RwLock::new(Some(self.cwd.read().as_ref().unwrap().fork())),
+            umask: AtomicU32::new(self.umask().bits()),
+            credentials: RwLock::new(self.credentials()),
             signals: Signals::new(),
 
             systrace: AtomicBool::new(self.process_leader().systrace()),
+            stop: Mutex::new(None),
+            stop_queue: WaitQueue::new(),
+            ptrace: Ptrace::new(),
             controlling_terminal: Mutex::new(
                 self.process_leader()
                     .controlling_terminal
                     .lock_irq()
                     .clone(),
             ),
+            clear_child_tid: AtomicUsize::new(0),
 
             mem_tags: Mutex::new(self.mem_tags.lock().clone()),
         });
@@ -415,7 +573,7 @@ impl Task {
             link: Default::default(),
             clink: Default::default(),
 
-            sleep_duration: AtomicUsize::new(0),
+            sched: self.sched.fork(),
             exit_status: Once::new(),
 
             tid: pid,
@@ -430,10 +588,16 @@ impl Task {
             parent: Mutex::new(None),
 
             cwd: RwLock::new(Some(self.cwd.read().as_ref().unwrap().fork())),
+            umask: AtomicU32::new(self.umask().bits()),
+            credentials: RwLock::new(self.credentials()),
             signals: Signals::new(),
 
             systrace: AtomicBool::new(self.systrace()),
+            stop: Mutex::new(None),
+            stop_queue: WaitQueue::new(),
+            ptrace: Ptrace::new(),
             controlling_terminal: Mutex::new(self.controlling_terminal.lock_irq().clone()),
+            clear_child_tid: AtomicUsize::new(0),
 
             mem_tags: Mutex::new(self.mem_tags.lock().clone()),
         });
@@ -451,13 +615,13 @@ impl Task {
         *self.parent.lock() = parent;
     }
 
+    /// Removes `child` from the children. Its parent is kept until it is reaped, so that an
+    /// exited child still reports it.
     fn remove_child(&self, child: &Task) {
         let mut children = self.children.lock_irq();
 
         if child.clink.is_linked() {
             let mut cursor = unsafe { children.cursor_mut_from_ptr(child) };
-
-            child.set_parent(None);
             cursor.remove();
         }
     }
@@ -473,20 +637,12 @@ impl Task {
         self.exit_status.get().unwrap()
     }
 
-    pub fn set_sleep_duration(&self, duration: usize) {
-        self.sleep_duration.store(duration, Ordering::SeqCst);
-    }
-
-    pub fn load_sleep_duration(&self) -> usize {
-        self.sleep_duration.load(Ordering::SeqCst)
-    }
-
     pub fn waitpid(
         &self,
         pid: isize,
         status: &mut u32,
         flags: WaitPidFlags,
-    ) -> SignalResult<usize> {
+    ) -> Result<usize, SyscallError> {
         if pid == -1 {
             // wait for any child process if no specific process is requested.
             //
@@ -502,12 +658,104 @@ impl Task {
                 .collect::<alloc::vec::Vec<_>>();
 
             pids.extend(self.children.lock_irq().iter().map(|e| e.pid().as_usize()));
-            self.zombies.waitpid(&pids, status, flags)
+            pids.extend(self.ptrace.tracees().iter().map(|e| e.pid().as_usize()));
+
+            self.zombies
+                .waitpid(self.sched(), &pids, true, status, flags, |pid| {
+                    self.child_state(pid, flags)
+                })
         } else {
-            self.zombies.waitpid(&[pid as _], status, flags)
+            self.zombies
+                .waitpid(self.sched(), &[pid as _], false, status, flags, |pid| {
+                    self.child_state(pid, flags)
+                })
+        }
+    }
+
+    /// Returns the state of the child or tracee with the provided `pid`. A stop is reported only
+    /// once.
+    fn child_state(&self, pid: usize, flags: WaitPidFlags) -> ChildState {
+        let task = self
+            .children
+            .lock_irq()
+            .iter()
+            .find(|e| e.pid().as_usize() == pid)
+            .map(|e| e.this())
+            .or_else(|| {
+                self.ptrace
+                    .tracees()
+                    .iter()
+                    .find(|e| e.pid().as_usize() == pid)
+                    .cloned()
+            });
+
+        let Some(task) = task else {
+            return ChildState::Gone;
+        };
+
+        if task.is_traced_by(self) && task.ptrace_take_exit() {
+            return ChildState::Exited(task);
+        }
+
+        let mut stop = task.stop.lock_irq();
+        let Some(stop) = stop.as_mut().filter(|stop| !stop.reported) else {
+            return ChildState::Alive;
+        };
+
+        let signal = match stop.reason {
+            StopReason::Trace(signal) if task.is_traced_by(self) => signal,
+            StopReason::Signal(signal) if flags.contains(WaitPidFlags::WUNTRACED) => signal,
+            _ => return ChildState::Alive,
+        };
+
+        stop.reported = true;
+        ChildState::Stopped(signal)
+    }
+
+    /// Stops the current task until it is resumed, either by `SIGCONT` or by its tracer depending
+    /// on the stop `reason`. A pending `SIGKILL` also ends the stop.
+    pub fn stop(&self, reason: StopReason, frame: &mut InterruptStack) {
+        *self.stop.lock_irq() = Some(Stop {
+            reason,
+            frame: NonNull::from(frame),
+            reported: false,
+        });
+
+        let (waiter, code, signal) = match reason {
+            StopReason::Signal(signal) => (self.get_parent(), CLD_STOPPED, signal),
+            StopReason::Trace(signal) => (self.tracer(), CLD_TRAPPED, signal),
+        };
+
+        if let Some(waiter) = waiter {
+            waiter.zombies.block.notify_all();
+            waiter.notify_child(SigInfo::child(code, self.pid().as_usize(), signal as i32));
+        }
+
+        while self
+            .stop_queue
+            .block_on(&self.stop, |stop| stop.is_none())
+            .is_err()
+        {
+            self.stop_queue.remove(self);
+
+            if self.signals().is_pending(SIGKILL as u64) {
+                *self.stop.lock_irq() = None;
+                break;
+            }
         }
     }
 
+    /// Resumes the task if it is stopped.
+    pub fn resume(&self) {
+        *self.stop.lock_irq() = None;
+        self.stop_queue.notify_all();
+    }
+
+    /// Returns the reason the task is stopped for, or [`None`] if it is not stopped.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop.lock_irq().as_ref().map(|stop| stop.reason)
+    }
+
     pub fn path(&self) -> Option<PathBuf> {
         self.executable.lock().as_ref().map(|e| e.absolute_path())
     }
@@ -542,6 +790,9 @@ impl Task {
         // Clear the signals that are pending for this task on exec.
         self.signals().clear();
 
+        // The address belongs to the old image.
+        self.clear_child_tid.store(0, Ordering::SeqCst);
+
         self.arch_task_mut().exec(vm, executable, argv, envv)
     }
 
@@ -549,6 +800,27 @@ impl Task {
         &self.vm
     }
 
+    /// Sets the address of the thread ID that is cleared when the task exits.
+    pub fn set_clear_child_tid(&self, address: usize) {
+        self.clear_child_tid.store(address, Ordering::SeqCst);
+    }
+
+    /// Zeroes the thread ID registered with `set_tid_address` and wakes up the tasks waiting on
+    /// it, which is how a thread joining this one learns that it exited. Must be called by the
+    /// task itself, while its address space is still active.
+    pub(super) fn clear_child_tid(&self) {
+        let address = self.clear_child_tid.swap(0, Ordering::SeqCst);
+
+        if address == 0 {
+            return;
+        }
+
+        // The task is exiting, so a bad address is silently ignored.
+        if copy_to_user(address as *mut u32, &0).is_ok() {
+            let _ = crate::syscall::futex::wake_up(VirtAddr::new(address as u64));
+        }
+    }
+
     /// Returns a immutable reference to the inner [ArchTask] structure.
     pub fn arch_task(&self) -> &ArchTask {
         unsafe { &(*self.arch_task.get()) }
@@ -601,9 +873,9 @@ impl Task {
                 Weak::weak_count(&self.sref)
             );
             if Arc::strong_count(&self.file_table) == 1 {
-                self.file_table.0.read().iter().for_each(|file| {
+                self.file_table.files.read().iter().for_each(|file| {
                     if let Some(handle) = file {
-                        handle.inode().close(handle.flags());
+                        handle.close();
                     }
                 });
             }
@@ -659,6 +931,45 @@ impl Task {
         self.cwd.write().as_mut().unwrap().filesystem = filesystem;
     }
 
+    /// Returns the file mode creation mask of the process.
+    pub fn umask(&self) -> Mode {
+        let umask = self.process_leader().umask.load(Ordering::SeqCst);
+        Mode::from_bits_truncate(umask)
+    }
+
+    /// Sets the file mode creation mask of the process and returns the previous one.
+    pub fn set_umask(&self, umask: Mode) -> Mode {
+        let umask = umask & (Mode::S_IRWXU | Mode::S_IRWXG | Mode::S_IRWXO);
+        let old = self
+            .process_leader()
+            .umask
+            .swap(umask.bits(), Ordering::SeqCst);
+
+        Mode::from_bits_truncate(old)
+    }
+
+    /// Returns the credentials of the process.
+    pub fn credentials(&self) -> Credentials {
+        self.process_leader().credentials.read().clone()
+    }
+
+    /// Replaces the supplementary group IDs of the process.
+    pub fn set_supplementary_groups(&self, groups: &[u32]) {
+        let mut groups = groups.to_vec();
+        groups.sort_unstable();
+
+        self.process_leader()
+            .credentials
+            .write()
+            .supplementary_groups = groups;
+    }
+
+    /// Returns the permissions a file created with the `requested` ones gets, after clearing
+    /// the bits set in the file mode creation mask.
+    pub fn creation_mode(&self, requested: Mode) -> Mode {
+        (requested - Mode::S_IFMT) - self.umask()
+    }
+
     pub fn get_parent(&self) -> Option<Arc<Task>> {
         let parent = self.parent.lock();
         parent.clone()
@@ -684,7 +995,36 @@ impl Task {
     }
 
     pub fn signal(&self, signal: usize) -> bool {
-        match self.signals().trigger(signal, false) {
+        self.signal_info(SigInfo::new(signal, SI_USER, 0))
+    }
+
+    /// Sends the signal described by `info`, which is passed to the handler if it was installed
+    /// with `SA_SIGINFO`. Tasks that have exited ignore signals.
+    pub fn signal_info(&self, info: SigInfo) -> bool {
+        let signal = info.si_signo as usize;
+
+        if self.has_exited() {
+            return false;
+        }
+
+        if signal == SIGCONT {
+            // Continuing happens when the signal is generated, regardless of whether it is
+            // blocked or handled. Any pending stop signals are discarded.
+            for stop in [SIGSTOP, SIGTSTP, SIGTTIN, SIGTTOU] {
+                self.signals().clear_pending(stop as u64);
+            }
+
+            if let Some(StopReason::Signal(_)) = self.stop_reason() {
+                self.resume();
+
+                if let Some(parent) = self.get_parent() {
+                    let pid = self.pid().as_usize();
+                    parent.notify_child(SigInfo::child(CLD_CONTINUED, pid, SIGCONT as i32));
+                }
+            }
+        }
+
+        match self.signals().trigger(info, false) {
             TriggerResult::Triggered => {
                 self.wake_up();
                 true
@@ -720,17 +1060,131 @@ impl Task {
         }
     }
 
+    /// Sends `SIGCHLD` with `info` about a child whose state changed. Stops and continues are not
+    /// reported if the handler was installed with `SA_NOCLDSTOP`.
+    fn notify_child(&self, info: SigInfo) {
+        let flags = self.signals().entry(SIGCHLD).flags();
+
+        if info.si_code != CLD_EXITED
+            && info.si_code != CLD_KILLED
+            && flags.contains(SignalFlags::SA_NOCLDSTOP)
+        {
+            return;
+        }
+
+        self.signal_info(info);
+    }
+
+    /// Returns whether the children of the process are reaped as soon as they exit instead of
+    /// becoming zombies, which is the case if it ignores `SIGCHLD` or set `SA_NOCLDWAIT` for it.
+    fn reaps_children(&self) -> bool {
+        let entry = self.signals().entry(SIGCHLD);
+
+        matches!(entry.handler(), SignalHandler::Ignore)
+            || entry.flags().contains(SignalFlags::SA_NOCLDWAIT)
+    }
+
+    /// Returns whether the task has exited.
+    pub fn has_exited(&self) -> bool {
+        self.exit_status.get().is_some()
+    }
+
+    /// Removes the exited task from the process table, once it was waited for or if nothing
+    /// waits for it.
+    fn release(&self) {
+        self.set_parent(None);
+        scheduler::get_scheduler().release(&self.this());
+    }
+
+    /// Hands the children of the exiting task over to init, which reaps them once they exit.
+    /// Threads of the same process are left alone.
+    fn reparent_children(&self) {
+        let Some(init) = scheduler::get_scheduler().find_task(TaskId::INIT) else {
+            return;
+        };
+
+        if init.pid() == self.pid() {
+            return;
+        }
+
+        let mut children = self.children.lock_irq();
+        let mut cursor = children.front_mut();
+
+        while let Some(child) = cursor.get() {
+            if child.pid() == self.pid() {
+                cursor.move_next();
+                continue;
+            }
+
+            let child = cursor.remove().unwrap();
+            init.add_child(child);
+        }
+
+        core::mem::drop(children);
+
+        let mut zombies = core::mem::take(&mut *self.zombies.list.lock_irq());
+
+        if zombies.is_empty() {
+            return;
+        }
+
+        while let Some(zombie) = zombies.pop_front() {
+            zombie.set_parent(Some(init.clone()));
+            init.zombies.add_zombie(zombie);
+        }
+
+        init.signal(SIGCHLD);
+    }
+
     pub(super) fn make_zombie(&self) {
         self.detach();
+        self.ptrace_exit();
+        crate::ipc::sem::exit(self.pid());
         self.arch_task_mut().dealloc();
+        self.reparent_children();
 
-        if let Some(parent) = self.get_parent() {
+        if !self.ptrace_report_exit() {
+            self.notify_exit();
+        }
+    }
+
+    /// Returns the `SIGCHLD` information about the exit of the task.
+    fn exit_info(&self) -> SigInfo {
+        let pid = self.pid().as_usize();
+
+        match self.exit_status() {
+            ExitStatus::Normal(code) => SigInfo::child(CLD_EXITED, pid, *code as i32 & 0xff),
+            ExitStatus::Signal(signal) => SigInfo::child(CLD_KILLED, pid, *signal as i32),
+        }
+    }
+
+    /// Hands the exited task over to its parent, which either waits for it or reaps it right
+    /// away.
+    fn notify_exit(&self) {
+        // Nothing waits for the tasks without a parent, such as kernel tasks.
+        let Some(parent) = self.get_parent() else {
+            self.release();
+            return;
+        };
+
+        // Threads share the address space of their creator and are not waited for either.
+        let thread = Arc::ptr_eq(&self.vm, &parent.vm);
+
+        if thread || parent.reaps_children() {
             parent.remove_child(self);
+            self.release();
+
+            // Waiters fail with `ECHILD` if this was the last child.
+            parent.zombies.block.notify_all();
+        } else {
+            // The zombie is added before it is removed from the children, so it is always
+            // found by waiters.
             parent.zombies.add_zombie(self.this());
+            parent.remove_child(self);
+        }
 
-            if self.is_process_leader() {
-                parent.signal(aero_syscall::signal::SIGCHLD);
-            }
+        if !thread {
+            parent.notify_child(self.exit_info());
         }
     }
 
@@ -751,15 +1205,56 @@ impl Task {
         }
     }
 
-    pub fn attach(&self, terminal: Arc<dyn TerminalDevice>) {
-        if self.is_session_leader() {
-            terminal.attach(self.sref.upgrade().unwrap());
-            *self.controlling_terminal.lock_irq() = Some(terminal);
-        } else {
-            // FIXME: If its not the session leader then we needs to be a part of the the same
-            // session as the terminal's foreground group.
-            *self.controlling_terminal.lock_irq() = Some(terminal);
+    /// Makes `terminal` the controlling terminal of the session led by the task (`TIOCSCTTY`).
+    ///
+    /// Fails with [`FileSystemError::NotPermitted`] if the task is not a session leader, if its
+    /// session already has another controlling terminal or if `terminal` controls another
+    /// session.
+    pub fn attach(&self, terminal: Arc<dyn TerminalDevice>) -> fs::Result<()> {
+        let session = SESSIONS
+            .find(self.session_id())
+            .filter(|_| self.is_session_leader())
+            .ok_or(FileSystemError::NotPermitted)?;
+
+        if let Some(current) = session.controlling_tty() {
+            // Acquiring the controlling terminal of the session again does nothing.
+            if Arc::ptr_eq(&current, &terminal) {
+                return Ok(());
+            }
+
+            return Err(FileSystemError::NotPermitted);
         }
+
+        if SESSIONS.is_controlling(&terminal) {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        session.set_controlling_tty(Some(&terminal));
+        terminal.attach(self.this());
+        *self.controlling_terminal.lock_irq() = Some(terminal);
+        Ok(())
+    }
+
+    /// Makes the task lose its controlling terminal (`TIOCNOTTY`). If it is the session leader,
+    /// the session and all of its processes lose it.
+    pub fn disown_terminal(&self) -> fs::Result<()> {
+        if self.controlling_terminal.lock_irq().take().is_none() {
+            return Err(FileSystemError::NoTty);
+        }
+
+        if !self.is_session_leader() {
+            return Ok(());
+        }
+
+        if let Some(session) = SESSIONS.find(self.session_id()) {
+            session.set_controlling_tty(None);
+
+            for task in session.tasks() {
+                task.controlling_terminal.lock_irq().take();
+            }
+        }
+
+        Ok(())
     }
 
     /// Returns the controlling terminal of the task.
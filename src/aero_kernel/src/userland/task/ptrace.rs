@@ -0,0 +1,380 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Process tracing (`ptrace(2)`).
+//!
+//! A tracee only stops for its tracer at syscall entry and exit (when resumed with
+//! `PTRACE_SYSCALL`), when one of its hardware watchpoints triggers and on `SIGSTOP` or
+//! `SIGTSTP`. Other signals are delivered as usual.
+
+use aero_syscall::signal::{SigInfo, SIGSTOP, SIGTRAP, SI_USER, TRAP_HWBKPT};
+use aero_syscall::{PtraceRegs, PtraceWatchpoint, SyscallError, PTRACE_WATCHPOINT_COUNT};
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::arch::controlregs::RFlags;
+use crate::arch::interrupts::InterruptStack;
+use crate::arch::task::user_range_ok;
+use crate::mem::paging::{PageTableFlags, Translate, TranslateResult, VirtAddr};
+use crate::utils::sync::{Mutex, MutexGuard};
+
+use super::{StopReason, Task};
+
+pub struct Ptrace {
+    tracer: Mutex<Option<Weak<Task>>>,
+    tracees: Mutex<Vec<Arc<Task>>>,
+    /// Whether the tracee stops on the next syscall entry and exit.
+    trace_syscalls: AtomicBool,
+    /// Information about the signal that caused the last trace stop (`PTRACE_GETSIGINFO`).
+    siginfo: Mutex<Option<SigInfo>>,
+    /// Mask of the watchpoints that caused the last `SIGTRAP`.
+    triggered: AtomicU8,
+    /// Whether the task exited and its tracer is yet to wait for it, see
+    /// [`Task::ptrace_report_exit`].
+    exit_pending: AtomicBool,
+}
+
+impl Ptrace {
+    pub(super) fn new() -> Self {
+        Self {
+            tracer: Mutex::new(None),
+            tracees: Mutex::new(Vec::new()),
+            trace_syscalls: AtomicBool::new(false),
+            siginfo: Mutex::new(None),
+            triggered: AtomicU8::new(0),
+            exit_pending: AtomicBool::new(false),
+        }
+    }
+
+    pub(super) fn tracees(&self) -> MutexGuard<Vec<Arc<Task>>> {
+        self.tracees.lock_irq()
+    }
+}
+
+impl Task {
+    /// Returns the task tracing this task.
+    pub fn tracer(&self) -> Option<Arc<Task>> {
+        self.ptrace
+            .tracer
+            .lock_irq()
+            .as_ref()
+            .and_then(Weak::upgrade)
+    }
+
+    pub fn is_traced(&self) -> bool {
+        self.tracer().is_some()
+    }
+
+    pub(super) fn is_traced_by(&self, tracer: &Task) -> bool {
+        self.tracer()
+            .is_some_and(|this| core::ptr::eq(this.as_ref(), tracer))
+    }
+
+    fn set_tracer(&self, tracer: &Task) -> Result<(), SyscallError> {
+        let mut this = self.ptrace.tracer.lock_irq();
+
+        if this.as_ref().is_some_and(|t| t.strong_count() > 0) {
+            return Err(SyscallError::EPERM);
+        }
+
+        *this = Some(tracer.sref.clone());
+        tracer.ptrace.tracees.lock_irq().push(self.this());
+        Ok(())
+    }
+
+    /// Makes the parent of the current task its tracer (`PTRACE_TRACEME`).
+    pub fn ptrace_traceme(&self) -> Result<(), SyscallError> {
+        let parent = self.get_parent().ok_or(SyscallError::EPERM)?;
+        self.set_tracer(&parent)
+    }
+
+    /// Attaches to `tracee` and sends it a `SIGSTOP` (`PTRACE_ATTACH`).
+    pub fn ptrace_attach(&self, tracee: &Task) -> Result<(), SyscallError> {
+        if tracee.pid() == self.pid()
+            || tracee.process_leader().pid() == self.pid()
+            || tracee.has_exited()
+        {
+            return Err(SyscallError::EPERM);
+        }
+
+        tracee.set_tracer(self)?;
+        tracee.signal(SIGSTOP);
+        Ok(())
+    }
+
+    /// Returns the tracee with the provided `pid`.
+    pub fn tracee(&self, pid: usize) -> Result<Arc<Task>, SyscallError> {
+        self.ptrace
+            .tracees()
+            .iter()
+            .find(|e| e.pid().as_usize() == pid)
+            .cloned()
+            .ok_or(SyscallError::ESRCH)
+    }
+
+    /// Calls `f` with the user register frame of the tracee. The stop lock is held throughout,
+    /// so the tracee cannot be resumed while its frame or memory is accessed. Fails with `EBUSY`
+    /// if the tracee is currently running.
+    fn with_tracee_frame<R>(
+        &self,
+        f: impl FnOnce(&mut InterruptStack) -> Result<R, SyscallError>,
+    ) -> Result<R, SyscallError> {
+        match self.stop.lock_irq().as_mut() {
+            Some(stop) if matches!(stop.reason, StopReason::Trace(_)) => {
+                // SAFETY: The tracee is stopped and cannot be resumed while the lock is held, so
+                // its frame is valid and not being accessed.
+                f(unsafe { stop.frame.as_mut() })
+            }
+
+            _ => Err(SyscallError::EBUSY),
+        }
+    }
+
+    /// Resumes the stopped tracee. If `syscalls` is set, it stops again on the next syscall
+    /// entry or exit (`PTRACE_CONT` and `PTRACE_SYSCALL`).
+    pub fn ptrace_resume(&self, syscalls: bool) -> Result<(), SyscallError> {
+        self.with_tracee_frame(|_| Ok(()))?;
+        self.ptrace.trace_syscalls.store(syscalls, Ordering::SeqCst);
+        self.resume();
+        Ok(())
+    }
+
+    /// Detaches the tracer from this task and resumes it (`PTRACE_DETACH`). If the task exited
+    /// and the tracer did not wait for it, the exit is reported to the parent instead.
+    pub fn ptrace_detach(&self) {
+        if let Some(tracer) = self.ptrace.tracer.lock_irq().take() {
+            if let Some(tracer) = tracer.upgrade() {
+                tracer.ptrace.tracees().retain(|e| e.pid() != self.pid());
+            }
+        }
+
+        self.ptrace.trace_syscalls.store(false, Ordering::SeqCst);
+
+        if let Some(StopReason::Trace(_)) = self.stop_reason() {
+            self.resume();
+        }
+
+        if self.ptrace.exit_pending.swap(false, Ordering::SeqCst) {
+            self.notify_exit();
+        }
+    }
+
+    /// Detaches the task from all of its tracees on exit.
+    pub(super) fn ptrace_exit(&self) {
+        let tracees = core::mem::take(&mut *self.ptrace.tracees());
+        for tracee in tracees {
+            tracee.ptrace_detach();
+        }
+    }
+
+    /// Reports the exit of the task to its tracer if the tracer is not its parent. The parent
+    /// is only notified once the tracer waited for the task or detached from it. Returns
+    /// whether the exit is left to the tracer, otherwise the task is detached from its tracer.
+    pub(super) fn ptrace_report_exit(&self) -> bool {
+        let tracer = self.tracer().filter(|tracer| {
+            !self
+                .get_parent()
+                .is_some_and(|parent| Arc::ptr_eq(&parent, tracer))
+        });
+
+        let reported = tracer.as_ref().is_some_and(|tracer| {
+            // The tracer takes its tracees with this lock held when it exits, so the exit is
+            // either seen by it or the task is already detached.
+            let tracees = tracer.ptrace.tracees();
+            let traced = tracees.iter().any(|e| core::ptr::eq(e.as_ref(), self));
+
+            self.ptrace.exit_pending.store(traced, Ordering::SeqCst);
+            traced
+        });
+
+        match tracer {
+            Some(tracer) if reported => {
+                tracer.zombies.block.notify_all();
+                tracer.notify_child(self.exit_info());
+                true
+            }
+
+            _ => {
+                self.ptrace_detach();
+                false
+            }
+        }
+    }
+
+    /// Consumes the pending exit of the tracee, so it is reported to a single waiter.
+    pub(super) fn ptrace_take_exit(&self) -> bool {
+        self.ptrace.exit_pending.swap(false, Ordering::SeqCst)
+    }
+
+    /// Detaches the tracer from the exited tracee after the tracer waited for it, and hands the
+    /// tracee over to its parent.
+    pub(super) fn ptrace_exit_reported(&self) {
+        self.ptrace_detach();
+        self.notify_exit();
+    }
+
+    pub fn ptrace_get_regs(&self) -> Result<PtraceRegs, SyscallError> {
+        self.with_tracee_frame(|frame| Ok(frame.ptrace_regs()))
+    }
+
+    pub fn ptrace_set_regs(&self, regs: &PtraceRegs) -> Result<(), SyscallError> {
+        self.with_tracee_frame(|frame| {
+            if frame.set_ptrace_regs(regs) {
+                Ok(())
+            } else {
+                Err(SyscallError::EIO)
+            }
+        })
+    }
+
+    /// Translates the user address `addr` in the tracee's address space to its kernel mapping.
+    ///
+    /// Pages that are not mapped yet or are copy-on-write cannot be accessed and fail with `EIO`.
+    fn tracee_translate(&self, addr: VirtAddr, write: bool) -> Result<*mut u8, SyscallError> {
+        if !user_range_ok(addr.as_ptr(), 1) {
+            return Err(SyscallError::EIO);
+        }
+
+        let table = self.arch_task_mut().address_space_mut().offset_page_table();
+
+        match table.translate(addr) {
+            TranslateResult::Mapped {
+                frame,
+                offset,
+                flags,
+            } if flags.contains(PageTableFlags::USER_ACCESSIBLE)
+                && (!write || flags.contains(PageTableFlags::WRITABLE)) =>
+            {
+                Ok((frame.start_address() + offset).as_hhdm_virt().as_mut_ptr())
+            }
+
+            _ => Err(SyscallError::EIO),
+        }
+    }
+
+    /// Reads a word from the tracee's memory at `addr` (`PTRACE_PEEKDATA`).
+    pub fn ptrace_peek(&self, addr: usize) -> Result<u64, SyscallError> {
+        self.with_tracee_frame(|_| {
+            let mut word = [0u8; 8];
+
+            for (i, byte) in word.iter_mut().enumerate() {
+                let addr = VirtAddr::new(addr.wrapping_add(i) as u64);
+                let ptr = self.tracee_translate(addr, false)?;
+
+                // SAFETY: The page is mapped in the tracee's address space.
+                *byte = unsafe { ptr.read() };
+            }
+
+            Ok(u64::from_ne_bytes(word))
+        })
+    }
+
+    /// Writes a word to the tracee's memory at `addr` (`PTRACE_POKEDATA`).
+    pub fn ptrace_poke(&self, addr: usize, data: u64) -> Result<(), SyscallError> {
+        self.with_tracee_frame(|_| {
+            // Translate all of the bytes first so the write is not torn on failure.
+            let mut ptrs = [core::ptr::null_mut(); 8];
+
+            for (i, ptr) in ptrs.iter_mut().enumerate() {
+                *ptr = self.tracee_translate(VirtAddr::new(addr.wrapping_add(i) as u64), true)?;
+            }
+
+            for (ptr, byte) in ptrs.iter().zip(data.to_ne_bytes()) {
+                // SAFETY: The page is mapped writable in the tracee's address space.
+                unsafe { ptr.write(byte) };
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn ptrace_get_siginfo(&self) -> Result<SigInfo, SyscallError> {
+        self.with_tracee_frame(|_| self.ptrace.siginfo.lock_irq().ok_or(SyscallError::EINVAL))
+    }
+
+    /// Sets the hardware watchpoint at `index` of the stopped tracee.
+    pub fn ptrace_set_watchpoint(
+        &self,
+        index: usize,
+        watchpoint: &PtraceWatchpoint,
+    ) -> Result<(), SyscallError> {
+        // The debug registers are loaded when the tracee is switched to.
+        self.with_tracee_frame(|_| self.arch_task_mut().debug_regs.set(index, watchpoint))
+    }
+
+    /// Returns the hardware watchpoints of the stopped tracee and the mask of the ones that
+    /// caused its last `SIGTRAP`.
+    pub fn ptrace_get_watchpoints(
+        &self,
+    ) -> Result<([PtraceWatchpoint; PTRACE_WATCHPOINT_COUNT], u8), SyscallError> {
+        self.with_tracee_frame(|_| {
+            let regs = &self.arch_task().debug_regs;
+            let watchpoints = core::array::from_fn(|i| regs.get(i));
+
+            Ok((watchpoints, self.ptrace.triggered.load(Ordering::SeqCst)))
+        })
+    }
+
+    /// Stops the current task for its tracer, recording `info` for `PTRACE_GETSIGINFO`.
+    fn ptrace_stop(&self, info: SigInfo, frame: &mut InterruptStack) {
+        *self.ptrace.siginfo.lock_irq() = Some(info);
+        self.stop(StopReason::Trace(info.si_signo as usize), frame);
+    }
+
+    /// Stops the current task for its tracer on the stop `signal`.
+    pub fn ptrace_signal_stop(&self, signal: usize, frame: &mut InterruptStack) {
+        self.ptrace_stop(SigInfo::new(signal, SI_USER, 0), frame);
+    }
+
+    /// Stops the current task for its tracer on syscall entry or exit if requested by
+    /// `PTRACE_SYSCALL`.
+    pub fn ptrace_syscall_stop(&self, frame: &mut InterruptStack) {
+        if self.ptrace.trace_syscalls.load(Ordering::SeqCst) && self.is_traced() {
+            self.ptrace_stop(SigInfo::new(SIGTRAP, SIGTRAP as i32, 0), frame);
+        }
+    }
+
+    /// Handles a debug exception raised in userland. `triggered` is the mask of the watchpoints
+    /// that caused it.
+    ///
+    /// The tracer is notified with a `SIGTRAP` stop whose `si_code` is `TRAP_HWBKPT` and whose
+    /// `si_addr` is the address of the first watchpoint that triggered. Debug exceptions that are
+    /// not caused by a watchpoint are delivered as a `SIGTRAP` signal.
+    pub fn ptrace_debug_trap(&self, triggered: u8, frame: &mut InterruptStack) {
+        let regs = self.arch_task().debug_regs;
+        let triggered = triggered & regs.enabled();
+
+        if triggered == 0 || !self.is_traced() {
+            self.signal(SIGTRAP);
+            return;
+        }
+
+        // Execute watchpoints trigger before the instruction runs. Resume flag suppresses them
+        // for one instruction, so the tracee does not trap again on the same one.
+        if (0..PTRACE_WATCHPOINT_COUNT).any(|i| triggered & (1 << i) != 0 && regs.is_execute(i)) {
+            frame.iret.rflags |= RFlags::RESUME_FLAG.bits();
+        }
+
+        self.ptrace.triggered.store(triggered, Ordering::SeqCst);
+
+        let addr = regs.addr(triggered.trailing_zeros() as usize);
+        self.ptrace_stop(SigInfo::new(SIGTRAP, TRAP_HWBKPT, addr), frame);
+    }
+}
@@ -15,9 +15,11 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use hashbrown::HashMap;
 
+use crate::userland::terminal::TerminalDevice;
 use crate::utils::sync::Mutex;
 
 use super::{Task, TaskId};
@@ -65,6 +67,11 @@ impl Group {
         self.tasks.lock_irq().is_empty()
     }
 
+    /// Returns the processes part of the process group.
+    pub fn tasks(&self) -> Vec<Arc<Task>> {
+        self.tasks.lock_irq().values().cloned().collect()
+    }
+
     pub fn signal(&self, target: usize) {
         for (_, task) in self.tasks.lock_irq().iter() {
             log::error!("Sending signal to task: {:?}", task.path());
@@ -77,6 +84,8 @@ impl Group {
 /// Process Session
 pub struct Session {
     groups: Mutex<HashMap<usize, Arc<Group>>>,
+    /// Controlling terminal of the session, acquired by the session leader.
+    controlling_tty: Mutex<Option<Weak<dyn TerminalDevice>>>,
 }
 
 impl Session {
@@ -89,11 +98,32 @@ impl Session {
 
         Arc::new(Self {
             groups: Mutex::new(groups),
+            controlling_tty: Mutex::new(None),
         })
     }
 
+    /// Returns the controlling terminal of the session.
+    pub fn controlling_tty(&self) -> Option<Arc<dyn TerminalDevice>> {
+        self.controlling_tty.lock_irq().as_ref()?.upgrade()
+    }
+
+    pub fn set_controlling_tty(&self, terminal: Option<&Arc<dyn TerminalDevice>>) {
+        *self.controlling_tty.lock_irq() = terminal.map(Arc::downgrade);
+    }
+
+    /// Returns the processes part of the session.
+    pub fn tasks(&self) -> Vec<Arc<Task>> {
+        let groups = self.groups.lock_irq().values().cloned().collect::<Vec<_>>();
+        groups.iter().flat_map(|group| group.tasks()).collect()
+    }
+
     pub fn find(&self, target: &Arc<Task>) -> Option<Arc<Group>> {
-        self.groups.lock_irq().get(&target.group_id()).cloned()
+        self.find_by_id(target.group_id())
+    }
+
+    /// Returns the process group `group_id` of the session.
+    pub fn find_by_id(&self, group_id: usize) -> Option<Arc<Group>> {
+        self.groups.lock_irq().get(&group_id).cloned()
     }
 
     pub fn register_task(&self, task: Arc<Task>) {
@@ -138,10 +168,39 @@ impl SessionList {
             .insert(leader.pid().as_usize(), Session::new(leader));
     }
 
+    /// Returns the session `session_id`.
+    pub fn find(&self, session_id: usize) -> Option<Arc<Session>> {
+        self.0.lock_irq().get(&session_id).cloned()
+    }
+
+    /// Returns whether `terminal` is the controlling terminal of a session.
+    pub fn is_controlling(&self, terminal: &Arc<dyn TerminalDevice>) -> bool {
+        let sessions = self.0.lock_irq().values().cloned().collect::<Vec<_>>();
+
+        sessions.iter().any(|session| {
+            session
+                .controlling_tty()
+                .is_some_and(|tty| Arc::ptr_eq(&tty, terminal))
+        })
+    }
+
     pub fn find_group(&self, target: &Arc<Task>) -> Option<Arc<Group>> {
         self.0.lock_irq().get(&target.session_id())?.find(target)
     }
 
+    /// Returns the process group `group_id` if it is part of the session `session_id`.
+    pub fn find_group_in(&self, session_id: usize, group_id: usize) -> Option<Arc<Group>> {
+        self.0.lock_irq().get(&session_id)?.find_by_id(group_id)
+    }
+
+    /// Returns the process group `group_id`, whichever session it is part of.
+    pub fn find_group_by_id(&self, group_id: usize) -> Option<Arc<Group>> {
+        self.0
+            .lock_irq()
+            .values()
+            .find_map(|session| session.find_by_id(group_id))
+    }
+
     pub fn register_task(&self, task: Arc<Task>) {
         assert!(task.is_process_leader());
 
@@ -172,13 +231,9 @@ impl SessionList {
     pub fn isolate(&self, task: &Arc<Task>) {
         assert!(!task.is_group_leader() && !task.is_session_leader());
 
+        // The process leaves its session, which lives on with the other processes.
         let leader = task.process_leader();
-
-        {
-            let mut sessions = self.0.lock_irq();
-            sessions.remove(&task.session_id());
-        }
-
+        self.remove_task(&leader);
         self.create_session(leader)
     }
 }
@@ -18,6 +18,7 @@
 use core::fmt::Write;
 use core::ops::Range;
 
+use aero_syscall::consts::SealFlags;
 use aero_syscall::{MMapFlags, MMapProt};
 
 use alloc::boxed::Box;
@@ -34,7 +35,7 @@ use crate::arch::task::userland_last_address;
 use crate::fs::block::PageCacheItem;
 use crate::fs::cache::{DirCacheImpl, DirCacheItem};
 use crate::fs::file_table::FileHandle;
-use crate::fs::inode::MMapPage;
+use crate::fs::inode::{DirEntry, MMapPage, MappableRegion};
 use crate::fs::{FileSystemError, Path};
 use crate::mem::paging::*;
 use crate::mem::AddressSpace;
@@ -63,6 +64,89 @@ bitflags::bitflags! {
 const VM_PROT_MASK: VmFlag =
     VmFlag::from_bits_retain(VmFlag::READ.bits() | VmFlag::WRITE.bits() | VmFlag::EXEC.bits());
 
+/// Marks a page table entry whose frame is charged to the VM (see [`MemCharge`]).
+const CHARGED: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Resident memory accounting of a VM.
+///
+/// The private frames mapped into the VM are charged to it: anonymous pages and private copies
+/// of file pages. Page cache pages that are mapped directly are not. The page table entries of
+/// charged pages are marked with [`CHARGED`] so that they are uncharged when unmapped. The
+/// marks are not copied on fork, so a child is only charged for the pages it copies on write.
+struct MemCharge {
+    /// Number of charged pages.
+    resident: usize,
+    /// Maximum number of charged pages, if limited.
+    limit: Option<usize>,
+    /// Set when a charge failed due to the limit.
+    limit_hit: bool,
+}
+
+impl MemCharge {
+    const fn new() -> Self {
+        Self {
+            resident: 0,
+            limit: None,
+            limit_hit: false,
+        }
+    }
+
+    /// Charges a page to the VM. Returns `false` if that would exceed the limit.
+    #[must_use]
+    fn try_charge(&mut self) -> bool {
+        if self.limit.is_some_and(|limit| self.resident >= limit) {
+            self.limit_hit = true;
+            return false;
+        }
+
+        self.resident += 1;
+        true
+    }
+
+    fn uncharge(&mut self) {
+        debug_assert!(self.resident > 0, "uncharging an uncharged page");
+        self.resident = self.resident.saturating_sub(1);
+    }
+}
+
+/// Returns the [`CHARGED`] flag of the page table entry mapping `address`, if any.
+fn charged_flag(offset_table: &OffsetPageTable, address: VirtAddr) -> PageTableFlags {
+    match offset_table.translate(address) {
+        TranslateResult::Mapped { flags, .. } => flags & CHARGED,
+        _ => PageTableFlags::empty(),
+    }
+}
+
+/// The outcome of a user page fault.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageFaultResult {
+    /// The faulting page has been mapped.
+    Handled,
+    /// The access does not fall within a mapping that permits it.
+    Invalid,
+    /// Resolving the fault would exceed the memory limit of the VM.
+    LimitExceeded,
+}
+
+/// Allocates a frame holding a private copy of the first `size` bytes of the cached page, the
+/// rest of it being zeroed.
+fn copy_cached_page(page_cache: &PageCacheItem, size: usize) -> PhysFrame {
+    let page: Page = Page::containing_address(page_cache.data_addr().as_hhdm_virt());
+
+    let new_frame: PhysFrame = PhysFrame::containing_address(
+        FRAME_ALLOCATOR
+            .alloc_zeroed(Size4KiB::SIZE as usize)
+            .unwrap(),
+    );
+
+    let new_slice = new_frame.as_slice_mut::<u8>();
+    new_slice[..size].copy_from_slice(unsafe {
+        core::slice::from_raw_parts(page.start_address().as_ptr::<u8>(), size)
+    });
+
+    new_frame
+}
+
 impl From<MMapProt> for VmFlag {
     #[inline]
     fn from(value: MMapProt) -> Self {
@@ -368,6 +452,26 @@ impl MMapFile {
             mappings: HashMap::new(),
         }
     }
+
+    /// Returns the offset into the file at which the mapping starts.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the directory entry of the file backing the mapping.
+    #[inline]
+    pub fn file(&self) -> &DirCacheItem {
+        &self.file
+    }
+
+    /// Gives the copy of the mapping in a forked child its own file, if the inode asks for one.
+    /// See [`fork_mapping`](crate::fs::inode::INodeInterface::fork_mapping).
+    fn fork(&mut self) {
+        if let Some(inode) = self.file.inode().fork_mapping() {
+            self.file = DirEntry::from_inode(inode, self.file.name());
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -400,10 +504,23 @@ impl Mapping {
         self.flags & VM_PROT_MASK
     }
 
+    #[inline]
+    pub fn is_shared(&self) -> bool {
+        self.flags.contains(VmFlag::SHARED)
+    }
+
+    /// Returns whether writes through the mapping reach the backing file, now or after it is
+    /// made writable with `mprotect`.
+    #[inline]
+    pub fn is_shared_writable(&self) -> bool {
+        self.flags.contains(VmFlag::SHARED | VmFlag::MAY_WRITE)
+    }
+
     /// Handler routine for private anonymous pages. Since its an anonymous page is not
     /// backed by a file, we have to alloctate a frame and map it at the faulted address.
     fn handle_pf_private_anon(
         &mut self,
+        charge: &mut MemCharge,
         offset_table: &mut OffsetPageTable,
         reason: PageFaultErrorCode,
         address: VirtAddr,
@@ -411,6 +528,10 @@ impl Mapping {
         let addr_aligned = address.align_down(Size4KiB::SIZE);
 
         if !reason.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+            if !charge.try_charge() {
+                return false;
+            }
+
             let frame: PhysFrame =
                 PhysFrame::containing_address(pmm_alloc(BuddyOrdering::Size4KiB));
 
@@ -421,7 +542,10 @@ impl Mapping {
                     // NOTE: We dont need to remove the writeable flag from this mapping, since
                     // the writeable flag will be removed from the parent and child on fork so,
                     // the mapping gets copied on write.
-                    PageTableFlags::USER_ACCESSIBLE | PageTableFlags::PRESENT | self.flags.into(),
+                    PageTableFlags::USER_ACCESSIBLE
+                        | PageTableFlags::PRESENT
+                        | CHARGED
+                        | self.flags.into(),
                 )
             }
             .expect("Failed to identity map userspace private mapping")
@@ -429,7 +553,7 @@ impl Mapping {
 
             true
         } else if reason.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
-            self.handle_cow(offset_table, addr_aligned, false)
+            self.handle_cow(charge, offset_table, addr_aligned, false)
         } else {
             if !self.refresh_flags {
                 return false;
@@ -439,11 +563,14 @@ impl Mapping {
                 // The page is present but most likely the flags need to be updated after
                 // mprotect(2).
                 let page: Page<Size4KiB> = Page::containing_address(address);
+                let charged = charged_flag(offset_table, address);
+
                 offset_table
                     .update_flags(
                         page,
                         PageTableFlags::USER_ACCESSIBLE
                             | PageTableFlags::PRESENT
+                            | charged
                             | self.flags.into(),
                     )
                     .unwrap()
@@ -460,6 +587,7 @@ impl Mapping {
     /// the allocated frame at the faulted address.
     fn handle_pf_file(
         &mut self,
+        charge: &mut MemCharge,
         offset_table: &mut OffsetPageTable,
         reason: PageFaultErrorCode,
         addr: VirtAddr,
@@ -473,18 +601,88 @@ impl Mapping {
             let addr = addr.align_down(Size4KiB::SIZE);
             let size = Size4KiB::SIZE.min(file.size as u64 - (addr - self.start_addr));
 
+            // Device memory is mapped in one go, as far as the region the device hands out
+            // goes, instead of a page per fault.
+            match file
+                .file
+                .inode()
+                .mmap_region(offset as usize, (self.end_addr - addr) as usize)
+            {
+                Ok(region) => return self.map_region(offset_table, reason, addr, region),
+                Err(FileSystemError::NotSupported) => {}
+                Err(_) => return false,
+            }
+
             return if self.flags.contains(VmFlag::SHARED) {
                 self.handle_pf_shared_file(offset_table, reason, addr, offset as _, size as _)
             } else {
-                self.handle_pf_private_file(offset_table, reason, addr, offset as _, size as _)
+                self.handle_pf_private_file(
+                    charge,
+                    offset_table,
+                    reason,
+                    addr,
+                    offset as _,
+                    size as _,
+                )
             };
         }
 
         false
     }
 
+    /// Maps the frames of `region` from `addr` onwards, skipping the pages that are already
+    /// mapped.
+    fn map_region(
+        &self,
+        offset_table: &mut OffsetPageTable,
+        reason: PageFaultErrorCode,
+        addr: VirtAddr,
+        region: MappableRegion,
+    ) -> bool {
+        if reason.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+            || (reason.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
+                && !self.flags.contains(VmFlag::WRITE))
+        {
+            return false;
+        }
+
+        let flags = if self.flags.contains(VmFlag::SHARED) {
+            self.flags
+        } else if self.flags.contains(VmFlag::MAY_WRITE) {
+            // TODO: Support writable private mappings of device memory.
+            return false;
+        } else {
+            // Read-only shared mappings are turned into private ones, in which case the frames
+            // can be mapped directly since they are never written through this mapping.
+            self.flags & !VmFlag::WRITE
+        };
+
+        let flags = PageTableFlags::PRESENT
+            | PageTableFlags::USER_ACCESSIBLE
+            | flags.into()
+            | region.cache().page_table_flags();
+
+        let pages = Page::range(
+            Page::containing_address(addr),
+            Page::containing_address(self.end_addr),
+        );
+
+        for (page, frame) in pages.zip(region.frames()) {
+            if offset_table.translate_addr(page.start_address()).is_some() {
+                continue;
+            }
+
+            unsafe { offset_table.map_to(page, frame, flags) }
+                .expect("failed to map device memory")
+                .flush();
+        }
+
+        true
+    }
+
     fn handle_pf_private_file(
         &mut self,
+        charge: &mut MemCharge,
         offset_table: &mut OffsetPageTable,
         reason: PageFaultErrorCode,
 
@@ -493,65 +691,78 @@ impl Mapping {
         size: usize,
     ) -> bool {
         let mmap_file = self.file.as_mut().unwrap();
-        let page_cache = if let MMapPage::PageCache(page_cache) =
-            mmap_file.file.inode().mmap_v2(offset).unwrap()
-        {
-            page_cache
-        } else {
-            todo!()
+
+        // TODO: Support writable private mappings of files that are not backed by the page cache.
+        let page_cache = match mmap_file.file.inode().mmap_v2(offset) {
+            Ok(MMapPage::PageCache(page_cache)) => page_cache,
+
+            // Read-only shared mappings are turned into private ones, in which case the frame
+            // can be mapped directly since it is never written through this mapping.
+            Ok(MMapPage::Direct(frame))
+                if !self.flags.contains(VmFlag::MAY_WRITE)
+                    && !reason.contains(PageFaultErrorCode::CAUSED_BY_WRITE) =>
+            {
+                unsafe {
+                    offset_table.map_to(
+                        Page::containing_address(addr),
+                        frame,
+                        PageTableFlags::PRESENT
+                            | PageTableFlags::USER_ACCESSIBLE
+                            | (self.flags & !VmFlag::WRITE).into(),
+                    )
+                }
+                .expect("failed to map frame for read-only file mapping")
+                .flush();
+
+                return true;
+            }
+
+            _ => return false,
         };
 
         if !reason.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
             && !reason.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
         {
+            let mut flags = PageTableFlags::PRESENT
+                | PageTableFlags::USER_ACCESSIBLE
+                | (self.flags & !VmFlag::WRITE).into();
+
             let frame = if size == Size4KiB::SIZE as usize {
                 page_cache.page()
             } else {
-                // The end needs to be zeroed out so we cannot directly map the cached page.
-                let page: Page = Page::containing_address(page_cache.data_addr().as_hhdm_virt());
-
-                let new_frame: PhysFrame = PhysFrame::containing_address(
-                    FRAME_ALLOCATOR
-                        .alloc_zeroed(Size4KiB::SIZE as usize)
-                        .unwrap(),
-                );
+                if !charge.try_charge() {
+                    return false;
+                }
 
-                let new_slice = new_frame.as_slice_mut::<u8>();
-                new_slice[..size].copy_from_slice(unsafe {
-                    core::slice::from_raw_parts(page.start_address().as_ptr::<u8>(), size)
-                });
+                flags.insert(CHARGED);
 
-                new_frame
+                // The end needs to be zeroed out so we cannot directly map the cached page.
+                copy_cached_page(&page_cache, size)
             };
 
-            unsafe {
-                offset_table.map_to(
-                    Page::containing_address(addr),
-                    frame,
-                    PageTableFlags::PRESENT
-                        | PageTableFlags::USER_ACCESSIBLE
-                        | (self.flags & !VmFlag::WRITE).into(),
-                )
-            }
-            .expect("failed to map allocated frame for private file read")
-            .flush();
+            unsafe { offset_table.map_to(Page::containing_address(addr), frame, flags) }
+                .expect("failed to map allocated frame for private file read")
+                .flush();
 
             true
         } else if !reason.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
             && reason.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
         {
             // We are writing to private file mapping so copy the content of the page.
-            let frame = mmap_file
-                .file
-                .inode()
-                .mmap(offset, size, MMapFlags::empty())
-                .expect("handle_pf_file: file does not support mmap");
+            if !charge.try_charge() {
+                return false;
+            }
+
+            let frame = copy_cached_page(&page_cache, size);
 
             unsafe {
                 offset_table.map_to(
                     Page::containing_address(addr),
                     frame,
-                    PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | self.flags.into(),
+                    PageTableFlags::PRESENT
+                        | PageTableFlags::USER_ACCESSIBLE
+                        | CHARGED
+                        | self.flags.into(),
                 )
             }
             .expect("failed to map allocated frame for private file read")
@@ -562,7 +773,7 @@ impl Mapping {
         } else if reason.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
             && reason.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
         {
-            if self.handle_cow(offset_table, addr, true) {
+            if self.handle_cow(charge, offset_table, addr, true) {
                 self.file.as_mut().unwrap().mappings.remove(&addr);
                 return true;
             }
@@ -584,7 +795,9 @@ impl Mapping {
         _size: usize,
     ) -> bool {
         let mmap_file = self.file.as_mut().unwrap();
-        let mmap_page = mmap_file.file.inode().mmap_v2(offset).unwrap();
+        let Ok(mmap_page) = mmap_file.file.inode().mmap_v2(offset) else {
+            return false;
+        };
 
         if reason.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
             if reason.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
@@ -644,7 +857,8 @@ impl Mapping {
     }
 
     /// Copies the contents of the `page` page to a newly allocated frame and maps it to
-    /// the `page` page with the provided `protection` protection flags.
+    /// the `page` page with the provided `protection` protection flags. The caller is
+    /// responsible for charging the new frame.
     fn map_copied(
         offset_table: &mut OffsetPageTable,
         page: Page<Size4KiB>,
@@ -675,7 +889,10 @@ impl Mapping {
                 .map_to(
                     page,
                     new_frame,
-                    PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | flags.into(),
+                    PageTableFlags::PRESENT
+                        | PageTableFlags::USER_ACCESSIBLE
+                        | CHARGED
+                        | flags.into(),
                 )?
                 .flush();
         }
@@ -692,6 +909,7 @@ impl Mapping {
     /// * The provided `address` is not aligned to a page boundary.
     fn handle_cow(
         &mut self,
+        charge: &mut MemCharge,
         offset_table: &mut OffsetPageTable,
         address: VirtAddr,
         copy: bool,
@@ -700,10 +918,16 @@ impl Mapping {
 
         let page: Page<Size4KiB> = Page::containing_address(address);
 
-        if let TranslateResult::Mapped { frame, .. } = offset_table.translate(address) {
+        if let TranslateResult::Mapped { frame, flags, .. } = offset_table.translate(address) {
             let phys_addr = frame.start_address();
 
             if let Some(vm_frame) = phys_addr.as_vm_frame() {
+                // Pages inherited on fork are charged to the parent. Either way, the page ends
+                // up private to this VM.
+                if !flags.contains(CHARGED) && !charge.try_charge() {
+                    return false;
+                }
+
                 if vm_frame.ref_count() > 1 || copy {
                     // This page is used by more then one process, so make it a private copy.
                     Self::map_copied(offset_table, page, self.flags).unwrap();
@@ -714,6 +938,7 @@ impl Mapping {
                             page,
                             PageTableFlags::PRESENT
                                 | PageTableFlags::USER_ACCESSIBLE
+                                | CHARGED
                                 | self.flags.into(),
                         )
                     }
@@ -730,6 +955,7 @@ impl Mapping {
 
     fn unmap(
         &mut self,
+        charge: &mut MemCharge,
         offset_table: &mut OffsetPageTable,
         start: VirtAddr,
         end: VirtAddr,
@@ -737,8 +963,16 @@ impl Mapping {
         let mut unmap_range_inner = |range: Range<VirtAddr>| -> Result<(), UnmapError> {
             for addr in range.step_by(Size4KiB::SIZE as usize) {
                 let page: Page = Page::containing_address(addr);
+                let charged = charged_flag(offset_table, addr);
+
                 match offset_table.unmap(page) {
-                    Ok((_, flusher)) => flusher.flush(),
+                    Ok((_, flusher)) => {
+                        flusher.flush();
+
+                        if !charged.is_empty() {
+                            charge.uncharge();
+                        }
+                    }
                     Err(UnmapError::PageNotMapped) => {}
                     Err(e) => return Err(e),
                 }
@@ -830,12 +1064,14 @@ impl Mapping {
 
 struct VmProtected {
     mappings: LinkedList<Mapping>,
+    charge: MemCharge,
 }
 
 impl VmProtected {
     fn new() -> Self {
         Self {
             mappings: LinkedList::new(),
+            charge: MemCharge::new(),
         }
     }
 
@@ -869,13 +1105,19 @@ impl VmProtected {
             let mut offset_table = address_space.offset_page_table();
 
             match (!map.flags.contains(VmFlag::SHARED), map.file.is_none()) {
-                (true, true) => {
-                    map.handle_pf_private_anon(&mut offset_table, reason, accessed_address)
-                }
-
-                (true | false, false) => {
-                    map.handle_pf_file(&mut offset_table, reason, accessed_address)
-                }
+                (true, true) => map.handle_pf_private_anon(
+                    &mut self.charge,
+                    &mut offset_table,
+                    reason,
+                    accessed_address,
+                ),
+
+                (true | false, false) => map.handle_pf_file(
+                    &mut self.charge,
+                    &mut offset_table,
+                    reason,
+                    accessed_address,
+                ),
 
                 (false, true) => unreachable!("shared and anonymous mapping"),
             }
@@ -1246,7 +1488,8 @@ impl VmProtected {
     /// Clears all of the mappings without unmapping them. The caller is responsible
     /// for going through the page table and unmapping all of the pages.
     fn clear(&mut self) {
-        self.mappings.clear()
+        self.mappings.clear();
+        self.charge.resident = 0;
     }
 
     fn munmap(&mut self, address: VirtAddr, size: usize) -> bool {
@@ -1263,7 +1506,7 @@ impl VmProtected {
             if map.end_addr <= start {
                 cursor.move_next();
             } else {
-                match map.unmap(&mut offset_table, start, end) {
+                match map.unmap(&mut self.charge, &mut offset_table, start, end) {
                     Ok(result) => match result {
                         UnmapResult::None => return success,
                         UnmapResult::Start => return true,
@@ -1352,6 +1595,11 @@ impl VmProtected {
         {
             let parent = parent.inner.lock();
             self.mappings.clone_from(&parent.mappings);
+            self.charge.limit = parent.charge.limit;
+        }
+
+        for file in self.mappings.iter_mut().filter_map(|map| map.file.as_mut()) {
+            file.fork();
         }
 
         let mut address_space = AddressSpace::new().unwrap();
@@ -1364,7 +1612,8 @@ impl VmProtected {
             // Do not copy page table entries where a page fault can map them correctly.
             !map.flags.contains(VmFlag::SHARED) && map.flags.contains(VmFlag::MAY_WRITE)
         }) {
-            offset_table.copy_page_range(&mut current, map.start_addr..=map.end_addr);
+            // The pages stay charged to the parent until the child copies them on write.
+            offset_table.copy_page_range(&mut current, map.start_addr..=map.end_addr, CHARGED);
         }
 
         address_space
@@ -1401,7 +1650,11 @@ impl Vm {
             (MMapFlags::MAP_SHARED, Some(file)) => {
                 vm_flags.insert(VmFlag::SHARED);
 
-                if !file.is_writable() {
+                let write_sealed = file.inode().seals().is_ok_and(|seals| {
+                    seals.intersects(SealFlags::WRITE | SealFlags::FUTURE_WRITE)
+                });
+
+                if !file.is_writable() || write_sealed {
                     if protection.contains(MMapProt::PROT_WRITE) {
                         return None; // EACCES
                     }
@@ -1476,10 +1729,37 @@ impl Vm {
         &self,
         reason: PageFaultErrorCode,
         accessed_address: VirtAddr,
-    ) -> bool {
+    ) -> PageFaultResult {
+        let mut this = self.inner.lock();
+
+        if this.handle_page_fault(reason, accessed_address) {
+            PageFaultResult::Handled
+        } else if core::mem::take(&mut this.charge.limit_hit) {
+            PageFaultResult::LimitExceeded
+        } else {
+            PageFaultResult::Invalid
+        }
+    }
+
+    /// Returns the amount of memory charged to the VM in bytes.
+    pub fn resident_size(&self) -> usize {
+        self.inner.lock().charge.resident * Size4KiB::SIZE as usize
+    }
+
+    /// Returns the memory limit of the VM in bytes, if any.
+    pub fn memory_limit(&self) -> Option<usize> {
         self.inner
             .lock()
-            .handle_page_fault(reason, accessed_address)
+            .charge
+            .limit
+            .map(|pages| pages * Size4KiB::SIZE as usize)
+    }
+
+    /// Sets the memory limit of the VM, rounded down to a page boundary. Page faults that would
+    /// take the resident memory of the VM over the limit fail. Lowering the limit below the
+    /// current usage does not reclaim any memory.
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        self.inner.lock().charge.limit = limit.map(|bytes| bytes / Size4KiB::SIZE as usize);
     }
 
     pub fn for_each_mapping<F>(&self, mut f: F)
@@ -0,0 +1,405 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Lock dependency tracker (`lockdep`).
+//!
+//! Every [`Mutex`], [`BMutex`] and [`RwLock`] belongs to a lock class, which is the location
+//! where the lock was created. All of the inodes of a filesystem, for example, share the lock
+//! class of the `RwLock` created in the inode constructor.
+//!
+//! Each CPU keeps a stack of the locks it holds. When a lock is acquired, a dependency from the
+//! class of every held lock to the class of the acquired one is added to the dependency graph.
+//! The first time a new dependency closes a cycle in the graph, the locks can be acquired in
+//! conflicting orders (for example, A then B on one path and B then A on another), which
+//! deadlocks once both paths run at the same time. This is reported with the backtrace that
+//! recorded the conflicting dependency and the current backtrace, whether or not the deadlock
+//! actually happens in this run.
+//!
+//! Known dependencies only cost a bit test. The held-lock stack is switched together with the
+//! task, as blocking locks ([`BMutex`]) can be held across a context switch.
+//!
+//! [`Mutex`]: super::sync::Mutex
+//! [`BMutex`]: super::sync::BMutex
+//! [`RwLock`]: super::sync::RwLock
+
+use core::panic::Location;
+use core::sync::atomic::{AtomicPtr, AtomicU16, AtomicU64, AtomicUsize, Ordering};
+
+use crate::arch::cpu_local;
+use crate::unwind;
+use crate::utils::sync::IrqGuard;
+
+/// Maximum number of lock classes. Locks created after the class table is full are not tracked.
+const MAX_CLASSES: usize = 512;
+/// Maximum number of locks held by a CPU at once. Deeper nesting is not tracked.
+const MAX_HELD: usize = 32;
+/// Maximum number of dependencies with a recorded backtrace.
+const MAX_TRACES: usize = 1024;
+/// Number of frames recorded per backtrace.
+const TRACE_DEPTH: usize = 8;
+
+/// Marks a lock class ID that has not been looked up yet.
+const NO_CLASS: u16 = u16::MAX;
+
+static CLASSES: [AtomicPtr<Location<'static>>; MAX_CLASSES] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CLASSES];
+
+/// Adjacency matrix of the dependency graph. Bit `to` of row `from` is set if a lock of class
+/// `to` has been acquired while holding a lock of class `from`.
+static DEPENDENCIES: [AtomicU64; MAX_CLASSES * MAX_CLASSES / 64] =
+    [const { AtomicU64::new(0) }; MAX_CLASSES * MAX_CLASSES / 64];
+
+/// Protects the recorded backtraces and the cycle search. Not tracked itself and only taken with
+/// interrupts disabled.
+static GRAPH: spin::Mutex<Graph> = spin::Mutex::new(Graph::new());
+
+/// Number of reported lock ordering cycles.
+static CYCLES: AtomicUsize = AtomicUsize::new(0);
+
+#[cpu_local]
+static mut HELD_LOCKS: HeldLocks = HeldLocks::new();
+
+/// The lock class of a lock, keyed by the location where the lock was created.
+pub struct LockClass {
+    key: &'static Location<'static>,
+    id: AtomicU16,
+}
+
+impl LockClass {
+    #[track_caller]
+    pub const fn new() -> Self {
+        Self {
+            key: Location::caller(),
+            id: AtomicU16::new(NO_CLASS),
+        }
+    }
+
+    /// Returns the ID of the class, registering it on first use. Returns [`None`] if the class
+    /// table is full.
+    fn id(&self) -> Option<u16> {
+        match self.id.load(Ordering::Relaxed) {
+            NO_CLASS => {
+                let id = register_class(self.key)?;
+                self.id.store(id, Ordering::Relaxed);
+                Some(id)
+            }
+
+            id => Some(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HeldLock {
+    class: u16,
+    lock: usize,
+}
+
+/// The stack of locks held by a CPU or, while it is switched out, by a task.
+#[derive(Clone)]
+pub struct HeldLocks {
+    locks: [HeldLock; MAX_HELD],
+    len: usize,
+    /// Set while the tracker itself runs (for example, while logging a report) to not track its
+    /// own lock acquisitions.
+    busy: bool,
+}
+
+impl HeldLocks {
+    pub const fn new() -> Self {
+        Self {
+            locks: [HeldLock { class: 0, lock: 0 }; MAX_HELD],
+            len: 0,
+            busy: false,
+        }
+    }
+
+    fn held(&self) -> &[HeldLock] {
+        &self.locks[..self.len]
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Trace {
+    from: u16,
+    to: u16,
+    frames: [usize; TRACE_DEPTH],
+    len: usize,
+}
+
+impl Trace {
+    const EMPTY: Self = Self {
+        from: 0,
+        to: 0,
+        frames: [0; TRACE_DEPTH],
+        len: 0,
+    };
+
+    fn frames(&self) -> &[usize] {
+        &self.frames[..self.len]
+    }
+}
+
+struct Graph {
+    traces: [Trace; MAX_TRACES],
+    traces_len: usize,
+
+    // Scratch space of the cycle search.
+    visited: [u64; MAX_CLASSES / 64],
+    stack: [u16; MAX_CLASSES],
+}
+
+impl Graph {
+    const fn new() -> Self {
+        Self {
+            traces: [Trace::EMPTY; MAX_TRACES],
+            traces_len: 0,
+
+            visited: [0; MAX_CLASSES / 64],
+            stack: [0; MAX_CLASSES],
+        }
+    }
+
+    fn record_trace(&mut self, from: u16, to: u16) {
+        if self.traces_len == MAX_TRACES {
+            return;
+        }
+
+        let trace = &mut self.traces[self.traces_len];
+
+        trace.from = from;
+        trace.to = to;
+        trace.len = unwind::capture_stack_trace(&mut trace.frames);
+
+        self.traces_len += 1;
+    }
+
+    fn trace(&self, from: u16, to: u16) -> Option<Trace> {
+        self.traces[..self.traces_len]
+            .iter()
+            .find(|trace| trace.from == from && trace.to == to)
+            .copied()
+    }
+
+    /// Searches for a path of dependencies from `from` to `to` and returns the class that
+    /// precedes `to` on it.
+    fn find_path(&mut self, from: u16, to: u16) -> Option<u16> {
+        self.visited.fill(0);
+        self.visited[from as usize / 64] |= 1 << (from % 64);
+
+        self.stack[0] = from;
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let class = self.stack[len];
+
+            let row = class as usize * (MAX_CLASSES / 64);
+
+            for word in 0..MAX_CLASSES / 64 {
+                let mut bits = DEPENDENCIES[row + word].load(Ordering::Relaxed);
+
+                while bits != 0 {
+                    let next = (word * 64 + bits.trailing_zeros() as usize) as u16;
+                    bits &= bits - 1;
+
+                    if next == to {
+                        return Some(class);
+                    }
+
+                    if self.visited[word] & (1 << (next % 64)) == 0 {
+                        self.visited[word] |= 1 << (next % 64);
+
+                        self.stack[len] = next;
+                        len += 1;
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn register_class(key: &'static Location<'static>) -> Option<u16> {
+    // Locations of the same call site are equal, but not necessarily at the same address.
+    let hash = (key.line() as usize)
+        .wrapping_mul(31)
+        .wrapping_add(key.column() as usize);
+
+    for probe in 0..MAX_CLASSES {
+        let index = (hash + probe) % MAX_CLASSES;
+        let slot = &CLASSES[index];
+        let key_ptr = key as *const _ as *mut _;
+
+        let current = match slot.compare_exchange(
+            core::ptr::null_mut(),
+            key_ptr,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => return Some(index as u16),
+            Err(current) => current,
+        };
+
+        // SAFETY: Only `'static` locations are stored in the class table.
+        if unsafe { &*current } == key {
+            return Some(index as u16);
+        }
+    }
+
+    None
+}
+
+fn class_location(class: u16) -> &'static Location<'static> {
+    // SAFETY: Class IDs are only handed out for registered classes.
+    unsafe { &*CLASSES[class as usize].load(Ordering::Acquire) }
+}
+
+fn depends(from: u16, to: u16) -> bool {
+    let bit = from as usize * MAX_CLASSES + to as usize;
+    DEPENDENCIES[bit / 64].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0
+}
+
+/// Adds the dependency `from -> to`. Returns `true` if it was not known before.
+fn add_dependency(from: u16, to: u16) -> bool {
+    let bit = from as usize * MAX_CLASSES + to as usize;
+    let mask = 1 << (bit % 64);
+
+    DEPENDENCIES[bit / 64].fetch_or(mask, Ordering::Relaxed) & mask == 0
+}
+
+/// Returns the held-lock stack of the current CPU, or [`None`] if the CPU-local data has not
+/// been set up yet.
+fn held_locks() -> Option<&'static mut HeldLocks> {
+    if !cpu_local::is_initialized() {
+        return None;
+    }
+
+    // SAFETY: The stack is CPU-local and only accessed with interrupts disabled.
+    Some(unsafe { &mut *HELD_LOCKS })
+}
+
+/// Records that the current CPU is about to acquire `lock` of class `class`. Must be called
+/// before spinning on the lock, so that a cycle is reported even if the lock deadlocks.
+pub fn acquire(class: &LockClass, lock: usize) {
+    let _guard = IrqGuard::new();
+
+    let Some(held) = held_locks() else {
+        return;
+    };
+
+    if held.busy {
+        return;
+    }
+
+    let Some(id) = class.id() else {
+        return;
+    };
+
+    held.busy = true;
+
+    for prev in 0..held.len {
+        let prev = held.locks[prev].class;
+
+        // Nested locks of the same class (for example, two inodes) are not ordered.
+        if prev == id || depends(prev, id) || !add_dependency(prev, id) {
+            continue;
+        }
+
+        let mut graph = GRAPH.lock();
+        graph.record_trace(prev, id);
+
+        if let Some(before) = graph.find_path(id, prev) {
+            let trace = graph.trace(before, prev);
+            core::mem::drop(graph);
+
+            report_cycle(id, prev, before, trace);
+        }
+    }
+
+    if held.len < MAX_HELD {
+        held.locks[held.len] = HeldLock { class: id, lock };
+        held.len += 1;
+    }
+
+    held.busy = false;
+}
+
+/// Records that the current CPU released `lock`.
+pub fn release(lock: usize) {
+    let _guard = IrqGuard::new();
+
+    let Some(held) = held_locks() else {
+        return;
+    };
+
+    // Locks are not necessarily released in the reverse order of acquisition.
+    if let Some(index) = held.held().iter().rposition(|e| e.lock == lock) {
+        held.locks.copy_within(index + 1..held.len, index);
+        held.len -= 1;
+    }
+}
+
+/// Saves the held-lock stack of the current CPU to `from` and switches to the one of `to`.
+/// Called on context switch.
+pub fn switch(from: &mut HeldLocks, to: &HeldLocks) {
+    if let Some(held) = held_locks() {
+        from.clone_from(held);
+        held.clone_from(to);
+    }
+}
+
+/// Returns the number of lock ordering cycles reported so far.
+pub fn cycles() -> usize {
+    CYCLES.load(Ordering::SeqCst)
+}
+
+fn report_cycle(acquired: u16, held: u16, before: u16, trace: Option<Trace>) {
+    CYCLES.fetch_add(1, Ordering::SeqCst);
+
+    log::warn!("{:=^80}", " LOCKDEP: POSSIBLE DEADLOCK ");
+    log::warn!(
+        "acquiring lock {} while holding lock {}",
+        class_location(acquired),
+        class_location(held)
+    );
+
+    if before == acquired {
+        log::warn!("but the opposite order has been observed before:");
+    } else {
+        log::warn!(
+            "but lock {} has been acquired before while holding lock {}, which depends on {}:",
+            class_location(held),
+            class_location(before),
+            class_location(acquired)
+        );
+    }
+
+    match trace {
+        Some(trace) => unwind::log_stack_trace(trace.frames()),
+        None => log::warn!("<backtrace not recorded>"),
+    }
+
+    log::warn!("current backtrace:");
+
+    let mut frames = [0; TRACE_DEPTH];
+    let len = unwind::capture_stack_trace(&mut frames);
+    unwind::log_stack_trace(&frames[..len]);
+
+    log::warn!("{:=^80}", "");
+}
@@ -16,14 +16,12 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 use alloc::alloc::alloc_zeroed;
-use alloc::sync::Arc;
 use core::alloc::Layout;
-use core::any::Any;
 use core::cell::UnsafeCell;
-use core::marker::PhantomData;
+use core::mem;
 use core::ptr::Unique;
-use core::{mem, ptr};
 
+use crate::arch::user_copy::{probe_user, probe_user_write};
 use crate::mem::paging::{align_down, ReadErr, VirtAddr};
 
 #[cfg(target_arch = "x86_64")]
@@ -37,24 +35,39 @@ fn get_cpu_count() -> usize {
 pub mod bitmap;
 pub mod buffer;
 pub mod dma;
+#[cfg(feature = "lockdep")]
+pub mod lockdep;
+pub mod regex;
 pub mod sync;
 
+/// Validates a pointer to a userspace structure that the kernel writes to.
+///
+/// The check only holds until the task blocks; another thread may unmap the memory or make it
+/// read-only while it sleeps. Results produced after blocking should be written with
+/// [`copy_to_user`](crate::arch::user_copy::copy_to_user) instead.
 pub fn validate_mut_ptr<T>(ptr: *mut T) -> Result<&'static mut T, ReadErr> {
-    VirtAddr::new(ptr as _).read_mut::<T>()
+    let reference = VirtAddr::new(ptr as _).read_mut::<T>()?; // ensure non-null and aligned
+    probe_user_write(ptr.cast(), mem::size_of::<T>())?; // ensure in-range, mapped and writable
+
+    Ok(reference)
 }
 
 pub fn validate_ptr<T>(ptr: *const T) -> Result<&'static T, ReadErr> {
-    // SAFETY: Safe to cast const pointer to mutable since the pointer is not
-    //         mutated and the returned reference is immutable.
-    validate_mut_ptr(ptr as *mut T).map(|e| &*e)
+    let reference = VirtAddr::new(ptr as _).read_mut::<T>()?; // ensure non-null and aligned
+    probe_user(ptr.cast(), mem::size_of::<T>())?; // ensure in-range and mapped
+
+    Ok(reference)
 }
 
+/// Validates a userspace buffer that the kernel writes to. See [`validate_mut_ptr`] for the
+/// caveats.
 pub fn validate_slice_mut<T>(ptr: *mut T, len: usize) -> Result<&'static mut [T], ReadErr> {
     if len == 0 {
         Ok(&mut [])
     } else {
-        let _ = validate_ptr(ptr)?; // ensure non-null and in-range
-        let _ = validate_ptr(unsafe { ptr.add(len) })?; // ensure in-range
+        let _ = VirtAddr::new(ptr as _).read_mut::<T>()?; // ensure non-null and aligned
+        let size = mem::size_of::<T>().checked_mul(len).ok_or(ReadErr::Fault)?;
+        probe_user_write(ptr.cast(), size)?; // ensure in-range, mapped and writable
 
         // SAFETY: We have validated the pointer above.
         Ok(unsafe { core::slice::from_raw_parts_mut(ptr, len) })
@@ -62,9 +75,16 @@ pub fn validate_slice_mut<T>(ptr: *mut T, len: usize) -> Result<&'static mut [T]
 }
 
 pub fn validate_slice<T>(ptr: *const T, len: usize) -> Result<&'static [T], ReadErr> {
-    // SAFETY: Safe to cast const pointer to mutable since the pointer is not
-    //         mutated and the returned reference is immutable.
-    validate_slice_mut(ptr as *mut T, len).map(|e| &*e)
+    if len == 0 {
+        Ok(&[])
+    } else {
+        let _ = VirtAddr::new(ptr as _).read_mut::<T>()?; // ensure non-null and aligned
+        let size = mem::size_of::<T>().checked_mul(len).ok_or(ReadErr::Fault)?;
+        probe_user(ptr.cast(), size)?; // ensure in-range and mapped
+
+        // SAFETY: We have validated the pointer above.
+        Ok(unsafe { core::slice::from_raw_parts(ptr, len) })
+    }
 }
 
 pub fn validate_str(ptr: *const u8, len: usize) -> Result<&'static str, ReadErr> {
@@ -82,16 +102,6 @@ pub fn validate_array_mut<T, const COUNT: usize>(
     slice.map(|e| unsafe { &mut *(e.as_ptr() as *mut [T; COUNT]) })
 }
 
-pub trait Downcastable: Any + Send + Sync {
-    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
-}
-
-impl<T: Any + Send + Sync> Downcastable for T {
-    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
-        self
-    }
-}
-
 /// Just like [`Cell`] but with [volatile] read / write operations
 ///
 /// [`Cell`]: https://doc.rust-lang.org/std/cell/struct.Cell.html
@@ -236,29 +246,3 @@ macro_rules! extern_sym {
         unsafe { ::core::ptr::addr_of!($sym) }
     }};
 }
-
-#[repr(C)]
-#[derive(Debug, Default)]
-pub struct IncompleteArrayField<T>(PhantomData<T>, [T; 0]);
-
-impl<T> IncompleteArrayField<T> {
-    #[inline]
-    pub unsafe fn as_mut_ptr(&mut self) -> *mut T {
-        ptr::from_mut(self).cast::<T>()
-    }
-
-    #[inline]
-    pub unsafe fn as_ptr(&self) -> *const T {
-        ptr::from_ref(self).cast::<T>()
-    }
-
-    #[inline]
-    pub unsafe fn as_mut_slice(&mut self, len: usize) -> &mut [T] {
-        core::slice::from_raw_parts_mut(self.as_mut_ptr(), len)
-    }
-
-    #[inline]
-    pub unsafe fn as_slice(&self, len: usize) -> &[T] {
-        core::slice::from_raw_parts(self.as_ptr(), len)
-    }
-}
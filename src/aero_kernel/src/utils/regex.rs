@@ -0,0 +1,291 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! A small regular expression engine, used to select the kernel tests to run.
+//!
+//! The supported syntax is a subset of the one of the `regex` crate:
+//!
+//! * `.` matches any character, and `\` escapes the character after it. `\d`, `\w` and `\s` match
+//!   digits, word characters and whitespace.
+//! * `[a-z_]` matches one of the listed characters or ranges, and `[^0-9]` any other character.
+//! * `*`, `+` and `?` repeat the item before them, as many times as possible.
+//! * `^` and `$` match the start and the end of the text.
+//! * `(...)` groups items, and `|` separates alternatives.
+//!
+//! Like with the `regex` crate, a pattern matches if it matches anywhere in the text. The matcher
+//! backtracks, so it is only meant for short texts such as test names.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A `(` without a matching `)`, or the other way around.
+    UnmatchedParen,
+    /// A `[` without a matching `]`.
+    UnclosedClass,
+    /// A `*`, `+` or `?` with nothing before it.
+    NothingToRepeat,
+    /// The pattern ends with a `\`.
+    TrailingBackslash,
+}
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+struct Parser<'a> {
+    pattern: core::iter::Peekable<core::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn alternate(&mut self) -> Result<Node, Error> {
+        let mut branches = alloc::vec![self.concat()?];
+
+        while self.pattern.next_if_eq(&'|').is_some() {
+            branches.push(self.concat()?);
+        }
+
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+
+        while let Some(&c) = self.pattern.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            let mut node = self.atom()?;
+
+            while let Some(c) = self.pattern.next_if(|c| matches!(c, '*' | '+' | '?')) {
+                let (min, max) = match c {
+                    '*' => (0, None),
+                    '+' => (1, None),
+                    _ => (0, Some(1)),
+                };
+
+                node = Node::Repeat {
+                    node: Box::new(node),
+                    min,
+                    max,
+                };
+            }
+
+            nodes.push(node);
+        }
+
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        Ok(match self.pattern.next().unwrap() {
+            '(' => {
+                let node = self.alternate()?;
+                self.pattern.next_if_eq(&')').ok_or(Error::UnmatchedParen)?;
+                node
+            }
+
+            '*' | '+' | '?' => return Err(Error::NothingToRepeat),
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '[' => self.class()?,
+
+            '\\' => match self.pattern.next().ok_or(Error::TrailingBackslash)? {
+                'd' => Node::Class {
+                    ranges: DIGIT.to_vec(),
+                    negated: false,
+                },
+                'w' => Node::Class {
+                    ranges: WORD.to_vec(),
+                    negated: false,
+                },
+                's' => Node::Class {
+                    ranges: SPACE.to_vec(),
+                    negated: false,
+                },
+                c => Node::Char(c),
+            },
+
+            c => Node::Char(c),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, Error> {
+        let negated = self.pattern.next_if_eq(&'^').is_some();
+        let mut ranges = Vec::new();
+
+        // A `]` right after the `[` is part of the class.
+        let mut first = true;
+
+        loop {
+            let start = match self.pattern.next().ok_or(Error::UnclosedClass)? {
+                ']' if !first => break,
+                '\\' => self.pattern.next().ok_or(Error::UnclosedClass)?,
+                c => c,
+            };
+
+            first = false;
+
+            // A `-` at the end of the class is a literal.
+            let mut lookahead = self.pattern.clone();
+            let end = match (lookahead.next(), lookahead.next()) {
+                (Some('-'), Some(end)) if end != ']' => {
+                    self.pattern.next();
+                    self.pattern.next();
+
+                    if end == '\\' {
+                        self.pattern.next().ok_or(Error::UnclosedClass)?
+                    } else {
+                        end
+                    }
+                }
+
+                _ => start,
+            };
+
+            ranges.push((start, end));
+        }
+
+        Ok(Node::Class { ranges, negated })
+    }
+}
+
+/// A compiled regular expression. See the [module level documentation](self) for the syntax.
+#[derive(Debug)]
+pub struct Regex {
+    root: Node,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            pattern: pattern.chars().peekable(),
+        };
+
+        let root = parser.alternate()?;
+
+        // The only thing that stops the parser early is an unmatched `)`.
+        if parser.pattern.next().is_some() {
+            return Err(Error::UnmatchedParen);
+        }
+
+        Ok(Self { root })
+    }
+
+    /// Returns true if the regular expression matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+        (0..=text.len()).any(|start| matches(&self.root, &text, start, &mut |_| true))
+    }
+}
+
+/// Returns true if `node` matches `text` at `pos`, and `next` accepts the position right after
+/// the match. Every possible match is tried, longest first, until `next` accepts one.
+fn matches(node: &Node, text: &[char], pos: usize, next: &mut dyn FnMut(usize) -> bool) -> bool {
+    match node {
+        Node::Char(c) => text.get(pos) == Some(c) && next(pos + 1),
+        Node::Any => pos < text.len() && next(pos + 1),
+
+        Node::Class { ranges, negated } => {
+            text.get(pos).is_some_and(|c| {
+                let found = ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(c));
+                found != *negated
+            }) && next(pos + 1)
+        }
+
+        Node::Start => pos == 0 && next(pos),
+        Node::End => pos == text.len() && next(pos),
+
+        Node::Concat(nodes) => matches_all(nodes, text, pos, next),
+        Node::Alternate(branches) => branches
+            .iter()
+            .any(|branch| matches(branch, text, pos, next)),
+
+        Node::Repeat { node, min, max } => repeat(node, *min, *max, text, pos, next),
+    }
+}
+
+fn matches_all(
+    nodes: &[Node],
+    text: &[char],
+    pos: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    match nodes.split_first() {
+        Some((first, rest)) => matches(first, text, pos, &mut |pos| {
+            matches_all(rest, text, pos, next)
+        }),
+        None => next(pos),
+    }
+}
+
+fn repeat(
+    node: &Node,
+    min: usize,
+    max: Option<usize>,
+    text: &[char],
+    pos: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    // One more repetition is tried first. Once `min` is reached, a repetition that matches the
+    // empty string would loop forever, so it is not counted.
+    if max != Some(0)
+        && matches(node, text, pos, &mut |end| {
+            (end != pos || min > 0)
+                && repeat(
+                    node,
+                    min.saturating_sub(1),
+                    max.map(|max| max - 1),
+                    text,
+                    end,
+                    next,
+                )
+        })
+    {
+        return true;
+    }
+
+    min == 0 && next(pos)
+}
@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use aero_syscall::SyscallError;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
@@ -22,6 +23,8 @@ use crate::arch::interrupts;
 use crate::userland::scheduler;
 use crate::userland::signals::SignalResult;
 use crate::userland::task::Task;
+#[cfg(feature = "lockdep")]
+use crate::utils::lockdep::{self, LockClass};
 
 /// Used to manage and block threads that are waiting for a condition to be true.
 pub struct WaitQueue {
@@ -68,6 +71,40 @@ impl WaitQueue {
         Ok(lock)
     }
 
+    /// Same as [`WaitQueue::block_on`], except that the wait is also given up if a signal is
+    /// pending, before blocking and after each wakeup, failing with [`SyscallError::EINTR`].
+    /// A signal that arrives before the task goes to sleep would otherwise not wake it up.
+    pub fn block_on_interruptible<'future, T, F: FnMut(&mut MutexGuard<T>) -> bool>(
+        &self,
+        mutex: &'future Mutex<T>,
+        mut future: F,
+    ) -> Result<MutexGuard<'future, T>, SyscallError> {
+        let mut lock = mutex.lock_irq();
+
+        if future(&mut lock) {
+            return Ok(lock);
+        }
+
+        let scheduler = scheduler::get_scheduler();
+        let task = scheduler.current_task();
+
+        self.queue.lock_irq().push(task.clone());
+
+        while !future(&mut lock) {
+            core::mem::drop(lock);
+
+            if task.signals().has_pending() || scheduler.inner.await_io().is_err() {
+                self.remove(&task);
+                return Err(SyscallError::EINTR);
+            }
+
+            lock = mutex.lock_irq();
+        }
+
+        self.remove(&task);
+        Ok(lock)
+    }
+
     pub fn insert(&self, task: Arc<Task>) {
         self.queue.lock_irq().push(task);
     }
@@ -150,6 +187,7 @@ pub struct BMutex<T: ?Sized> {
 }
 
 impl<T> BMutex<T> {
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new(value: T) -> Self {
         Self {
             wq: WaitQueue::new(),
@@ -158,6 +196,9 @@ impl<T> BMutex<T> {
     }
 
     pub fn lock(&self) -> BMutexGuard<T> {
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(&self.spin.class, self.spin.addr());
+
         let task = scheduler::get_scheduler().current_task();
         self.wq.insert(task.clone());
 
@@ -196,31 +237,50 @@ impl<'a, T: ?Sized> core::ops::DerefMut for BMutexGuard<'a, T> {
 
 impl<'a, T: ?Sized> Drop for BMutexGuard<'a, T> {
     fn drop(&mut self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.mutex.spin.addr());
+
         self.mutex.wq.notify();
     }
 }
 
 /// A spin-based lock providing mutually exclusive access to data.
 pub struct Mutex<T: ?Sized> {
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
     inner: spin::Mutex<T>,
 }
 
 impl<T> Mutex<T> {
     /// Creates a new [`Mutex`] wrapping the supplied data.
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new(value: T) -> Self {
         Self {
+            #[cfg(feature = "lockdep")]
+            class: LockClass::new(),
             inner: spin::Mutex::new(value),
         }
     }
 
+    /// Returns the address of the lock, which identifies it to the lock dependency tracker.
+    #[cfg(feature = "lockdep")]
+    fn addr(&self) -> usize {
+        self as *const Self as *const u8 as usize
+    }
+
     /// Locks the [`Mutex`] and returns a guard that permits access to the inner data.
     ///
     /// The returned value may be dereferenced for data access and the lock will be dropped
     /// when the guard falls out of scope.
     pub fn lock(&self) -> MutexGuard<T> {
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(&self.class, self.addr());
+
         MutexGuard {
             guard: core::mem::ManuallyDrop::new(self.inner.lock()),
             irq_lock: false,
+            #[cfg(feature = "lockdep")]
+            lock: self.addr(),
         }
     }
 
@@ -238,9 +298,14 @@ impl<T> Mutex<T> {
             interrupts::disable_interrupts();
         }
 
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(&self.class, self.addr());
+
         MutexGuard {
             guard: core::mem::ManuallyDrop::new(self.inner.lock()),
             irq_lock,
+            #[cfg(feature = "lockdep")]
+            lock: self.addr(),
         }
     }
 
@@ -252,6 +317,9 @@ impl<T> Mutex<T> {
     /// can be useful in some instances for exposing the lock to FFI that doesn't know how to deal
     /// with RAII.
     pub unsafe fn force_unlock(&self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.addr());
+
         self.inner.force_unlock()
     }
 }
@@ -259,6 +327,8 @@ impl<T> Mutex<T> {
 pub struct MutexGuard<'a, T: ?Sized + 'a> {
     guard: core::mem::ManuallyDrop<spin::MutexGuard<'a, T>>,
     irq_lock: bool,
+    #[cfg(feature = "lockdep")]
+    lock: usize,
 }
 
 impl<'a, T: ?Sized> core::ops::Deref for MutexGuard<'a, T> {
@@ -284,6 +354,9 @@ impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
             core::mem::ManuallyDrop::drop(&mut self.guard);
         }
 
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.lock);
+
         if self.irq_lock {
             unsafe {
                 interrupts::enable_interrupts();
@@ -291,3 +364,116 @@ impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
         }
     }
 }
+
+/// A spin-based reader-writer lock, allowing any number of readers or a single writer at a time.
+pub struct RwLock<T: ?Sized> {
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
+    inner: spin::RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    /// Creates a new [`RwLock`] wrapping the supplied data.
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub const fn new(value: T) -> Self {
+        Self {
+            #[cfg(feature = "lockdep")]
+            class: LockClass::new(),
+            inner: spin::RwLock::new(value),
+        }
+    }
+
+    /// Returns the address of the lock, which identifies it to the lock dependency tracker.
+    #[cfg(feature = "lockdep")]
+    fn addr(&self) -> usize {
+        self as *const Self as *const u8 as usize
+    }
+
+    /// Locks the [`RwLock`] with shared read access, spinning while there is a writer.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        // Readers are tracked like writers: a reader waiting for a writer deadlocks just the
+        // same.
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(&self.class, self.addr());
+
+        RwLockReadGuard {
+            guard: core::mem::ManuallyDrop::new(self.inner.read()),
+            #[cfg(feature = "lockdep")]
+            lock: self.addr(),
+        }
+    }
+
+    /// Locks the [`RwLock`] with exclusive write access, spinning while there are readers or
+    /// a writer.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(&self.class, self.addr());
+
+        RwLockWriteGuard {
+            guard: core::mem::ManuallyDrop::new(self.inner.write()),
+            #[cfg(feature = "lockdep")]
+            lock: self.addr(),
+        }
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    guard: core::mem::ManuallyDrop<spin::RwLockReadGuard<'a, T>>,
+    #[cfg(feature = "lockdep")]
+    lock: usize,
+}
+
+impl<'a, T: ?Sized> core::ops::Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            core::mem::ManuallyDrop::drop(&mut self.guard);
+        }
+
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.lock);
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    guard: core::mem::ManuallyDrop<spin::RwLockWriteGuard<'a, T>>,
+    #[cfg(feature = "lockdep")]
+    lock: usize,
+}
+
+impl<'a, T: ?Sized> core::ops::Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> core::ops::DerefMut for RwLockWriteGuard<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            core::mem::ManuallyDrop::drop(&mut self.guard);
+        }
+
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.lock);
+    }
+}
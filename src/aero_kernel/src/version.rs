@@ -0,0 +1,33 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Version of the kernel, as reported by `uname`.
+
+// Generated by `build.rs`:
+//
+// * `COMMIT`: the commit the kernel is built from, as given by `git describe`.
+// * `BUILD_DATE`: the date the kernel was built on.
+include!(concat!(env!("OUT_DIR"), "/version.rs"));
+
+/// The kernel release. Programs compare it against the Linux version that introduced the
+/// features they need, so it is kept in line with the Linux ABI that Aero implements.
+pub const RELEASE: &str = "6.0.0-aero";
+
+/// Returns the version string of the kernel, such as `#1c265a5c0e1f Fri Oct 16 12:00:00 UTC 2026`.
+pub fn version() -> String {
+    alloc::format!("#{COMMIT} {BUILD_DATE}")
+}
@@ -17,14 +17,25 @@
 
 use proc_macro::TokenStream;
 use syn::spanned::Spanned;
+use syn::{ItemTrait, TypeParamBound};
+
+/// Returns whether `bound` is the marker trait `name` (e.g. `Send` or `core::marker::Send`).
+fn is_marker(bound: &TypeParamBound, name: &str) -> bool {
+    match bound {
+        TypeParamBound::Trait(bound) => bound
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == name),
+        TypeParamBound::Lifetime(_) => false,
+    }
+}
 
 pub fn parse(_: TokenStream, item: TokenStream) -> TokenStream {
-    let parsed_trait = syn::parse_macro_input!(item as syn::ItemTrait);
+    let mut parsed_trait = syn::parse_macro_input!(item as ItemTrait);
 
-    let vis = &parsed_trait.vis;
-    let name = &parsed_trait.ident;
-    let items = &parsed_trait.items;
-    let generics = &parsed_trait.generics;
+    let vis = parsed_trait.vis.clone();
+    let name = parsed_trait.ident.clone();
 
     // `auto` and `unsafe` traits are not allowed:
     if let Some(token) = parsed_trait.auto_token {
@@ -33,19 +44,61 @@ pub fn parse(_: TokenStream, item: TokenStream) -> TokenStream {
         emit_error!(token.span(), "`unsafe` traits are not downcastable")
     }
 
-    let super_traits = parsed_trait.supertraits.clone();
+    // The trait objects are shared between threads as `Arc`s, and downcasting an `Arc` requires
+    // the object to be `Send + Sync`:
+    for marker in ["Send", "Sync"] {
+        if !parsed_trait
+            .supertraits
+            .iter()
+            .any(|bound| is_marker(bound, marker))
+        {
+            emit_error!(
+                name.span(),
+                "downcastable traits must be `Send + Sync`";
+                help = "add `{}` to the supertraits of `{}`", marker, name
+            );
+        }
+    }
+
+    // The methods used to downcast are provided by a supertrait with a blanket implementation, so
+    // the implementors of the trait do not have to implement them.
+    let any_trait = quote::format_ident!("__{}Any", name);
+
+    parsed_trait.supertraits.push(syn::parse_quote!(#any_trait));
+
+    let (impl_generics, ty_generics, where_clause) = parsed_trait.generics.split_for_impl();
 
     quote::quote! {
-        #vis trait #name #generics: #super_traits + crate::utils::Downcastable {
-            #(#items)*
+        #parsed_trait
+
+        #[doc(hidden)]
+        #vis trait #any_trait: ::core::any::Any + Send + Sync {
+            fn as_any(&self) -> &dyn ::core::any::Any;
+            fn as_any_arc(self: alloc::sync::Arc<Self>) -> alloc::sync::Arc<dyn ::core::any::Any + Send + Sync>;
+        }
+
+        impl<Target: ::core::any::Any + Send + Sync> #any_trait for Target {
+            fn as_any(&self) -> &dyn ::core::any::Any {
+                self
+            }
+
+            fn as_any_arc(self: alloc::sync::Arc<Self>) -> alloc::sync::Arc<dyn ::core::any::Any + Send + Sync> {
+                self
+            }
         }
 
         // #[downcast]: implement downcast functions:
-        impl dyn #name #generics {
+        impl #impl_generics dyn #name #ty_generics #where_clause {
+            /// Downcasts the trait object to a reference to the underlying object if it is of
+            /// type `Target`.
+            pub fn downcast_ref<Target: 'static>(&self) -> Option<&Target> {
+                self.as_any().downcast_ref::<Target>()
+            }
+
             /// Downcast's an `Arc`ed trait object to an `Arc`ed object if the underlying object
-            /// is of type `T`.
-            pub fn downcast_arc<T: #name #generics>(self: &alloc::sync::Arc<Self>) -> Option<alloc::sync::Arc<T>> {
-                self.clone().as_any().downcast::<T>().ok()
+            /// is of type `Target`.
+            pub fn downcast_arc<Target: ::core::any::Any + Send + Sync>(self: &alloc::sync::Arc<Self>) -> Option<alloc::sync::Arc<Target>> {
+                self.clone().as_any_arc().downcast::<Target>().ok()
             }
         }
     }
@@ -43,7 +43,7 @@ fn make_command_enum(ast: &DeriveInput) -> TokenStream {
                 syn::Fields::Unit => quote::quote!(#path => Self::#ident),
                 syn::Fields::Unnamed(fields) => {
                     assert!(fields.unnamed.len() == 1);
-                    quote::quote!(#path => Self::#ident(crate::syscall::SysArg::from_usize(arg)))
+                    quote::quote!(#path => Self::#ident(crate::syscall::SysArg::from_usize(arg)?))
                 }
 
                 _ => panic!("`Ioctl` derive macro can only be used on enums with unit variants."),
@@ -55,11 +55,11 @@ fn make_command_enum(ast: &DeriveInput) -> TokenStream {
 
     quote::quote! {
         impl #name {
-            pub fn from_command_arg(cmd: usize, arg: usize) -> Self {
-                match cmd {
+            pub fn from_command_arg(cmd: usize, arg: usize) -> Result<Self, crate::mem::paging::ReadErr> {
+                Ok(match cmd {
                     #(#pattern_match,)*
                     _ => unimplemented!("unknown command: {cmd:#x}")
-                }
+                })
             }
         }
     }
@@ -49,12 +49,54 @@ pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
 /// Functions that use this macro are not allowed to be `async`, `unsafe`, or `const` and must
 /// have a valid return-type of `Result<usize, AeroSyscallError>`. In addition, the function cannot
 /// have generic parameters.
+///
+/// With `number(SYS_*)`, the function is also registered in the syscall table under that number,
+/// with a handler that decodes its arguments from the argument registers. Registering two
+/// functions under the same number fails to link.
+///
+/// ## Example
+/// ```rust,ignore
+/// #[syscall(number(SYS_READ))]
+/// pub fn read(fd: FileDescriptor, buffer: &mut [u8]) -> Result<usize, SyscallError> {
+///     // ...
+/// }
+/// ```
 #[proc_macro_attribute]
 #[proc_macro_error]
 pub fn syscall(attr: TokenStream, item: TokenStream) -> TokenStream {
     syscall::parse(attr, item)
 }
 
+/// Allows trait objects of the trait to be downcast to the concrete type implementing it.
+///
+/// The trait gets `as_any` and `as_any_arc` methods, and `dyn Trait` gets `downcast_ref` and
+/// `downcast_arc`. The trait must be `Send + Sync`.
+///
+/// ## Example
+/// ```rust
+/// extern crate alloc;
+///
+/// use std::sync::Arc;
+///
+/// #[aero_proc::downcastable]
+/// trait Shape: Send + Sync {}
+///
+/// struct Square;
+/// impl Shape for Square {}
+///
+/// let shape: Arc<dyn Shape> = Arc::new(Square);
+///
+/// assert!(shape.downcast_ref::<Square>().is_some());
+/// assert!(shape.downcast_arc::<Square>().is_some());
+/// ```
+///
+/// ```rust,compile_fail
+/// extern crate alloc;
+///
+/// // error: downcastable traits must be `Send + Sync`
+/// #[aero_proc::downcastable]
+/// trait Shape: Send {}
+/// ```
 #[proc_macro_attribute]
 #[proc_macro_error]
 pub fn downcastable(attr: TokenStream, item: TokenStream) -> TokenStream {
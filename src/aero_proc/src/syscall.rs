@@ -36,6 +36,8 @@ enum ArgType {
 #[derive(Default)]
 struct Config {
     no_return: bool,
+    /// The `SYS_*` constant the syscall is registered under, from `number(SYS_*)`.
+    number: Option<syn::Path>,
 }
 
 // TODO: determine if no_return by checking the return type of the syscall function;
@@ -54,6 +56,14 @@ fn parse_attribute_args(args: Vec<NestedMeta>) -> Config {
                         }
                     }
                 }
+                syn::Meta::List(list) if list.path.is_ident("number") => {
+                    match list.nested.iter().collect::<Vec<_>>().as_slice() {
+                        [NestedMeta::Meta(syn::Meta::Path(number))] => {
+                            config.number = Some(number.clone())
+                        }
+                        _ => emit_error!(list.span(), "expected `number(SYS_*)`"),
+                    }
+                }
                 _ => emit_error!(meta.span(), "unknown attribute"),
             },
             NestedMeta::Lit(e) => emit_error!(e.span(), "unknown attribute"),
@@ -179,10 +189,53 @@ pub fn parse(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
+    let entry = config.number.map(|number| {
+        let entry_name =
+            quote::format_ident!("__SYSCALL_ENTRY_{}", name.to_string().to_uppercase());
+        let arg_count = processed_args.len();
+        let arg_regs = (0..arg_count).map(syn::Index::from);
+
+        // A symbol named after the value of the number is defined alongside the entry, so that
+        // two syscalls registered under the same number fail to link, even if they use different
+        // constants.
+        quote! {
+            ::core::arch::global_asm!(
+                ".globl __aero_syscall_{number}",
+                "__aero_syscall_{number}:",
+                number = const {
+                    use aero_syscall::prelude::*;
+                    #number
+                },
+            );
+
+            #[used]
+            #[link_section = ".syscalls"]
+            static #entry_name: crate::syscall::SyscallEntry = crate::syscall::SyscallEntry {
+                number: {
+                    use aero_syscall::prelude::*;
+                    #number
+                },
+                name: stringify!(#name),
+                args: #arg_count,
+                handler: {
+                    fn handler(
+                        args: &[usize; 6],
+                    ) -> ::core::result::Result<usize, aero_syscall::SyscallError> {
+                        #name(#(args[#arg_regs]),*)
+                    }
+
+                    handler
+                },
+            };
+        }
+    });
+
     let result = quote! {
         #(#attrs)* #vis fn #name(#(#processed_args),*) #ret {
             #compiled_body
         }
+
+        #entry
     };
 
     result.into()
@@ -321,7 +374,7 @@ fn process_call_args(args: &Punctuated<FnArg, syn::Token![,]>) -> Vec<Expr> {
                         }
                     }
                 } else {
-                    result.push(syn::parse_quote!(crate::syscall::SysArg::from_usize(#ident)));
+                    result.push(syn::parse_quote!(crate::syscall::SysArg::from_usize(#ident)?));
                 }
             }
         }
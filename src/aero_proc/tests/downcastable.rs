@@ -0,0 +1,139 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+extern crate alloc;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use aero_proc::downcastable;
+
+/// Documented to check that the attributes of the trait are kept.
+#[downcastable]
+pub trait Shape: Send + Sync {
+    fn area(&self) -> usize;
+}
+
+#[downcastable]
+trait Generic<T: Copy + 'static>: core::marker::Send + core::marker::Sync {
+    fn get(&self) -> T;
+}
+
+#[derive(Debug, PartialEq)]
+struct Square(usize);
+
+impl Shape for Square {
+    fn area(&self) -> usize {
+        self.0 * self.0
+    }
+}
+
+impl Generic<usize> for Square {
+    fn get(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Rectangle(usize, usize);
+
+impl Shape for Rectangle {
+    fn area(&self) -> usize {
+        self.0 * self.1
+    }
+}
+
+static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+struct Dropped;
+
+impl Shape for Dropped {
+    fn area(&self) -> usize {
+        0
+    }
+}
+
+impl Drop for Dropped {
+    fn drop(&mut self) {
+        DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn downcast_ref() {
+    let square: &dyn Shape = &Square(2);
+
+    assert_eq!(square.downcast_ref::<Square>(), Some(&Square(2)));
+    assert_eq!(square.downcast_ref::<Rectangle>(), None);
+
+    // Types that do not implement the trait never match.
+    assert_eq!(square.downcast_ref::<usize>(), None);
+}
+
+#[test]
+fn downcast_arc() {
+    let shapes: Vec<Arc<dyn Shape>> = vec![Arc::new(Square(3)), Arc::new(Rectangle(2, 5))];
+
+    let square = shapes[0].downcast_arc::<Square>().unwrap();
+    assert_eq!(*square, Square(3));
+    assert!(shapes[0].downcast_arc::<Rectangle>().is_none());
+
+    // The downcast `Arc` shares the allocation with the trait object.
+    assert_eq!(Arc::strong_count(&square), 2);
+    assert!(core::ptr::eq(
+        Arc::as_ptr(&square).cast::<()>(),
+        Arc::as_ptr(&shapes[0]).cast::<()>()
+    ));
+
+    let rectangle = <dyn Shape>::downcast_arc::<Rectangle>(&shapes[1]).unwrap();
+    assert_eq!(rectangle.area(), 10);
+    assert!(shapes[1].downcast_arc::<Square>().is_none());
+}
+
+#[test]
+fn failed_downcast_arc_releases_reference() {
+    DROPS.store(0, Ordering::SeqCst);
+
+    let shape: Arc<dyn Shape> = Arc::new(Dropped);
+    assert!(shape.downcast_arc::<Square>().is_none());
+    assert_eq!(Arc::strong_count(&shape), 1);
+
+    drop(shape);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn as_any() {
+    let square: Arc<dyn Shape> = Arc::new(Square(4));
+
+    // Called on the trait object, not on the `Arc` (which is `Any` itself).
+    assert!((*square).as_any().is::<Square>());
+    assert!(!(*square).as_any().is::<Rectangle>());
+    assert_eq!(square.area(), 16);
+
+    let any = square.as_any_arc();
+    assert_eq!(*any.downcast::<Square>().unwrap(), Square(4));
+}
+
+#[test]
+fn generic_trait() {
+    let value: Arc<dyn Generic<usize>> = Arc::new(Square(7));
+
+    assert_eq!(value.downcast_ref::<Square>().map(Square::area), Some(49));
+    assert_eq!(value.downcast_arc::<Square>().unwrap().get(), 7);
+    assert!(value.downcast_arc::<Rectangle>().is_none());
+}
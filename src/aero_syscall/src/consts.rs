@@ -102,6 +102,81 @@ pub const SYS_DEBUG: usize = 78;
 pub const SYS_SETSOCKOPT: usize = 79;
 pub const SYS_GETSOCKOPT: usize = 80;
 pub const SYS_SYMLINK_AT: usize = 81;
+pub const SYS_OPENAT2: usize = 82;
+pub const SYS_PTRACE: usize = 83;
+pub const SYS_GETPRIORITY: usize = 84;
+pub const SYS_SETPRIORITY: usize = 85;
+pub const SYS_SETDOMAINNAME: usize = 86;
+pub const SYS_MEMFD_CREATE: usize = 87;
+pub const SYS_FTRUNCATE: usize = 88;
+pub const SYS_KCMP: usize = 89;
+pub const SYS_UMASK: usize = 90;
+pub const SYS_IO_URING_SETUP: usize = 91;
+pub const SYS_IO_URING_ENTER: usize = 92;
+pub const SYS_IO_URING_REGISTER: usize = 93;
+pub const SYS_INOTIFY_INIT: usize = 94;
+pub const SYS_INOTIFY_ADD_WATCH: usize = 95;
+pub const SYS_INOTIFY_RM_WATCH: usize = 96;
+pub const SYS_PERF_EVENT_OPEN: usize = 97;
+pub const SYS_DUP3: usize = 98;
+pub const SYS_SCHED_GETAFFINITY: usize = 99;
+pub const SYS_SYSCONF: usize = 100;
+pub const SYS_STATX: usize = 101;
+pub const SYS_TIMES: usize = 102;
+pub const SYS_ALARM: usize = 103;
+pub const SYS_MEMBARRIER: usize = 104;
+pub const SYS_MQ_OPEN: usize = 105;
+pub const SYS_MQ_SEND: usize = 106;
+pub const SYS_MQ_RECEIVE: usize = 107;
+pub const SYS_MQ_UNLINK: usize = 108;
+pub const SYS_SEM_OPEN: usize = 109;
+pub const SYS_SEM_POST: usize = 110;
+pub const SYS_SEM_WAIT: usize = 111;
+pub const SYS_SEM_TRYWAIT: usize = 112;
+pub const SYS_SEM_TIMEDWAIT: usize = 113;
+pub const SYS_SEM_UNLINK: usize = 114;
+pub const SYS_SHMGET: usize = 115;
+pub const SYS_SHMAT: usize = 116;
+pub const SYS_SHMDT: usize = 117;
+pub const SYS_SHMCTL: usize = 118;
+pub const SYS_MSGGET: usize = 119;
+pub const SYS_MSGSND: usize = 120;
+pub const SYS_MSGRCV: usize = 121;
+pub const SYS_MSGCTL: usize = 122;
+pub const SYS_SEMGET: usize = 123;
+pub const SYS_SEMOP: usize = 124;
+pub const SYS_SEMCTL: usize = 125;
+pub const SYS_SET_TID_ADDRESS: usize = 126;
+pub const SYS_GETRUSAGE: usize = 127;
+pub const SYS_GETGROUPS: usize = 128;
+pub const SYS_SETGROUPS: usize = 129;
+pub const SYS_FALLOCATE: usize = 130;
+pub const SYS_MOUNT: usize = 131;
+pub const SYS_STATFS: usize = 132;
+pub const SYS_GETRLIMIT: usize = 133;
+pub const SYS_SETRLIMIT: usize = 134;
+
+// constants for getpriority() and setpriority()'s `which` argument:
+// mlibc/abis/linux/resource.h
+pub const PRIO_PROCESS: usize = 0;
+pub const PRIO_PGRP: usize = 1;
+pub const PRIO_USER: usize = 2;
+
+// constants for getrlimit() and setrlimit()'s `resource` argument:
+// mlibc/abis/linux/resource.h
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIM_NLIMITS: usize = 16;
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+// constants for sysconf()'s `name` argument:
+// mlibc/options/posix/include/unistd.h
+pub const _SC_ARG_MAX: usize = 0;
+pub const _SC_CLK_TCK: usize = 2;
+pub const _SC_OPEN_MAX: usize = 4;
+pub const _SC_PAGESIZE: usize = 30;
+pub const _SC_PAGE_SIZE: usize = _SC_PAGESIZE;
+pub const _SC_NPROCESSORS_CONF: usize = 83;
+pub const _SC_NPROCESSORS_ONLN: usize = 84;
 
 // constants for fcntl()'s command argument:
 // mlibc/abis/linux/fcntl.h
@@ -129,11 +204,6 @@ pub const F_DUPFD_CLOEXEC: usize = 1030;
 pub const F_ADD_SEALS: usize = 1033;
 pub const F_GET_SEALS: usize = 1034;
 
-pub const F_SEAL_SEAL: usize = 0x0001;
-pub const F_SEAL_SHRINK: usize = 0x0002;
-pub const F_SEAL_GROW: usize = 0x0004;
-pub const F_SEAL_WRITE: usize = 0x0008;
-
 pub const F_RDLCK: usize = 0;
 pub const F_WRLCK: usize = 1;
 pub const F_UNLCK: usize = 2;
@@ -147,6 +217,23 @@ bitflags::bitflags! {
     }
 }
 
+// constants for fcntl()'s additional argument of F_ADD_SEALS and F_GET_SEALS:
+bitflags::bitflags! {
+    pub struct SealFlags: usize {
+        /// Prevents further seals from being added.
+        const SEAL         = 0x0001;
+        /// Prevents the file from shrinking.
+        const SHRINK       = 0x0002;
+        /// Prevents the file from growing.
+        const GROW         = 0x0004;
+        /// Prevents the contents of the file from being modified, which fails if the file has a
+        /// writable shared mapping.
+        const WRITE        = 0x0008;
+        /// Same as `WRITE`, but existing writable shared mappings can still modify the file.
+        const FUTURE_WRITE = 0x0010;
+    }
+}
+
 // constants for the epoll API:
 bitflags::bitflags! {
     pub struct EPollFlags: usize {
@@ -246,6 +333,103 @@ bitflags::bitflags! {
     }
 }
 
+// constants for memfd_create:
+bitflags::bitflags! {
+    // mlibc/options/linux/include/sys/mman.h
+    pub struct MemFdFlags: usize {
+        const CLOEXEC       = 1;
+        /// Allows seals to be added to the file with `F_ADD_SEALS`.
+        const ALLOW_SEALING = 2;
+    }
+}
+
+// constants for fallocate:
+bitflags::bitflags! {
+    // linux/falloc.h
+    pub struct FallocateFlags: usize {
+        /// Allocates the range without changing the size of the file.
+        const KEEP_SIZE  = 0x01;
+        /// Deallocates the range, which then reads as zeroes. Must be used with `KEEP_SIZE`.
+        const PUNCH_HOLE = 0x02;
+    }
+}
+
+// constants for mount:
+bitflags::bitflags! {
+    // linux/mount.h
+    pub struct MountFlags: usize {
+        /// Mounts the filesystem read-only.
+        const RDONLY  = 1;
+        /// Changes the flags of an existing mount.
+        const REMOUNT = 32;
+    }
+}
+
+// constants for the inotify API:
+bitflags::bitflags! {
+    // mlibc/options/linux/include/sys/inotify.h
+    pub struct InotifyFlags: usize {
+        const CLOEXEC  = OpenFlags::O_CLOEXEC.bits();
+        const NONBLOCK = OpenFlags::O_NONBLOCK.bits();
+    }
+}
+
+bitflags::bitflags! {
+    // linux/inotify.h
+    #[repr(transparent)]
+    pub struct InotifyMask: u32 {
+        const ACCESS        = 0x00000001;
+        const MODIFY        = 0x00000002;
+        const ATTRIB        = 0x00000004;
+        const CLOSE_WRITE   = 0x00000008;
+        const CLOSE_NOWRITE = 0x00000010;
+        const OPEN          = 0x00000020;
+        const MOVED_FROM    = 0x00000040;
+        const MOVED_TO      = 0x00000080;
+        const CREATE        = 0x00000100;
+        const DELETE        = 0x00000200;
+        const DELETE_SELF   = 0x00000400;
+        const MOVE_SELF     = 0x00000800;
+
+        /// The file system containing the watched object was unmounted.
+        const UNMOUNT       = 0x00002000;
+        /// The event queue overflowed.
+        const Q_OVERFLOW    = 0x00004000;
+        /// The watch was removed.
+        const IGNORED       = 0x00008000;
+
+        /// Only watch the path if it is a directory.
+        const ONLYDIR       = 0x01000000;
+        /// Do not follow the path if it is a symbolic link.
+        const DONT_FOLLOW   = 0x02000000;
+        const EXCL_UNLINK   = 0x04000000;
+        /// Fail if the path is already watched.
+        const MASK_CREATE   = 0x10000000;
+        /// Add to the mask of an existing watch instead of replacing it.
+        const MASK_ADD      = 0x20000000;
+        /// The subject of the event is a directory.
+        const ISDIR         = 0x40000000;
+        /// Remove the watch after the first event.
+        const ONESHOT       = 0x80000000;
+
+        const CLOSE = Self::CLOSE_WRITE.bits | Self::CLOSE_NOWRITE.bits;
+        const MOVE = Self::MOVED_FROM.bits | Self::MOVED_TO.bits;
+        const ALL_EVENTS = 0x00000fff;
+    }
+}
+
+// structures for the inotify API:
+//
+// The event is followed by `len` bytes of the NUL-terminated and padded name.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct InotifyEvent {
+    pub wd: i32,
+    pub mask: InotifyMask,
+    pub cookie: u32,
+    pub len: u32,
+}
+
 // framebuffer constants:
 //
 // NOTE: The framebuffer constants and structs are derived from the layout
@@ -0,0 +1,188 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Structures and constants of the `io_uring` interface.
+//!
+//! The layout of the structures follows `linux/io_uring.h`.
+
+/// `mmap` offset of the submission queue ring.
+pub const IORING_OFF_SQ_RING: usize = 0;
+/// `mmap` offset of the completion queue ring.
+pub const IORING_OFF_CQ_RING: usize = 0x8000000;
+/// `mmap` offset of the submission queue entries.
+pub const IORING_OFF_SQES: usize = 0x10000000;
+
+/// Maximum number of submission queue entries of a ring.
+pub const IORING_MAX_ENTRIES: usize = 4096;
+/// Maximum number of completion queue entries of a ring.
+pub const IORING_MAX_CQ_ENTRIES: usize = 2 * IORING_MAX_ENTRIES;
+
+/// Passed to `offset` of read and write requests to use the file position instead.
+pub const IORING_OFFSET_CURRENT: u64 = u64::MAX;
+
+/// Set in [`ProbeOp::flags`] if the operation is supported.
+pub const IO_URING_OP_SUPPORTED: u16 = 1 << 0;
+
+bitflags::bitflags! {
+    #[derive(Default)]
+    pub struct SetupFlags: u32 {
+        /// Use the number of completion queue entries passed in [`IoUringParams::cq_entries`].
+        const CQSIZE = 1 << 3;
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Default)]
+    pub struct Features: u32 {
+        /// Both rings are mapped with a single `mmap`.
+        const SINGLE_MMAP = 1 << 0;
+        /// Completions are never dropped when the completion queue is full.
+        const NODROP = 1 << 1;
+    }
+}
+
+bitflags::bitflags! {
+    pub struct SqRingFlags: u32 {
+        /// Completions are waiting in the kernel for room in the completion queue.
+        const CQ_OVERFLOW = 1 << 1;
+    }
+}
+
+bitflags::bitflags! {
+    pub struct EnterFlags: usize {
+        /// Wait for `min_complete` completions before returning.
+        const GETEVENTS = 1 << 0;
+    }
+}
+
+// linux/io_uring.h
+#[derive(Debug, Copy, Clone, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum IoUringOp {
+    Accept = 13,
+    Read = 22,
+    Write = 23,
+    Send = 26,
+    Recv = 27,
+}
+
+impl IoUringOp {
+    /// The last operation supported by the kernel.
+    pub const LAST: Self = Self::Recv;
+}
+
+// linux/io_uring.h
+#[derive(Debug, Copy, Clone, PartialEq, FromPrimitive)]
+pub enum RegisterOp {
+    Probe = 8,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct SqRingOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub flags: u32,
+    pub dropped: u32,
+    pub array: u32,
+    pub resv1: u32,
+    pub user_addr: u64,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct CqRingOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub overflow: u32,
+    pub cqes: u32,
+    pub flags: u32,
+    pub resv1: u32,
+    pub user_addr: u64,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct IoUringParams {
+    pub sq_entries: u32,
+    pub cq_entries: u32,
+    pub flags: SetupFlags,
+    pub sq_thread_cpu: u32,
+    pub sq_thread_idle: u32,
+    pub features: Features,
+    pub wq_fd: u32,
+    pub resv: [u32; 3],
+    pub sq_off: SqRingOffsets,
+    pub cq_off: CqRingOffsets,
+}
+
+/// Submission queue entry.
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct Sqe {
+    pub opcode: u8,
+    pub flags: u8,
+    pub ioprio: u16,
+    pub fd: i32,
+    /// Offset into the file for reads and writes, or the address of the address length for
+    /// accepts.
+    pub off: u64,
+    /// Address of the buffer, or of the socket address for accepts.
+    pub addr: u64,
+    pub len: u32,
+    /// Operation specific flags, such as the message flags of sends and receives.
+    pub op_flags: u32,
+    pub user_data: u64,
+    pub buf_index: u16,
+    pub personality: u16,
+    pub splice_fd_in: i32,
+    pub addr3: u64,
+    pub pad: u64,
+}
+
+/// Completion queue entry.
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct Cqe {
+    pub user_data: u64,
+    /// Result of the request, or the negated error code if it failed.
+    pub res: i32,
+    pub flags: u32,
+}
+
+/// Header of the buffer passed to [`RegisterOp::Probe`], followed by `ops_len` [`ProbeOp`]s.
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct Probe {
+    pub last_op: u8,
+    pub ops_len: u8,
+    pub resv: u16,
+    pub resv2: [u32; 3],
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct ProbeOp {
+    pub op: u8,
+    pub resv: u8,
+    pub flags: u16,
+    pub resv2: u32,
+}
@@ -23,8 +23,16 @@
 #[macro_use]
 extern crate num_derive;
 
+#[cfg(any(test, feature = "mock"))]
+extern crate std;
+
 pub mod consts;
+pub mod io_uring;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod netlink;
+pub mod perf;
+pub mod process;
 pub mod signal;
 pub mod socket;
 pub mod syscall;
@@ -36,6 +44,7 @@ use core::ffi;
 use core::time::Duration;
 
 use byte_endian::BigEndian;
+use num_traits::FromPrimitive;
 
 pub use crate::syscall::*;
 
@@ -107,7 +116,7 @@ bitflags::bitflags! {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, FromPrimitive)]
 #[repr(isize)]
 #[allow(clippy::enum_clike_unportable_variant)]
 pub enum SyscallError {
@@ -197,9 +206,146 @@ pub enum SyscallError {
     ENOMEDIUM = 1082,
     ENOTBLK = 1083,
 
+    /// Only used by the kernel: the system call was interrupted by a signal and is restarted,
+    /// unless the signal handler was installed without `SA_RESTART` (in which case it fails with
+    /// [`SyscallError::EINTR`]). Never returned to userland.
+    ERESTARTSYS = 512,
+
     Unknown = isize::MAX,
 }
 
+impl SyscallError {
+    /// Returns the POSIX description of the error, like `strerror(3)`.
+    pub fn strerror(&self) -> &'static str {
+        match self {
+            Self::EDOM => "numerical argument out of domain",
+            Self::EILSEQ => "illegal byte sequence",
+            Self::ERANGE => "result not representable",
+            Self::E2BIG => "argument list too long",
+            Self::EACCES => "permission denied",
+            Self::EADDRINUSE => "address in use",
+            Self::EADDRNOTAVAIL => "address not available",
+            Self::EAFNOSUPPORT => "address family not supported by protocol",
+            Self::EAGAIN => "resource temporarily unavailable",
+            Self::EALREADY => "operation already in progress",
+            Self::EBADF => "bad file descriptor",
+            Self::EBADMSG => "bad message",
+            Self::EBUSY => "resource busy",
+            Self::ECANCELED => "operation canceled",
+            Self::ECHILD => "no child process",
+            Self::ECONNABORTED => "connection aborted",
+            Self::ECONNREFUSED => "connection refused",
+            Self::ECONNRESET => "connection reset by peer",
+            Self::EDEADLK => "resource deadlock would occur",
+            Self::EDESTADDRREQ => "destination address required",
+            Self::EDQUOT => "quota exceeded",
+            Self::EEXIST => "file exists",
+            Self::EFAULT => "bad address",
+            Self::EFBIG => "file too large",
+            Self::EHOSTUNREACH => "host is unreachable",
+            Self::EIDRM => "identifier removed",
+            Self::EINPROGRESS => "operation in progress",
+            Self::EINTR => "interrupted system call",
+            Self::EINVAL => "invalid argument",
+            Self::EIO => "i/o error",
+            Self::EISCONN => "socket is connected",
+            Self::EISDIR => "is a directory",
+            Self::ELOOP => "symbolic link loop",
+            Self::EMFILE => "no file descriptors available",
+            Self::EMLINK => "too many links",
+            Self::EMSGSIZE => "message too large",
+            Self::EMULTIHOP => "multihop attempted",
+            Self::ENAMETOOLONG => "filename too long",
+            Self::ENETDOWN => "network is down",
+            Self::ENETRESET => "connection reset by network",
+            Self::ENETUNREACH => "network unreachable",
+            Self::ENFILE => "too many open files in system",
+            Self::ENOBUFS => "no buffer space available",
+            Self::ENODEV => "no such device",
+            Self::ENOENT => "no such file or directory",
+            Self::ENOEXEC => "exec format error",
+            Self::ENOLCK => "no locks available",
+            Self::ENOLINK => "link has been severed",
+            Self::ENOMEM => "out of memory",
+            Self::ENOMSG => "no message of desired type",
+            Self::ENOPROTOOPT => "protocol not available",
+            Self::ENOSPC => "no space left on device",
+            Self::ENOSYS => "function not implemented",
+            Self::ENOTCONN => "socket not connected",
+            Self::ENOTDIR => "not a directory",
+            Self::ENOTEMPTY => "directory not empty",
+            Self::ENOTRECOVERABLE => "state not recoverable",
+            Self::ENOTSOCK => "not a socket",
+            Self::ENOTSUP => "not supported",
+            Self::ENOTTY => "not a tty",
+            Self::ENXIO => "no such device or address",
+            Self::EOPNOTSUPP => "operation not supported on socket",
+            Self::EOVERFLOW => "value too large for data type",
+            Self::EOWNERDEAD => "previous owner died",
+            Self::EPERM => "operation not permitted",
+            Self::EPIPE => "broken pipe",
+            Self::EPROTO => "protocol error",
+            Self::EPROTONOSUPPORT => "protocol not supported",
+            Self::EPROTOTYPE => "protocol wrong type for socket",
+            Self::EROFS => "read-only file system",
+            Self::ESPIPE => "invalid seek",
+            Self::ESRCH => "no such process",
+            Self::ESTALE => "stale file handle",
+            Self::ETIMEDOUT => "operation timed out",
+            Self::ETXTBSY => "text file busy",
+            Self::EXDEV => "cross-device link",
+            Self::ENODATA => "no data available",
+            Self::ETIME => "timer expired",
+            Self::ENOKEY => "required key not available",
+            Self::ESHUTDOWN => "cannot send after socket shutdown",
+            Self::EHOSTDOWN => "host is down",
+            Self::EBADFD => "file descriptor in bad state",
+            Self::ENOMEDIUM => "no medium found",
+            Self::ENOTBLK => "block device required",
+            Self::ERESTARTSYS => "interrupted system call should be restarted",
+            Self::Unknown => "unknown error",
+        }
+    }
+}
+
+/// An error code that does not correspond to any [`SyscallError`] variant.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct UnknownError(pub isize);
+
+impl TryFrom<isize> for SyscallError {
+    type Error = UnknownError;
+
+    fn try_from(code: isize) -> core::result::Result<Self, Self::Error> {
+        Self::from_isize(code).ok_or(UnknownError(code))
+    }
+}
+
+impl TryFrom<usize> for SyscallError {
+    type Error = UnknownError;
+
+    fn try_from(code: usize) -> core::result::Result<Self, Self::Error> {
+        Self::from_usize(code).ok_or(UnknownError(code as isize))
+    }
+}
+
+impl From<SyscallError> for isize {
+    fn from(error: SyscallError) -> Self {
+        error as isize
+    }
+}
+
+impl core::fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.strerror())
+    }
+}
+
+impl From<SyscallError> for core::fmt::Error {
+    fn from(_: SyscallError) -> Self {
+        core::fmt::Error
+    }
+}
+
 #[derive(Debug)]
 #[repr(usize)]
 pub enum SysFileType {
@@ -304,6 +450,12 @@ impl From<usize> for SeekWhence {
     }
 }
 
+// arch_prctl commands
+pub const ARCH_SET_GS: usize = 0x1001;
+pub const ARCH_SET_FS: usize = 0x1002;
+pub const ARCH_GET_FS: usize = 0x1003;
+pub const ARCH_GET_GS: usize = 0x1004;
+
 pub const TIOCGWINSZ: usize = 0x5413;
 pub const TIOCSWINSZ: usize = 0x5414;
 pub const TCGETS: usize = 0x5401;
@@ -312,6 +464,8 @@ pub const TCSETSF: usize = 0x5404;
 pub const TIOCSCTTY: usize = 0x540e;
 pub const TIOCNOTTY: usize = 0x5422;
 pub const TIOCGPGRP: usize = 0x540f;
+pub const TIOCSPGRP: usize = 0x5410;
+pub const KDKBDREP: usize = 0x4b52;
 
 #[derive(Default, Debug, Copy, Clone)]
 #[repr(C)]
@@ -322,6 +476,15 @@ pub struct WinSize {
     pub ws_ypixel: u16,
 }
 
+/// The key repeat settings of the keyboard, used by `KDKBDREP`. The delay before a held down key
+/// repeats and the period between the repeats are in milliseconds.
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+pub struct KbdRepeat {
+    pub delay: i32,
+    pub period: i32,
+}
+
 // indices for the c_cc array in struct termios
 //
 // abis/linux/termios.h
@@ -456,6 +619,12 @@ impl Termios {
 
 pub const AT_FDCWD: isize = -100;
 
+// mode bits of `access`, from abi-bits/access.h:
+pub const F_OK: usize = 0;
+pub const X_OK: usize = 1;
+pub const W_OK: usize = 2;
+pub const R_OK: usize = 4;
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct SysInfo {
@@ -496,19 +665,20 @@ pub fn isize_as_syscall_result(value: isize) -> Result<usize> {
     if value >= 0 {
         Ok(value as usize)
     } else {
-        let err: SyscallError = unsafe { core::mem::transmute((-value) as u64) };
-        Err(err)
+        Err(SyscallError::try_from(value.wrapping_neg()).unwrap_or(SyscallError::Unknown))
     }
 }
 
-pub fn sys_ipc_send(pid: usize, message: &[u8]) -> Result<()> {
+/// Sends `message` to the process `pid` and returns the sequence number the kernel assigned to
+/// it, which identifies the message in the kernel IPC trace.
+pub fn sys_ipc_send(pid: usize, message: &[u8]) -> Result<usize> {
     let value = syscall3(
         prelude::SYS_IPC_SEND,
         pid,
         message.as_ptr() as usize,
         message.len(),
     );
-    isize_as_syscall_result(value as _).map(|_| ())
+    isize_as_syscall_result(value as _)
 }
 
 pub fn sys_ipc_recv<'a>(
@@ -536,6 +706,632 @@ pub fn sys_ipc_become_root() -> Result<()> {
     isize_as_syscall_result(value as _).map(|_| ())
 }
 
+/// Returns the PID of the calling process.
+pub fn sys_getpid() -> usize {
+    syscall0(prelude::SYS_GETPID)
+}
+
+/// Puts the calling thread to sleep for at least `duration`, which the kernel rounds up to whole
+/// seconds.
+pub fn sys_sleep(duration: &TimeSpec) -> Result<()> {
+    let value = syscall1(prelude::SYS_SLEEP, duration as *const TimeSpec as usize);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Terminates all of the other processes, syncs the filesystems and carries out `cmd`. Only
+/// returns on failure, except for [`RebootCmd::CadOn`] and [`RebootCmd::CadOff`] which change
+/// what Ctrl-Alt-Del does.
+pub fn sys_reboot(cmd: RebootCmd) -> Result<()> {
+    let value = syscall4(
+        prelude::SYS_REBOOT,
+        REBOOT_MAGIC1 as usize,
+        REBOOT_MAGIC2 as usize,
+        cmd as usize,
+        0,
+    );
+
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Powers off the system, the same as `sys_reboot(RebootCmd::PowerOff)`.
+pub fn sys_shutdown() -> ! {
+    syscall0(prelude::SYS_SHUTDOWN);
+    unreachable!("shutdown returned")
+}
+
+/// Reads the host name into `buffer` and returns it. Fails with `ENAMETOOLONG` if it does not
+/// fit, including its NUL terminator.
+pub fn sys_gethostname(buffer: &mut [u8]) -> Result<&str> {
+    let value = syscall2(
+        prelude::SYS_GETHOSTNAME,
+        buffer.as_mut_ptr() as usize,
+        buffer.len(),
+    );
+
+    let size = isize_as_syscall_result(value as _)?;
+    core::str::from_utf8(&buffer[..size]).map_err(|_| SyscallError::EILSEQ)
+}
+
+/// Sets the host name of the system. The name must be at most 64 bytes long and must not
+/// contain NUL or whitespace characters.
+pub fn sys_sethostname(name: &str) -> Result<()> {
+    let value = syscall2(prelude::SYS_SETHOSTNAME, name.as_ptr() as usize, name.len());
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Sets the NIS domain name of the system, with the same restrictions as [`sys_sethostname`].
+pub fn sys_setdomainname(name: &str) -> Result<()> {
+    let value = syscall2(
+        prelude::SYS_SETDOMAINNAME,
+        name.as_ptr() as usize,
+        name.len(),
+    );
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Opens the file at `path`, relative to the current directory, and returns its file
+/// descriptor. `mode` holds the permissions of the file if it is created.
+pub fn sys_open(path: &str, flags: OpenFlags, mode: usize) -> Result<usize> {
+    let value = syscall5(
+        prelude::SYS_OPEN,
+        AT_FDCWD as usize,
+        path.as_ptr() as usize,
+        path.len(),
+        flags.bits(),
+        mode,
+    );
+    isize_as_syscall_result(value as _)
+}
+
+/// Closes the file descriptor `fd`.
+pub fn sys_close(fd: usize) -> Result<()> {
+    let value = syscall1(prelude::SYS_CLOSE, fd);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Maps `size` bytes of the file `fd` starting at `offset`, or anonymous memory if `flags`
+/// contains [`MMapFlags::MAP_ANONYOMUS`] and `fd` is `usize::MAX`. Returns the address of the
+/// mapping, which is `address` if [`MMapFlags::MAP_FIXED`] is set.
+pub fn sys_mmap(
+    address: usize,
+    size: usize,
+    protection: MMapProt,
+    flags: MMapFlags,
+    fd: usize,
+    offset: usize,
+) -> Result<*mut u8> {
+    let value = syscall6(
+        prelude::SYS_MMAP,
+        address,
+        size,
+        protection.bits(),
+        flags.bits(),
+        fd,
+        offset,
+    );
+    isize_as_syscall_result(value as _).map(|address| address as *mut u8)
+}
+
+/// Removes the mappings of the `size` bytes at `address`.
+pub fn sys_munmap(address: *mut u8, size: usize) -> Result<()> {
+    let value = syscall2(prelude::SYS_MUNMAP, address as usize, size);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Creates an empty anonymous file that lives in memory and returns its file descriptor. The
+/// `name` is only used for debugging.
+pub fn sys_memfd_create(name: &str, flags: consts::MemFdFlags) -> Result<usize> {
+    let value = syscall3(
+        prelude::SYS_MEMFD_CREATE,
+        name.as_ptr() as usize,
+        name.len(),
+        flags.bits(),
+    );
+    isize_as_syscall_result(value as _)
+}
+
+/// Truncates or extends the file referred to by `fd` to `length` bytes.
+pub fn sys_ftruncate(fd: usize, length: usize) -> Result<()> {
+    let value = syscall2(prelude::SYS_FTRUNCATE, fd, length);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Allocates the disk space of the `len` bytes at `offset` in the file referred to by `fd`, so
+/// that writing to them later does not fail with `ENOSPC`.
+pub fn sys_fallocate(
+    fd: usize,
+    flags: consts::FallocateFlags,
+    offset: usize,
+    len: usize,
+) -> Result<()> {
+    let value = syscall4(prelude::SYS_FALLOCATE, fd, flags.bits(), offset, len);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Changes the flags of the mount at `target`, which requires [`MountFlags::REMOUNT`]. For
+/// example, [`MountFlags::RDONLY`] makes the mount read-only until it is remounted without it.
+pub fn sys_mount(target: &str, flags: consts::MountFlags) -> Result<()> {
+    let value = syscall3(
+        prelude::SYS_MOUNT,
+        target.as_ptr() as usize,
+        target.len(),
+        flags.bits(),
+    );
+
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Adds `seals` to the memory-backed file referred to by `fd`, which must have been created with
+/// [`MemFdFlags::ALLOW_SEALING`].
+///
+/// [`MemFdFlags::ALLOW_SEALING`]: consts::MemFdFlags::ALLOW_SEALING
+pub fn sys_add_seals(fd: usize, seals: consts::SealFlags) -> Result<()> {
+    let value = syscall3(prelude::SYS_FCNTL, fd, prelude::F_ADD_SEALS, seals.bits());
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Returns the seals of the file referred to by `fd`.
+pub fn sys_get_seals(fd: usize) -> Result<consts::SealFlags> {
+    let value = syscall3(prelude::SYS_FCNTL, fd, prelude::F_GET_SEALS, 0);
+    isize_as_syscall_result(value as _).map(consts::SealFlags::from_bits_truncate)
+}
+
+/// Returns how the kernel object of type `typ` used by the process `pid1` orders compared to the
+/// one used by the process `pid2`. Equal objects are the same object. For [`KcmpType::File`],
+/// `idx1` and `idx2` are the file descriptors to compare.
+pub fn sys_kcmp(
+    pid1: usize,
+    pid2: usize,
+    typ: KcmpType,
+    idx1: usize,
+    idx2: usize,
+) -> Result<core::cmp::Ordering> {
+    let value = syscall5(prelude::SYS_KCMP, pid1, pid2, typ as usize, idx1, idx2);
+
+    isize_as_syscall_result(value as _).map(|ordering| match ordering {
+        0 => core::cmp::Ordering::Equal,
+        1 => core::cmp::Ordering::Less,
+        _ => core::cmp::Ordering::Greater,
+    })
+}
+
+/// Registers `tid` to be zeroed when the calling thread exits, followed by a futex wake on it.
+/// Returns the thread ID of the caller.
+pub fn sys_set_tid_address(tid: *mut u32) -> usize {
+    syscall1(prelude::SYS_SET_TID_ADDRESS, tid as usize)
+}
+
+/// Sets the file mode creation mask of the calling process and returns the previous one.
+pub fn sys_umask(mask: Mode) -> Result<Mode> {
+    let value = syscall1(prelude::SYS_UMASK, mask.bits() as usize);
+    isize_as_syscall_result(value as _).map(|old| Mode::from_bits_truncate(old as u32))
+}
+
+/// Returns the status of the file at `path`, relative to the directory `fd` (or the current
+/// directory if it is [`AT_FDCWD`]). `mask` is the set of fields the caller is interested in, and
+/// the kernel sets `statx.stx_mask` to the fields it filled in.
+pub fn sys_statx(
+    fd: usize,
+    path: &str,
+    flags: AtFlags,
+    mask: StatxMask,
+    statx: &mut Statx,
+) -> Result<()> {
+    let value = syscall6(
+        prelude::SYS_STATX,
+        fd,
+        path.as_ptr() as usize,
+        path.len(),
+        flags.bits(),
+        mask.bits() as usize,
+        statx as *mut Statx as usize,
+    );
+
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Returns information about the filesystem that the file at `path` is on, relative to the
+/// directory `fd` (or the current directory if it is [`AT_FDCWD`]). An empty `path` refers to the
+/// file descriptor `fd` itself (`fstatfs`).
+pub fn sys_statfs(fd: usize, path: &str, statfs: &mut StatFs) -> Result<()> {
+    let value = syscall4(
+        prelude::SYS_STATFS,
+        fd,
+        path.as_ptr() as usize,
+        path.len(),
+        statfs as *mut StatFs as usize,
+    );
+
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Returns the value of the system configuration variable `name`, one of the `_SC_*`
+/// constants. Fails with `EINVAL` if `name` is not supported.
+pub fn sys_sysconf(name: usize) -> Result<usize> {
+    let value = syscall1(prelude::SYS_SYSCONF, name);
+    isize_as_syscall_result(value as _)
+}
+
+/// Creates an `io_uring` instance with at least `entries` submission queue entries and returns a
+/// file descriptor referring to it. The offsets of the rings are returned in `params`.
+pub fn sys_io_uring_setup(entries: usize, params: &mut io_uring::IoUringParams) -> Result<usize> {
+    let value = syscall2(
+        prelude::SYS_IO_URING_SETUP,
+        entries,
+        params as *mut io_uring::IoUringParams as usize,
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Submits `to_submit` requests from the submission queue and, with
+/// [`io_uring::EnterFlags::GETEVENTS`], waits until at least `min_complete` completions are
+/// available. Returns the number of submitted requests.
+pub fn sys_io_uring_enter(
+    fd: usize,
+    to_submit: usize,
+    min_complete: usize,
+    flags: io_uring::EnterFlags,
+) -> Result<usize> {
+    let value = syscall6(
+        prelude::SYS_IO_URING_ENTER,
+        fd,
+        to_submit,
+        min_complete,
+        flags.bits(),
+        0,
+        0,
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Creates an inotify instance and returns a file descriptor referring to it. Events are read
+/// from it as [`consts::InotifyEvent`] records, each followed by the name of the entry.
+pub fn sys_inotify_init1(flags: consts::InotifyFlags) -> Result<usize> {
+    let value = syscall1(prelude::SYS_INOTIFY_INIT, flags.bits());
+    isize_as_syscall_result(value as _)
+}
+
+/// Watches `path` for the events in `mask` and returns the watch descriptor. Watching a path
+/// that is already watched by the instance `fd` updates its watch.
+pub fn sys_inotify_add_watch(fd: usize, path: &str, mask: consts::InotifyMask) -> Result<usize> {
+    let value = syscall4(
+        prelude::SYS_INOTIFY_ADD_WATCH,
+        fd,
+        path.as_ptr() as usize,
+        path.len(),
+        mask.bits() as usize,
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Removes the watch `wd` from the inotify instance `fd`.
+pub fn sys_inotify_rm_watch(fd: usize, wd: usize) -> Result<usize> {
+    let value = syscall2(prelude::SYS_INOTIFY_RM_WATCH, fd, wd);
+    isize_as_syscall_result(value as _)
+}
+
+/// Opens a counter for the event described by `attr` and returns a file descriptor referring to
+/// it. Only the calling thread (`pid == 0`) can be measured.
+pub fn sys_perf_event_open(
+    attr: &perf::PerfEventAttr,
+    pid: isize,
+    cpu: isize,
+    group_fd: isize,
+    flags: perf::PerfEventFlags,
+) -> Result<usize> {
+    let value = syscall5(
+        prelude::SYS_PERF_EVENT_OPEN,
+        attr as *const _ as usize,
+        pid as usize,
+        cpu as usize,
+        group_fd as usize,
+        flags.bits(),
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Performs the registration operation `opcode` on the `io_uring` instance referred to by `fd`.
+pub fn sys_io_uring_register(
+    fd: usize,
+    opcode: io_uring::RegisterOp,
+    arg: usize,
+    nr_args: usize,
+) -> Result<usize> {
+    let value = syscall4(
+        prelude::SYS_IO_URING_REGISTER,
+        fd,
+        opcode as usize,
+        arg,
+        nr_args,
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Duplicates `fd` onto `new_fd` and returns `new_fd`. If `new_fd` was open, it is closed first;
+/// if it is equal to `fd`, nothing is done. The close-on-exec flag of `new_fd` is always cleared.
+pub fn sys_dup2(fd: usize, new_fd: usize) -> Result<usize> {
+    let value = syscall2(prelude::SYS_DUP2, fd, new_fd);
+    isize_as_syscall_result(value as _)
+}
+
+/// Same as [`sys_dup2`], except that `flags` may contain [`OpenFlags::O_CLOEXEC`] to set the
+/// close-on-exec flag of `new_fd`, and that `fd` equal to `new_fd` fails with `EINVAL`.
+pub fn sys_dup3(fd: usize, new_fd: usize, flags: OpenFlags) -> Result<usize> {
+    let value = syscall3(prelude::SYS_DUP3, fd, new_fd, flags.bits());
+    isize_as_syscall_result(value as _)
+}
+
+/// Sends the data gathered from the I/O vectors of `header` on the socket `fd`, along with its
+/// control messages. Returns the number of bytes sent.
+pub fn sys_sendmsg(
+    fd: usize,
+    header: &socket::MessageHeader,
+    flags: socket::MessageFlags,
+) -> Result<usize> {
+    let value = syscall3(
+        prelude::SYS_SOCK_SEND,
+        fd,
+        header as *const socket::MessageHeader as usize,
+        flags.bits(),
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Receives data from the socket `fd` into the I/O vectors of `header`. The source address, the
+/// size of the control messages and the flags of the message (e.g. [`socket::MessageFlags::TRUNC`])
+/// are returned in `header`.
+pub fn sys_recvmsg(
+    fd: usize,
+    header: &mut socket::MessageHeader,
+    flags: socket::MessageFlags,
+) -> Result<usize> {
+    let value = syscall3(
+        prelude::SYS_SOCK_RECV,
+        fd,
+        header as *mut socket::MessageHeader as usize,
+        flags.bits(),
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Opens the POSIX message queue `name` and returns a file descriptor referring to it. With
+/// [`OpenFlags::O_CREAT`], a missing queue is created with the limits in `attr`, or the default
+/// ones if it is `None`.
+pub fn sys_mq_open(
+    name: &str,
+    flags: OpenFlags,
+    mode: Mode,
+    attr: Option<&MqAttr>,
+) -> Result<usize> {
+    let value = syscall5(
+        prelude::SYS_MQ_OPEN,
+        name.as_ptr() as usize,
+        name.len(),
+        flags.bits(),
+        mode.bits() as usize,
+        attr.map_or(0, |attr| attr as *const MqAttr as usize),
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Adds `message` with the given `priority` to the message queue `fd`, blocking while the queue
+/// is full unless it was opened with [`OpenFlags::O_NONBLOCK`].
+pub fn sys_mq_send(fd: usize, message: &[u8], priority: u32) -> Result<()> {
+    let value = syscall4(
+        prelude::SYS_MQ_SEND,
+        fd,
+        message.as_ptr() as usize,
+        message.len(),
+        priority as usize,
+    );
+
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Removes the oldest message of the highest priority from the message queue `fd` and copies it
+/// into `buffer`, blocking while the queue is empty unless it was opened with
+/// [`OpenFlags::O_NONBLOCK`]. Returns the size of the message and stores its priority in
+/// `priority`.
+pub fn sys_mq_receive(fd: usize, buffer: &mut [u8], priority: Option<&mut u32>) -> Result<usize> {
+    let value = syscall4(
+        prelude::SYS_MQ_RECEIVE,
+        fd,
+        buffer.as_mut_ptr() as usize,
+        buffer.len(),
+        priority.map_or(0, |priority| priority as *mut u32 as usize),
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Removes the name of the message queue `name`. The queue is destroyed once every file
+/// descriptor referring to it is closed.
+pub fn sys_mq_unlink(name: &str) -> Result<()> {
+    let value = syscall2(prelude::SYS_MQ_UNLINK, name.as_ptr() as usize, name.len());
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Opens the named POSIX semaphore `name` and returns a file descriptor referring to it. With
+/// [`OpenFlags::O_CREAT`], a missing semaphore is created with the initial `value`.
+pub fn sys_sem_open(name: &str, flags: OpenFlags, mode: Mode, value: u32) -> Result<usize> {
+    let value = syscall5(
+        prelude::SYS_SEM_OPEN,
+        name.as_ptr() as usize,
+        name.len(),
+        flags.bits(),
+        mode.bits() as usize,
+        value as usize,
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Increments the semaphore `fd`, waking up one of the tasks waiting for it.
+pub fn sys_sem_post(fd: usize) -> Result<()> {
+    let value = syscall1(prelude::SYS_SEM_POST, fd);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Decrements the semaphore `fd`, blocking while its value is zero.
+pub fn sys_sem_wait(fd: usize) -> Result<()> {
+    let value = syscall1(prelude::SYS_SEM_WAIT, fd);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Same as [`sys_sem_wait`], except that it fails with `EAGAIN` instead of blocking.
+pub fn sys_sem_trywait(fd: usize) -> Result<()> {
+    let value = syscall1(prelude::SYS_SEM_TRYWAIT, fd);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Same as [`sys_sem_wait`], except that it fails with `ETIMEDOUT` once the absolute
+/// `CLOCK_REALTIME` time `deadline` has passed.
+pub fn sys_sem_timedwait(fd: usize, deadline: &TimeSpec) -> Result<()> {
+    let value = syscall2(
+        prelude::SYS_SEM_TIMEDWAIT,
+        fd,
+        deadline as *const TimeSpec as usize,
+    );
+
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Removes the name of the semaphore `name`. The semaphore is destroyed once every file
+/// descriptor referring to it is closed.
+pub fn sys_sem_unlink(name: &str) -> Result<()> {
+    let value = syscall2(prelude::SYS_SEM_UNLINK, name.as_ptr() as usize, name.len());
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Returns the identifier of the System V shared memory segment with the key `key`. With
+/// [`IPC_CREAT`], a missing segment of `size` bytes is created; [`IPC_PRIVATE`] always creates a
+/// new segment.
+pub fn sys_shmget(key: i32, size: usize, flags: usize) -> Result<usize> {
+    let value = syscall3(prelude::SYS_SHMGET, key as usize, size, flags);
+    isize_as_syscall_result(value as _)
+}
+
+/// Attaches the shared memory segment `id` to the address space of the calling process and
+/// returns the address it is attached at. A null `address` lets the kernel pick one.
+pub fn sys_shmat(id: usize, address: usize, flags: usize) -> Result<usize> {
+    let value = syscall3(prelude::SYS_SHMAT, id, address, flags);
+    isize_as_syscall_result(value as _)
+}
+
+/// Detaches the shared memory segment attached at `address`.
+pub fn sys_shmdt(address: usize) -> Result<()> {
+    let value = syscall1(prelude::SYS_SHMDT, address);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Performs the control operation `command` on the shared memory segment `id`. `buffer` is
+/// filled by [`IPC_STAT`] and read by [`IPC_SET`].
+pub fn sys_shmctl(id: usize, command: usize, buffer: Option<&mut ShmidDs>) -> Result<usize> {
+    let value = syscall3(
+        prelude::SYS_SHMCTL,
+        id,
+        command,
+        buffer.map_or(0, |buffer| buffer as *mut ShmidDs as usize),
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Returns the identifier of the System V message queue with the key `key`. With [`IPC_CREAT`],
+/// a missing queue is created; [`IPC_PRIVATE`] always creates a new queue.
+pub fn sys_msgget(key: i32, flags: usize) -> Result<usize> {
+    let value = syscall2(prelude::SYS_MSGGET, key as usize, flags);
+    isize_as_syscall_result(value as _)
+}
+
+/// Sends the first `size` bytes of the text of `message` to the message queue `id`, blocking
+/// while the queue is full unless [`IPC_NOWAIT`] is set.
+pub fn sys_msgsnd<const N: usize>(
+    id: usize,
+    message: &MsgBuf<N>,
+    size: usize,
+    flags: usize,
+) -> Result<()> {
+    // The kernel would read past the end of `message`.
+    if size > N {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let value = syscall4(
+        prelude::SYS_MSGSND,
+        id,
+        message as *const MsgBuf<N> as usize,
+        size,
+        flags,
+    );
+
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Receives a message of the type selected by `mtype` from the message queue `id` into
+/// `message`, and returns the size of its text. Blocks while there is no such message unless
+/// [`IPC_NOWAIT`] is set.
+pub fn sys_msgrcv<const N: usize>(
+    id: usize,
+    message: &mut MsgBuf<N>,
+    mtype: i64,
+    flags: usize,
+) -> Result<usize> {
+    let value = syscall5(
+        prelude::SYS_MSGRCV,
+        id,
+        message as *mut MsgBuf<N> as usize,
+        N,
+        mtype as usize,
+        flags,
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Performs the control operation `command` on the message queue `id`. `buffer` is filled by
+/// [`IPC_STAT`] and read by [`IPC_SET`].
+pub fn sys_msgctl(id: usize, command: usize, buffer: Option<&mut MsqidDs>) -> Result<usize> {
+    let value = syscall3(
+        prelude::SYS_MSGCTL,
+        id,
+        command,
+        buffer.map_or(0, |buffer| buffer as *mut MsqidDs as usize),
+    );
+
+    isize_as_syscall_result(value as _)
+}
+
+/// Returns the identifier of the System V semaphore set with the key `key`. With [`IPC_CREAT`],
+/// a missing set of `nsems` semaphores is created; [`IPC_PRIVATE`] always creates a new set.
+pub fn sys_semget(key: i32, nsems: usize, flags: usize) -> Result<usize> {
+    let value = syscall3(prelude::SYS_SEMGET, key as usize, nsems, flags);
+    isize_as_syscall_result(value as _)
+}
+
+/// Performs the operations `ops` on the semaphore set `id` atomically, blocking until all of
+/// them can be performed unless one that cannot has [`IPC_NOWAIT`] set.
+pub fn sys_semop(id: usize, ops: &[SemBuf]) -> Result<()> {
+    let value = syscall3(prelude::SYS_SEMOP, id, ops.as_ptr() as usize, ops.len());
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Performs the control operation `command` on the semaphore `semnum` of the set `id`. `arg` is
+/// a value for [`SETVAL`], a pointer to a [`SemidDs`] for [`IPC_STAT`] and [`IPC_SET`], or a
+/// pointer to an array of values for [`GETALL`] and [`SETALL`].
+pub fn sys_semctl(id: usize, semnum: usize, command: usize, arg: usize) -> Result<usize> {
+    let value = syscall4(prelude::SYS_SEMCTL, id, semnum, command, arg);
+    isize_as_syscall_result(value as _)
+}
+
 // Sockets
 pub trait SocketAddr: Send + Sync {}
 
@@ -546,19 +1342,63 @@ pub struct SocketAddrUnix {
     pub path: [u8; 108],
 }
 
+/// The name of a unix socket address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnixSocketName<'a> {
+    /// The address of a socket that is not bound.
+    Unnamed,
+    /// The path of a socket file, without the NUL terminator.
+    Path(&'a [u8]),
+    /// A name in the abstract namespace, without the leading NUL byte. It is not NUL terminated
+    /// and may contain NUL bytes, so its end is given by the length of the address.
+    Abstract(&'a [u8]),
+}
+
 impl SocketAddrUnix {
-    pub fn path_len(&self) -> u8 {
-        if self.path[0] == 0 {
-            if self.path[1] == 0 {
-                // address is unnamed
-                return 0;
-            } else {
-                // abstract socket address
-                unimplemented!()
+    /// Offset of the path in the address, which is the length of an unnamed address.
+    pub const PATH_OFFSET: usize = core::mem::size_of::<u32>();
+
+    /// Creates an address with the name `name`, and returns it along with its length. Returns
+    /// [`None`] if the name does not fit in the address.
+    pub fn new(name: UnixSocketName) -> Option<(Self, usize)> {
+        let mut address = Self::default();
+
+        let length = match name {
+            UnixSocketName::Unnamed => 0,
+
+            UnixSocketName::Path(path) => {
+                address.path.get_mut(..path.len())?.copy_from_slice(path);
+
+                // The path is not NUL terminated if it fills the whole buffer.
+                core::cmp::min(path.len() + 1, address.path.len())
             }
-        }
 
-        (self.path.iter().position(|&c| c == 0).unwrap_or(108) as u8) + 1
+            UnixSocketName::Abstract(name) => {
+                address
+                    .path
+                    .get_mut(1..name.len() + 1)?
+                    .copy_from_slice(name);
+                name.len() + 1
+            }
+        };
+
+        Some((address, Self::PATH_OFFSET + length))
+    }
+
+    /// Returns the name of the address, which is `length` bytes long including the family.
+    pub fn name(&self, length: usize) -> UnixSocketName<'_> {
+        let length = length
+            .saturating_sub(Self::PATH_OFFSET)
+            .min(self.path.len());
+
+        match &self.path[..length] {
+            [] => UnixSocketName::Unnamed,
+            [0, name @ ..] => UnixSocketName::Abstract(name),
+            path => {
+                let end = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+                UnixSocketName::Path(&path[..end])
+            }
+        }
     }
 }
 
@@ -732,6 +1572,136 @@ pub struct Stat {
     pub __unused: [ffi::c_long; 3],
 }
 
+// linux/magic.h
+pub const EXT2_SUPER_MAGIC: u64 = 0xef53;
+pub const TMPFS_MAGIC: u64 = 0x0102_1994;
+
+// mlibc/abis/linux/statfs.h
+#[repr(C)]
+#[derive(Debug, Default, Clone)]
+pub struct StatFs {
+    /// Type of the filesystem, one of the `*_MAGIC` constants.
+    pub f_type: u64,
+    pub f_bsize: u64,
+    pub f_blocks: u64,
+    pub f_bfree: u64,
+    /// Free blocks that can be used by unprivileged users.
+    pub f_bavail: u64,
+    pub f_files: u64,
+    pub f_ffree: u64,
+    pub f_fsid: [i32; 2],
+    pub f_namelen: u64,
+    pub f_frsize: u64,
+    pub f_flags: u64,
+    pub f_spare: [u64; 4],
+}
+
+bitflags::bitflags! {
+    // linux/stat.h
+    #[derive(Default)]
+    #[repr(transparent)]
+    pub struct StatxMask: u32 {
+        const TYPE = 0x1;
+        const MODE = 0x2;
+        const NLINK = 0x4;
+        const UID = 0x8;
+        const GID = 0x10;
+        const ATIME = 0x20;
+        const MTIME = 0x40;
+        const CTIME = 0x80;
+        const INO = 0x100;
+        const SIZE = 0x200;
+        const BLOCKS = 0x400;
+        /// The fields that are also in [`Stat`].
+        const BASIC_STATS = 0x7ff;
+        const BTIME = 0x800;
+
+        /// Reserved for future extension, it is an error to request it.
+        const RESERVED = 0x8000_0000;
+    }
+}
+
+// linux/stat.h
+#[derive(Debug, Default, Clone)]
+#[repr(C)]
+pub struct StatxTimestamp {
+    pub tv_sec: i64,
+    pub tv_nsec: u32,
+    pub __reserved: i32,
+}
+
+impl From<TimeSpec> for StatxTimestamp {
+    fn from(value: TimeSpec) -> Self {
+        Self {
+            tv_sec: value.tv_sec as i64,
+            tv_nsec: value.tv_nsec as u32,
+            __reserved: 0,
+        }
+    }
+}
+
+impl From<Duration> for StatxTimestamp {
+    fn from(value: Duration) -> Self {
+        TimeSpec::from(value).into()
+    }
+}
+
+// linux/stat.h
+#[derive(Debug, Default, Clone)]
+#[repr(C)]
+pub struct Statx {
+    /// The fields that have been filled in.
+    pub stx_mask: StatxMask,
+    pub stx_blksize: u32,
+    pub stx_attributes: u64,
+    pub stx_nlink: u32,
+    pub stx_uid: u32,
+    pub stx_gid: u32,
+    pub stx_mode: u16,
+    pub __spare0: u16,
+    pub stx_ino: u64,
+    pub stx_size: u64,
+    pub stx_blocks: u64,
+    pub stx_attributes_mask: u64,
+    pub stx_atime: StatxTimestamp,
+    /// Time of creation of the file, only valid if `stx_mask` contains [`StatxMask::BTIME`].
+    pub stx_btime: StatxTimestamp,
+    pub stx_ctime: StatxTimestamp,
+    pub stx_mtime: StatxTimestamp,
+    pub stx_rdev_major: u32,
+    pub stx_rdev_minor: u32,
+    pub stx_dev_major: u32,
+    pub stx_dev_minor: u32,
+    pub __spare2: [u64; 14],
+}
+
+static_assertions::const_assert_eq!(core::mem::size_of::<Statx>(), 256);
+
+#[cfg(target_arch = "x86_64")]
+impl From<Stat> for Statx {
+    fn from(stat: Stat) -> Self {
+        Self {
+            stx_mask: StatxMask::BASIC_STATS,
+            stx_blksize: stat.st_blksize as u32,
+            stx_nlink: stat.st_nlink,
+            stx_uid: stat.st_uid,
+            stx_gid: stat.st_gid,
+            stx_mode: stat.st_mode.bits() as u16,
+            stx_ino: stat.st_ino,
+            stx_size: stat.st_size as u64,
+            stx_blocks: stat.st_blocks,
+            stx_atime: stat.st_atim.into(),
+            stx_ctime: stat.st_ctim.into(),
+            stx_mtime: stat.st_mtim.into(),
+            stx_rdev_major: (stat.st_rdev >> 8) as u32 & 0xfff,
+            stx_rdev_minor: (stat.st_rdev as u32 & 0xff) | ((stat.st_rdev >> 12) as u32 & !0xff),
+            stx_dev_major: (stat.st_dev >> 8) as u32 & 0xfff,
+            stx_dev_minor: (stat.st_dev as u32 & 0xff) | ((stat.st_dev >> 12) as u32 & !0xff),
+            ..Default::default()
+        }
+    }
+}
+
 bitflags::bitflags! {
     // mlibc/abis/linux/fcntl.h
     #[repr(transparent)]
@@ -755,3 +1725,1350 @@ bitflags::bitflags! {
         const NO_AUTOMOUNT = 0x800;
     }
 }
+
+bitflags::bitflags! {
+    // linux/openat2.h
+    #[repr(transparent)]
+    pub struct ResolveFlags: u64 {
+        /// Block mount-point crossings (including bind-mounts).
+        const NO_XDEV = 0x01;
+        /// Block traversal through procfs-style "magic-links". Aero has none, so this is always
+        /// satisfied.
+        const NO_MAGICLINKS = 0x02;
+        /// Block traversal through all symbolic links.
+        const NO_SYMLINKS = 0x04;
+        /// Block "lexical" trickery like "..", symlinks, and absolute paths which escape the
+        /// directory file descriptor.
+        const BENEATH = 0x08;
+    }
+}
+
+/// Argument structure for `openat2(2)`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct OpenHow {
+    /// `O_*` flags.
+    pub flags: u64,
+    /// `O_CREAT` and `O_TMPFILE` file mode.
+    pub mode: u64,
+    /// `RESOLVE_*` flags.
+    pub resolve: u64,
+}
+
+/// Attributes of a POSIX message queue, used by `mq_open(3)`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct MqAttr {
+    /// Flags of the message queue description (`0` or `O_NONBLOCK`).
+    pub mq_flags: i64,
+    /// Maximum number of messages in the queue.
+    pub mq_maxmsg: i64,
+    /// Maximum size of a message, in bytes.
+    pub mq_msgsize: i64,
+    /// Number of messages currently in the queue.
+    pub mq_curmsgs: i64,
+}
+
+/// Messages of a POSIX message queue must have a priority below this value.
+pub const MQ_PRIO_MAX: u32 = 32768;
+
+/// Maximum value of a POSIX semaphore.
+pub const SEM_VALUE_MAX: u32 = i32::MAX as u32;
+
+// mlibc/abis/linux/ipc.h
+pub const IPC_PRIVATE: i32 = 0;
+
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+pub const IPC_NOWAIT: usize = 0o4000;
+
+pub const IPC_RMID: usize = 0;
+pub const IPC_SET: usize = 1;
+pub const IPC_STAT: usize = 2;
+
+// mlibc/abis/linux/shm.h
+pub const SHM_RDONLY: usize = 0o10000;
+pub const SHM_RND: usize = 0o20000;
+
+/// Ownership and permissions of a System V IPC object.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct IpcPerm {
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub cuid: u32,
+    pub cgid: u32,
+    pub mode: u32,
+    pub seq: i32,
+    pub __pad1: i64,
+    pub __pad2: i64,
+}
+
+/// Attributes of a System V shared memory segment, returned by `shmctl(IPC_STAT)`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ShmidDs {
+    pub shm_perm: IpcPerm,
+    /// Size of the segment, in bytes.
+    pub shm_segsz: usize,
+    /// Time of the last `shmat`.
+    pub shm_atime: i64,
+    /// Time of the last `shmdt`.
+    pub shm_dtime: i64,
+    /// Time of the creation or the last `shmctl(IPC_SET)`.
+    pub shm_ctime: i64,
+    /// PID of the creator.
+    pub shm_cpid: i32,
+    /// PID of the process that last attached or detached the segment.
+    pub shm_lpid: i32,
+    /// Number of current attachments.
+    pub shm_nattch: u64,
+    pub __pad1: u64,
+    pub __pad2: u64,
+}
+
+static_assertions::const_assert_eq!(core::mem::size_of::<ShmidDs>(), 112);
+
+// mlibc/abis/linux/msg.h
+pub const MSG_NOERROR: usize = 0o10000;
+pub const MSG_EXCEPT: usize = 0o20000;
+
+/// Maximum size of the text of a System V message.
+pub const MSGMAX: usize = 8192;
+/// Default maximum number of bytes in a System V message queue.
+pub const MSGMNB: usize = 16384;
+
+/// Attributes of a System V message queue, returned by `msgctl(IPC_STAT)`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct MsqidDs {
+    pub msg_perm: IpcPerm,
+    /// Time of the last `msgsnd`.
+    pub msg_stime: i64,
+    /// Time of the last `msgrcv`.
+    pub msg_rtime: i64,
+    /// Time of the creation or the last `msgctl(IPC_SET)`.
+    pub msg_ctime: i64,
+    /// Number of bytes in the queue.
+    pub msg_cbytes: u64,
+    /// Number of messages in the queue.
+    pub msg_qnum: u64,
+    /// Maximum number of bytes in the queue.
+    pub msg_qbytes: u64,
+    /// PID of the process that last sent a message.
+    pub msg_lspid: i32,
+    /// PID of the process that last received a message.
+    pub msg_lrpid: i32,
+    pub __unused: [u64; 2],
+}
+
+static_assertions::const_assert_eq!(core::mem::size_of::<MsqidDs>(), 120);
+
+// mlibc/abis/linux/sem.h
+pub const SEM_UNDO: usize = 0x1000;
+
+pub const GETPID: usize = 11;
+pub const GETVAL: usize = 12;
+pub const GETALL: usize = 13;
+pub const GETNCNT: usize = 14;
+pub const GETZCNT: usize = 15;
+pub const SETVAL: usize = 16;
+pub const SETALL: usize = 17;
+
+/// Maximum number of semaphores in a System V semaphore set.
+pub const SEMMSL: usize = 32000;
+/// Maximum number of operations in a single `semop` call.
+pub const SEMOPM: usize = 500;
+/// Maximum value of a System V semaphore.
+pub const SEMVMX: u16 = 32767;
+
+/// An operation on a semaphore of a System V semaphore set, performed by `semop`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SemBuf {
+    /// Index of the semaphore in the set.
+    pub sem_num: u16,
+    /// Added to the value of the semaphore if positive. A negative operation waits until the
+    /// value is at least its absolute value before subtracting it, and zero waits until the value
+    /// is zero.
+    pub sem_op: i16,
+    /// [`IPC_NOWAIT`] and [`SEM_UNDO`].
+    pub sem_flg: i16,
+}
+
+/// Attributes of a System V semaphore set, returned by `semctl(IPC_STAT)`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SemidDs {
+    pub sem_perm: IpcPerm,
+    /// Time of the last `semop`.
+    pub sem_otime: i64,
+    pub __unused1: u64,
+    /// Time of the creation or the last `semctl(IPC_SET)`.
+    pub sem_ctime: i64,
+    pub __unused2: u64,
+    /// Number of semaphores in the set.
+    pub sem_nsems: u64,
+    pub __unused3: u64,
+    pub __unused4: u64,
+}
+
+static_assertions::const_assert_eq!(core::mem::size_of::<SemidDs>(), 104);
+
+/// A System V message with room for `N` bytes of text, as sent by `msgsnd` and received by
+/// `msgrcv`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MsgBuf<const N: usize> {
+    /// Type of the message, which must be positive.
+    pub mtype: i64,
+    pub mtext: [u8; N],
+}
+
+impl<const N: usize> MsgBuf<N> {
+    pub fn new(mtype: i64) -> Self {
+        Self {
+            mtype,
+            mtext: [0; N],
+        }
+    }
+}
+
+// sys/ptrace.h
+#[derive(Debug, Copy, Clone, FromPrimitive, PartialEq)]
+pub enum PtraceRequest {
+    TraceMe = 0,
+    PeekText = 1,
+    PeekData = 2,
+    PokeText = 4,
+    PokeData = 5,
+    Cont = 7,
+    GetRegs = 12,
+    SetRegs = 13,
+    Attach = 16,
+    Detach = 17,
+    Syscall = 24,
+    GetSigInfo = 0x4202,
+    /// Sets the hardware watchpoint at index `addr` of the tracee to the [`PtraceWatchpoint`]
+    /// pointed to by `data` (Aero specific).
+    SetWatchpoint = 0x4a00,
+    /// Reads the hardware watchpoints of the tracee into the array of
+    /// [`PTRACE_WATCHPOINT_COUNT`] [`PtraceWatchpoint`]s pointed to by `data`, and returns the
+    /// mask of the watchpoints that caused the last `SIGTRAP` (Aero specific).
+    GetWatchpoints = 0x4a01,
+}
+
+/// Number of hardware watchpoints of a task.
+pub const PTRACE_WATCHPOINT_COUNT: usize = 4;
+
+/// Accesses that trigger a hardware watchpoint.
+#[derive(Debug, Copy, Clone, FromPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum WatchpointKind {
+    /// Executing the instruction at the address.
+    Execute = 0,
+    /// Writing to the watched range.
+    Write = 1,
+    /// Reading from or writing to the watched range.
+    ReadWrite = 3,
+}
+
+/// A hardware watchpoint, as set by `PTRACE_SET_WATCHPOINT` and read by
+/// `PTRACE_GET_WATCHPOINTS`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PtraceWatchpoint {
+    /// Start of the watched range, aligned to its length.
+    pub addr: u64,
+    /// Length of the watched range: 1, 2, 4 or 8 bytes, and 1 for [`WatchpointKind::Execute`].
+    /// Zero if the watchpoint is disabled.
+    pub len: u32,
+    /// The [`WatchpointKind`] of the watchpoint.
+    pub kind: u32,
+}
+
+// linux/kcmp.h
+#[derive(Debug, Copy, Clone, FromPrimitive, PartialEq)]
+pub enum KcmpType {
+    /// Compares the files referred to by two file descriptors.
+    File = 0,
+    /// Compares the address spaces.
+    Vm = 1,
+    /// Compares the filesystem roots.
+    Fs = 3,
+}
+
+// linux/reboot.h
+pub const REBOOT_MAGIC1: u32 = 0xfee1_dead;
+pub const REBOOT_MAGIC2: u32 = 0x2812_1969;
+
+// linux/reboot.h
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, FromPrimitive, PartialEq)]
+pub enum RebootCmd {
+    /// Restarts the system.
+    Restart = 0x0123_4567,
+    /// Halts the system and powers it off.
+    PowerOff = 0x4321_fedc,
+    /// Halts the system without powering it off.
+    Halt = 0xcdef_0123,
+    /// Makes Ctrl-Alt-Del restart the system right away.
+    CadOn = 0x89ab_cdef,
+    /// Makes Ctrl-Alt-Del send `SIGINT` to init, which decides what to do.
+    CadOff = 0,
+}
+
+/// Register set of a stopped tracee, as read by `PTRACE_GETREGS` and written by
+/// `PTRACE_SETREGS`. The layout mirrors the kernel's interrupt frame.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PtraceRegs {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_code_round_trip() {
+        let mut known = 0;
+
+        for code in (0..=2000).chain([isize::MAX]) {
+            if let Ok(error) = SyscallError::try_from(code) {
+                assert_eq!(isize::from(error), code);
+                assert_eq!(SyscallError::try_from(code as usize), Ok(error));
+                known += 1;
+            }
+        }
+
+        assert_eq!(SyscallError::try_from(1043isize), Ok(SyscallError::ENOENT));
+        assert_eq!(
+            SyscallError::try_from(isize::MAX),
+            Ok(SyscallError::Unknown)
+        );
+        // Every variant of `SyscallError`.
+        assert_eq!(known, 86);
+    }
+
+    #[test]
+    fn unknown_error_code() {
+        for code in [0, 4, 1000, 1033, 1074, 1084, -1, isize::MIN] {
+            assert_eq!(SyscallError::try_from(code), Err(UnknownError(code)));
+        }
+
+        assert_eq!(isize_as_syscall_result(-1033), Err(SyscallError::Unknown));
+        assert_eq!(
+            isize_as_syscall_result(isize::MIN),
+            Err(SyscallError::Unknown)
+        );
+        assert_eq!(isize_as_syscall_result(-1043), Err(SyscallError::ENOENT));
+        assert_eq!(isize_as_syscall_result(42), Ok(42));
+
+        // The kernel decodes the result of a system call to tell whether to restart it.
+        assert_eq!(
+            isize_as_syscall_result(-512),
+            Err(SyscallError::ERESTARTSYS)
+        );
+    }
+
+    #[test]
+    fn syscall_error_mapping() {
+        mock::reset();
+
+        for code in 1..=2000isize {
+            let Ok(error) = SyscallError::try_from(code) else {
+                // Values the kernel never returns are still errors.
+                mock::push_result(code.wrapping_neg() as usize);
+                assert_eq!(sys_ftruncate(3, 0), Err(SyscallError::Unknown));
+                continue;
+            };
+
+            mock::push_error(error);
+            assert_eq!(sys_ftruncate(3, 0), Err(error));
+        }
+
+        mock::push_result(0);
+        assert_eq!(sys_ftruncate(3, 4096), Ok(()));
+
+        let calls = mock::take_calls();
+        assert_eq!(calls.len(), 2001);
+        assert_eq!(
+            calls.last(),
+            Some(&mock::SyscallCall::new(prelude::SYS_FTRUNCATE, &[3, 4096]))
+        );
+    }
+
+    #[test]
+    fn ipc_recv_slice_len() {
+        mock::reset();
+
+        let mut pid = 0;
+        let mut message = [0u8; 16];
+        let message_ptr = message.as_ptr() as usize;
+
+        mock::push_result(5);
+        let received = sys_ipc_recv(&mut pid, &mut message, true).unwrap();
+        assert_eq!(received.len(), 5);
+        assert_eq!(received.as_ptr() as usize, message_ptr);
+
+        mock::push_error(SyscallError::EAGAIN);
+        assert_eq!(
+            sys_ipc_recv(&mut pid, &mut message, false),
+            Err(SyscallError::EAGAIN)
+        );
+
+        let pid_ptr = &mut pid as *mut usize as usize;
+        assert_eq!(
+            mock::take_calls(),
+            [
+                mock::SyscallCall::new(prelude::SYS_IPC_RECV, &[pid_ptr, message_ptr, 16, 1]),
+                mock::SyscallCall::new(prelude::SYS_IPC_RECV, &[pid_ptr, message_ptr, 16, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn ipc_recv_oversized_result() {
+        mock::reset();
+
+        let mut pid = 0;
+        let mut message = [0u8; 16];
+
+        // The kernel never reports more bytes than the buffer holds.
+        mock::push_result(17);
+        let _ = sys_ipc_recv(&mut pid, &mut message, true);
+    }
+
+    #[test]
+    fn unix_address_names() {
+        let (address, length) = SocketAddrUnix::new(UnixSocketName::Unnamed).unwrap();
+        assert_eq!(length, 4);
+        assert_eq!(address.name(length), UnixSocketName::Unnamed);
+
+        let (address, length) = SocketAddrUnix::new(UnixSocketName::Path(b"/tmp/sock")).unwrap();
+        assert_eq!(length, 4 + 10);
+        assert_eq!(address.name(length), UnixSocketName::Path(b"/tmp/sock"));
+
+        // The path ends at the first NUL byte, whatever the length of the address is.
+        let size = core::mem::size_of::<SocketAddrUnix>();
+        assert_eq!(address.name(size), UnixSocketName::Path(b"/tmp/sock"));
+        assert_eq!(address.name(4 + 4), UnixSocketName::Path(b"/tmp"));
+
+        // The path is not NUL terminated if it fills the whole buffer.
+        let (address, length) = SocketAddrUnix::new(UnixSocketName::Path(&[b'a'; 108])).unwrap();
+        assert_eq!(length, size);
+        assert_eq!(address.name(length), UnixSocketName::Path(&[b'a'; 108]));
+        assert!(SocketAddrUnix::new(UnixSocketName::Path(&[b'a'; 109])).is_none());
+
+        // Abstract names end with the address and may contain NUL bytes.
+        let (address, length) = SocketAddrUnix::new(UnixSocketName::Abstract(b"bus\0x")).unwrap();
+        assert_eq!(length, 4 + 1 + 5);
+        assert_eq!(address.name(length), UnixSocketName::Abstract(b"bus\0x"));
+        assert_eq!(
+            address.name(size),
+            UnixSocketName::Abstract(&address.path[1..])
+        );
+
+        let (address, length) = SocketAddrUnix::new(UnixSocketName::Abstract(b"")).unwrap();
+        assert_eq!(address.name(length), UnixSocketName::Abstract(b""));
+        assert!(SocketAddrUnix::new(UnixSocketName::Abstract(&[1; 108])).is_none());
+    }
+
+    #[test]
+    fn socket_flags_to_open_flags() {
+        assert_eq!(OpenFlags::from(SocketFlags::empty()), OpenFlags::empty());
+        assert_eq!(
+            OpenFlags::from(SocketFlags::all()),
+            OpenFlags::O_NONBLOCK | OpenFlags::O_CLOEXEC
+        );
+        assert_eq!(OpenFlags::from(SocketFlags::CLOEXEC), OpenFlags::O_CLOEXEC);
+    }
+
+    /// Calls `wrapper` with `raw` as the result of its system call. Checks that it made exactly
+    /// `call` and that it returned `expected`.
+    #[track_caller]
+    fn check_wrapper<T: PartialEq + core::fmt::Debug>(
+        wrapper: impl FnOnce() -> T,
+        call: (usize, &[usize]),
+        raw: usize,
+        expected: T,
+    ) {
+        mock::reset();
+        mock::push_result(raw);
+
+        assert_eq!(wrapper(), expected);
+        assert_eq!(mock::take_calls(), [mock::SyscallCall::new(call.0, call.1)]);
+    }
+
+    #[test]
+    fn wrapper_arguments() {
+        use crate::process::*;
+        use consts::{FallocateFlags, MemFdFlags, MountFlags, SealFlags};
+        use prelude::*;
+        use socket::{IoVec, MessageFlags, MessageHeader};
+
+        let path = "/tmp/file";
+        let (path_ptr, path_len) = (path.as_ptr() as usize, path.len());
+
+        let bytes = [1u8, 2, 3];
+        let bytes_ptr = bytes.as_ptr() as usize;
+        let mut buffer = [0u8; 64];
+        let buffer_ptr = buffer.as_ptr() as usize;
+
+        let duration = TimeSpec::from(Duration::from_secs(2));
+        let duration_ptr = &duration as *const TimeSpec as usize;
+
+        check_wrapper(
+            || sys_open(path, OpenFlags::O_RDWR | OpenFlags::O_CREAT, 0o644),
+            (
+                SYS_OPEN,
+                &[AT_FDCWD as usize, path_ptr, path_len, 0o102, 0o644],
+            ),
+            3,
+            Ok(3),
+        );
+        check_wrapper(|| sys_close(3), (SYS_CLOSE, &[3]), 0, Ok(()));
+        check_wrapper(
+            || {
+                sys_mmap(
+                    0,
+                    8192,
+                    MMapProt::PROT_READ | MMapProt::PROT_WRITE,
+                    MMapFlags::MAP_SHARED,
+                    3,
+                    4096,
+                )
+            },
+            (SYS_MMAP, &[0, 8192, 3, 2, 3, 4096]),
+            0x1000_0000,
+            Ok(0x1000_0000 as *mut u8),
+        );
+        check_wrapper(
+            || sys_munmap(0x1000_0000 as *mut u8, 8192),
+            (SYS_MUNMAP, &[0x1000_0000, 8192]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_sysconf(_SC_PAGESIZE),
+            (SYS_SYSCONF, &[30]),
+            4096,
+            Ok(4096),
+        );
+        check_wrapper(
+            || sys_dup3(3, 4, OpenFlags::O_CLOEXEC),
+            (SYS_DUP3, &[3, 4, 0o2000000]),
+            4,
+            Ok(4),
+        );
+        check_wrapper(
+            || sys_sleep(&duration),
+            (SYS_SLEEP, &[duration_ptr]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_ipc_send(7, &bytes),
+            (SYS_IPC_SEND, &[7, bytes_ptr, 3]),
+            42,
+            Ok(42),
+        );
+
+        // Bits that are not part of `Mode` are dropped from the previous mask.
+        check_wrapper(
+            || sys_umask(Mode::S_IRWXG | Mode::S_IRWXO),
+            (SYS_UMASK, &[0o77]),
+            0o22 | (1 << 20),
+            Ok(Mode::S_IWGRP | Mode::S_IWOTH),
+        );
+
+        let mut statx = Statx::default();
+        let statx_ptr = &mut statx as *mut Statx as usize;
+        check_wrapper(
+            || {
+                sys_statx(
+                    AT_FDCWD as usize,
+                    path,
+                    AtFlags::SYMLINK_NOFOLLOW,
+                    StatxMask::BASIC_STATS | StatxMask::BTIME,
+                    &mut statx,
+                )
+            },
+            (
+                SYS_STATX,
+                &[
+                    AT_FDCWD as usize,
+                    path_ptr,
+                    path_len,
+                    0x100,
+                    0xfff,
+                    statx_ptr,
+                ],
+            ),
+            0,
+            Ok(()),
+        );
+
+        let mut statfs = StatFs::default();
+        let statfs_ptr = &mut statfs as *mut StatFs as usize;
+        check_wrapper(
+            || sys_statfs(AT_FDCWD as usize, path, &mut statfs),
+            (
+                SYS_STATFS,
+                &[AT_FDCWD as usize, path_ptr, path_len, statfs_ptr],
+            ),
+            0,
+            Ok(()),
+        );
+
+        check_wrapper(
+            || {
+                sys_fallocate(
+                    3,
+                    FallocateFlags::PUNCH_HOLE | FallocateFlags::KEEP_SIZE,
+                    4096,
+                    8192,
+                )
+            },
+            (SYS_FALLOCATE, &[3, 3, 4096, 8192]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_mount(path, MountFlags::REMOUNT | MountFlags::RDONLY),
+            (SYS_MOUNT, &[path_ptr, path_len, 33]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_reboot(RebootCmd::CadOff),
+            (SYS_REBOOT, &[0xfee1_dead, 0x2812_1969, 0, 0]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_reboot(RebootCmd::Restart),
+            (SYS_REBOOT, &[0xfee1_dead, 0x2812_1969, 0x0123_4567, 0]),
+            0,
+            Ok(()),
+        );
+
+        check_wrapper(
+            || sys_memfd_create(path, MemFdFlags::CLOEXEC | MemFdFlags::ALLOW_SEALING),
+            (SYS_MEMFD_CREATE, &[path_ptr, path_len, 3]),
+            3,
+            Ok(3),
+        );
+        check_wrapper(
+            || sys_add_seals(3, SealFlags::SHRINK | SealFlags::GROW),
+            (SYS_FCNTL, &[3, 1033, 0b110]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_get_seals(3),
+            (SYS_FCNTL, &[3, 1034]),
+            0b111,
+            Ok(SealFlags::SEAL | SealFlags::SHRINK | SealFlags::GROW),
+        );
+
+        let mut tid = 0u32;
+        let tid_ptr = &mut tid as *mut u32 as usize;
+        check_wrapper(
+            || sys_set_tid_address(&mut tid),
+            (SYS_SET_TID_ADDRESS, &[tid_ptr]),
+            7,
+            7,
+        );
+
+        let mut tms = Tms::default();
+        let tms_ptr = &mut tms as *mut Tms as usize;
+        check_wrapper(
+            || sys_times(&mut tms),
+            (SYS_TIMES, &[tms_ptr]),
+            1234,
+            Ok(1234),
+        );
+
+        // The previous alarm had 3 seconds left.
+        check_wrapper(|| sys_alarm(0), (SYS_ALARM, &[0]), 3, 3);
+
+        let mut usage = RUsage::default();
+        let usage_ptr = &mut usage as *mut RUsage as usize;
+        check_wrapper(
+            || sys_getrusage(RUSAGE_CHILDREN, &mut usage),
+            (SYS_GETRUSAGE, &[usize::MAX, usage_ptr]),
+            0,
+            Ok(()),
+        );
+
+        let mut limit = RLimit::default();
+        let limit_ptr = &mut limit as *mut RLimit as usize;
+        check_wrapper(
+            || sys_getrlimit(RLIMIT_NOFILE, &mut limit),
+            (SYS_GETRLIMIT, &[7, limit_ptr]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_setrlimit(RLIMIT_NOFILE, &limit),
+            (SYS_SETRLIMIT, &[7, limit_ptr]),
+            -1063isize as usize,
+            Err(SyscallError::EPERM),
+        );
+
+        let groups = [10, 4];
+        check_wrapper(
+            || sys_setgroups(&groups),
+            (SYS_SETGROUPS, &[groups.as_ptr() as usize, 2]),
+            0,
+            Ok(()),
+        );
+
+        let mut list = [0u32; 4];
+        let list_ptr = list.as_ptr() as usize;
+        check_wrapper(
+            || sys_getgroups(&mut list),
+            (SYS_GETGROUPS, &[list_ptr, 4]),
+            2,
+            Ok(2),
+        );
+
+        // Only the number of groups is returned for an empty list.
+        let mut none: [u32; 0] = [];
+        let none_ptr = none.as_ptr() as usize;
+        check_wrapper(
+            || sys_getgroups(&mut none),
+            (SYS_GETGROUPS, &[none_ptr, 0]),
+            2,
+            Ok(2),
+        );
+
+        let supported = MembarrierCmd::GLOBAL | MembarrierCmd::PRIVATE_EXPEDITED;
+        check_wrapper(
+            || sys_membarrier(MembarrierCmd::empty()),
+            (SYS_MEMBARRIER, &[0, 0, 0]),
+            supported.bits(),
+            Ok(supported),
+        );
+
+        let attr = MqAttr {
+            mq_maxmsg: 4,
+            mq_msgsize: 64,
+            ..Default::default()
+        };
+        let attr_ptr = &attr as *const MqAttr as usize;
+        check_wrapper(
+            || {
+                sys_mq_open(
+                    path,
+                    OpenFlags::O_RDWR | OpenFlags::O_CREAT,
+                    Mode::S_IRUSR | Mode::S_IWUSR,
+                    Some(&attr),
+                )
+            },
+            (SYS_MQ_OPEN, &[path_ptr, path_len, 0o102, 0o600, attr_ptr]),
+            3,
+            Ok(3),
+        );
+
+        let mut priority = 0;
+        let priority_ptr = &mut priority as *mut u32 as usize;
+        check_wrapper(
+            || sys_mq_receive(3, &mut buffer, Some(&mut priority)),
+            (SYS_MQ_RECEIVE, &[3, buffer_ptr, 64, priority_ptr]),
+            5,
+            Ok(5),
+        );
+        check_wrapper(
+            || sys_mq_receive(3, &mut buffer, None),
+            (SYS_MQ_RECEIVE, &[3, buffer_ptr, 64, 0]),
+            0,
+            Ok(0),
+        );
+
+        check_wrapper(
+            || sys_sem_open(path, OpenFlags::O_CREAT, Mode::S_IRUSR | Mode::S_IWUSR, 2),
+            (SYS_SEM_OPEN, &[path_ptr, path_len, 0o100, 0o600, 2]),
+            3,
+            Ok(3),
+        );
+        check_wrapper(
+            || sys_sem_timedwait(3, &duration),
+            (SYS_SEM_TIMEDWAIT, &[3, duration_ptr]),
+            0,
+            Ok(()),
+        );
+
+        check_wrapper(
+            || sys_shmget(IPC_PRIVATE, 8192, IPC_CREAT | 0o600),
+            (SYS_SHMGET, &[0, 8192, 0o1600]),
+            1,
+            Ok(1),
+        );
+        check_wrapper(
+            || sys_shmat(1, 0, SHM_RDONLY),
+            (SYS_SHMAT, &[1, 0, 0o10000]),
+            0x7000_0000_0000,
+            Ok(0x7000_0000_0000),
+        );
+        check_wrapper(|| sys_shmdt(0x1000), (SYS_SHMDT, &[0x1000]), 0, Ok(()));
+
+        let mut shmid = ShmidDs::default();
+        let shmid_ptr = &mut shmid as *mut ShmidDs as usize;
+        check_wrapper(
+            || sys_shmctl(1, IPC_STAT, Some(&mut shmid)),
+            (SYS_SHMCTL, &[1, 2, shmid_ptr]),
+            0,
+            Ok(0),
+        );
+        check_wrapper(
+            || sys_shmctl(1, IPC_RMID, None),
+            (SYS_SHMCTL, &[1, 0, 0]),
+            0,
+            Ok(0),
+        );
+
+        check_wrapper(
+            || sys_msgget(0x1234, IPC_CREAT | 0o600),
+            (SYS_MSGGET, &[0x1234, 0o1600]),
+            2,
+            Ok(2),
+        );
+
+        let mut message = MsgBuf::<16>::new(3);
+        let message_ptr = &message as *const MsgBuf<16> as usize;
+        check_wrapper(
+            || sys_msgsnd(2, &message, 5, IPC_NOWAIT),
+            (SYS_MSGSND, &[2, message_ptr, 5, 0o4000]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_msgrcv(2, &mut message, -3, MSG_NOERROR),
+            (SYS_MSGRCV, &[2, message_ptr, 16, -3i64 as usize, 0o10000]),
+            5,
+            Ok(5),
+        );
+
+        check_wrapper(
+            || sys_semget(0x5678, 2, IPC_CREAT | 0o600),
+            (SYS_SEMGET, &[0x5678, 2, 0o1600]),
+            3,
+            Ok(3),
+        );
+
+        let ops = [
+            SemBuf {
+                sem_num: 0,
+                sem_op: -1,
+                sem_flg: SEM_UNDO as i16,
+            },
+            SemBuf {
+                sem_num: 1,
+                sem_op: 0,
+                sem_flg: IPC_NOWAIT as i16,
+            },
+        ];
+        check_wrapper(
+            || sys_semop(3, &ops),
+            (SYS_SEMOP, &[3, ops.as_ptr() as usize, 2]),
+            0,
+            Ok(()),
+        );
+        check_wrapper(
+            || sys_semctl(3, 0, SETVAL, 5),
+            (SYS_SEMCTL, &[3, 0, 16, 5]),
+            0,
+            Ok(0),
+        );
+        check_wrapper(
+            || sys_semctl(3, 0, GETVAL, 0),
+            (SYS_SEMCTL, &[3, 0, 12]),
+            5,
+            Ok(5),
+        );
+
+        let data = [0u8; 8];
+        let mut iovecs = [IoVec::from_slice(&data)];
+        let mut header = MessageHeader::new::<SocketAddrUnix>(None, &mut iovecs);
+        let header_ptr = &mut header as *mut MessageHeader as usize;
+        check_wrapper(
+            || sys_sendmsg(3, &header, MessageFlags::NOSIGNAL),
+            (SYS_SOCK_SEND, &[3, header_ptr, 0x10]),
+            8,
+            Ok(8),
+        );
+        check_wrapper(
+            || sys_recvmsg(3, &mut header, MessageFlags::PEEK | MessageFlags::DONTWAIT),
+            (SYS_SOCK_RECV, &[3, header_ptr, 0x1020]),
+            8,
+            Ok(8),
+        );
+    }
+
+    #[test]
+    fn statx_from_stat() {
+        let stat = Stat {
+            st_dev: (8 << 8) | 1,
+            st_ino: 12,
+            st_nlink: 1,
+            st_mode: Mode::S_IFREG | Mode::S_IRUSR,
+            st_size: 4097,
+            st_blksize: 4096,
+            st_atim: Duration::new(1, 5).into(),
+            ..Default::default()
+        };
+
+        let statx = Statx::from(stat);
+
+        // Only the fields that are in `Stat` are filled in.
+        assert_eq!(statx.stx_mask, StatxMask::BASIC_STATS);
+        assert!(!statx.stx_mask.contains(StatxMask::BTIME));
+
+        assert_eq!((statx.stx_dev_major, statx.stx_dev_minor), (8, 1));
+        assert_eq!(statx.stx_ino, 12);
+        assert_eq!(statx.stx_mode, 0o100400);
+        assert_eq!(statx.stx_size, 4097);
+        assert_eq!(statx.stx_blksize, 4096);
+        assert_eq!((statx.stx_atime.tv_sec, statx.stx_atime.tv_nsec), (1, 5));
+    }
+
+    #[test]
+    fn message_header_scatter() {
+        use socket::{IoVec, MessageHeader};
+
+        let (mut a, mut b, mut c) = ([0u8; 2], [0u8; 3], [0u8; 4]);
+        let mut iovecs = [
+            IoVec::from_slice_mut(&mut a),
+            IoVec::from_slice_mut(&mut b),
+            IoVec::from_slice_mut(&mut c),
+        ];
+
+        let mut header = MessageHeader::new::<SocketAddrUnix>(None, &mut iovecs);
+        assert_eq!(header.iovecs_len(), 9);
+        assert_eq!(header.scatter(b"abcdef"), 6);
+        assert_eq!(header.scatter(b"0123456789"), 9);
+
+        assert_eq!((&a, &b, &c), (b"01", b"234", b"5678"));
+    }
+
+    #[test]
+    fn control_message_alignment() {
+        use socket::{
+            cmsg_align, cmsg_len, cmsg_space, ControlMessageType, ControlMessages,
+            SocketOptionLevel,
+        };
+
+        // The header is 12 bytes, padded to the alignment of `size_t`.
+        assert_eq!(cmsg_align(12), 16);
+        assert_eq!(cmsg_len(4), 20);
+        assert_eq!(cmsg_space(4), 24);
+        assert_eq!(cmsg_space(8), 24);
+
+        // Two `SCM_RIGHTS` messages with one and two file descriptors.
+        let mut control = [0u8; 56];
+        let mut put = |offset: usize, fds: &[i32]| {
+            let len = cmsg_len(fds.len() * 4) as u32;
+
+            control[offset..offset + 4].copy_from_slice(&len.to_ne_bytes());
+            control[offset + 4..offset + 8].copy_from_slice(&1i32.to_ne_bytes());
+            control[offset + 8..offset + 12].copy_from_slice(&1i32.to_ne_bytes());
+
+            for (i, fd) in fds.iter().enumerate() {
+                let start = offset + cmsg_len(0) + i * 4;
+                control[start..start + 4].copy_from_slice(&fd.to_ne_bytes());
+            }
+        };
+
+        put(0, &[7]);
+        put(cmsg_space(4), &[8, 9]);
+
+        let messages = ControlMessages::new(&control).collect::<std::vec::Vec<_>>();
+        assert_eq!(messages.len(), 2);
+
+        for (message, _) in &messages {
+            assert_eq!(message.level(), Some(SocketOptionLevel::Socket));
+            assert_eq!(message.kind(), Some(ControlMessageType::Rights));
+        }
+
+        assert_eq!(messages[0].1, 7i32.to_ne_bytes());
+        assert_eq!(messages[1].1.len(), 8);
+
+        // A message that claims to be longer than the buffer ends the iteration.
+        control[24..28].copy_from_slice(&64u32.to_ne_bytes());
+        assert_eq!(ControlMessages::new(&control).count(), 1);
+    }
+
+    #[test]
+    fn nice_priority() {
+        mock::reset();
+
+        // `getpriority` returns `20 - nice`.
+        mock::push_result(20);
+        mock::push_result(0);
+        mock::push_result(15);
+        assert_eq!(process::sys_nice(5), Ok(5));
+
+        let calls = mock::take_calls();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(
+            calls[1],
+            mock::SyscallCall::new(prelude::SYS_SETPRIORITY, &[prelude::PRIO_PROCESS, 0, 5])
+        );
+    }
+
+    #[test]
+    fn timespec_from_duration() {
+        let time = TimeSpec::from(Duration::new(3, 500));
+        assert_eq!((time.tv_sec, time.tv_nsec), (3, 500));
+
+        let time = TimeSpec::from(Duration::from_millis(1500));
+        assert_eq!((time.tv_sec, time.tv_nsec), (1, 500_000_000));
+    }
+
+    #[test]
+    fn siginfo_layout() {
+        use crate::signal::{SigInfo, CLD_EXITED, SIGCHLD, SIGSEGV};
+
+        let word = |info: &SigInfo, offset: usize| {
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    (info as *const SigInfo).cast::<u8>(),
+                    core::mem::size_of::<SigInfo>(),
+                )
+            };
+
+            u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+
+        // `si_pid` and `si_status` are at offset 16 and 24 of `siginfo_t`.
+        let info = SigInfo::child(CLD_EXITED, 42, 7);
+        assert_eq!((info.si_signo, info.si_code), (SIGCHLD as i32, CLD_EXITED));
+        assert_eq!((info.si_pid(), info.si_status()), (42, 7));
+        assert_eq!((word(&info, 16), word(&info, 24)), (42, 7));
+
+        // `si_addr` shares offset 16 with `si_pid`.
+        let info = SigInfo::new(SIGSEGV, 0, 0x1234_5678_9abc);
+        assert_eq!(info.si_addr(), 0x1234_5678_9abc);
+        assert_eq!((word(&info, 16), word(&info, 20)), (0x5678_9abc, 0x1234));
+    }
+
+    #[test]
+    fn wait_status() {
+        use crate::process::WaitStatus;
+        use crate::signal::{SIGKILL, SIGSEGV, SIGSTOP};
+
+        // Statuses of children that called `exit(3)`, were killed by `SIGKILL`, dumped core on
+        // `SIGSEGV`, were stopped by `SIGSTOP` and were continued.
+        let statuses = [
+            (0x0300, WaitStatus::Exited(3), 3),
+            (
+                0x0009,
+                WaitStatus::Signaled {
+                    signal: SIGKILL,
+                    core_dumped: false,
+                },
+                137,
+            ),
+            (
+                0x008b,
+                WaitStatus::Signaled {
+                    signal: SIGSEGV,
+                    core_dumped: true,
+                },
+                139,
+            ),
+            (0x137f, WaitStatus::Stopped(SIGSTOP), 147),
+            (0xffff, WaitStatus::Continued, 0),
+        ];
+
+        for (raw, status, code) in statuses {
+            assert_eq!(WaitStatus::from_raw(raw), status);
+            assert_eq!(status.into_raw(), raw);
+            assert_eq!(status.shell_code(), code);
+        }
+
+        // An exit code of 139 is not a `SIGSEGV`.
+        assert_eq!(WaitStatus::from_raw(139 << 8), WaitStatus::Exited(139));
+    }
+
+    fn call_numbers(calls: &[mock::SyscallCall]) -> std::vec::Vec<usize> {
+        calls.iter().map(|call| call.number).collect()
+    }
+
+    /// Queues the result of `sys_pipe`, which writes the descriptors to the array passed to it.
+    fn push_pipe(read: i32, write: i32) {
+        mock::push_handler(move |args| {
+            // SAFETY: The wrapper passes a pointer to an array of two `i32`s.
+            unsafe { (args[0] as *mut [i32; 2]).write([read, write]) };
+            0
+        });
+    }
+
+    #[test]
+    fn spawn_parent() {
+        use crate::process::{spawn, SpawnOptions};
+        use prelude::*;
+
+        mock::reset();
+
+        let options = SpawnOptions {
+            new_process_group: true,
+            ..Default::default()
+        };
+
+        // The child exec'd, so the parent reads EOF from the pipe.
+        push_pipe(5, 6);
+        mock::push_result(42);
+        mock::push_result(0);
+        mock::push_result(0);
+        mock::push_result(0);
+        mock::push_result(0);
+        assert_eq!(spawn("/bin/true", &[], &[], &options), Ok(42));
+
+        let calls = mock::take_calls();
+        assert_eq!(
+            call_numbers(&calls),
+            [
+                SYS_PIPE,
+                SYS_FORK,
+                SYS_SETPGID,
+                SYS_CLOSE,
+                SYS_READ,
+                SYS_CLOSE
+            ]
+        );
+
+        // The pipe is closed in the child once `exec` succeeds.
+        assert_eq!(calls[0].args[1], OpenFlags::O_CLOEXEC.bits());
+        assert_eq!(calls[2].args[..2], [42, 42]);
+        assert_eq!(calls[3].args[0], 6);
+        assert_eq!((calls[4].args[0], calls[4].args[2]), (5, 8));
+        assert_eq!(calls[5].args[0], 5);
+    }
+
+    #[test]
+    fn spawn_parent_errors() {
+        use crate::process::{spawn, SpawnOptions};
+        use prelude::*;
+
+        let options = SpawnOptions::default();
+        mock::reset();
+
+        mock::push_error(SyscallError::EMFILE);
+        assert_eq!(
+            spawn("/bin/true", &[], &[], &options),
+            Err(SyscallError::EMFILE)
+        );
+        assert_eq!(call_numbers(&mock::take_calls()), [SYS_PIPE]);
+
+        // Both ends of the pipe are closed if the fork fails.
+        push_pipe(5, 6);
+        mock::push_error(SyscallError::EAGAIN);
+        mock::push_result(0);
+        mock::push_result(0);
+        assert_eq!(
+            spawn("/bin/true", &[], &[], &options),
+            Err(SyscallError::EAGAIN)
+        );
+        assert_eq!(
+            mock::take_calls()[1..],
+            [
+                mock::SyscallCall::new(SYS_FORK, &[]),
+                mock::SyscallCall::new(SYS_CLOSE, &[5]),
+                mock::SyscallCall::new(SYS_CLOSE, &[6]),
+            ]
+        );
+
+        // The child reports the error of `exec`. Interrupted and short reads are retried and
+        // the failed child is reaped.
+        let errno = (isize::from(SyscallError::ENOENT) as usize).to_ne_bytes();
+        let (head, tail) = errno.split_at(3);
+        let (head, tail) = (head.to_vec(), tail.to_vec());
+
+        let push_read = |bytes: std::vec::Vec<u8>| {
+            mock::push_handler(move |args| {
+                // SAFETY: The wrapper passes the buffer and its length.
+                let buffer =
+                    unsafe { core::slice::from_raw_parts_mut(args[1] as *mut u8, args[2]) };
+                buffer[..bytes.len()].copy_from_slice(&bytes);
+                bytes.len()
+            });
+        };
+
+        push_pipe(5, 6);
+        mock::push_result(42);
+        mock::push_result(0);
+        push_read(head);
+        mock::push_error(SyscallError::EINTR);
+        push_read(tail);
+        mock::push_result(0);
+        mock::push_error(SyscallError::EINTR);
+        mock::push_result(42);
+        assert_eq!(
+            spawn("/bin/missing", &[], &[], &options),
+            Err(SyscallError::ENOENT)
+        );
+
+        let calls = mock::take_calls();
+        assert_eq!(
+            call_numbers(&calls),
+            [
+                SYS_PIPE,
+                SYS_FORK,
+                SYS_CLOSE,
+                SYS_READ,
+                SYS_READ,
+                SYS_READ,
+                SYS_CLOSE,
+                SYS_WAITPID,
+                SYS_WAITPID
+            ]
+        );
+
+        // The second read continues where the first one stopped.
+        assert_eq!(calls[5].args[1], calls[3].args[1] + 3);
+        assert_eq!(calls[5].args[2], 5);
+        assert_eq!(calls[8].args[0], 42);
+    }
+
+    /// Runs `spawn` as the child of the fork, until it exits. Returns the calls it made and the
+    /// error it reported to the parent.
+    fn spawn_child(
+        path: &str,
+        options: &process::SpawnOptions,
+    ) -> (std::vec::Vec<mock::SyscallCall>, Option<SyscallError>) {
+        use std::rc::Rc;
+
+        let reported = Rc::new(core::cell::Cell::new(None));
+        let report = reported.clone();
+
+        mock::push_handler(move |args| {
+            let mut errno = [0u8; 8];
+
+            // SAFETY: The wrapper passes the buffer and its length.
+            errno.copy_from_slice(unsafe {
+                core::slice::from_raw_parts(args[1] as *const u8, args[2])
+            });
+
+            report.set(SyscallError::try_from(usize::from_ne_bytes(errno)).ok());
+            args[2]
+        });
+
+        // `exit` does not return.
+        mock::push_result(0);
+
+        let result = std::panic::catch_unwind(|| {
+            let _ = process::spawn(path, &["ls"], &["A=b"], options);
+        });
+
+        assert!(result.is_err());
+        (mock::take_calls(), reported.get())
+    }
+
+    #[test]
+    fn spawn_child_setup() {
+        use crate::process::SpawnOptions;
+        use prelude::*;
+
+        mock::reset();
+
+        let options = SpawnOptions {
+            stdin: Some(0),
+            stdout: Some(9),
+            cwd: Some("/tmp"),
+            new_process_group: true,
+            ..Default::default()
+        };
+
+        push_pipe(5, 6);
+        mock::push_result(0);
+        mock::push_result(0);
+        mock::push_result(0);
+        mock::push_result(0);
+        mock::push_result(1);
+        mock::push_result(0);
+        mock::push_error(SyscallError::ENOENT);
+
+        let (calls, reported) = spawn_child("/bin/ls", &options);
+        assert_eq!(reported, Some(SyscallError::ENOENT));
+
+        assert_eq!(
+            call_numbers(&calls),
+            [
+                SYS_PIPE,
+                SYS_FORK,
+                SYS_CLOSE,
+                SYS_SETPGID,
+                SYS_FCNTL,
+                SYS_DUP2,
+                SYS_CHDIR,
+                SYS_EXEC,
+                SYS_WRITE,
+                SYS_EXIT
+            ]
+        );
+
+        assert_eq!(calls[2].args[0], 5);
+        assert_eq!(calls[3].args[..2], [0, 0]);
+        // Standard input is already in place and only loses its close-on-exec flag.
+        assert_eq!(calls[4].args[..3], [0, F_SETFD, 0]);
+        assert_eq!(calls[5].args[..2], [9, 1]);
+        assert_eq!(calls[6].args[2], 4);
+        assert_eq!(calls[7].args[1], 7);
+        assert_eq!((calls[7].args[3], calls[7].args[5]), (1, 1));
+        assert_eq!(calls[8].args[0], 6);
+        assert_eq!(calls[9].args[0], 127);
+    }
+
+    #[test]
+    fn spawn_child_setup_error() {
+        use crate::process::SpawnOptions;
+        use prelude::*;
+
+        mock::reset();
+
+        let options = SpawnOptions {
+            stderr: Some(9),
+            ..Default::default()
+        };
+
+        // A failed setup step is reported without calling `exec`.
+        push_pipe(5, 6);
+        mock::push_result(0);
+        mock::push_result(0);
+        mock::push_error(SyscallError::EBADF);
+
+        let (calls, reported) = spawn_child("/bin/ls", &options);
+        assert_eq!(reported, Some(SyscallError::EBADF));
+
+        assert_eq!(
+            call_numbers(&calls),
+            [SYS_PIPE, SYS_FORK, SYS_CLOSE, SYS_DUP2, SYS_WRITE, SYS_EXIT]
+        );
+        assert_eq!(calls[3].args[..2], [9, 2]);
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Scripted system call backend, used instead of the system call instruction in tests and with
+//! the `mock` feature.
+//!
+//! Every system call is recorded, and returns the next result queued with [`push_result`]:
+//!
+//! ```ignore
+//! mock::push_error(SyscallError::EBADF);
+//! assert_eq!(sys_dup2(7, 1), Err(SyscallError::EBADF));
+//! assert_eq!(mock::take_calls(), [SyscallCall::new(SYS_DUP2, &[7, 1])]);
+//! ```
+//!
+//! A result queued with [`push_handler`] is computed from the arguments of the call instead,
+//! which emulates system calls that write to user memory.
+//!
+//! The state is per thread, so tests running in parallel do not see each other's calls.
+
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use crate::SyscallError;
+
+/// A system call made through the mock backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyscallCall {
+    pub number: usize,
+    /// The arguments of the call. Arguments that were not passed are zero.
+    pub args: [usize; 6],
+}
+
+impl SyscallCall {
+    pub fn new(number: usize, args: &[usize]) -> Self {
+        let mut padded = [0; 6];
+        padded[..args.len()].copy_from_slice(args);
+
+        Self {
+            number,
+            args: padded,
+        }
+    }
+}
+
+type Handler = Box<dyn FnOnce(&[usize; 6]) -> usize>;
+
+#[derive(Default)]
+struct MockState {
+    calls: Vec<SyscallCall>,
+    results: VecDeque<Handler>,
+}
+
+std::thread_local! {
+    static STATE: RefCell<MockState> = RefCell::new(MockState::default());
+}
+
+/// Queues the raw value returned by the next system call.
+pub fn push_result(value: usize) {
+    push_handler(move |_| value);
+}
+
+/// Queues `handler` to run on the next system call. It is passed the arguments of the call and
+/// returns its raw result.
+pub fn push_handler(handler: impl FnOnce(&[usize; 6]) -> usize + 'static) {
+    STATE.with(|state| state.borrow_mut().results.push_back(Box::new(handler)));
+}
+
+/// Queues `error` as the result of the next system call, encoded the way the kernel returns it.
+pub fn push_error(error: SyscallError) {
+    push_result(isize::from(error).wrapping_neg() as usize)
+}
+
+/// Returns the system calls made since the last call, oldest first.
+pub fn take_calls() -> Vec<SyscallCall> {
+    STATE.with(|state| core::mem::take(&mut state.borrow_mut().calls))
+}
+
+/// Forgets the recorded calls and the queued results.
+pub fn reset() {
+    STATE.with(|state| *state.borrow_mut() = MockState::default());
+}
+
+pub(crate) fn dispatch(number: usize, args: &[usize]) -> usize {
+    let call = SyscallCall::new(number, args);
+
+    let handler = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.calls.push(call);
+
+        state
+            .results
+            .pop_front()
+            .unwrap_or_else(|| panic!("mock: no result queued for system call {number}"))
+    });
+
+    handler(&call.args)
+}
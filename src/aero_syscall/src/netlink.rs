@@ -60,7 +60,7 @@ pub enum RtAttrType {
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u16)]
 pub enum MessageType {
-    Noop,
+    Noop = 1,
     Error,
     Done,    // end of a dump
     Overrun, // data lost
@@ -228,3 +228,40 @@ pub struct rtattr {
     pub rta_len: u16,
     pub rta_type: RtAttrType,
 }
+
+/// Header of the link messages, such as `RTM_NEWLINK`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ifinfomsg {
+    pub ifi_family: u8,
+    pub __ifi_pad: u8,
+    /// Hardware type of the link (`ARPHRD_*`).
+    pub ifi_type: u16,
+    /// Index of the link.
+    pub ifi_index: i32,
+    pub ifi_flags: InterfaceFlags,
+    pub ifi_change: u32,
+}
+
+const_assert_eq!(core::mem::size_of::<ifinfomsg>(), 16);
+
+// Attributes of the link messages, from `linux/if_link.h`.
+pub const IFLA_ADDRESS: u16 = 1;
+pub const IFLA_IFNAME: u16 = 3;
+pub const IFLA_MTU: u16 = 4;
+
+// Hardware types of the links, from `linux/if_arp.h`.
+pub const ARPHRD_ETHER: u16 = 1;
+pub const ARPHRD_LOOPBACK: u16 = 772;
+
+bitflags::bitflags! {
+    // linux/if.h
+    #[repr(transparent)]
+    pub struct InterfaceFlags: u32 {
+        const UP = 0x1;
+        const BROADCAST = 0x2;
+        const LOOPBACK = 0x8;
+        const RUNNING = 0x40;
+        const MULTICAST = 0x1000;
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Structures and constants of the `perf_event_open` interface.
+//!
+//! The layout of the structures follows `linux/perf_event.h`.
+
+/// Size of the first published version of [`PerfEventAttr`].
+pub const PERF_ATTR_SIZE_VER0: u32 = 64;
+
+/// Enables the counter.
+pub const PERF_EVENT_IOC_ENABLE: usize = 0x2400;
+/// Disables the counter.
+pub const PERF_EVENT_IOC_DISABLE: usize = 0x2401;
+/// Resets the count of the counter to zero.
+pub const PERF_EVENT_IOC_RESET: usize = 0x2403;
+
+// linux/perf_event.h
+#[derive(Debug, Copy, Clone, PartialEq, FromPrimitive)]
+#[repr(u32)]
+pub enum PerfType {
+    Hardware = 0,
+    Software = 1,
+    Tracepoint = 2,
+    HwCache = 3,
+    Raw = 4,
+    Breakpoint = 5,
+}
+
+// linux/perf_event.h
+#[derive(Debug, Copy, Clone, PartialEq, FromPrimitive)]
+#[repr(u64)]
+pub enum PerfHwId {
+    CpuCycles = 0,
+    Instructions = 1,
+    CacheReferences = 2,
+    CacheMisses = 3,
+    BranchInstructions = 4,
+    BranchMisses = 5,
+    BusCycles = 6,
+    StalledCyclesFrontend = 7,
+    StalledCyclesBackend = 8,
+    RefCpuCycles = 9,
+}
+
+bitflags::bitflags! {
+    /// The bitfield that follows [`PerfEventAttr::read_format`].
+    #[derive(Default)]
+    #[repr(transparent)]
+    pub struct PerfAttrFlags: u64 {
+        /// The counter starts disabled and is enabled with [`PERF_EVENT_IOC_ENABLE`].
+        const DISABLED = 1 << 0;
+        /// Do not count events that occur in user mode.
+        const EXCLUDE_USER = 1 << 4;
+        /// Do not count events that occur in kernel mode.
+        const EXCLUDE_KERNEL = 1 << 5;
+    }
+}
+
+bitflags::bitflags! {
+    pub struct PerfEventFlags: usize {
+        const FD_NO_GROUP = 1 << 0;
+        const FD_OUTPUT = 1 << 1;
+        const PID_CGROUP = 1 << 2;
+        const FD_CLOEXEC = 1 << 3;
+    }
+}
+
+/// Describes the event to count, passed to `perf_event_open`. Only the fields of
+/// [`PERF_ATTR_SIZE_VER0`] are defined; the kernel ignores the remaining ones.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PerfEventAttr {
+    /// The [`PerfType`] of the event.
+    pub typ: u32,
+    /// Size of the structure, for forward and backward compatibility.
+    pub size: u32,
+    /// The event, interpreted according to `typ` ([`PerfHwId`] for hardware events).
+    pub config: u64,
+    pub sample_period: u64,
+    pub sample_type: u64,
+    pub read_format: u64,
+    pub flags: PerfAttrFlags,
+    pub wakeup_events: u32,
+    pub bp_type: u32,
+    pub config1: u64,
+}
+
+const _: () = assert!(core::mem::size_of::<PerfEventAttr>() == PERF_ATTR_SIZE_VER0 as usize);
@@ -0,0 +1,487 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Process management.
+//!
+//! [`spawn`] provides `posix_spawn`-style process creation on top of `fork` and `exec`. The
+//! child reports a failed `exec` (or a failed setup step before it) back to the parent
+//! through a close-on-exec pipe: a successful `exec` closes the write end and the parent reads
+//! EOF, otherwise the child writes the error number to the pipe and exits with status 127.
+
+use core::convert::Infallible;
+
+use crate::prelude::*;
+use crate::time::TimeVal;
+use crate::{
+    isize_as_syscall_result, sys_close, sys_dup2, OpenFlags, Result, SyscallError, AT_FDCWD,
+};
+
+/// Exit status of a child that failed before or in `exec`.
+const SPAWN_FAILED: usize = 127;
+
+/// Options for [`spawn`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpawnOptions<'a> {
+    /// File descriptor to use as the standard input of the child. Inherited if `None`.
+    pub stdin: Option<usize>,
+    /// File descriptor to use as the standard output of the child. Inherited if `None`.
+    pub stdout: Option<usize>,
+    /// File descriptor to use as the standard error of the child. Inherited if `None`.
+    pub stderr: Option<usize>,
+    /// Working directory of the child. Inherited if `None`.
+    pub cwd: Option<&'a str>,
+    /// Whether the child is moved into a new process group.
+    pub new_process_group: bool,
+}
+
+/// Spawns `path` with the provided arguments and environment (`KEY=VALUE` pairs) and returns
+/// the PID of the child.
+///
+/// ## Errors
+///
+/// Returns the error of the failed `fork` or, if the child could not be set up or `exec`
+/// failed, the error it reported. The failed child is reaped before returning.
+pub fn spawn(path: &str, argv: &[&str], envv: &[&str], options: &SpawnOptions) -> Result<usize> {
+    let (read, write) = sys_pipe(OpenFlags::O_CLOEXEC)?;
+
+    let pid = match sys_fork() {
+        Ok(pid) => pid,
+        Err(err) => {
+            let _ = sys_close(read);
+            let _ = sys_close(write);
+            return Err(err);
+        }
+    };
+
+    if pid == 0 {
+        let _ = sys_close(read);
+
+        let err = match spawn_child(path, argv, envv, options) {
+            Ok(never) => match never {},
+            Err(err) => err,
+        };
+
+        let _ = sys_write(write, &(err as usize).to_ne_bytes());
+        sys_exit(SPAWN_FAILED);
+    }
+
+    if options.new_process_group {
+        // Also done by the child. Setting it from both sides makes sure that the process group
+        // exists by the time we return, whichever of the two runs first.
+        let _ = sys_setpgid(pid, pid);
+    }
+
+    let _ = sys_close(write);
+
+    let mut errno = [0u8; core::mem::size_of::<usize>()];
+    let mut filled = 0;
+
+    while filled < errno.len() {
+        match sys_read(read, &mut errno[filled..]) {
+            Ok(0) => break,
+            Ok(size) => filled += size,
+            Err(SyscallError::EINTR) => continue,
+            Err(_) => break,
+        }
+    }
+
+    let _ = sys_close(read);
+
+    if filled == errno.len() {
+        let mut status = 0;
+        while let Err(SyscallError::EINTR) = sys_waitpid(pid, &mut status) {}
+
+        let errno = usize::from_ne_bytes(errno);
+        return isize_as_syscall_result(-(errno as isize));
+    }
+
+    Ok(pid)
+}
+
+/// Returns the lowest nice value of the process, process group or user selected by `which`
+/// (one of `PRIO_PROCESS`, `PRIO_PGRP` and `PRIO_USER`) and `who`. A `who` of zero selects the
+/// calling process, its process group or its user.
+pub fn sys_getpriority(which: usize, who: usize) -> Result<isize> {
+    let value = syscall2(SYS_GETPRIORITY, which, who);
+
+    // The kernel returns `20 - nice` to keep the result positive.
+    isize_as_syscall_result(value as _).map(|priority| 20 - priority as isize)
+}
+
+/// Sets the nice value of the selected processes, clamped to the `-20..=19` range.
+pub fn sys_setpriority(which: usize, who: usize, nice: isize) -> Result<()> {
+    let value = syscall3(SYS_SETPRIORITY, which, who, nice as usize);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Adds `increment` to the nice value of the calling process and returns the new nice value.
+pub fn sys_nice(increment: isize) -> Result<isize> {
+    let nice = sys_getpriority(PRIO_PROCESS, 0)?;
+    sys_setpriority(PRIO_PROCESS, 0, nice + increment)?;
+    sys_getpriority(PRIO_PROCESS, 0)
+}
+
+/// Returns the PID of the parent of the calling process. Orphaned processes are adopted by
+/// init, whose PID is 1.
+pub fn sys_getppid() -> usize {
+    syscall0(SYS_GETPPID)
+}
+
+/// Maximum number of supplementary group IDs of a process.
+pub const NGROUPS_MAX: usize = 65536;
+
+/// Stores the supplementary group IDs of the calling process into `groups` and returns their
+/// number. An empty `groups` only returns the number.
+///
+/// ## Errors
+///
+/// Fails with `EINVAL` if `groups` is not empty but too small to hold all of them.
+pub fn sys_getgroups(groups: &mut [u32]) -> Result<usize> {
+    let value = syscall2(SYS_GETGROUPS, groups.as_mut_ptr() as usize, groups.len());
+    isize_as_syscall_result(value as _)
+}
+
+/// Replaces the supplementary group IDs of the calling process with `groups`, which can hold up
+/// to [`NGROUPS_MAX`] of them.
+pub fn sys_setgroups(groups: &[u32]) -> Result<()> {
+    let value = syscall2(SYS_SETGROUPS, groups.as_ptr() as usize, groups.len());
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Set in the status of a process killed by a signal if it dumped core (`WCOREFLAG`).
+pub const WCOREFLAG: u32 = 0x80;
+
+/// A status reported by `waitpid`, decoded. The bit layout is the one of mlibc/abis/linux/wait.h
+/// (`W_EXITCODE` and `W_STOPCODE`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WaitStatus {
+    /// The process exited with the code (`WIFEXITED`).
+    Exited(u8),
+    /// The process was killed by the signal (`WIFSIGNALED`).
+    Signaled { signal: usize, core_dumped: bool },
+    /// The process was stopped by the signal (`WIFSTOPPED`).
+    Stopped(usize),
+    /// The stopped process was continued (`WIFCONTINUED`).
+    Continued,
+}
+
+impl WaitStatus {
+    pub const fn from_raw(status: u32) -> Self {
+        let signal = (status & 0x7f) as usize;
+
+        if status == 0xffff {
+            Self::Continued
+        } else if signal == 0 {
+            Self::Exited((status >> 8) as u8)
+        } else if signal == 0x7f {
+            Self::Stopped(((status >> 8) & 0xff) as usize)
+        } else {
+            Self::Signaled {
+                signal,
+                core_dumped: status & WCOREFLAG != 0,
+            }
+        }
+    }
+
+    pub const fn into_raw(self) -> u32 {
+        match self {
+            Self::Exited(code) => (code as u32) << 8,
+            Self::Signaled {
+                signal,
+                core_dumped,
+            } => signal as u32 | if core_dumped { WCOREFLAG } else { 0 },
+            Self::Stopped(signal) => (signal as u32) << 8 | 0x7f,
+            Self::Continued => 0xffff,
+        }
+    }
+
+    /// Returns the status as shells report it in `$?`: the exit code, or 128 plus the number of
+    /// the signal that killed or stopped the process.
+    pub const fn shell_code(self) -> i32 {
+        match self {
+            Self::Exited(code) => code as i32,
+            Self::Signaled { signal, .. } | Self::Stopped(signal) => 128 + signal as i32,
+            Self::Continued => 0,
+        }
+    }
+}
+
+/// CPU times of a process and of its children that have been waited for, in clock ticks
+/// (`sysconf(_SC_CLK_TCK)` per second). Laid out like `struct tms`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tms {
+    pub tms_utime: i64,
+    pub tms_stime: i64,
+    pub tms_cutime: i64,
+    pub tms_cstime: i64,
+}
+
+/// Stores the CPU times of the calling process into `tms` and returns the number of clock ticks
+/// elapsed since boot.
+pub fn sys_times(tms: &mut Tms) -> Result<usize> {
+    let value = syscall1(SYS_TIMES, tms as *mut Tms as usize);
+    isize_as_syscall_result(value as _)
+}
+
+/// Resource usage of the calling process.
+pub const RUSAGE_SELF: isize = 0;
+/// Resource usage of the children of the calling process that have been waited for.
+pub const RUSAGE_CHILDREN: isize = -1;
+/// Resource usage of the calling thread.
+pub const RUSAGE_THREAD: isize = 1;
+
+/// Resource usage returned by [`sys_getrusage`]. Laid out like `struct rusage`; the fields that
+/// are not tracked are zero.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RUsage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    pub ru_maxrss: i64,
+    pub ru_ixrss: i64,
+    pub ru_idrss: i64,
+    pub ru_isrss: i64,
+    /// Number of page faults serviced without any I/O.
+    pub ru_minflt: i64,
+    /// Number of page faults that required I/O.
+    pub ru_majflt: i64,
+    pub ru_nswap: i64,
+    pub ru_inblock: i64,
+    pub ru_oublock: i64,
+    pub ru_msgsnd: i64,
+    pub ru_msgrcv: i64,
+    pub ru_nsignals: i64,
+    pub ru_nvcsw: i64,
+    pub ru_nivcsw: i64,
+}
+
+/// Stores the resource usage selected by `who` (one of [`RUSAGE_SELF`], [`RUSAGE_CHILDREN`] or
+/// [`RUSAGE_THREAD`]) into `usage`.
+pub fn sys_getrusage(who: isize, usage: &mut RUsage) -> Result<()> {
+    let value = syscall2(SYS_GETRUSAGE, who as usize, usage as *mut RUsage as usize);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// A resource limit, laid out like `struct rlimit`. The soft limit `rlim_cur` is the one that is
+/// enforced and can be raised up to the hard limit `rlim_max`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RLimit {
+    pub rlim_cur: u64,
+    pub rlim_max: u64,
+}
+
+/// Stores the limits of `resource` (one of the `RLIMIT_*` constants) of the calling process into
+/// `limit`. Resources that are not limited report [`RLIM_INFINITY`].
+pub fn sys_getrlimit(resource: usize, limit: &mut RLimit) -> Result<()> {
+    let value = syscall2(SYS_GETRLIMIT, resource, limit as *mut RLimit as usize);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Sets the limits of `resource` of the calling process to `limit`. Fails with `EPERM` if the
+/// hard limit would be raised.
+pub fn sys_setrlimit(resource: usize, limit: &RLimit) -> Result<()> {
+    let value = syscall2(SYS_SETRLIMIT, resource, limit as *const RLimit as usize);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Sends `SIGALRM` to the calling process in `seconds` seconds, replacing the previous alarm. A
+/// `seconds` of zero cancels it. Returns the number of seconds that were left until the previous
+/// alarm, or zero if there was none.
+pub fn sys_alarm(seconds: usize) -> usize {
+    syscall1(SYS_ALARM, seconds)
+}
+
+/// Maximum number of CPUs in a [`CpuSet`].
+pub const CPU_SETSIZE: usize = 1024;
+
+/// A set of CPUs, laid out like `cpu_set_t`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuSet {
+    bits: [u64; CPU_SETSIZE / 64],
+}
+
+impl CpuSet {
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; CPU_SETSIZE / 64],
+        }
+    }
+
+    /// Adds `cpu` to the set. CPUs past [`CPU_SETSIZE`] are ignored.
+    pub fn insert(&mut self, cpu: usize) {
+        if cpu < CPU_SETSIZE {
+            self.bits[cpu / 64] |= 1 << (cpu % 64);
+        }
+    }
+
+    pub fn contains(&self, cpu: usize) -> bool {
+        cpu < CPU_SETSIZE && self.bits[cpu / 64] & (1 << (cpu % 64)) != 0
+    }
+
+    /// Returns the number of CPUs in the set (`CPU_COUNT`).
+    pub fn count(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+}
+
+impl Default for CpuSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stores the set of CPUs the process `pid` may run on into `set`. A `pid` of zero selects the
+/// calling process.
+///
+/// Setting the affinity is not supported yet, so every process may run on all of the online
+/// CPUs.
+pub fn sys_sched_getaffinity(pid: usize, set: &mut CpuSet) -> Result<()> {
+    let value = syscall2(SYS_SCHED_GETAFFINITY, pid, set as *mut CpuSet as usize);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+bitflags::bitflags! {
+    // linux/membarrier.h
+    //
+    /// Commands of [`sys_membarrier`]. Every command is a single bit, and the empty set is
+    /// `MEMBARRIER_CMD_QUERY`.
+    #[derive(Default)]
+    pub struct MembarrierCmd: usize {
+        /// Every running thread of the system executes a memory barrier.
+        const GLOBAL = 1 << 0;
+        /// Like [`Self::GLOBAL`], for the processes that registered for it.
+        const GLOBAL_EXPEDITED = 1 << 1;
+        const REGISTER_GLOBAL_EXPEDITED = 1 << 2;
+        /// Every running thread of the calling process executes a memory barrier.
+        const PRIVATE_EXPEDITED = 1 << 3;
+        const REGISTER_PRIVATE_EXPEDITED = 1 << 4;
+    }
+}
+
+/// Issues a memory barrier on the threads selected by `cmd`, which returns once all of them
+/// executed it. An empty `cmd` returns the set of supported commands instead.
+pub fn sys_membarrier(cmd: MembarrierCmd) -> Result<MembarrierCmd> {
+    let value = syscall3(SYS_MEMBARRIER, cmd.bits(), 0, 0);
+    isize_as_syscall_result(value as _).map(MembarrierCmd::from_bits_truncate)
+}
+
+/// Applies the spawn options in the child and executes `path`. Only returns on failure.
+fn spawn_child(
+    path: &str,
+    argv: &[&str],
+    envv: &[&str],
+    options: &SpawnOptions,
+) -> Result<Infallible> {
+    if options.new_process_group {
+        sys_setpgid(0, 0)?;
+    }
+
+    let stdio = [options.stdin, options.stdout, options.stderr];
+
+    for (target, fd) in stdio.into_iter().enumerate() {
+        match fd {
+            // The descriptor is already in place, so only make sure it survives the `exec`.
+            Some(fd) if fd == target => sys_clear_cloexec(fd)?,
+            Some(fd) => {
+                sys_dup2(fd, target)?;
+            }
+            None => {}
+        }
+    }
+
+    if let Some(cwd) = options.cwd {
+        sys_chdir(cwd)?;
+    }
+
+    sys_exec(path, argv, envv)
+}
+
+fn sys_fork() -> Result<usize> {
+    let value = syscall0(SYS_FORK);
+    isize_as_syscall_result(value as _)
+}
+
+fn sys_exec(path: &str, argv: &[&str], envv: &[&str]) -> Result<Infallible> {
+    // The kernel expects the arguments and environment as arrays of `(ptr, len)` pairs, which
+    // is the layout of `&str`.
+    let value = syscall6(
+        SYS_EXEC,
+        path.as_ptr() as usize,
+        path.len(),
+        argv.as_ptr() as usize,
+        argv.len(),
+        envv.as_ptr() as usize,
+        envv.len(),
+    );
+
+    isize_as_syscall_result(value as _)?;
+    unreachable!("exec returned without an error")
+}
+
+fn sys_exit(status: usize) -> ! {
+    syscall1(SYS_EXIT, status);
+    unreachable!("exit returned")
+}
+
+fn sys_waitpid(pid: usize, status: &mut u32) -> Result<usize> {
+    let value = syscall3(SYS_WAITPID, pid, status as *mut u32 as usize, 0);
+    isize_as_syscall_result(value as _)
+}
+
+fn sys_setpgid(pid: usize, pgid: usize) -> Result<()> {
+    let value = syscall2(SYS_SETPGID, pid, pgid);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+fn sys_pipe(flags: OpenFlags) -> Result<(usize, usize)> {
+    let mut fds = [0i32; 2];
+    let value = syscall2(SYS_PIPE, fds.as_mut_ptr() as usize, flags.bits());
+
+    isize_as_syscall_result(value as _).map(|_| (fds[0] as usize, fds[1] as usize))
+}
+
+fn sys_clear_cloexec(fd: usize) -> Result<()> {
+    let value = syscall3(SYS_FCNTL, fd, F_SETFD, 0);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+fn sys_chdir(path: &str) -> Result<()> {
+    let value = syscall3(
+        SYS_CHDIR,
+        AT_FDCWD as usize,
+        path.as_ptr() as usize,
+        path.len(),
+    );
+
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+fn sys_read(fd: usize, buffer: &mut [u8]) -> Result<usize> {
+    let value = syscall3(SYS_READ, fd, buffer.as_mut_ptr() as usize, buffer.len());
+    isize_as_syscall_result(value as _)
+}
+
+fn sys_write(fd: usize, buffer: &[u8]) -> Result<usize> {
+    let value = syscall3(SYS_WRITE, fd, buffer.as_ptr() as usize, buffer.len());
+    isize_as_syscall_result(value as _)
+}
@@ -165,3 +165,80 @@ impl From<SigProcMask> for usize {
         s as u64 as usize
     }
 }
+
+// `si_code` values, mlibc/abis/linux/signal.h
+/// Sent by `kill` or `raise`.
+pub const SI_USER: i32 = 0;
+/// Process breakpoint.
+pub const TRAP_BRKPT: i32 = 1;
+/// Process trace trap.
+pub const TRAP_TRACE: i32 = 2;
+/// Hardware breakpoint or watchpoint.
+pub const TRAP_HWBKPT: i32 = 4;
+/// The child has exited.
+pub const CLD_EXITED: i32 = 1;
+/// The child was killed by a signal.
+pub const CLD_KILLED: i32 = 2;
+/// The child was killed by a signal and dumped core.
+pub const CLD_DUMPED: i32 = 3;
+/// The traced child has stopped.
+pub const CLD_TRAPPED: i32 = 4;
+/// The child was stopped by a signal.
+pub const CLD_STOPPED: i32 = 5;
+/// The stopped child was continued.
+pub const CLD_CONTINUED: i32 = 6;
+
+/// Information about a signal, as read by `PTRACE_GETSIGINFO` or received by a handler installed
+/// with `SA_SIGINFO`. The layout follows `siginfo_t`; the fields after `si_code` depend on the
+/// signal and are read with the accessors.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SigInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad: i32,
+    fields: [u32; 28],
+}
+
+impl SigInfo {
+    pub fn new(signal: usize, code: i32, addr: u64) -> Self {
+        let mut fields = [0; 28];
+        fields[0] = addr as u32;
+        fields[1] = (addr >> 32) as u32;
+
+        Self {
+            si_signo: signal as i32,
+            si_errno: 0,
+            si_code: code,
+            _pad: 0,
+            fields,
+        }
+    }
+
+    /// Creates the information of a `SIGCHLD` about the child `pid`. `status` is the exit code
+    /// for [`CLD_EXITED`] and the signal otherwise.
+    pub fn child(code: i32, pid: usize, status: i32) -> Self {
+        let mut info = Self::new(SIGCHLD, code, 0);
+        info.fields[0] = pid as u32;
+        info.fields[2] = status as u32;
+        info
+    }
+
+    /// The address that caused the signal, for `SIGSEGV`, `SIGBUS` and `SIGTRAP`.
+    pub fn si_addr(&self) -> u64 {
+        self.fields[0] as u64 | (self.fields[1] as u64) << 32
+    }
+
+    /// The process that sent the signal or, for `SIGCHLD`, the child whose state changed.
+    pub fn si_pid(&self) -> usize {
+        self.fields[0] as usize
+    }
+
+    /// The exit code or the signal of the child, for `SIGCHLD`.
+    pub fn si_status(&self) -> i32 {
+        self.fields[2] as i32
+    }
+}
+
+const _: () = assert!(core::mem::size_of::<SigInfo>() == 128);
@@ -1,5 +1,7 @@
 #![allow(non_camel_case_types)]
 
+use num_traits::FromPrimitive;
+
 use crate::SocketAddr;
 
 mod c {
@@ -53,21 +55,45 @@ pub struct MessageHeader {
     iovec: *mut IoVec, // todo: use Option<NonNull<IoVec>>
     iovec_len: i32,    // todo: use ffi::c_int
 
-    control: *const u8,
+    control: *mut u8,
     control_len: c::socklen_t,
 
     pub flags: i32, // todo: use ffi::c_int
 }
 
 impl MessageHeader {
-    pub fn name_mut<T: SocketAddr>(&mut self) -> Option<&mut T> {
-        if self.name.is_null() {
-            return None;
+    /// Creates a message header for `sendmsg` or `recvmsg` without any control data. `name` is
+    /// the destination address when sending and receives the source address when receiving.
+    pub fn new<T: SocketAddr>(name: Option<&mut T>, iovecs: &mut [IoVec]) -> Self {
+        let (name, name_len) = match name {
+            Some(name) => (name as *mut T as *mut u8, core::mem::size_of::<T>()),
+            None => (core::ptr::null_mut(), 0),
+        };
+
+        Self {
+            name,
+            name_len: name_len as c::socklen_t,
+            iovec: iovecs.as_mut_ptr(),
+            iovec_len: iovecs.len() as i32,
+            control: core::ptr::null_mut(),
+            control_len: 0,
+            flags: 0,
         }
+    }
 
-        assert!(self.name_len >= core::mem::size_of::<T>() as u32);
+    /// Returns the socket address buffer (`msg_name`) and its size, if any. The buffer is not
+    /// dereferenced, as it is a userspace pointer when the header comes from a system call.
+    pub fn name(&self) -> Option<(*mut u8, usize)> {
+        if self.name.is_null() {
+            None
+        } else {
+            Some((self.name, self.name_len as usize))
+        }
+    }
 
-        unsafe { Some(&mut *(self.name as *mut T)) }
+    /// Sets the size of the socket address returned in `msg_name`.
+    pub fn set_name_len(&mut self, name_len: c::socklen_t) {
+        self.name_len = name_len;
     }
 
     pub fn iovecs(&self) -> &[IoVec] {
@@ -78,9 +104,44 @@ impl MessageHeader {
         unsafe { core::slice::from_raw_parts_mut(self.iovec, self.iovec_len as usize) }
     }
 
-    pub fn control(&self) -> &[ControlMessage] {
-        assert!(self.control_len == 0);
-        &[]
+    /// Returns the total size of the I/O vectors.
+    pub fn iovecs_len(&self) -> usize {
+        self.iovecs().iter().map(IoVec::len).sum()
+    }
+
+    /// Copies `data` into the I/O vectors, filling each one before moving on to the next. Returns
+    /// the number of bytes copied, which is less than the size of `data` if it does not fit.
+    pub fn scatter(&mut self, data: &[u8]) -> usize {
+        let mut copied = 0;
+
+        for iovec in self.iovecs_mut() {
+            let iovec = iovec.as_slice_mut();
+            let size = core::cmp::min(iovec.len(), data.len() - copied);
+
+            iovec[..size].copy_from_slice(&data[copied..copied + size]);
+            copied += size;
+
+            if copied == data.len() {
+                break;
+            }
+        }
+
+        copied
+    }
+
+    /// Returns the buffer of the control messages (`msg_control`) and its size, if any. Like
+    /// [`MessageHeader::name`], the buffer is not dereferenced.
+    pub fn control(&self) -> Option<(*mut u8, usize)> {
+        if self.control.is_null() {
+            None
+        } else {
+            Some((self.control, self.control_len as usize))
+        }
+    }
+
+    /// Sets the size of the control messages returned in `msg_control`.
+    pub fn set_control_len(&mut self, control_len: c::socklen_t) {
+        self.control_len = control_len;
     }
 }
 
@@ -93,6 +154,24 @@ pub struct IoVec {
 }
 
 impl IoVec {
+    /// Creates an I/O vector for data that is only read, such as the buffers passed to
+    /// `sendmsg`.
+    pub fn from_slice(buffer: &[u8]) -> Self {
+        Self {
+            base: buffer.as_ptr() as *mut u8,
+            len: buffer.len(),
+        }
+    }
+
+    /// Creates an I/O vector for data that is written, such as the buffers passed to
+    /// `recvmsg`.
+    pub fn from_slice_mut(buffer: &mut [u8]) -> Self {
+        Self {
+            base: buffer.as_mut_ptr(),
+            len: buffer.len(),
+        }
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         // SAFETY: We know that the `base` pointer is valid and initialized.
         unsafe { core::slice::from_raw_parts_mut(self.base, self.len) }
@@ -111,19 +190,96 @@ impl IoVec {
 }
 
 /// Control Message Header (`struct cmsghdr`).
-#[derive(Debug)]
+///
+/// The level and the type are kept as integers as they come from userspace; use
+/// [`ControlMessage::level`] and [`ControlMessage::kind`] to decode them.
+#[derive(Debug, Copy, Clone)]
 #[repr(C)]
 pub struct ControlMessage {
     /// Data byte count, including the header.
     pub cmsg_len: c::socklen_t,
     /// Originating protocol.
-    pub cmsg_level: SocketOptionLevel,
+    pub cmsg_level: i32,
     /// Protocol-specific type.
-    pub cmsg_type: ControlMessageType,
+    pub cmsg_type: i32,
     // followed by cmsg_data: [u8; cmsg_len - sizeof(struct cmsghdr)]
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+impl ControlMessage {
+    pub fn level(&self) -> Option<SocketOptionLevel> {
+        SocketOptionLevel::from_i32(self.cmsg_level)
+    }
+
+    pub fn kind(&self) -> Option<ControlMessageType> {
+        ControlMessageType::from_i32(self.cmsg_type)
+    }
+}
+
+/// Rounds `len` up to the alignment of control messages (`CMSG_ALIGN`).
+pub const fn cmsg_align(len: usize) -> usize {
+    let align = core::mem::align_of::<usize>();
+    (len + align - 1) & !(align - 1)
+}
+
+/// Returns the value of `cmsg_len` for a control message with `len` bytes of data (`CMSG_LEN`).
+pub const fn cmsg_len(len: usize) -> usize {
+    cmsg_align(core::mem::size_of::<ControlMessage>()) + len
+}
+
+/// Returns the space taken by a control message with `len` bytes of data, including the padding
+/// before the next one (`CMSG_SPACE`).
+pub const fn cmsg_space(len: usize) -> usize {
+    cmsg_align(core::mem::size_of::<ControlMessage>()) + cmsg_align(len)
+}
+
+/// Iterator over the control messages in the control buffer of a [`MessageHeader`] and their
+/// data.
+///
+/// Iteration stops at the first message whose length does not fit in the remaining buffer, like
+/// `CMSG_NXTHDR` does.
+pub struct ControlMessages<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> ControlMessages<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        Self { buffer }
+    }
+}
+
+impl<'a> Iterator for ControlMessages<'a> {
+    type Item = (ControlMessage, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.len() < core::mem::size_of::<ControlMessage>() {
+            return None;
+        }
+
+        // SAFETY: The buffer is large enough to hold the header. It is not necessarily aligned
+        // as it comes from userspace.
+        let header = unsafe {
+            self.buffer
+                .as_ptr()
+                .cast::<ControlMessage>()
+                .read_unaligned()
+        };
+        let len = header.cmsg_len as usize;
+
+        if len < cmsg_len(0) || len > self.buffer.len() {
+            self.buffer = &[];
+            return None;
+        }
+
+        let data = &self.buffer[cmsg_len(0)..len];
+
+        let next = core::cmp::min(cmsg_align(len), self.buffer.len());
+        self.buffer = &self.buffer[next..];
+
+        Some((header, data))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, FromPrimitive)]
 #[repr(i32)]
 pub enum ControlMessageType {
     Rights = c::SCM_RIGHTS,
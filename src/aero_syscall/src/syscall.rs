@@ -15,11 +15,20 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+//! The raw system call functions that every wrapper goes through.
+//!
+//! Normal builds issue the system call instruction directly. Tests of this crate, and builds
+//! with the `mock` feature enabled, forward every call to the [`mock`](crate::mock) backend
+//! instead, so the wrappers can be tested on the host.
+
+#[cfg(not(any(test, feature = "mock")))]
 use core::arch::asm;
 
+#[cfg(not(any(test, feature = "mock")))]
 macro_rules! define_syscall_fns{
     ($(pub fn $sys_fn:ident($a:ident $(,$b:ident $(,$c:ident $(,$d:ident $(,$e:ident $(,$f:ident $(,$g:ident)?)?)?)?)?)?) -> usize;)+) => {
         $(
+            #[inline]
             pub fn $sys_fn(mut $a: usize, $($b: usize, $($c: usize, $($d: usize, $($e: usize, $($f: usize, $($g: usize)?)?)?)?)?)?) -> usize {
                 #[cfg(target_arch = "x86_64")]
                 unsafe {
@@ -42,6 +51,18 @@ macro_rules! define_syscall_fns{
     }
 }
 
+#[cfg(any(test, feature = "mock"))]
+macro_rules! define_syscall_fns{
+    ($(pub fn $sys_fn:ident($a:ident $(,$b:ident $(,$c:ident $(,$d:ident $(,$e:ident $(,$f:ident $(,$g:ident)?)?)?)?)?)?) -> usize;)+) => {
+        $(
+            pub fn $sys_fn($a: usize, $($b: usize, $($c: usize, $($d: usize, $($e: usize, $($f: usize, $($g: usize)?)?)?)?)?)?) -> usize {
+                let args: &[usize] = &[$($b, $($c, $($d, $($e, $($f, $($g)?)?)?)?)?)?];
+                crate::mock::dispatch($a, args)
+            }
+        )+
+    }
+}
+
 define_syscall_fns!(
     pub fn syscall0(a) -> usize;
     pub fn syscall1(a, b) -> usize;
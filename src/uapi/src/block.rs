@@ -0,0 +1,19 @@
+use crate::ioctl;
+
+pub const BLKROSET: usize = ioctl::io(0x12, 93);
+pub const BLKROGET: usize = ioctl::io(0x12, 94);
+pub const BLKGETSIZE: usize = ioctl::io(0x12, 96);
+pub const BLKSSZGET: usize = ioctl::io(0x12, 104);
+pub const BLKBSZGET: usize = ioctl::ior::<usize>(0x12, 112);
+pub const BLKGETSIZE64: usize = ioctl::ior::<usize>(0x12, 114);
+
+pub const HDIO_GETGEO: usize = 0x0301;
+
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct HdGeometry {
+    pub heads: u8,
+    pub sectors: u8,
+    pub cylinders: u16,
+    pub start: u64,
+}
@@ -246,6 +246,136 @@ pub struct DrmModeMapDumb {
     pub offset: u64,
 }
 
+#[repr(C)]
+pub struct DrmModeGetPlaneRes {
+    pub plane_id_ptr: u64, // pointer to `u32` array of plane IDs
+    pub count_planes: u32,
+}
+
+/// Type of a plane, as exposed through its `type` property.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DrmPlaneType {
+    Overlay = 0,
+    Primary = 1,
+    Cursor = 2,
+}
+
+#[repr(C)]
+pub struct DrmModeGetPlane {
+    pub plane_id: u32, // ID of the plane
+
+    pub crtc_id: u32, // ID of the current CRTC
+    pub fb_id: u32,   // ID of the current framebuffer
+
+    pub possible_crtcs: u32, // bitmask of the CRTC indices the plane can be used with
+    pub gamma_size: u32,
+
+    pub count_format_types: u32, // number of formats
+    pub format_type_ptr: u64,    // pointer to `u32` array of fourcc formats
+}
+
+#[repr(C)]
+pub struct DrmModeSetPlane {
+    pub plane_id: u32,
+    pub crtc_id: u32,
+    pub fb_id: u32, // framebuffer ID, or zero to disable the plane
+    pub flags: u32,
+
+    // Destination rectangle on the CRTC, in pixels.
+    pub crtc_x: i32,
+    pub crtc_y: i32,
+    pub crtc_w: u32,
+    pub crtc_h: u32,
+
+    // Source rectangle in the framebuffer, in 16.16 fixed point.
+    pub src_x: u32,
+    pub src_y: u32,
+    pub src_h: u32,
+    pub src_w: u32,
+}
+
+pub const DRM_PROP_NAME_LEN: usize = 32;
+
+// Property types:
+pub const DRM_MODE_PROP_RANGE: u32 = 1 << 1;
+pub const DRM_MODE_PROP_IMMUTABLE: u32 = 1 << 2;
+pub const DRM_MODE_PROP_ENUM: u32 = 1 << 3; // enumerated type with text strings
+pub const DRM_MODE_PROP_OBJECT: u32 = 1 << 6; // value is the ID of a mode object
+pub const DRM_MODE_PROP_SIGNED_RANGE: u32 = 2 << 6;
+pub const DRM_MODE_PROP_ATOMIC: u32 = 0x80000000; // only visible to atomic clients
+
+// Mode object types:
+pub const DRM_MODE_OBJECT_CRTC: u32 = 0xcccccccc;
+pub const DRM_MODE_OBJECT_CONNECTOR: u32 = 0xc0c0c0c0;
+pub const DRM_MODE_OBJECT_ENCODER: u32 = 0xe0e0e0e0;
+pub const DRM_MODE_OBJECT_PROPERTY: u32 = 0xb0b0b0b0;
+pub const DRM_MODE_OBJECT_FB: u32 = 0xfbfbfbfb;
+pub const DRM_MODE_OBJECT_PLANE: u32 = 0xeeeeeeee;
+pub const DRM_MODE_OBJECT_ANY: u32 = 0;
+
+#[repr(C)]
+pub struct DrmModePropertyEnum {
+    pub value: u64,
+    pub name: [ffi::c_char; DRM_PROP_NAME_LEN],
+}
+
+#[repr(C)]
+pub struct DrmModeGetProperty {
+    pub values_ptr: u64, // pointer to `u64` array of values (the range or object type)
+    pub enum_blob_ptr: u64, // pointer to `DrmModePropertyEnum` array
+
+    pub prop_id: u32,
+    pub flags: u32, // type of the property
+    pub name: [ffi::c_char; DRM_PROP_NAME_LEN],
+
+    pub count_values: u32,
+    pub count_enum_blobs: u32,
+}
+
+#[repr(C)]
+pub struct DrmModeObjGetProperties {
+    pub props_ptr: u64,       // pointer to `u32` array of property IDs
+    pub prop_values_ptr: u64, // pointer to `u64` array of property values
+    pub count_props: u32,     // number of properties
+    pub obj_id: u32,
+    pub obj_type: u32, // type of the object, or `DRM_MODE_OBJECT_ANY`
+}
+
+// Atomic commit flags:
+pub const DRM_MODE_PAGE_FLIP_EVENT: u32 = 0x01;
+pub const DRM_MODE_ATOMIC_TEST_ONLY: u32 = 0x0100;
+pub const DRM_MODE_ATOMIC_NONBLOCK: u32 = 0x0200;
+pub const DRM_MODE_ATOMIC_ALLOW_MODESET: u32 = 0x0400;
+
+pub const DRM_MODE_ATOMIC_FLAGS: u32 = DRM_MODE_PAGE_FLIP_EVENT
+    | DRM_MODE_ATOMIC_TEST_ONLY
+    | DRM_MODE_ATOMIC_NONBLOCK
+    | DRM_MODE_ATOMIC_ALLOW_MODESET;
+
+/// Sets the properties of a number of mode objects at once. For each object in `objs_ptr`,
+/// `count_props_ptr` holds the number of its `(property, value)` pairs, which follow each other
+/// in `props_ptr` and `prop_values_ptr`.
+#[repr(C)]
+pub struct DrmModeAtomicReq {
+    pub flags: u32,
+    pub count_objs: u32,
+    pub objs_ptr: u64,        // pointer to `u32` array of object IDs
+    pub count_props_ptr: u64, // pointer to `u32` array of property counts, one per object
+    pub props_ptr: u64,       // pointer to `u32` array of property IDs
+    pub prop_values_ptr: u64, // pointer to `u64` array of property values
+    pub reserved: u64,        // must be zero
+    pub user_data: u64,
+}
+
+/// Builds the fourcc code of a pixel format.
+pub const fn fourcc_code(a: u8, b: u8, c: u8, d: u8) -> u32 {
+    (a as u32) | ((b as u32) << 8) | ((c as u32) << 16) | ((d as u32) << 24)
+}
+
+/// 32 bpp RGB format, with 8 unused bits: `[31:0] x:R:G:B 8:8:8:8 little endian`.
+pub const DRM_FORMAT_XRGB8888: u32 = fourcc_code(b'X', b'R', b'2', b'4');
+
 // DRM IOCTL constants:
 pub const DRM_IOCTL_VERSION: usize = drm_iowr::<DrmVersion>(0x00);
 pub const DRM_IOCTL_GET_CAP: usize = drm_iowr::<DrmGetCap>(0x0c);
@@ -255,7 +385,16 @@ pub const DRM_IOCTL_GET_CRTC: usize = drm_iowr::<DrmModeCrtc>(0xa1);
 pub const DRM_IOCTL_SET_CRTC: usize = drm_iowr::<DrmModeCrtc>(0xa2);
 pub const DRM_IOCTL_GET_ENCODER: usize = drm_iowr::<DrmModeGetEncoder>(0xa6);
 pub const DRM_IOCTL_GET_CONNECTOR: usize = drm_iowr::<DrmModeGetConnector>(0xa7);
+pub const DRM_IOCTL_MODE_GETPROPERTY: usize = drm_iowr::<DrmModeGetProperty>(0xaa);
+pub const DRM_IOCTL_MODE_GETFB: usize = drm_iowr::<DrmModeFbCmd>(0xad);
 pub const DRM_IOCTL_MODE_ADDFB: usize = drm_iowr::<DrmModeFbCmd>(0xae);
 
 pub const DRM_IOCTL_MODE_CREATE_DUMB: usize = drm_iowr::<DrmModeCreateDumb>(0xb2);
 pub const DRM_IOCTL_MODE_MAP_DUMB: usize = drm_iowr::<DrmModeMapDumb>(0xb3);
+
+pub const DRM_IOCTL_MODE_GETPLANERESOURCES: usize = drm_iowr::<DrmModeGetPlaneRes>(0xb5);
+pub const DRM_IOCTL_MODE_GETPLANE: usize = drm_iowr::<DrmModeGetPlane>(0xb6);
+pub const DRM_IOCTL_MODE_SETPLANE: usize = drm_iowr::<DrmModeSetPlane>(0xb7);
+
+pub const DRM_IOCTL_MODE_OBJ_GETPROPERTIES: usize = drm_iowr::<DrmModeObjGetProperties>(0xb9);
+pub const DRM_IOCTL_MODE_ATOMIC: usize = drm_iowr::<DrmModeAtomicReq>(0xbc);
@@ -1,5 +1,6 @@
 #![no_std]
 
+pub mod block;
 pub mod drm;
 pub mod ioctl;
 pub mod pty;
@@ -54,7 +54,8 @@ if ! lsmod | grep -q 'loop'; then
 fi
 
 $SUID_BINARY losetup -Pf --show $IMAGE_PATH > loopback_dev
-$SUID_BINARY mkfs.ext2 `cat loopback_dev`p1 -I128
+# 256 byte inodes have room for the creation time of the files.
+$SUID_BINARY mkfs.ext2 `cat loopback_dev`p1 -I256
 rm -rf disk_image/
 mkdir disk_image
 $SUID_BINARY mount `cat loopback_dev`p1 disk_image
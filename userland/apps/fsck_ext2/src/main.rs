@@ -0,0 +1,888 @@
+//! Checks the consistency of an ext2 filesystem, without repairing it.
+//!
+//! ext2 has no checksums, so the checks are structural:
+//!
+//! 1. The superblock and the block group descriptors describe a valid layout.
+//! 2. The directory tree is walked from the root. Every directory entry is valid and refers to an
+//!    inode in use, and every block of a reachable inode is inside of the filesystem, does not
+//!    overlap the metadata and is not used by any other inode.
+//! 3. The block and inode bitmaps match the blocks and inodes that are reachable.
+//! 4. The link count of every inode matches the directory entries referring to it.
+//! 5. The free counts of the groups and of the superblock match the bitmaps.
+//!
+//! The exit status follows the `fsck` conventions: 0 if the filesystem is clean, 4 if problems
+//! were found, 8 if it could not be checked and 16 on usage errors.
+
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::{env, process};
+
+use aero_ext2::{
+    FileType, GroupDescriptor, INode, OnDisk, SuperBlock, GOOD_OLD_FIRST_INODE,
+    GOOD_OLD_INODE_SIZE, ROOT_INODE, SUPERBLOCK_OFFSET,
+};
+
+const EXIT_CLEAN: i32 = 0;
+const EXIT_UNCORRECTED: i32 = 4;
+const EXIT_ERROR: i32 = 8;
+const EXIT_USAGE: i32 = 16;
+
+/// `feature_compat`: blocks are reserved after the BGDT to grow the filesystem, and are owned
+/// by [`RESIZE_INODE`].
+const FEATURE_COMPAT_RESIZE_INODE: u32 = 0x10;
+/// `feature_ro_compat`: files may be larger than 2 GiB.
+const FEATURE_RO_COMPAT_LARGE_FILE: u32 = 0x2;
+
+/// The reserved inode owning the reserved BGDT blocks.
+const RESIZE_INODE: usize = 7;
+
+/// The problems of each kind that are printed; the rest are only counted.
+const MAX_REPORTED: usize = 20;
+
+/// Errors that stop the check.
+enum Error {
+    Io(io::Error),
+    /// The filesystem is too damaged to be checked further. The problem was already reported.
+    Fatal,
+    Unsupported(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+struct Bitmap(Vec<u64>);
+
+impl Bitmap {
+    fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    fn get(&self, index: usize) -> bool {
+        self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn set(&mut self, index: usize) {
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+}
+
+struct Fsck {
+    file: File,
+    superblock: SuperBlock,
+    groups: Vec<GroupDescriptor>,
+    block_size: usize,
+    inode_size: usize,
+    first_inode: usize,
+    inodes_count: usize,
+    blocks_count: usize,
+
+    /// Blocks that hold the superblock and BGDT copies, the bitmaps and the inode tables.
+    metadata: Bitmap,
+    /// Blocks used by the reachable inodes.
+    used_blocks: Bitmap,
+    /// Reachable inodes, including the reserved ones.
+    used_inodes: Bitmap,
+    /// Reachable directories.
+    directories: Bitmap,
+    /// Number of directory entries referring to each inode.
+    links: Vec<u32>,
+
+    problems: usize,
+    /// Number of problems of the kind being reported, see [`Self::report`].
+    reported: usize,
+}
+
+impl Fsck {
+    fn open(path: &str) -> Result<Self, Error> {
+        let mut file = File::open(path)?;
+
+        let mut bytes = [0; 1024];
+        file.seek(SeekFrom::Start(SUPERBLOCK_OFFSET as u64))?;
+        file.read_exact(&mut bytes)?;
+
+        let superblock = SuperBlock::from_bytes(&bytes);
+        let magic = superblock.magic;
+
+        if magic != SuperBlock::MAGIC {
+            return Err(Error::Unsupported(format!(
+                "bad magic number in superblock ({magic:#x})"
+            )));
+        }
+
+        let rev_level = superblock.rev_level;
+        let feature_incompat = superblock.feature_incompat;
+
+        if rev_level > 1 {
+            return Err(Error::Unsupported(format!("unknown revision {rev_level}")));
+        }
+
+        if rev_level == 1 && feature_incompat & !SuperBlock::FEATURE_INCOMPAT_FILETYPE != 0 {
+            return Err(Error::Unsupported(format!(
+                "unsupported incompatible features ({feature_incompat:#x})"
+            )));
+        }
+
+        let (inode_size, first_inode) = if rev_level == 0 {
+            (GOOD_OLD_INODE_SIZE, GOOD_OLD_FIRST_INODE)
+        } else {
+            (
+                superblock.inode_size as usize,
+                superblock.first_ino as usize,
+            )
+        };
+
+        let inodes_count = superblock.inodes_count as usize;
+        let blocks_count = superblock.blocks_count as usize;
+
+        let mut fsck = Self {
+            file,
+            superblock,
+            groups: Vec::new(),
+            block_size: 0,
+            inode_size,
+            first_inode,
+            inodes_count,
+            blocks_count,
+
+            metadata: Bitmap::new(blocks_count),
+            used_blocks: Bitmap::new(blocks_count),
+            used_inodes: Bitmap::new(inodes_count + 1),
+            directories: Bitmap::new(inodes_count + 1),
+            links: vec![0; inodes_count + 1],
+
+            problems: 0,
+            reported: 0,
+        };
+
+        fsck.check_superblock()?;
+        fsck.read_groups()?;
+
+        Ok(fsck)
+    }
+
+    fn problem(&mut self, message: impl Display) {
+        self.problems += 1;
+        self.reported += 1;
+
+        if self.reported <= MAX_REPORTED {
+            println!("{message}");
+        } else if self.reported == MAX_REPORTED + 1 {
+            println!("...");
+        }
+    }
+
+    /// Runs `check`, printing at most [`MAX_REPORTED`] of the problems it finds.
+    fn report<T>(&mut self, check: impl FnOnce(&mut Self) -> T) -> T {
+        self.reported = 0;
+        check(self)
+    }
+
+    /// Reports a problem with the layout, which prevents the rest of the check.
+    fn fatal(&mut self, message: impl Display) -> Error {
+        self.problem(message);
+        Error::Fatal
+    }
+
+    fn check_superblock(&mut self) -> Result<(), Error> {
+        let sb = self.superblock;
+
+        if sb.log_block_size > 6 {
+            let log_block_size = sb.log_block_size;
+            return Err(self.fatal(format!("invalid block size (log {log_block_size})")));
+        }
+
+        self.block_size = sb.block_size();
+        let bits_per_block = 8 * self.block_size as u32;
+
+        let first_data_block = sb.first_data_block;
+        let expected_first = (self.block_size == 1024) as u32;
+
+        if first_data_block != expected_first {
+            return Err(self.fatal(format!(
+                "first data block is {first_data_block}, should be {expected_first}"
+            )));
+        }
+
+        let blocks_per_group = sb.blocks_per_group;
+        let inodes_per_group = sb.inodes_per_group;
+
+        if blocks_per_group == 0 || blocks_per_group > bits_per_block {
+            return Err(self.fatal(format!("invalid blocks per group ({blocks_per_group})")));
+        }
+
+        if inodes_per_group == 0 || inodes_per_group > bits_per_block {
+            return Err(self.fatal(format!("invalid inodes per group ({inodes_per_group})")));
+        }
+
+        if self.blocks_count <= first_data_block as usize {
+            let blocks_count = self.blocks_count;
+            return Err(self.fatal(format!("invalid blocks count ({blocks_count})")));
+        }
+
+        let groups = sb.bgdt_len();
+
+        if self.inodes_count != groups * inodes_per_group as usize {
+            let inodes_count = self.inodes_count;
+            return Err(self.fatal(format!(
+                "inodes count is {inodes_count}, should be {}",
+                groups * inodes_per_group as usize
+            )));
+        }
+
+        let inode_size = self.inode_size;
+
+        if !inode_size.is_power_of_two()
+            || inode_size < GOOD_OLD_INODE_SIZE
+            || inode_size > self.block_size
+        {
+            return Err(self.fatal(format!("invalid inode size ({inode_size})")));
+        }
+
+        let first_inode = self.first_inode;
+
+        if first_inode <= ROOT_INODE || first_inode > self.inodes_count {
+            return Err(self.fatal(format!("invalid first inode ({first_inode})")));
+        }
+
+        if sb.r_blocks_count > sb.blocks_count {
+            let r_blocks_count = sb.r_blocks_count;
+            self.problem(format!(
+                "reserved blocks count ({r_blocks_count}) is too large"
+            ));
+        }
+
+        if sb.state & SuperBlock::STATE_ERROR != 0 {
+            self.problem("the filesystem has errors recorded in its superblock");
+        } else if sb.state & SuperBlock::STATE_VALID == 0 {
+            println!("note: the filesystem was not cleanly unmounted");
+        }
+
+        Ok(())
+    }
+
+    /// Reads the BGDT and marks the blocks holding the metadata of each group.
+    fn read_groups(&mut self) -> Result<(), Error> {
+        let sb = self.superblock;
+        let len = sb.bgdt_len();
+
+        let mut bytes = vec![0; len * core::mem::size_of::<GroupDescriptor>()];
+        self.file.seek(SeekFrom::Start(sb.bgdt_block() as u64))?;
+        self.file.read_exact(&mut bytes)?;
+
+        self.groups = bytes
+            .chunks(core::mem::size_of::<GroupDescriptor>())
+            .map(GroupDescriptor::from_bytes)
+            .collect();
+
+        let table_blocks =
+            (sb.inodes_per_group as usize * self.inode_size).div_ceil(self.block_size);
+        let mut valid = true;
+
+        for group in 0..len {
+            let first = sb.group_first_block(group);
+            let end = first + sb.group_blocks(group);
+            let descriptor = self.groups[group];
+
+            if sb.has_superblock(group) {
+                let copy = 1 + sb.bgdt_blocks() + sb.reserved_gdt_blocks as usize;
+
+                for block in first..(first + copy).min(end) {
+                    self.metadata.set(block);
+                }
+            }
+
+            let locations = [
+                ("block bitmap", descriptor.block_bitmap as usize, 1),
+                ("inode bitmap", descriptor.inode_bitmap as usize, 1),
+                ("inode table", descriptor.inode_table as usize, table_blocks),
+            ];
+
+            for (name, start, blocks) in locations {
+                if start < first || start + blocks > end {
+                    self.problem(format!(
+                        "group {group}: {name} at block {start} is not in the group \
+                         ({first}-{})",
+                        end - 1
+                    ));
+
+                    valid = false;
+                    continue;
+                }
+
+                for block in start..start + blocks {
+                    if self.metadata.get(block) {
+                        self.problem(format!(
+                            "group {group}: {name} overlaps other metadata at block {block}"
+                        ));
+
+                        valid = false;
+                        break;
+                    }
+
+                    self.metadata.set(block);
+                }
+            }
+        }
+
+        if !valid {
+            return Err(Error::Fatal);
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self, offset: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buffer)?;
+        Ok(())
+    }
+
+    fn read_block(&mut self, block: usize) -> Result<Vec<u8>, Error> {
+        let mut data = vec![0; self.block_size];
+        self.read((block * self.block_size) as u64, &mut data)?;
+        Ok(data)
+    }
+
+    fn read_inode(&mut self, ino: usize) -> Result<INode, Error> {
+        let inodes_per_group = self.superblock.inodes_per_group as usize;
+        let table = self.groups[(ino - 1) / inodes_per_group].inode_table as usize;
+        let offset = table * self.block_size + (ino - 1) % inodes_per_group * self.inode_size;
+
+        let mut bytes = [0; core::mem::size_of::<INode>()];
+        self.read(offset as u64, &mut bytes)?;
+
+        Ok(INode::from_bytes(&bytes))
+    }
+
+    /// Marks `block` as used by the inode `ino`, and returns whether it is valid.
+    fn claim_block(&mut self, ino: usize, block: usize) -> bool {
+        if block < self.superblock.first_data_block as usize || block >= self.blocks_count {
+            self.problem(format!(
+                "inode {ino}: block {block} is outside of the filesystem"
+            ));
+        } else if self.metadata.get(block) {
+            self.problem(format!(
+                "inode {ino}: block {block} overlaps the filesystem metadata"
+            ));
+        } else if self.used_blocks.get(block) {
+            self.problem(format!(
+                "inode {ino}: block {block} is also used by another inode"
+            ));
+        } else {
+            self.used_blocks.set(block);
+            return true;
+        }
+
+        false
+    }
+
+    /// Claims the blocks referred to by the indirect block `block` of depth `depth`, appends the
+    /// data blocks to `data` and returns the number of blocks claimed. Holes are skipped.
+    fn claim_indirect(
+        &mut self,
+        ino: usize,
+        block: usize,
+        depth: usize,
+        data: &mut Vec<usize>,
+    ) -> Result<usize, Error> {
+        let entries = self.read_block(block)?;
+        let mut claimed = 0;
+
+        for entry in entries.chunks(4) {
+            let child = u32::from_le_bytes(entry.try_into().unwrap()) as usize;
+
+            if child == 0 || !self.claim_block(ino, child) {
+                continue;
+            }
+
+            claimed += 1;
+
+            if depth == 0 {
+                data.push(child);
+            } else {
+                claimed += self.claim_indirect(ino, child, depth - 1, data)?;
+            }
+        }
+
+        Ok(claimed)
+    }
+
+    /// Claims the blocks of the inode `ino` and returns its data blocks, without the holes.
+    fn claim_inode(&mut self, ino: usize, inode: &INode) -> Result<Vec<usize>, Error> {
+        let mut data = Vec::new();
+
+        // The data pointers of fast symbolic links and devices hold the target and the device
+        // number.
+        let has_blocks = match inode.file_type() {
+            FileType::Symlink => !inode.is_fast_symlink(),
+            FileType::File | FileType::Directory => true,
+            _ => false,
+        };
+
+        if !has_blocks {
+            return Ok(data);
+        }
+
+        let large_file = self.superblock.feature_ro_compat & FEATURE_RO_COMPAT_LARGE_FILE != 0;
+
+        if inode.file_type() == FileType::File && inode.size() > i32::MAX as usize && !large_file {
+            self.problem(format!(
+                "inode {ino}: larger than 2 GiB, but the filesystem does not support large files"
+            ));
+        }
+
+        let mut claimed = 0;
+
+        for (i, &block) in inode.data_ptr.iter().enumerate() {
+            let block = block as usize;
+            let depth = i.saturating_sub(INode::SINGLY_INDIRECT);
+
+            if block == 0 || !self.claim_block(ino, block) {
+                continue;
+            }
+
+            claimed += 1;
+
+            if i < INode::SINGLY_INDIRECT {
+                data.push(block);
+            } else {
+                claimed += self.claim_indirect(ino, block, depth, &mut data)?;
+            }
+        }
+
+        let sectors = claimed * (self.block_size / 512);
+        let block_count = inode.block_count as usize;
+
+        if block_count != sectors {
+            self.problem(format!(
+                "inode {ino}: block count is {block_count} sectors, should be {sectors}"
+            ));
+        }
+
+        Ok(data)
+    }
+
+    fn has_resize_inode(&self) -> bool {
+        self.superblock.feature_compat & FEATURE_COMPAT_RESIZE_INODE != 0
+    }
+
+    /// Claims the blocks of the resize inode. Its doubly indirect block lists the reserved BGDT
+    /// blocks of the first group, and each of those lists its backups in the other groups. The
+    /// reserved blocks are already accounted for as metadata.
+    fn claim_resize_inode(&mut self, inode: &INode) -> Result<(), Error> {
+        let ino = RESIZE_INODE;
+        let block = inode.data_ptr[INode::DOUBLY_INDIRECT] as usize;
+
+        if block == 0 {
+            if self.superblock.reserved_gdt_blocks != 0 {
+                self.problem(format!(
+                    "inode {ino}: the reserved BGDT blocks are not listed"
+                ));
+            }
+
+            return Ok(());
+        }
+
+        if !self.claim_block(ino, block) {
+            return Ok(());
+        }
+
+        let mut blocks = 1;
+
+        for entry in self.read_block(block)?.chunks(4) {
+            let reserved = u32::from_le_bytes(entry.try_into().unwrap()) as usize;
+
+            if reserved == 0 {
+                continue;
+            }
+
+            for copy in core::iter::once(reserved).chain(self.read_backups(reserved)?) {
+                if copy >= self.blocks_count || !self.metadata.get(copy) {
+                    self.problem(format!(
+                        "inode {ino}: block {copy} is not a reserved BGDT block"
+                    ));
+                }
+
+                blocks += 1;
+            }
+        }
+
+        let sectors = blocks * (self.block_size / 512);
+        let block_count = inode.block_count as usize;
+
+        if block_count != sectors {
+            self.problem(format!(
+                "inode {ino}: block count is {block_count} sectors, should be {sectors}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the backups listed in the reserved BGDT block `block`.
+    fn read_backups(&mut self, block: usize) -> Result<Vec<usize>, Error> {
+        if block >= self.blocks_count {
+            return Ok(Vec::new());
+        }
+
+        Ok(self
+            .read_block(block)?
+            .chunks(4)
+            .map(|entry| u32::from_le_bytes(entry.try_into().unwrap()) as usize)
+            .filter(|&backup| backup != 0)
+            .collect())
+    }
+
+    /// Walks the directory tree from the root and claims the blocks of every reachable inode.
+    fn walk(&mut self) -> Result<(), Error> {
+        let root = self.read_inode(ROOT_INODE)?;
+
+        if !root.is_used() || root.file_type() != FileType::Directory {
+            return Err(self.fatal("the root inode is not a directory"));
+        }
+
+        // The reserved inodes are always in use.
+        for ino in 1..self.first_inode {
+            self.used_inodes.set(ino);
+
+            let inode = self.read_inode(ino)?;
+
+            if ino == RESIZE_INODE && self.has_resize_inode() {
+                self.claim_resize_inode(&inode)?;
+            } else if ino != ROOT_INODE && inode.is_used() {
+                self.claim_inode(ino, &inode)?;
+            }
+        }
+
+        self.directories.set(ROOT_INODE);
+
+        // The directories to check, with their parents.
+        let mut stack = vec![(ROOT_INODE, root, ROOT_INODE)];
+
+        while let Some((ino, inode, parent)) = stack.pop() {
+            let blocks = self.claim_inode(ino, &inode)?;
+
+            let size = inode.size();
+
+            if size % self.block_size != 0 {
+                self.problem(format!(
+                    "directory {ino}: size {size} is not a multiple of the block size"
+                ));
+            } else if blocks.len() != size / self.block_size {
+                self.problem(format!(
+                    "directory {ino}: has {} blocks, but its size is {size} bytes",
+                    blocks.len()
+                ));
+            }
+
+            let mut index = 0;
+
+            for block in blocks {
+                let data = self.read_block(block)?;
+                let mut offset = 0;
+
+                while offset < data.len() {
+                    let entry = &data[offset..];
+                    let child = u32::from_le_bytes(entry[0..4].try_into().unwrap()) as usize;
+                    let size = u16::from_le_bytes(entry[4..6].try_into().unwrap()) as usize;
+                    let name_size = entry[6] as usize;
+
+                    if size < 8 || size & 3 != 0 || size > entry.len() || name_size + 8 > size {
+                        self.problem(format!(
+                            "directory {ino}: invalid entry at offset {offset} of block {block}"
+                        ));
+
+                        break;
+                    }
+
+                    let name = String::from_utf8_lossy(&entry[8..8 + name_size]).into_owned();
+                    offset += size;
+
+                    if child == 0 {
+                        continue;
+                    }
+
+                    let expected = match index {
+                        0 => Some((".", ino)),
+                        1 => Some(("..", parent)),
+                        _ => None,
+                    };
+
+                    index += 1;
+
+                    if let Some((expected_name, expected_child)) = expected {
+                        if name != expected_name || child != expected_child {
+                            self.problem(format!(
+                                "directory {ino}: entry `{name}` -> {child} should be \
+                                 `{expected_name}` -> {expected_child}"
+                            ));
+                        }
+                    } else if name == "." || name == ".." {
+                        self.problem(format!("directory {ino}: duplicate `{name}` entry"));
+                    }
+
+                    if child > self.inodes_count {
+                        self.problem(format!(
+                            "directory {ino}: entry `{name}` refers to invalid inode {child}"
+                        ));
+
+                        continue;
+                    }
+
+                    let child_inode = self.read_inode(child)?;
+
+                    if !child_inode.is_used() {
+                        self.problem(format!(
+                            "directory {ino}: entry `{name}` refers to unused inode {child}"
+                        ));
+
+                        continue;
+                    }
+
+                    self.links[child] += 1;
+
+                    if index <= 2 {
+                        continue;
+                    }
+
+                    if child_inode.file_type() == FileType::Directory {
+                        if self.directories.get(child) {
+                            self.problem(format!(
+                                "directory {child} is linked more than once (`{name}` in \
+                                 directory {ino})"
+                            ));
+
+                            continue;
+                        }
+
+                        self.directories.set(child);
+                        self.used_inodes.set(child);
+                        stack.push((child, child_inode, ino));
+                    } else if !self.used_inodes.get(child) {
+                        self.used_inodes.set(child);
+                        self.claim_inode(child, &child_inode)?;
+                    }
+                }
+            }
+
+            if index < 2 {
+                self.problem(format!("directory {ino}: missing `.` or `..` entry"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_link_counts(&mut self) -> Result<(), Error> {
+        // The link counts of the reserved inodes other than the root are not used.
+        let first_inode = self.first_inode;
+        let inodes =
+            (ROOT_INODE..=self.inodes_count).filter(|&ino| ino == ROOT_INODE || ino >= first_inode);
+
+        for ino in inodes {
+            if !self.used_inodes.get(ino) {
+                continue;
+            }
+
+            let links = self.links[ino] as usize;
+            let hl_count = self.read_inode(ino)?.hl_count as usize;
+
+            if links != hl_count {
+                self.problem(format!(
+                    "inode {ino}: link count is {hl_count}, should be {links}"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compares the bitmaps of each group with the blocks and inodes in use, and checks the free
+    /// counts.
+    fn check_bitmaps(&mut self) -> Result<(), Error> {
+        let sb = self.superblock;
+        let inodes_per_group = sb.inodes_per_group as usize;
+        let bits_per_block = 8 * self.block_size;
+
+        let mut free_blocks = 0;
+        let mut free_inodes = 0;
+
+        for group in 0..self.groups.len() {
+            let descriptor = self.groups[group];
+            let first = sb.group_first_block(group);
+            let blocks = sb.group_blocks(group);
+
+            let bitmap = self.read_block(descriptor.block_bitmap as usize)?;
+            let is_set = |bit: usize| bitmap[bit / 8] & (1 << (bit % 8)) != 0;
+            let mut free = 0;
+
+            for bit in 0..blocks {
+                let block = first + bit;
+                let used = self.metadata.get(block) || self.used_blocks.get(block);
+
+                if !is_set(bit) {
+                    free += 1;
+                }
+
+                match (is_set(bit), used) {
+                    (true, false) => {
+                        self.problem(format!("block {block} is marked in use but is free"))
+                    }
+                    (false, true) => {
+                        self.problem(format!("block {block} is in use but is marked free"))
+                    }
+                    _ => {}
+                }
+            }
+
+            if (blocks..bits_per_block).any(|bit| !is_set(bit)) {
+                self.problem(format!(
+                    "group {group}: padding at the end of the block bitmap is not set"
+                ));
+            }
+
+            let free_blocks_count = descriptor.free_blocks_count as usize;
+
+            if free_blocks_count != free {
+                self.problem(format!(
+                    "group {group}: free blocks count is {free_blocks_count}, should be {free}"
+                ));
+            }
+
+            free_blocks += free;
+
+            let bitmap = self.read_block(descriptor.inode_bitmap as usize)?;
+            let is_set = |bit: usize| bitmap[bit / 8] & (1 << (bit % 8)) != 0;
+            let mut free = 0;
+            let mut dirs = 0;
+
+            for bit in 0..inodes_per_group {
+                let ino = group * inodes_per_group + bit + 1;
+                let used = self.used_inodes.get(ino);
+
+                if !is_set(bit) {
+                    free += 1;
+                }
+
+                if self.directories.get(ino) {
+                    dirs += 1;
+                }
+
+                match (is_set(bit), used) {
+                    (true, false) if self.read_inode(ino)?.is_used() => self.problem(format!(
+                        "inode {ino} is in use but no directory refers to it"
+                    )),
+                    (true, false) => {
+                        self.problem(format!("inode {ino} is marked in use but is free"))
+                    }
+                    (false, true) => {
+                        self.problem(format!("inode {ino} is in use but is marked free"))
+                    }
+                    _ => {}
+                }
+            }
+
+            if (inodes_per_group..bits_per_block).any(|bit| !is_set(bit)) {
+                self.problem(format!(
+                    "group {group}: padding at the end of the inode bitmap is not set"
+                ));
+            }
+
+            let free_inodes_count = descriptor.free_inodes_count as usize;
+            let used_dirs_count = descriptor.used_dirs_count as usize;
+
+            if free_inodes_count != free {
+                self.problem(format!(
+                    "group {group}: free inodes count is {free_inodes_count}, should be {free}"
+                ));
+            }
+
+            if used_dirs_count != dirs {
+                self.problem(format!(
+                    "group {group}: directories count is {used_dirs_count}, should be {dirs}"
+                ));
+            }
+
+            free_inodes += free;
+        }
+
+        let free_blocks_count = sb.free_blocks_count as usize;
+        let free_inodes_count = sb.free_inodes_count as usize;
+
+        if free_blocks_count != free_blocks {
+            self.problem(format!(
+                "free blocks count is {free_blocks_count}, should be {free_blocks}"
+            ));
+        }
+
+        if free_inodes_count != free_inodes {
+            self.problem(format!(
+                "free inodes count is {free_inodes_count}, should be {free_inodes}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check(&mut self) -> Result<(), Error> {
+        self.report(Self::walk)?;
+        self.report(Self::check_link_counts)?;
+        self.report(Self::check_bitmaps)
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let [path] = args.as_slice() else {
+        eprintln!("usage: fsck.ext2 <device>");
+        process::exit(EXIT_USAGE);
+    };
+
+    let result = Fsck::open(path).and_then(|mut fsck| {
+        fsck.check()?;
+        Ok(fsck)
+    });
+
+    let fsck = match result {
+        Ok(fsck) => fsck,
+        Err(Error::Io(err)) => {
+            eprintln!("fsck.ext2: {path}: {err}");
+            process::exit(EXIT_ERROR);
+        }
+
+        Err(Error::Unsupported(err)) => {
+            eprintln!("fsck.ext2: {path}: {err}");
+            process::exit(EXIT_ERROR);
+        }
+
+        Err(Error::Fatal) => {
+            println!("{path}: the filesystem is too damaged to be checked further");
+            process::exit(EXIT_UNCORRECTED);
+        }
+    };
+
+    let sb = fsck.superblock;
+    let (blocks_count, free_blocks_count) = (sb.blocks_count, sb.free_blocks_count);
+    let (inodes_count, free_inodes_count) = (sb.inodes_count, sb.free_inodes_count);
+
+    if fsck.problems == 0 {
+        println!(
+            "{path}: clean, {}/{inodes_count} files, {}/{blocks_count} blocks",
+            inodes_count - free_inodes_count,
+            blocks_count - free_blocks_count,
+        );
+
+        process::exit(EXIT_CLEAN);
+    }
+
+    let problems = fsck.problems;
+    let plural = if problems == 1 { "" } else { "s" };
+
+    println!("{path}: {problems} problem{plural} found");
+    process::exit(EXIT_UNCORRECTED);
+}
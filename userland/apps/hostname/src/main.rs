@@ -0,0 +1,36 @@
+use std::env;
+use std::fs;
+
+/// File that init reads the host name from on boot.
+const HOSTNAME_PATH: &str = "/etc/hostname";
+
+fn main() {
+    let mut args = env::args().skip(1);
+
+    let result = match (args.next(), args.next()) {
+        (None, _) => print_hostname(),
+        (Some(name), None) => set_hostname(&name),
+        _ => Err(String::from("usage: hostname [NAME]")),
+    };
+
+    if let Err(err) = result {
+        eprintln!("hostname: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn print_hostname() -> Result<(), String> {
+    let mut buffer = [0; 65];
+    let name = aero_syscall::sys_gethostname(&mut buffer).map_err(|err| err.to_string())?;
+
+    println!("{name}");
+    Ok(())
+}
+
+/// Sets the host name and persists it so that it survives a reboot.
+fn set_hostname(name: &str) -> Result<(), String> {
+    aero_syscall::sys_sethostname(name).map_err(|err| format!("{name}: {err}"))?;
+
+    fs::write(HOSTNAME_PATH, format!("{name}\n"))
+        .map_err(|err| format!("failed to write {HOSTNAME_PATH}: {err}"))
+}
@@ -18,60 +18,51 @@
 use std::error::Error;
 use std::fs::{File, OpenOptions};
 use std::os::fd::AsRawFd;
-use std::process::Command;
+
+use aero_syscall::process::{self, SpawnOptions};
 
 const TTY_PATH: &str = "/dev/vtty";
 const DEV_NULL: &str = "/dev/null";
 
-struct FileSet<const N: usize>([File; N]);
+/// Spawns `path` with `stdio` as its standard input, output and error and the environment of
+/// init with `extra_env` added.
+fn spawn(path: &str, stdio: &[File; 3], extra_env: &[&str]) -> Result<usize, Box<dyn Error>> {
+    let env = std::env::vars()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>();
 
-impl<const N: usize> FileSet<N> {
-    fn new(files: [File; N]) -> Self {
-        Self(files)
-    }
+    let mut envv = env.iter().map(String::as_str).collect::<Vec<_>>();
+    envv.extend_from_slice(extra_env);
 
-    fn remove_cloexec(&self) {
-        for file in self.0.iter() {
-            // By default rust automatically sets the close-on-exe flag to prevent
-            // leaking file descriptors.
-            //
-            // OpenOptions::custom_flags() only allows insertion of flags and are
-            // overwritten by the flags set by the standard library. So here, we
-            // need to manually update the flags after opening the file.
-            let fd = file.as_raw_fd();
+    let [stdin, stdout, stderr] = stdio;
+    let options = SpawnOptions {
+        stdin: Some(stdin.as_raw_fd() as usize),
+        stdout: Some(stdout.as_raw_fd() as usize),
+        stderr: Some(stderr.as_raw_fd() as usize),
+        ..Default::default()
+    };
 
-            unsafe {
-                assert!(libc::fcntl(fd, libc::F_SETFD, 0 /* flags */) == 0);
-            }
-        }
-    }
+    process::spawn(path, &[path], &envv, &options)
+        .map_err(|err| format!("failed to spawn {path}: {err}").into())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // Open the stdin, stdout and stderr file descriptors.
-    let stdin = OpenOptions::new().read(true).open(TTY_PATH)?; // fd=0
-    let stdout = OpenOptions::new().write(true).open(TTY_PATH)?; // fd=1
-    let stderr = OpenOptions::new().write(true).open(TTY_PATH)?; // fd=2
-
-    {
-        let stdset = FileSet::new([stdin, stdout, stderr]);
-        stdset.remove_cloexec();
-
-        Command::new("dhcpd").spawn()?;
-    }
+    let tty = [
+        OpenOptions::new().read(true).open(TTY_PATH)?,
+        OpenOptions::new().write(true).open(TTY_PATH)?,
+        OpenOptions::new().write(true).open(TTY_PATH)?,
+    ];
 
-    // Swap the `/dev/tty` std{in,out,err} file descriptors with `/dev/null` to suppress the Xorg
-    // server logs.
-    let stdin = OpenOptions::new().read(true).open(DEV_NULL)?; // fd=0
-    let stdout = OpenOptions::new().write(true).open(DEV_NULL)?; // fd=1
-    let stderr = OpenOptions::new().write(true).open(DEV_NULL)?; // fd=2
+    spawn("/usr/bin/dhcpd", &tty, &[])?;
 
-    let stdset = FileSet::new([stdin, stdout, stderr]);
-    stdset.remove_cloexec();
+    // Use `/dev/null` as the std{in,out,err} of the Xorg server to suppress its logs.
+    let null = [
+        OpenOptions::new().read(true).open(DEV_NULL)?,
+        OpenOptions::new().write(true).open(DEV_NULL)?,
+        OpenOptions::new().write(true).open(DEV_NULL)?,
+    ];
 
-    Command::new("startx")
-        .env("RUST_BACKTRACE", "full")
-        .spawn()?;
+    spawn("/usr/bin/startx", &null, &["RUST_BACKTRACE=full"])?;
 
     Ok(())
 }
@@ -0,0 +1,209 @@
+use std::{env, fs, io};
+
+use aero_syscall::OpenFlags;
+
+/// An open file of a process, as described by `/proc/<pid>/fdinfo/<fd>`.
+struct OpenFile {
+    fd: usize,
+    pos: usize,
+    flags: OpenFlags,
+    path: String,
+    /// The lines after the common ones, which depend on the kind of file.
+    extra: Vec<(String, String)>,
+}
+
+impl OpenFile {
+    fn parse(fd: usize, fdinfo: &str) -> Result<Self, String> {
+        let mut fields = fdinfo
+            .lines()
+            .map(|line| {
+                let (key, value) = line
+                    .split_once(':')
+                    .ok_or_else(|| format!("malformed line `{line}`"))?;
+
+                Ok((key.to_string(), value.trim().to_string()))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut take = |key: &str| {
+            let index = fields
+                .iter()
+                .position(|(name, _)| name == key)
+                .ok_or_else(|| format!("missing `{key}`"))?;
+
+            Ok::<_, String>(fields.remove(index).1)
+        };
+
+        let pos = take("pos")?;
+        let flags = take("flags")?;
+        take("ino")?;
+        let path = take("path")?;
+
+        let pos = pos.parse().map_err(|_| format!("invalid pos `{pos}`"))?;
+        let flags = usize::from_str_radix(&flags, 8)
+            .map(OpenFlags::from_bits_truncate)
+            .map_err(|_| format!("invalid flags `{flags}`"))?;
+
+        Ok(Self {
+            fd,
+            pos,
+            flags,
+            path,
+            extra: fields,
+        })
+    }
+
+    fn extra(&self, key: &str) -> Option<&str> {
+        self.extra
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the access mode in the notation of lsof(8).
+    fn mode(&self) -> char {
+        match self.flags & OpenFlags::O_ACCMODE {
+            OpenFlags::O_RDONLY => 'r',
+            OpenFlags::O_WRONLY => 'w',
+            OpenFlags::O_RDWR => 'u',
+            _ => ' ',
+        }
+    }
+
+    fn kind(&self) -> &str {
+        if self.extra("pipe_size").is_some() {
+            "FIFO"
+        } else if let Some(kind) = self.extra("type") {
+            kind.split_whitespace().next().unwrap_or("sock")
+        } else {
+            "REG"
+        }
+    }
+
+    fn name(&self) -> String {
+        if let Some(size) = self.extra("pipe_size") {
+            return format!(
+                "{} ({size} bytes, {} readers, {} writers)",
+                self.path,
+                self.extra("pipe_readers").unwrap_or("?"),
+                self.extra("pipe_writers").unwrap_or("?"),
+            );
+        }
+
+        if let Some(state) = self.extra("state") {
+            let local = self.extra("local").unwrap_or("?");
+
+            return match self.extra("peer") {
+                Some(peer) => format!("{local} -> {peer} ({state})"),
+                None => format!("{local} ({state})"),
+            };
+        }
+
+        self.path.clone()
+    }
+}
+
+/// Returns the numeric entries of `dir`, sorted.
+fn numeric_entries(dir: &str) -> io::Result<Vec<usize>> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect::<Vec<_>>();
+
+    entries.sort_unstable();
+    Ok(entries)
+}
+
+fn process_name(pid: usize) -> String {
+    fs::read_to_string(format!("/proc/{pid}/status"))
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Name:"))
+                .map(|name| name.trim().to_string())
+        })
+        .unwrap_or_else(|| String::from("?"))
+}
+
+fn list_files(pid: usize) -> Result<Vec<OpenFile>, String> {
+    // The process may have exited since `/proc` was read.
+    let fds = match numeric_entries(&format!("/proc/{pid}/fd")) {
+        Ok(fds) => fds,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(format!("/proc/{pid}/fd: {err}")),
+    };
+
+    let mut files = vec![];
+
+    for fd in fds {
+        let path = format!("/proc/{pid}/fdinfo/{fd}");
+
+        // The file may have been closed since the directory was read.
+        let fdinfo = match fs::read_to_string(&path) {
+            Ok(fdinfo) => fdinfo,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(format!("{path}: {err}")),
+        };
+
+        files.push(OpenFile::parse(fd, &fdinfo).map_err(|err| format!("{path}: {err}"))?);
+    }
+
+    Ok(files)
+}
+
+fn main() {
+    let pids = env::args()
+        .skip(1)
+        .map(|arg| arg.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|_| {
+            eprintln!("usage: lsof [PID...]");
+            std::process::exit(1);
+        });
+
+    let pids = if pids.is_empty() {
+        numeric_entries("/proc").unwrap_or_else(|err| {
+            eprintln!("lsof: /proc: {err}");
+            std::process::exit(1);
+        })
+    } else {
+        pids
+    };
+
+    let mut failed = false;
+
+    println!(
+        "{:<16} {:>6} {:>4} {:<4} {:>10}  NAME",
+        "COMMAND", "PID", "FD", "TYPE", "OFFSET"
+    );
+
+    for pid in pids {
+        let files = match list_files(pid) {
+            Ok(files) => files,
+            Err(err) => {
+                eprintln!("lsof: {err}");
+                failed = true;
+                continue;
+            }
+        };
+
+        let name = process_name(pid);
+
+        for file in files {
+            println!(
+                "{:<16} {:>6} {:>3}{} {:<4} {:>10}  {}",
+                name,
+                pid,
+                file.fd,
+                file.mode(),
+                file.kind(),
+                file.pos,
+                file.name()
+            );
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,497 @@
+//! Creates a revision 1 ext2 filesystem on a block device or a file.
+//!
+//! Every block group holds its block and inode bitmaps and its inode table, and the groups
+//! selected by the sparse superblock rule also start with a copy of the superblock and of the
+//! block group descriptor table. Only the root directory and `lost+found` are created.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{env, process};
+
+use aero_ext2::{
+    DirEntry, FileType, GroupDescriptor, INode, INodeExtra, OnDisk, SuperBlock, ROOT_INODE,
+    SUPERBLOCK_OFFSET,
+};
+
+const INODE_SIZE: usize = 256;
+
+/// One inode is created for every `BYTES_PER_INODE` bytes of the filesystem.
+const BYTES_PER_INODE: u64 = 8192;
+
+/// `lost+found` takes the first inode that is not reserved.
+const FIRST_INODE: usize = 11;
+
+/// Percentage of the blocks that are reserved for the super user.
+const RESERVED_PERCENT: u64 = 5;
+
+/// A block group with fewer blocks than this left after its metadata is not created.
+const MIN_GROUP_DATA_BLOCKS: usize = 50;
+
+struct Options {
+    path: String,
+    size: Option<u64>,
+    block_size: Option<usize>,
+}
+
+impl Options {
+    fn parse() -> Option<Self> {
+        let mut args = env::args().skip(1);
+        let mut positional = Vec::new();
+        let mut block_size = None;
+
+        while let Some(arg) = args.next() {
+            if arg == "-b" {
+                let size = args.next()?.parse().ok()?;
+
+                if ![1024, 2048, 4096].contains(&size) {
+                    return None;
+                }
+
+                block_size = Some(size);
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        let (path, size) = match positional.as_slice() {
+            [path] => (path.clone(), None),
+            [path, size] => (path.clone(), Some(parse_size(size)?)),
+            _ => return None,
+        };
+
+        Some(Self {
+            path,
+            size,
+            block_size,
+        })
+    }
+}
+
+/// Parses a size in bytes, with an optional `K`, `M` or `G` suffix.
+fn parse_size(arg: &str) -> Option<u64> {
+    let (digits, shift) = match arg.chars().last()?.to_ascii_uppercase() {
+        'K' => (&arg[..arg.len() - 1], 10),
+        'M' => (&arg[..arg.len() - 1], 20),
+        'G' => (&arg[..arg.len() - 1], 30),
+        _ => (arg, 0),
+    };
+
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// Where the metadata of a block group is.
+struct Group {
+    first_block: usize,
+    blocks: usize,
+    descriptor: GroupDescriptor,
+    /// Number of blocks used at the start of the group.
+    used_blocks: usize,
+}
+
+struct Layout {
+    superblock: SuperBlock,
+    groups: Vec<Group>,
+}
+
+impl Layout {
+    fn new(size: u64, block_size: usize, now: u32, uuid: [u64; 2]) -> Result<Self, String> {
+        let blocks_per_group = 8 * block_size;
+        let first_data_block = (block_size == 1024) as usize;
+        let mut blocks_count = (size / block_size as u64).min(u32::MAX as u64) as usize;
+
+        if blocks_count <= first_data_block {
+            return Err("the device is too small".into());
+        }
+
+        let mut superblock = SuperBlock::from_bytes(&[0; core::mem::size_of::<SuperBlock>()]);
+
+        superblock.blocks_count = blocks_count as u32;
+        superblock.first_data_block = first_data_block as u32;
+        superblock.log_block_size = block_size.trailing_zeros() - 10;
+        superblock.log_frag_size = superblock.log_block_size;
+        superblock.blocks_per_group = blocks_per_group as u32;
+        superblock.frags_per_group = blocks_per_group as u32;
+        superblock.feature_incompat = SuperBlock::FEATURE_INCOMPAT_FILETYPE;
+        superblock.feature_ro_compat = SuperBlock::FEATURE_RO_COMPAT_SPARSE_SUPER;
+
+        let inodes_per_block = block_size / INODE_SIZE;
+        let mut groups = superblock.bgdt_len();
+
+        // Drop the last group if it is too small to be useful.
+        let last_blocks = superblock.group_blocks(groups - 1);
+        let table_blocks = inodes_per_group(size, groups, block_size) / inodes_per_block;
+
+        if last_blocks
+            < metadata_blocks(&superblock, groups - 1, table_blocks) + MIN_GROUP_DATA_BLOCKS
+        {
+            if groups == 1 {
+                return Err("the device is too small".into());
+            }
+
+            blocks_count -= last_blocks;
+            groups -= 1;
+            superblock.blocks_count = blocks_count as u32;
+        }
+
+        let inodes_per_group = inodes_per_group(size, groups, block_size);
+        let table_blocks = inodes_per_group / inodes_per_block;
+
+        superblock.inodes_per_group = inodes_per_group as u32;
+        superblock.inodes_count = (inodes_per_group * groups) as u32;
+        superblock.r_blocks_count = (blocks_count as u64 * RESERVED_PERCENT / 100) as u32;
+        superblock.wtime = now;
+        superblock.max_mnt_count = u16::MAX;
+        superblock.magic = SuperBlock::MAGIC;
+        superblock.state = SuperBlock::STATE_VALID;
+        superblock.errors = SuperBlock::ERRORS_CONTINUE;
+        superblock.lastcheck = now;
+        superblock.rev_level = 1;
+        superblock.first_ino = FIRST_INODE as u32;
+        superblock.inode_size = INODE_SIZE as u16;
+        superblock.uuid = uuid;
+        superblock.mkfs_time = now;
+
+        let groups = (0..groups)
+            .map(|group| {
+                let first_block = superblock.group_first_block(group);
+                let blocks = superblock.group_blocks(group);
+                let bitmaps = first_block + metadata_blocks(&superblock, group, 0) - 2;
+
+                Group {
+                    first_block,
+                    blocks,
+                    descriptor: GroupDescriptor {
+                        block_bitmap: bitmaps as u32,
+                        inode_bitmap: bitmaps as u32 + 1,
+                        inode_table: bitmaps as u32 + 2,
+                        free_blocks_count: 0,
+                        free_inodes_count: inodes_per_group as u16,
+                        used_dirs_count: 0,
+                        pad: 0,
+                        reserved: [0; 12],
+                    },
+                    used_blocks: metadata_blocks(&superblock, group, table_blocks),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if groups[0].blocks < groups[0].used_blocks + MIN_GROUP_DATA_BLOCKS {
+            return Err("the device is too small".into());
+        }
+
+        Ok(Self { superblock, groups })
+    }
+
+    fn block_size(&self) -> usize {
+        self.superblock.block_size()
+    }
+
+    /// Allocates a block in the first group, for the directories created by `mkfs`.
+    fn allocate_block(&mut self) -> usize {
+        let group = &mut self.groups[0];
+        group.used_blocks += 1;
+        group.first_block + group.used_blocks - 1
+    }
+
+    /// Updates the free counts, once all of the blocks and inodes are allocated.
+    fn finish(&mut self, used_inodes: usize, dirs: usize) {
+        for group in self.groups.iter_mut() {
+            group.descriptor.free_blocks_count = (group.blocks - group.used_blocks) as u16;
+        }
+
+        let first = &mut self.groups[0].descriptor;
+        first.free_inodes_count -= used_inodes as u16;
+        first.used_dirs_count = dirs as u16;
+
+        self.superblock.free_blocks_count = self
+            .groups
+            .iter()
+            .map(|group| group.descriptor.free_blocks_count as u32)
+            .sum();
+
+        self.superblock.free_inodes_count = self.superblock.inodes_count - used_inodes as u32;
+    }
+}
+
+/// Returns the number of blocks at the start of the block group `group`, which hold the copy of
+/// the superblock and of the BGDT, the bitmaps and the `table_blocks` blocks of the inode table.
+fn metadata_blocks(superblock: &SuperBlock, group: usize, table_blocks: usize) -> usize {
+    let copy = if superblock.has_superblock(group) {
+        1 + superblock.bgdt_blocks()
+    } else {
+        0
+    };
+
+    copy + 2 + table_blocks
+}
+
+/// Returns the number of inodes per group, which fills whole inode table blocks and is a
+/// multiple of 8 so the inode bitmap is made of whole bytes.
+fn inodes_per_group(size: u64, groups: usize, block_size: usize) -> usize {
+    let inodes_per_block = block_size / INODE_SIZE;
+    let inodes = (size / BYTES_PER_INODE) as usize;
+
+    inodes
+        .div_ceil(groups)
+        .max(FIRST_INODE + 5)
+        .next_multiple_of(inodes_per_block.max(8))
+        .min(8 * block_size)
+}
+
+fn directory_inode(
+    permissions: u16,
+    links: u16,
+    block: usize,
+    block_size: usize,
+    now: u32,
+) -> INode {
+    let mut inode = INode::default();
+
+    inode.set_file_type(FileType::Directory);
+    inode.set_permissions(permissions);
+    inode.set_size(block_size);
+    inode.set_last_access(Duration::from_secs(now as u64));
+    inode.set_last_modification(Duration::from_secs(now as u64));
+    inode.creation_time = now;
+    inode.hl_count = links;
+    inode.block_count = (block_size / 512) as u32;
+    inode.data_ptr[0] = block as u32;
+    inode
+}
+
+/// Returns a directory block with the given `(inode, name)` entries, which are all directories.
+/// The last entry spans the rest of the block.
+fn directory_block(block_size: usize, entries: &[(usize, &str)]) -> Vec<u8> {
+    let mut block = vec![0; block_size];
+    let mut offset = 0;
+
+    for (i, &(inode, name)) in entries.iter().enumerate() {
+        let size = if i == entries.len() - 1 {
+            block_size - offset
+        } else {
+            DirEntry::record_size(name.len())
+        };
+
+        let entry = &mut block[offset..offset + size];
+
+        entry[0..4].copy_from_slice(&(inode as u32).to_le_bytes());
+        entry[4..6].copy_from_slice(&(size as u16).to_le_bytes());
+        entry[6] = name.len() as u8;
+        entry[7] = FileType::Directory.dirent_type();
+        entry[8..8 + name.len()].copy_from_slice(name.as_bytes());
+
+        offset += size;
+    }
+
+    block
+}
+
+/// Returns a bitmap block with the first `used` bits set, as well as the bits past `len`.
+fn bitmap_block(block_size: usize, used: usize, len: usize) -> Vec<u8> {
+    let mut block = vec![0; block_size];
+
+    for bit in (0..used).chain(len..8 * block_size) {
+        block[bit / 8] |= 1 << (bit % 8);
+    }
+
+    block
+}
+
+fn write_at(file: &mut File, offset: u64, data: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(data)
+}
+
+fn generate_uuid() -> [u64; 2] {
+    let mut bytes = [0; 16];
+
+    if File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .is_err()
+    {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        bytes[..8].copy_from_slice(&now.as_nanos().to_le_bytes()[..8]);
+        bytes[8..12].copy_from_slice(&process::id().to_le_bytes());
+    }
+
+    // Version 4 (random), variant 1.
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    [
+        u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+        u64::from_le_bytes(bytes[8..].try_into().unwrap()),
+    ]
+}
+
+fn mkfs(options: &Options) -> Result<(), String> {
+    let path = &options.path;
+    let error = |err: io::Error| format!("{path}: {err}");
+
+    // An image file is created if its size is known.
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(options.size.is_some())
+        .truncate(false)
+        .open(path)
+        .map_err(error)?;
+
+    let metadata = file.metadata().map_err(error)?;
+
+    let size = match options.size {
+        Some(size) => {
+            // A file is grown to the size of the filesystem.
+            if metadata.is_file() && metadata.len() < size {
+                file.set_len(size).map_err(error)?;
+            }
+
+            size
+        }
+
+        None if metadata.len() > 0 => metadata.len(),
+        None => return Err(format!("{path}: unknown size, pass it as an argument")),
+    };
+
+    // Small filesystems use small blocks, so less space is wasted.
+    let block_size = options
+        .block_size
+        .unwrap_or(if size < 512 << 20 { 1024 } else { 4096 });
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as u32)
+        .unwrap_or_default();
+
+    let mut layout = Layout::new(size, block_size, now, generate_uuid())?;
+
+    let SuperBlock {
+        blocks_count,
+        inodes_count,
+        ..
+    } = layout.superblock;
+
+    println!(
+        "Creating filesystem with {blocks_count} {}k blocks and {inodes_count} inodes in {} block \
+         groups",
+        block_size / 1024,
+        layout.groups.len()
+    );
+
+    let root_block = layout.allocate_block();
+    let lost_found_block = layout.allocate_block();
+
+    // The root directory, `lost+found` and the reserved inodes before it.
+    layout.finish(FIRST_INODE, 2);
+
+    let inode_table_size = layout.superblock.inodes_per_group as usize * INODE_SIZE;
+    let zeroes = vec![0; inode_table_size.min(1 << 20)];
+    let block_size = block_size as u64;
+
+    for (i, group) in layout.groups.iter().enumerate() {
+        let descriptor = &group.descriptor;
+        let inode_bitmap_used = if i == 0 { FIRST_INODE } else { 0 };
+        let inodes_per_group = layout.superblock.inodes_per_group as usize;
+
+        let block_bitmap = bitmap_block(layout.block_size(), group.used_blocks, group.blocks);
+        let inode_bitmap = bitmap_block(layout.block_size(), inode_bitmap_used, inodes_per_group);
+
+        write_at(
+            &mut file,
+            descriptor.block_bitmap as u64 * block_size,
+            &block_bitmap,
+        )
+        .map_err(error)?;
+        write_at(
+            &mut file,
+            descriptor.inode_bitmap as u64 * block_size,
+            &inode_bitmap,
+        )
+        .map_err(error)?;
+
+        let table = descriptor.inode_table as u64 * block_size;
+
+        for offset in (0..inode_table_size).step_by(zeroes.len()) {
+            let size = zeroes.len().min(inode_table_size - offset);
+            write_at(&mut file, table + offset as u64, &zeroes[..size]).map_err(error)?;
+        }
+    }
+
+    let directories = [
+        (ROOT_INODE, 0o755, 3, root_block, ROOT_INODE),
+        (FIRST_INODE, 0o700, 2, lost_found_block, ROOT_INODE),
+    ];
+
+    for (inode, permissions, links, block, parent) in directories {
+        let mut entries = vec![(inode, "."), (parent, "..")];
+
+        if inode == ROOT_INODE {
+            entries.push((FIRST_INODE, "lost+found"));
+        }
+
+        let data = directory_block(layout.block_size(), &entries);
+        write_at(&mut file, block as u64 * block_size, &data).map_err(error)?;
+
+        let mut bytes = directory_inode(permissions, links, block, layout.block_size(), now)
+            .as_bytes()
+            .to_vec();
+
+        bytes.extend_from_slice(INodeExtra::new(Duration::from_secs(now as u64)).as_bytes());
+
+        let table = layout.groups[0].descriptor.inode_table as u64 * block_size;
+        write_at(&mut file, table + ((inode - 1) * INODE_SIZE) as u64, &bytes).map_err(error)?;
+    }
+
+    // The superblock is written last, so the device is only recognized as ext2 once everything
+    // else is in place.
+    let bgdt = layout
+        .groups
+        .iter()
+        .flat_map(|group| group.descriptor.as_bytes().to_vec())
+        .collect::<Vec<_>>();
+
+    for (i, group) in layout.groups.iter().enumerate().rev() {
+        if !layout.superblock.has_superblock(i) {
+            continue;
+        }
+
+        let mut superblock = layout.superblock;
+        superblock.block_group_nr = i as u16;
+
+        let mut bytes = superblock.as_bytes().to_vec();
+        bytes.resize(1024, 0);
+
+        // The primary superblock is always 1024 bytes from the start of the device, and the
+        // BGDT is in the block after it.
+        let (superblock_offset, bgdt_offset) = if i == 0 {
+            (
+                SUPERBLOCK_OFFSET as u64,
+                layout.superblock.bgdt_block() as u64,
+            )
+        } else {
+            let start = group.first_block as u64 * block_size;
+            (start, start + block_size)
+        };
+
+        write_at(&mut file, bgdt_offset, &bgdt).map_err(error)?;
+        write_at(&mut file, superblock_offset, &bytes).map_err(error)?;
+    }
+
+    file.sync_all().map_err(error)
+}
+
+fn main() {
+    let Some(options) = Options::parse() else {
+        eprintln!("usage: mkfs.ext2 [-b 1024|2048|4096] <device> [size[K|M|G]]");
+        process::exit(1);
+    };
+
+    if let Err(err) = mkfs(&options) {
+        eprintln!("mkfs.ext2: {err}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![feature(prelude_import)]
+#![allow(internal_features)]
+
+#[prelude_import]
+use aero_std::prelude::rust_2021::*;
+
+use aero_std::process;
+use aero_syscall::process::CpuSet;
+
+fn main() {
+    // `--all` counts the installed CPUs instead of the available ones. They are the same until
+    // the affinity of a process can be restricted.
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    if args.iter().any(|arg| arg != "--all") {
+        eprintln!("usage: nproc [--all]");
+        process::exit(1);
+    }
+
+    let mut set = CpuSet::new();
+
+    if let Err(err) = aero_syscall::process::sys_sched_getaffinity(0, &mut set) {
+        eprintln!("nproc: failed to get the CPU affinity: {err}");
+        process::exit(1);
+    }
+
+    println!("{}", set.count());
+}
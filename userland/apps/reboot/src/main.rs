@@ -0,0 +1,19 @@
+use std::env;
+use std::path::Path;
+
+use aero_syscall::RebootCmd;
+
+fn main() {
+    // Installed as both `reboot` and `halt`, so the command depends on the name we are run as.
+    let name = env::args().next().unwrap_or_default();
+    let name = Path::new(&name).file_name().and_then(|name| name.to_str());
+
+    let cmd = match name {
+        Some("halt") => RebootCmd::Halt,
+        _ => RebootCmd::Restart,
+    };
+
+    let err = aero_syscall::sys_reboot(cmd).unwrap_err();
+    eprintln!("reboot: {cmd:?} failed: {err}");
+    std::process::exit(1);
+}
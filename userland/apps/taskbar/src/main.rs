@@ -0,0 +1,146 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! A bar along the bottom edge of the screen with one button per window, which focuses the
+//! window when clicked.
+//!
+//! The window server cannot show the contents of windows yet, so the bar is printed as a line of
+//! text whenever it changes. The buttons split the width of the bar evenly.
+
+use aero_ipc::spsc::SharedSpsc;
+use aero_ipc::{InputEvent, WindowInfo, WindowService};
+use aero_syscall::SyscallError;
+
+use std::time::Duration;
+
+/// Height of the bar, in pixels.
+const HEIGHT: u32 = 24;
+
+const LEFT_BUTTON: u32 = 1;
+
+/// How long to wait for the window server to be started.
+const DISCOVER_TIMEOUT: Duration = Duration::from_secs(10);
+const DISCOVER_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Taskbar {
+    window: usize,
+    width: u32,
+    /// The windows shown in the bar, in the order they were created.
+    entries: Vec<WindowInfo>,
+}
+
+impl Taskbar {
+    /// Updates the bar from the window list, redrawing it if it has changed.
+    fn update(&mut self, windows: &[WindowInfo]) {
+        let mut entries = windows
+            .iter()
+            .filter(|info| info.id != self.window)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // The window server lists the windows in stacking order, which changes every time the
+        // focus does. The buttons should stay in place.
+        entries.sort_unstable_by_key(|info| info.id);
+
+        if entries != self.entries {
+            self.entries = entries;
+            self.draw();
+        }
+    }
+
+    fn draw(&self) {
+        let buttons = self
+            .entries
+            .iter()
+            .map(|info| {
+                if info.focused {
+                    format!("[{}]", info.name)
+                } else {
+                    info.name.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        println!("[taskbar] | {} |", buttons.join(" | "));
+    }
+
+    /// Returns the window whose button is at `x`.
+    fn entry_at(&self, x: i32) -> Option<&WindowInfo> {
+        let width = self.width / self.entries.len().max(1) as u32;
+
+        self.entries
+            .get(usize::try_from(x).ok()? / width.max(1) as usize)
+    }
+}
+
+fn main() -> Result<(), SyscallError> {
+    let window_server = WindowService::discover(DISCOVER_TIMEOUT, DISCOVER_INTERVAL)?;
+    let window = window_server.create_window("Taskbar");
+
+    let (width, height) = window_server.screen_size();
+    window_server.move_window(
+        window,
+        0,
+        height.saturating_sub(HEIGHT) as i32,
+        width,
+        HEIGHT,
+    );
+
+    let fd = window_server
+        .input_queue(window)
+        .ok_or(SyscallError::ENOMEM)?;
+    let mut input = SharedSpsc::<InputEvent>::open(window_server.pid(), fd)?;
+
+    let mut taskbar = Taskbar {
+        window,
+        width,
+        entries: vec![],
+    };
+
+    taskbar.update(&window_server.list_windows());
+
+    let mut buttons = 0;
+
+    loop {
+        let Some(event) = input.pop() else {
+            std::thread::yield_now();
+            continue;
+        };
+
+        match event.kind {
+            InputEvent::WINDOWS_CHANGED => taskbar.update(&window_server.list_windows()),
+
+            InputEvent::MOUSE => {
+                let pressed = event.code & !buttons;
+                buttons = event.code;
+
+                if pressed & LEFT_BUTTON != 0 {
+                    if let Some(info) = taskbar.entry_at(event.x) {
+                        window_server.focus_window(info.id);
+                    }
+                }
+            }
+
+            InputEvent::CLOSE => {
+                window_server.destroy_window(window);
+                return Ok(());
+            }
+
+            _ => {}
+        }
+    }
+}
@@ -15,22 +15,38 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_ipc::{SystemService, WindowService};
-use aero_syscall::{sys_ipc_discover_root, SyscallError};
+use aero_ipc::spsc::SharedSpsc;
+use aero_ipc::{InputEvent, WindowService};
+use aero_syscall::SyscallError;
 
-fn discover_service(name: &str) -> Result<usize, SyscallError> {
-    let root_pid = sys_ipc_discover_root()?;
-    let system = SystemService::open(root_pid);
+use std::time::Duration;
 
-    system.discover(name).map_err(|_| SyscallError::ENOMSG)
-}
+/// How long to wait for the window server to be started.
+const DISCOVER_TIMEOUT: Duration = Duration::from_secs(10);
+const DISCOVER_INTERVAL: Duration = Duration::from_secs(1);
 
 fn main() -> Result<(), SyscallError> {
-    let window_server = WindowService::open(discover_service("WindowServer")?);
+    let window_server = WindowService::discover(DISCOVER_TIMEOUT, DISCOVER_INTERVAL)?;
 
     window_server.create_window("Test window 1");
     window_server.create_window("Test window 2");
-    window_server.create_window("Test window 3");
+    let window = window_server.create_window("Test window 3");
+
+    let fd = window_server
+        .input_queue(window)
+        .ok_or(SyscallError::ENOMEM)?;
+    let mut input = SharedSpsc::<InputEvent>::open(window_server.pid(), fd)?;
+
+    loop {
+        while let Some(event) = input.pop() {
+            println!("[window_test] {:?}", event);
+
+            if event.kind == InputEvent::CLOSE {
+                window_server.destroy_window(window);
+                return Ok(());
+            }
+        }
 
-    Ok(())
+        std::thread::yield_now();
+    }
 }
@@ -2,9 +2,54 @@
 #include <stddef.h>
 #include <stdio.h>
 #include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
 #include <sys/wait.h>
 #include <unistd.h>
 
+#define SYS_SETHOSTNAME 36
+
+// Applies the host name persisted in `/etc/hostname`.
+static void set_hostname(void) {
+  char name[128];
+
+  int fd = open("/etc/hostname", O_RDONLY);
+  if (fd < 0)
+    return;
+
+  ssize_t len = read(fd, name, sizeof(name) - 1);
+  close(fd);
+
+  if (len <= 0)
+    return;
+
+  // Only the first line holds the name.
+  name[len] = '\0';
+  len = strcspn(name, "\n");
+
+  long ret;
+  asm volatile("syscall"
+               : "=a"(ret)
+               : "a"(SYS_SETHOSTNAME), "D"(name), "S"(len)
+               : "rcx", "r11", "memory");
+
+  if (ret < 0)
+    fprintf(stderr, "init: invalid host name in /etc/hostname\n");
+}
+
+// Creates the runtime directory of root on the `/run` tmpfs, so that it starts out empty on every
+// boot.
+static void make_runtime_dir(void) {
+  mkdir("/run/user", 0755);
+
+  if (mkdir("/run/user/0", 0700) < 0) {
+    perror("init: failed to create /run/user/0");
+    return;
+  }
+
+  setenv("XDG_RUNTIME_DIR", "/run/user/0", 1);
+}
+
 int main() {
   int fd_stdin = open("/dev/vtty", O_RDONLY);
   int fd_stdout = open("/dev/vtty", O_WRONLY);
@@ -12,11 +57,15 @@ int main() {
 
   printf("Hello world\n");
 
+  set_hostname();
+
   setenv("TERM", "linux", 1);
   setenv("USER", "root", 1);
   setenv("PATH", "/usr/local/bin:/usr/bin", 1);
   setenv("HOME", "/home/aero", 1);
 
+  make_runtime_dir();
+
   int pid = fork();
 
   if (!pid) {
@@ -24,8 +73,10 @@ int main() {
     chdir(getenv("HOME"));
     execvp("/usr/bin/bash", args);
   } else {
+    // Orphaned processes are reparented to init, so reap them as well until the shell exits.
     int status;
-    waitpid(pid, &status, 0);
+    while (wait(&status) != pid)
+      ;
   }
 
   return 0;
@@ -17,8 +17,97 @@ ipc! {
     }
 }
 
+/// A window, as listed by `WindowService::list_windows`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub id: usize,
+    pub name: String,
+    pub focused: bool,
+}
+
 ipc! {
+    #[retry_discover("WindowServer")]
     trait WindowService {
         fn create_window(name: &str) -> usize;
+        fn destroy_window(window: usize) -> bool;
+        fn input_queue(window: usize) -> Option<usize>;
+        fn list_windows() -> Vec<crate::WindowInfo>;
+        fn focus_window(window: usize) -> bool;
+        fn move_window(window: usize, x: i32, y: i32, width: u32, height: u32) -> bool;
+        fn screen_size() -> (u32, u32);
     }
 }
+
+/// An event delivered by the window server to a window.
+///
+/// Key and mouse events go to the focused window, while [`InputEvent::CLOSE`] and
+/// [`InputEvent::WINDOWS_CHANGED`] are not tied to any input device.
+///
+/// `WindowService::input_queue` returns the file descriptor of the [`SharedSpsc`] holding the
+/// events of a window in the window server, which the client opens with [`SharedSpsc::open`].
+///
+/// [`SharedSpsc`]: crate::spsc::SharedSpsc
+/// [`SharedSpsc::open`]: crate::spsc::SharedSpsc::open
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    /// One of the `InputEvent::*` kinds.
+    pub kind: u32,
+    /// The PS/2 scancode of a key event, or the buttons held down in a mouse event.
+    pub code: u32,
+    /// The horizontal motion of a mouse event.
+    pub dx: i32,
+    /// The vertical motion of a mouse event.
+    pub dy: i32,
+    /// The horizontal position of the cursor in a mouse event, relative to the window.
+    pub x: i32,
+    /// The vertical position of the cursor in a mouse event, relative to the window.
+    pub y: i32,
+}
+
+impl InputEvent {
+    /// The user asked for the window to be closed.
+    pub const CLOSE: u32 = 2;
+    pub const KEY: u32 = 0;
+    pub const MOUSE: u32 = 1;
+    /// A window was created, destroyed, focused or moved, see `WindowService::list_windows`.
+    pub const WINDOWS_CHANGED: u32 = 3;
+
+    const fn new(kind: u32) -> Self {
+        Self {
+            kind,
+            code: 0,
+            dx: 0,
+            dy: 0,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    pub const fn key(scancode: u8) -> Self {
+        Self {
+            code: scancode as u32,
+            ..Self::new(Self::KEY)
+        }
+    }
+
+    pub const fn mouse(dx: i32, dy: i32, buttons: u32) -> Self {
+        Self {
+            code: buttons,
+            dx,
+            dy,
+            ..Self::new(Self::MOUSE)
+        }
+    }
+
+    pub const fn close() -> Self {
+        Self::new(Self::CLOSE)
+    }
+
+    pub const fn windows_changed() -> Self {
+        Self::new(Self::WINDOWS_CHANGED)
+    }
+}
+
+// SAFETY: The event only consists of integers.
+unsafe impl crate::spsc::Plain for InputEvent {}
@@ -17,20 +17,121 @@
 #![feature(decl_macro)]
 
 mod interfaces;
+pub mod spsc;
 
+pub extern crate aero_syscall;
 pub extern crate postcard;
 pub extern crate serde;
 
-use aero_syscall::{sys_ipc_recv, sys_ipc_send};
+use aero_syscall::{
+    sys_getpid, sys_ipc_discover_root, sys_ipc_recv, sys_ipc_send, sys_sleep, SyscallError,
+    TimeSpec,
+};
 use core::ops::DerefMut;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
 use lazy_static::lazy_static;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub use interfaces::*;
 
+/// Version of the message framing, see [`MessageHeader`].
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Tag at the start of every message. The first version of the framing started with the bare
+/// message ID, whose encoding never begins with these bytes, so such messages are rejected
+/// instead of being misread.
+const MAGIC: [u8; 4] = *b"aIPC";
+
+/// Whether a message is a request or the reply to one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Request,
+    Reply,
+}
+
+/// Why a message could not be framed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The message does not start with [`MAGIC`], as is the case for messages of the first
+    /// version of the framing.
+    UnknownFormat,
+    /// The message uses another version of the framing.
+    UnsupportedVersion(u8),
+    /// The header is truncated or invalid.
+    Malformed,
+}
+
+/// The header at the start of every message, followed by the serialized method and arguments of
+/// a request or the serialized result of a reply.
+///
+/// A reply carries the ID and nonce of its request. The nonce is picked by the client for each
+/// exchange and is only sent to the server, so other processes cannot forge a reply to a pending
+/// request, and replies to past requests are not accepted again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageHeader {
+    magic: [u8; 4],
+    version: u8,
+    pub direction: Direction,
+    pub id: usize,
+    pub nonce: u64,
+}
+
+impl MessageHeader {
+    pub fn request(id: usize, nonce: u64) -> Self {
+        Self {
+            magic: MAGIC,
+            version: PROTOCOL_VERSION,
+            direction: Direction::Request,
+            id,
+            nonce,
+        }
+    }
+
+    /// Returns the header of the reply to this request.
+    pub fn reply(&self) -> Self {
+        Self {
+            direction: Direction::Reply,
+            ..*self
+        }
+    }
+
+    /// Returns whether `reply`, received from `src`, answers this request sent to `dest`.
+    pub fn is_answered_by(&self, dest: usize, src: usize, reply: &MessageHeader) -> bool {
+        src == dest
+            && reply.direction == Direction::Reply
+            && reply.id == self.id
+            && reply.nonce == self.nonce
+    }
+
+    /// Returns the message made of this header followed by `body`.
+    pub fn frame(&self, body: &[u8]) -> Vec<u8> {
+        let mut msg = postcard::to_allocvec(self).expect("header failed to serialize!");
+        msg.extend_from_slice(body);
+        msg
+    }
+
+    /// Splits `msg` into its header and body.
+    pub fn parse(msg: &[u8]) -> Result<(Self, &[u8]), FrameError> {
+        if !msg.starts_with(&MAGIC) {
+            return Err(FrameError::UnknownFormat);
+        }
+
+        match msg.get(MAGIC.len()) {
+            Some(&PROTOCOL_VERSION) => {}
+            Some(&version) => return Err(FrameError::UnsupportedVersion(version)),
+            None => return Err(FrameError::Malformed),
+        }
+
+        postcard::take_from_bytes(msg).map_err(|_| FrameError::Malformed)
+    }
+}
+
 /// A MessageHandler is a trait describing an IPC client
 pub trait MessageHandler: Send + Sync {
+    /// Handles the request `msg` from `src`, which has already been stripped of its header, and
+    /// returns the body of the reply. `Ok(None)` means that the method is not implemented by this
+    /// handler.
     fn handle(&mut self, src: usize, msg: &[u8]) -> Result<Option<Vec<u8>>, ()>;
 }
 
@@ -39,6 +140,8 @@ pub trait MessageHandler: Send + Sync {
 pub trait MessageTransport {
     fn alloc_id() -> usize;
     fn free_id(id: usize);
+
+    /// Sends the request body `data` to `meta` and returns the body of its reply.
     fn exchange(meta: usize, mid: usize, data: &[u8]) -> Vec<u8>;
 }
 
@@ -48,6 +151,38 @@ pub struct SendReceiveTransport;
 // trust me, this seed is fine
 static IDALLOC: AtomicUsize = AtomicUsize::new(0xde73_ce13_600f_e4e9);
 
+static NONCE_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the nonce of a new exchange.
+///
+/// There is no source of randomness yet, so a counter is mixed with the PID. This keeps replies
+/// to other exchanges out, but a process that knows the PID of the client can predict them.
+fn next_nonce() -> u64 {
+    let state = NONCE_STATE.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::SeqCst);
+    let mut z = state ^ ((sys_getpid() as u64) << 32);
+
+    // splitmix64
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Returns whether the calls and replies are logged to stderr, which is enabled by setting the
+/// `AIPC_TRACE` environment variable to anything but `0`.
+///
+/// Every line starts with the sequence number the kernel assigned to the message, so that the
+/// log can be matched with the kernel IPC trace (`ipctrace=1` on the kernel command line).
+fn tracing() -> bool {
+    static TRACING: spin::Once<bool> = spin::Once::new();
+
+    *TRACING.call_once(|| std::env::var_os("AIPC_TRACE").is_some_and(|value| value != "0"))
+}
+
+/// Returns the name of the method called by the request body `body`.
+fn method_name(body: &[u8]) -> &str {
+    postcard::take_from_bytes::<&str>(body).map_or("<malformed>", |(name, _)| name)
+}
+
 impl MessageTransport for SendReceiveTransport {
     fn alloc_id() -> usize {
         let value = IDALLOC.fetch_add(1, Ordering::SeqCst);
@@ -62,28 +197,88 @@ impl MessageTransport for SendReceiveTransport {
     fn free_id(_: usize) {}
 
     fn exchange(meta: usize, mid: usize, msg: &[u8]) -> Vec<u8> {
+        let request = MessageHeader::request(mid, next_nonce());
+
         // send the data
-        sys_ipc_send(meta, msg).expect("exchange failed: request failed!");
+        let sequence =
+            sys_ipc_send(meta, &request.frame(msg)).expect("exchange failed: request failed!");
+
+        if tracing() {
+            eprintln!(
+                "aipc[{}]: #{sequence} call {} id={mid:#x} -> {meta}",
+                sys_getpid(),
+                method_name(msg)
+            );
+        }
+
         // now wait for a response
         loop {
             // get a response
-            let rx = service_with_response_finding();
-
-            // if we got a response
-            if let Some((srcpid, mut msg)) = rx {
-                // and the response has the correct message ID...
-                let mut deser = postcard::Deserializer::from_bytes(&msg);
-                let msgid =
-                    usize::deserialize(&mut deser).expect("message ID not present in the message!");
-                if msgid == (mid << 1) | 1 && meta == srcpid {
-                    // return the message contents!
-                    return msg.split_off(core::mem::size_of::<usize>());
+            let Some((srcpid, reply)) = service_with_response_finding() else {
+                continue;
+            };
+
+            // only parsed replies are returned
+            let (header, body) = MessageHeader::parse(&reply).unwrap();
+
+            if request.is_answered_by(meta, srcpid, &header) {
+                if tracing() {
+                    eprintln!(
+                        "aipc[{}]: #{sequence} answered by {srcpid} (id={mid:#x})",
+                        sys_getpid()
+                    );
                 }
+
+                // return the message contents!
+                return body.to_vec();
             }
+
+            println!(
+                "\x1b[32;1mwarn\x1b[0m dropped unexpected reply (id={:#x}) from {}!",
+                header.id, srcpid
+            );
         }
     }
 }
 
+/// Returns the PID of the service announced as `name` to the system server.
+///
+/// Services start in parallel, so `name` may not have been announced yet. Until it is, the
+/// lookup is retried after sleeping for `interval`, which doubles after every attempt. The time
+/// spent sleeping is bounded by `timeout`.
+///
+/// ## Errors
+/// * `ETIMEDOUT`: The service was not announced before `timeout` expired.
+/// * `ENOMSG`: The system server failed to look up the service.
+pub fn discover_with_retry(
+    name: &str,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<usize, SyscallError> {
+    let system = SystemService::open(sys_ipc_discover_root()?);
+
+    let mut slept = Duration::ZERO;
+    let mut interval = interval;
+
+    loop {
+        match system.discover(name) {
+            Ok(pid) => return Ok(pid),
+            Err(SystemServiceError::NotFound) => {}
+            Err(_) => return Err(SyscallError::ENOMSG),
+        }
+
+        if slept >= timeout {
+            return Err(SyscallError::ETIMEDOUT);
+        }
+
+        let delay = interval.min(timeout - slept);
+        sys_sleep(&TimeSpec::from(delay))?;
+
+        slept += delay;
+        interval = interval.saturating_mul(2);
+    }
+}
+
 /// The IPC interface macro
 ///
 /// You can create interfaces like this:
@@ -92,17 +287,48 @@ impl MessageTransport for SendReceiveTransport {
 ///     trait Hello {
 ///         fn hello(favorite_number: i32) -> ();
 ///     }
+/// }
 /// ```
 ///
 /// Then, Hello::Client is the client interface, Hello::Server is the server
 /// interface and Hello::handler instantiates a MessageHandler that can be added
 /// to the listening pool.
+///
+/// A request body is the method name followed by its arguments, and a reply body is the result.
+/// The header of both is handled by the transport, see [`MessageHeader`].
+///
+/// With `#[retry_discover("Hello")]` before the trait, Hello::discover(timeout, interval) opens
+/// the service announced as `Hello`, see [`discover_with_retry`].
 #[macro_export]
 macro_rules! ipc {
+    { #[retry_discover($service:literal)] trait $nm:ident {
+        $(
+            fn $fnnm:ident($($argname:ident : $argty:ty),*) $(-> $t:ty)?;
+        )*
+    } } => {
+        $crate::ipc! { @def [$service] $nm {
+            $(
+                fn $fnnm($($argname : $argty),*) $(-> $t)?;
+            )*
+        } }
+    };
+
     { trait $nm:ident {
         $(
             fn $fnnm:ident($($argname:ident : $argty:ty),*) $(-> $t:ty)?;
         )*
+    } } => {
+        $crate::ipc! { @def [] $nm {
+            $(
+                fn $fnnm($($argname : $argty),*) $(-> $t)?;
+            )*
+        } }
+    };
+
+    { @def [$($service:literal)?] $nm:ident {
+        $(
+            fn $fnnm:ident($($argname:ident : $argty:ty),*) $(-> $t:ty)?;
+        )*
     } } => {
         #[allow(non_snake_case)]
         pub mod $nm {
@@ -119,7 +345,6 @@ macro_rules! ipc {
                     pub fn $fnnm(&self, $($argname: $argty),*) $(-> $t)? {
                         let mid = T::alloc_id();
                         let msg = postcard::to_allocvec(&(
-                            mid<<1, // messageid
                             concat!(stringify!($nm), "::", stringify!($fnnm)) // method
                             $(, $argname)* // args
                         )).expect("serialize failed!");
@@ -134,6 +359,15 @@ macro_rules! ipc {
                 Client { pid, phantom: ::core::marker::PhantomData{} }
             }
 
+            $(
+                pub fn discover(
+                    timeout: ::core::time::Duration,
+                    interval: ::core::time::Duration,
+                ) -> Result<Client<$crate::SendReceiveTransport>, $crate::aero_syscall::SyscallError> {
+                    $crate::discover_with_retry($service, timeout, interval).map(open)
+                }
+            )?
+
             pub trait Server: Send + Sync {
                 $(
                     fn $fnnm(&self, $($argname: $argty),*) $(-> $t)?;
@@ -153,11 +387,6 @@ macro_rules! ipc {
                     let mut deser = postcard::Deserializer::from_bytes(msg);
                     // TODO(pitust): cache this in the receive part of the handler
                     //? i don't think it would help *that* much though
-                    let msgid: usize = usize::deserialize(&mut deser).or_else(|_e| {
-                        println!("\x1b[31;1merr\x1b[0m message id failed to deserialize!");
-                        Err(())
-                    })?;
-
                     let method = String::deserialize(&mut deser).or_else(|_e| {
                         println!("\x1b[31;1merr\x1b[0m message name failed to deserialize!");
                         Err(())
@@ -166,14 +395,14 @@ macro_rules! ipc {
                     match method.as_str() {
                         $(
                             concat!(stringify!($nm), "::", stringify!($fnnm)) => {
-                                Ok(Some(postcard::to_allocvec(&(msgid|1, self.0.$fnnm(
+                                Ok(Some(postcard::to_allocvec(&self.0.$fnnm(
                                     $(
                                         <$argty>::deserialize(&mut deser).or_else(|_e| {
                                             println!("\x1b[31;1merr\x1b[0m message deserialization failed!");
                                             Err(())
                                         })?
                                     ),*
-                                ))).expect("reply failed to serialize!")))
+                                )).expect("reply failed to serialize!")))
                             },
                         )*
                         _ => Ok(None)
@@ -198,23 +427,37 @@ pub fn listen(iface: Box<dyn MessageHandler>) {
     list.push(iface);
 }
 
-/// Handle an IPC request from a specified process.
+/// Handle an IPC request from a specified process, returning the reply to send back.
+///
+/// Replies and messages that are not framed with this version of the protocol are dropped.
 pub fn handle_request(src: usize, msg: &[u8]) -> Option<Vec<u8>> {
     let mut list = HANDLER_LIST
         .try_lock()
         .expect("cannot nest request handlers!");
 
-    if (msg[0] & 1) == 1 {
-        println!(
-            "\x1b[32;1mwarn\x1b[0m received random response from {}!",
-            src
-        );
-        return None;
-    }
+    let (header, body) = match MessageHeader::parse(msg) {
+        Ok((header, body)) if header.direction == Direction::Request => (header, body),
+
+        Ok(_) => {
+            println!(
+                "\x1b[32;1mwarn\x1b[0m received random response from {}!",
+                src
+            );
+            return None;
+        }
+
+        Err(error) => {
+            println!(
+                "\x1b[32;1mwarn\x1b[0m dropped message from {} ({:?})!",
+                src, error
+            );
+            return None;
+        }
+    };
 
     for i in list.deref_mut() {
-        match i.handle(src, msg) {
-            Ok(Some(data)) => return Some(data),
+        match i.handle(src, body) {
+            Ok(Some(data)) => return Some(header.reply().frame(&data)),
             Ok(None) => {}
             Err(_) => return None,
         }
@@ -234,17 +477,37 @@ fn service_with_response_finding() -> Option<(usize, Vec<u8>)> {
     let msg = sys_ipc_recv(&mut src, arena.as_mut(), true).expect("sys_ipc_recv failed!");
 
     // if it's a response
-    if (msg[0] & 1) == 1 {
-        return Some((src, msg.to_vec()));
-    }
-
-    if let Some(data) = handle_request(src, msg) {
-        sys_ipc_send(src, &data).expect("sys_ipc_send failed, reply dropped!");
+    if let Ok((header, _)) = MessageHeader::parse(msg) {
+        if header.direction == Direction::Reply {
+            return Some((src, msg.to_vec()));
+        }
     }
 
+    serve(src, msg);
     None
 }
 
+/// Handles the request `msg` from `src` and sends the reply back.
+fn serve(src: usize, msg: &[u8]) {
+    let Some(data) = handle_request(src, msg) else {
+        return;
+    };
+
+    let sequence = sys_ipc_send(src, &data).expect("sys_ipc_send failed, reply dropped!");
+
+    if tracing() {
+        // Only requests that parsed are replied to.
+        let (header, body) = MessageHeader::parse(msg).unwrap();
+
+        eprintln!(
+            "aipc[{}]: #{sequence} reply {} id={:#x} -> {src}",
+            sys_getpid(),
+            method_name(body),
+            header.id
+        );
+    }
+}
+
 /// Service one request from the IPC queues
 pub fn service_request() {
     let mut src: usize = 0;
@@ -253,8 +516,110 @@ pub fn service_request() {
         .expect("service_request: receive arena is locked!");
 
     let msg = sys_ipc_recv(&mut src, arena.as_mut(), true).expect("sys_ipc_recv failed!");
+    serve(src, msg);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Only the server side is used, since the client makes system calls.
+    #[allow(dead_code)]
+    mod echo {
+        crate::ipc! {
+            trait Echo {
+                fn echo(value: u32) -> u32;
+            }
+        }
+    }
+
+    use echo::Echo;
+
+    struct EchoServer;
+
+    impl Echo::Server for EchoServer {
+        fn echo(&self, value: u32) -> u32 {
+            value
+        }
+    }
+
+    fn echo_request(header: &MessageHeader, value: u32) -> Vec<u8> {
+        header.frame(&postcard::to_allocvec(&("Echo::echo", value)).unwrap())
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = MessageHeader::request(0x1234, 0xdead_beef);
+        let msg = header.frame(&[1, 2, 3]);
+
+        assert_eq!(MessageHeader::parse(&msg), Ok((header, &[1, 2, 3][..])));
+
+        let reply = header.reply();
+        assert_eq!(reply.direction, Direction::Reply);
+        assert_eq!((reply.id, reply.nonce), (header.id, header.nonce));
+    }
+
+    #[test]
+    fn header_rejects_other_formats() {
+        // A request of the first version of the framing: the message ID, followed by the method.
+        let old = postcard::to_allocvec(&(0xde73_ce13_600f_e4e9usize << 1, "Echo::echo", 1u32))
+            .unwrap();
+        assert_eq!(MessageHeader::parse(&old), Err(FrameError::UnknownFormat));
+        assert_eq!(MessageHeader::parse(&[]), Err(FrameError::UnknownFormat));
+
+        let mut msg = MessageHeader::request(1, 2).frame(&[]);
+        msg[MAGIC.len()] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            MessageHeader::parse(&msg),
+            Err(FrameError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+        );
+
+        let msg = MessageHeader::request(1, 2).frame(&[]);
+        assert_eq!(
+            MessageHeader::parse(&msg[..MAGIC.len()]),
+            Err(FrameError::Malformed)
+        );
+        assert_eq!(
+            MessageHeader::parse(&msg[..msg.len() - 1]),
+            Err(FrameError::Malformed)
+        );
+    }
+
+    #[test]
+    fn reply_matching() {
+        let request = MessageHeader::request(7, 42);
+        let reply = request.reply();
+
+        assert!(request.is_answered_by(3, 3, &reply));
+        // Sent by another process.
+        assert!(!request.is_answered_by(3, 4, &reply));
+        // Not a reply.
+        assert!(!request.is_answered_by(3, 3, &request));
+        // Answers another exchange.
+        assert!(!request.is_answered_by(3, 3, &MessageHeader::request(8, 42).reply()));
+        assert!(!request.is_answered_by(3, 3, &MessageHeader::request(7, 43).reply()));
+    }
+
+    // The handler list is global, so the requests are all handled by this test.
+    #[test]
+    fn handle_requests() {
+        listen(Echo::handler(EchoServer));
+
+        let request = MessageHeader::request(5, 99);
+        let reply = handle_request(1, &echo_request(&request, 12)).unwrap();
+
+        let (header, body) = MessageHeader::parse(&reply).unwrap();
+        assert_eq!(header, request.reply());
+        assert_eq!(postcard::from_bytes::<u32>(body), Ok(12));
+
+        // Replies are not requests, even when they are well formed.
+        assert_eq!(handle_request(1, &echo_request(&request.reply(), 12)), None);
+
+        let old = postcard::to_allocvec(&(5usize << 1, "Echo::echo", 12u32)).unwrap();
+        assert_eq!(handle_request(1, &old), None);
 
-    if let Some(data) = handle_request(src, msg) {
-        sys_ipc_send(src, &data).expect("sys_ipc_send failed, reply dropped!");
+        let mut msg = echo_request(&request, 12);
+        msg[MAGIC.len()] = 1;
+        assert_eq!(handle_request(1, &msg), None);
     }
 }
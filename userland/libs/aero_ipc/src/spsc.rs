@@ -0,0 +1,510 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Lock-free single-producer single-consumer ring buffers.
+//!
+//! [`AtomicRingBuffer`] passes items between two threads of a process, [`SharedSpsc`] between
+//! two processes through a memfd that is mapped into both of them. Neither of them takes a lock
+//! or makes a system call to push or pop an item, which makes them suitable for high-volume
+//! streams such as input events.
+//!
+//! ```ignore
+//! // In the producer:
+//! let mut queue = SharedSpsc::<InputEvent>::new(256)?;
+//! queue.push(InputEvent::key(0x1e));
+//!
+//! // In the consumer, given the PID of the producer and `queue.fd()`:
+//! let mut queue = SharedSpsc::<InputEvent>::open(pid, fd)?;
+//! while let Some(event) = queue.pop() {
+//!     // ...
+//! }
+//! ```
+
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of, MaybeUninit};
+use core::ops::Deref;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use aero_syscall::consts::MemFdFlags;
+use aero_syscall::prelude::*;
+use aero_syscall::{
+    isize_as_syscall_result, sys_close, sys_ftruncate, sys_memfd_create, sys_mmap, sys_munmap,
+    sys_open, AtFlags, MMapFlags, MMapProt, OpenFlags, Result, Stat, SyscallError,
+};
+
+/// Identifies the memory of a [`SharedSpsc`].
+const SHARED_MAGIC: u64 = u64::from_be_bytes(*b"AEROSPSC");
+
+/// Keeps the producer and consumer indices on separate cache lines.
+#[repr(C, align(64))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// The indices of a ring buffer.
+///
+/// Both indices only ever grow (wrapping around on overflow) and are masked to get the slot,
+/// so that a full buffer (`tail - head == capacity`) can be told apart from an empty one.
+#[repr(C)]
+struct Indices {
+    /// Index of the next item to pop. Only written by the consumer.
+    head: CachePadded<AtomicUsize>,
+    /// Index of the next item to push. Only written by the producer.
+    tail: CachePadded<AtomicUsize>,
+}
+
+impl Indices {
+    const fn new() -> Self {
+        Self {
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+
+        tail.wrapping_sub(head)
+    }
+
+    /// ## Safety
+    ///
+    /// `slots` must point to `mask + 1` slots and there must be no concurrent pushes.
+    unsafe fn push<T>(&self, slots: *mut MaybeUninit<T>, mask: usize, item: T) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+
+        // Acquire the consumer's reads of the slots it has popped, before they get overwritten.
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) > mask {
+            return false;
+        }
+
+        slots.add(tail & mask).write(MaybeUninit::new(item));
+
+        // Publish the item to the consumer.
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// ## Safety
+    ///
+    /// `slots` must point to `mask + 1` slots and there must be no concurrent pops.
+    unsafe fn pop<T>(&self, slots: *const MaybeUninit<T>, mask: usize) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+
+        // Acquire the item written by the producer.
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let item = slots.add(head & mask).read().assume_init();
+
+        // Hand the slot back to the producer.
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(item)
+    }
+}
+
+/// A bounded lock-free queue between one producer thread and one consumer thread.
+///
+/// The buffer is [split](AtomicRingBuffer::split) into a [`Producer`] and a [`Consumer`], which
+/// can be moved to different threads.
+pub struct AtomicRingBuffer<T> {
+    indices: Indices,
+    mask: usize,
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+// SAFETY: Items are only pushed through the `Producer` and popped through the `Consumer`, of
+// which there is at most one at a time each.
+unsafe impl<T: Send> Send for AtomicRingBuffer<T> {}
+unsafe impl<T: Send> Sync for AtomicRingBuffer<T> {}
+
+impl<T> AtomicRingBuffer<T> {
+    /// Creates an empty buffer that holds up to `capacity` items, rounded up to the next power
+    /// of two.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+
+        Self {
+            indices: Indices::new(),
+            mask: capacity - 1,
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        }
+    }
+
+    /// Returns the maximum number of items the buffer holds.
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Returns the number of items in the buffer.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits the buffer into its producing and consuming halves.
+    pub fn split(&mut self) -> (Producer<'_, T>, Consumer<'_, T>) {
+        (Producer { buffer: self }, Consumer { buffer: self })
+    }
+
+    fn slots(&self) -> *mut MaybeUninit<T> {
+        UnsafeCell::raw_get(self.slots.as_ptr())
+    }
+}
+
+impl<T> Drop for AtomicRingBuffer<T> {
+    fn drop(&mut self) {
+        // SAFETY: The buffer is borrowed mutably, so there are no concurrent pops.
+        while unsafe { self.indices.pop(self.slots(), self.mask) }.is_some() {}
+    }
+}
+
+/// The producing half of an [`AtomicRingBuffer`].
+pub struct Producer<'a, T> {
+    buffer: &'a AtomicRingBuffer<T>,
+}
+
+impl<T> Producer<'_, T> {
+    /// Appends `item` to the buffer. Returns `false` and drops `item` if the buffer is full.
+    pub fn push(&mut self, item: T) -> bool {
+        let buffer = self.buffer;
+
+        // SAFETY: There is only one producer per buffer.
+        unsafe { buffer.indices.push(buffer.slots(), buffer.mask, item) }
+    }
+}
+
+/// The consuming half of an [`AtomicRingBuffer`].
+pub struct Consumer<'a, T> {
+    buffer: &'a AtomicRingBuffer<T>,
+}
+
+impl<T> Consumer<'_, T> {
+    /// Removes the oldest item from the buffer and returns it, or [`None`] if it is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let buffer = self.buffer;
+
+        // SAFETY: There is only one consumer per buffer.
+        unsafe { buffer.indices.pop(buffer.slots(), buffer.mask) }
+    }
+}
+
+/// Types that can be exchanged with another process through shared memory.
+///
+/// ## Safety
+///
+/// Every bit pattern must be a valid value of the type and the type must not contain pointers
+/// or references, as the other process is free to write anything into the shared memory.
+pub unsafe trait Plain: Copy + 'static {}
+
+macro_rules! impl_plain {
+    ($($ty:ty),*) => {
+        $(unsafe impl Plain for $ty {})*
+    };
+}
+
+impl_plain!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+unsafe impl<T: Plain, const N: usize> Plain for [T; N] {}
+
+/// The header at the start of the memory of a [`SharedSpsc`], followed by the slots.
+#[repr(C)]
+struct SharedHeader {
+    magic: u64,
+    slot_size: usize,
+    capacity: usize,
+    indices: Indices,
+}
+
+/// A bounded lock-free queue between a producer and a consumer process, backed by a memfd.
+///
+/// One process creates the queue with [`SharedSpsc::new`] and the other one opens it with
+/// [`SharedSpsc::open`] (or [`SharedSpsc::from_fd`] if it already has the file descriptor).
+/// Only one of the processes may push items and only the other one may pop them.
+pub struct SharedSpsc<T: Plain> {
+    fd: usize,
+    header: NonNull<SharedHeader>,
+    size: usize,
+    /// Capacity minus one. Kept out of the shared memory, so that the other process cannot
+    /// make us access memory past the slots.
+    mask: usize,
+    _marker: PhantomData<T>,
+}
+
+// SAFETY: The mapping is owned by the queue, which is only accessed through `&mut self`.
+unsafe impl<T: Plain + Send> Send for SharedSpsc<T> {}
+
+impl<T: Plain> SharedSpsc<T> {
+    /// Creates an empty queue that holds up to `capacity` items, rounded up to the next power
+    /// of two. The memfd is closed on `exec`.
+    pub fn new(capacity: usize) -> Result<Self> {
+        let capacity = capacity.max(1).next_power_of_two();
+        let size = Self::slots_offset() + capacity * size_of::<T>();
+
+        let fd = sys_memfd_create("spsc", MemFdFlags::CLOEXEC)?;
+
+        let queue = sys_ftruncate(fd, size).and_then(|_| Self::map(fd, size, capacity));
+        let queue = queue.inspect_err(|_| {
+            let _ = sys_close(fd);
+        })?;
+
+        // SAFETY: The memory is mapped and not shared with anybody else yet.
+        unsafe {
+            queue.header.as_ptr().write(SharedHeader {
+                magic: SHARED_MAGIC,
+                slot_size: size_of::<T>(),
+                capacity,
+                indices: Indices::new(),
+            });
+        }
+
+        Ok(queue)
+    }
+
+    /// Maps the queue in the memfd `fd`, which was created by [`SharedSpsc::new`] in this or
+    /// another process. The queue takes ownership of `fd`.
+    ///
+    /// ## Errors
+    ///
+    /// Fails with `EINVAL` if `fd` does not contain a queue of `T`s.
+    pub fn from_fd(fd: usize) -> Result<Self> {
+        let queue = Self::map_existing(fd);
+
+        if queue.is_err() {
+            let _ = sys_close(fd);
+        }
+
+        queue
+    }
+
+    /// Opens the queue that process `pid` has open as `fd`, through `/proc/<pid>/fd/<fd>`.
+    pub fn open(pid: usize, fd: usize) -> Result<Self> {
+        let path = format!("/proc/{pid}/fd/{fd}");
+        let fd = sys_open(&path, OpenFlags::O_RDWR | OpenFlags::O_CLOEXEC, 0)?;
+
+        Self::from_fd(fd)
+    }
+
+    /// Returns the file descriptor of the memfd.
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+
+    /// Returns the maximum number of items the queue holds.
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Returns the number of items in the queue.
+    pub fn len(&self) -> usize {
+        self.header().indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends `item` to the queue. Returns `false` if the queue is full.
+    pub fn push(&mut self, item: T) -> bool {
+        // SAFETY: The slots were validated when the queue was mapped.
+        unsafe { self.header().indices.push(self.slots(), self.mask, item) }
+    }
+
+    /// Removes the oldest item from the queue and returns it, or [`None`] if it is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        // SAFETY: The slots were validated when the queue was mapped.
+        unsafe { self.header().indices.pop(self.slots(), self.mask) }
+    }
+
+    fn header(&self) -> &SharedHeader {
+        // SAFETY: The header is mapped for as long as the queue lives.
+        unsafe { self.header.as_ref() }
+    }
+
+    fn slots(&self) -> *mut MaybeUninit<T> {
+        // SAFETY: The slots follow the header in the mapping.
+        unsafe {
+            self.header
+                .as_ptr()
+                .cast::<u8>()
+                .add(Self::slots_offset())
+                .cast()
+        }
+    }
+
+    /// Returns the offset of the slots from the start of the memory.
+    fn slots_offset() -> usize {
+        size_of::<SharedHeader>().next_multiple_of(align_of::<T>())
+    }
+
+    fn map(fd: usize, size: usize, capacity: usize) -> Result<Self> {
+        let address = sys_mmap(
+            0,
+            size,
+            MMapProt::PROT_READ | MMapProt::PROT_WRITE,
+            MMapFlags::MAP_SHARED,
+            fd,
+            0,
+        )?;
+
+        Ok(Self {
+            fd,
+            header: NonNull::new(address.cast()).ok_or(SyscallError::EFAULT)?,
+            size,
+            mask: capacity - 1,
+            _marker: PhantomData,
+        })
+    }
+
+    fn map_existing(fd: usize) -> Result<Self> {
+        let size = sys_fstat_size(fd)?;
+
+        if size < size_of::<SharedHeader>() {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let mut queue = Self::map(fd, size, 1)?;
+        let header = queue.header();
+        let capacity = header.capacity;
+
+        let valid = header.magic == SHARED_MAGIC
+            && header.slot_size == size_of::<T>()
+            && capacity.is_power_of_two()
+            && capacity
+                .checked_mul(size_of::<T>())
+                .and_then(|slots| slots.checked_add(Self::slots_offset()))
+                .is_some_and(|end| end <= size);
+
+        if !valid {
+            // Leave the file descriptor to the caller.
+            let _ = sys_munmap(queue.header.as_ptr().cast(), queue.size);
+            core::mem::forget(queue);
+
+            return Err(SyscallError::EINVAL);
+        }
+
+        queue.mask = capacity - 1;
+        Ok(queue)
+    }
+}
+
+impl<T: Plain> Drop for SharedSpsc<T> {
+    fn drop(&mut self) {
+        let _ = sys_munmap(self.header.as_ptr().cast(), self.size);
+        let _ = sys_close(self.fd);
+    }
+}
+
+fn sys_fstat_size(fd: usize) -> Result<usize> {
+    let mut stat = Stat::default();
+    let value = syscall5(
+        SYS_FSTAT,
+        fd,
+        "".as_ptr() as usize,
+        0,
+        AtFlags::EMPTY_PATH.bits(),
+        &mut stat as *mut Stat as usize,
+    );
+
+    isize_as_syscall_result(value as _)?;
+    usize::try_from(stat.st_size).map_err(|_| SyscallError::EINVAL)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_fifo() {
+        let mut buffer = AtomicRingBuffer::new(3);
+        assert_eq!(buffer.capacity(), 4);
+
+        let (mut producer, mut consumer) = buffer.split();
+
+        for i in 0..4 {
+            assert!(producer.push(i));
+        }
+
+        // The buffer is full.
+        assert!(!producer.push(4));
+        assert_eq!(consumer.pop(), Some(0));
+        assert!(producer.push(4));
+
+        // The indices wrap around the slots.
+        for i in 1..5 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn ring_buffer_threads() {
+        const COUNT: usize = 100_000;
+
+        let mut buffer = AtomicRingBuffer::new(64);
+        let (mut producer, mut consumer) = buffer.split();
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..COUNT {
+                    while !producer.push(i) {
+                        std::hint::spin_loop();
+                    }
+                }
+            });
+
+            for i in 0..COUNT {
+                loop {
+                    if let Some(item) = consumer.pop() {
+                        assert_eq!(item, i);
+                        break;
+                    }
+
+                    std::hint::spin_loop();
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn ring_buffer_drops_items() {
+        let item = std::sync::Arc::new(());
+        let mut buffer = AtomicRingBuffer::new(4);
+
+        buffer.split().0.push(item.clone());
+        buffer.split().0.push(item.clone());
+        drop(buffer);
+
+        assert_eq!(std::sync::Arc::strong_count(&item), 1);
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Stack traces of the calling thread, printed by the panic handler.
+//!
+//! The stack is walked by following the frame pointers, so the program must be built with
+//! `-C force-frame-pointers=yes`, as `build-support/rust/config.toml` does. The return addresses
+//! are resolved with the symbol table and the DWARF line tables of `/proc/self/exe`, which are
+//! only available if the executable was not stripped.
+
+use core::arch::asm;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use gimli::{Dwarf, EndianSlice, LittleEndian, SectionId};
+use xmas_elf::sections::{SectionData, ShType};
+use xmas_elf::symbol_table::{Entry, Entry64};
+use xmas_elf::ElfFile;
+
+use crate::{eprintln, fs};
+
+/// Maximum number of frames that are captured.
+const MAX_FRAMES: usize = 64;
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// Returns the return addresses of the frames on the stack of the calling thread, innermost
+/// first.
+#[inline(never)]
+fn capture() -> Vec<usize> {
+    let mut frames = Vec::new();
+    let mut rbp: usize;
+
+    unsafe {
+        asm!("mov {}, rbp", out(reg) rbp);
+    }
+
+    // `_start` clears the frame pointer, which terminates the chain. The frames of the callers
+    // are at higher addresses, anything else means that a function did not keep the frame
+    // pointer.
+    while frames.len() < MAX_FRAMES
+        && rbp != 0
+        && rbp.is_multiple_of(core::mem::align_of::<usize>())
+    {
+        // SAFETY: `rbp` points to the saved frame pointer of the caller, followed by the return
+        // address.
+        let (next, rip) = unsafe {
+            let frame = rbp as *const usize;
+            (*frame, *frame.add(1))
+        };
+
+        if rip == 0 {
+            break;
+        }
+
+        frames.push(rip);
+
+        if next <= rbp {
+            break;
+        }
+
+        rbp = next;
+    }
+
+    frames
+}
+
+/// The debugging information of the executable.
+struct Symbols<'a> {
+    elf: ElfFile<'a>,
+    symbols: &'a [Entry64],
+    dwarf: Option<Dwarf<Reader<'a>>>,
+    /// Difference between the run-time and the link-time addresses, which is not zero if the
+    /// executable is position independent.
+    bias: usize,
+}
+
+impl<'a> Symbols<'a> {
+    fn new(image: &'a [u8]) -> Option<Self> {
+        extern "C" {
+            fn _start();
+        }
+
+        let elf = ElfFile::new(image).ok()?;
+        let symbols = elf
+            .section_iter()
+            .filter(|section| section.get_type() == Ok(ShType::SymTab))
+            .find_map(|section| match section.get_data(&elf) {
+                Ok(SectionData::SymbolTable64(symbols)) => Some(symbols),
+                _ => None,
+            })?;
+
+        let start = symbols
+            .iter()
+            .find(|symbol| symbol.get_name(&elf) == Ok("_start"))
+            .map_or(0, |symbol| symbol.value() as usize);
+
+        let dwarf = Dwarf::load(|id: SectionId| -> Result<Reader<'a>, ()> {
+            let data = elf
+                .find_section_by_name(id.name())
+                .map_or(&[][..], |section| section.raw_data(&elf));
+
+            Ok(EndianSlice::new(data, LittleEndian))
+        })
+        .ok();
+
+        Some(Self {
+            elf,
+            symbols,
+            dwarf,
+            bias: (_start as *const () as usize).wrapping_sub(start),
+        })
+    }
+
+    /// Returns the demangled name of the function containing `address`.
+    fn function(&self, address: usize) -> Option<rustc_demangle::Demangle<'a>> {
+        let address = address.wrapping_sub(self.bias);
+
+        self.symbols
+            .iter()
+            .find(|symbol| {
+                let start = symbol.value() as usize;
+                (start..start + symbol.size() as usize).contains(&address)
+            })
+            .and_then(|symbol| symbol.get_name(&self.elf).ok())
+            .map(rustc_demangle::demangle)
+    }
+
+    /// Returns the source file and line of the instruction at `address`.
+    fn location(&self, address: usize) -> Option<(String, u64)> {
+        let dwarf = self.dwarf.as_ref()?;
+        let address = address.wrapping_sub(self.bias) as u64;
+
+        let mut units = dwarf.units();
+
+        while let Ok(Some(header)) = units.next() {
+            let Ok(unit) = dwarf.unit(header) else {
+                continue;
+            };
+
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+
+            let mut rows = program.rows();
+            // The row that starts the range of addresses the next row ends.
+            let mut previous = None;
+
+            while let Ok(Some((header, row))) = rows.next_row() {
+                if let Some((start, file, line)) = previous {
+                    if (start..row.address()).contains(&address) {
+                        let file = header.file(file)?;
+                        let path = dwarf.attr_string(&unit, file.path_name()).ok()?;
+
+                        return Some((path.to_string_lossy().into_owned(), line));
+                    }
+                }
+
+                previous = (!row.end_sequence()).then(|| {
+                    let line = row.line().map_or(0, |line| line.get());
+                    (row.address(), row.file_index(), line)
+                });
+            }
+        }
+
+        None
+    }
+}
+
+/// Prints the stack trace of the calling thread to the standard error.
+pub(crate) fn print() {
+    let frames = capture();
+
+    let image = fs::read("/proc/self/exe").unwrap_or_default();
+    let symbols = Symbols::new(&image);
+
+    eprintln!("stack backtrace:");
+
+    for (depth, &rip) in frames.iter().enumerate() {
+        // The return address is the instruction after the call, which may already belong to
+        // the next function or line.
+        let call = rip - 1;
+
+        let function = symbols.as_ref().and_then(|symbols| symbols.function(call));
+        let location = symbols.as_ref().and_then(|symbols| symbols.location(call));
+
+        match (function, location) {
+            (Some(function), Some((file, line))) => {
+                eprintln!("#{depth:<2} {rip:#018x} in {function:#} ({file}:{line})")
+            }
+            (Some(function), None) => eprintln!("#{depth:<2} {rip:#018x} in {function:#}"),
+            (None, _) => eprintln!("#{depth:<2} {rip:#018x} in ??"),
+        }
+    }
+}
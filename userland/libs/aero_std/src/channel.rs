@@ -0,0 +1,292 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Multi-producer, single-consumer FIFO channels.
+//!
+//! A channel is created with [`channel`]. The [`Sender`] can be cloned to send messages from
+//! several threads, while the [`Receiver`] receives them in the order they were sent. The channel
+//! is unbounded, so sending never blocks.
+//!
+//! ```ignore
+//! let (sender, receiver) = channel::channel();
+//!
+//! thread::spawn(move || {
+//!     for i in 0..10 {
+//!         sender.send(i);
+//!     }
+//! });
+//!
+//! while let Some(i) = receiver.recv() {
+//!     println!("{i}");
+//! }
+//! ```
+
+use core::fmt;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use crate::sys;
+
+/// The state shared by the senders and the receiver of a channel.
+struct Shared<T> {
+    queue: spin::Mutex<VecDeque<T>>,
+    /// Bumped whenever a message is sent or the last sender is dropped. The receiver waits on it
+    /// while the queue is empty.
+    futex: AtomicU32,
+    /// Number of live senders.
+    senders: AtomicUsize,
+}
+
+impl<T> Shared<T> {
+    /// Wakes up the receiver if it is waiting for a message.
+    fn notify(&self) {
+        self.futex.fetch_add(1, Ordering::Release);
+        let _ = sys::sys_futex_wake(&self.futex);
+    }
+}
+
+/// Creates a new channel and returns its sending and receiving halves.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: spin::Mutex::new(VecDeque::new()),
+        futex: AtomicU32::new(0),
+        senders: AtomicUsize::new(1),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// The sending half of a channel. Can be cloned to send from several threads.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends `value` on the channel and wakes up the receiver if it is waiting.
+    ///
+    /// Messages sent after the [`Receiver`] has been dropped are never received and are
+    /// dropped along with the channel.
+    pub fn send(&self, value: T) {
+        self.shared.queue.lock().push_back(value);
+        self.shared.notify();
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Wake up the receiver so it notices that the channel is disconnected.
+            self.shared.notify();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next message, sleeping until one is sent. Returns `None` once the channel is
+    /// empty and all of the [`Sender`]s have been dropped.
+    pub fn recv(&self) -> Option<T> {
+        loop {
+            // Read the sequence before checking the queue, so a message sent in between makes
+            // the wait fail instead of being missed.
+            let sequence = self.shared.futex.load(Ordering::Acquire);
+
+            match self.try_recv() {
+                Ok(value) => return Some(value),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+
+            let _ = sys::sys_futex_wait(&self.shared.futex, sequence);
+        }
+    }
+
+    /// Receives the next message if there is one, without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.shared.queue.lock().pop_front() {
+            return Ok(value);
+        }
+
+        if self.shared.senders.load(Ordering::Acquire) != 0 {
+            return Err(TryRecvError::Empty);
+        }
+
+        // The last sender might have sent a message right before it was dropped.
+        self.shared
+            .queue
+            .lock()
+            .pop_front()
+            .ok_or(TryRecvError::Disconnected)
+    }
+
+    /// Returns an iterator that receives messages until the channel is disconnected.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+/// An iterator over the messages of a [`Receiver`], created with [`Receiver::iter`].
+pub struct Iter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv()
+    }
+}
+
+/// The error returned by [`Receiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// The channel is empty, but there are senders left.
+    Empty,
+    /// The channel is empty and all of the senders have been dropped.
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("receiving on an empty channel"),
+            Self::Disconnected => f.write_str("receiving on a disconnected channel"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use aero_syscall::mock::{self, SyscallCall};
+    use aero_syscall::prelude::*;
+
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Queues the results of `count` successful system calls.
+    fn push_results(count: usize) {
+        for _ in 0..count {
+            mock::push_result(0);
+        }
+    }
+
+    #[test]
+    fn messages_are_received_in_order() {
+        mock::reset();
+        let (sender, receiver) = channel();
+
+        push_results(3);
+        for i in 0..3 {
+            sender.send(i);
+        }
+
+        assert_eq!(receiver.try_recv(), Ok(0));
+        assert_eq!(receiver.recv(), Some(1));
+        assert_eq!(receiver.recv(), Some(2));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+        let futex = &sender.shared.futex as *const AtomicU32 as usize;
+        push_results(1);
+        drop(sender);
+
+        // Every message and the drop of the last sender wake up the receiver, but receiving
+        // from a non-empty queue does not sleep.
+        let wake = SyscallCall::new(SYS_FUTEX_WAKE, &[futex]);
+        assert_eq!(mock::take_calls(), [wake; 4]);
+    }
+
+    #[test]
+    fn disconnects_after_the_last_sender_is_dropped() {
+        mock::reset();
+        let (sender, receiver) = channel();
+        let cloned = sender.clone();
+
+        push_results(2);
+        cloned.send(1);
+        drop(cloned);
+        assert_eq!(receiver.try_recv(), Ok(1));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+        // Messages sent right before the last sender is dropped are still received.
+        push_results(2);
+        sender.send(2);
+        drop(sender);
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), [2]);
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(receiver.recv(), None);
+    }
+
+    #[test]
+    fn recv_sleeps_until_a_message_is_sent() {
+        mock::reset();
+        let (sender, receiver) = channel();
+        let futex = &sender.shared.futex as *const AtomicU32 as usize;
+
+        // Send the message and disconnect while the receiver is asleep.
+        mock::push_handler(move |_| {
+            push_results(2);
+            sender.send(42);
+            drop(sender);
+            0
+        });
+
+        assert_eq!(receiver.recv(), Some(42));
+        assert_eq!(receiver.recv(), None);
+
+        let calls = mock::take_calls();
+        let wake = SyscallCall::new(SYS_FUTEX_WAKE, &[futex]);
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0].number, SYS_FUTEX_WAIT);
+        assert_eq!(calls[0].args[..2], [futex, 0]);
+        assert_eq!(calls[1..], [wake; 2]);
+    }
+}
@@ -0,0 +1,958 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! A hash map implemented with open addressing.
+//!
+//! The entries are kept in a single table with a power of two number of slots. Collisions are
+//! resolved with linear probing and removals shift the following entries back, so there are no
+//! tombstones. The table grows once it is three quarters full.
+//!
+//! Keys are hashed with SipHash, keyed with random keys read from `/dev/urandom` once per
+//! process.
+
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash, Hasher};
+use core::iter::FusedIterator;
+use core::ops::Index;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::{fmt, mem};
+
+use alloc::vec::Vec;
+
+use crate::fs::File;
+use crate::io::Read;
+
+/// Number of slots of the first table allocated by a map.
+const MIN_SLOTS: usize = 8;
+
+/// The default hasher of [`HashMap`], SipHash-2-4.
+#[derive(Debug, Clone)]
+#[allow(deprecated)]
+pub struct DefaultHasher(core::hash::SipHasher);
+
+impl DefaultHasher {
+    /// Creates a hasher with fixed keys, which always hashes a value to the same hash.
+    #[allow(deprecated)]
+    pub fn new() -> Self {
+        Self(core::hash::SipHasher::new_with_keys(0, 0))
+    }
+}
+
+impl Default for DefaultHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for DefaultHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes)
+    }
+
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+/// Builds [`DefaultHasher`]s with random keys, the default hasher builder of [`HashMap`].
+///
+/// Every `RandomState` gets different keys, so the iteration order of two maps with the same
+/// contents usually differs.
+#[derive(Debug, Clone)]
+pub struct RandomState {
+    k0: u64,
+    k1: u64,
+}
+
+impl RandomState {
+    pub fn new() -> Self {
+        static SEED: spin::Once<(u64, u64)> = spin::Once::new();
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let (k0, k1) = *SEED.call_once(|| {
+            let mut seed = [0; 16];
+
+            // Fall back to fixed keys if the random device is not available.
+            if let Ok(mut urandom) = File::open("/dev/urandom") {
+                let _ = urandom.read(&mut seed);
+            }
+
+            let (k0, k1) = seed.split_at(8);
+            (
+                u64::from_ne_bytes(k0.try_into().unwrap()),
+                u64::from_ne_bytes(k1.try_into().unwrap()),
+            )
+        });
+
+        Self {
+            k0: k0.wrapping_add(COUNTER.fetch_add(1, Ordering::Relaxed)),
+            k1,
+        }
+    }
+}
+
+impl Default for RandomState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for RandomState {
+    type Hasher = DefaultHasher;
+
+    #[allow(deprecated)]
+    fn build_hasher(&self) -> DefaultHasher {
+        DefaultHasher(core::hash::SipHasher::new_with_keys(self.k0, self.k1))
+    }
+}
+
+#[derive(Clone)]
+struct Bucket<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// A hash map with keys of type `K` and values of type `V`.
+pub struct HashMap<K, V, S = RandomState> {
+    slots: Vec<Option<Bucket<K, V>>>,
+    len: usize,
+    hash_builder: S,
+}
+
+impl<K, V> HashMap<K, V, RandomState> {
+    /// Creates an empty map. No memory is allocated until the first insertion.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    /// Creates an empty map with room for at least `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Creates an empty map that hashes the keys with `hash_builder`.
+    pub const fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            slots: Vec::new(),
+            len: 0,
+            hash_builder,
+        }
+    }
+
+    /// Creates an empty map with room for at least `capacity` entries that hashes the keys with
+    /// `hash_builder`.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        let mut map = Self::with_hasher(hash_builder);
+
+        if capacity != 0 {
+            map.slots = empty_slots(slots_for(capacity));
+        }
+
+        map
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Returns the number of entries the map can hold without growing.
+    pub fn capacity(&self) -> usize {
+        max_len(self.slots.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all of the entries, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the entries, in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            slots: self.slots.iter(),
+            len: self.len,
+        }
+    }
+
+    /// Returns an iterator over the entries with mutable references to the values, in arbitrary
+    /// order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            slots: self.slots.iter_mut(),
+            len: self.len,
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> + '_ {
+        self.iter_mut().map(|(_, value)| value)
+    }
+
+    /// Removes all of the entries and returns them as an iterator. The allocated memory is kept.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.len = 0;
+
+        self.slots
+            .iter_mut()
+            .filter_map(|slot| slot.take())
+            .map(|bucket| (bucket.key, bucket.value))
+    }
+
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> HashMap<K, V, S> {
+    /// Makes room for at least `additional` more entries.
+    pub fn reserve(&mut self, additional: usize) {
+        let len = self.len.checked_add(additional).expect("capacity overflow");
+
+        if len > self.capacity() {
+            self.resize(slots_for(len));
+        }
+    }
+
+    /// Shrinks the table as much as possible.
+    pub fn shrink_to_fit(&mut self) {
+        let slots = if self.len == 0 {
+            0
+        } else {
+            slots_for(self.len)
+        };
+
+        if slots < self.slots.len() {
+            self.resize(slots);
+        }
+    }
+
+    /// Inserts `value` with `key` and returns the value it replaced, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Returns the entry of `key` for in-place manipulation.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        self.reserve(1);
+
+        let hash = self.hash(&key);
+
+        match self.probe(hash, &key) {
+            Ok(index) => Entry::Occupied(OccupiedEntry { map: self, index }),
+            Err(index) => Entry::Vacant(VacantEntry {
+                map: self,
+                index,
+                hash,
+                key,
+            }),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        self.slots[index].as_ref().map(|bucket| &bucket.value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        self.slots[index].as_mut().map(|bucket| &mut bucket.value)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        self.slots[index]
+            .as_ref()
+            .map(|bucket| (&bucket.key, &bucket.value))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Removes `key` from the map and returns its value, if it was present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes `key` from the map and returns the stored key and its value, if it was present.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        let bucket = self.remove_at(index);
+
+        Some((bucket.key, bucket.value))
+    }
+
+    /// Keeps only the entries for which `f` returns `true`.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let len = self.len;
+
+        for slot in self.slots.iter_mut() {
+            if let Some(bucket) = slot {
+                if !f(&bucket.key, &mut bucket.value) {
+                    *slot = None;
+                    self.len -= 1;
+                }
+            }
+        }
+
+        // The removals leave holes in the probe sequences, so put the remaining entries back
+        // into place.
+        if self.len != len {
+            self.resize(self.slots.len());
+        }
+    }
+
+    /// Returns the index of the slot holding `key`.
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.probe(self.hash(key), key).ok()
+    }
+
+    /// Looks up `key` in the table, which must have at least one free slot. Returns the index of
+    /// the slot holding `key` or the index of the free slot it would be inserted into.
+    fn probe<Q>(&self, hash: u64, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let mask = self.mask();
+        let mut index = hash as usize & mask;
+
+        loop {
+            match &self.slots[index] {
+                Some(bucket) if bucket.hash == hash && bucket.key.borrow() == key => {
+                    return Ok(index)
+                }
+
+                Some(_) => index = (index + 1) & mask,
+                None => return Err(index),
+            }
+        }
+    }
+
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hash_builder.hash_one(key)
+    }
+
+    /// Removes the entry at `index` and shifts the entries following it back to keep the probe
+    /// sequences free of holes.
+    fn remove_at(&mut self, index: usize) -> Bucket<K, V> {
+        let mask = self.mask();
+        let bucket = self.slots[index].take().unwrap();
+
+        let mut hole = index;
+        let mut next = (index + 1) & mask;
+
+        while let Some(entry) = &self.slots[next] {
+            let ideal = entry.hash as usize & mask;
+
+            // The entry can move into the hole if the hole is between its ideal slot and the
+            // slot it is in.
+            if (next.wrapping_sub(ideal) & mask) >= (next.wrapping_sub(hole) & mask) {
+                self.slots[hole] = self.slots[next].take();
+                hole = next;
+            }
+
+            next = (next + 1) & mask;
+        }
+
+        self.len -= 1;
+        bucket
+    }
+
+    /// Moves the entries into a new table with `slots` slots.
+    fn resize(&mut self, slots: usize) {
+        let old = mem::replace(&mut self.slots, empty_slots(slots));
+
+        for bucket in old.into_iter().flatten() {
+            let mask = self.mask();
+            let mut index = bucket.hash as usize & mask;
+
+            while self.slots[index].is_some() {
+                index = (index + 1) & mask;
+            }
+
+            self.slots[index] = Some(bucket);
+        }
+    }
+}
+
+/// Returns the number of slots needed to hold `len` entries.
+fn slots_for(len: usize) -> usize {
+    let slots = len.checked_mul(4).expect("capacity overflow") / 3 + 1;
+    slots.next_power_of_two().max(MIN_SLOTS)
+}
+
+/// Returns the number of entries a table with `slots` slots can hold.
+fn max_len(slots: usize) -> usize {
+    slots / 4 * 3
+}
+
+fn empty_slots<K, V>(slots: usize) -> Vec<Option<Bucket<K, V>>> {
+    let mut table = Vec::with_capacity(slots);
+    table.resize_with(slots, || None);
+    table
+}
+
+impl<K, V, S: Default> Default for HashMap<K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> Clone for HashMap<K, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            slots: self.slots.clone(),
+            len: self.len,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for HashMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Hash + Eq, V: PartialEq, S: BuildHasher> PartialEq for HashMap<K, V, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl<K: Hash + Eq, V: Eq, S: BuildHasher> Eq for HashMap<K, V, S> {}
+
+impl<K, Q, V, S> Index<&Q> for HashMap<K, V, S>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    /// Returns the value of `key`. Panics if the key is not present.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found in the map")
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> Extend<(K, V)> for HashMap<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)> for HashMap<K, V, S> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Hash + Eq, V, const N: usize> From<[(K, V); N]> for HashMap<K, V, RandomState> {
+    fn from(entries: [(K, V); N]) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
+    type IntoIter = Iter<'a, K, V>;
+    type Item = (&'a K, &'a V);
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+    type IntoIter = IterMut<'a, K, V>;
+    type Item = (&'a K, &'a mut V);
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for HashMap<K, V, S> {
+    type IntoIter = IntoIter<K, V>;
+    type Item = (K, V);
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            slots: self.slots.into_iter(),
+            len: self.len,
+        }
+    }
+}
+
+/// An iterator over the entries of a [`HashMap`].
+pub struct Iter<'a, K, V> {
+    slots: core::slice::Iter<'a, Option<Bucket<K, V>>>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bucket = self.slots.find_map(Option::as_ref)?;
+        self.len -= 1;
+
+        Some((&bucket.key, &bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// An iterator over the entries of a [`HashMap`] with mutable references to the values.
+pub struct IterMut<'a, K, V> {
+    slots: core::slice::IterMut<'a, Option<Bucket<K, V>>>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bucket = self.slots.find_map(Option::as_mut)?;
+        self.len -= 1;
+
+        Some((&bucket.key, &mut bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+/// An owning iterator over the entries of a [`HashMap`].
+pub struct IntoIter<K, V> {
+    slots: alloc::vec::IntoIter<Option<Bucket<K, V>>>,
+    len: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bucket = self.slots.find_map(|slot| slot)?;
+        self.len -= 1;
+
+        Some((bucket.key, bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+/// An entry of a [`HashMap`], returned by [`HashMap::entry`].
+pub enum Entry<'a, K, V, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value, inserting `default` first if the entry is vacant.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Returns the value, inserting the result of `default` first if the entry is vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Returns the value, inserting the result of `default` called with the key first if the
+    /// entry is vacant.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+
+        self
+    }
+}
+
+impl<'a, K, V: Default, S> Entry<'a, K, V, S> {
+    /// Returns the value, inserting the default value first if the entry is vacant.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+/// An occupied entry of a [`HashMap`].
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
+    fn bucket(&self) -> &Bucket<K, V> {
+        self.map.slots[self.index].as_ref().unwrap()
+    }
+
+    fn bucket_mut(&mut self) -> &mut Bucket<K, V> {
+        self.map.slots[self.index].as_mut().unwrap()
+    }
+
+    pub fn key(&self) -> &K {
+        &self.bucket().key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.bucket().value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.bucket_mut().value
+    }
+
+    /// Converts the entry into a mutable reference to the value, which lives as long as the
+    /// borrow of the map.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.slots[self.index].as_mut().unwrap().value
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> OccupiedEntry<'_, K, V, S> {
+    /// Removes the entry from the map and returns its value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry from the map and returns its key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        let bucket = self.map.remove_at(self.index);
+        (bucket.key, bucket.value)
+    }
+}
+
+/// A vacant entry of a [`HashMap`].
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    map: &'a mut HashMap<K, V, S>,
+    /// The free slot found by [`HashMap::entry`], which made sure that the table has room for
+    /// another entry.
+    index: usize,
+    hash: u64,
+    key: K,
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` with the key of the entry and returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let bucket = Bucket {
+            hash: self.hash,
+            key: self.key,
+            value,
+        };
+
+        self.map.len += 1;
+        &mut self.map.slots[self.index].insert(bucket).value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::hash::BuildHasherDefault;
+
+    use alloc::collections::BTreeMap;
+
+    use super::*;
+
+    /// Hashes a `u64` to itself, which places the keys at known slots.
+    #[derive(Default)]
+    struct IdentityHasher(u64);
+
+    impl Hasher for IdentityHasher {
+        fn write(&mut self, _bytes: &[u8]) {
+            unimplemented!()
+        }
+
+        fn write_u64(&mut self, value: u64) {
+            self.0 = value;
+        }
+
+        fn finish(&self) -> u64 {
+            self.0
+        }
+    }
+
+    type IdentityMap = HashMap<u64, u64, BuildHasherDefault<IdentityHasher>>;
+
+    /// Builds a map with the first table size, holding the keys in insertion order.
+    fn identity_map(keys: &[u64]) -> IdentityMap {
+        let mut map = IdentityMap::default();
+
+        for &key in keys {
+            map.insert(key, key * 10);
+        }
+
+        assert_eq!(map.slots.len(), MIN_SLOTS);
+        map
+    }
+
+    fn slot_keys(map: &IdentityMap) -> Vec<Option<u64>> {
+        map.slots
+            .iter()
+            .map(|slot| slot.as_ref().map(|bucket| bucket.key))
+            .collect()
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let mut map = HashMap::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+
+        assert!(map.is_empty());
+        assert_eq!(map.insert("one", 1), None);
+        assert_eq!(map.insert("two", 2), None);
+        assert_eq!(map.insert("one", 3), Some(1));
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("one"), Some(&3));
+        assert_eq!(map["two"], 2);
+        assert!(!map.contains_key("three"));
+
+        assert_eq!(map.remove("one"), Some(3));
+        assert_eq!(map.remove("one"), None);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn remove_shifts_collisions_back() {
+        // 1, 9 and 17 all want slot 1, so they end up in slots 1 to 3 and push 2 out of its
+        // ideal slot into slot 4.
+        let mut map = identity_map(&[1, 9, 17, 2]);
+        assert_eq!(
+            slot_keys(&map),
+            [None, Some(1), Some(9), Some(17), Some(2), None, None, None]
+        );
+
+        assert_eq!(map.remove(&1), Some(10));
+        assert_eq!(
+            slot_keys(&map),
+            [None, Some(9), Some(17), Some(2), None, None, None, None]
+        );
+
+        for key in [9, 17, 2] {
+            assert_eq!(map.get(&key), Some(&(key * 10)));
+        }
+    }
+
+    #[test]
+    fn remove_keeps_entries_in_their_ideal_slot() {
+        // 3 is in its ideal slot, so it must not move into the hole left by 9.
+        let mut map = identity_map(&[1, 9, 3]);
+
+        assert_eq!(map.remove(&1), Some(10));
+        assert_eq!(
+            slot_keys(&map),
+            [None, Some(9), None, Some(3), None, None, None, None]
+        );
+
+        assert_eq!(map.get(&9), Some(&90));
+        assert_eq!(map.get(&3), Some(&30));
+    }
+
+    #[test]
+    fn remove_shifts_across_the_end_of_the_table() {
+        // 7, 15 and 23 all want the last slot, so the probe sequence wraps around.
+        let mut map = identity_map(&[7, 15, 23]);
+        assert_eq!(
+            slot_keys(&map),
+            [Some(15), Some(23), None, None, None, None, None, Some(7)]
+        );
+
+        assert_eq!(map.remove(&7), Some(70));
+        assert_eq!(
+            slot_keys(&map),
+            [Some(23), None, None, None, None, None, None, Some(15)]
+        );
+
+        assert_eq!(map.get(&15), Some(&150));
+        assert_eq!(map.get(&23), Some(&230));
+    }
+
+    #[test]
+    fn grows_and_matches_btree_map() {
+        let mut map = HashMap::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+        let mut expected = BTreeMap::new();
+
+        for i in 0..1000u32 {
+            let key = i.wrapping_mul(2654435761) % 512;
+
+            if i % 3 == 0 {
+                assert_eq!(map.remove(&key), expected.remove(&key));
+            } else {
+                assert_eq!(map.insert(key, i), expected.insert(key, i));
+            }
+
+            assert_eq!(map.len(), expected.len());
+        }
+
+        assert!(map.len() <= max_len(map.slots.len()));
+
+        let mut entries = map.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn entry() {
+        let mut map = HashMap::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+
+        for word in ["a", "b", "a", "c", "a"] {
+            map.entry(word).and_modify(|count| *count += 1).or_insert(1);
+        }
+
+        assert_eq!(map["a"], 3);
+        assert_eq!(map["b"], 1);
+
+        match map.entry("b") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 1),
+            Entry::Vacant(_) => panic!("\"b\" is in the map"),
+        }
+
+        assert_eq!(*map.entry("d").or_default(), 0);
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn retain() {
+        let mut map = identity_map(&[1, 9, 17, 2]);
+        map.retain(|&key, _| key != 1 && key != 17);
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&9), Some(&90));
+        assert_eq!(map.get(&2), Some(&20));
+        assert_eq!(slot_keys(&map).iter().flatten().count(), 2);
+    }
+}
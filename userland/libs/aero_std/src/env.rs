@@ -0,0 +1,208 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Inspection and manipulation of the process's environment.
+//!
+//! The command-line arguments and the initial environment are read from the stack layout set
+//! up by the kernel on `exec`, which `_start` hands over to [`init`]:
+//!
+//! ```text
+//! argc | argv[0] .. argv[argc - 1] | NULL | envp[0] .. | NULL | auxv ..
+//! ```
+//!
+//! Entries that are not valid UTF-8 are converted lossily.
+
+use core::ffi::CStr;
+use core::fmt;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use spin::{Lazy, Mutex};
+
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+static ENVP: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+
+/// The environment of the process, initialized from `envp` on first access.
+static VARS: Lazy<Mutex<Vec<(String, String)>>> = Lazy::new(|| Mutex::new(initial_vars()));
+
+/// Saves the pointers to the command-line arguments and the environment.
+///
+/// ## Safety
+/// `argv` must point to `argc` valid C strings and `envp` must point to a NULL terminated
+/// array of valid C strings. Both must live for the rest of the program.
+#[cfg_attr(test, allow(dead_code))]
+pub(crate) unsafe fn init(argc: usize, argv: *const *const u8, envp: *const *const u8) {
+    ARGC.store(argc, Ordering::SeqCst);
+    ARGV.store(argv.cast_mut(), Ordering::SeqCst);
+    ENVP.store(envp.cast_mut(), Ordering::SeqCst);
+}
+
+fn from_c_str(ptr: *const u8) -> String {
+    // SAFETY: The pointers saved by `init` point to valid C strings.
+    let string = unsafe { CStr::from_ptr(ptr.cast()) };
+    string.to_string_lossy().into_owned()
+}
+
+fn initial_vars() -> Vec<(String, String)> {
+    let mut vars = Vec::new();
+    let mut envp = ENVP.load(Ordering::SeqCst).cast_const();
+
+    if envp.is_null() {
+        return vars;
+    }
+
+    // SAFETY: `envp` is NULL terminated (see `init`).
+    unsafe {
+        while !(*envp).is_null() {
+            let var = from_c_str(*envp);
+
+            // Entries without a `=` are not valid and are skipped.
+            if let Some((key, value)) = var.split_once('=') {
+                vars.push((key.to_string(), value.to_string()));
+            }
+
+            envp = envp.add(1);
+        }
+    }
+
+    vars
+}
+
+/// An iterator over the command-line arguments of the process, starting with the program
+/// name. Returned by [`args`].
+pub struct Args {
+    next: usize,
+    end: usize,
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.next == self.end {
+            return None;
+        }
+
+        let argv = ARGV.load(Ordering::SeqCst).cast_const();
+
+        // SAFETY: `next` is in bounds of `argv` (see `init`).
+        let arg = from_c_str(unsafe { *argv.add(self.next) });
+        self.next += 1;
+
+        Some(arg)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.next;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+/// Returns the command-line arguments of the process.
+pub fn args() -> Args {
+    Args {
+        next: 0,
+        end: ARGC.load(Ordering::SeqCst),
+    }
+}
+
+/// An iterator over a snapshot of the environment variables of the process. Returned by
+/// [`vars`].
+pub struct Vars {
+    inner: alloc::vec::IntoIter<(String, String)>,
+}
+
+impl Iterator for Vars {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<(String, String)> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Returns the `(key, value)` pairs of the environment variables of the process at the time
+/// of the call.
+pub fn vars() -> Vars {
+    Vars {
+        inner: VARS.lock().clone().into_iter(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VarError {
+    /// The environment variable is not set.
+    NotPresent,
+}
+
+impl fmt::Display for VarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarError::NotPresent => f.write_str("environment variable not found"),
+        }
+    }
+}
+
+/// Returns the value of the environment variable `key`.
+pub fn var(key: &str) -> Result<String, VarError> {
+    VARS.lock()
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.clone())
+        .ok_or(VarError::NotPresent)
+}
+
+/// ## Panics
+/// If `key` is empty or contains `=` or NUL, or if `value` contains NUL.
+fn check_var(key: &str, value: &str) {
+    if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+        panic!("failed to set environment variable `{key:?}` to `{value:?}`: invalid argument");
+    }
+}
+
+/// Sets the environment variable `key` to `value`, replacing its previous value.
+///
+/// ## Panics
+/// If `key` is empty or contains `=` or NUL, or if `value` contains NUL.
+pub fn set_var(key: &str, value: &str) {
+    check_var(key, value);
+
+    let mut vars = VARS.lock();
+
+    if let Some((_, v)) = vars.iter_mut().find(|(k, _)| k == key) {
+        *v = value.to_string();
+    } else {
+        vars.push((key.to_string(), value.to_string()));
+    }
+}
+
+/// Removes the environment variable `key`. Does nothing if it is not set.
+///
+/// ## Panics
+/// If `key` is empty or contains `=` or NUL.
+pub fn remove_var(key: &str) {
+    check_var(key, "");
+    VARS.lock().retain(|(k, _)| k != key);
+}
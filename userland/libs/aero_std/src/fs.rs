@@ -0,0 +1,206 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Filesystem access.
+//!
+//! ```ignore
+//! let mut file = File::create("/tmp/hello")?;
+//! file.write_all(b"hello")?;
+//!
+//! assert_eq!(fs::read_to_string("/tmp/hello")?, "hello");
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use aero_syscall::{OpenFlags, Result, SeekWhence};
+
+use crate::io::{Read, Seek, SeekFrom, Write};
+use crate::sys;
+
+/// Permissions of newly created files, before the umask is applied.
+const DEFAULT_MODE: usize = 0o666;
+
+/// An open file, closed on drop.
+#[derive(Debug)]
+pub struct File {
+    fd: usize,
+}
+
+impl File {
+    /// Opens the file at `path` for reading.
+    pub fn open(path: &str) -> Result<File> {
+        OpenOptions::new().read(true).open(path)
+    }
+
+    /// Opens the file at `path` for writing, creating it if it does not exist and truncating
+    /// it otherwise.
+    pub fn create(path: &str) -> Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+    }
+
+    /// Returns a blank set of [`OpenOptions`].
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
+    /// Takes ownership of the open file descriptor `fd`.
+    pub(crate) fn from_raw_fd(fd: usize) -> File {
+        File { fd }
+    }
+
+    /// Returns the underlying file descriptor.
+    pub fn as_raw_fd(&self) -> usize {
+        self.fd
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        sys::sys_read(self.fd, buffer)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        sys::sys_write(self.fd, buffer)
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, position: SeekFrom) -> Result<u64> {
+        let (offset, whence) = match position {
+            SeekFrom::Start(offset) => (offset as isize, SeekWhence::SeekSet),
+            SeekFrom::End(offset) => (offset as isize, SeekWhence::SeekEnd),
+            SeekFrom::Current(offset) => (offset as isize, SeekWhence::SeekCur),
+        };
+
+        sys::sys_seek(self.fd, offset, whence).map(|offset| offset as u64)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        // Errors are ignored: the file descriptor is released either way.
+        let _ = sys::sys_close(self.fd);
+    }
+}
+
+/// Options for how a file is opened, as with `open(2)`.
+#[derive(Debug, Default, Clone)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+}
+
+impl OpenOptions {
+    /// Creates a blank set of options with everything set to `false`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Opens the file for writing with every write going to the end of the file.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Creates the file if it does not exist.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates the file, failing with `EEXIST` if it already exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    pub fn open(&self, path: &str) -> Result<File> {
+        let fd = sys::sys_open(path, self.flags(), DEFAULT_MODE)?;
+        Ok(File { fd })
+    }
+
+    fn flags(&self) -> OpenFlags {
+        let mut flags = match (self.read, self.write || self.append) {
+            (true, true) => OpenFlags::O_RDWR,
+            (false, true) => OpenFlags::O_WRONLY,
+            _ => OpenFlags::O_RDONLY,
+        };
+
+        flags |= OpenFlags::O_CLOEXEC;
+
+        if self.append {
+            flags |= OpenFlags::O_APPEND;
+        }
+
+        if self.truncate {
+            flags |= OpenFlags::O_TRUNC;
+        }
+
+        if self.create_new {
+            flags |= OpenFlags::O_CREAT | OpenFlags::O_EXCL;
+        } else if self.create {
+            flags |= OpenFlags::O_CREAT;
+        }
+
+        flags
+    }
+}
+
+/// Reads the whole file at `path`.
+pub fn read(path: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads the whole file at `path` as a string. Fails with `EILSEQ` if it is not valid UTF-8.
+pub fn read_to_string(path: &str) -> Result<String> {
+    let mut string = String::new();
+    File::open(path)?.read_to_string(&mut string)?;
+    Ok(string)
+}
+
+/// Writes `contents` to the file at `path`, replacing its contents if it already exists.
+pub fn write(path: &str, contents: impl AsRef<[u8]>) -> Result<()> {
+    File::create(path)?.write_all(contents.as_ref())
+}
@@ -0,0 +1,136 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! The global allocator.
+//!
+//! Small allocations are served from per size class free lists, which are refilled with
+//! chunks mapped with `mmap`. Allocations larger than the largest size class are mapped
+//! directly.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+
+use aero_syscall::{MMapFlags, MMapProt};
+use spin::Mutex;
+
+use crate::sys;
+
+const PAGE_SIZE: usize = 4096;
+
+const MIN_CLASS_SIZE: usize = 16;
+const MAX_CLASS_SIZE: usize = 2048;
+const CLASS_COUNT: usize = 8; // 16, 32, .., 2048
+
+/// Size of the chunks the size class free lists are refilled with.
+const CHUNK_SIZE: usize = 16 * PAGE_SIZE;
+
+struct FreeNode {
+    next: *mut FreeNode,
+}
+
+struct FreeLists([*mut FreeNode; CLASS_COUNT]);
+
+// SAFETY: The free lists are only accessed with the lock held.
+unsafe impl Send for FreeLists {}
+
+pub struct Heap {
+    free_lists: Mutex<FreeLists>,
+}
+
+impl Heap {
+    pub const fn new() -> Self {
+        Self {
+            free_lists: Mutex::new(FreeLists([ptr::null_mut(); CLASS_COUNT])),
+        }
+    }
+
+    /// Returns the index of the smallest size class that fits `layout`.
+    fn size_class(layout: Layout) -> Option<usize> {
+        let size = layout
+            .size()
+            .max(layout.align())
+            .max(MIN_CLASS_SIZE)
+            .next_power_of_two();
+
+        if size > MAX_CLASS_SIZE {
+            None
+        } else {
+            Some((size / MIN_CLASS_SIZE).trailing_zeros() as usize)
+        }
+    }
+
+    fn map(size: usize) -> *mut u8 {
+        sys::sys_mmap(
+            size,
+            MMapProt::PROT_READ | MMapProt::PROT_WRITE,
+            MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS,
+        )
+        .unwrap_or(ptr::null_mut())
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some(class) = Self::size_class(layout) else {
+            // Mappings are only page aligned.
+            if layout.align() > PAGE_SIZE {
+                return ptr::null_mut();
+            }
+
+            return Self::map(layout.size().next_multiple_of(PAGE_SIZE));
+        };
+
+        let mut free_lists = self.free_lists.lock();
+        let head = &mut free_lists.0[class];
+
+        if head.is_null() {
+            let chunk = Self::map(CHUNK_SIZE);
+
+            if chunk.is_null() {
+                return ptr::null_mut();
+            }
+
+            // Chunks are page aligned, so every object is aligned to its size.
+            let size = MIN_CLASS_SIZE << class;
+
+            for offset in (0..CHUNK_SIZE).step_by(size).rev() {
+                let node = chunk.add(offset).cast::<FreeNode>();
+                node.write(FreeNode { next: *head });
+                *head = node;
+            }
+        }
+
+        let node = *head;
+        *head = (*node).next;
+
+        node.cast()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let Some(class) = Self::size_class(layout) else {
+            let _ = sys::sys_munmap(ptr, layout.size().next_multiple_of(PAGE_SIZE));
+            return;
+        };
+
+        let mut free_lists = self.free_lists.lock();
+        let head = &mut free_lists.0[class];
+
+        let node = ptr.cast::<FreeNode>();
+        node.write(FreeNode { next: *head });
+        *head = node;
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! I/O traits and the standard output and error streams.
+
+use core::fmt;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use aero_syscall::{Result, SyscallError};
+
+use crate::sys;
+
+const STDOUT_FILENO: usize = 1;
+const STDERR_FILENO: usize = 2;
+
+/// Writes all of `buffer` to `fd`, retrying on short writes.
+fn write_all(fd: usize, mut buffer: &[u8]) -> fmt::Result {
+    while !buffer.is_empty() {
+        match sys::sys_write(fd, buffer) {
+            Ok(0) | Err(_) => return Err(fmt::Error),
+            Ok(written) => buffer = &buffer[written..],
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads bytes from a source.
+pub trait Read {
+    /// Reads some bytes into `buffer` and returns how many were read. Zero is returned at the
+    /// end of the source.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize>;
+
+    /// Reads all bytes until the end of the source and appends them to `buffer`.
+    fn read_to_end(&mut self, buffer: &mut Vec<u8>) -> Result<usize> {
+        let start = buffer.len();
+        let mut chunk = [0u8; 512];
+
+        loop {
+            match self.read(&mut chunk) {
+                Ok(0) => return Ok(buffer.len() - start),
+                Ok(size) => buffer.extend_from_slice(&chunk[..size]),
+                Err(SyscallError::EINTR) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Reads all bytes until the end of the source and appends them to `buffer`. Fails with
+    /// `EILSEQ` if the data is not valid UTF-8, in which case `buffer` is left untouched.
+    fn read_to_string(&mut self, buffer: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let size = self.read_to_end(&mut bytes)?;
+        let string = core::str::from_utf8(&bytes).map_err(|_| SyscallError::EILSEQ)?;
+
+        buffer.push_str(string);
+        Ok(size)
+    }
+}
+
+/// Writes bytes to a sink.
+pub trait Write {
+    /// Writes some bytes from `buffer` and returns how many were written.
+    fn write(&mut self, buffer: &[u8]) -> Result<usize>;
+
+    /// Flushes any buffered data to the sink.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Writes all of `buffer`, retrying on short writes. Fails with `EIO` if the sink stops
+    /// accepting data.
+    fn write_all(&mut self, mut buffer: &[u8]) -> Result<()> {
+        while !buffer.is_empty() {
+            match self.write(buffer) {
+                Ok(0) => return Err(SyscallError::EIO),
+                Ok(written) => buffer = &buffer[written..],
+                Err(SyscallError::EINTR) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Position to seek to, relative to the start, the end or the current position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+/// A cursor that can be moved within a stream of bytes.
+pub trait Seek {
+    /// Moves the cursor to `position` and returns the new offset from the start.
+    fn seek(&mut self, position: SeekFrom) -> Result<u64>;
+
+    /// Returns the current offset from the start.
+    fn stream_position(&mut self) -> Result<u64> {
+        self.seek(SeekFrom::Current(0))
+    }
+
+    /// Moves the cursor back to the start.
+    fn rewind(&mut self) -> Result<()> {
+        self.seek(SeekFrom::Start(0)).map(|_| ())
+    }
+}
+
+pub struct Stdout;
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(STDOUT_FILENO, s.as_bytes())
+    }
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        sys::sys_write(STDOUT_FILENO, buffer)
+    }
+}
+
+pub struct Stderr;
+
+impl fmt::Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(STDERR_FILENO, s.as_bytes())
+    }
+}
+
+impl Write for Stderr {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        sys::sys_write(STDERR_FILENO, buffer)
+    }
+}
+
+pub fn stdout() -> Stdout {
+    Stdout
+}
+
+pub fn stderr() -> Stderr {
+    Stderr
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    fmt::Write::write_fmt(&mut Stdout, args).expect("print: failed to write to stdout");
+}
+
+#[doc(hidden)]
+pub fn _eprint(args: fmt::Arguments) {
+    fmt::Write::write_fmt(&mut Stderr, args).expect("eprint: failed to write to stderr");
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::_eprint(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::eprint!("{}\n", format_args!($($arg)*)));
+}
@@ -0,0 +1,68 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! A minimal standard library for Aero userland programs written without `std`.
+//!
+//! Programs using it are `#![no_std]` and pull in the prelude with:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![feature(prelude_import)]
+//!
+//! #[prelude_import]
+//! use aero_std::prelude::rust_2021::*;
+//!
+//! fn main() {
+//!     println!("hello from {}", env::args().next().unwrap());
+//! }
+//! ```
+//!
+//! A panic prints its message and location and exits with status 101. With the `RUST_BACKTRACE`
+//! environment variable set to anything but `0`, the stack trace is printed as well, which
+//! requires the program to be built with `-C force-frame-pointers=yes`. The userland target
+//! enables it in `build-support/rust/config.toml`.
+//!
+//! The unit tests run on the host with `cargo test`, against the scripted system calls of
+//! `aero_syscall::mock`.
+
+#![no_std]
+#![feature(never_type)]
+#![cfg_attr(not(test), feature(lang_items))]
+#![allow(internal_features)]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+pub mod channel;
+pub mod collections;
+pub mod env;
+pub mod fs;
+pub mod io;
+pub mod net;
+pub mod prelude;
+pub mod process;
+pub mod sync;
+pub mod thread;
+
+#[cfg(not(test))]
+mod backtrace;
+#[cfg(not(test))]
+mod heap;
+#[cfg(not(test))]
+mod rt;
+mod sys;
@@ -0,0 +1,256 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! TCP and UDP networking over IPv4.
+//!
+//! Addresses are `"a.b.c.d:port"` strings. Host names are not resolved, so the IP address has
+//! to be given in dotted-quad notation.
+//!
+//! ```ignore
+//! let mut stream = TcpStream::connect("10.0.2.2:80")?;
+//! stream.write_all(b"GET / HTTP/1.0\r\n\r\n")?;
+//!
+//! let mut response = String::new();
+//! stream.read_to_string(&mut response)?;
+//! ```
+
+use core::fmt;
+use core::str::FromStr;
+
+use aero_syscall::socket::{IoVec, MessageFlags, MessageHeader};
+use aero_syscall::{
+    InAddr, Result, SocketAddrInet, SocketFlags, SocketType, SyscallError, AF_INET,
+};
+
+use crate::fs::File;
+use crate::io::{Read, Write};
+use crate::sys;
+
+/// Maximum number of pending connections of a [`TcpListener`].
+const LISTEN_BACKLOG: usize = 128;
+
+/// An IPv4 address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ipv4Addr([u8; 4]);
+
+impl Ipv4Addr {
+    /// `127.0.0.1`
+    pub const LOCALHOST: Self = Self::new(127, 0, 0, 1);
+    /// `0.0.0.0`, which binds to all of the interfaces.
+    pub const UNSPECIFIED: Self = Self::new(0, 0, 0, 0);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Self {
+        Self([a, b, c, d])
+    }
+
+    pub const fn octets(&self) -> [u8; 4] {
+        self.0
+    }
+}
+
+impl FromStr for Ipv4Addr {
+    type Err = SyscallError;
+
+    /// Parses an address in dotted-quad notation. Fails with `EINVAL` otherwise.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut octets = [0; 4];
+        let mut parts = s.split('.');
+
+        for octet in octets.iter_mut() {
+            *octet = parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or(SyscallError::EINVAL)?;
+        }
+
+        if parts.next().is_some() {
+            return Err(SyscallError::EINVAL);
+        }
+
+        Ok(Self(octets))
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{a}.{b}.{c}.{d}")
+    }
+}
+
+/// An IPv4 address and port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SocketAddrV4 {
+    ip: Ipv4Addr,
+    port: u16,
+}
+
+impl SocketAddrV4 {
+    pub const fn new(ip: Ipv4Addr, port: u16) -> Self {
+        Self { ip, port }
+    }
+
+    pub const fn ip(&self) -> Ipv4Addr {
+        self.ip
+    }
+
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn to_inet(self) -> SocketAddrInet {
+        SocketAddrInet {
+            family: AF_INET,
+            port: self.port.into(),
+            // The address is stored in network byte order.
+            sin_addr: InAddr {
+                addr: u32::from_ne_bytes(self.ip.octets()),
+            },
+            padding: [0; 8],
+        }
+    }
+
+    fn from_inet(address: &SocketAddrInet) -> Self {
+        let ip = address.sin_addr.addr.to_ne_bytes();
+        Self::new(Ipv4Addr(ip), address.port())
+    }
+}
+
+impl FromStr for SocketAddrV4 {
+    type Err = SyscallError;
+
+    /// Parses a `"a.b.c.d:port"` address. Fails with `EINVAL` otherwise.
+    fn from_str(s: &str) -> Result<Self> {
+        let (ip, port) = s.rsplit_once(':').ok_or(SyscallError::EINVAL)?;
+        let port = port.parse().map_err(|_| SyscallError::EINVAL)?;
+
+        Ok(Self::new(ip.parse()?, port))
+    }
+}
+
+impl fmt::Display for SocketAddrV4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.ip, self.port)
+    }
+}
+
+/// Creates an IPv4 socket of the provided type. The socket is closed on `exec`.
+fn socket(socket_type: SocketType) -> Result<File> {
+    let socket_type = socket_type as usize | SocketFlags::CLOEXEC.bits();
+    let fd = sys::sys_socket(AF_INET, socket_type, 0)?;
+
+    Ok(File::from_raw_fd(fd))
+}
+
+/// A TCP connection, closed on drop.
+#[derive(Debug)]
+pub struct TcpStream(File);
+
+impl TcpStream {
+    /// Opens a TCP connection to `addr`.
+    pub fn connect(addr: &str) -> Result<TcpStream> {
+        let address = addr.parse::<SocketAddrV4>()?.to_inet();
+        let socket = socket(SocketType::Stream)?;
+
+        sys::sys_connect(socket.as_raw_fd(), &address)?;
+        Ok(TcpStream(socket))
+    }
+
+    /// Returns the underlying file descriptor.
+    pub fn as_raw_fd(&self) -> usize {
+        self.0.as_raw_fd()
+    }
+}
+
+impl Read for TcpStream {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.0.read(buffer)
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        self.0.write(buffer)
+    }
+}
+
+/// A TCP socket listening for connections, closed on drop.
+#[derive(Debug)]
+pub struct TcpListener(File);
+
+impl TcpListener {
+    /// Creates a TCP socket bound to `addr` and listens for connections on it.
+    pub fn bind(addr: &str) -> Result<TcpListener> {
+        let address = addr.parse::<SocketAddrV4>()?.to_inet();
+        let socket = socket(SocketType::Stream)?;
+
+        sys::sys_bind(socket.as_raw_fd(), &address)?;
+        sys::sys_listen(socket.as_raw_fd(), LISTEN_BACKLOG)?;
+        Ok(TcpListener(socket))
+    }
+
+    /// Blocks until a connection is made and returns it.
+    pub fn accept(&self) -> Result<TcpStream> {
+        let fd = sys::sys_accept(self.as_raw_fd())?;
+        Ok(TcpStream(File::from_raw_fd(fd)))
+    }
+
+    /// Returns the underlying file descriptor.
+    pub fn as_raw_fd(&self) -> usize {
+        self.0.as_raw_fd()
+    }
+}
+
+/// A UDP socket, closed on drop.
+#[derive(Debug)]
+pub struct UdpSocket(File);
+
+impl UdpSocket {
+    /// Creates a UDP socket bound to `addr`.
+    pub fn bind(addr: &str) -> Result<UdpSocket> {
+        let address = addr.parse::<SocketAddrV4>()?.to_inet();
+        let socket = socket(SocketType::Dgram)?;
+
+        sys::sys_bind(socket.as_raw_fd(), &address)?;
+        Ok(UdpSocket(socket))
+    }
+
+    /// Sends `buffer` as a single datagram to `addr` and returns the number of bytes sent.
+    pub fn send_to(&self, buffer: &[u8], addr: &str) -> Result<usize> {
+        let mut address = addr.parse::<SocketAddrV4>()?.to_inet();
+        let mut iovecs = [IoVec::from_slice(buffer)];
+        let mut header = MessageHeader::new(Some(&mut address), &mut iovecs);
+
+        sys::sys_sock_send(self.as_raw_fd(), &mut header, MessageFlags::empty())
+    }
+
+    /// Receives a single datagram into `buffer` and returns its size and the address it was
+    /// sent from. The part of the datagram that does not fit into `buffer` is discarded.
+    pub fn recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddrV4)> {
+        let mut address = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0).to_inet();
+        let mut iovecs = [IoVec::from_slice_mut(buffer)];
+        let mut header = MessageHeader::new(Some(&mut address), &mut iovecs);
+
+        let size = sys::sys_sock_recv(self.as_raw_fd(), &mut header, MessageFlags::empty())?;
+        Ok((size, SocketAddrV4::from_inet(&address)))
+    }
+
+    /// Returns the underlying file descriptor.
+    pub fn as_raw_fd(&self) -> usize {
+        self.0.as_raw_fd()
+    }
+}
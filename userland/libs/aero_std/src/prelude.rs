@@ -15,32 +15,23 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-#[cfg(feature = "ci")]
-use crate::emu;
+//! The `aero_std` prelude, the equivalent of the `std` prelude for programs built on
+//! `aero_std`.
 
-pub struct Test {
-    pub test_fn: fn(),
-    pub path: &'static str,
-}
-
-pub(crate) fn test_runner(tests: &[&Test]) {
-    crate::rendy::clear_screen(true);
-    crate::logger::set_rendy_debug(true);
-
-    log::info!("running {} tests", tests.len());
+pub mod rust_2021 {
+    pub use core::prelude::rust_2021::*;
 
-    let mut passed = 0usize;
+    pub use alloc::borrow::ToOwned;
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
 
-    for test in tests {
-        (test.test_fn)();
-        log::info!("test {} ... ok", test.path);
+    pub use crate::{eprint, eprintln, print, println};
 
-        passed += 1;
-    }
+    pub use crate::fs::{self, File};
+    pub use crate::io::{Read, Seek, Write};
+    pub use crate::sync::{Condvar, Mutex, RwLock};
 
-    log::info!("");
-    log::info!(
-        "test result: ok. {} passed; 0 failed; 0 ignored; 0 measured; 0 filtered out",
-        passed
-    );
+    pub use crate::env;
 }
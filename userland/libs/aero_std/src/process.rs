@@ -0,0 +1,462 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Processes.
+//!
+//! ```ignore
+//! let mut child = Command::new("ls")
+//!     .arg("-l")
+//!     .current_dir("/tmp")
+//!     .stdout(Stdio::piped())
+//!     .spawn()?;
+//!
+//! let mut listing = String::new();
+//! child.stdout.take().unwrap().read_to_string(&mut listing)?;
+//!
+//! assert!(child.wait()?.success());
+//! ```
+
+use core::fmt::Debug;
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use aero_syscall::process::{SpawnOptions, WaitStatus};
+use aero_syscall::signal::SIGABRT;
+use aero_syscall::{OpenFlags, Result, SyscallError, WaitPidFlags};
+
+use crate::fs::{File, OpenOptions};
+use crate::io::{Read, Write};
+use crate::{env, sys};
+
+/// Search path used to look up programs if `PATH` is not set.
+const DEFAULT_PATH: &str = "/usr/bin:/bin";
+
+/// Group database read by [`initgroups`].
+const GROUP_DATABASE: &str = "/etc/group";
+
+/// Terminates the process with the provided exit `code`.
+pub fn exit(code: i32) -> ! {
+    sys::sys_exit(code as usize)
+}
+
+/// Terminates the process abnormally by raising `SIGABRT`.
+pub fn abort() -> ! {
+    let _ = sys::sys_kill(sys::sys_getpid(), SIGABRT);
+
+    // The signal is handled or ignored, exit as if it had killed the process.
+    sys::sys_exit(128 + SIGABRT)
+}
+
+/// Returns the process ID of the calling process.
+pub fn id() -> usize {
+    sys::sys_getpid()
+}
+
+/// Sets the supplementary groups of the calling process to `group` and the groups that list
+/// `user` as a member in `/etc/group`, like `initgroups(3)`. Changing them requires the
+/// `CAP_SETGID` capability.
+pub fn initgroups(user: &str, group: u32) -> Result<()> {
+    let database = match crate::fs::read_to_string(GROUP_DATABASE) {
+        Err(SyscallError::ENOENT) => String::new(),
+        database => database?,
+    };
+
+    let mut groups = Vec::from([group]);
+
+    // Each line is `name:password:gid:member,member,...`.
+    for line in database.lines() {
+        let mut fields = line.split(':');
+
+        let (Some(gid), Some(members)) = (fields.nth(2), fields.next()) else {
+            continue;
+        };
+
+        match gid.parse() {
+            Ok(gid) if !groups.contains(&gid) && members.split(',').any(|m| m == user) => {
+                groups.push(gid)
+            }
+            _ => {}
+        }
+    }
+
+    aero_syscall::process::sys_setgroups(&groups)
+}
+
+enum StdioKind {
+    Inherit,
+    Piped,
+    Null,
+}
+
+/// What to connect a standard stream of a child process to.
+pub struct Stdio(StdioKind);
+
+impl Stdio {
+    /// The child inherits the stream from the parent.
+    pub fn inherit() -> Self {
+        Self(StdioKind::Inherit)
+    }
+
+    /// A new pipe connects the child's stream to the parent, available through the
+    /// `stdin`, `stdout` and `stderr` fields of [`Child`].
+    pub fn piped() -> Self {
+        Self(StdioKind::Piped)
+    }
+
+    /// The stream is connected to `/dev/null`.
+    pub fn null() -> Self {
+        Self(StdioKind::Null)
+    }
+
+    /// Opens the stream, returning the end that is passed to the child and, if piped, the end
+    /// that the parent keeps. `read` is whether the child reads from the stream.
+    fn open(&self, read: bool) -> Result<(Option<File>, Option<File>)> {
+        match self.0 {
+            StdioKind::Inherit => Ok((None, None)),
+
+            StdioKind::Null => {
+                let file = OpenOptions::new()
+                    .read(read)
+                    .write(!read)
+                    .open("/dev/null")?;
+
+                Ok((Some(file), None))
+            }
+
+            StdioKind::Piped => {
+                let (rx, tx) = sys::sys_pipe(OpenFlags::O_CLOEXEC)?;
+                let (rx, tx) = (File::from_raw_fd(rx), File::from_raw_fd(tx));
+
+                Ok(if read {
+                    (Some(rx), Some(tx))
+                } else {
+                    (Some(tx), Some(rx))
+                })
+            }
+        }
+    }
+}
+
+/// A process builder, similar to `std::process::Command`.
+pub struct Command {
+    program: String,
+    args: Vec<String>,
+    envs: Vec<(String, String)>,
+    env_clear: bool,
+    cwd: Option<String>,
+    stdin: Stdio,
+    stdout: Stdio,
+    stderr: Stdio,
+}
+
+impl Command {
+    /// Creates a builder for running `program`. If `program` does not contain a `/`, it is
+    /// looked up in the directories of the `PATH` environment variable.
+    ///
+    /// By default, the child inherits the environment, the working directory and the standard
+    /// streams of the parent.
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_owned(),
+            args: Vec::new(),
+            envs: Vec::new(),
+            env_clear: false,
+            cwd: None,
+            stdin: Stdio::inherit(),
+            stdout: Stdio::inherit(),
+            stderr: Stdio::inherit(),
+        }
+    }
+
+    pub fn arg(&mut self, arg: &str) -> &mut Self {
+        self.args.push(arg.to_owned());
+        self
+    }
+
+    pub fn args<'a>(&mut self, args: impl IntoIterator<Item = &'a str>) -> &mut Self {
+        self.args.extend(args.into_iter().map(str::to_owned));
+        self
+    }
+
+    /// Sets the environment variable `key` to `value` in the child.
+    pub fn env(&mut self, key: &str, value: &str) -> &mut Self {
+        self.envs.retain(|(k, _)| k != key);
+        self.envs.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Does not pass the environment of the parent to the child. Variables set with
+    /// [`Command::env`] are still passed.
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.env_clear = true;
+        self
+    }
+
+    pub fn current_dir(&mut self, dir: &str) -> &mut Self {
+        self.cwd = Some(dir.to_owned());
+        self
+    }
+
+    pub fn stdin(&mut self, stdin: Stdio) -> &mut Self {
+        self.stdin = stdin;
+        self
+    }
+
+    pub fn stdout(&mut self, stdout: Stdio) -> &mut Self {
+        self.stdout = stdout;
+        self
+    }
+
+    pub fn stderr(&mut self, stderr: Stdio) -> &mut Self {
+        self.stderr = stderr;
+        self
+    }
+
+    /// Spawns the program as a child process.
+    ///
+    /// ## Errors
+    ///
+    /// Fails with the error of `fork`, or with the error the child encountered while setting
+    /// up its standard streams and working directory or executing the program.
+    pub fn spawn(&mut self) -> Result<Child> {
+        let (child_stdin, stdin) = self.stdin.open(true)?;
+        let (child_stdout, stdout) = self.stdout.open(false)?;
+        let (child_stderr, stderr) = self.stderr.open(false)?;
+
+        let options = SpawnOptions {
+            stdin: child_stdin.as_ref().map(File::as_raw_fd),
+            stdout: child_stdout.as_ref().map(File::as_raw_fd),
+            stderr: child_stderr.as_ref().map(File::as_raw_fd),
+            cwd: self.cwd.as_deref(),
+            new_process_group: false,
+        };
+
+        let argv = core::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+
+        let env = self.child_env();
+        let envv = env.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let pid = if self.program.contains('/') {
+            aero_syscall::process::spawn(&self.program, &argv, &envv, &options)?
+        } else {
+            self.spawn_from_path(&argv, &envv, &options)?
+        };
+
+        // The child's ends of the pipes are closed here, when they are dropped.
+        Ok(Child {
+            pid,
+            stdin: stdin.map(ChildStdin),
+            stdout: stdout.map(ChildStdout),
+            stderr: stderr.map(ChildStderr),
+        })
+    }
+
+    /// Spawns the program and waits for it to exit.
+    pub fn status(&mut self) -> Result<ExitStatus> {
+        self.spawn()?.wait()
+    }
+
+    /// Returns the environment of the child as `KEY=VALUE` pairs.
+    fn child_env(&self) -> Vec<String> {
+        let inherited = if self.env_clear {
+            None
+        } else {
+            Some(env::vars().filter(|(key, _)| !self.envs.iter().any(|(k, _)| k == key)))
+        };
+
+        inherited
+            .into_iter()
+            .flatten()
+            .chain(self.envs.iter().cloned())
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+
+    /// Tries to spawn the program from each directory of the search path in turn.
+    fn spawn_from_path(
+        &self,
+        argv: &[&str],
+        envv: &[&str],
+        options: &SpawnOptions,
+    ) -> Result<usize> {
+        let search_path = self
+            .envs
+            .iter()
+            .find(|(key, _)| key == "PATH")
+            .map(|(_, value)| value.clone())
+            .or_else(|| env::var("PATH").ok())
+            .unwrap_or_else(|| DEFAULT_PATH.to_owned());
+
+        for dir in search_path.split(':').filter(|dir| !dir.is_empty()) {
+            let path = format!("{dir}/{}", self.program);
+
+            match aero_syscall::process::spawn(&path, argv, envv, options) {
+                Err(SyscallError::ENOENT) | Err(SyscallError::ENOTDIR) => continue,
+                result => return result,
+            }
+        }
+
+        Err(SyscallError::ENOENT)
+    }
+}
+
+/// The exit status of a child process, as reported by `waitpid`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExitStatus(WaitStatus);
+
+impl ExitStatus {
+    /// Returns whether the process exited with a code of zero.
+    pub fn success(&self) -> bool {
+        self.code() == Some(0)
+    }
+
+    /// Returns the exit code of the process, or [`None`] if it was killed by a signal.
+    pub fn code(&self) -> Option<i32> {
+        match self.0 {
+            WaitStatus::Exited(code) => Some(code as i32),
+            _ => None,
+        }
+    }
+
+    /// Returns the signal that killed the process, if any.
+    pub fn signal(&self) -> Option<i32> {
+        match self.0 {
+            WaitStatus::Signaled { signal, .. } => Some(signal as i32),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the process dumped core when it was killed.
+    pub fn core_dumped(&self) -> bool {
+        matches!(
+            self.0,
+            WaitStatus::Signaled {
+                core_dumped: true,
+                ..
+            }
+        )
+    }
+
+    /// Returns the status as shells report it in `$?`, which is 128 plus the signal number for
+    /// processes killed by a signal.
+    pub fn shell_code(&self) -> i32 {
+        self.0.shell_code()
+    }
+}
+
+/// A spawned child process.
+pub struct Child {
+    pid: usize,
+    /// The parent's end of the child's standard input, if it is piped.
+    pub stdin: Option<ChildStdin>,
+    /// The parent's end of the child's standard output, if it is piped.
+    pub stdout: Option<ChildStdout>,
+    /// The parent's end of the child's standard error, if it is piped.
+    pub stderr: Option<ChildStderr>,
+}
+
+impl Child {
+    /// Returns the process ID of the child.
+    pub fn id(&self) -> usize {
+        self.pid
+    }
+
+    /// Waits for the child to exit. The child's standard input is closed first, so that a
+    /// child reading from it does not wait forever.
+    pub fn wait(&mut self) -> Result<ExitStatus> {
+        self.stdin.take();
+
+        let mut status = 0;
+
+        loop {
+            match sys::sys_waitpid(self.pid, &mut status, WaitPidFlags::empty()) {
+                Ok(_) => return Ok(ExitStatus(WaitStatus::from_raw(status))),
+                Err(SyscallError::EINTR) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// The parent's end of a child's piped standard input.
+pub struct ChildStdin(File);
+
+impl Write for ChildStdin {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        self.0.write(buffer)
+    }
+}
+
+/// The parent's end of a child's piped standard output.
+pub struct ChildStdout(File);
+
+impl Read for ChildStdout {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.0.read(buffer)
+    }
+}
+
+/// The parent's end of a child's piped standard error.
+pub struct ChildStderr(File);
+
+impl Read for ChildStderr {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.0.read(buffer)
+    }
+}
+
+/// A trait for the values that can be returned from `main`, converting them into the exit
+/// status of the process.
+#[cfg_attr(not(test), lang = "termination")]
+pub trait Termination {
+    fn report(self) -> i32;
+}
+
+impl Termination for () {
+    fn report(self) -> i32 {
+        0
+    }
+}
+
+impl Termination for ! {
+    fn report(self) -> i32 {
+        self
+    }
+}
+
+impl Termination for i32 {
+    fn report(self) -> i32 {
+        self
+    }
+}
+
+impl<T: Termination, E: Debug> Termination for core::result::Result<T, E> {
+    fn report(self) -> i32 {
+        match self {
+            Ok(value) => value.report(),
+            Err(err) => {
+                crate::eprintln!("Error: {err:?}");
+                1
+            }
+        }
+    }
+}
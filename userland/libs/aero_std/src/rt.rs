@@ -0,0 +1,102 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! The program runtime: the entry point, the heap and the panic handler. Left out of the unit
+//! tests, which run on the host on top of `std`.
+
+use core::arch::global_asm;
+use core::panic::PanicInfo;
+use core::sync::atomic::AtomicBool;
+
+use crate::process::Termination;
+use crate::{backtrace, env, eprintln, heap, sys, thread};
+
+#[global_allocator]
+static HEAP: heap::Heap = heap::Heap::new();
+
+// The kernel enters the program with the stack pointer pointing to `argc`. Clear the frame
+// pointer to terminate backtraces and realign the stack as required by the System V ABI.
+global_asm!(
+    ".global _start",
+    "_start:",
+    "xor rbp, rbp",
+    "mov rdi, rsp",
+    "and rsp, -16",
+    "call {start}",
+    "ud2",
+    start = sym start,
+);
+
+unsafe extern "C" fn start(stack: *const usize) -> ! {
+    extern "C" {
+        // Generated by the compiler for the `main` function of the program, which calls
+        // `lang_start` below.
+        fn main(argc: i32, argv: *const *const u8) -> i32;
+    }
+
+    let argc = *stack;
+    let argv = stack.add(1).cast::<*const u8>();
+    let envp = argv.add(argc + 1);
+
+    env::init(argc, argv, envp);
+
+    let status = main(argc as i32, argv);
+    sys::sys_exit(status as usize)
+}
+
+#[lang = "start"]
+fn lang_start<T: Termination + 'static>(
+    main: fn() -> T,
+    _argc: isize,
+    _argv: *const *const u8,
+    _sigpipe: u8,
+) -> isize {
+    main().report() as isize
+}
+
+/// Set by the first panic of the main thread, so that a panic while reporting it does not
+/// recurse. Other threads keep the flag in their thread control block.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if thread::start_panicking(&PANICKING) {
+        eprintln!("panicked while processing panic: {}", info.message());
+        thread::exit_panicked()
+    }
+
+    match info.location() {
+        Some(location) => eprintln!("panicked at {location}:\n{}", info.message()),
+        None => eprintln!("panicked:\n{}", info.message()),
+    }
+
+    match env::var("RUST_BACKTRACE").as_deref() {
+        Ok("0") | Err(_) => {
+            eprintln!(
+                "note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace"
+            )
+        }
+        Ok(_) => backtrace::print(),
+    }
+
+    // Exits only the calling thread if it is not the main thread, reporting the panic to the
+    // thread that joins it.
+    thread::exit_panicked()
+}
+
+#[lang = "eh_personality"]
+fn rust_eh_personality() {}
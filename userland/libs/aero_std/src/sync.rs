@@ -0,0 +1,596 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Blocking synchronization primitives backed by futexes.
+//!
+//! The locks are taken with a single compare-and-swap on a 32-bit futex word when they are not
+//! contended. Otherwise, the waiting thread sleeps in the kernel with `futex_wait` until the
+//! lock is released and the owner wakes it up with `futex_wake`.
+//!
+//! A panic exits the program, so the locks are never poisoned.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::sys;
+
+/// The mutex is not locked.
+const UNLOCKED: u32 = 0;
+/// The mutex is locked and nobody is waiting for it.
+const LOCKED: u32 = 1;
+/// The mutex is locked and there might be threads waiting for it.
+const CONTENDED: u32 = 2;
+
+/// A mutual exclusion lock protecting shared data.
+pub struct Mutex<T: ?Sized> {
+    futex: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            futex: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Acquires the mutex, sleeping until it is available.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .futex
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
+        }
+
+        MutexGuard { mutex: self }
+    }
+
+    /// Acquires the mutex if it is available.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.futex
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn lock_contended(&self) {
+        // Mark the mutex as contended, as we do not know whether somebody else is waiting for
+        // it. Acquired if it was unlocked.
+        while self.futex.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            // Fails with `EAGAIN` if the mutex was released in the meantime.
+            let _ = sys::sys_futex_wait(&self.futex, CONTENDED);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.futex.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            let _ = sys::sys_futex_wake(&self.futex);
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+
+        d.finish_non_exhaustive()
+    }
+}
+
+/// Releases the [`Mutex`] when dropped.
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Bits of the reader-writer lock state holding the number of readers, or [`WRITE_LOCKED`].
+const READERS_MASK: u32 = (1 << 31) - 1;
+/// The lock is held by a writer.
+const WRITE_LOCKED: u32 = READERS_MASK;
+/// The lock is held and there might be threads waiting for it.
+const WAITING: u32 = 1 << 31;
+/// Maximum number of readers, which keeps the reader count from overflowing into
+/// [`WRITE_LOCKED`].
+const MAX_READERS: u32 = WRITE_LOCKED - 1;
+
+/// A reader-writer lock, allowing any number of readers or a single writer at a time.
+///
+/// The lock is not fair: a steady stream of readers can starve a writer.
+pub struct RwLock<T: ?Sized> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Acquires the lock with shared read access, sleeping while it is held by a writer.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        let mut state = self.state.load(Ordering::Relaxed);
+
+        loop {
+            if state & READERS_MASK < MAX_READERS {
+                match self.state.compare_exchange_weak(
+                    state,
+                    state + 1,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return RwLockReadGuard { lock: self },
+                    Err(current) => state = current,
+                }
+            } else {
+                state = self.wait(state);
+            }
+        }
+    }
+
+    /// Acquires the lock with exclusive write access, sleeping while it is held.
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let mut state = self.state.load(Ordering::Relaxed);
+
+        loop {
+            if state & READERS_MASK == 0 {
+                match self.state.compare_exchange_weak(
+                    state,
+                    state | WRITE_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return RwLockWriteGuard { lock: self },
+                    Err(current) => state = current,
+                }
+            } else {
+                state = self.wait(state);
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Marks the lock as waited for and sleeps until it is released. Returns the new state.
+    fn wait(&self, state: u32) -> u32 {
+        if state & WAITING == 0 {
+            if let Err(current) = self.state.compare_exchange_weak(
+                state,
+                state | WAITING,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                return current;
+            }
+        }
+
+        // Fails with `EAGAIN` if the lock was released in the meantime.
+        let _ = sys::sys_futex_wait(&self.state, state | WAITING);
+        self.state.load(Ordering::Relaxed)
+    }
+
+    fn read_unlock(&self) {
+        let mut state = self.state.load(Ordering::Relaxed);
+
+        loop {
+            // The last reader releases the lock and wakes up the waiters.
+            let new = if state & READERS_MASK == 1 {
+                0
+            } else {
+                state - 1
+            };
+
+            match self
+                .state
+                .compare_exchange_weak(state, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) if new == 0 && state & WAITING != 0 => {
+                    let _ = sys::sys_futex_wake(&self.state);
+                    return;
+                }
+
+                Ok(_) => return,
+                Err(current) => state = current,
+            }
+        }
+    }
+
+    fn write_unlock(&self) {
+        if self.state.swap(0, Ordering::Release) & WAITING != 0 {
+            let _ = sys::sys_futex_wake(&self.state);
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Releases the shared read access of the [`RwLock`] when dropped.
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+/// Releases the exclusive write access of the [`RwLock`] when dropped.
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
+
+/// A condition variable, used to sleep until a [`Mutex`] protected condition becomes true.
+///
+/// Wake ups can be spurious, so the condition has to be checked again after [`Condvar::wait`]
+/// returns:
+///
+/// ```ignore
+/// let mut ready = mutex.lock();
+///
+/// while !*ready {
+///     ready = condvar.wait(ready);
+/// }
+/// ```
+pub struct Condvar {
+    /// Incremented on every notification.
+    futex: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            futex: AtomicU32::new(0),
+        }
+    }
+
+    /// Releases the mutex of `guard`, sleeps until notified and acquires the mutex again.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+
+        // Read before unlocking, so that a notification sent after the unlock changes the futex
+        // word and the wait below returns immediately.
+        let sequence = self.futex.load(Ordering::Relaxed);
+        drop(guard);
+
+        let _ = sys::sys_futex_wait(&self.futex, sequence);
+
+        // The other notified threads might be waiting for the mutex as well.
+        mutex.lock_contended();
+        MutexGuard { mutex }
+    }
+
+    /// Wakes up a thread waiting on the condition variable. The kernel wakes up all of the
+    /// waiters of a futex, so the other waiters see a spurious wake up.
+    pub fn notify_one(&self) {
+        self.notify_all();
+    }
+
+    /// Wakes up all of the threads waiting on the condition variable.
+    pub fn notify_all(&self) {
+        self.futex.fetch_add(1, Ordering::Relaxed);
+        let _ = sys::sys_futex_wake(&self.futex);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::thread;
+
+    use aero_syscall::mock::{self, SyscallCall};
+    use aero_syscall::prelude::*;
+
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn futex_address(futex: &AtomicU32) -> usize {
+        futex as *const AtomicU32 as usize
+    }
+
+    /// Checks that the only system call made was a `futex_wait` on `futex` for `expected`.
+    fn assert_waited(futex: &AtomicU32, expected: u32) {
+        let calls = mock::take_calls();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].number, SYS_FUTEX_WAIT);
+        assert_eq!(
+            calls[0].args[..2],
+            [futex_address(futex), expected as usize]
+        );
+    }
+
+    /// Runs `hold` on another thread, which takes a lock and calls the function it is passed
+    /// while holding it. Returns once the lock is taken, with a `futex_wait` queued that lets the
+    /// thread release the lock. The thread returns the system calls it made.
+    fn hold_lock<F>(hold: F) -> thread::JoinHandle<Vec<SyscallCall>>
+    where
+        F: FnOnce(&dyn Fn()) + Send + 'static,
+    {
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let (released_tx, released_rx) = mpsc::channel();
+
+        let holder = thread::spawn(move || {
+            hold(&|| {
+                locked_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+
+                // For the `futex_wake` made when the lock is released.
+                mock::push_result(0);
+            });
+
+            released_tx.send(()).unwrap();
+            mock::take_calls()
+        });
+
+        locked_rx.recv().unwrap();
+        mock::push_handler(move |_| {
+            release_tx.send(()).unwrap();
+            released_rx.recv().unwrap();
+            0
+        });
+
+        holder
+    }
+
+    #[test]
+    fn mutex_uncontended() {
+        mock::reset();
+        let mutex = Mutex::new(0);
+
+        *mutex.lock() += 1;
+        let guard = mutex.try_lock().unwrap();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+
+        assert_eq!(mutex.into_inner(), 1);
+        assert_eq!(mock::take_calls(), []);
+    }
+
+    #[test]
+    fn mutex_contended() {
+        mock::reset();
+        let mutex = Arc::new(Mutex::new(0));
+
+        let holder = hold_lock({
+            let mutex = mutex.clone();
+
+            move |release| {
+                let mut guard = mutex.lock();
+                *guard = 1;
+                release();
+            }
+        });
+
+        let guard = mutex.lock();
+        assert_eq!(*guard, 1);
+        assert_waited(&mutex.futex, CONTENDED);
+
+        let wake = SyscallCall::new(SYS_FUTEX_WAKE, &[futex_address(&mutex.futex)]);
+        assert_eq!(holder.join().unwrap(), [wake]);
+
+        // The mutex stays marked as contended, as there might be other waiters.
+        mock::push_result(0);
+        drop(guard);
+        assert_eq!(mock::take_calls(), [wake]);
+    }
+
+    #[test]
+    fn rwlock_uncontended() {
+        mock::reset();
+        let lock = RwLock::new(0);
+
+        let first = lock.read();
+        let second = lock.read();
+        assert_eq!(*first + *second, 0);
+        drop((first, second));
+
+        *lock.write() += 1;
+        assert_eq!(*lock.read(), 1);
+        assert_eq!(mock::take_calls(), []);
+    }
+
+    #[test]
+    fn rwlock_read_waits_for_writer() {
+        mock::reset();
+        let lock = Arc::new(RwLock::new(0));
+
+        let holder = hold_lock({
+            let lock = lock.clone();
+
+            move |release| {
+                let mut guard = lock.write();
+                *guard = 1;
+                release();
+            }
+        });
+
+        assert_eq!(*lock.read(), 1);
+        assert_waited(&lock.state, WRITE_LOCKED | WAITING);
+
+        let wake = SyscallCall::new(SYS_FUTEX_WAKE, &[futex_address(&lock.state)]);
+        assert_eq!(holder.join().unwrap(), [wake]);
+    }
+
+    #[test]
+    fn rwlock_write_waits_for_readers() {
+        mock::reset();
+        let lock = Arc::new(RwLock::new(0));
+
+        let holder = hold_lock({
+            let lock = lock.clone();
+
+            move |release| {
+                let _guard = lock.read();
+                release();
+            }
+        });
+
+        *lock.write() = 1;
+        assert_waited(&lock.state, 1 | WAITING);
+
+        let wake = SyscallCall::new(SYS_FUTEX_WAKE, &[futex_address(&lock.state)]);
+        assert_eq!(holder.join().unwrap(), [wake]);
+        assert_eq!(*lock.read(), 1);
+    }
+
+    #[test]
+    fn condvar_wait() {
+        mock::reset();
+        let pair = Arc::new((Mutex::new(false), Condvar::new()));
+
+        // Notify the condition variable while the test thread is asleep.
+        let notifier = pair.clone();
+        mock::push_handler(move |_| {
+            let (ready, condvar) = &*notifier;
+            *ready.lock() = true;
+
+            mock::push_result(0);
+            condvar.notify_one();
+            0
+        });
+
+        let (ready, condvar) = &*pair;
+        let mut guard = ready.lock();
+
+        while !*guard {
+            guard = condvar.wait(guard);
+        }
+
+        // Reacquiring the mutex marks it as contended.
+        mock::push_result(0);
+        drop(guard);
+
+        let calls = mock::take_calls();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0].number, SYS_FUTEX_WAIT);
+        assert_eq!(calls[0].args[..2], [futex_address(&condvar.futex), 0]);
+        assert_eq!(
+            calls[1..],
+            [
+                SyscallCall::new(SYS_FUTEX_WAKE, &[futex_address(&condvar.futex)]),
+                SyscallCall::new(SYS_FUTEX_WAKE, &[futex_address(&ready.futex)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Thin wrappers around the raw system calls used by `aero_std`.
+
+use core::sync::atomic::AtomicU32;
+
+use aero_syscall::prelude::*;
+use aero_syscall::socket::{MessageFlags, MessageHeader};
+use aero_syscall::{
+    isize_as_syscall_result, MMapFlags, MMapProt, OpenFlags, Result, SeekWhence, SocketAddr,
+    TimeSpec, WaitPidFlags,
+};
+
+pub use aero_syscall::{sys_close, sys_munmap, sys_open};
+
+pub fn sys_read(fd: usize, buffer: &mut [u8]) -> Result<usize> {
+    let value = syscall3(SYS_READ, fd, buffer.as_mut_ptr() as usize, buffer.len());
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_seek(fd: usize, offset: isize, whence: SeekWhence) -> Result<usize> {
+    let value = syscall3(SYS_SEEK, fd, offset as usize, whence as usize);
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_write(fd: usize, buffer: &[u8]) -> Result<usize> {
+    let value = syscall3(SYS_WRITE, fd, buffer.as_ptr() as usize, buffer.len());
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_pipe(flags: OpenFlags) -> Result<(usize, usize)> {
+    let mut fds = [0i32; 2];
+    let value = syscall2(SYS_PIPE, fds.as_mut_ptr() as usize, flags.bits());
+    isize_as_syscall_result(value as _).map(|_| (fds[0] as usize, fds[1] as usize))
+}
+
+pub fn sys_socket(domain: u32, socket_type: usize, protocol: usize) -> Result<usize> {
+    let value = syscall3(SYS_SOCKET, domain as usize, socket_type, protocol);
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_bind<T: SocketAddr>(fd: usize, address: &T) -> Result<()> {
+    let size = core::mem::size_of::<T>();
+    let value = syscall3(SYS_BIND, fd, address as *const T as usize, size);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+pub fn sys_connect<T: SocketAddr>(fd: usize, address: &T) -> Result<()> {
+    let size = core::mem::size_of::<T>();
+    let value = syscall3(SYS_CONNECT, fd, address as *const T as usize, size);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> Result<()> {
+    let value = syscall2(SYS_LISTEN, fd, backlog);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+pub fn sys_accept(fd: usize) -> Result<usize> {
+    let value = syscall3(SYS_ACCEPT, fd, 0, 0);
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_sock_send(fd: usize, header: &mut MessageHeader, flags: MessageFlags) -> Result<usize> {
+    let value = syscall3(
+        SYS_SOCK_SEND,
+        fd,
+        header as *mut MessageHeader as usize,
+        flags.bits(),
+    );
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_sock_recv(fd: usize, header: &mut MessageHeader, flags: MessageFlags) -> Result<usize> {
+    let value = syscall3(
+        SYS_SOCK_RECV,
+        fd,
+        header as *mut MessageHeader as usize,
+        flags.bits(),
+    );
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_getpid() -> usize {
+    syscall0(SYS_GETPID)
+}
+
+pub fn sys_kill(pid: usize, signal: usize) -> Result<()> {
+    let value = syscall2(SYS_KILL, pid, signal);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+pub fn sys_waitpid(pid: usize, status: &mut u32, flags: WaitPidFlags) -> Result<usize> {
+    let value = syscall3(SYS_WAITPID, pid, status as *mut u32 as usize, flags.bits());
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_exit(status: usize) -> ! {
+    syscall1(SYS_EXIT, status);
+    unreachable!("sys_exit: returned")
+}
+
+pub fn sys_mmap(size: usize, protection: MMapProt, flags: MMapFlags) -> Result<*mut u8> {
+    aero_syscall::sys_mmap(0, size, protection, flags, usize::MAX, 0)
+}
+
+/// Sleeps until `word` is woken up with [`sys_futex_wake`], unless it no longer contains
+/// `expected`. Fails with `EAGAIN` in that case.
+pub fn sys_futex_wait(word: &AtomicU32, expected: u32) -> Result<()> {
+    // The kernel ignores the timeout, but requires it to be valid.
+    let timeout = TimeSpec::default();
+    let value = syscall3(
+        SYS_FUTEX_WAIT,
+        word.as_ptr() as usize,
+        expected as usize,
+        &timeout as *const TimeSpec as usize,
+    );
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Wakes up all of the threads waiting on `word`.
+pub fn sys_futex_wake(word: &AtomicU32) -> Result<()> {
+    let value = syscall1(SYS_FUTEX_WAKE, word.as_ptr() as usize);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+/// Starts a thread in the address space of the calling process at `entry`, with the stack
+/// pointer set to `stack`, and returns its thread ID.
+pub fn sys_clone(entry: usize, stack: usize) -> Result<usize> {
+    let value = syscall2(SYS_CLONE, entry, stack);
+    isize_as_syscall_result(value as _)
+}
+
+pub fn sys_gettid() -> usize {
+    syscall0(SYS_GETTID)
+}
+
+pub fn sys_sleep(duration: &TimeSpec) -> Result<()> {
+    let value = syscall1(SYS_SLEEP, duration as *const TimeSpec as usize);
+    isize_as_syscall_result(value as _).map(|_| ())
+}
+
+pub fn sys_arch_prctl(command: usize, address: usize) -> Result<usize> {
+    let value = syscall2(SYS_ARCH_PRCTL, command, address);
+    isize_as_syscall_result(value as _)
+}
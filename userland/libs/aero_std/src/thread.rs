@@ -0,0 +1,311 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Native threads.
+//!
+//! A thread runs in the address space of the process on a stack of [`STACK_SIZE`] bytes. Its
+//! FS base points to its thread control block, which is placed at the top of the stack mapping.
+//!
+//! ```ignore
+//! let counter = Arc::new(Mutex::new(0));
+//!
+//! let handles = (0..4)
+//!     .map(|_| {
+//!         let counter = counter.clone();
+//!         thread::spawn(move || *counter.lock() += 1)
+//!     })
+//!     .collect::<Vec<_>>();
+//!
+//! for handle in handles {
+//!     handle.join().unwrap();
+//! }
+//!
+//! assert_eq!(*counter.lock(), 4);
+//! ```
+
+use core::arch::{asm, global_asm};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::offset_of;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::time::Duration;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use aero_syscall::consts::{SYS_EXIT, SYS_FUTEX_WAKE};
+use aero_syscall::{MMapFlags, MMapProt, TimeSpec, ARCH_GET_FS, ARCH_SET_FS};
+
+use crate::sys;
+
+/// Size of the stack mapping of a thread, including its thread control block.
+pub const STACK_SIZE: usize = 256 * 1024;
+
+/// Exit status of a thread that panicked.
+const PANIC_EXIT_STATUS: usize = 101;
+
+/// Values of [`ThreadControlBlock::done`].
+const RUNNING: u32 = 0;
+const FINISHED: u32 = 1;
+const PANICKED: u32 = 2;
+
+/// The result of [`JoinHandle::join`].
+pub type Result<T> = core::result::Result<T, Panicked>;
+
+/// The error returned by [`JoinHandle::join`] if the thread panicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Panicked;
+
+impl fmt::Display for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the joined thread panicked")
+    }
+}
+
+/// The thread control block, at the top of the stack mapping of a thread.
+#[repr(C)]
+struct ThreadControlBlock {
+    /// Points to the thread control block itself, as required by the x86_64 TLS ABI (`fs:0`).
+    this: *mut ThreadControlBlock,
+    /// Set to [`FINISHED`] or [`PANICKED`] once the thread has finished. Only written by the
+    /// thread after the last use of its stack, so the stack can be unmapped once it is set.
+    done: AtomicU32,
+    /// Set by the first panic of the thread, so that a panic while reporting it does not recurse.
+    panicking: AtomicBool,
+}
+
+/// The result of a thread, shared between the thread and its [`JoinHandle`].
+struct Packet<T> {
+    result: UnsafeCell<Option<T>>,
+}
+
+// The result is only written by the thread and only read after the thread has finished.
+unsafe impl<T: Send> Sync for Packet<T> {}
+
+/// Passed to the thread on its stack.
+struct Start {
+    main: Box<dyn FnOnce()>,
+    tcb: *mut ThreadControlBlock,
+}
+
+// The kernel starts the thread with the stack pointer pointing to the `Start` pointer. Clear the
+// frame pointer to terminate backtraces and realign the stack as required by the System V ABI.
+global_asm!(
+    ".global aero_std_thread_start",
+    "aero_std_thread_start:",
+    "xor rbp, rbp",
+    "mov rdi, [rsp]",
+    "and rsp, -16",
+    "call {start}",
+    "ud2",
+    start = sym thread_start,
+);
+
+extern "C" {
+    fn aero_std_thread_start();
+}
+
+unsafe extern "C" fn thread_start(start: *mut Start) -> ! {
+    let Start { main, tcb } = *Box::from_raw(start);
+
+    let _ = sys::sys_arch_prctl(ARCH_SET_FS, tcb as usize);
+    main();
+
+    finish(tcb, FINISHED, 0)
+}
+
+/// Sets `done` of the thread to `state`, wakes up the thread that joins it and exits it with
+/// `status`.
+unsafe fn finish(tcb: *mut ThreadControlBlock, state: u32, status: usize) -> ! {
+    // Exit without touching the stack anymore, as the thread that joins this one unmaps it as
+    // soon as `done` is set.
+    let done = (tcb as *mut u8).add(offset_of!(ThreadControlBlock, done));
+
+    asm!(
+        "mov dword ptr [rdi], esi",
+        "mov rax, {futex_wake}",
+        "syscall",
+        "mov rax, {exit}",
+        "mov rdi, rdx",
+        "syscall",
+        "ud2",
+        futex_wake = const SYS_FUTEX_WAKE,
+        exit = const SYS_EXIT,
+        in("rdi") done,
+        in("esi") state,
+        in("rdx") status,
+        options(noreturn, nostack),
+    );
+}
+
+/// Returns the thread control block of the calling thread, or `None` on the main thread, which
+/// has none.
+fn current_tcb() -> Option<*mut ThreadControlBlock> {
+    match sys::sys_arch_prctl(ARCH_GET_FS, 0) {
+        Ok(0) | Err(_) => None,
+        Ok(base) => Some(base as *mut ThreadControlBlock),
+    }
+}
+
+/// Marks the calling thread as panicking and returns whether it already was. Falls back to
+/// `main_panicking` on the main thread.
+#[cfg_attr(test, allow(dead_code))]
+pub(crate) fn start_panicking(main_panicking: &AtomicBool) -> bool {
+    let panicking = match current_tcb() {
+        // SAFETY: The stack mapping of the calling thread is mapped while it runs.
+        Some(tcb) => unsafe { &(*tcb).panicking },
+        None => main_panicking,
+    };
+
+    panicking.swap(true, Ordering::SeqCst)
+}
+
+/// Exits the calling thread after a panic, reporting the panic to the thread that joins it. On
+/// the main thread, exits the process.
+#[cfg_attr(test, allow(dead_code))]
+pub(crate) fn exit_panicked() -> ! {
+    match current_tcb() {
+        // SAFETY: The thread control block belongs to the calling thread.
+        Some(tcb) => unsafe { finish(tcb, PANICKED, PANIC_EXIT_STATUS) },
+        None => sys::sys_exit(PANIC_EXIT_STATUS),
+    }
+}
+
+/// An owned permission to join on a thread. The thread is detached when the handle is dropped,
+/// in which case its stack is never unmapped.
+pub struct JoinHandle<T> {
+    id: usize,
+    stack: *mut u8,
+    tcb: *mut ThreadControlBlock,
+    packet: Arc<Packet<T>>,
+}
+
+unsafe impl<T: Send> Send for JoinHandle<T> {}
+unsafe impl<T: Send> Sync for JoinHandle<T> {}
+
+impl<T> JoinHandle<T> {
+    /// Returns the thread ID of the thread.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns `true` if the thread has finished, including by panicking.
+    pub fn is_finished(&self) -> bool {
+        self.done().load(Ordering::Acquire) != RUNNING
+    }
+
+    /// Waits for the thread to finish and returns its result, or [`Panicked`] if it panicked.
+    pub fn join(self) -> Result<T> {
+        while !self.is_finished() {
+            // Fails with `EAGAIN` if the thread finished in the meantime.
+            let _ = sys::sys_futex_wait(self.done(), RUNNING);
+        }
+
+        let state = self.done().load(Ordering::Acquire);
+        let _ = sys::sys_munmap(self.stack, STACK_SIZE);
+
+        if state == PANICKED {
+            return Err(Panicked);
+        }
+
+        // SAFETY: The thread has finished, so the result is no longer accessed by it.
+        Ok(
+            unsafe { (*self.packet.result.get()).take() }
+                .expect("thread finished without a result"),
+        )
+    }
+
+    fn done(&self) -> &AtomicU32 {
+        // SAFETY: The stack mapping is only unmapped by `join`, which consumes the handle.
+        unsafe { &(*self.tcb).done }
+    }
+}
+
+/// Spawns a new thread running `f` and returns a [`JoinHandle`] for it.
+///
+/// ## Panics
+///
+/// Panics if the stack of the thread cannot be allocated or the thread cannot be started.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let stack = sys::sys_mmap(
+        STACK_SIZE,
+        MMapProt::PROT_READ | MMapProt::PROT_WRITE,
+        MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS,
+    )
+    .expect("failed to allocate the thread stack");
+
+    let packet = Arc::new(Packet {
+        result: UnsafeCell::new(None),
+    });
+
+    // SAFETY: The stack mapping is large enough for the thread control block and the start
+    // pointer below it, and mappings are page aligned.
+    unsafe {
+        let tcb = stack
+            .add(STACK_SIZE - core::mem::size_of::<ThreadControlBlock>())
+            .cast::<ThreadControlBlock>();
+
+        tcb.write(ThreadControlBlock {
+            this: tcb,
+            done: AtomicU32::new(RUNNING),
+            panicking: AtomicBool::new(false),
+        });
+
+        let thread_packet = packet.clone();
+        let main = Box::new(move || {
+            let result = f();
+            *thread_packet.result.get() = Some(result);
+        });
+
+        let start = Box::into_raw(Box::new(Start { main, tcb }));
+
+        // Keep the stack pointer 16-byte aligned below the thread control block.
+        let stack_top = (tcb as usize & !0xf) - 16;
+        (stack_top as *mut *mut Start).write(start);
+
+        match sys::sys_clone(aero_std_thread_start as *const () as usize, stack_top) {
+            Ok(id) => JoinHandle {
+                id,
+                stack,
+                tcb,
+                packet,
+            },
+
+            Err(err) => {
+                drop(Box::from_raw(start));
+                let _ = sys::sys_munmap(stack, STACK_SIZE);
+
+                panic!("failed to spawn a thread: {err}");
+            }
+        }
+    }
+}
+
+/// Returns the thread ID of the calling thread.
+pub fn current_id() -> usize {
+    sys::sys_gettid()
+}
+
+/// Puts the calling thread to sleep for at least `duration`. The kernel rounds the duration up
+/// to whole seconds.
+pub fn sleep(duration: Duration) {
+    let _ = sys::sys_sleep(&TimeSpec::from(duration));
+}
@@ -16,9 +16,8 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 use std::error::Error;
-use std::process::Command;
-
 use aero_ipc::{SystemService, SystemServiceError, SystemServiceResult};
+use aero_syscall::process::{self, SpawnOptions};
 use aero_syscall::*;
 use hashbrown::hash_map::Entry;
 use hashbrown::HashMap;
@@ -29,13 +28,25 @@ fn main() -> core::result::Result<(), Box<dyn Error>> {
 
     aero_ipc::listen(SystemService::handler(SystemServer::new()));
 
-    Command::new("/usr/bin/window_server").spawn()?;
+    spawn_window_server()?;
 
     loop {
         aero_ipc::service_request();
     }
 }
 
+fn spawn_window_server() -> core::result::Result<usize, Box<dyn Error>> {
+    const PATH: &str = "/usr/bin/window_server";
+
+    let env = std::env::vars()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>();
+    let envv = env.iter().map(String::as_str).collect::<Vec<_>>();
+
+    process::spawn(PATH, &[PATH], &envv, &SpawnOptions::default())
+        .map_err(|err| format!("failed to spawn {PATH}: {err}").into())
+}
+
 struct SystemServer {
     services: RwLock<HashMap<String, usize>>,
 }
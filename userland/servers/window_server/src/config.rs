@@ -0,0 +1,256 @@
+// Copyright (C) 2021-2024 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Parser for `/etc/winserver.conf`.
+//!
+//! The file is made of `key=value` lines. Blank lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # Shortcuts are written as `<modifier>+...+<key>=<action>`.
+//! alt+tab=cycle_focus
+//! alt+f4=close_window
+//! super+enter=spawn_terminal
+//!
+//! # Modifiers to hold down to move a window by dragging it with the left button.
+//! move_modifier=alt
+//! # Program spawned by the `spawn_terminal` action.
+//! terminal=/usr/bin/window_test
+//! ```
+
+use core::ops::BitOr;
+
+pub const CONFIG_PATH: &str = "/etc/winserver.conf";
+
+/// A set of modifier keys.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const ALT: Self = Self(1 << 0);
+    pub const CTRL: Self = Self(1 << 1);
+    pub const NONE: Self = Self(0);
+    pub const SHIFT: Self = Self(1 << 2);
+    pub const SUPER: Self = Self(1 << 3);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, other: Self, value: bool) {
+        if value {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "alt" => Some(Self::ALT),
+            "ctrl" => Some(Self::CTRL),
+            "shift" => Some(Self::SHIFT),
+            "super" => Some(Self::SUPER),
+            _ => None,
+        }
+    }
+
+    fn parse(names: &str) -> Option<Self> {
+        names
+            .split('+')
+            .try_fold(Self::NONE, |acc, name| Some(acc | Self::from_name(name)?))
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Focuses the next window in the stacking order.
+    CycleFocus,
+    /// Sends [`aero_ipc::InputEvent::CLOSE`] to the focused window.
+    CloseWindow,
+    /// Spawns [`Config::terminal`].
+    SpawnTerminal,
+}
+
+impl Action {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "cycle_focus" => Some(Self::CycleFocus),
+            "close_window" => Some(Self::CloseWindow),
+            "spawn_terminal" => Some(Self::SpawnTerminal),
+            _ => None,
+        }
+    }
+}
+
+/// A key pressed while exactly `modifiers` are held down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shortcut {
+    pub modifiers: Modifiers,
+    /// The PS/2 (set 1) make code of the key.
+    pub scancode: u8,
+}
+
+impl Shortcut {
+    fn parse(keys: &str) -> Option<Self> {
+        let (modifiers, key) = match keys.rsplit_once('+') {
+            Some((modifiers, key)) => (Modifiers::parse(modifiers)?, key),
+            None => (Modifiers::NONE, keys),
+        };
+
+        Some(Self {
+            modifiers,
+            scancode: scancode(key)?,
+        })
+    }
+}
+
+/// Returns the make code of the key called `name`.
+fn scancode(name: &str) -> Option<u8> {
+    const ROWS: [(&str, u8); 4] = [
+        ("1234567890", 0x02),
+        ("qwertyuiop", 0x10),
+        ("asdfghjkl", 0x1e),
+        ("zxcvbnm", 0x2c),
+    ];
+
+    let code = match name {
+        "escape" => 0x01,
+        "backspace" => 0x0e,
+        "tab" => 0x0f,
+        "enter" => 0x1c,
+        "space" => 0x39,
+        "f11" => 0x57,
+        "f12" => 0x58,
+
+        _ => {
+            if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                return (1..=10).contains(&n).then(|| 0x3a + n);
+            }
+
+            let mut chars = name.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return None;
+            };
+
+            return ROWS
+                .iter()
+                .find_map(|(row, first)| Some(first + row.find(c)? as u8));
+        }
+    };
+
+    Some(code)
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub shortcuts: Vec<(Shortcut, Action)>,
+    pub move_modifier: Modifiers,
+    pub terminal: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let shortcut = |keys| Shortcut::parse(keys).unwrap();
+
+        Self {
+            shortcuts: vec![
+                (shortcut("alt+tab"), Action::CycleFocus),
+                (shortcut("alt+f4"), Action::CloseWindow),
+                (shortcut("super+enter"), Action::SpawnTerminal),
+            ],
+            move_modifier: Modifiers::ALT,
+            terminal: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration from [`CONFIG_PATH`], falling back to the defaults if it does not
+    /// exist. Invalid lines are reported and skipped.
+    pub fn load() -> Self {
+        match std::fs::read_to_string(CONFIG_PATH) {
+            Ok(text) => Self::parse(&text),
+            Err(err) => {
+                println!("[window_server] using the default configuration: {CONFIG_PATH}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut config = Self {
+            shortcuts: vec![],
+            ..Self::default()
+        };
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Err(err) = config.parse_line(line) {
+                println!("[window_server] {CONFIG_PATH}:{}: {err}", i + 1);
+            }
+        }
+
+        config
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| format!("expected `key=value`, found `{line}`"))?;
+
+        match key {
+            "terminal" => self.terminal = Some(value.to_owned()),
+
+            "move_modifier" => {
+                self.move_modifier = Modifiers::parse(value)
+                    .ok_or_else(|| format!("invalid modifiers `{value}`"))?;
+            }
+
+            _ => {
+                let shortcut =
+                    Shortcut::parse(key).ok_or_else(|| format!("invalid shortcut `{key}`"))?;
+                let action =
+                    Action::parse(value).ok_or_else(|| format!("unknown action `{value}`"))?;
+
+                self.shortcuts.retain(|(other, _)| *other != shortcut);
+                self.shortcuts.push((shortcut, action));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn action(&self, modifiers: Modifiers, scancode: u8) -> Option<Action> {
+        self.shortcuts.iter().find_map(|(shortcut, action)| {
+            (shortcut.modifiers == modifiers && shortcut.scancode == scancode).then_some(*action)
+        })
+    }
+}
@@ -15,9 +15,27 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_ipc::{SystemService, WindowService};
+mod config;
+
+use std::sync::Arc;
+
+use aero_ipc::spsc::SharedSpsc;
+use aero_ipc::{InputEvent, SystemService, WindowInfo, WindowService};
+use aero_syscall::consts::{FramebufferVScreenInfo, FBIOGET_VSCREENINFO};
+use aero_syscall::process::{self, SpawnOptions};
 use aero_syscall::sys_ipc_discover_root;
 
+use config::{Action, Config, Modifiers};
+
+/// Number of input events buffered for a window before new ones are dropped.
+const INPUT_QUEUE_SIZE: usize = 256;
+
+/// Size of the screen if it cannot be queried from the framebuffer.
+const DEFAULT_SCREEN_SIZE: (u32, u32) = (1024, 768);
+
+/// Size of newly created windows.
+const DEFAULT_WINDOW_SIZE: (u32, u32) = (640, 480);
+
 fn main() {
     let self_pid = unsafe { libc::getpid() as usize };
     let ipc_root = sys_ipc_discover_root().unwrap();
@@ -25,19 +43,477 @@ fn main() {
 
     system_client.announce(self_pid, "WindowServer").unwrap();
 
-    aero_ipc::listen(WindowService::handler(WindowServer));
+    let server = WindowServer::new(screen_size().unwrap_or(DEFAULT_SCREEN_SIZE));
+    let manager = WindowManager::new(server.windows.clone(), server.screen, Config::load());
+
+    std::thread::spawn(move || manager.run());
+
+    aero_ipc::listen(WindowService::handler(server));
 
     loop {
         aero_ipc::service_request();
     }
 }
 
-struct WindowServer;
+/// Returns the resolution of `/dev/fb0`.
+fn screen_size() -> Option<(u32, u32)> {
+    let fd = unsafe { libc::open(c"/dev/fb0".as_ptr(), libc::O_RDONLY) };
+
+    if fd < 0 {
+        return None;
+    }
+
+    let mut info = FramebufferVScreenInfo::default();
+    let result = unsafe { libc::ioctl(fd, FBIOGET_VSCREENINFO as _, &mut info) };
+
+    unsafe { libc::close(fd) };
+    (result == 0).then_some((info.xres, info.yres))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        (self.x..self.x + self.width as i32).contains(&x)
+            && (self.y..self.y + self.height as i32).contains(&y)
+    }
+}
+
+struct Window {
+    id: usize,
+    name: String,
+    input: Option<SharedSpsc<InputEvent>>,
+    rect: Rect,
+}
+
+impl Window {
+    fn send(&mut self, event: InputEvent) -> bool {
+        self.input.as_mut().is_some_and(|queue| queue.push(event))
+    }
+}
+
+#[derive(Default)]
+struct Windows {
+    /// The windows from the bottom to the top of the stack. The top one has the focus.
+    stack: Vec<Window>,
+    next_id: usize,
+}
+
+impl Windows {
+    fn position(&self, id: usize) -> Option<usize> {
+        self.stack.iter().position(|window| window.id == id)
+    }
+
+    fn focused(&mut self) -> Option<&mut Window> {
+        self.stack.last_mut()
+    }
+
+    /// Returns the position of the topmost window under the point.
+    fn window_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.stack
+            .iter()
+            .rposition(|window| window.rect.contains(x, y))
+    }
+
+    /// Raises the window at `position` to the top of the stack, giving it the focus.
+    fn focus(&mut self, position: usize) {
+        if position + 1 != self.stack.len() {
+            let window = self.stack.remove(position);
+
+            self.stack.push(window);
+            self.changed();
+        }
+    }
+
+    /// Focuses the bottom window, so that repeating it goes through every window.
+    fn cycle_focus(&mut self) {
+        if self.stack.len() > 1 {
+            self.focus(0);
+        }
+    }
+
+    /// Tells every window that the window list has changed.
+    fn changed(&mut self) {
+        for window in self.stack.iter_mut() {
+            // Clients that do not drain their queue only miss the notification.
+            window.send(InputEvent::windows_changed());
+        }
+    }
+}
+
+struct WindowServer {
+    windows: Arc<spin::Mutex<Windows>>,
+    screen: (u32, u32),
+}
+
+impl WindowServer {
+    fn new(screen: (u32, u32)) -> Self {
+        Self {
+            windows: Arc::default(),
+            screen,
+        }
+    }
+}
 
 impl WindowService::Server for WindowServer {
     fn create_window(&self, name: &str) -> usize {
         println!("[window_server] creating window with name: {}", name);
 
-        0
+        let input = SharedSpsc::new(INPUT_QUEUE_SIZE)
+            .map_err(|err| {
+                println!("[window_server] failed to create the input queue of {name}: {err}")
+            })
+            .ok();
+
+        let mut windows = self.windows.lock();
+
+        let id = windows.next_id;
+        windows.next_id += 1;
+
+        // Cascade the new windows so that they do not hide each other completely.
+        let offset = 32 * (id % 8) as i32;
+        let (width, height) = DEFAULT_WINDOW_SIZE;
+
+        windows.stack.push(Window {
+            id,
+            name: name.to_owned(),
+            input,
+            rect: Rect {
+                x: offset,
+                y: offset,
+                width,
+                height,
+            },
+        });
+
+        windows.changed();
+        id
+    }
+
+    fn destroy_window(&self, window: usize) -> bool {
+        let mut windows = self.windows.lock();
+        let Some(position) = windows.position(window) else {
+            return false;
+        };
+
+        windows.stack.remove(position);
+        windows.changed();
+        true
+    }
+
+    fn input_queue(&self, window: usize) -> Option<usize> {
+        let windows = self.windows.lock();
+        let position = windows.position(window)?;
+
+        windows.stack[position]
+            .input
+            .as_ref()
+            .map(|queue| queue.fd())
+    }
+
+    fn list_windows(&self) -> Vec<WindowInfo> {
+        let windows = self.windows.lock();
+        let focused = windows.stack.last().map(|window| window.id);
+
+        windows
+            .stack
+            .iter()
+            .map(|window| WindowInfo {
+                id: window.id,
+                name: window.name.clone(),
+                focused: Some(window.id) == focused,
+            })
+            .collect()
+    }
+
+    fn focus_window(&self, window: usize) -> bool {
+        let mut windows = self.windows.lock();
+        let Some(position) = windows.position(window) else {
+            return false;
+        };
+
+        windows.focus(position);
+        true
+    }
+
+    fn move_window(&self, window: usize, x: i32, y: i32, width: u32, height: u32) -> bool {
+        let mut windows = self.windows.lock();
+        let Some(position) = windows.position(window) else {
+            return false;
+        };
+
+        windows.stack[position].rect = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        windows.changed();
+        true
+    }
+
+    fn screen_size(&self) -> (u32, u32) {
+        self.screen
+    }
+}
+
+/// Mouse packet, as read from `/dev/mouse0`.
+#[repr(C)]
+#[derive(Default)]
+struct MousePacket {
+    x: i16,
+    y: i16,
+    flags: u8,
+}
+
+fn open_device(path: &std::ffi::CStr) -> Option<libc::pollfd> {
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) };
+
+    if fd < 0 {
+        println!("[window_server] failed to open {path:?}");
+        return None;
+    }
+
+    Some(libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    })
+}
+
+/// Scancode prefix of the extended keys.
+const EXTENDED: u8 = 0xe0;
+/// Bit set in the scancode of a key release.
+const RELEASE: u8 = 0x80;
+const LEFT_BUTTON: u32 = 1;
+
+/// A window being moved with the mouse.
+struct Drag {
+    window: usize,
+    /// Position of the cursor relative to the window when the drag started.
+    grab: (i32, i32),
+}
+
+/// Routes the keyboard and mouse input to the focused window, except for the window management
+/// shortcuts and mouse actions which are handled here.
+struct WindowManager {
+    windows: Arc<spin::Mutex<Windows>>,
+    config: Config,
+    screen: (u32, u32),
+    cursor: (i32, i32),
+    buttons: u32,
+    modifiers: Modifiers,
+    /// Whether the previous scancode was [`EXTENDED`].
+    extended: bool,
+    /// Keys whose press triggered a shortcut, so that their release is swallowed too.
+    swallowed: [bool; 128],
+    drag: Option<Drag>,
+}
+
+impl WindowManager {
+    fn new(windows: Arc<spin::Mutex<Windows>>, screen: (u32, u32), config: Config) -> Self {
+        Self {
+            windows,
+            config,
+            screen,
+            cursor: (screen.0 as i32 / 2, screen.1 as i32 / 2),
+            buttons: 0,
+            modifiers: Modifiers::NONE,
+            extended: false,
+            swallowed: [false; 128],
+            drag: None,
+        }
+    }
+
+    fn run(mut self) {
+        let mut fds = [c"/dev/kbd0", c"/dev/mouse0"].map(|path| {
+            open_device(path).unwrap_or(libc::pollfd {
+                fd: -1,
+                events: 0,
+                revents: 0,
+            })
+        });
+
+        loop {
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } < 0 {
+                continue;
+            }
+
+            if fds[0].revents & libc::POLLIN != 0 {
+                let mut scancodes = [0u8; 64];
+                let len = unsafe { libc::read(fds[0].fd, scancodes.as_mut_ptr().cast(), 64) };
+
+                if len > 0 {
+                    for &scancode in &scancodes[..len as usize] {
+                        self.handle_scancode(scancode);
+                    }
+                }
+            }
+
+            if fds[1].revents & libc::POLLIN != 0 {
+                let mut packet = MousePacket::default();
+                let size = core::mem::size_of::<MousePacket>();
+                let len = unsafe {
+                    libc::read(fds[1].fd, (&mut packet as *mut MousePacket).cast(), size)
+                };
+
+                if len as usize == size {
+                    self.handle_mouse(&packet);
+                }
+            }
+        }
+    }
+
+    fn handle_scancode(&mut self, scancode: u8) {
+        if scancode == EXTENDED {
+            // Held back until the next scancode tells whether the key is swallowed.
+            self.extended = true;
+            return;
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+        let pressed = scancode & RELEASE == 0;
+        let key = scancode & !RELEASE;
+
+        let modifier = match (extended, key) {
+            (_, 0x38) => Modifiers::ALT,
+            (_, 0x1d) => Modifiers::CTRL,
+            (false, 0x2a | 0x36) => Modifiers::SHIFT,
+            (true, 0x5b | 0x5c) => Modifiers::SUPER,
+            _ => Modifiers::NONE,
+        };
+
+        if modifier != Modifiers::NONE {
+            self.modifiers.set(modifier, pressed);
+        } else if pressed {
+            if let Some(action) = self.config.action(self.modifiers, key) {
+                self.swallowed[key as usize] = true;
+                self.perform(action);
+                return;
+            }
+        } else if core::mem::take(&mut self.swallowed[key as usize]) {
+            return;
+        }
+
+        let mut windows = self.windows.lock();
+        let Some(focused) = windows.focused() else {
+            return;
+        };
+
+        if extended {
+            focused.send(InputEvent::key(EXTENDED));
+        }
+
+        // Drop the events the client has not caught up with, instead of blocking the delivery
+        // to the other windows.
+        if !focused.send(InputEvent::key(scancode)) {
+            println!("[window_server] input queue of {} is full", focused.name);
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::CycleFocus => self.windows.lock().cycle_focus(),
+
+            Action::CloseWindow => {
+                if let Some(window) = self.windows.lock().focused() {
+                    window.send(InputEvent::close());
+                }
+            }
+
+            Action::SpawnTerminal => self.spawn_terminal(),
+        }
+    }
+
+    fn spawn_terminal(&self) {
+        let Some(path) = self.config.terminal.as_deref() else {
+            println!(
+                "[window_server] no terminal is configured in {}",
+                config::CONFIG_PATH
+            );
+            return;
+        };
+
+        // Reap the terminals that have exited since the last one was spawned.
+        while unsafe { libc::waitpid(-1, core::ptr::null_mut(), libc::WNOHANG) } > 0 {}
+
+        let env = std::env::vars()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>();
+        let envv = env.iter().map(String::as_str).collect::<Vec<_>>();
+
+        if let Err(err) = process::spawn(path, &[path], &envv, &SpawnOptions::default()) {
+            println!("[window_server] failed to spawn {path}: {err}");
+        }
+    }
+
+    fn handle_mouse(&mut self, packet: &MousePacket) {
+        let (dx, dy) = (packet.x as i32, packet.y as i32);
+        let buttons = (packet.flags & 0b111) as u32;
+        let pressed = buttons & !self.buttons;
+
+        self.buttons = buttons;
+
+        // The mouse reports upward motion as positive.
+        self.cursor = (
+            (self.cursor.0 + dx).clamp(0, self.screen.0 as i32 - 1),
+            (self.cursor.1 - dy).clamp(0, self.screen.1 as i32 - 1),
+        );
+
+        let (x, y) = self.cursor;
+        let mut windows = self.windows.lock();
+
+        if let Some(drag) = &self.drag {
+            if let Some(position) = windows.position(drag.window) {
+                let rect = &mut windows.stack[position].rect;
+
+                rect.x = x - drag.grab.0;
+                rect.y = y - drag.grab.1;
+            }
+
+            if buttons & LEFT_BUTTON == 0 {
+                self.drag = None;
+                windows.changed();
+            }
+
+            return;
+        }
+
+        if pressed & LEFT_BUTTON != 0 {
+            // Clicking a window focuses it, and starts moving it if the move modifier is held.
+            if let Some(position) = windows.window_at(x, y) {
+                windows.focus(position);
+
+                let window = windows.focused().unwrap();
+
+                if self.modifiers.contains(self.config.move_modifier) {
+                    self.drag = Some(Drag {
+                        window: window.id,
+                        grab: (x - window.rect.x, y - window.rect.y),
+                    });
+
+                    return;
+                }
+            }
+        }
+
+        let Some(focused) = windows.focused() else {
+            return;
+        };
+
+        let mut event = InputEvent::mouse(dx, dy, buttons);
+        event.x = x - focused.rect.x;
+        event.y = y - focused.rect.y;
+
+        if !focused.send(event) {
+            println!("[window_server] input queue of {} is full", focused.name);
+        }
     }
 }
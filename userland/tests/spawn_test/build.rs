@@ -0,0 +1,6 @@
+fn main() {
+    // `aero_std` provides the entry point, so leave out the one of the C library. The C library
+    // is still linked for `memcpy` and the other functions the compiler expects.
+    println!("cargo:rustc-link-arg-bins=-nostartfiles");
+    println!("cargo:rustc-link-lib=c");
+}
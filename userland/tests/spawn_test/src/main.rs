@@ -0,0 +1,81 @@
+//! Tests `aero_syscall::process::spawn` against the kernel. Run by `utest`, which expects an
+//! exit code of zero.
+
+#![no_std]
+#![feature(prelude_import)]
+#![allow(internal_features)]
+
+#[prelude_import]
+use aero_std::prelude::rust_2021::*;
+
+use aero_std::process::{Command, Stdio};
+use aero_syscall::process::{spawn, SpawnOptions};
+use aero_syscall::SyscallError;
+
+/// Runs `command` with its standard output piped and returns what it wrote.
+fn read_stdout(command: &mut Command) -> String {
+    let mut child = command.stdout(Stdio::piped()).spawn().unwrap();
+
+    let mut output = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+
+    assert!(child.wait().unwrap().success());
+    output
+}
+
+fn exec_failure() {
+    let options = SpawnOptions::default();
+    assert_eq!(
+        spawn("/does-not-exist", &["/does-not-exist"], &[], &options),
+        Err(SyscallError::ENOENT)
+    );
+
+    // A failed setup step is reported the same way.
+    let options = SpawnOptions {
+        cwd: Some("/does-not-exist"),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        spawn("/usr/bin/echo", &["/usr/bin/echo"], &[], &options),
+        Err(SyscallError::ENOENT)
+    );
+}
+
+fn redirect_stdio() {
+    let output = read_stdout(Command::new("/usr/bin/echo").arg("hello"));
+    assert_eq!(output, "hello\n");
+
+    let output = read_stdout(Command::new("/usr/bin/pwd").current_dir("/tmp"));
+    assert_eq!(output, "/tmp\n");
+
+    // Redirect stdin as well: `cat` copies it to the redirected stdout.
+    let mut child = Command::new("/usr/bin/cat")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(b"piped\n").unwrap();
+
+    let mut output = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+
+    assert!(child.wait().unwrap().success());
+    assert_eq!(output, "piped\n");
+}
+
+fn main() {
+    exec_failure();
+    redirect_stdio();
+}
@@ -6,27 +6,53 @@
 #include <cassert>
 #include <fcntl.h>
 #include <csetjmp>
+#include <ctype.h>
+#include <dirent.h>
 #include <fstream>
+#include <grp.h>
 #include <sys/stat.h>
 #include <errno.h>
 #include <iostream>
+#include <iterator>
+#include <sched.h>
+#include <signal.h>
+#include <set>
+#include <sstream>
+#include <string>
+#include <limits.h>
+#include <netinet/in.h>
 #include <stddef.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
+#include <poll.h>
+#include <pthread.h>
 #include <sys/wait.h>
 #include <sys/epoll.h>
 #include <sys/eventfd.h>
+#include <sys/inotify.h>
 #include <sys/socket.h>
 #include <sys/mman.h>
+#include <sys/mount.h>
+#include <sys/reboot.h>
+#include <sys/resource.h>
+#include <sys/shm.h>
+#include <sys/statvfs.h>
 #include <sys/types.h>
 #include <sys/un.h>
+#include <sys/utsname.h>
+#include <sys/ioctl.h>
+#include <time.h>
 #include <unistd.h>
 #include <vector>
 #include <cassert>
+#include <drm/drm.h>
 
 #if defined(__aero__)
 #include <aero/syscall.h>
+#include <linux/io_uring.h>
+#include <linux/perf_event.h>
+#include <linux/openat2.h>
 #elif defined(__linux__)
 #include <sys/syscall.h>
 #else
@@ -89,6 +115,44 @@ void enable_systrace() {
 	asm volatile("syscall" : "=a"(ret) : "a"(SYS_TRACE) : "rcx", "r11", "memory");
 }
 
+#if defined(__aero__)
+// Issues the system call `number` with up to six arguments and returns the result of the kernel,
+// which is a negated error code on failure. Used for the system calls that mlibc has no wrapper
+// for, and to pass arguments that the wrapper would reject.
+template <typename... Args>
+static long aero_syscall(long number, Args... args) {
+	static_assert(sizeof...(Args) <= 6, "system calls take at most six arguments");
+
+	long argv[6] = {(long)args...};
+	register long r10 __asm__("r10") = argv[3];
+	register long r8 __asm__("r8") = argv[4];
+	register long r9 __asm__("r9") = argv[5];
+	long ret;
+
+	asm volatile(
+		"syscall"
+		: "=a"(ret)
+		: "a"(number), "D"(argv[0]), "S"(argv[1]), "d"(argv[2]), "r"(r10), "r"(r8), "r"(r9)
+		: "rcx", "r11", "memory"
+	);
+
+	return ret;
+}
+
+// Like `aero_syscall`, but returns -1 and sets `errno` on failure.
+template <typename... Args>
+static long aero_syscall_errno(long number, Args... args) {
+	long ret = aero_syscall(number, args...);
+
+	if (ret < 0) {
+		errno = -ret;
+		return -1;
+	}
+
+	return ret;
+}
+#endif
+
 #define assert_errno(fail_func, expr) ((void)(((expr) ? 1 : 0) || (assert_errno_fail(fail_func, #expr, __FILE__, __PRETTY_FUNCTION__, __LINE__), 0)))
 
 inline void assert_errno_fail(const char *fail_func, const char *expr,
@@ -159,6 +223,418 @@ DEFINE_TEST(unix_getname, ([] {
 	unlink(NAMED_PATH);
 }));
 
+#define SYSLOG_PATH "/tmp/log.sock"
+#define SYSLOG_CLIENT_PATH "/tmp/log-client.sock"
+
+static sockaddr_un unix_address(const char *path) {
+	struct sockaddr_un addr;
+	memset(&addr, 0, sizeof(struct sockaddr_un));
+	addr.sun_family = AF_UNIX;
+	strncpy(addr.sun_path, path, sizeof(addr.sun_path) - 1);
+	return addr;
+}
+
+DEFINE_TEST(unix_dgram_syslog, ([] {
+	unlink(SYSLOG_PATH);
+	unlink(SYSLOG_CLIENT_PATH);
+
+	// Datagrams of different sizes. The second one is sent from an unbound socket.
+	const std::vector<std::string> messages{
+		"<13>boot: starting",
+		"<14>x",
+		"<15>dhcpd: lease acquired for eth0, renewing in 3600 seconds",
+	};
+
+	int ready[2];
+	if(pipe(ready))
+		assert(!"pipe() failed");
+
+	pid_t child = fork();
+	if(!child) {
+		// The "syslogd".
+		int server_fd = socket(AF_UNIX, SOCK_DGRAM, 0);
+		if(server_fd == -1)
+			assert(!"server socket() failed");
+
+		struct sockaddr_un server_addr = unix_address(SYSLOG_PATH);
+		if(bind(server_fd, (struct sockaddr *)&server_addr, sizeof(struct sockaddr_un)))
+			assert(!"bind() failed");
+
+		if(write(ready[1], "r", 1) != 1)
+			assert(!"write() failed");
+
+		for(size_t i = 0; i < messages.size(); i++) {
+			char buf[256];
+			struct sockaddr_un sender;
+			socklen_t sender_length = sizeof(struct sockaddr_un);
+			memset(&sender, 0, sizeof(struct sockaddr_un));
+
+			ssize_t size = recvfrom(server_fd, buf, sizeof(buf), 0,
+					(struct sockaddr *)&sender, &sender_length);
+			if(size < 0)
+				assert(!"recvfrom() failed");
+
+			assert(size == (ssize_t)messages[i].size());
+			assert(!memcmp(buf, messages[i].data(), size));
+
+			if(i == 1) {
+				// Unnamed address. Linux leaves out the family as well.
+				assert(sender_length <= offsetof(sockaddr_un, sun_path));
+			} else {
+				assert(sender.sun_family == AF_UNIX);
+				assert(sender_length == offsetof(sockaddr_un, sun_path)
+						+ strlen(SYSLOG_CLIENT_PATH) + 1);
+				assert(!strcmp(sender.sun_path, SYSLOG_CLIENT_PATH));
+			}
+		}
+
+		exit(0);
+	}
+
+	char c;
+	if(read(ready[0], &c, 1) != 1)
+		assert(!"read() failed");
+
+	int client_fd = socket(AF_UNIX, SOCK_DGRAM, 0);
+	int unbound_fd = socket(AF_UNIX, SOCK_DGRAM, 0);
+	if(client_fd == -1 || unbound_fd == -1)
+		assert(!"client socket() failed");
+
+	struct sockaddr_un client_addr = unix_address(SYSLOG_CLIENT_PATH);
+	if(bind(client_fd, (struct sockaddr *)&client_addr, sizeof(struct sockaddr_un)))
+		assert(!"client bind() failed");
+
+	struct sockaddr_un server_addr = unix_address(SYSLOG_PATH);
+
+	for(size_t i = 0; i < messages.size(); i++) {
+		int fd = i == 1 ? unbound_fd : client_fd;
+		ssize_t size = sendto(fd, messages[i].data(), messages[i].size(), 0,
+				(struct sockaddr *)&server_addr, sizeof(struct sockaddr_un));
+		if(size != (ssize_t)messages[i].size())
+			assert(!"sendto() failed");
+	}
+
+	int status;
+	if(waitpid(child, &status, 0) != child)
+		assert(!"waitpid() failed");
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	close(client_fd);
+	close(unbound_fd);
+	close(ready[0]);
+	close(ready[1]);
+	unlink(SYSLOG_PATH);
+	unlink(SYSLOG_CLIENT_PATH);
+}));
+
+DEFINE_TEST(unix_sendto_bad_address, ([] {
+	int fd = socket(AF_UNIX, SOCK_DGRAM, 0);
+	assert_errno("socket", fd != -1);
+
+	struct sockaddr_un addr = unix_address(SYSLOG_PATH);
+
+	// The address cannot be longer than a unix socket address.
+	char big[sizeof(struct sockaddr_un) + 16];
+	memset(big, 0, sizeof(big));
+	memcpy(big, &addr, sizeof(addr));
+	assert(sendto(fd, "x", 1, 0, (struct sockaddr *)big, sizeof(big)) == -1 && errno == EINVAL);
+
+	void *page = mmap(nullptr, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	assert_errno("mmap", page != MAP_FAILED);
+	munmap(page, 4096);
+
+	assert(sendto(fd, "x", 1, 0, (struct sockaddr *)page, sizeof(addr)) == -1 && errno == EFAULT);
+
+	close(fd);
+}))
+
+DEFINE_TEST(unix_recvmsg_iovecs, ([] {
+	int fds[2];
+	assert_errno("socketpair", !socketpair(AF_UNIX, SOCK_STREAM, 0, fds));
+
+	char a[] = "abc", b[] = "defg", c[] = "hi";
+	struct iovec send_iov[3] = {{a, 3}, {b, 4}, {c, 2}};
+
+	struct msghdr msg;
+	memset(&msg, 0, sizeof(msg));
+	msg.msg_iov = send_iov;
+	msg.msg_iovlen = 3;
+	assert(sendmsg(fds[0], &msg, 0) == 9);
+
+	// The data is split across the receive buffers regardless of how it was sent.
+	char first[4], second[5];
+	struct iovec recv_iov[2] = {{first, sizeof(first)}, {second, sizeof(second)}};
+
+	memset(&msg, 0, sizeof(msg));
+	msg.msg_iov = recv_iov;
+	msg.msg_iovlen = 2;
+	assert(recvmsg(fds[1], &msg, 0) == 9);
+	assert(!memcmp(first, "abcd", 4));
+	assert(!memcmp(second, "efghi", 5));
+	assert(!(msg.msg_flags & MSG_TRUNC));
+
+	close(fds[0]);
+	close(fds[1]);
+}))
+
+DEFINE_TEST(unix_recv_peek, ([] {
+	int fds[2];
+	assert_errno("socketpair", !socketpair(AF_UNIX, SOCK_STREAM, 0, fds));
+	assert(send(fds[0], "hello", 5, 0) == 5);
+
+	// Peeking leaves the data queued for the next read.
+	char buf[8];
+	assert(recv(fds[1], buf, sizeof(buf), MSG_PEEK) == 5);
+	assert(!memcmp(buf, "hello", 5));
+
+	memset(buf, 0, sizeof(buf));
+	assert(recv(fds[1], buf, sizeof(buf), 0) == 5);
+	assert(!memcmp(buf, "hello", 5));
+
+	// The socket is blocking, but `MSG_DONTWAIT` makes this call non-blocking.
+	assert(recv(fds[1], buf, sizeof(buf), MSG_DONTWAIT) == -1 && errno == EAGAIN);
+
+	close(fds[0]);
+	close(fds[1]);
+}))
+
+DEFINE_TEST(unix_dgram_trunc, ([] {
+	int fds[2];
+	assert_errno("socketpair", !socketpair(AF_UNIX, SOCK_DGRAM, 0, fds));
+
+	assert(send(fds[0], "0123456789", 10, 0) == 10);
+	assert(send(fds[0], "0123456789", 10, 0) == 10);
+	assert(send(fds[0], "xy", 2, 0) == 2);
+
+	// The rest of a datagram that does not fit is discarded.
+	char buf[4];
+	struct iovec iov = {buf, sizeof(buf)};
+	struct msghdr msg;
+	memset(&msg, 0, sizeof(msg));
+	msg.msg_iov = &iov;
+	msg.msg_iovlen = 1;
+
+	assert(recvmsg(fds[1], &msg, 0) == 4);
+	assert(msg.msg_flags & MSG_TRUNC);
+	assert(!memcmp(buf, "0123", 4));
+
+	// `MSG_TRUNC` reports the size of the whole datagram.
+	assert(recv(fds[1], buf, sizeof(buf), MSG_TRUNC) == 10);
+
+	msg.msg_flags = 0;
+	assert(recvmsg(fds[1], &msg, 0) == 2);
+	assert(!(msg.msg_flags & MSG_TRUNC));
+	assert(!memcmp(buf, "xy", 2));
+
+	close(fds[0]);
+	close(fds[1]);
+}))
+
+DEFINE_TEST(unix_recvmsg_short_name, ([] {
+	int fds[2];
+	assert_errno("socketpair", !socketpair(AF_UNIX, SOCK_DGRAM, 0, fds));
+	assert(send(fds[0], "x", 1, 0) == 1);
+
+	// An address that does not fit in `msg_name` is truncated, and `msg_namelen` reports
+	// its full length.
+	struct sockaddr_un name;
+	memset(&name, 0xff, sizeof(name));
+
+	char buf[1];
+	struct iovec iov = {buf, sizeof(buf)};
+	struct msghdr msg;
+	memset(&msg, 0, sizeof(msg));
+	msg.msg_name = &name;
+	msg.msg_namelen = 1;
+	msg.msg_iov = &iov;
+	msg.msg_iovlen = 1;
+
+	assert(recvmsg(fds[1], &msg, 0) == 1);
+	assert(msg.msg_namelen > 1);
+	assert(((unsigned char *)&name)[1] == 0xff);
+
+	// The control buffer is copied in as well.
+	void *page = mmap(nullptr, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	assert_errno("mmap", page != MAP_FAILED);
+	munmap(page, 4096);
+
+	memset(&msg, 0, sizeof(msg));
+	msg.msg_iov = &iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = page;
+	msg.msg_controllen = 64;
+	assert(sendmsg(fds[0], &msg, 0) == -1 && errno == EFAULT);
+
+	close(fds[0]);
+	close(fds[1]);
+}))
+
+// Fills `addr` with the abstract name `name` and returns the length of the address.
+static socklen_t abstract_address(struct sockaddr_un *addr, const char *name) {
+	memset(addr, 0, sizeof(*addr));
+	addr->sun_family = AF_UNIX;
+	memcpy(addr->sun_path + 1, name, strlen(name));
+	return offsetof(sockaddr_un, sun_path) + 1 + strlen(name);
+}
+
+DEFINE_TEST(unix_abstract, ([] {
+	struct sockaddr_un addr;
+	socklen_t addr_len = abstract_address(&addr, "utest-abstract");
+
+	int server_fd = socket(AF_UNIX, SOCK_STREAM, 0);
+	assert_errno("socket", server_fd >= 0);
+	assert_errno("bind", !bind(server_fd, (struct sockaddr *)&addr, addr_len));
+	assert_errno("listen", !listen(server_fd, 1));
+
+	// The name is taken until the socket is closed.
+	int other_fd = socket(AF_UNIX, SOCK_STREAM, 0);
+	assert_errno("socket", other_fd >= 0);
+	assert(bind(other_fd, (struct sockaddr *)&addr, addr_len) == -1);
+	assert(errno == EADDRINUSE);
+
+	struct sockaddr_un name;
+	socklen_t name_len;
+
+	pid_t child = fork();
+	if(!child) {
+		close(server_fd);
+
+		int client_fd = socket(AF_UNIX, SOCK_STREAM, 0);
+		if(client_fd == -1)
+			exit(1);
+		if(connect(client_fd, (struct sockaddr *)&addr, addr_len))
+			exit(2);
+
+		// The peer name keeps the leading NUL byte and is not NUL terminated.
+		name_len = sizeof(name);
+		if(getpeername(client_fd, (struct sockaddr *)&name, &name_len))
+			exit(3);
+		if(name_len != addr_len || memcmp(name.sun_path, addr.sun_path, addr_len - offsetof(sockaddr_un, sun_path)))
+			exit(4);
+
+		char buf[1];
+		if(recv(client_fd, buf, 1, 0) != 1)
+			exit(5);
+		exit(0);
+	}
+
+	int peer_fd = accept(server_fd, nullptr, nullptr);
+	assert_errno("accept", peer_fd >= 0);
+
+	name_len = sizeof(name);
+	assert_errno("getsockname", !getsockname(server_fd, (struct sockaddr *)&name, &name_len));
+	assert(name_len == addr_len);
+	assert(!memcmp(name.sun_path, addr.sun_path, addr_len - offsetof(sockaddr_un, sun_path)));
+
+	name_len = sizeof(name);
+	assert_errno("getsockname", !getsockname(peer_fd, (struct sockaddr *)&name, &name_len));
+	assert(name_len == addr_len);
+
+	assert(send(peer_fd, "x", 1, 0) == 1);
+
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	// Abstract names are not files, so closing the last descriptor releases the name.
+	close(peer_fd);
+	close(server_fd);
+	assert_errno("bind", !bind(other_fd, (struct sockaddr *)&addr, addr_len));
+	close(other_fd);
+
+	int client_fd = socket(AF_UNIX, SOCK_STREAM, 0);
+	assert_errno("socket", client_fd >= 0);
+	assert(connect(client_fd, (struct sockaddr *)&addr, addr_len) == -1);
+	assert(errno == ECONNREFUSED);
+
+	// Binding to an unnamed address assigns a unique abstract name of five hexadecimal digits.
+	struct sockaddr_un unnamed;
+	memset(&unnamed, 0, sizeof(unnamed));
+	unnamed.sun_family = AF_UNIX;
+	assert_errno("bind", !bind(client_fd, (struct sockaddr *)&unnamed, offsetof(sockaddr_un, sun_path)));
+
+	name_len = sizeof(name);
+	assert_errno("getsockname", !getsockname(client_fd, (struct sockaddr *)&name, &name_len));
+	assert(name_len == offsetof(sockaddr_un, sun_path) + 6);
+	assert(name.sun_path[0] == '\0');
+
+	for(int i = 1; i < 6; i++)
+		assert(isxdigit(name.sun_path[i]));
+
+	close(client_fd);
+}))
+
+static int udp_loopback_socket(in_port_t port, struct sockaddr_in *addr) {
+	memset(addr, 0, sizeof(*addr));
+	addr->sin_family = AF_INET;
+	addr->sin_port = htons(port);
+	addr->sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+
+	int fd = socket(AF_INET, SOCK_DGRAM, 0);
+	if(fd == -1 || bind(fd, (struct sockaddr *)addr, sizeof(*addr)))
+		return -1;
+	return fd;
+}
+
+DEFINE_TEST(udp_loopback, ([] {
+	struct sockaddr_in server_addr, client_addr;
+
+	int server_fd = udp_loopback_socket(4000, &server_addr);
+	assert_errno("socket", server_fd >= 0);
+	int client_fd = udp_loopback_socket(4001, &client_addr);
+	assert_errno("socket", client_fd >= 0);
+
+	assert_errno("sendto", sendto(client_fd, "parent", 6, 0,
+			(struct sockaddr *)&server_addr, sizeof(server_addr)) == 6);
+
+	pid_t child = fork();
+	if(!child) {
+		struct sockaddr_in addr;
+		int fd = udp_loopback_socket(4002, &addr);
+		if(fd == -1)
+			exit(1);
+		if(sendto(fd, "child", 5, 0, (struct sockaddr *)&server_addr, sizeof(server_addr)) != 5)
+			exit(2);
+		exit(0);
+	}
+
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	// The datagrams arrive in the order they were sent, from the address of the sender.
+	const char *expected[] = {"parent", "child"};
+	in_port_t ports[] = {4001, 4002};
+
+	for(int i = 0; i < 2; i++) {
+		char buf[16];
+		struct sockaddr_in from;
+		socklen_t from_len = sizeof(from);
+
+		ssize_t len = recvfrom(server_fd, buf, sizeof(buf), 0, (struct sockaddr *)&from, &from_len);
+		assert_errno("recvfrom", len == (ssize_t)strlen(expected[i]));
+		assert(!memcmp(buf, expected[i], len));
+
+		assert(from_len == sizeof(from));
+		assert(from.sin_family == AF_INET);
+		assert(from.sin_port == htons(ports[i]));
+		assert(from.sin_addr.s_addr == htonl(INADDR_LOOPBACK));
+	}
+
+	close(client_fd);
+	close(server_fd);
+
+	// The loopback interface is listed along with the other interfaces.
+	std::ifstream dev("/proc/net/dev");
+	assert(dev.is_open());
+
+	std::string line;
+	bool found = false;
+	while(std::getline(dev, line))
+		found |= line.find(" lo:") != std::string::npos;
+	assert(found);
+}))
+
 DEFINE_TEST(epoll_mod_active, ([] {
 	int e;
 	int pending;
@@ -649,122 +1125,4082 @@ DEFINE_TEST(stat, ([] {
 		assert(!"unlink() failed");
 }))
 
-static inline bool cpuid(uint32_t leaf, uint32_t subleaf,
-                         uint32_t *eax, uint32_t *ebx, uint32_t *ecx, uint32_t *edx)  {
-	uint32_t cpuid_max;
-    asm volatile ("cpuid"
-                  : "=a" (cpuid_max)
-                  : "a" (leaf & 0x80000000) : "rbx", "rcx", "rdx");
-    if (leaf > cpuid_max)
-        return false;
-    asm volatile ("cpuid"
-                  : "=a" (*eax), "=b" (*ebx), "=c" (*ecx), "=d" (*edx)
-                  : "a" (leaf), "c" (subleaf));
-    return true;
-}
+DEFINE_TEST(symlink_limits, ([] {
+	if (mkdir("/tmp/symlinks", 0777) == -1)
+		assert(!"mkdir() failed");
 
-// Returns [`true`] if the `SYSENTER` and `SYSEXIT` and associated MSRs are supported.
-bool has_sysenter_sysexit() {
-	uint32_t eax, ebx, ecx, edx;
-	// LEAF 1: Processor and Processor Feature Identifiers
-	if (!cpuid(1, 0, &eax, &ebx, &ecx, &edx)) {
-		return false;
+	FILE *file = fopen("/tmp/symlinks/f", "w");
+	assert(file);
+	fclose(file);
+
+	// a -> b -> a
+	assert_errno("symlink", symlink("b", "/tmp/symlinks/a") != -1);
+	assert_errno("symlink", symlink("a", "/tmp/symlinks/b") != -1);
+
+	assert(open("/tmp/symlinks/a", O_RDONLY) == -1 && errno == ELOOP);
+	assert(open("/tmp/symlinks/b/f", O_RDONLY) == -1 && errno == ELOOP);
+
+	struct stat statbuf;
+	assert(stat("/tmp/symlinks/a", &statbuf) == -1 && errno == ELOOP);
+	assert_errno("fstatat", fstatat(AT_FDCWD, "/tmp/symlinks/a", &statbuf, AT_SYMLINK_NOFOLLOW) != -1);
+	assert(S_ISLNK(statbuf.st_mode));
+
+	// chainN -> chainN-1 -> ... -> chain1 -> f, so that opening chainN follows N links.
+	const int chain_length = 50;
+
+	for (int i = 1; i <= chain_length; i++) {
+		std::string target = i == 1 ? "f" : "chain" + std::to_string(i - 1);
+		std::string path = "/tmp/symlinks/chain" + std::to_string(i);
+		assert_errno("symlink", symlink(target.c_str(), path.c_str()) != -1);
 	}
-	return edx & (1 << 11);
-}
 
-#if defined(__aero__)
-DEFINE_TEST(bad_sysenter, ([] {
-	if (!has_sysenter_sysexit()) {
-		printf("test skipped... sysenter not supported\n");
-		return;
+	// At most 40 links are followed.
+	for (int length : {1, 30, 40}) {
+		std::string path = "/tmp/symlinks/chain" + std::to_string(length);
+		int fd = open(path.c_str(), O_RDONLY);
+		assert_errno("open", fd != -1);
+		close(fd);
 	}
 
-	int pid = fork();
+	for (int length : {41, 50}) {
+		std::string path = "/tmp/symlinks/chain" + std::to_string(length);
+		assert(open(path.c_str(), O_RDONLY) == -1 && errno == ELOOP);
+	}
 
-	if (!pid) {
-		register long r11 __asm__("r11") = (size_t)0xf0f0 << 48;
-		register long rcx __asm__("rcx") = (size_t)0xf0f0 << 48;
+	// O_NOFOLLOW fails on a trailing symbolic link, but not on one in the middle of the path.
+	assert(open("/tmp/symlinks/chain1", O_RDONLY | O_NOFOLLOW) == -1 && errno == ELOOP);
 
-		asm volatile(
-			"sysenter\n"
-			:  	
-			: "r"(r11), "r"(rcx)
-		);
-		
-		__builtin_unreachable();
-	} else {
-		int status = 0;
-		if (!waitpid(pid, &status, 0))
-			assert(!"waitpid() failed");
+	assert_errno("symlink", symlink(".", "/tmp/symlinks/dir") != -1);
+	int fd = open("/tmp/symlinks/dir/f", O_RDONLY | O_NOFOLLOW);
+	assert_errno("open", fd != -1);
+	close(fd);
 
-		// FIXME: should we get killed with SIGSEGV instead?
-		assert(WIFEXITED(status));
+	// A path component is at most NAME_MAX bytes long.
+	std::string longest = "/tmp/symlinks/" + std::string(NAME_MAX, 'n');
+	assert(open(longest.c_str(), O_RDONLY) == -1 && errno == ENOENT);
+
+	std::string overlong = "/tmp/symlinks/" + std::string(NAME_MAX + 1, 'n');
+	assert(open(overlong.c_str(), O_RDONLY) == -1 && errno == ENAMETOOLONG);
+	assert(open(overlong.c_str(), O_RDONLY | O_CREAT, 0666) == -1 && errno == ENAMETOOLONG);
+
+	// A path is shorter than PATH_MAX bytes, which includes the null terminator.
+	std::string dots;
+	while (dots.size() + strlen("/tmp/symlinks/f") < PATH_MAX - 1)
+		dots += "./";
+
+	std::string path = "/tmp/symlinks/" + dots + "f";
+	assert(path.size() == PATH_MAX - 1);
+
+	fd = open(path.c_str(), O_RDONLY);
+	assert_errno("open", fd != -1);
+	close(fd);
+
+	path.insert(strlen("/tmp/symlinks/"), "/");
+	assert(open(path.c_str(), O_RDONLY) == -1 && errno == ENAMETOOLONG);
+
+	for (int i = 1; i <= chain_length; i++)
+		unlink(("/tmp/symlinks/chain" + std::to_string(i)).c_str());
+
+	unlink("/tmp/symlinks/a");
+	unlink("/tmp/symlinks/b");
+	unlink("/tmp/symlinks/dir");
+	unlink("/tmp/symlinks/f");
+	rmdir("/tmp/symlinks");
+}))
+
+#if defined(__aero__)
+#define SYS_OPENAT2 82
+
+static int openat2(int dirfd, const char *path, uint64_t flags, uint64_t resolve) {
+	struct open_how how;
+	memset(&how, 0, sizeof(how));
+	how.flags = flags;
+	how.resolve = resolve;
+
+	long ret;
+	register long r10 __asm__("r10") = (long)&how;
+	register long r8 __asm__("r8") = sizeof(how);
+
+	asm volatile(
+		"syscall"
+		: "=a"(ret)
+		: "a"(SYS_OPENAT2), "D"(dirfd), "S"(path), "d"(strlen(path)), "r"(r10), "r"(r8)
+		: "rcx", "r11", "memory"
+	);
+
+	if (ret < 0) {
+		errno = -ret;
+		return -1;
 	}
+
+	return ret;
+}
+
+DEFINE_TEST(openat2_resolve, ([] {
+	if (mkdir("/tmp/openat2", 0777) == -1)
+		assert(!"mkdir() failed");
+
+	FILE *file = fopen("/tmp/openat2/file", "w");
+	assert(file);
+	fclose(file);
+
+	if (symlink("file", "/tmp/openat2/link") == -1)
+		assert(!"(1) symlink() failed");
+
+	if (symlink("/tmp/openat2/file", "/tmp/openat2/abs") == -1)
+		assert(!"(2) symlink() failed");
+
+	int dirfd = open("/tmp/openat2", O_RDONLY | O_DIRECTORY);
+	assert_errno("open", dirfd != -1);
+
+	auto check_ok = [&](const char *path, uint64_t resolve) {
+		int fd = openat2(dirfd, path, O_RDONLY, resolve);
+		assert_errno("openat2", fd != -1);
+		close(fd);
+	};
+
+	auto check_err = [&](const char *path, uint64_t resolve, int err) {
+		assert(openat2(dirfd, path, O_RDONLY, resolve) == -1 && errno == err);
+	};
+
+	// RESOLVE_BENEATH: the lookup must not escape the directory file descriptor.
+	check_ok("file", RESOLVE_BENEATH);
+	check_ok("link", RESOLVE_BENEATH);
+	check_ok("./../openat2/file", 0);
+	check_err("../openat2/file", RESOLVE_BENEATH, EXDEV);
+	check_err("/tmp/openat2/file", RESOLVE_BENEATH, EXDEV);
+	check_err("abs", RESOLVE_BENEATH, EXDEV);
+
+	// RESOLVE_NO_SYMLINKS: no symbolic link may be followed.
+	check_ok("file", RESOLVE_NO_SYMLINKS);
+	check_err("link", RESOLVE_NO_SYMLINKS, ELOOP);
+	check_err("abs", RESOLVE_NO_SYMLINKS, ELOOP);
+
+	// RESOLVE_NO_XDEV: `/dev` is a different filesystem mounted on the root filesystem.
+	int fd = openat2(AT_FDCWD, "/dev/null", O_RDONLY, 0);
+	assert_errno("openat2", fd != -1);
+	close(fd);
+
+	assert(openat2(AT_FDCWD, "/dev/null", O_RDONLY, RESOLVE_NO_XDEV) == -1 && errno == EXDEV);
+
+	close(dirfd);
+	unlink("/tmp/openat2/abs");
+	unlink("/tmp/openat2/link");
+	unlink("/tmp/openat2/file");
+	rmdir("/tmp/openat2");
+}))
+
+#define SYS_STATX 101
+
+#define STATX_BASIC_STATS_ 0x7ff
+#define STATX_BTIME_ 0x800
+#define STATX_RESERVED_ 0x80000000
+#define AT_STATX_FORCE_SYNC_ 0x2000
+#define AT_STATX_DONT_SYNC_ 0x4000
+
+// Laid out like Linux's `struct statx`.
+struct statx_timestamp_ {
+	int64_t tv_sec;
+	uint32_t tv_nsec;
+	int32_t reserved;
+};
+
+struct statx_ {
+	uint32_t stx_mask;
+	uint32_t stx_blksize;
+	uint64_t stx_attributes;
+	uint32_t stx_nlink;
+	uint32_t stx_uid;
+	uint32_t stx_gid;
+	uint16_t stx_mode;
+	uint16_t spare0;
+	uint64_t stx_ino;
+	uint64_t stx_size;
+	uint64_t stx_blocks;
+	uint64_t stx_attributes_mask;
+	struct statx_timestamp_ stx_atime;
+	struct statx_timestamp_ stx_btime;
+	struct statx_timestamp_ stx_ctime;
+	struct statx_timestamp_ stx_mtime;
+	uint32_t stx_rdev_major;
+	uint32_t stx_rdev_minor;
+	uint32_t stx_dev_major;
+	uint32_t stx_dev_minor;
+	uint64_t spare2[14];
+};
+
+static_assert(sizeof(struct statx_) == 256);
+
+static bool same_time(const struct statx_timestamp_ &stx, const struct timespec &ts) {
+	return stx.tv_sec == ts.tv_sec && stx.tv_nsec == ts.tv_nsec;
+}
+
+static void assert_statx_matches(const struct statx_ &stx, const struct stat &st) {
+	assert((stx.stx_mask & STATX_BASIC_STATS_) == STATX_BASIC_STATS_);
+	assert(stx.stx_mode == st.st_mode);
+	assert(stx.stx_ino == st.st_ino);
+	assert(stx.stx_nlink == st.st_nlink);
+	assert(stx.stx_uid == st.st_uid);
+	assert(stx.stx_gid == st.st_gid);
+	assert(stx.stx_size == (uint64_t)st.st_size);
+	assert(stx.stx_blocks == (uint64_t)st.st_blocks);
+	assert(same_time(stx.stx_atime, st.st_atim));
+	assert(same_time(stx.stx_mtime, st.st_mtim));
+	assert(same_time(stx.stx_ctime, st.st_ctim));
+}
+
+DEFINE_TEST(statx_file, ([] {
+	int fd = open("/tmp/statx", O_CREAT | O_TRUNC | O_RDWR, 0644);
+	assert_errno("open", fd >= 0);
+
+	char buffer[1234] = {};
+	assert(write(fd, buffer, sizeof(buffer)) == sizeof(buffer));
+	assert_errno("fchmod", !fchmod(fd, 0640));
+
+	struct stat st;
+	assert_errno("stat", !stat("/tmp/statx", &st));
+
+	struct statx_ stx;
+	memset(&stx, 0xff, sizeof(stx));
+	assert_errno("statx", !aero_syscall_errno(SYS_STATX, AT_FDCWD, "/tmp/statx", strlen("/tmp/statx"), 0, STATX_BASIC_STATS_, &stx));
+	assert_statx_matches(stx, st);
+	assert(stx.stx_size == sizeof(buffer));
+	assert(S_ISREG(stx.stx_mode) && (stx.stx_mode & 0777) == 0640);
+
+	// tmpfs does not record when a file was created.
+	assert(!(stx.stx_mask & STATX_BTIME_));
+
+	// The sync flags return the same cached attributes.
+	memset(&stx, 0, sizeof(stx));
+	assert_errno("statx", !aero_syscall_errno(SYS_STATX, AT_FDCWD, "/tmp/statx", strlen("/tmp/statx"), AT_STATX_DONT_SYNC_, STATX_BASIC_STATS_, &stx));
+	assert_statx_matches(stx, st);
+	assert(aero_syscall_errno(SYS_STATX, AT_FDCWD, "/tmp/statx", strlen("/tmp/statx"), AT_STATX_FORCE_SYNC_ | AT_STATX_DONT_SYNC_, STATX_BASIC_STATS_, &stx) == -1 && errno == EINVAL);
+	assert(aero_syscall_errno(SYS_STATX, AT_FDCWD, "/tmp/statx", strlen("/tmp/statx"), 0, STATX_RESERVED_, &stx) == -1 && errno == EINVAL);
+
+	// An empty path refers to `dirfd` itself with `AT_EMPTY_PATH`.
+	memset(&stx, 0, sizeof(stx));
+	assert_errno("statx", !aero_syscall_errno(SYS_STATX, fd, "", strlen(""), AT_EMPTY_PATH, STATX_BASIC_STATS_, &stx));
+	assert_statx_matches(stx, st);
+	assert(aero_syscall_errno(SYS_STATX, fd, "", strlen(""), 0, STATX_BASIC_STATS_, &stx) == -1 && errno == ENOENT);
+
+	// `AT_SYMLINK_NOFOLLOW` describes the link rather than its target.
+	assert_errno("symlink", !symlink("/tmp/statx", "/tmp/statx-link"));
+	assert_errno("lstat", !lstat("/tmp/statx-link", &st));
+	memset(&stx, 0, sizeof(stx));
+	assert_errno("statx", !aero_syscall_errno(SYS_STATX, AT_FDCWD, "/tmp/statx-link", strlen("/tmp/statx-link"), AT_SYMLINK_NOFOLLOW, STATX_BASIC_STATS_, &stx));
+	assert_statx_matches(stx, st);
+	assert(S_ISLNK(stx.stx_mode));
+
+	close(fd);
+	unlink("/tmp/statx-link");
+	unlink("/tmp/statx");
+
+	// The root filesystem is ext2 with 256 byte inodes, which hold the creation time.
+	assert_errno("stat", !stat("/", &st));
+	memset(&stx, 0, sizeof(stx));
+	assert_errno("statx", !aero_syscall_errno(SYS_STATX, AT_FDCWD, "/", strlen("/"), 0, STATX_BASIC_STATS_ | STATX_BTIME_, &stx));
+	assert_statx_matches(stx, st);
+	assert(stx.stx_mask & STATX_BTIME_);
+	assert(stx.stx_btime.tv_sec > 0 && stx.stx_btime.tv_nsec < 1000000000);
+	assert(stx.stx_btime.tv_sec <= stx.stx_mtime.tv_sec);
 }))
 #endif
 
 #if defined(__aero__)
-DEFINE_TEST(sysenter_system_call, ([] {
-	if (!has_sysenter_sysexit()) {
-		printf("test skipped... sysenter not supported\n");
+// An address in the higher half, which is never accessible from userland.
+#define KERNEL_ADDRESS ((void *)0xffffffff80000000)
+
+DEFINE_TEST(ioctl_bad_pointer, ([] {
+	int tty = open("/dev/vtty", O_RDWR);
+	assert_errno("open", tty != -1);
+
+	assert(ioctl(tty, TIOCGWINSZ, nullptr) == -1 && errno == EFAULT);
+	assert(ioctl(tty, TIOCGWINSZ, KERNEL_ADDRESS) == -1 && errno == EFAULT);
+
+	close(tty);
+
+	int card = open("/dev/dri/card0", O_RDWR);
+	if (card == -1) {
+		printf("test partially skipped... no DRM device\n");
 		return;
 	}
 
-	int fds[2];
-	if (pipe(fds) == -1)
-		assert(!"pipe() failed");
+	assert(ioctl(card, DRM_IOCTL_VERSION, nullptr) == -1 && errno == EFAULT);
+	assert(ioctl(card, DRM_IOCTL_VERSION, KERNEL_ADDRESS) == -1 && errno == EFAULT);
 
-	int pid = fork();
+	// The string buffers inside of the structure are user pointers as well.
+	struct drm_version version;
+	memset(&version, 0, sizeof(version));
+	version.name_len = 16;
+	version.name = (char *)KERNEL_ADDRESS;
 
-	if (!pid) {
-		close(fds[0]);
+	assert(ioctl(card, DRM_IOCTL_VERSION, &version) == -1 && errno == EFAULT);
 
-		const char *buf = "Hello, world!\n";
-		size_t buf_size = strlen(buf);
+	close(card);
+}))
 
-		__asm__ __volatile__ (
-			"mov %%rsp, %%r11\n\t"
-			"lea 1f(%%rip), %%rcx\n\t"
-			"sysenter\n\t"
-			"1:"
-			:
-			: "a"(uint64_t(1)), "D"(uint64_t(fds[1])), "S"(buf), "d"(buf_size + 1)
-			: "rcx", "r11"
-		);
+DEFINE_TEST(read_only_pointer, ([] {
+	void *page = mmap(nullptr, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	assert_errno("mmap", page != MAP_FAILED);
 
-		exit(0);
-	} else {
-		close(fds[1]);
+	// The results cannot be written to a read-only page.
+	int tty = open("/dev/vtty", O_RDWR);
+	assert_errno("open", tty != -1);
+	assert(ioctl(tty, TIOCGWINSZ, page) == -1 && errno == EFAULT);
+	close(tty);
 
-		int status = 0;
-		if (!waitpid(pid, &status, 0))
-			assert(!"waitpid() failed");
+	int fds[2];
+	assert_errno("pipe", pipe(fds) != -1);
+	assert_errno("write", write(fds[1], "x", 1) == 1);
+	assert(read(fds[0], page, 1) == -1 && errno == EFAULT);
 
-		assert(WIFEXITED(status));
+	close(fds[0]);
+	close(fds[1]);
+	munmap(page, 4096);
+}))
 
-		char tmp[15];
-		ssize_t n = read(fds[0], tmp, sizeof(tmp));
+DEFINE_TEST(socket_address_length, ([] {
+	int udp = socket(AF_INET, SOCK_DGRAM, 0);
+	assert_errno("socket", udp != -1);
+
+	struct sockaddr_in in = {};
+	in.sin_family = AF_INET;
+	in.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+
+	// The length must cover the whole address.
+	assert(bind(udp, (struct sockaddr *)&in, sizeof(in) - 1) == -1 && errno == EINVAL);
+	assert(bind(udp, (struct sockaddr *)KERNEL_ADDRESS, sizeof(in)) == -1 && errno == EFAULT);
+	close(udp);
+
+	// A unix socket address ends with its name, so only `length` bytes of it are read. Place
+	// it right before an unmapped page.
+	long page_size = sysconf(_SC_PAGESIZE);
+	char *pages = (char *)mmap(nullptr, page_size * 2, PROT_READ | PROT_WRITE,
+		MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	assert_errno("mmap", pages != MAP_FAILED);
+	assert_errno("munmap", munmap(pages + page_size, page_size) != -1);
+
+	const char name[] = "\0utest-short-address";
+	socklen_t length = offsetof(struct sockaddr_un, sun_path) + sizeof(name) - 1;
+
+	struct sockaddr_un *un = (struct sockaddr_un *)(pages + page_size - length);
+	un->sun_family = AF_UNIX;
+	memcpy(un->sun_path, name, sizeof(name) - 1);
+
+	int sock = socket(AF_UNIX, SOCK_STREAM, 0);
+	assert_errno("socket", sock != -1);
+	assert_errno("bind", bind(sock, (struct sockaddr *)un, length) != -1);
+	close(sock);
+
+	// Longer than any unix socket address.
+	sock = socket(AF_UNIX, SOCK_STREAM, 0);
+	assert_errno("socket", sock != -1);
+
+	struct sockaddr_un large = {};
+	large.sun_family = AF_UNIX;
+	assert(bind(sock, (struct sockaddr *)&large, sizeof(large) + 1) == -1 && errno == EINVAL);
+
+	close(sock);
+	munmap(pages, page_size);
+}))
 
-		assert(n == 15);
-		assert(!strcmp(tmp, "Hello, world!\n"));
+DEFINE_TEST(drm_getfb, ([] {
+	int card = open("/dev/dri/card0", O_RDWR);
+	if (card == -1) {
+		printf("test skipped... no DRM device\n");
+		return;
 	}
+
+	struct drm_mode_card_res res;
+	memset(&res, 0, sizeof(res));
+	assert_errno("GETRESOURCES", ioctl(card, DRM_IOCTL_MODE_GETRESOURCES, &res) == 0);
+
+	struct drm_mode_create_dumb dumb;
+	memset(&dumb, 0, sizeof(dumb));
+	dumb.width = res.max_width;
+	dumb.height = res.max_height;
+	dumb.bpp = 32;
+	assert_errno("CREATE_DUMB", ioctl(card, DRM_IOCTL_MODE_CREATE_DUMB, &dumb) == 0);
+
+	struct drm_mode_fb_cmd fb;
+	memset(&fb, 0, sizeof(fb));
+	fb.width = dumb.width;
+	fb.height = dumb.height;
+	fb.pitch = dumb.pitch;
+	fb.bpp = 32;
+	fb.depth = 24;
+	fb.handle = dumb.handle;
+	assert_errno("ADDFB", ioctl(card, DRM_IOCTL_MODE_ADDFB, &fb) == 0);
+
+	// The framebuffer reports the geometry it was created with.
+	struct drm_mode_fb_cmd query;
+	memset(&query, 0, sizeof(query));
+	query.fb_id = fb.fb_id;
+	assert_errno("GETFB", ioctl(card, DRM_IOCTL_MODE_GETFB, &query) == 0);
+
+	assert(query.width == dumb.width && query.height == dumb.height);
+	assert(query.pitch == dumb.pitch && query.bpp == 32 && query.depth == 24);
+	assert(query.handle == dumb.handle);
+
+	// The ID of a CRTC does not refer to a framebuffer.
+	uint32_t crtc_id;
+	res.count_fbs = res.count_connectors = res.count_encoders = 0;
+	res.count_crtcs = 1;
+	res.crtc_id_ptr = (uintptr_t)&crtc_id;
+	assert_errno("GETRESOURCES", ioctl(card, DRM_IOCTL_MODE_GETRESOURCES, &res) == 0);
+
+	query.fb_id = crtc_id;
+	assert(ioctl(card, DRM_IOCTL_MODE_GETFB, &query) == -1 && errno == ENOENT);
+
+	close(card);
 }))
-#endif
 
-std::vector<abstract_test_case *> &test_case_ptrs() {
-	static std::vector<abstract_test_case *> singleton;
-	return singleton;
-}
+#define SYS_GETRUSAGE 127
 
-void abstract_test_case::register_case(abstract_test_case *tcp) {
-	test_case_ptrs().push_back(tcp);
-}
+DEFINE_TEST(drm_mmap_dumb, ([] {
+	int card = open("/dev/dri/card0", O_RDWR);
+	if (card == -1) {
+		printf("test skipped... no DRM device\n");
+		return;
+	}
 
-int main() {
-  // Go through all tests and run them.
+	struct drm_mode_create_dumb dumb;
+	memset(&dumb, 0, sizeof(dumb));
+	dumb.width = 2048;
+	dumb.height = 1024;
+	dumb.bpp = 32;
+	assert_errno("CREATE_DUMB", ioctl(card, DRM_IOCTL_MODE_CREATE_DUMB, &dumb) == 0);
+
+	struct drm_mode_map_dumb map;
+	memset(&map, 0, sizeof(map));
+	map.handle = dumb.handle;
+	assert_errno("MAP_DUMB", ioctl(card, DRM_IOCTL_MODE_MAP_DUMB, &map) == 0);
+
+	void *buffer = mmap(nullptr, dumb.size, PROT_READ | PROT_WRITE, MAP_SHARED, card, map.offset);
+	assert_errno("mmap", buffer != MAP_FAILED);
+
+	struct rusage before, after;
+	assert_errno("getrusage", aero_syscall_errno(SYS_GETRUSAGE, RUSAGE_SELF, &before) == 0);
+
+	// The whole buffer is mapped on the first fault, instead of a page per fault.
+	memset(buffer, 0xaa, dumb.size);
+
+	assert_errno("getrusage", aero_syscall_errno(SYS_GETRUSAGE, RUSAGE_SELF, &after) == 0);
+	assert(after.ru_minflt - before.ru_minflt < 16);
+	assert(((unsigned char *)buffer)[dumb.size - 1] == 0xaa);
+
+	assert_errno("munmap", munmap(buffer, dumb.size) == 0);
+	close(card);
+}))
+#endif
+
+#if defined(__aero__)
+// From `<linux/kd.h>`.
+#define AERO_KDKBDREP 0x4b52
+
+struct aero_kbd_repeat {
+	int delay;
+	int period;
+};
+
+DEFINE_TEST(kbd_repeat, ([] {
+	int tty = open("/dev/vtty", O_RDWR);
+	assert_errno("open", tty != -1);
+
+	// Values that are not positive are left unchanged, which only queries the settings.
+	aero_kbd_repeat old = {-1, 0};
+	assert_errno("ioctl", ioctl(tty, AERO_KDKBDREP, &old) != -1);
+	assert(old.delay == 500 && old.period == 33);
+
+	// The keyboard only supports some delays and periods, the closest ones are used.
+	aero_kbd_repeat repeat = {300, 100};
+	assert_errno("ioctl", ioctl(tty, AERO_KDKBDREP, &repeat) != -1);
+	assert(repeat.delay == 500 && repeat.period == 100);
+
+	repeat = {2000, 0};
+	assert_errno("ioctl", ioctl(tty, AERO_KDKBDREP, &repeat) != -1);
+	assert(repeat.delay == 1000 && repeat.period == 100);
+
+	repeat = old;
+	assert_errno("ioctl", ioctl(tty, AERO_KDKBDREP, &repeat) != -1);
+	assert(repeat.delay == old.delay && repeat.period == old.period);
+
+	close(tty);
+}))
+#endif
+
+// Returns whether the slave numbered `number` is listed in /dev/pts.
+static bool pts_listed(unsigned int number) {
+	DIR *dir = opendir("/dev/pts");
+	assert_errno("opendir", dir);
+
+	char name[16];
+	snprintf(name, sizeof(name), "%u", number);
+
+	bool found = false;
+	while (struct dirent *entry = readdir(dir)) {
+		if (!strcmp(entry->d_name, name))
+			found = true;
+	}
+
+	closedir(dir);
+	return found;
+}
+
+DEFINE_TEST(devpts_slaves, ([] {
+	int first = open("/dev/ptmx", O_RDWR | O_NOCTTY);
+	assert_errno("open", first != -1);
+	int second = open("/dev/ptmx", O_RDWR | O_NOCTTY);
+	assert_errno("open", second != -1);
+
+	unsigned int first_number, second_number;
+	assert_errno("TIOCGPTN", ioctl(first, TIOCGPTN, &first_number) == 0);
+	assert_errno("TIOCGPTN", ioctl(second, TIOCGPTN, &second_number) == 0);
+
+	assert(first_number != second_number);
+	assert(pts_listed(first_number) && pts_listed(second_number));
+
+	// The slave named after the number is the other end of the master.
+	char path[32];
+	snprintf(path, sizeof(path), "/dev/pts/%u", first_number);
+
+	int unlock = 0;
+	assert_errno("TIOCSPTLCK", ioctl(first, TIOCSPTLCK, &unlock) == 0);
+
+	int slave = open(path, O_RDWR | O_NOCTTY);
+	assert_errno("open", slave != -1);
+	assert(write(slave, "x", 1) == 1);
+
+	char c;
+	assert(read(first, &c, 1) == 1 && c == 'x');
+	close(slave);
+
+	int lock = 1;
+	assert_errno("TIOCSPTLCK", ioctl(first, TIOCSPTLCK, &lock) == 0);
+	assert(open(path, O_RDWR | O_NOCTTY) == -1 && errno == EIO);
+
+	// Closing the master tears down the pair, the old path does not refer to it anymore.
+	close(first);
+	assert(!pts_listed(first_number) && pts_listed(second_number));
+	assert(open(path, O_RDWR | O_NOCTTY) == -1 && errno == ENOENT);
+
+	close(second);
+	assert(!pts_listed(second_number));
+}))
+
+// Opens the slave of a new pseudo terminal, without making it the controlling terminal.
+static int open_pty_slave(int *master) {
+	*master = open("/dev/ptmx", O_RDWR | O_NOCTTY);
+	if (*master == -1)
+		return -1;
+
+	unsigned int number;
+	int unlock = 0;
+	if (ioctl(*master, TIOCGPTN, &number) || ioctl(*master, TIOCSPTLCK, &unlock))
+		return -1;
+
+	char path[32];
+	snprintf(path, sizeof(path), "/dev/pts/%u", number);
+	return open(path, O_RDWR | O_NOCTTY);
+}
+
+DEFINE_TEST(controlling_terminal, ([] {
+	int child = fork();
+	if (!child) {
+		// Giving up the controlling terminal hangs up the foreground process group.
+		signal(SIGHUP, SIG_IGN);
+
+		// The new session has no controlling terminal.
+		if (setsid() == -1)
+			exit(1);
+		if (open("/dev/tty", O_RDWR) != -1 || errno != ENXIO)
+			exit(2);
+
+		int master, other_master;
+		int slave = open_pty_slave(&master);
+		int other_slave = open_pty_slave(&other_master);
+		if (slave == -1 || other_slave == -1)
+			exit(3);
+
+		if (ioctl(slave, TIOCSCTTY, 0))
+			exit(4);
+		int tty = open("/dev/tty", O_RDWR);
+		if (tty == -1)
+			exit(5);
+
+		// A session has a single controlling terminal.
+		if (ioctl(other_slave, TIOCSCTTY, 0) != -1 || errno != EPERM)
+			exit(6);
+
+		if (ioctl(tty, TIOCNOTTY))
+			exit(7);
+		if (open("/dev/tty", O_RDWR) != -1 || errno != ENXIO)
+			exit(8);
+
+		exit(0);
+	}
+
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}))
+
+static std::string read_file(const char *path) {
+	std::ifstream file(path);
+	assert(file.is_open());
+	return std::string(std::istreambuf_iterator<char>(file), std::istreambuf_iterator<char>());
+}
+
+DEFINE_TEST(proc_maps, ([] {
+	size_t size = 4096 * 2;
+	void *mem = mmap(nullptr, size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	assert_errno("mmap", mem != MAP_FAILED);
+
+	char expected[64];
+	snprintf(expected, sizeof(expected), "%08lx-%08lx rw-p 00000000 00:00 0\n",
+			(unsigned long)mem, (unsigned long)mem + size);
+
+	std::string maps = read_file("/proc/self/maps");
+	assert(maps.find(expected) != std::string::npos);
+	assert(maps.find("[stack]") != std::string::npos);
+	assert(maps.find("/usr/bin/utest") != std::string::npos);
+
+	char path[64];
+	snprintf(path, sizeof(path), "/proc/%d/maps", getpid());
+	assert(read_file(path).find(expected) != std::string::npos);
+
+	// Unmapping an area in between reads continues from the next area.
+	int fd = open("/proc/self/maps", O_RDONLY);
+	assert_errno("open", fd != -1);
+
+	char buf[16];
+	assert(read(fd, buf, sizeof(buf)) == sizeof(buf));
+	assert(!munmap(mem, size));
+
+	while (read(fd, buf, sizeof(buf)) > 0)
+		;
+
+	close(fd);
+	assert(read_file("/proc/self/maps").find(expected) == std::string::npos);
+}))
+
+DEFINE_TEST(proc_exe, ([] {
+	// Opening `exe` opens the executable of the process.
+	std::string exe = read_file("/proc/self/exe");
+	assert(exe.compare(0, 4, "\x7f" "ELF") == 0);
+
+	char path[64];
+	snprintf(path, sizeof(path), "/proc/%d/exe", getpid());
+	assert(read_file(path) == exe);
+}))
+
+#if defined(__aero__)
+DEFINE_TEST(proc_fdinfo, ([] {
+	int fds[2];
+	assert_errno("pipe", !pipe(fds));
+	assert(write(fds[1], "hello", 5) == 5);
+
+	char path[64];
+	snprintf(path, sizeof(path), "/proc/%d/fdinfo/%d", getpid(), fds[0]);
+
+	std::string info = read_file(path);
+	assert(info.find("pos:\t0\n") == 0);
+	assert(info.find("flags:\t00\n") != std::string::npos);
+	assert(info.find("pipe_size:\t5\n") != std::string::npos);
+	assert(info.find("pipe_readers:\t1\n") != std::string::npos);
+	assert(info.find("pipe_writers:\t1\n") != std::string::npos);
+
+	// Both ends are listed, named after their file descriptor.
+	int found = 0;
+	DIR *dir = opendir("/proc/self/fdinfo");
+	assert_errno("opendir", dir);
+
+	while (struct dirent *entry = readdir(dir)) {
+		int fd = atoi(entry->d_name);
+		if (entry->d_name[0] != '.' && (fd == fds[0] || fd == fds[1]))
+			found++;
+	}
+
+	closedir(dir);
+	assert(found == 2);
+
+	close(fds[0]);
+	close(fds[1]);
+
+	int fd = open(path, O_RDONLY);
+	assert(fd == -1 && errno == ENOENT);
+}))
+
+DEFINE_TEST(proc_ipc, ([] {
+	// Nothing was sent to us, so our queue is empty.
+	assert(read_file("/proc/self/ipc").empty());
+
+	// The tracing knob only accepts `0` and `1`.
+	std::string trace = read_file("/proc/sys/ipc_trace");
+	assert(trace == "0\n" || trace == "1\n");
+
+	int fd = open("/proc/sys/ipc_trace", O_WRONLY);
+	assert_errno("open", fd >= 0);
+	assert(write(fd, "2", 1) == -1 && errno == EINVAL);
+	assert(write(fd, trace.c_str(), trace.size()) == (ssize_t)trace.size());
+	close(fd);
+
+	assert(read_file("/proc/sys/ipc_trace") == trace);
+}))
+
+DEFINE_TEST(sysconf_values, ([] {
+	assert(sysconf(_SC_PAGESIZE) == 4096);
+	assert(sysconf(_SC_PAGESIZE) == getpagesize());
+	assert(sysconf(_SC_CLK_TCK) == 100);
+	assert(sysconf(_SC_ARG_MAX) == 128 * 1024);
+
+	// Every CPU listed in `/proc/cpuinfo` is online.
+	std::string info = read_file("/proc/cpuinfo");
+	long cpus = 0;
+	for (size_t pos = 0; (pos = info.find("processor\t:", pos)) != std::string::npos; pos++)
+		cpus++;
+
+	assert(cpus >= 1);
+	assert(sysconf(_SC_NPROCESSORS_ONLN) == cpus);
+	assert(sysconf(_SC_NPROCESSORS_CONF) == cpus);
+
+	// File descriptors are numbered below `_SC_OPEN_MAX`, the soft `RLIMIT_NOFILE` limit.
+	struct rlimit limit;
+	assert_errno("getrlimit", getrlimit(RLIMIT_NOFILE, &limit) != -1);
+
+	long open_max = sysconf(_SC_OPEN_MAX);
+	assert(open_max == 256 && (rlim_t)open_max == limit.rlim_cur);
+	assert_errno("dup2", dup2(0, open_max - 1) == open_max - 1);
+	assert(dup2(0, open_max) == -1);
+	close(open_max - 1);
+
+	// Lowering the soft limit lowers `_SC_OPEN_MAX` and the file descriptors that can be opened.
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		struct rlimit lowered = {16, limit.rlim_max};
+		if (setrlimit(RLIMIT_NOFILE, &lowered) == -1 || sysconf(_SC_OPEN_MAX) != 16)
+			_exit(1);
+
+		if (dup2(0, 16) != -1 || dup2(0, 15) != 15)
+			_exit(2);
+
+		// The hard limit can not be raised.
+		struct rlimit raised = {16, limit.rlim_max + 1};
+		_exit(setrlimit(RLIMIT_NOFILE, &raised) == -1 && errno == EPERM ? 0 : 3);
+	}
+
+	int status;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	assert(sysconf(_SC_OPEN_MAX) == open_max);
+
+	errno = 0;
+	assert(sysconf(-1) == -1 && errno == EINVAL);
+	errno = 0;
+	assert(sysconf(9999) == -1 && errno == EINVAL);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_GETPRIORITY 84
+#define SYS_SETPRIORITY 85
+
+// Returns the `utime` field of `/proc/<pid>/stat`, in clock ticks.
+static unsigned long proc_utime(pid_t pid) {
+	char path[64];
+	snprintf(path, sizeof(path), "/proc/%d/stat", pid);
+
+	std::string stat = read_file(path);
+	size_t comm_end = stat.rfind(')');
+	assert(comm_end != std::string::npos);
+
+	unsigned long utime;
+	assert(sscanf(stat.c_str() + comm_end + 2,
+		"%*c %*d %*d %*d %*d %*d %*u %*u %*u %*u %*u %lu", &utime) == 1);
+
+	return utime;
+}
+
+DEFINE_TEST(nice_cpu_share, ([] {
+	auto spin = [](int nice) {
+		pid_t pid = fork();
+		assert_errno("fork", pid >= 0);
+
+		if (!pid) {
+			if (aero_syscall_errno(SYS_SETPRIORITY, PRIO_PROCESS, 0, nice) == -1)
+				_exit(1);
+
+			while (true)
+				;
+		}
+
+		return pid;
+	};
+
+	pid_t low = spin(19);
+	pid_t high = spin(0);
+
+	sleep(3);
+
+	// `getpriority` returns `20 - nice`.
+	assert_errno("getpriority", aero_syscall_errno(SYS_GETPRIORITY, PRIO_PROCESS, low, 0) == 20 - 19);
+	assert_errno("getpriority", aero_syscall_errno(SYS_GETPRIORITY, PRIO_PROCESS, high, 0) == 20 - 0);
+
+	unsigned long low_utime = proc_utime(low);
+	unsigned long high_utime = proc_utime(high);
+
+	for (pid_t pid : {low, high}) {
+		kill(pid, SIGKILL);
+		assert_errno("waitpid", waitpid(pid, nullptr, 0) == pid);
+	}
+
+	// The weights of nice 0 and nice 19 differ by a factor of about 68.
+	assertf(high_utime > low_utime * 10, "nice 0: %lu ticks, nice 19: %lu ticks",
+		high_utime, low_utime);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_PTRACE 83
+
+#define AERO_PTRACE_TRACEME 0
+#define AERO_PTRACE_PEEKDATA 2
+#define AERO_PTRACE_CONT 7
+#define AERO_PTRACE_GETREGS 12
+#define AERO_PTRACE_SYSCALL 24
+
+struct ptrace_regs {
+	uint64_t r15, r14, r13, r12, rbp, rbx;
+	uint64_t r11, r10, r9, r8, rsi, rdi, rdx, rcx, rax;
+	uint64_t rip, cs, rflags, rsp, ss;
+};
+
+DEFINE_TEST(ptrace_syscall_stop, ([] {
+	const char *path = "/tmp/ptrace-open";
+
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		if (aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_TRACEME, 0, 0, 0) == -1)
+			_exit(1);
+
+		kill(getpid(), SIGSTOP);
+		open(path, O_RDONLY);
+		_exit(0);
+	}
+
+	int status = 0;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFSTOPPED(status) && WSTOPSIG(status) == SIGSTOP);
+
+	// Registers can only be accessed by the tracer.
+	struct ptrace_regs regs;
+	assert(aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_GETREGS, getpid(), 0, (uintptr_t)&regs) == -1);
+	assert(errno == ESRCH);
+
+	bool entering = true;
+	bool seen_open = false;
+
+	while (true) {
+		assert_errno("ptrace", aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_SYSCALL, pid, 0, 0) != -1);
+		assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+
+		if (WIFEXITED(status))
+			break;
+
+		assert(WIFSTOPPED(status) && WSTOPSIG(status) == SIGTRAP);
+		assert_errno("ptrace", aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_GETREGS, pid, 0, (uintptr_t)&regs) != -1);
+
+		if (entering && regs.rax == SYS_OPEN) {
+			// sys_open(dirfd, path, path_len, flags, mode)
+			std::string name;
+
+			for (size_t i = 0; i < regs.rdx; i += sizeof(long)) {
+				long word;
+				assert_errno("ptrace", aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_PEEKDATA, pid, regs.rsi + i, (uintptr_t)&word) != -1);
+				name.append((char *)&word, sizeof(long));
+			}
+
+			name.resize(regs.rdx);
+			assert(name == path);
+			seen_open = true;
+		}
+
+		entering = !entering;
+	}
+
+	assert(seen_open);
+	assert(WEXITSTATUS(status) == 0);
+
+	// The tracee is gone, so it can no longer be resumed.
+	assert(aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_CONT, pid, 0, 0) == -1);
+	assert(errno == ESRCH);
+}))
+
+#define AERO_PTRACE_GETSIGINFO 0x4202
+#define AERO_PTRACE_SET_WATCHPOINT 0x4a00
+#define AERO_PTRACE_GET_WATCHPOINTS 0x4a01
+
+#define AERO_WATCHPOINT_WRITE 1
+
+struct ptrace_watchpoint {
+	uint64_t addr;
+	uint32_t len;
+	uint32_t kind;
+};
+
+static volatile uint64_t watched_value;
+
+DEFINE_TEST(ptrace_watchpoint, ([] {
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		if (aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_TRACEME, 0, 0, 0) == -1)
+			_exit(1);
+
+		kill(getpid(), SIGSTOP);
+		watched_value = 42;
+		_exit(0);
+	}
+
+	int status = 0;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFSTOPPED(status) && WSTOPSIG(status) == SIGSTOP);
+
+	// The child is a copy of this process, so the variable is at the same address.
+	struct ptrace_watchpoint wp = {(uintptr_t)&watched_value, sizeof(watched_value),
+		AERO_WATCHPOINT_WRITE};
+
+	// Watchpoints must be naturally aligned.
+	struct ptrace_watchpoint unaligned = {wp.addr + 1, 8, AERO_WATCHPOINT_WRITE};
+	assert(aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_SET_WATCHPOINT, pid, 0, (uintptr_t)&unaligned) == -1);
+	assert(errno == EINVAL);
+
+	assert_errno("ptrace", aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_SET_WATCHPOINT, pid, 0, (uintptr_t)&wp) != -1);
+	assert_errno("ptrace", aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_CONT, pid, 0, 0) != -1);
+
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFSTOPPED(status) && WSTOPSIG(status) == SIGTRAP);
+
+	siginfo_t info;
+	assert_errno("ptrace", aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_GETSIGINFO, pid, 0, (uintptr_t)&info) != -1);
+	assert(info.si_signo == SIGTRAP);
+	assert(info.si_code == TRAP_HWBKPT);
+	assert(info.si_addr == (void *)&watched_value);
+
+	struct ptrace_watchpoint wps[4];
+	long triggered = aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_GET_WATCHPOINTS, pid, 0, (uintptr_t)wps);
+	assert_errno("ptrace", triggered != -1);
+	assert(triggered == 1);
+	assert(wps[0].addr == wp.addr && wps[0].len == wp.len && wps[0].kind == wp.kind);
+	assert(wps[1].len == 0);
+
+	assert_errno("ptrace", aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_CONT, pid, 0, 0) != -1);
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}))
+
+#define AERO_PTRACE_ATTACH 16
+
+DEFINE_TEST(ptrace_attach_exit, ([] {
+	int go[2];
+	assert_errno("pipe", pipe(go) != -1);
+
+	pid_t tracee = fork();
+	assert_errno("fork", tracee >= 0);
+
+	if (!tracee) {
+		char c;
+		close(go[1]);
+
+		if (read(go[0], &c, 1) != 1)
+			_exit(1);
+
+		_exit(7);
+	}
+
+	close(go[0]);
+
+	// The tracer is a sibling of the tracee, so it only sees the exit through ptrace.
+	pid_t tracer = fork();
+	assert_errno("fork", tracer >= 0);
+
+	if (!tracer) {
+		int status = 0;
+
+		if (aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_ATTACH, tracee, 0, 0) == -1)
+			_exit(1);
+
+		if (waitpid(tracee, &status, 0) != tracee || !WIFSTOPPED(status)
+				|| WSTOPSIG(status) != SIGSTOP)
+			_exit(2);
+
+		if (aero_syscall_errno(SYS_PTRACE, AERO_PTRACE_CONT, tracee, 0, 0) == -1 || write(go[1], "x", 1) != 1)
+			_exit(3);
+
+		if (waitpid(tracee, &status, 0) != tracee || !WIFEXITED(status)
+				|| WEXITSTATUS(status) != 7)
+			_exit(4);
+
+		_exit(0);
+	}
+
+	close(go[1]);
+
+	int status = 0;
+	assert_errno("waitpid", waitpid(tracer, &status, 0) == tracer);
+	assertf(WIFEXITED(status) && WEXITSTATUS(status) == 0, "tracer failed with %d",
+		WEXITSTATUS(status));
+
+	// Once the tracer waited for the exit, the tracee is handed back to its parent.
+	assert_errno("waitpid", waitpid(tracee, &status, 0) == tracee);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 7);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_KCMP 89
+
+#define AERO_KCMP_FILE 0
+#define AERO_KCMP_VM 1
+
+DEFINE_TEST(kcmp_dup, ([] {
+	pid_t self = getpid();
+
+	int fd = open("/tmp/kcmp-dup", O_RDWR | O_CREAT, 0644);
+	assert_errno("open", fd >= 0);
+
+	int dup_fd = dup(fd);
+	assert_errno("dup", dup_fd >= 0);
+
+	int other_fd = open("/dev/null", O_RDONLY);
+	assert_errno("open", other_fd >= 0);
+
+	assert(aero_syscall_errno(SYS_KCMP, self, self, AERO_KCMP_FILE, fd, dup_fd) == 0);
+
+	// Different files are ordered consistently in both directions.
+	long ordering = aero_syscall_errno(SYS_KCMP, self, self, AERO_KCMP_FILE, fd, other_fd);
+	assert(ordering == 1 || ordering == 2);
+	assert(aero_syscall_errno(SYS_KCMP, self, self, AERO_KCMP_FILE, other_fd, fd) == 3 - ordering);
+
+	assert(aero_syscall_errno(SYS_KCMP, self, self, AERO_KCMP_FILE, fd, 1000) == -1);
+	assert(errno == EBADF);
+
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		pause();
+		_exit(0);
+	}
+
+	// The child inherited the file descriptor, but not the address space.
+	assert(aero_syscall_errno(SYS_KCMP, self, pid, AERO_KCMP_FILE, fd, fd) == 0);
+	assert(aero_syscall_errno(SYS_KCMP, self, pid, AERO_KCMP_VM, 0, 0) != 0);
+	assert(aero_syscall_errno(SYS_KCMP, self, self, AERO_KCMP_VM, 0, 0) == 0);
+
+	kill(pid, SIGKILL);
+	assert_errno("waitpid", waitpid(pid, nullptr, 0) == pid);
+
+	assert(aero_syscall_errno(SYS_KCMP, self, pid, AERO_KCMP_FILE, fd, fd) == -1);
+	assert(errno == ESRCH);
+
+	close(other_fd);
+	close(dup_fd);
+	close(fd);
+	unlink("/tmp/kcmp-dup");
+}))
+
+DEFINE_TEST(umask_create, ([] {
+	mode_t old = umask(077);
+
+	int fd = open("/tmp/umask-create", O_RDWR | O_CREAT | O_EXCL, 0666);
+	assert_errno("open", fd >= 0);
+
+	struct stat st;
+	assert_errno("fstat", fstat(fd, &st) == 0);
+	assert((st.st_mode & 0777) == 0600);
+
+	close(fd);
+	unlink("/tmp/umask-create");
+
+	assert(umask(old) == 077);
+}))
+
+DEFINE_TEST(umask_fork, ([] {
+	mode_t old = umask(027);
+
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		// The child inherits the mask, but changing it does not affect the parent.
+		if (umask(077) != 027)
+			_exit(1);
+
+		_exit(umask(0) == 077 ? 0 : 1);
+	}
+
+	int status;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	assert(umask(old) == 027);
+}))
+
+#define SYS_IO_URING_SETUP 91
+#define SYS_IO_URING_ENTER 92
+
+DEFINE_TEST(io_uring_pipe, ([] {
+	struct io_uring_params params;
+	memset(&params, 0, sizeof(params));
+
+	int ring = aero_syscall_errno(SYS_IO_URING_SETUP, 4, &params);
+	assert_errno("io_uring_setup", ring >= 0);
+	assert(params.sq_entries == 4);
+	assert(params.features & IORING_FEAT_SINGLE_MMAP);
+
+	size_t sq_size = params.sq_off.array + params.sq_entries * sizeof(unsigned);
+	size_t cq_size = params.cq_off.cqes + params.cq_entries * sizeof(struct io_uring_cqe);
+	size_t rings_size = sq_size > cq_size ? sq_size : cq_size;
+
+	auto rings = (char *)mmap(nullptr, rings_size, PROT_READ | PROT_WRITE, MAP_SHARED,
+			ring, IORING_OFF_SQ_RING);
+	assert_errno("mmap", rings != MAP_FAILED);
+
+	auto sqes = (struct io_uring_sqe *)mmap(nullptr, params.sq_entries * sizeof(struct io_uring_sqe),
+			PROT_READ | PROT_WRITE, MAP_SHARED, ring, IORING_OFF_SQES);
+	assert_errno("mmap", sqes != MAP_FAILED);
+
+	auto sq_tail = (unsigned *)(rings + params.sq_off.tail);
+	auto sq_mask = *(unsigned *)(rings + params.sq_off.ring_mask);
+	auto sq_array = (unsigned *)(rings + params.sq_off.array);
+
+	auto cq_head = (unsigned *)(rings + params.cq_off.head);
+	auto cq_tail = (unsigned *)(rings + params.cq_off.tail);
+	auto cq_mask = *(unsigned *)(rings + params.cq_off.ring_mask);
+	auto cqes = (struct io_uring_cqe *)(rings + params.cq_off.cqes);
+
+	int fds[2];
+	assert_errno("pipe", pipe(fds) == 0);
+
+	char in[6] = {};
+	char out[] = "hello";
+
+	auto push = [&](uint8_t opcode, int fd, void *buffer, unsigned len, uint64_t user_data) {
+		unsigned tail = *sq_tail;
+		struct io_uring_sqe *sqe = &sqes[tail & sq_mask];
+
+		memset(sqe, 0, sizeof(*sqe));
+		sqe->opcode = opcode;
+		sqe->fd = fd;
+		sqe->off = (uint64_t)-1;
+		sqe->addr = (uint64_t)buffer;
+		sqe->len = len;
+		sqe->user_data = user_data;
+
+		sq_array[tail & sq_mask] = tail & sq_mask;
+		__atomic_store_n(sq_tail, tail + 1, __ATOMIC_RELEASE);
+	};
+
+	// The read is submitted first, so it has to wait for the write to complete.
+	push(IORING_OP_READ, fds[0], in, 5, 1);
+	push(IORING_OP_WRITE, fds[1], out, 5, 2);
+
+	int submitted = aero_syscall_errno(SYS_IO_URING_ENTER, ring, 2, 2, IORING_ENTER_GETEVENTS);
+	assert_errno("io_uring_enter", submitted == 2);
+
+	unsigned head = *cq_head;
+	assert(__atomic_load_n(cq_tail, __ATOMIC_ACQUIRE) - head == 2);
+
+	bool seen[2] = {};
+
+	for (unsigned i = 0; i < 2; i++) {
+		struct io_uring_cqe *cqe = &cqes[(head + i) & cq_mask];
+
+		assert(cqe->user_data == 1 || cqe->user_data == 2);
+		assert(cqe->res == 5);
+		seen[cqe->user_data - 1] = true;
+	}
+
+	assert(seen[0] && seen[1]);
+	__atomic_store_n(cq_head, head + 2, __ATOMIC_RELEASE);
+
+	assert(!strcmp(in, "hello"));
+
+	// Requests on invalid file descriptors complete with an error.
+	push(IORING_OP_READ, 1000, in, 5, 3);
+	assert_errno("io_uring_enter", aero_syscall_errno(SYS_IO_URING_ENTER, ring, 1, 1, IORING_ENTER_GETEVENTS) == 1);
+
+	head = *cq_head;
+	assert(cqes[head & cq_mask].user_data == 3);
+	assert(cqes[head & cq_mask].res == -EBADF);
+	__atomic_store_n(cq_head, head + 1, __ATOMIC_RELEASE);
+
+	munmap(sqes, params.sq_entries * sizeof(struct io_uring_sqe));
+	munmap(rings, rings_size);
+	close(fds[0]);
+	close(fds[1]);
+	close(ring);
+}))
+
+#define SYS_PERF_EVENT_OPEN 97
+
+static uint64_t perf_read(int fd) {
+	uint64_t value;
+	assert_errno("read", read(fd, &value, sizeof(value)) == sizeof(value));
+	return value;
+}
+
+DEFINE_TEST(perf_event_cycles, ([] {
+	struct perf_event_attr attr;
+	memset(&attr, 0, sizeof(attr));
+
+	attr.type = PERF_TYPE_HARDWARE;
+	attr.size = sizeof(attr);
+	attr.config = PERF_COUNT_HW_CPU_CYCLES;
+	attr.disabled = 1;
+
+	int fd = aero_syscall_errno(SYS_PERF_EVENT_OPEN, &attr, 0, -1, -1, PERF_FLAG_FD_CLOEXEC);
+	if (fd == -1 && (errno == EOPNOTSUPP || errno == ENOENT)) {
+		printf("test skipped... no performance monitoring unit\n");
+		return;
+	}
+
+	assert_errno("perf_event_open", fd != -1);
+
+	// Only the calling thread can be measured.
+	assert(aero_syscall_errno(SYS_PERF_EVENT_OPEN, &attr, 1, -1, -1, 0) == -1 && errno == EINVAL);
+
+	// The counter does not run until it is enabled.
+	assert(perf_read(fd) == 0);
+
+	assert_errno("ioctl", ioctl(fd, PERF_EVENT_IOC_ENABLE, 0) != -1);
+
+	volatile uint64_t sum = 0;
+	for (int i = 0; i < 100000; i++)
+		sum += i;
+
+	assert_errno("ioctl", ioctl(fd, PERF_EVENT_IOC_DISABLE, 0) != -1);
+
+	uint64_t cycles = perf_read(fd);
+	assert(cycles > 0);
+	assert(perf_read(fd) == cycles);
+
+	assert_errno("ioctl", ioctl(fd, PERF_EVENT_IOC_RESET, 0) != -1);
+	assert(perf_read(fd) == 0);
+
+	close(fd);
+}))
+#endif
+
+static inline bool cpuid(uint32_t leaf, uint32_t subleaf,
+                         uint32_t *eax, uint32_t *ebx, uint32_t *ecx, uint32_t *edx)  {
+	uint32_t cpuid_max;
+    asm volatile ("cpuid"
+                  : "=a" (cpuid_max)
+                  : "a" (leaf & 0x80000000) : "rbx", "rcx", "rdx");
+    if (leaf > cpuid_max)
+        return false;
+    asm volatile ("cpuid"
+                  : "=a" (*eax), "=b" (*ebx), "=c" (*ecx), "=d" (*edx)
+                  : "a" (leaf), "c" (subleaf));
+    return true;
+}
+
+// Returns [`true`] if the `SYSENTER` and `SYSEXIT` and associated MSRs are supported.
+bool has_sysenter_sysexit() {
+	uint32_t eax, ebx, ecx, edx;
+	// LEAF 1: Processor and Processor Feature Identifiers
+	if (!cpuid(1, 0, &eax, &ebx, &ecx, &edx)) {
+		return false;
+	}
+	return edx & (1 << 11);
+}
+
+#if defined(__aero__)
+DEFINE_TEST(bad_sysenter, ([] {
+	if (!has_sysenter_sysexit()) {
+		printf("test skipped... sysenter not supported\n");
+		return;
+	}
+
+	int pid = fork();
+
+	if (!pid) {
+		register long r11 __asm__("r11") = (size_t)0xf0f0 << 48;
+		register long rcx __asm__("rcx") = (size_t)0xf0f0 << 48;
+
+		asm volatile(
+			"sysenter\n"
+			:  	
+			: "r"(r11), "r"(rcx)
+		);
+		
+		__builtin_unreachable();
+	} else {
+		int status = 0;
+		if (!waitpid(pid, &status, 0))
+			assert(!"waitpid() failed");
+
+		// FIXME: should we get killed with SIGSEGV instead?
+		assert(WIFEXITED(status));
+	}
+}))
+#endif
+
+#if defined(__aero__)
+DEFINE_TEST(sysenter_system_call, ([] {
+	if (!has_sysenter_sysexit()) {
+		printf("test skipped... sysenter not supported\n");
+		return;
+	}
+
+	int fds[2];
+	if (pipe(fds) == -1)
+		assert(!"pipe() failed");
+
+	int pid = fork();
+
+	if (!pid) {
+		close(fds[0]);
+
+		const char *buf = "Hello, world!\n";
+		size_t buf_size = strlen(buf);
+
+		__asm__ __volatile__ (
+			"mov %%rsp, %%r11\n\t"
+			"lea 1f(%%rip), %%rcx\n\t"
+			"sysenter\n\t"
+			"1:"
+			:
+			: "a"(uint64_t(1)), "D"(uint64_t(fds[1])), "S"(buf), "d"(buf_size + 1)
+			: "rcx", "r11"
+		);
+
+		exit(0);
+	} else {
+		close(fds[1]);
+
+		int status = 0;
+		if (!waitpid(pid, &status, 0))
+			assert(!"waitpid() failed");
+
+		assert(WIFEXITED(status));
+
+		char tmp[15];
+		ssize_t n = read(fds[0], tmp, sizeof(tmp));
+
+		assert(n == 15);
+		assert(!strcmp(tmp, "Hello, world!\n"));
+	}
+}))
+#endif
+
+#if defined(__aero__)
+// The spawn helper of `aero_syscall` is tested against the kernel by the `spawn_test` program.
+DEFINE_TEST(spawn_rust_helper, ([] {
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		char *argv[] = {(char *)"/usr/bin/spawn_test", nullptr};
+		execv(argv[0], argv);
+		_exit(127);
+	}
+
+	int status;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}))
+#endif
+
+DEFINE_TEST(dup_same_fd, ([] {
+	int fds[2];
+	assert_errno("pipe", pipe2(fds, O_CLOEXEC) != -1);
+
+	// `dup2` onto itself only checks that the descriptor is valid, and keeps its flags.
+	assert(dup2(fds[0], fds[0]) == fds[0]);
+	assert(fcntl(fds[0], F_GETFD) == FD_CLOEXEC);
+
+	close(fds[0]);
+	assert(dup2(fds[0], fds[0]) == -1);
+
+	// `dup3` onto itself is an error.
+	assert(dup3(fds[1], fds[1], 0) == -1 && errno == EINVAL);
+	assert(dup3(fds[1], fds[1], O_CLOEXEC) == -1 && errno == EINVAL);
+
+	// `O_CLOEXEC` is the only flag `dup3` accepts.
+	assert(dup3(fds[1], 100, O_NONBLOCK) == -1 && errno == EINVAL);
+	assert(fcntl(100, F_GETFD) == -1);
+
+	close(fds[1]);
+}))
+
+DEFINE_TEST(dup_cloexec, ([] {
+	int fds[2];
+	assert_errno("pipe", pipe2(fds, O_CLOEXEC) != -1);
+
+	int other = open("/dev/null", O_RDONLY);
+	assert_errno("open", other != -1);
+
+	// The close-on-exec flag is not copied from the duplicated descriptor, and the file `other`
+	// referred to is replaced.
+	assert(dup2(fds[1], other) == other);
+	assert(fcntl(other, F_GETFD) == 0);
+
+	assert(write(other, "x", 1) == 1);
+
+	char c;
+	assert(read(fds[0], &c, 1) == 1 && c == 'x');
+
+	assert(dup3(fds[1], other, O_CLOEXEC) == other);
+	assert(fcntl(other, F_GETFD) == FD_CLOEXEC);
+
+	assert(dup3(fds[1], other, 0) == other);
+	assert(fcntl(other, F_GETFD) == 0);
+
+	close(other);
+	close(fds[0]);
+	close(fds[1]);
+}))
+
+// Runs `echo hello` with its standard output duplicated onto a pipe with `dup3(flags)`, and
+// returns what reached the pipe.
+static std::string dup3_echo_output(int flags) {
+	int fds[2];
+	assert_errno("pipe", pipe(fds) != -1);
+
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		if (dup3(fds[1], STDOUT_FILENO, flags) == -1)
+			_exit(1);
+
+		close(fds[0]);
+		close(fds[1]);
+
+		char *argv[] = {(char *)"/usr/bin/echo", (char *)"hello", nullptr};
+		execv("/usr/bin/echo", argv);
+		_exit(127);
+	}
+
+	close(fds[1]);
+
+	std::string output;
+	char buf[64];
+	ssize_t n;
+
+	while ((n = read(fds[0], buf, sizeof(buf))) > 0)
+		output.append(buf, n);
+
+	close(fds[0]);
+
+	int status = 0;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) != 127);
+
+	return output;
+}
+
+DEFINE_TEST(dup3_cloexec_exec, ([] {
+	assert(dup3_echo_output(0) == "hello\n");
+
+	// The duplicate is closed by `exec`, so `echo` has no standard output.
+	assert(dup3_echo_output(O_CLOEXEC) == "");
+}))
+
+// Returns `false` (and reports the test as skipped) if inotify is not supported.
+static bool inotify_supported(int fd) {
+	if (fd == -1 && errno == ENOSYS) {
+		printf("test skipped... inotify not supported\n");
+		return false;
+	}
+
+	assert_errno("inotify_init1", fd != -1);
+	return true;
+}
+
+// Runs `f` in a child process and waits until it signals completion through a pipe.
+template <typename F>
+static void inotify_run_child(F f) {
+	int fds[2];
+	assert_errno("pipe", pipe(fds) != -1);
+
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		close(fds[0]);
+		f();
+
+		char done = 1;
+		assert_errno("write", write(fds[1], &done, 1) == 1);
+		_exit(0);
+	}
+
+	close(fds[1]);
+
+	char done;
+	assert_errno("read", read(fds[0], &done, 1) == 1);
+	close(fds[0]);
+
+	int status = 0;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// Reads the next event from `fd` and checks its mask and name.
+static void inotify_expect(int fd, uint32_t mask, const char *name) {
+	alignas(struct inotify_event) char buf[sizeof(struct inotify_event) + NAME_MAX + 1];
+
+	ssize_t n = read(fd, buf, sizeof(buf));
+	assert_errno("read", n >= (ssize_t)sizeof(struct inotify_event));
+
+	auto event = reinterpret_cast<struct inotify_event *>(buf);
+	assert(event->mask & mask);
+
+	if (name) {
+		assert(event->len > 0);
+		assert(!strcmp(event->name, name));
+	}
+}
+
+DEFINE_TEST(inotify_create_notify, ([] {
+	int fd = inotify_init1(0);
+	if (!inotify_supported(fd))
+		return;
+
+	assert_errno("mkdir", mkdir("/tmp/inotify-create", 0777) != -1);
+	assert_errno("inotify_add_watch", inotify_add_watch(fd, "/tmp/inotify-create", IN_CREATE) != -1);
+
+	inotify_run_child([] {
+		int file = open("/tmp/inotify-create/file", O_CREAT | O_WRONLY, 0666);
+		assert_errno("open", file != -1);
+		close(file);
+	});
+
+	inotify_expect(fd, IN_CREATE, "file");
+
+	unlink("/tmp/inotify-create/file");
+	rmdir("/tmp/inotify-create");
+	close(fd);
+}))
+
+DEFINE_TEST(inotify_write_notify, ([] {
+	int fd = inotify_init1(0);
+	if (!inotify_supported(fd))
+		return;
+
+	int file = open("/tmp/inotify-write", O_CREAT | O_WRONLY, 0666);
+	assert_errno("open", file != -1);
+	close(file);
+
+	assert_errno("inotify_add_watch", inotify_add_watch(fd, "/tmp/inotify-write", IN_MODIFY) != -1);
+
+	inotify_run_child([] {
+		int file = open("/tmp/inotify-write", O_WRONLY);
+		assert_errno("open", file != -1);
+		assert_errno("write", write(file, "aero", 4) == 4);
+		close(file);
+	});
+
+	inotify_expect(fd, IN_MODIFY, nullptr);
+
+	unlink("/tmp/inotify-write");
+	close(fd);
+}))
+
+DEFINE_TEST(inotify_delete_notify, ([] {
+	int fd = inotify_init1(0);
+	if (!inotify_supported(fd))
+		return;
+
+	assert_errno("mkdir", mkdir("/tmp/inotify-delete", 0777) != -1);
+
+	int file = open("/tmp/inotify-delete/file", O_CREAT | O_WRONLY, 0666);
+	assert_errno("open", file != -1);
+	close(file);
+
+	assert_errno("inotify_add_watch", inotify_add_watch(fd, "/tmp/inotify-delete", IN_DELETE) != -1);
+
+	inotify_run_child([] {
+		assert_errno("unlink", unlink("/tmp/inotify-delete/file") != -1);
+	});
+
+	inotify_expect(fd, IN_DELETE, "file");
+
+	rmdir("/tmp/inotify-delete");
+	close(fd);
+}))
+
+DEFINE_TEST(inotify_nonblock, ([] {
+	int fd = inotify_init1(IN_NONBLOCK);
+	if (!inotify_supported(fd))
+		return;
+
+	int file = open("/tmp/inotify-nonblock", O_CREAT | O_WRONLY, 0666);
+	assert_errno("open", file != -1);
+	close(file);
+
+	assert_errno("inotify_add_watch",
+		inotify_add_watch(fd, "/tmp/inotify-nonblock", IN_CLOSE_WRITE) != -1);
+
+	char buf[sizeof(struct inotify_event) + NAME_MAX + 1];
+	assert(read(fd, buf, sizeof(buf)) == -1);
+	assert(errno == EAGAIN);
+
+	inotify_run_child([] {
+		int file = open("/tmp/inotify-nonblock", O_WRONLY);
+		assert_errno("open", file != -1);
+		close(file);
+	});
+
+	inotify_expect(fd, IN_CLOSE_WRITE, nullptr);
+
+	assert(read(fd, buf, sizeof(buf)) == -1);
+	assert(errno == EAGAIN);
+
+	unlink("/tmp/inotify-nonblock");
+	close(fd);
+}))
+
+DEFINE_TEST(inotify_overflow, ([] {
+	// Linux defaults `max_queued_events` to 16384.
+	constexpr size_t max_events = 16384;
+
+	int fd = inotify_init1(IN_NONBLOCK);
+	if (!inotify_supported(fd))
+		return;
+
+	int file = open("/tmp/inotify-overflow", O_CREAT | O_WRONLY, 0666);
+	assert_errno("open", file != -1);
+	close(file);
+
+	assert_errno("inotify_add_watch",
+		inotify_add_watch(fd, "/tmp/inotify-overflow", IN_OPEN | IN_CLOSE_NOWRITE) != -1);
+
+	// Opening and closing the file queues two events which are not coalesced, since identical
+	// events are only merged when they are queued back to back.
+	inotify_run_child([] {
+		for (size_t i = 0; i < (max_events + 1) / 2 + 1; i++) {
+			int file = open("/tmp/inotify-overflow", O_RDONLY);
+			assert_errno("open", file != -1);
+			close(file);
+		}
+	});
+
+	alignas(struct inotify_event) char buf[4096];
+	size_t events = 0;
+	bool overflowed = false;
+
+	while (true) {
+		ssize_t n = read(fd, buf, sizeof(buf));
+		if (n == -1) {
+			assert(errno == EAGAIN);
+			break;
+		}
+
+		for (char *ptr = buf; ptr < buf + n;) {
+			auto event = reinterpret_cast<struct inotify_event *>(ptr);
+
+			if (event->mask & IN_Q_OVERFLOW) {
+				assert(event->wd == -1);
+				overflowed = true;
+			} else {
+				events++;
+			}
+
+			ptr += sizeof(struct inotify_event) + event->len;
+		}
+	}
+
+	assert(overflowed);
+	assert(events <= max_events);
+
+	unlink("/tmp/inotify-overflow");
+	close(fd);
+}))
+
+#define SYS_INOTIFY_INIT 94
+#define SYS_INOTIFY_ADD_WATCH 95
+
+DEFINE_TEST(inotify_raw_rename, ([] {
+	assert_errno("mkdir", mkdir("/tmp/inotify-raw", 0777) != -1);
+
+	int fd = aero_syscall_errno(SYS_INOTIFY_INIT, IN_NONBLOCK);
+	assert_errno("inotify_init", fd != -1);
+
+	int wd = aero_syscall_errno(SYS_INOTIFY_ADD_WATCH, fd, "/tmp/inotify-raw", strlen("/tmp/inotify-raw"), IN_CREATE | IN_MOVE | IN_DELETE);
+	assert_errno("inotify_add_watch", wd != -1);
+
+	int file = open("/tmp/inotify-raw/a", O_CREAT | O_WRONLY, 0666);
+	assert_errno("open", file != -1);
+	close(file);
+
+	assert_errno("rename", rename("/tmp/inotify-raw/a", "/tmp/inotify-raw/b") != -1);
+	assert_errno("unlink", unlink("/tmp/inotify-raw/b") != -1);
+
+	alignas(struct inotify_event) char buf[4096];
+	ssize_t n = read(fd, buf, sizeof(buf));
+	assert_errno("read", n > 0);
+
+	std::vector<struct inotify_event *> events;
+	for (char *ptr = buf; ptr < buf + n;) {
+		auto event = reinterpret_cast<struct inotify_event *>(ptr);
+		assert(event->wd == wd);
+
+		events.push_back(event);
+		ptr += sizeof(struct inotify_event) + event->len;
+	}
+
+	assert(events.size() == 4);
+
+	assert(events[0]->mask == IN_CREATE && !strcmp(events[0]->name, "a"));
+	assert(events[1]->mask == IN_MOVED_FROM && !strcmp(events[1]->name, "a"));
+	assert(events[2]->mask == IN_MOVED_TO && !strcmp(events[2]->name, "b"));
+	assert(events[3]->mask == IN_DELETE && !strcmp(events[3]->name, "b"));
+
+	// Both halves of the rename share a cookie, which no other event has.
+	assert(events[1]->cookie && events[1]->cookie == events[2]->cookie);
+	assert(!events[0]->cookie && !events[3]->cookie);
+
+	// The queue is now empty.
+	assert(read(fd, buf, sizeof(buf)) == -1 && errno == EAGAIN);
+
+	close(fd);
+	rmdir("/tmp/inotify-raw");
+}))
+
+// Reads the names of the remaining entries of `dir`, except for "." and "..".
+static std::vector<std::string> readdir_names(DIR *dir) {
+	std::vector<std::string> names;
+
+	errno = 0;
+	while (struct dirent *entry = readdir(dir)) {
+		if (strcmp(entry->d_name, ".") && strcmp(entry->d_name, ".."))
+			names.push_back(entry->d_name);
+	}
+
+	assert_errno("readdir", errno == 0);
+	return names;
+}
+
+DEFINE_TEST(readdir_skip_deleted, ([] {
+	// `/tmp` is a tmpfs, the root filesystem is ext2.
+	constexpr int files = 1000;
+	char path[64];
+
+	assert_errno("mkdir", mkdir("/readdir-holes", 0777) != -1);
+
+	for (int i = 0; i < files; i++) {
+		sprintf(path, "/readdir-holes/%d", i);
+
+		int fd = open(path, O_CREAT | O_WRONLY, 0666);
+		assert_errno("open", fd != -1);
+		close(fd);
+	}
+
+	// Deletes every other file, which leaves holes all over the directory.
+	for (int i = 0; i < files; i += 2) {
+		sprintf(path, "/readdir-holes/%d", i);
+		assert_errno("unlink", unlink(path) != -1);
+		assert(access(path, F_OK) == -1 && errno == ENOENT);
+	}
+
+	DIR *dir = opendir("/readdir-holes");
+	assert_errno("opendir", dir);
+
+	auto names = readdir_names(dir);
+	std::set<std::string> unique(names.begin(), names.end());
+
+	assert(names.size() == files / 2);
+	assert(unique.size() == names.size());
+
+	for (int i = 1; i < files; i += 2)
+		assert(unique.count(std::to_string(i)));
+
+	// Resuming at a saved position must return the same entry, even after entries were added.
+	rewinddir(dir);
+
+	for (int i = 0; i < 100; i++)
+		assert(readdir(dir));
+
+	long position = telldir(dir);
+	assert_errno("telldir", position != -1);
+
+	struct dirent *entry = readdir(dir);
+	assert(entry);
+	std::string expected = entry->d_name;
+
+	for (int i = files; i < files + 10; i++) {
+		sprintf(path, "/readdir-holes/%d", i);
+
+		int fd = open(path, O_CREAT | O_WRONLY, 0666);
+		assert_errno("open", fd != -1);
+		close(fd);
+	}
+
+	seekdir(dir, position);
+	entry = readdir(dir);
+	assert(entry);
+	assert(expected == entry->d_name);
+
+	closedir(dir);
+
+	for (int i = 1; i < files + 10; i++) {
+		sprintf(path, "/readdir-holes/%d", i);
+		unlink(path);
+	}
+
+	rmdir("/readdir-holes");
+}))
+
+// Workers of the `fs_stress` test, which run concurrently on a shared directory. Each one is
+// seeded from its index so a failure can be reproduced, and runs for a fixed number of
+// iterations.
+namespace fs_stress {
+
+// `/tmp` is a tmpfs, the root filesystem is ext2.
+constexpr const char *root = "/fs-stress";
+constexpr int file_workers = 8;
+constexpr int iterations = 64;
+constexpr uint64_t base_seed = 0x5eed;
+
+// xorshift64*
+struct rng {
+	uint64_t state;
+
+	uint64_t next() {
+		state ^= state >> 12;
+		state ^= state << 25;
+		state ^= state >> 27;
+		return state * 0x2545f4914f6cdd1dull;
+	}
+};
+
+static uint64_t worker_seed(int id) {
+	return base_seed + id;
+}
+
+// FNV-1a
+static uint32_t checksum(const std::vector<uint8_t> &data) {
+	uint32_t hash = 2166136261u;
+
+	for (uint8_t byte : data)
+		hash = (hash ^ byte) * 16777619u;
+
+	return hash;
+}
+
+#define stress_assert(id, A) assertf(A, "worker %d (seed %#lx)", id, worker_seed(id))
+
+// Creates, writes, renames, reads back and deletes files of random sizes.
+static void file_worker(int id) {
+	rng random{worker_seed(id)};
+	char path[64], renamed[64];
+
+	for (int i = 0; i < iterations; i++) {
+		sprintf(path, "%s/w%d-%d", root, id, i);
+		sprintf(renamed, "%s/w%d-%d.renamed", root, id, i);
+
+		// Up to three blocks, so the writes cross block boundaries.
+		std::vector<uint8_t> data(1 + random.next() % (3 * PAGE_SIZE));
+
+		for (auto &byte : data)
+			byte = random.next();
+
+		int fd = open(path, O_CREAT | O_EXCL | O_WRONLY, 0644);
+		stress_assert(id, fd != -1);
+
+		for (size_t done = 0; done < data.size();) {
+			ssize_t written = write(fd, data.data() + done, data.size() - done);
+			stress_assert(id, written > 0);
+			done += written;
+		}
+
+		close(fd);
+
+		stress_assert(id, rename(path, renamed) != -1);
+		stress_assert(id, access(path, F_OK) == -1 && errno == ENOENT);
+
+		std::vector<uint8_t> read_back(data.size() + 1);
+		size_t done = 0;
+
+		fd = open(renamed, O_RDONLY);
+		stress_assert(id, fd != -1);
+
+		while (ssize_t bytes = read(fd, read_back.data() + done, read_back.size() - done)) {
+			stress_assert(id, bytes > 0);
+			done += bytes;
+		}
+
+		close(fd);
+		read_back.resize(done);
+
+		stress_assert(id, read_back.size() == data.size());
+		stress_assert(id, checksum(read_back) == checksum(data));
+
+		stress_assert(id, unlink(renamed) != -1);
+	}
+}
+
+// Scans the directory while the other workers add, rename and remove entries in it.
+static void scan_worker(int id) {
+	for (int i = 0; i < iterations * 4; i++) {
+		DIR *dir = opendir(root);
+		stress_assert(id, dir);
+
+		auto names = readdir_names(dir);
+		closedir(dir);
+
+		// The names are never reused, so an entry is returned at most once by a scan.
+		std::set<std::string> unique(names.begin(), names.end());
+		stress_assert(id, unique.size() == names.size());
+
+		for (auto &name : names)
+			stress_assert(id, name[0] == 'w' || name[0] == 'd');
+	}
+}
+
+// Creates and removes nested directories.
+static void dir_worker(int id) {
+	char top[64], middle[64], bottom[64], file[64];
+
+	for (int i = 0; i < iterations; i++) {
+		sprintf(top, "%s/d%d-%d", root, id, i);
+		sprintf(middle, "%s/a", top);
+		sprintf(bottom, "%s/b", middle);
+		sprintf(file, "%s/file", bottom);
+
+		stress_assert(id, mkdir(top, 0755) != -1);
+		stress_assert(id, mkdir(middle, 0755) != -1);
+		stress_assert(id, mkdir(bottom, 0755) != -1);
+
+		int fd = open(file, O_CREAT | O_WRONLY, 0644);
+		stress_assert(id, fd != -1);
+		close(fd);
+
+		stress_assert(id, rmdir(middle) == -1 && errno == ENOTEMPTY);
+
+		stress_assert(id, unlink(file) != -1);
+		stress_assert(id, rmdir(bottom) != -1);
+		stress_assert(id, rmdir(middle) != -1);
+		stress_assert(id, rmdir(top) != -1);
+	}
+}
+
+#undef stress_assert
+
+} // namespace fs_stress
+
+DEFINE_TEST(fs_stress, ([] {
+	using namespace fs_stress;
+
+	assert_errno("mkdir", mkdir(root, 0777) != -1);
+
+	struct statvfs before;
+	assert_errno("statvfs", statvfs(root, &before) != -1);
+	assert(before.f_blocks && before.f_bfree <= before.f_blocks);
+
+	std::vector<void (*)(int)> workers(file_workers, file_worker);
+	workers.push_back(scan_worker);
+	workers.push_back(dir_worker);
+
+	std::vector<pid_t> children;
+
+	for (size_t id = 0; id < workers.size(); id++) {
+		pid_t child = fork();
+		assert_errno("fork", child != -1);
+
+		if (!child) {
+			workers[id](id);
+			_exit(0);
+		}
+
+		children.push_back(child);
+	}
+
+	for (size_t id = 0; id < children.size(); id++) {
+		int status;
+		assert_errno("waitpid", waitpid(children[id], &status, 0) == children[id]);
+		assertf(WIFEXITED(status) && WEXITSTATUS(status) == 0, "worker %zu (seed %#lx) failed",
+				id, worker_seed(id));
+	}
+
+	DIR *dir = opendir(root);
+	assert_errno("opendir", dir);
+	assert(readdir_names(dir).empty());
+	closedir(dir);
+
+	struct statvfs after;
+	assert_errno("statvfs", statvfs(root, &after) != -1);
+
+	// The blocks of the files are all freed, but the directory may have grown.
+	assert(after.f_bfree + 16 >= before.f_bfree);
+
+	assert_errno("rmdir", rmdir(root) != -1);
+}))
+
+DEFINE_TEST(append_ignores_offset, ([] {
+	// `/tmp` is a tmpfs, the root filesystem is ext2.
+	const char *path = "/append-offset";
+
+	int fd = open(path, O_CREAT | O_TRUNC | O_RDWR | O_APPEND, 0644);
+	assert_errno("open", fd != -1);
+
+	assert_errno("write", write(fd, "abc", 3) == 3);
+
+	// The write goes to the end of the file, and leaves the offset there.
+	assert_errno("lseek", lseek(fd, 0, SEEK_SET) == 0);
+	assert_errno("write", write(fd, "de", 2) == 2);
+	assert_errno("lseek", lseek(fd, 0, SEEK_CUR) == 5);
+
+	// The end of the file is found again after a truncation.
+	assert_errno("ftruncate", ftruncate(fd, 1) != -1);
+	assert_errno("write", write(fd, "f", 1) == 1);
+	assert_errno("lseek", lseek(fd, 0, SEEK_CUR) == 2);
+
+	// Without the flag, writes go to the offset again.
+	assert_errno("fcntl", fcntl(fd, F_SETFL, 0) != -1);
+	assert_errno("lseek", lseek(fd, 0, SEEK_SET) == 0);
+	assert_errno("write", write(fd, "g", 1) == 1);
+
+	close(fd);
+	assert(read_file(path) == "gf");
+	assert_errno("unlink", unlink(path) != -1);
+
+	// Pipes ignore the flag.
+	int fds[2];
+	assert_errno("pipe", pipe(fds) != -1);
+	assert_errno("fcntl", fcntl(fds[1], F_SETFL, O_APPEND) != -1);
+	assert_errno("write", write(fds[1], "hi", 2) == 2);
+
+	char buffer[2];
+	assert_errno("read", read(fds[0], buffer, 2) == 2);
+	assert(!memcmp(buffer, "hi", 2));
+
+	close(fds[0]);
+	close(fds[1]);
+}))
+
+DEFINE_TEST(append_concurrent, ([] {
+	constexpr int writers = 2;
+	constexpr int lines = 1000;
+	// `/tmp` is a tmpfs, the root filesystem is ext2.
+	const char *path = "/append-concurrent";
+
+	int fd = open(path, O_CREAT | O_TRUNC | O_WRONLY, 0644);
+	assert_errno("open", fd != -1);
+	close(fd);
+
+	pid_t children[writers];
+
+	for (int id = 0; id < writers; id++) {
+		children[id] = fork();
+		assert_errno("fork", children[id] != -1);
+
+		if (!children[id]) {
+			// Each writer has an open file description of its own.
+			int fd = open(path, O_WRONLY | O_APPEND);
+			if (fd == -1)
+				_exit(1);
+
+			for (int i = 0; i < lines; i++) {
+				char line[32];
+				int len = snprintf(line, sizeof(line), "writer %d line %04d\n", id, i);
+
+				if (write(fd, line, len) != len)
+					_exit(1);
+			}
+
+			_exit(0);
+		}
+	}
+
+	for (int id = 0; id < writers; id++) {
+		int status;
+		assert_errno("waitpid", waitpid(children[id], &status, 0) == children[id]);
+		assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	}
+
+	// Every line is intact, and the lines of each writer are all there, in order.
+	std::istringstream contents(read_file(path));
+	std::string line;
+	int next[writers] = {};
+	int total = 0;
+
+	while (std::getline(contents, line)) {
+		int id, i;
+		char rest;
+		assertf(sscanf(line.c_str(), "writer %d line %d%c", &id, &i, &rest) == 2,
+				"corrupted line %d: %s", total, line.c_str());
+		assert(id >= 0 && id < writers);
+		assertf(i == next[id], "writer %d: expected line %d, found %d", id, next[id], i);
+
+		next[id]++;
+		total++;
+	}
+
+	assert(total == writers * lines);
+	assert_errno("unlink", unlink(path) != -1);
+}))
+
+DEFINE_TEST(tmpfs_rename, ([] {
+	assert_errno("mkdir", mkdir("/tmp/tmpfs-rename", 0755) != -1);
+	assert_errno("mkdir", mkdir("/tmp/tmpfs-rename/a", 0755) != -1);
+	assert_errno("mkdir", mkdir("/tmp/tmpfs-rename/b", 0755) != -1);
+
+	int fd = open("/tmp/tmpfs-rename/a/file", O_CREAT | O_EXCL | O_RDWR, 0644);
+	assert_errno("open", fd != -1);
+	assert_errno("write", write(fd, "hello", 5) == 5);
+
+	assert_errno("rename", rename("/tmp/tmpfs-rename/a/file", "/tmp/tmpfs-rename/b/moved") != -1);
+
+	struct stat st;
+	assert(stat("/tmp/tmpfs-rename/a/file", &st) == -1 && errno == ENOENT);
+	assert_errno("stat", stat("/tmp/tmpfs-rename/b/moved", &st) != -1);
+	assert(st.st_size == 5);
+
+	// The open file is the one that was moved.
+	assert_errno("pwrite", pwrite(fd, "j", 1, 0) == 1);
+	close(fd);
+	assert(read_file("/tmp/tmpfs-rename/b/moved") == "jello");
+
+	// A directory moves along with its entries, but not beneath itself.
+	assert_errno("rename", rename("/tmp/tmpfs-rename/b", "/tmp/tmpfs-rename/a/b") != -1);
+	assert(read_file("/tmp/tmpfs-rename/a/b/moved") == "jello");
+	assert(rename("/tmp/tmpfs-rename/a", "/tmp/tmpfs-rename/a/b/a") == -1 && errno == EINVAL);
+
+	assert(rmdir("/tmp/tmpfs-rename/a") == -1 && errno == ENOTEMPTY);
+
+	assert_errno("unlink", unlink("/tmp/tmpfs-rename/a/b/moved") != -1);
+	assert_errno("rmdir", rmdir("/tmp/tmpfs-rename/a/b") != -1);
+	assert_errno("rmdir", rmdir("/tmp/tmpfs-rename/a") != -1);
+	assert_errno("rmdir", rmdir("/tmp/tmpfs-rename") != -1);
+}))
+
+DEFINE_TEST(tmpfs_mmap_shared, ([] {
+	const char *path = "/tmp/tmpfs-mmap";
+	const size_t size = 2 * 4096;
+
+	int fd = open(path, O_CREAT | O_EXCL | O_RDWR, 0644);
+	assert_errno("open", fd != -1);
+	assert_errno("ftruncate", ftruncate(fd, size) != -1);
+
+	// Both mappings and the file share the same pages.
+	char *first = (char *)mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+	assert_errno("mmap", first != MAP_FAILED);
+	char *second = (char *)mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+	assert_errno("mmap", second != MAP_FAILED);
+
+	strcpy(first + 4096, "shared");
+	assert(strcmp(second + 4096, "shared") == 0);
+
+	char buffer[7];
+	assert_errno("pread", pread(fd, buffer, sizeof(buffer), 4096) == sizeof(buffer));
+	assert(strcmp(buffer, "shared") == 0);
+
+	assert_errno("pwrite", pwrite(fd, "file", 4, 0) == 4);
+	assert(memcmp(first, "file", 4) == 0);
+
+	munmap(second, size);
+	munmap(first, size);
+	close(fd);
+	assert_errno("unlink", unlink(path) != -1);
+}))
+
+#if defined(__aero__)
+#define SYS_INFO 37
+
+// Filled in by the `info` system call, which mlibc does not wrap as `sysinfo`.
+struct aero_sysinfo {
+	long uptime;
+	unsigned long loads[3];
+	unsigned long totalram;
+	unsigned long freeram;
+	unsigned long sharedram;
+	unsigned long bufferram;
+	unsigned long totalswap;
+	unsigned long freeswap;
+	unsigned short procs;
+	unsigned short pad;
+	unsigned long totalhigh;
+	unsigned long freehigh;
+	unsigned int mem_unit;
+};
+
+static aero_sysinfo sysinfo_raw() {
+	aero_sysinfo info = {};
+	long ret;
+
+	asm volatile(
+		"syscall"
+		: "=a"(ret)
+		: "a"(SYS_INFO), "D"(&info)
+		: "rcx", "r11", "memory"
+	);
+
+	assert(ret == 0);
+	return info;
+}
+
+DEFINE_TEST(tmpfs_frees_unlinked_files, ([] {
+	constexpr size_t size = 16 << 20;
+	const char *path = "/tmp/tmpfs-free";
+
+	// Both buffers are touched before measuring, so that their pages are not counted.
+	std::vector<char> buffer(size, 'a');
+	std::vector<char> read_back(size);
+
+	unsigned long before = sysinfo_raw().freeram;
+
+	for (int i = 0; i < 4; i++) {
+		int fd = open(path, O_CREAT | O_EXCL | O_RDWR, 0644);
+		assert_errno("open", fd != -1);
+		assert_errno("write", write(fd, buffer.data(), size) == (ssize_t)size);
+
+		// The contents are kept in memory...
+		unsigned long used = sysinfo_raw().freeram;
+		assertf(used + size / 2 < before, "%lu bytes free after writing, %lu before", used,
+				before);
+
+		assert_errno("pread", pread(fd, read_back.data(), size, 0) == (ssize_t)size);
+		assert(read_back == buffer);
+
+		close(fd);
+		assert_errno("unlink", unlink(path) != -1);
+
+		// ...and freed as soon as the file is gone. Allow for what the kernel allocated meanwhile.
+		unsigned long freed = sysinfo_raw().freeram;
+		assertf(freed + (1 << 20) >= before, "%lu bytes free after unlinking, %lu before", freed,
+				before);
+	}
+}))
+
+DEFINE_TEST(tmpfs_enospc, ([] {
+	const char *path = "/tmp/tmpfs-enospc";
+
+	int fd = open(path, O_CREAT | O_EXCL | O_RDWR, 0644);
+	assert_errno("open", fd != -1);
+
+	// `/tmp` can hold up to half of the memory, and a file cannot be larger than that, even if it
+	// is sparse.
+	aero_sysinfo info = sysinfo_raw();
+	off_t limit = (off_t)info.totalram * info.mem_unit / 2;
+
+	assert(pwrite(fd, "a", 1, limit) == -1 && errno == ENOSPC);
+	assert(ftruncate(fd, limit + 1) == -1 && errno == ENOSPC);
+
+	struct stat st;
+	assert_errno("fstat", fstat(fd, &st) != -1);
+	assert(st.st_size == 0);
+
+	// Within the limit, only the pages written to are allocated.
+	assert_errno("pwrite", pwrite(fd, "a", 1, limit / 2) == 1);
+	assert_errno("fstat", fstat(fd, &st) != -1);
+	assert(st.st_size == limit / 2 + 1);
+	assert(st.st_blocks <= 8);
+
+	close(fd);
+	assert_errno("unlink", unlink(path) != -1);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_FALLOCATE 130
+
+#define AERO_FALLOC_FL_KEEP_SIZE 0x01
+#define AERO_FALLOC_FL_PUNCH_HOLE 0x02
+
+static bool is_zeroed(int fd, off_t offset, size_t len) {
+	std::vector<char> buffer(len, 1);
+	assert_errno("pread", pread(fd, buffer.data(), len, offset) == (ssize_t)len);
+
+	for (char c : buffer) {
+		if (c)
+			return false;
+	}
+
+	return true;
+}
+
+DEFINE_TEST(fallocate, ([] {
+	// `/tmp` is a tmpfs, the root filesystem is ext2.
+	const char *path = "/fallocate-test";
+	constexpr long size = 64 * 1024;
+
+	unlink(path);
+	int fd = open(path, O_CREAT | O_EXCL | O_RDWR, 0644);
+	assert_errno("open", fd != -1);
+
+	struct stat st;
+
+	// The allocated range reads as zeroes and extends the file.
+	assert(aero_syscall(SYS_FALLOCATE, fd, 0, 0, size) == 0);
+	assert_errno("fstat", fstat(fd, &st) != -1);
+	assert(st.st_size == size);
+	assert(is_zeroed(fd, 0, size));
+
+	// Writing inside of the file does not shrink it.
+	assert_errno("pwrite", pwrite(fd, "hello", 5, 0) == 5);
+	assert_errno("fstat", fstat(fd, &st) != -1);
+	assert(st.st_size == size);
+
+	// With `FALLOC_FL_KEEP_SIZE`, the blocks after the end of the file are allocated without
+	// changing its size.
+	assert(aero_syscall(SYS_FALLOCATE, fd, AERO_FALLOC_FL_KEEP_SIZE, size, size) == 0);
+	assert_errno("fstat", fstat(fd, &st) != -1);
+	assert(st.st_size == size);
+
+	assert_errno("pwrite", pwrite(fd, "a", 1, size + size / 2) == 1);
+	assert_errno("fstat", fstat(fd, &st) != -1);
+	assert(st.st_size == size + size / 2 + 1);
+	assert(is_zeroed(fd, size, size / 2));
+
+	// Punching a hole zeroes the range but keeps the size.
+	assert(aero_syscall(SYS_FALLOCATE, fd, AERO_FALLOC_FL_PUNCH_HOLE | AERO_FALLOC_FL_KEEP_SIZE, 0, 4096) == 0);
+	assert(is_zeroed(fd, 0, 4096));
+	assert_errno("fstat", fstat(fd, &st) != -1);
+	assert(st.st_size == size + size / 2 + 1);
+
+	assert(aero_syscall(SYS_FALLOCATE, fd, AERO_FALLOC_FL_PUNCH_HOLE, 0, 4096) == -EOPNOTSUPP);
+	assert(aero_syscall(SYS_FALLOCATE, fd, 0x100, 0, 4096) == -EOPNOTSUPP);
+	assert(aero_syscall(SYS_FALLOCATE, fd, 0, 0, 0) == -EINVAL);
+	assert(aero_syscall(SYS_FALLOCATE, fd, 0, -1, 4096) == -EINVAL);
+
+	// Nothing is allocated if there is not enough space for all of the range.
+	assert(aero_syscall(SYS_FALLOCATE, fd, AERO_FALLOC_FL_KEEP_SIZE, 0, 1l << 40) == -ENOSPC);
+	assert_errno("fstat", fstat(fd, &st) != -1);
+	assert(st.st_size == size + size / 2 + 1);
+
+	close(fd);
+
+	fd = open(path, O_RDONLY);
+	assert_errno("open", fd != -1);
+	assert(aero_syscall(SYS_FALLOCATE, fd, 0, 0, 4096) == -EBADF);
+	close(fd);
+
+	assert_errno("unlink", unlink(path) != -1);
+}))
+#endif
+
+#if defined(__aero__)
+DEFINE_TEST(read_only_mount, ([] {
+	const char *dir = "/tmp/ro-test";
+	const char *file = "/tmp/ro-test/file";
+	const char *subdir = "/tmp/ro-test/dir";
+
+	assert_errno("mkdir", mkdir(dir, 0755) != -1);
+	assert_errno("mkdir", mkdir(subdir, 0755) != -1);
+
+	int fd = open(file, O_CREAT | O_EXCL | O_WRONLY, 0644);
+	assert_errno("open", fd != -1);
+	assert_errno("write", write(fd, "hello", 5) == 5);
+
+	// Only the root of a mount can be remounted, and new mounts are not supported.
+	assert(mount(nullptr, dir, nullptr, MS_REMOUNT | MS_RDONLY, nullptr) == -1 && errno == EINVAL);
+	assert(mount(nullptr, "/tmp", nullptr, MS_RDONLY, nullptr) == -1 && errno == EOPNOTSUPP);
+
+	assert_errno("mount", mount(nullptr, "/tmp", nullptr, MS_REMOUNT | MS_RDONLY, nullptr) != -1);
+
+	// The files can still be read.
+	char buffer[5];
+	int rfd = open(file, O_RDONLY);
+	assert_errno("open", rfd != -1);
+	assert_errno("read", read(rfd, buffer, 5) == 5);
+	assert(!memcmp(buffer, "hello", 5));
+	close(rfd);
+
+	// Opening for writing fails up front.
+	assert(open(file, O_WRONLY) == -1 && errno == EROFS);
+	assert(open(file, O_RDWR) == -1 && errno == EROFS);
+	assert(open(file, O_RDONLY | O_TRUNC) == -1 && errno == EROFS);
+	assert(open("/tmp/ro-test/new", O_CREAT | O_WRONLY, 0644) == -1 && errno == EROFS);
+	assert(access(file, W_OK) == -1 && errno == EROFS);
+
+	// Files that were opened for writing before the remount cannot be written to either.
+	assert(write(fd, "a", 1) == -1 && errno == EROFS);
+	assert(ftruncate(fd, 0) == -1 && errno == EROFS);
+
+	// Nor can the directories be changed.
+	assert(mkdir("/tmp/ro-test/new", 0755) == -1 && errno == EROFS);
+	assert(rmdir(subdir) == -1 && errno == EROFS);
+	assert(unlink(file) == -1 && errno == EROFS);
+	assert(rename(file, "/tmp/ro-test/renamed") == -1 && errno == EROFS);
+	assert(link(file, "/tmp/ro-test/link") == -1 && errno == EROFS);
+	assert(symlink(file, "/tmp/ro-test/symlink") == -1 && errno == EROFS);
+
+	// Device nodes are not part of the filesystem data.
+	int null = open("/dev/null", O_WRONLY);
+	assert_errno("open", null != -1);
+	assert_errno("write", write(null, "a", 1) == 1);
+	close(null);
+
+	// Everything works again once the filesystem is writable.
+	assert_errno("mount", mount(nullptr, "/tmp", nullptr, MS_REMOUNT, nullptr) != -1);
+
+	assert_errno("write", write(fd, " world", 6) == 6);
+	assert_errno("ftruncate", ftruncate(fd, 5) != -1);
+	close(fd);
+
+	fd = open(file, O_RDWR | O_TRUNC);
+	assert_errno("open", fd != -1);
+	close(fd);
+
+	assert_errno("rename", rename(file, "/tmp/ro-test/renamed") != -1);
+	assert_errno("unlink", unlink("/tmp/ro-test/renamed") != -1);
+	assert_errno("rmdir", rmdir(subdir) != -1);
+	assert_errno("rmdir", rmdir(dir) != -1);
+}))
+
+DEFINE_TEST(read_only_mount_libc, ([] {
+	const char *dir = "/tmp/ro-libc-test";
+	const char *subdir = "/tmp/ro-libc-test/dir";
+
+	assert_errno("mkdir", mkdir(dir, 0755) != -1);
+
+	// The C library passes the target and the flags on to the same system call.
+	assert_errno("mount", mount(nullptr, "/tmp", nullptr, MS_REMOUNT | MS_RDONLY, nullptr) == 0);
+	assert(mkdir(subdir, 0755) == -1 && errno == EROFS);
+
+	assert(mount(nullptr, dir, nullptr, MS_REMOUNT, nullptr) == -1 && errno == EINVAL);
+	assert(mount("tmpfs", "/tmp", "tmpfs", MS_RDONLY, nullptr) == -1 && errno == EOPNOTSUPP);
+
+	assert_errno("mount", mount(nullptr, "/tmp", nullptr, MS_REMOUNT, nullptr) == 0);
+	assert_errno("mkdir", mkdir(subdir, 0755) != -1);
+	assert_errno("rmdir", rmdir(subdir) != -1);
+	assert_errno("rmdir", rmdir(dir) != -1);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_REBOOT 7
+
+// From `<linux/reboot.h>`.
+#define AERO_REBOOT_MAGIC1 0xfee1dead
+#define AERO_REBOOT_MAGIC2 0x28121969
+
+DEFINE_TEST(reboot_cad, ([] {
+	// `RB_ENABLE_CAD` does not fit in an `int`, so the C library passes it sign-extended.
+	assert_errno("reboot", reboot(RB_DISABLE_CAD) != -1);
+	assert_errno("reboot", reboot(RB_ENABLE_CAD) != -1);
+
+	// The system is not rebooted without the magic numbers or with an unknown command.
+	assert(aero_syscall(SYS_REBOOT, 0, AERO_REBOOT_MAGIC2, RB_ENABLE_CAD) == -EINVAL);
+	assert(aero_syscall(SYS_REBOOT, AERO_REBOOT_MAGIC1, 0, RB_ENABLE_CAD) == -EINVAL);
+	assert(aero_syscall(SYS_REBOOT, AERO_REBOOT_MAGIC1, AERO_REBOOT_MAGIC2, 0x1234) == -EINVAL);
+}))
+#endif
+
+#if defined(__aero__)
+// From `<linux/fs.h>` and `<linux/hdreg.h>`.
+#define BLKROSET _IO(0x12, 93)
+#define BLKROGET _IO(0x12, 94)
+#define BLKSSZGET _IO(0x12, 104)
+#define BLKGETSIZE64 _IOR(0x12, 114, size_t)
+#define HDIO_GETGEO 0x0301
+
+struct aero_hd_geometry {
+	unsigned char heads;
+	unsigned char sectors;
+	unsigned short cylinders;
+	unsigned long start;
+};
+
+DEFINE_TEST(block_ioctls, ([] {
+	int disk = open("/dev/nvme0n1", O_RDWR);
+	if (disk == -1 && errno == ENOENT) {
+		fprintf(stderr, "block_ioctls: no NVMe disk, skipping\n");
+		return;
+	}
+	assert_errno("open", disk != -1);
+
+	uint64_t disk_size;
+	assert_errno("ioctl", ioctl(disk, BLKGETSIZE64, &disk_size) != -1);
+	assert(disk_size > 0 && disk_size % 512 == 0);
+
+	int sector_size;
+	assert_errno("ioctl", ioctl(disk, BLKSSZGET, &sector_size) != -1);
+	assert(sector_size == 512);
+
+	aero_hd_geometry geo;
+	assert_errno("ioctl", ioctl(disk, HDIO_GETGEO, &geo) != -1);
+	assert(geo.heads == 255 && geo.sectors == 63 && geo.start == 0);
+	assert(geo.cylinders == disk_size / 512 / (255 * 63));
+
+	// The partition holding the root filesystem starts after the partition table.
+	int part = open("/dev/nvme0n1p0", O_RDONLY);
+	assert_errno("open", part != -1);
+
+	uint64_t part_size;
+	assert_errno("ioctl", ioctl(part, BLKGETSIZE64, &part_size) != -1);
+	assert(part_size > 0 && part_size < disk_size);
+
+	assert_errno("ioctl", ioctl(part, HDIO_GETGEO, &geo) != -1);
+	assert(geo.start > 0 && geo.start * 512 + part_size <= disk_size);
+	close(part);
+
+	assert(ioctl(disk, 0x1234, nullptr) == -1 && errno == ENOTTY);
+
+	// Writing back what is already on the disk leaves it unchanged if the write goes through.
+	char sector[512];
+	assert_errno("pread", pread(disk, sector, sizeof(sector), 0) == sizeof(sector));
+
+	int ro = 1;
+	assert_errno("ioctl", ioctl(disk, BLKROSET, &ro) != -1);
+
+	ro = 0;
+	assert_errno("ioctl", ioctl(disk, BLKROGET, &ro) != -1);
+	assert(ro == 1);
+	assert(pwrite(disk, sector, sizeof(sector), 0) == -1 && errno == EPERM);
+
+	ro = 0;
+	assert_errno("ioctl", ioctl(disk, BLKROSET, &ro) != -1);
+	assert_errno("ioctl", ioctl(disk, BLKROGET, &ro) != -1);
+	assert(ro == 0);
+	assert_errno("pwrite", pwrite(disk, sector, sizeof(sector), 0) == sizeof(sector));
+
+	close(disk);
+}))
+#endif
+
+#if defined(__aero__)
+// Returns the `VmRSS` field of `/proc/self/status`, in kB.
+static unsigned long proc_self_rss() {
+	std::string status = read_file("/proc/self/status");
+	size_t field = status.find("VmRSS:");
+	assert(field != std::string::npos);
+
+	unsigned long rss;
+	assert(sscanf(status.c_str() + field, "VmRSS: %lu kB", &rss) == 1);
+	return rss;
+}
+
+DEFINE_TEST(memory_limit, ([] {
+	constexpr size_t touched = 32 << 20;
+
+	auto spawn = [](const char *limit) {
+		pid_t pid = fork();
+		assert_errno("fork", pid >= 0);
+
+		if (!pid) {
+			if (limit) {
+				int fd = open("/proc/self/memory.max", O_WRONLY);
+				if (fd == -1 || write(fd, limit, strlen(limit)) != (ssize_t)strlen(limit))
+					_exit(1);
+				close(fd);
+			}
+
+			unsigned long rss = proc_self_rss();
+
+			char *mem = (char *)mmap(nullptr, touched, PROT_READ | PROT_WRITE,
+					MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+			if (mem == MAP_FAILED)
+				_exit(2);
+
+			for (size_t offset = 0; offset < touched; offset += 4096)
+				mem[offset] = 1;
+
+			// Every touched page is charged to the process.
+			if (proc_self_rss() < rss + touched / 1024)
+				_exit(3);
+
+			_exit(0);
+		}
+
+		return pid;
+	};
+
+	pid_t limited = spawn("16777216");
+	pid_t unlimited = spawn(nullptr);
+
+	int status;
+	assert_errno("waitpid", waitpid(limited, &status, 0) == limited);
+	assertf(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL,
+		"limited child was not killed (status %#x)", status);
+
+	assert_errno("waitpid", waitpid(unlimited, &status, 0) == unlimited);
+	assertf(WIFEXITED(status) && WEXITSTATUS(status) == 0,
+		"unlimited child failed (status %#x)", status);
+
+	// The limit can be read back and removed again.
+	int fd = open("/proc/self/memory.max", O_RDWR);
+	assert_errno("open", fd != -1);
+	assert_errno("write", write(fd, "max", 3) == 3);
+	close(fd);
+
+	assert(read_file("/proc/self/memory.max") == "max\n");
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_SETHOSTNAME 36
+
+DEFINE_TEST(sethostname, ([] {
+	char old[65];
+	assert_errno("gethostname", !gethostname(old, sizeof(old)));
+
+	char too_long[65];
+	memset(too_long, 'a', sizeof(too_long));
+
+	for (auto [name, len] : {std::pair<const char *, size_t>{"bad name", 8},
+			{"bad\0name", 8}, {too_long, sizeof(too_long)}}) {
+		assert(aero_syscall_errno(SYS_SETHOSTNAME, name, len) == -1);
+		assert(errno == EINVAL);
+	}
+
+	assert_errno("sethostname", aero_syscall_errno(SYS_SETHOSTNAME, "utest-host", 10) != -1);
+
+	// The new name is visible right away.
+	char name[65];
+	assert_errno("gethostname", !gethostname(name, sizeof(name)));
+	assert(!strcmp(name, "utest-host"));
+
+	struct utsname uts;
+	assert_errno("uname", !uname(&uts));
+	assert(!strcmp(uts.nodename, "utest-host"));
+
+	assert_errno("sethostname", aero_syscall_errno(SYS_SETHOSTNAME, old, strlen(old)) != -1);
+}))
+
+DEFINE_TEST(uname_version, ([] {
+	struct utsname uts;
+	assert_errno("uname", !uname(&uts));
+
+	assert(!strcmp(uts.sysname, "Aero"));
+	assert(!strcmp(uts.release, "6.0.0-aero"));
+	assert(!strcmp(uts.machine, "x86_64"));
+
+	// `#<commit> <build date>`
+	assert(uts.version[0] == '#');
+	assert(strchr(uts.version, ' '));
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_SCHED_GETAFFINITY 99
+
+DEFINE_TEST(cpuinfo_affinity, ([] {
+	cpu_set_t set;
+	assert_errno("sched_getaffinity", aero_syscall_errno(SYS_SCHED_GETAFFINITY, 0, &set) == sizeof(set));
+	assert(CPU_ISSET(0, &set));
+
+	cpu_set_t other;
+	assert_errno("sched_getaffinity", aero_syscall_errno(SYS_SCHED_GETAFFINITY, getppid(), &other) != -1);
+	assert(!memcmp(&set, &other, sizeof(set)));
+
+	assert(aero_syscall_errno(SYS_SCHED_GETAFFINITY, 0x7fffffff, &other) == -1 && errno == ESRCH);
+
+	// `/proc/cpuinfo` has a block for each of the online CPUs.
+	std::string cpuinfo = read_file("/proc/cpuinfo");
+	std::istringstream lines(cpuinfo);
+	std::string line;
+	int processors = 0;
+
+	while (std::getline(lines, line)) {
+		if (line.rfind("processor\t: ", 0) == 0) {
+			assert(CPU_ISSET(std::stoi(line.substr(12)), &set));
+			processors++;
+		} else if (line.rfind("flags\t\t: ", 0) == 0) {
+			std::istringstream flags(line.substr(9));
+			std::string flag;
+			bool has_fpu = false;
+
+			while (flags >> flag)
+				has_fpu |= flag == "fpu";
+
+			assert(has_fpu);
+		}
+	}
+
+	assert(processors == CPU_COUNT(&set));
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_ALARM 103
+
+DEFINE_TEST(orphan_reparent, ([] {
+	int fds[2];
+	assert_errno("pipe", pipe(fds) != -1);
+
+	pid_t child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child) {
+		pid_t grandchild = fork();
+		if (grandchild == -1)
+			_exit(1);
+
+		if (!grandchild) {
+			// Wait until the child has exited and init adopted us.
+			while (getppid() != 1)
+				sched_yield();
+
+			pid_t ppid = getppid();
+			write(fds[1], &ppid, sizeof(ppid));
+			_exit(0);
+		}
+
+		write(fds[1], &grandchild, sizeof(grandchild));
+		_exit(0);
+	}
+
+	close(fds[1]);
+
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	pid_t grandchild, ppid;
+	assert(read(fds[0], &grandchild, sizeof(grandchild)) == sizeof(grandchild));
+	assert(read(fds[0], &ppid, sizeof(ppid)) == sizeof(ppid));
+	assert(ppid == 1);
+	close(fds[0]);
+
+	// When the tests run as init, the orphan is handed over to us so we have to reap it.
+	// Otherwise, init does.
+	if (getpid() == 1) {
+		assert_errno("waitpid", waitpid(grandchild, &status, 0) == grandchild);
+		assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	}
+}))
+
+static volatile sig_atomic_t alarm_fired;
+
+DEFINE_TEST(alarm_interrupts_read, ([] {
+	struct sigaction sa = {}, old_sa;
+	sa.sa_handler = [](int) { alarm_fired = 1; };
+	sigemptyset(&sa.sa_mask);
+	assert_errno("sigaction", sigaction(SIGALRM, &sa, &old_sa) != -1);
+
+	int fds[2];
+	assert_errno("pipe", pipe(fds) != -1);
+
+	alarm_fired = 0;
+
+	// Setting an alarm returns the seconds left until the previous one.
+	assert(aero_syscall(SYS_ALARM, 10) == 0);
+	assert(aero_syscall(SYS_ALARM, 1) == 10);
+
+	// Nothing is ever written to the pipe, so only the alarm ends the read.
+	char c;
+	assert(read(fds[0], &c, 1) == -1 && errno == EINTR);
+	assert(alarm_fired);
+
+	// The alarm only goes off once.
+	assert(aero_syscall(SYS_ALARM, 0) == 0);
+
+	close(fds[0]);
+	close(fds[1]);
+	assert_errno("sigaction", sigaction(SIGALRM, &old_sa, nullptr) != -1);
+}))
+
+DEFINE_TEST(alarm_restarts_read, ([] {
+	struct sigaction sa = {}, old_sa;
+	sa.sa_handler = [](int) { alarm_fired = 1; };
+	sa.sa_flags = SA_RESTART;
+	sigemptyset(&sa.sa_mask);
+	assert_errno("sigaction", sigaction(SIGALRM, &sa, &old_sa) != -1);
+
+	int fds[2];
+	assert_errno("pipe", pipe(fds) != -1);
+
+	pid_t child = fork();
+	assert_errno("fork", child >= 0);
+
+	if (!child) {
+		// Only written once the alarm went off in the parent.
+		sleep(2);
+		if (write(fds[1], "x", 1) != 1)
+			_exit(1);
+		_exit(0);
+	}
+
+	alarm_fired = 0;
+	assert(aero_syscall(SYS_ALARM, 1) == 0);
+
+	// With `SA_RESTART`, the read carries on after the handler instead of failing with `EINTR`.
+	char c;
+	assert_errno("read", read(fds[0], &c, 1) == 1);
+	assert(c == 'x');
+	assert(alarm_fired);
+
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	close(fds[0]);
+	close(fds[1]);
+	assert_errno("sigaction", sigaction(SIGALRM, &old_sa, nullptr) != -1);
+}))
+
+DEFINE_TEST(alarm_interrupts_wait, ([] {
+	struct sigaction sa = {}, old_sa;
+	sa.sa_handler = [](int) { alarm_fired = 1; };
+	sigemptyset(&sa.sa_mask);
+	assert_errno("sigaction", sigaction(SIGALRM, &sa, &old_sa) != -1);
+
+	pid_t child = fork();
+	assert_errno("fork", child >= 0);
+
+	if (!child) {
+		for (;;)
+			pause();
+	}
+
+	alarm_fired = 0;
+	assert(aero_syscall(SYS_ALARM, 1) == 0);
+
+	// Without `SA_RESTART`, the wait fails with `EINTR` once the handler ran.
+	int status;
+	assert(waitpid(child, &status, 0) == -1 && errno == EINTR);
+	assert(alarm_fired);
+
+	assert_errno("kill", kill(child, SIGKILL) == 0);
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+
+	assert_errno("sigaction", sigaction(SIGALRM, &old_sa, nullptr) != -1);
+}))
+#endif
+
+static volatile sig_atomic_t sigchld_reaped;
+static volatile sig_atomic_t sigchld_unexpected;
+
+DEFINE_TEST(sigchld_handler_reaps, ([] {
+	struct sigaction sa = {}, old_sa;
+	sa.sa_sigaction = [](int signal, siginfo_t *info, void *) {
+		int saved_errno = errno;
+
+		if (signal != SIGCHLD || info->si_signo != SIGCHLD || info->si_code != CLD_EXITED
+				|| info->si_pid <= 0 || info->si_status != 7)
+			sigchld_unexpected = 1;
+
+		// Signals are not queued, so a single `SIGCHLD` may stand for several children.
+		int status;
+		while (waitpid(-1, &status, WNOHANG) > 0) {
+			if (WIFEXITED(status) && WEXITSTATUS(status) == 7)
+				sigchld_reaped++;
+			else
+				sigchld_unexpected = 1;
+		}
+
+		errno = saved_errno;
+	};
+	sa.sa_flags = SA_SIGINFO | SA_RESTART;
+	sigemptyset(&sa.sa_mask);
+	assert_errno("sigaction", sigaction(SIGCHLD, &sa, &old_sa) != -1);
+
+	sigchld_reaped = 0;
+	sigchld_unexpected = 0;
+
+	for (int i = 0; i < 3; i++) {
+		pid_t pid = fork();
+		assert_errno("fork", pid >= 0);
+
+		if (!pid)
+			_exit(7);
+	}
+
+	// Only the handler waits for the children.
+	for (int i = 0; i < 500 && sigchld_reaped < 3; i++)
+		usleep(10000);
+
+	assert(sigchld_reaped == 3);
+	assert(!sigchld_unexpected);
+	assert(waitpid(-1, nullptr, WNOHANG) == -1 && errno == ECHILD);
+
+	assert_errno("sigaction", sigaction(SIGCHLD, &old_sa, nullptr) != -1);
+}))
+
+// Returns the zombie children of the calling process, as listed in `/proc`.
+static std::vector<pid_t> proc_zombie_children() {
+	std::vector<pid_t> zombies;
+
+	DIR *dir = opendir("/proc");
+	assert_errno("opendir", dir);
+
+	while (struct dirent *entry = readdir(dir)) {
+		char *end;
+		long pid = strtol(entry->d_name, &end, 10);
+
+		if (*end || pid <= 0)
+			continue;
+
+		// The process may be gone by now.
+		char path[64];
+		snprintf(path, sizeof(path), "/proc/%ld/stat", pid);
+
+		std::ifstream file(path);
+		std::string stat((std::istreambuf_iterator<char>(file)), std::istreambuf_iterator<char>());
+		size_t comm_end = stat.rfind(')');
+
+		char state;
+		int ppid;
+
+		if (comm_end != std::string::npos
+				&& sscanf(stat.c_str() + comm_end + 2, "%c %d", &state, &ppid) == 2
+				&& state == 'Z' && ppid == getpid())
+			zombies.push_back(pid);
+	}
+
+	closedir(dir);
+	return zombies;
+}
+
+DEFINE_TEST(sigchld_ignored_reaps, ([] {
+	// By default, an exited child stays a zombie until it is waited for.
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid)
+		_exit(0);
+
+	for (int i = 0; i < 500 && proc_zombie_children().empty(); i++)
+		usleep(10000);
+
+	assert(proc_zombie_children() == std::vector<pid_t>{pid});
+	assert_errno("waitpid", waitpid(pid, nullptr, 0) == pid);
+	assert(proc_zombie_children().empty());
+
+	// Ignoring `SIGCHLD` or setting `SA_NOCLDWAIT` reaps the children as they exit.
+	struct sigaction configs[2] = {};
+	configs[0].sa_handler = SIG_IGN;
+	configs[1].sa_handler = SIG_DFL;
+	configs[1].sa_flags = SA_NOCLDWAIT;
+
+	for (struct sigaction &sa : configs) {
+		struct sigaction old_sa;
+		sigemptyset(&sa.sa_mask);
+		assert_errno("sigaction", sigaction(SIGCHLD, &sa, &old_sa) != -1);
+
+		for (int i = 0; i < 3; i++) {
+			pid_t pid = fork();
+			assert_errno("fork", pid >= 0);
+
+			if (!pid)
+				_exit(0);
+		}
+
+		usleep(200000);
+		assert(proc_zombie_children().empty());
+
+		// Waiting blocks until all of the children have exited, and then fails.
+		assert(waitpid(-1, nullptr, 0) == -1 && errno == ECHILD);
+		assert(proc_zombie_children().empty());
+
+		assert_errno("sigaction", sigaction(SIGCHLD, &old_sa, nullptr) != -1);
+	}
+}))
+
+static volatile sig_atomic_t sigchld_codes[8];
+static volatile sig_atomic_t sigchld_count;
+
+DEFINE_TEST(sigchld_stop, ([] {
+	struct sigaction sa = {}, old_sa;
+	sa.sa_sigaction = [](int, siginfo_t *info, void *) {
+		int count = sigchld_count;
+
+		if (count < 8) {
+			sigchld_codes[count] = info->si_code;
+			sigchld_count = count + 1;
+		}
+	};
+	sigemptyset(&sa.sa_mask);
+
+	// Without `SA_NOCLDSTOP`, stops and continues are reported as well.
+	for (int flags : {0, SA_NOCLDSTOP}) {
+		sa.sa_flags = SA_SIGINFO | SA_RESTART | flags;
+		assert_errno("sigaction", sigaction(SIGCHLD, &sa, &old_sa) != -1);
+		sigchld_count = 0;
+
+		int fds[2];
+		assert_errno("pipe", pipe(fds) == 0);
+
+		pid_t pid = fork();
+		assert_errno("fork", pid >= 0);
+
+		if (!pid) {
+			close(fds[1]);
+
+			// Job control signals stop the process by default.
+			raise(SIGTTOU);
+
+			// Signals are not queued, so the exit is only reported once the continue was.
+			char c;
+			read(fds[0], &c, 1);
+			_exit(3);
+		}
+
+		close(fds[0]);
+
+		int status;
+		assert_errno("waitpid", waitpid(pid, &status, WUNTRACED) == pid);
+		assert(WIFSTOPPED(status) && WSTOPSIG(status) == SIGTTOU);
+
+		assert_errno("kill", kill(pid, SIGCONT) == 0);
+
+		for (int i = 0; i < 20 && sigchld_count < (flags ? 0 : 2); i++)
+			usleep(10000);
+
+		close(fds[1]);
+		assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+		assert(WIFEXITED(status) && WEXITSTATUS(status) == 3);
+
+		for (int i = 0; i < 100 && sigchld_count < (flags ? 1 : 3); i++)
+			usleep(10000);
+
+		if (flags) {
+			assert(sigchld_count == 1);
+			assert(sigchld_codes[0] == CLD_EXITED);
+		} else {
+			assert(sigchld_count == 3);
+			assert(sigchld_codes[0] == CLD_STOPPED);
+			assert(sigchld_codes[1] == CLD_CONTINUED);
+			assert(sigchld_codes[2] == CLD_EXITED);
+		}
+
+		assert_errno("sigaction", sigaction(SIGCHLD, &old_sa, nullptr) != -1);
+	}
+}))
+
+// Returns the raw `waitpid` status of a child running `body`.
+static int child_wait_status(void (*body)(), int flags = 0) {
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		body();
+		_exit(0);
+	}
+
+	int status;
+	assert_errno("waitpid", waitpid(pid, &status, flags) == pid);
+
+	if (WIFSTOPPED(status)) {
+		kill(pid, SIGKILL);
+		assert_errno("waitpid", waitpid(pid, nullptr, 0) == pid);
+	}
+
+	return status;
+}
+
+DEFINE_TEST(wait_status_layout, ([] {
+	// The bit layout decoded by `WaitStatus` in aero_syscall.
+	int status = child_wait_status([] { _exit(3); });
+	assert(status == 0x0300);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 3);
+
+	status = child_wait_status([] { raise(SIGKILL); });
+	assert(status == SIGKILL);
+	assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL && !WCOREDUMP(status));
+
+	status = child_wait_status([] {
+		struct rlimit limit = {0, 0};
+		setrlimit(RLIMIT_CORE, &limit);
+		raise(SIGSEGV);
+	});
+	assert((status & 0x7f) == SIGSEGV);
+	assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV);
+
+	status = child_wait_status([] { raise(SIGSTOP); }, WUNTRACED);
+	assert(status == 0x137f);
+	assert(WIFSTOPPED(status) && WSTOPSIG(status) == SIGSTOP);
+}))
+
+#if defined(__aero__)
+#define SYS_MEMBARRIER 104
+#define MEMBARRIER_CMD_QUERY 0
+#define MEMBARRIER_CMD_GLOBAL (1 << 0)
+#define MEMBARRIER_CMD_PRIVATE_EXPEDITED (1 << 3)
+
+DEFINE_TEST(membarrier, ([] {
+	long supported = aero_syscall(SYS_MEMBARRIER, MEMBARRIER_CMD_QUERY, 0);
+	assert(supported & MEMBARRIER_CMD_GLOBAL);
+	assert(supported & MEMBARRIER_CMD_PRIVATE_EXPEDITED);
+
+	assert(aero_syscall(SYS_MEMBARRIER, MEMBARRIER_CMD_GLOBAL, 0) == 0);
+	assert(aero_syscall(SYS_MEMBARRIER, MEMBARRIER_CMD_PRIVATE_EXPEDITED, 0) == 0);
+
+	// Unknown flags and several commands at once are rejected.
+	assert(aero_syscall(SYS_MEMBARRIER, MEMBARRIER_CMD_GLOBAL, 1) == -EINVAL);
+	assert(aero_syscall(SYS_MEMBARRIER, MEMBARRIER_CMD_GLOBAL | MEMBARRIER_CMD_PRIVATE_EXPEDITED, 0) == -EINVAL);
+}))
+
+// Runs the ext2 tool `path` with `args` and returns its exit status and what it wrote.
+static int run_ext2_tool(const char *path, std::vector<const char *> args, std::string &output) {
+	std::vector<char *> argv{(char *)path};
+
+	for (const char *arg : args)
+		argv.push_back((char *)arg);
+
+	argv.push_back(nullptr);
+
+	int fds[2];
+	assert_errno("pipe", pipe(fds) != -1);
+
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid) {
+		close(fds[0]);
+		dup2(fds[1], STDOUT_FILENO);
+		execv(path, argv.data());
+		_exit(127);
+	}
+
+	close(fds[1]);
+
+	output.clear();
+	char buf[64];
+	ssize_t n;
+
+	while ((n = read(fds[0], buf, sizeof(buf))) > 0)
+		output.append(buf, n);
+
+	assert_errno("read", n == 0);
+	close(fds[0]);
+
+	int status = 0;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFEXITED(status));
+
+	return WEXITSTATUS(status);
+}
+
+DEFINE_TEST(mkfs_fsck_ext2, ([] {
+	const char *image = "/tmp/ext2.img";
+	std::string output;
+
+	for (const char *block_size : {"1024", "4096"}) {
+		unlink(image);
+
+		assert(run_ext2_tool("/usr/bin/mkfs.ext2", {"-b", block_size, image, "4M"}, output) == 0);
+		assert(run_ext2_tool("/usr/bin/fsck.ext2", {image}, output) == 0);
+		assert(output.find("clean") != std::string::npos);
+	}
+
+	// Corrupt the link count of the root directory, the second inode of the first group.
+	int fd = open(image, O_RDWR);
+	assert_errno("open", fd != -1);
+
+	uint32_t inode_table;
+	uint16_t inode_size;
+	assert(pread(fd, &inode_size, sizeof(inode_size), 1024 + 88) == sizeof(inode_size));
+	assert(pread(fd, &inode_table, sizeof(inode_table), 4096 + 8) == sizeof(inode_table));
+
+	uint16_t links = 7;
+	off_t offset = inode_table * 4096 + inode_size + 26;
+	assert(pwrite(fd, &links, sizeof(links), offset) == sizeof(links));
+	close(fd);
+
+	assert(run_ext2_tool("/usr/bin/fsck.ext2", {image}, output) == 4);
+	assert(output.find("link count") != std::string::npos);
+
+	unlink(image);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_MQ_OPEN 105
+#define SYS_MQ_SEND 106
+#define SYS_MQ_RECEIVE 107
+#define SYS_MQ_UNLINK 108
+
+struct aero_mq_attr {
+	long mq_flags;
+	long mq_maxmsg;
+	long mq_msgsize;
+	long mq_curmsgs;
+};
+
+// Receives the next message of `fd` and checks that it is `expected` with `expected_priority`.
+static void mq_expect(int fd, const char *expected, unsigned int expected_priority) {
+	char buffer[16];
+	unsigned int priority;
+
+	long size = aero_syscall(SYS_MQ_RECEIVE, fd, buffer, sizeof(buffer), &priority);
+	assert(size == (long)strlen(expected));
+	assert(!memcmp(buffer, expected, size));
+	assert(priority == expected_priority);
+}
+
+static short mq_poll_events(int fd) {
+	struct pollfd pfd = {fd, POLLIN | POLLOUT, 0};
+	assert_errno("poll", poll(&pfd, 1, 0) != -1);
+	return pfd.revents;
+}
+
+DEFINE_TEST(posix_mqueue, ([] {
+	const char *name = "/utest";
+	aero_mq_attr attr = {0, 2, 16, 0};
+
+	aero_syscall(SYS_MQ_UNLINK, name, strlen(name));
+
+	long fd = aero_syscall(SYS_MQ_OPEN, name, strlen(name), O_RDWR | O_CREAT | O_EXCL, 0600, &attr);
+	assert(fd >= 0);
+
+	assert(aero_syscall(SYS_MQ_OPEN, name, strlen(name), O_RDWR | O_CREAT | O_EXCL, 0600, &attr) == -EEXIST);
+	assert(aero_syscall(SYS_MQ_OPEN, "/utest-missing", strlen("/utest-missing"), O_RDWR, 0600, nullptr) == -ENOENT);
+	assert(aero_syscall(SYS_MQ_OPEN, "utest", strlen("utest"), O_RDWR | O_CREAT, 0600, nullptr) == -EINVAL);
+
+	aero_mq_attr bad = {0, 0, 16, 0};
+	assert(aero_syscall(SYS_MQ_OPEN, "/utest-bad", strlen("/utest-bad"), O_RDWR | O_CREAT, 0600, &bad) == -EINVAL);
+
+	long nonblock = aero_syscall(SYS_MQ_OPEN, name, strlen(name), O_RDWR | O_NONBLOCK, 0600, nullptr);
+	assert(nonblock >= 0);
+
+	assert(mq_poll_events(fd) == POLLOUT);
+
+	// The highest priority is received first, and equal priorities in the order they were sent.
+	assert(aero_syscall(SYS_MQ_SEND, fd, "low", strlen("low"), 1) == 0);
+	assert(aero_syscall(SYS_MQ_SEND, fd, "high", strlen("high"), 5) == 0);
+	assert(mq_poll_events(fd) == POLLIN);
+	assert(aero_syscall(SYS_MQ_SEND, nonblock, "full", strlen("full"), 1) == -EAGAIN);
+
+	mq_expect(fd, "high", 5);
+	mq_expect(fd, "low", 1);
+	assert(mq_poll_events(fd) == POLLOUT);
+
+	assert(aero_syscall(SYS_MQ_SEND, fd, "first", strlen("first"), 2) == 0);
+	assert(aero_syscall(SYS_MQ_SEND, fd, "second", strlen("second"), 2) == 0);
+	mq_expect(fd, "first", 2);
+	mq_expect(fd, "second", 2);
+
+	char buffer[16];
+	assert(aero_syscall(SYS_MQ_RECEIVE, nonblock, buffer, sizeof(buffer), nullptr) == -EAGAIN);
+	assert(aero_syscall(SYS_MQ_RECEIVE, fd, buffer, 8, nullptr) == -EMSGSIZE);
+	assert(aero_syscall(SYS_MQ_SEND, fd, "seventeen bytes!!", strlen("seventeen bytes!!"), 0) == -EMSGSIZE);
+
+	// A blocking receive waits for a message sent by another process.
+	pid_t child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child) {
+		usleep(100000);
+		exit(aero_syscall(SYS_MQ_SEND, fd, "child", strlen("child"), 3) == 0 ? 0 : 1);
+	}
+
+	mq_expect(fd, "child", 3);
+
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	// The queue outlives its name while it is open.
+	assert(aero_syscall(SYS_MQ_UNLINK, name, strlen(name)) == 0);
+	assert(aero_syscall(SYS_MQ_UNLINK, name, strlen(name)) == -ENOENT);
+	assert(aero_syscall(SYS_MQ_OPEN, name, strlen(name), O_RDWR, 0600, nullptr) == -ENOENT);
+
+	assert(aero_syscall(SYS_MQ_SEND, fd, "unlinked", strlen("unlinked"), 0) == 0);
+	mq_expect(nonblock, "unlinked", 0);
+
+	close(nonblock);
+	close(fd);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_SEM_OPEN 109
+#define SYS_SEM_POST 110
+#define SYS_SEM_WAIT 111
+#define SYS_SEM_TRYWAIT 112
+#define SYS_SEM_TIMEDWAIT 113
+#define SYS_SEM_UNLINK 114
+
+DEFINE_TEST(posix_semaphore, ([] {
+	const char *name = "/utest-sem";
+
+	aero_syscall(SYS_SEM_UNLINK, name, strlen(name));
+
+	long fd = aero_syscall(SYS_SEM_OPEN, name, strlen(name), O_CREAT | O_EXCL, 0600, 1);
+	assert(fd >= 0);
+
+	assert(aero_syscall(SYS_SEM_OPEN, name, strlen(name), O_CREAT | O_EXCL, 0600, 1) == -EEXIST);
+	assert(aero_syscall(SYS_SEM_OPEN, "/utest-sem-missing", strlen("/utest-sem-missing"), 0, 0600, 0) == -ENOENT);
+	assert(aero_syscall(SYS_SEM_OPEN, "utest-sem", strlen("utest-sem"), O_CREAT, 0600, 0) == -EINVAL);
+
+	assert(aero_syscall(SYS_SEM_TRYWAIT, fd) == 0);
+	assert(aero_syscall(SYS_SEM_TRYWAIT, fd) == -EAGAIN);
+
+	// A timed wait gives up once the deadline passed.
+	struct timespec deadline;
+	assert_errno("clock_gettime", clock_gettime(CLOCK_REALTIME, &deadline) == 0);
+	deadline.tv_sec += 1;
+
+	assert(aero_syscall(SYS_SEM_TIMEDWAIT, fd, &deadline) == -ETIMEDOUT);
+
+	struct timespec now;
+	assert_errno("clock_gettime", clock_gettime(CLOCK_REALTIME, &now) == 0);
+	assert(now.tv_sec >= deadline.tv_sec);
+
+	struct timespec invalid = {0, 1000000000};
+	assert(aero_syscall(SYS_SEM_TIMEDWAIT, fd, &invalid) == -EINVAL);
+
+	// Every post wakes up one of the waiters.
+	std::vector<pid_t> children;
+
+	for (int i = 0; i < 3; i++) {
+		pid_t child = fork();
+		assert_errno("fork", child != -1);
+
+		if (!child)
+			exit(aero_syscall(SYS_SEM_WAIT, fd) == 0 ? 0 : 1);
+
+		children.push_back(child);
+	}
+
+	usleep(100000);
+
+	for (int i = 0; i < 3; i++)
+		assert(aero_syscall(SYS_SEM_POST, fd) == 0);
+
+	for (pid_t child : children) {
+		int status;
+		assert_errno("waitpid", waitpid(child, &status, 0) == child);
+		assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	}
+
+	assert(aero_syscall(SYS_SEM_TRYWAIT, fd) == -EAGAIN);
+
+	// The semaphore outlives its name while it is open.
+	assert(aero_syscall(SYS_SEM_UNLINK, name, strlen(name)) == 0);
+	assert(aero_syscall(SYS_SEM_UNLINK, name, strlen(name)) == -ENOENT);
+	assert(aero_syscall(SYS_SEM_OPEN, name, strlen(name), 0, 0600, 0) == -ENOENT);
+
+	assert(aero_syscall(SYS_SEM_POST, fd) == 0);
+	assert(aero_syscall(SYS_SEM_WAIT, fd) == 0);
+
+	close(fd);
+}))
+#endif
+
+#if defined(__aero__)
+static unsigned long shm_nattch(int id) {
+	struct shmid_ds ds;
+	assert_errno("shmctl", shmctl(id, IPC_STAT, &ds) != -1);
+	return ds.shm_nattch;
+}
+
+DEFINE_TEST(sysv_shm, ([] {
+	int id = shmget(IPC_PRIVATE, 8192, IPC_CREAT | 0600);
+	assert_errno("shmget", id != -1);
+
+	assert(shmget(IPC_PRIVATE, 0, IPC_CREAT | 0600) == -1 && errno == EINVAL);
+
+	// Segments with a key are found again by it.
+	const key_t key = 0x75747374;
+	int keyed = shmget(key, 4096, IPC_CREAT | IPC_EXCL | 0600);
+	assert_errno("shmget", keyed != -1);
+	assert(keyed != id);
+
+	assert(shmget(key, 4096, IPC_CREAT | IPC_EXCL | 0600) == -1 && errno == EEXIST);
+	assert(shmget(key, 4096, 0) == keyed);
+	assert(shmget(key, 8192, 0) == -1 && errno == EINVAL);
+	assert(shmget(key + 1, 4096, 0) == -1 && errno == ENOENT);
+
+	assert_errno("shmctl", shmctl(keyed, IPC_RMID, nullptr) != -1);
+	assert(shmget(key, 4096, 0) == -1 && errno == ENOENT);
+
+	struct shmid_ds ds;
+	assert_errno("shmctl", shmctl(id, IPC_STAT, &ds) != -1);
+	assert(ds.shm_segsz == 8192);
+	assert((ds.shm_perm.mode & 0777) == 0600);
+	assert(ds.shm_cpid == getpid());
+	assert(ds.shm_nattch == 0);
+
+	char *memory = (char *)shmat(id, nullptr, 0);
+	assert_errno("shmat", memory != (char *)-1);
+	assert(((uintptr_t)memory & 0xfff) == 0);
+	assert(shm_nattch(id) == 1);
+	assert(memory[0] == 0 && memory[8191] == 0);
+
+	// A child attaching the segment again shares the memory with the parent.
+	pid_t child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child) {
+		// The attachment inherited from the parent is counted as well.
+		if (shm_nattch(id) != 2)
+			exit(1);
+
+		char *other = (char *)shmat(id, nullptr, 0);
+		if (other == (char *)-1 || shm_nattch(id) != 3)
+			exit(1);
+
+		strcpy(other + 4096, "hello from the child");
+		exit(shmdt(other) == 0 ? 0 : 1);
+	}
+
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	assert(!strcmp(memory + 4096, "hello from the child"));
+
+	// The attachments of the child are released when its address space is torn down, which
+	// happens asynchronously after it exits.
+	for (int i = 0; i < 1000 && shm_nattch(id) != 1; i++)
+		sched_yield();
+
+	assert(shm_nattch(id) == 1);
+
+	// Read-only attachments see the same memory.
+	strcpy(memory, "hello from the parent");
+
+	char *readonly = (char *)shmat(id, nullptr, SHM_RDONLY);
+	assert_errno("shmat", readonly != (char *)-1);
+	assert(readonly != memory);
+	assert(!strcmp(readonly, "hello from the parent"));
+	assert(shm_nattch(id) == 2);
+
+	assert(shmdt(readonly + 4096) == -1 && errno == EINVAL);
+	assert_errno("shmdt", shmdt(readonly) != -1);
+	assert(shm_nattch(id) == 1);
+
+	// A removed segment can no longer be attached, but stays mapped until it is detached.
+	assert_errno("shmctl", shmctl(id, IPC_RMID, nullptr) != -1);
+	assert(shmat(id, nullptr, 0) == (void *)-1 && errno == EINVAL);
+	assert(shmctl(id, IPC_RMID, nullptr) == -1 && errno == EINVAL);
+
+	memory[1] = 'E';
+	assert(!strcmp(memory, "hEllo from the parent"));
+
+	assert_errno("shmdt", shmdt(memory) != -1);
+	assert(shmdt(memory) == -1 && errno == EINVAL);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_MSGGET 119
+#define SYS_MSGSND 120
+#define SYS_MSGRCV 121
+#define SYS_MSGCTL 122
+
+#define AERO_MSG_NOERROR 010000
+#define AERO_MSG_EXCEPT 020000
+
+struct aero_msqid_ds {
+	int key;
+	unsigned int uid, gid, cuid, cgid, mode;
+	int seq;
+	long pad0[2];
+	long stime, rtime, ctime;
+	unsigned long cbytes, qnum, qbytes;
+	int lspid, lrpid;
+	unsigned long pad1[2];
+};
+
+struct aero_msgbuf {
+	long mtype;
+	char mtext[8192];
+};
+
+static long msg_send(long id, long mtype, const char *text, int flags = 0) {
+	static struct aero_msgbuf message;
+	message.mtype = mtype;
+	memcpy(message.mtext, text, strlen(text));
+	return aero_syscall(SYS_MSGSND, id, (long)&message, strlen(text), flags);
+}
+
+// Receives a message of the type selected by `mtype` and checks its type and text.
+static bool msg_expect(long id, long mtype, int flags, long expected_type, const char *expected) {
+	static struct aero_msgbuf message;
+	long size = aero_syscall(SYS_MSGRCV, id, (long)&message, sizeof(message.mtext), mtype, flags);
+
+	return size == (long)strlen(expected) && message.mtype == expected_type &&
+			!memcmp(message.mtext, expected, size);
+}
+
+DEFINE_TEST(sysv_msg, ([] {
+	long id = aero_syscall(SYS_MSGGET, IPC_PRIVATE, IPC_CREAT | 0600);
+	assert(id >= 0);
+
+	// Queues with a key are found again by it.
+	const long key = 0x75747375;
+	long keyed = aero_syscall(SYS_MSGGET, key, IPC_CREAT | IPC_EXCL | 0600);
+	assert(keyed >= 0 && keyed != id);
+	assert(aero_syscall(SYS_MSGGET, key, IPC_CREAT | IPC_EXCL | 0600) == -EEXIST);
+	assert(aero_syscall(SYS_MSGGET, key, 0) == keyed);
+	assert(aero_syscall(SYS_MSGCTL, keyed, IPC_RMID) == 0);
+	assert(aero_syscall(SYS_MSGGET, key, 0) == -ENOENT);
+
+	assert(msg_send(id, 0, "zero") == -EINVAL);
+
+	assert(msg_send(id, 3, "three") == 0);
+	assert(msg_send(id, 1, "one") == 0);
+	assert(msg_send(id, 2, "two") == 0);
+	assert(msg_send(id, 1, "uno") == 0);
+
+	struct aero_msqid_ds ds;
+	assert(aero_syscall(SYS_MSGCTL, id, IPC_STAT, (long)&ds) == 0);
+	assert(ds.qnum == 4 && ds.cbytes == 14);
+	assert((ds.mode & 0777) == 0600);
+	assert(ds.lspid == getpid());
+
+	// Messages are selected by their type.
+	assert(msg_expect(id, 2, 0, 2, "two"));
+	assert(msg_expect(id, -2, 0, 1, "one"));
+	assert(msg_expect(id, 1, AERO_MSG_EXCEPT, 3, "three"));
+	assert(msg_expect(id, 0, 0, 1, "uno"));
+	assert(aero_syscall(SYS_MSGRCV, id, (long)&ds, 0, 0, IPC_NOWAIT) == -ENOMSG);
+
+	// Messages larger than the buffer are only received truncated with `MSG_NOERROR`.
+	assert(msg_send(id, 5, "truncated") == 0);
+
+	struct aero_msgbuf message;
+	assert(aero_syscall(SYS_MSGRCV, id, (long)&message, 4, 0, 0) == -E2BIG);
+	assert(aero_syscall(SYS_MSGRCV, id, (long)&message, 4, 0, AERO_MSG_NOERROR) == 4);
+	assert(message.mtype == 5 && !memcmp(message.mtext, "trun", 4));
+
+	// Senders block while the queue is full.
+	std::string big(8192, 'x');
+	assert(msg_send(id, 1, big.c_str(), IPC_NOWAIT) == 0);
+	assert(msg_send(id, 1, big.c_str(), IPC_NOWAIT) == 0);
+	assert(msg_send(id, 1, "full", IPC_NOWAIT) == -EAGAIN);
+
+	assert(msg_expect(id, 0, 0, 1, big.c_str()));
+	assert(msg_expect(id, 0, 0, 1, big.c_str()));
+
+	// Receivers block until a message of their type is sent.
+	pid_t child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child)
+		exit(msg_expect(id, 7, 0, 7, "seven") ? 0 : 1);
+
+	usleep(100000);
+	assert(msg_send(id, 6, "six") == 0);
+	assert(msg_send(id, 7, "seven") == 0);
+
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	assert(msg_expect(id, 0, 0, 6, "six"));
+
+	// Removing the queue wakes up the blocked receivers.
+	child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child)
+		exit(aero_syscall(SYS_MSGRCV, id, (long)&message, 16, 0, 0) == -EIDRM ? 0 : 1);
+
+	usleep(100000);
+	assert(aero_syscall(SYS_MSGCTL, id, IPC_RMID) == 0);
+
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	assert(msg_send(id, 1, "gone") == -EINVAL);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_SEMGET 123
+#define SYS_SEMOP 124
+#define SYS_SEMCTL 125
+
+#define AERO_SEM_UNDO 0x1000
+#define AERO_GETPID 11
+#define AERO_GETVAL 12
+#define AERO_GETALL 13
+#define AERO_GETNCNT 14
+#define AERO_GETZCNT 15
+#define AERO_SETVAL 16
+#define AERO_SETALL 17
+
+struct aero_sembuf {
+	unsigned short num;
+	short op;
+	short flg;
+};
+
+struct aero_semid_ds {
+	int key;
+	unsigned int uid, gid, cuid, cgid, mode;
+	int seq;
+	long pad0[2];
+	long otime;
+	unsigned long pad1;
+	long ctime;
+	unsigned long pad2;
+	unsigned long nsems;
+	unsigned long pad3[2];
+};
+
+// Performs the single operation `op` on the semaphore `num` of the set `id`.
+static long sem_op(long id, unsigned short num, short op, short flg = 0) {
+	struct aero_sembuf sop = {num, op, flg};
+	return aero_syscall(SYS_SEMOP, id, (long)&sop, 1);
+}
+
+static long sem_get(long id, long num, long command) {
+	return aero_syscall(SYS_SEMCTL, id, num, command);
+}
+
+// Waits for `child` and returns whether it exited successfully.
+static bool sem_child_ok(pid_t child) {
+	int status;
+	assert_errno("waitpid", waitpid(child, &status, 0) == child);
+	return WIFEXITED(status) && WEXITSTATUS(status) == 0;
+}
+
+DEFINE_TEST(sysv_sem, ([] {
+	long id = aero_syscall(SYS_SEMGET, IPC_PRIVATE, 2, IPC_CREAT | 0600);
+	assert(id >= 0);
+
+	// Sets with a key are found again by it.
+	const long key = 0x75747376;
+	long keyed = aero_syscall(SYS_SEMGET, key, 1, IPC_CREAT | IPC_EXCL | 0600);
+	assert(keyed >= 0 && keyed != id);
+	assert(aero_syscall(SYS_SEMGET, key, 1, IPC_CREAT | IPC_EXCL | 0600) == -EEXIST);
+	assert(aero_syscall(SYS_SEMGET, key, 2, 0) == -EINVAL);
+	assert(aero_syscall(SYS_SEMGET, key, 0, 0) == keyed);
+	assert(aero_syscall(SYS_SEMCTL, keyed, 0, IPC_RMID) == 0);
+	assert(aero_syscall(SYS_SEMGET, key, 1, 0) == -ENOENT);
+
+	struct aero_semid_ds ds;
+	assert(aero_syscall(SYS_SEMCTL, id, 0, IPC_STAT, (long)&ds) == 0);
+	assert(ds.nsems == 2 && (ds.mode & 0777) == 0600 && ds.otime == 0);
+
+	// Semaphores start at zero, so only waiting for zero does not block.
+	assert(sem_op(id, 0, -1, IPC_NOWAIT) == -EAGAIN);
+	assert(sem_op(id, 0, 0) == 0);
+	assert(sem_op(id, 2, 1) == -EFBIG);
+
+	assert(aero_syscall(SYS_SEMCTL, id, 0, AERO_SETVAL, 3) == 0);
+	assert(sem_get(id, 0, AERO_GETVAL) == 3);
+	assert(aero_syscall(SYS_SEMCTL, id, 0, AERO_SETVAL, 32768) == -ERANGE);
+	assert(sem_op(id, 0, 32765) == -ERANGE);
+
+	// Either all the operations are performed or none is.
+	struct aero_sembuf ops[2] = {{0, -2, 0}, {1, -1, IPC_NOWAIT}};
+	assert(aero_syscall(SYS_SEMOP, id, (long)ops, 2) == -EAGAIN);
+	assert(sem_get(id, 0, AERO_GETVAL) == 3);
+
+	unsigned short values[2] = {3, 1};
+	assert(aero_syscall(SYS_SEMCTL, id, 0, AERO_SETALL, (long)values) == 0);
+	assert(aero_syscall(SYS_SEMOP, id, (long)ops, 2) == 0);
+	assert(aero_syscall(SYS_SEMCTL, id, 0, AERO_GETALL, (long)values) == 0);
+	assert(values[0] == 1 && values[1] == 0);
+	assert(sem_get(id, 1, AERO_GETPID) == getpid());
+
+	assert(aero_syscall(SYS_SEMCTL, id, 0, IPC_STAT, (long)&ds) == 0);
+	assert(ds.otime != 0);
+
+	// Operations block until they can be performed.
+	pid_t child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child)
+		exit(sem_op(id, 1, -2) == 0 ? 0 : 1);
+
+	usleep(100000);
+	assert(sem_get(id, 1, AERO_GETNCNT) == 1);
+	assert(sem_op(id, 1, 1) == 0);
+
+	usleep(100000);
+	assert(sem_get(id, 1, AERO_GETNCNT) == 1);
+	assert(sem_op(id, 1, 1) == 0);
+
+	assert(sem_child_ok(child));
+	assert(sem_get(id, 1, AERO_GETVAL) == 0);
+	assert(sem_get(id, 1, AERO_GETNCNT) == 0);
+
+	child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child)
+		exit(sem_op(id, 0, 0) == 0 ? 0 : 1);
+
+	usleep(100000);
+	assert(sem_get(id, 0, AERO_GETZCNT) == 1);
+	assert(sem_op(id, 0, -1) == 0);
+	assert(sem_child_ok(child));
+
+	// The operations performed with `SEM_UNDO` are reverted when the process exits.
+	child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child)
+		exit(sem_op(id, 0, 2, AERO_SEM_UNDO) == 0 && sem_op(id, 0, -1, AERO_SEM_UNDO) == 0 ? 0 : 1);
+
+	assert(sem_child_ok(child));
+	assert(sem_get(id, 0, AERO_GETVAL) == 0);
+	assert(sem_get(id, 0, AERO_GETPID) == child);
+
+	// Setting the value discards the pending adjustments.
+	child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child) {
+		if (sem_op(id, 1, 1, AERO_SEM_UNDO))
+			exit(1);
+
+		usleep(200000);
+		exit(0);
+	}
+
+	usleep(100000);
+	assert(aero_syscall(SYS_SEMCTL, id, 1, AERO_SETVAL, 5) == 0);
+	assert(sem_child_ok(child));
+	assert(sem_get(id, 1, AERO_GETVAL) == 5);
+
+	// Removing the set wakes up the blocked tasks.
+	child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child)
+		exit(sem_op(id, 0, -1) == -EIDRM ? 0 : 1);
+
+	usleep(100000);
+	assert(aero_syscall(SYS_SEMCTL, id, 0, IPC_RMID) == 0);
+	assert(sem_child_ok(child));
+
+	assert(sem_op(id, 0, 1) == -EINVAL);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_FCNTL 43
+#define SYS_MEMFD_CREATE 87
+#define SYS_FTRUNCATE 88
+
+#define AERO_MFD_ALLOW_SEALING 2
+#define AERO_F_ADD_SEALS 1033
+#define AERO_F_GET_SEALS 1034
+#define AERO_F_SEAL_SEAL 1
+#define AERO_F_SEAL_SHRINK 2
+#define AERO_F_SEAL_GROW 4
+#define AERO_F_SEAL_WRITE 8
+
+DEFINE_TEST(memfd_seal, ([] {
+	const long size = 1024 * 1024;
+
+	// Files that are not created to be sealed cannot be.
+	long fd = aero_syscall(SYS_MEMFD_CREATE, (long)"unsealable", 10, 0);
+	assert(fd >= 0);
+	assert(aero_syscall(SYS_FCNTL, fd, AERO_F_GET_SEALS) == AERO_F_SEAL_SEAL);
+	assert(aero_syscall(SYS_FCNTL, fd, AERO_F_ADD_SEALS, AERO_F_SEAL_GROW) == -EPERM);
+	close(fd);
+
+	fd = aero_syscall(SYS_MEMFD_CREATE, (long)"surface", 7, AERO_MFD_ALLOW_SEALING);
+	assert(fd >= 0);
+	assert(aero_syscall(SYS_FCNTL, fd, AERO_F_GET_SEALS) == 0);
+	assert(aero_syscall(SYS_FTRUNCATE, fd, size) == 0);
+
+	char *memory = (char *)mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+	assert_errno("mmap", memory != MAP_FAILED);
+
+	// The mapping is shared with the children, in both directions.
+	pid_t child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child) {
+		strcpy(memory + size - 16, "from the child");
+		exit(0);
+	}
+
+	assert(sem_child_ok(child));
+	assert(!strcmp(memory + size - 16, "from the child"));
+
+	strcpy(memory, "from the parent");
+	child = fork();
+	assert_errno("fork", child != -1);
+
+	if (!child)
+		exit(!strcmp(memory, "from the parent") ? 0 : 1);
+
+	assert(sem_child_ok(child));
+
+	// The size can no longer change once sealed.
+	assert(aero_syscall(SYS_FCNTL, fd, AERO_F_ADD_SEALS, AERO_F_SEAL_SHRINK | AERO_F_SEAL_GROW) == 0);
+	assert(aero_syscall(SYS_FTRUNCATE, fd, size * 2) == -EPERM);
+	assert(aero_syscall(SYS_FTRUNCATE, fd, size / 2) == -EPERM);
+	assert(aero_syscall(SYS_FTRUNCATE, fd, size) == 0);
+	assert(pwrite(fd, "x", 1, size) == -1 && errno == EPERM);
+	assert(pwrite(fd, "x", 1, 0) == 1 && memory[0] == 'x');
+
+	// The contents cannot be sealed while they can be written through a mapping.
+	assert(aero_syscall(SYS_FCNTL, fd, AERO_F_ADD_SEALS, AERO_F_SEAL_WRITE) == -EBUSY);
+	assert_errno("munmap", munmap(memory, size) == 0);
+	assert(aero_syscall(SYS_FCNTL, fd, AERO_F_ADD_SEALS, AERO_F_SEAL_WRITE | AERO_F_SEAL_SEAL) == 0);
+
+	assert(pwrite(fd, "y", 1, 0) == -1 && errno == EPERM);
+	assert(mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0) == MAP_FAILED && errno == EPERM);
+
+	memory = (char *)mmap(NULL, size, PROT_READ, MAP_SHARED, fd, 0);
+	assert_errno("mmap", memory != MAP_FAILED);
+	assert(!strcmp(memory, "xrom the parent"));
+
+	assert(aero_syscall(SYS_FCNTL, fd, AERO_F_GET_SEALS) ==
+	       (AERO_F_SEAL_SEAL | AERO_F_SEAL_SHRINK | AERO_F_SEAL_GROW | AERO_F_SEAL_WRITE));
+	assert(aero_syscall(SYS_FCNTL, fd, AERO_F_ADD_SEALS, AERO_F_SEAL_SHRINK) == -EPERM);
+
+	assert_errno("munmap", munmap(memory, size) == 0);
+	close(fd);
+}))
+#endif
+
+static thread_local int tls_value = 1;
+
+DEFINE_TEST(thread_local_storage, ([] {
+	tls_value = 2;
+
+	// Every thread starts with its own copy of the initial value.
+	int seen = 0;
+	pthread_t thread;
+	int ret = pthread_create(&thread, NULL, [](void *arg) -> void * {
+		*(int *)arg = tls_value;
+		tls_value = 3;
+		return NULL;
+	}, &seen);
+
+	assert(ret == 0);
+	assert(pthread_join(thread, NULL) == 0);
+	assert(seen == 1 && tls_value == 2);
+}))
+
+#if defined(__aero__)
+#define SYS_ARCH_PRCTL 10
+
+#define AERO_ARCH_SET_GS 0x1001
+#define AERO_ARCH_SET_FS 0x1002
+#define AERO_ARCH_GET_FS 0x1003
+
+DEFINE_TEST(arch_prctl_fs, ([] {
+	// The FS base points to the thread control block, whose first field points to itself.
+	unsigned long tcb;
+	asm volatile("mov %%fs:0, %0" : "=r"(tcb));
+	assert(aero_syscall(SYS_ARCH_PRCTL, AERO_ARCH_GET_FS) == (long)tcb);
+
+	// Only user addresses can be used as a segment base.
+	assert(aero_syscall(SYS_ARCH_PRCTL, AERO_ARCH_SET_FS, 0xffff800000000000) == -EPERM);
+	assert(aero_syscall(SYS_ARCH_PRCTL, AERO_ARCH_SET_FS, 0x8000000000000000) == -EPERM);
+	assert(aero_syscall(SYS_ARCH_PRCTL, AERO_ARCH_SET_GS, 0xffff800000000000) == -EPERM);
+	assert(aero_syscall(SYS_ARCH_PRCTL, AERO_ARCH_GET_FS) == (long)tcb);
+
+	assert(aero_syscall(SYS_ARCH_PRCTL, AERO_ARCH_SET_FS, tcb) == 0);
+	assert(tls_value == 2);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_GETTID 29
+#define SYS_FUTEX_WAIT 57
+#define SYS_SET_TID_ADDRESS 126
+
+static volatile uint32_t exit_tid;
+static volatile bool exit_tid_set;
+static volatile bool exit_tid_ok;
+
+DEFINE_TEST(set_tid_address, ([] {
+	long main_tid = aero_syscall(SYS_GETTID, 0);
+	assert(main_tid > 0);
+
+	pthread_t thread;
+	int ret = pthread_create(&thread, NULL, [](void *) -> void * {
+		long tid = aero_syscall(SYS_GETTID, 0);
+		exit_tid = tid;
+
+		// The thread ID of the caller is returned.
+		exit_tid_ok = aero_syscall(SYS_SET_TID_ADDRESS, (long)&exit_tid) == tid;
+		exit_tid_set = true;
+		return NULL;
+	}, NULL);
+
+	assert(ret == 0);
+
+	while (!exit_tid_set)
+		sched_yield();
+
+	assert(exit_tid_ok);
+
+	// The thread ID is zeroed when the thread exits, which wakes up the waiters on it.
+	struct timespec timeout = {};
+	uint32_t tid;
+
+	while ((tid = exit_tid) != 0) {
+		assert(tid != main_tid);
+		aero_syscall(SYS_FUTEX_WAIT, (long)&exit_tid, tid, (long)&timeout);
+	}
+
+	assert(pthread_join(thread, NULL) == 0);
+}))
+#endif
+
+#if defined(__aero__)
+DEFINE_TEST(supplementary_groups, ([] {
+	gid_t saved[64];
+	int count = getgroups(64, saved);
+	assert_errno("getgroups", count != -1);
+
+	gid_t groups[] = {100, 10, 50};
+	assert_errno("setgroups", setgroups(3, groups) != -1);
+
+	// An empty list only returns the number of groups.
+	assert(getgroups(0, nullptr) == 3);
+
+	gid_t list[4] = {};
+	assert(getgroups(2, list) == -1 && errno == EINVAL);
+	assert(getgroups(4, list) == 3);
+	assert(list[0] == 10 && list[1] == 50 && list[2] == 100);
+
+	// The groups are inherited by the children.
+	pid_t pid = fork();
+	assert_errno("fork", pid >= 0);
+
+	if (!pid)
+		_exit(getgroups(0, nullptr) == 3 ? 0 : 1);
+
+	int status;
+	assert_errno("waitpid", waitpid(pid, &status, 0) == pid);
+	assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	assert_errno("setgroups", setgroups(count, saved) != -1);
+}))
+#endif
+
+#if defined(__aero__)
+#define SYS_SLEEP 31
+#define SYS_IPC_SEND 45
+#define SYS_IPC_RECV 46
+
+DEFINE_TEST(ipc_send_recv, ([] {
+	// Only processes that exist can be sent a message.
+	assert(aero_syscall(SYS_IPC_SEND, 0x7fffffff, (long)"x", 1) == -EINVAL);
+
+	long first = aero_syscall(SYS_IPC_SEND, getpid(), (long)"hello", 5);
+	long second = aero_syscall(SYS_IPC_SEND, getpid(), (long)"hi", 2);
+	assert(first >= 0 && second > first);
+
+	// A message that does not fit is left at the front of the queue.
+	size_t from = 0;
+	char buffer[16] = {};
+	assert(aero_syscall(SYS_IPC_RECV, (long)&from, (long)buffer, 4, 0) == -E2BIG);
+	assert(from == 0);
+
+	// The length of the message is returned and the rest of the buffer is left alone.
+	memset(buffer, 'x', sizeof(buffer));
+	assert(aero_syscall(SYS_IPC_RECV, (long)&from, (long)buffer, sizeof(buffer), 1) == 5);
+	assert(from == (size_t)getpid());
+	assert(!memcmp(buffer, "helloxxx", 8));
+
+	assert(aero_syscall(SYS_IPC_RECV, (long)&from, (long)buffer, 2, 0) == 2);
+	assert(!memcmp(buffer, "hilloxxx", 8));
+}))
+
+DEFINE_TEST(sleep_timespec, ([] {
+	struct timespec invalid = {0, 1000000000};
+	assert(aero_syscall(SYS_SLEEP, (long)&invalid) == -EINVAL);
+	invalid = {-1, 0};
+	assert(aero_syscall(SYS_SLEEP, (long)&invalid) == -EINVAL);
+
+	struct timespec start, end;
+	assert_errno("clock_gettime", !clock_gettime(CLOCK_MONOTONIC, &start));
+
+	struct timespec duration = {0, 50 * 1000 * 1000};
+	assert(aero_syscall(SYS_SLEEP, (long)&duration) == 0);
+
+	assert_errno("clock_gettime", !clock_gettime(CLOCK_MONOTONIC, &end));
+	long elapsed_ms = (end.tv_sec - start.tv_sec) * 1000 + (end.tv_nsec - start.tv_nsec) / 1000000;
+	assert(elapsed_ms >= 50);
+}))
+
+DEFINE_TEST(unknown_syscall, ([] {
+	// The kernel returns the negated error code. Only the first attempt is logged, but the
+	// later ones fail all the same.
+	assert(aero_syscall(0xffff, 0) == -ENOSYS);
+	assert(aero_syscall(0xffff, 0) == -ENOSYS);
+}))
+#endif
+
+std::vector<abstract_test_case *> &test_case_ptrs() {
+	static std::vector<abstract_test_case *> singleton;
+	return singleton;
+}
+
+void abstract_test_case::register_case(abstract_test_case *tcp) {
+	test_case_ptrs().push_back(tcp);
+}
+
+// Machine-readable test events, reported to CI through `/dev/ci-events` when the kernel is built
+// with the `ci` feature. See `build-support/test_report.py` for the format.
+static int ci_events_fd = -1;
+static pid_t harness_pid;
+static const char *running_test;
+static int tests_passed;
+
+static void ci_event(const char *event, const char *name, const char *message = nullptr) {
+	if (ci_events_fd < 0)
+		return;
+
+	// Test names are identifiers and the messages are ours, so nothing needs escaping.
+	char line[256];
+	int len;
+	if (message)
+		len = snprintf(line, sizeof(line),
+			"{\"event\":\"%s\",\"suite\":\"userland\",\"name\":\"%s\",\"message\":\"%s\"}\n",
+			event, name, message);
+	else
+		len = snprintf(line, sizeof(line),
+			"{\"event\":\"%s\",\"suite\":\"userland\",\"name\":\"%s\"}\n", event, name);
+
+	write(ci_events_fd, line, len);
+}
+
+static void ci_summary(int failed) {
+	if (ci_events_fd < 0)
+		return;
+
+	char line[128];
+	int len = snprintf(line, sizeof(line),
+		"{\"event\":\"run_summary\",\"suite\":\"userland\",\"passed\":%d,\"failed\":%d,\"filtered\":0}\n",
+		tests_passed, failed);
+
+	write(ci_events_fd, line, len);
+}
+
+// A failing test aborts or crashes the harness, so the failure is reported from the signal
+// handler. The children forked by the tests inherit it and are left to die normally.
+static void on_fatal_signal(int sig) {
+	if (getpid() == harness_pid && running_test) {
+		char message[32];
+		snprintf(message, sizeof(message), "killed by signal %d", sig);
+
+		ci_event("test_fail", running_test, message);
+		ci_summary(1);
+	}
+
+	signal(sig, SIG_DFL);
+	raise(sig);
+}
+
+int main() {
+	harness_pid = getpid();
+	ci_events_fd = open("/dev/ci-events", O_WRONLY | O_CLOEXEC);
+
+	for (int sig : {SIGABRT, SIGSEGV, SIGBUS, SIGILL, SIGFPE})
+		signal(sig, on_fatal_signal);
+
+  // Go through all tests and run them.
   for(abstract_test_case *tcp : test_case_ptrs()) {
 		std::cout << "tests: Running " << tcp->name() << std::endl;
+
+		running_test = tcp->name();
+		ci_event("test_start", running_test);
 		tcp->run();
+		ci_event("test_ok", running_test);
+
+		running_test = nullptr;
+		tests_passed++;
 	}
+
+	ci_summary(0);
 }